@@ -0,0 +1,334 @@
+// src/archive.rs
+
+use crate::config::{CatalogItem, CustomVarDef, EnvProfile, ItemKind};
+use crate::db;
+use crate::error::{BathError, Result};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::Path;
+
+/// Format version written by `dump`; `load` accepts only this one.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// Largest archive `load` reads, so piping the wrong thing into `bath load -`
+/// fails instead of filling memory.
+pub const MAX_ARCHIVE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The whole database as editable JSON: profiles in their manual order, custom
+/// var definitions and catalog items. Deleted profiles and item usage are not
+/// part of it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Archive {
+    pub version: u32,
+    pub profiles: Vec<EnvProfile>,
+    pub custom_vars: Vec<CustomVarDef>,
+    pub items: Vec<ArchivedItem>,
+}
+
+/// A `CatalogItem` without its database id and usage counters.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ArchivedItem {
+    pub kind: ItemKind,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl From<CatalogItem> for ArchivedItem {
+    fn from(item: CatalogItem) -> Self {
+        ArchivedItem {
+            kind: item.kind,
+            value: item.value,
+            program: item.program,
+            version: item.version,
+            tags: item.tags,
+        }
+    }
+}
+
+impl From<ArchivedItem> for CatalogItem {
+    fn from(item: ArchivedItem) -> Self {
+        CatalogItem {
+            id: None,
+            kind: item.kind,
+            value: item.value,
+            program: item.program,
+            version: item.version,
+            tags: item.tags,
+            use_count: 0,
+            last_used_at: None,
+        }
+    }
+}
+
+/// How `load` treats what is already in the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Fail if a profile name is taken.
+    Strict,
+    /// Import next to the existing profiles, renaming taken names.
+    Merge,
+    /// Wipe the database first.
+    Replace,
+}
+
+/// Everything in the database. Profiles whose entries cannot be read are left
+/// out and returned by name.
+pub fn dump(conn: &Connection) -> Result<(Archive, Vec<String>)> {
+    let (profiles, broken) = db::load_profiles_checked(conn)?;
+    let archive = Archive {
+        version: ARCHIVE_VERSION,
+        profiles,
+        custom_vars: db::load_custom_var_defs(conn)?,
+        items: db::load_items(conn)?.into_iter().map(Into::into).collect(),
+    };
+    Ok((archive, broken.into_iter().map(|b| b.name).collect()))
+}
+
+/// Reads archive text from `reader`, refusing more than `MAX_ARCHIVE_BYTES`.
+pub fn read_text(reader: impl Read) -> std::io::Result<String> {
+    let mut text = String::new();
+    reader
+        .take(MAX_ARCHIVE_BYTES + 1)
+        .read_to_string(&mut text)?;
+    if text.len() as u64 > MAX_ARCHIVE_BYTES {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("larger than {} MiB", MAX_ARCHIVE_BYTES >> 20),
+        ));
+    }
+    Ok(text)
+}
+
+/// Parses an archive, checking its version before anything else.
+pub fn parse(text: &str) -> Result<Archive> {
+    let value: serde_json::Value = serde_json::from_str(text)?;
+    match value.get("version").and_then(serde_json::Value::as_u64) {
+        Some(v) if v == u64::from(ARCHIVE_VERSION) => Ok(serde_json::from_value(value)?),
+        Some(v) => Err(BathError::InvalidArgument(format!(
+            "archive version {v} is not supported (this bath reads version {ARCHIVE_VERSION})"
+        ))),
+        None => Err(BathError::InvalidArgument(
+            "not a bath archive: no \"version\" field".to_string(),
+        )),
+    }
+}
+
+/// Writes `archive` into the database in one transaction. Items with a kind
+/// and value already in the catalog are merged into it. Returns (archive name,
+/// stored name) per profile.
+pub fn load(conn: &Connection, archive: Archive, mode: LoadMode) -> Result<Vec<(String, String)>> {
+    db::atomically(conn, |tx| {
+        match mode {
+            LoadMode::Replace => db::clear_database(tx)?,
+            LoadMode::Strict => {
+                for p in &archive.profiles {
+                    if db::profile_exists(tx, &p.name)? {
+                        return Err(BathError::InvalidArgument(format!(
+                            "profile '{}' already exists (pass --merge or --replace)",
+                            p.name
+                        )));
+                    }
+                }
+            }
+            LoadMode::Merge => {}
+        }
+        let imported = db::import_profiles(tx, archive.profiles, &archive.custom_vars)?;
+        for item in archive.items {
+            db::save_item(tx, &mut item.into(), false)?;
+        }
+        Ok(imported)
+    })
+}
+
+/// Prints the archive, or writes it to `output`.
+pub fn dump_command(output: Option<&Path>) -> anyhow::Result<()> {
+    let conn = db::establish_connection()?;
+    let (archive, skipped) = dump(&conn)?;
+    for name in skipped {
+        crate::profiles::warn_unreadable(&name);
+    }
+    let json = serde_json::to_string_pretty(&archive)?;
+    match output {
+        Some(path) => std::fs::write(path, format!("{json}\n"))
+            .map_err(|e| BathError::file("write", path, e))?,
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+/// Loads the archive at `path`, or from stdin when `path` is `-`.
+pub fn load_command(path: &Path, mode: LoadMode) -> anyhow::Result<()> {
+    let text = if path.as_os_str() == "-" {
+        read_text(std::io::stdin().lock())
+            .map_err(|e| BathError::file("read", "standard input", e))?
+    } else {
+        std::fs::File::open(path)
+            .and_then(read_text)
+            .map_err(|e| BathError::file("read", path, e))?
+    };
+    let archive = parse(&text)?;
+    let conn = db::establish_connection()?;
+    let (defs, items) = (archive.custom_vars.len(), archive.items.len());
+    for (original, name) in load(&conn, archive, mode)? {
+        if original == name {
+            println!("Loaded profile '{name}'.");
+        } else {
+            println!("Loaded profile '{original}' as '{name}'.");
+        }
+    }
+    println!("Loaded {defs} custom var definition(s) and {items} item(s).");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Entry, PathEntry, VarKind};
+    use std::collections::HashMap;
+
+    fn populated() -> Result<Connection> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut gcc = EnvProfile::new("gcc");
+        gcc.description = Some("cross toolchain".to_string());
+        gcc.entries = vec![
+            Entry::Path(PathEntry {
+                path: "/opt/gcc/bin".to_string(),
+                program: Some("gcc".to_string()),
+                version: Some("13".to_string()),
+                mode: None,
+            }),
+            Entry::CustomPart {
+                name: "TOOLS".to_string(),
+                value: "/opt/tools".to_string(),
+                separator: ":".to_string(),
+                mode: None,
+            },
+        ];
+        db::save_profile(&conn, &gcc)?;
+        db::save_profile(&conn, &EnvProfile::new("empty"))?;
+        db::save_custom_var_def(&conn, &CustomVarDef::new("TOOLS", VarKind::List, ":"))?;
+        let mut item: CatalogItem = ArchivedItem {
+            kind: ItemKind::Path,
+            value: "/opt/gcc/bin".to_string(),
+            program: Some("gcc".to_string()),
+            version: None,
+            tags: vec!["core".to_string()],
+        }
+        .into();
+        db::save_item(&conn, &mut item, false)?;
+        Ok(conn)
+    }
+
+    fn json(conn: &Connection) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(dump(conn)?.0)?)
+    }
+
+    #[test]
+    fn dump_then_load_round_trips() -> Result<()> {
+        let source = populated()?;
+        let text = serde_json::to_string_pretty(&dump(&source)?.0)?;
+
+        let target = Connection::open_in_memory()?;
+        db::initialize_db(&target)?;
+        load(&target, parse(&text)?, LoadMode::Strict)?;
+        assert_eq!(json(&target)?, json(&source)?);
+        assert_eq!(
+            db::load_profile(&target, "gcc")?.description.as_deref(),
+            Some("cross toolchain")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn collisions_fail_merge_or_get_replaced() -> Result<()> {
+        let conn = populated()?;
+        let archive = || -> Result<Archive> { Ok(dump(&conn)?.0) };
+
+        assert!(load(&conn, archive()?, LoadMode::Strict).is_err());
+        assert_eq!(db::load_all_profiles(&conn)?.len(), 2);
+
+        let names = load(&conn, archive()?, LoadMode::Merge)?;
+        assert_eq!(names[0], ("gcc".to_string(), "gcc-imported".to_string()));
+        assert_eq!(db::load_all_profiles(&conn)?.len(), 4);
+        assert_eq!(db::load_items(&conn)?.len(), 1);
+        assert_eq!(db::load_custom_var_defs(&conn)?.len(), 1);
+
+        let original = populated()?;
+        load(&conn, dump(&original)?.0, LoadMode::Replace)?;
+        assert_eq!(json(&conn)?, json(&original)?);
+        Ok(())
+    }
+
+    #[test]
+    fn merged_children_extend_their_renamed_parent() -> Result<()> {
+        let base = |cc: &str| EnvProfile {
+            entries: vec![Entry::CC(cc.to_string())],
+            ..EnvProfile::new("base")
+        };
+        let source = Connection::open_in_memory()?;
+        db::initialize_db(&source)?;
+        db::save_profile(&source, &base("archive-gcc"))?;
+        let child = EnvProfile {
+            extends: Some("base".to_string()),
+            ..EnvProfile::new("child")
+        };
+        db::save_profile(&source, &child)?;
+
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        db::save_profile(&conn, &base("local-gcc"))?;
+        let names = load(&conn, dump(&source)?.0, LoadMode::Merge)?;
+        assert_eq!(
+            names,
+            [
+                ("base".to_string(), "base-imported".to_string()),
+                ("child".to_string(), "child".to_string()),
+            ]
+        );
+        assert_eq!(
+            db::load_profile(&conn, "child")?.extends.as_deref(),
+            Some("base-imported")
+        );
+        let inherited = crate::export::load_inherited(&conn, "child", &HashMap::new())?;
+        assert_eq!(inherited.entries, [Entry::CC("archive-gcc".to_string())]);
+        Ok(())
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let err = parse(r#"{"version": 2, "profiles": []}"#).unwrap_err();
+        assert!(err.to_string().contains("version 2"));
+        assert!(parse(r#"{"profiles": []}"#).is_err());
+        assert!(parse(r#"{"version": 1, "profiles": [], "custom_vars": [], "items": []}"#).is_ok());
+    }
+
+    #[test]
+    fn archives_are_read_from_byte_streams() -> anyhow::Result<()> {
+        let original = populated()?;
+        let bytes = serde_json::to_vec(&dump(&original)?.0)?;
+        let archive = parse(&read_text(&bytes[..])?)?;
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        load(&conn, archive, LoadMode::Strict)?;
+        assert_eq!(json(&conn)?, json(&original)?);
+
+        let invalid: &[u8] = &[b'{', 0xff, b'}'];
+        assert!(read_text(invalid).is_err());
+        let huge = std::io::repeat(b' ').take(MAX_ARCHIVE_BYTES + 1);
+        let err = read_text(huge).unwrap_err();
+        assert_eq!(err.to_string(), "larger than 64 MiB");
+        let err = BathError::file("read", "standard input", err);
+        assert_eq!(
+            err.to_string(),
+            "failed to read standard input: larger than 64 MiB"
+        );
+        Ok(())
+    }
+}
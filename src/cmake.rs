@@ -0,0 +1,222 @@
+// src/cmake.rs
+
+use crate::config::EnvProfile;
+use crate::db;
+use crate::export::{self, check_references, grouped_vars, load_inherited, unset_vars};
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// How a profile variable is written to a CMake toolchain file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CMakeRule {
+    /// `set(<cmake var> "<value>")` with the joined value.
+    Set(&'static str),
+    /// Every part, prefixed, is added to each of the listed flag variables.
+    Flags {
+        targets: &'static [&'static str],
+        prefix: &'static str,
+    },
+}
+
+const C_FLAGS: &str = "CMAKE_C_FLAGS_INIT";
+const CXX_FLAGS: &str = "CMAKE_CXX_FLAGS_INIT";
+const EXE_LINKER_FLAGS: &str = "CMAKE_EXE_LINKER_FLAGS_INIT";
+const SHARED_LINKER_FLAGS: &str = "CMAKE_SHARED_LINKER_FLAGS_INIT";
+
+/// Profile variable -> CMake rule. Flags go to the `*_INIT` variables, which CMake
+/// uses to initialize CMAKE_C_FLAGS and friends from a toolchain file.
+///
+/// Variables not listed here are exported as `set(ENV{VAR} ...)`.
+pub const CMAKE_MAPPINGS: &[(&str, CMakeRule)] = &[
+    ("CC", CMakeRule::Set("CMAKE_C_COMPILER")),
+    ("CXX", CMakeRule::Set("CMAKE_CXX_COMPILER")),
+    ("AR", CMakeRule::Set("CMAKE_AR")),
+    ("RANLIB", CMakeRule::Set("CMAKE_RANLIB")),
+    ("STRIP", CMakeRule::Set("CMAKE_STRIP")),
+    (
+        "CFLAGS",
+        CMakeRule::Flags {
+            targets: &[C_FLAGS],
+            prefix: "",
+        },
+    ),
+    (
+        "CXXFLAGS",
+        CMakeRule::Flags {
+            targets: &[CXX_FLAGS],
+            prefix: "",
+        },
+    ),
+    (
+        "CPPFLAGS",
+        CMakeRule::Flags {
+            targets: &[C_FLAGS, CXX_FLAGS],
+            prefix: "",
+        },
+    ),
+    (
+        "CPATH",
+        CMakeRule::Flags {
+            targets: &[C_FLAGS, CXX_FLAGS],
+            prefix: "-I",
+        },
+    ),
+    (
+        "C_INCLUDE_PATH",
+        CMakeRule::Flags {
+            targets: &[C_FLAGS],
+            prefix: "-I",
+        },
+    ),
+    (
+        "CPLUS_INCLUDE_PATH",
+        CMakeRule::Flags {
+            targets: &[CXX_FLAGS],
+            prefix: "-I",
+        },
+    ),
+    (
+        "LIBRARY_PATH",
+        CMakeRule::Flags {
+            targets: &[EXE_LINKER_FLAGS, SHARED_LINKER_FLAGS],
+            prefix: "-L",
+        },
+    ),
+    (
+        "LDFLAGS",
+        CMakeRule::Flags {
+            targets: &[EXE_LINKER_FLAGS, SHARED_LINKER_FLAGS],
+            prefix: "",
+        },
+    ),
+];
+
+pub fn cmake_rule(var: &str) -> Option<CMakeRule> {
+    CMAKE_MAPPINGS
+        .iter()
+        .find(|(name, _)| *name == var)
+        .map(|(_, rule)| *rule)
+}
+
+fn cmake_quote(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Renders a CMake toolchain file for a profile.
+///
+/// Compiler/tool settings come first, then the collected flags, then every
+/// unmapped variable as an environment assignment.
+pub fn generate_cmake_toolchain(profile: &EnvProfile) -> String {
+    let mut sets = Vec::new();
+    let mut flags: Vec<(&str, Vec<String>)> = Vec::new();
+    let mut envs = Vec::new();
+
+    for (name, joined, sep) in grouped_vars(&profile.entries) {
+        match cmake_rule(&name) {
+            Some(CMakeRule::Set(target)) => {
+                sets.push(format!("set({target} {})", cmake_quote(&joined)));
+            }
+            Some(CMakeRule::Flags { targets, prefix }) => {
+                let parts: Vec<String> = joined
+                    .split(sep.as_str())
+                    .filter(|p| !p.is_empty())
+                    .map(|p| format!("{prefix}{p}"))
+                    .collect();
+                for target in targets {
+                    match flags.iter_mut().find(|(t, _)| t == target) {
+                        Some((_, existing)) => existing.extend(parts.iter().cloned()),
+                        None => flags.push((target, parts.clone())),
+                    }
+                }
+            }
+            None => envs.push(format!("set(ENV{{{name}}} {})", cmake_quote(&joined))),
+        }
+    }
+    for name in unset_vars(&profile.entries) {
+        envs.push(format!("unset(ENV{{{name}}})"));
+    }
+
+    let mut lines = vec![format!(
+        "# CMake toolchain generated by bath (profile: {})",
+        profile.name
+    )];
+    lines.extend(sets);
+    lines.extend(
+        flags
+            .into_iter()
+            .map(|(target, parts)| format!("set({target} {})", cmake_quote(&parts.join(" ")))),
+    );
+    lines.extend(envs);
+    lines.join("\n")
+}
+
+/// Prints the toolchain file for a profile, or writes it to `output`.
+pub fn cmake_toolchain_command(profile_name: &str, output: Option<&Path>) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let env = export::current_env();
+    let profile = load_inherited(&conn, profile_name, &env)?;
+    check_references(&profile)?;
+    let out = generate_cmake_toolchain(&profile);
+    match output {
+        Some(path) => std::fs::write(path, format!("{out}\n"))
+            .with_context(|| format!("failed to write {}", path.display()))?,
+        None => println!("{out}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Entry;
+
+    #[test]
+    fn mapping_table_has_no_duplicate_vars() {
+        for (i, (name, _)) in CMAKE_MAPPINGS.iter().enumerate() {
+            assert!(
+                !CMAKE_MAPPINGS[i + 1..].iter().any(|(n, _)| n == name),
+                "{name} mapped twice"
+            );
+        }
+        assert_eq!(cmake_rule("CC"), Some(CMakeRule::Set("CMAKE_C_COMPILER")));
+        assert_eq!(cmake_rule("LANG"), None);
+    }
+
+    #[test]
+    fn toolchain_maps_tools_flags_and_env() {
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::CFlag("-O2".to_string()),
+                Entry::CC("aarch64-linux-gnu-gcc".to_string()),
+                Entry::CPath("/opt/sysroot/include".to_string()),
+                Entry::LibraryPath("/opt/sysroot/lib".to_string()),
+                Entry::LDFlag("-static".to_string()),
+                Entry::Lang("C".to_string()),
+            ],
+            ..EnvProfile::new("cross")
+        };
+        assert_eq!(
+            generate_cmake_toolchain(&profile),
+            [
+                "# CMake toolchain generated by bath (profile: cross)",
+                "set(CMAKE_C_COMPILER \"aarch64-linux-gnu-gcc\")",
+                "set(CMAKE_C_FLAGS_INIT \"-O2 -I/opt/sysroot/include\")",
+                "set(CMAKE_CXX_FLAGS_INIT \"-I/opt/sysroot/include\")",
+                "set(CMAKE_EXE_LINKER_FLAGS_INIT \"-L/opt/sysroot/lib -static\")",
+                "set(CMAKE_SHARED_LINKER_FLAGS_INIT \"-L/opt/sysroot/lib -static\")",
+                "set(ENV{LANG} \"C\")",
+            ]
+            .join("\n")
+        );
+    }
+}
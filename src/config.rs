@@ -1,11 +1,75 @@
+use crate::export::OperationMode;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PathEntry {
     pub path: String,
-    pub program: String,
-    pub version: String,
+    /// The tool the directory provides, if noted.
+    #[serde(
+        default,
+        deserialize_with = "empty_as_none",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub program: Option<String>,
+    /// Version of `program`, if noted.
+    #[serde(
+        default,
+        deserialize_with = "empty_as_none",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub version: Option<String>,
+    /// Mode pinned for this part; `None` inherits the variable's mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<OperationMode>,
+}
+
+impl PathEntry {
+    /// "program version" with whatever of the two is set, `None` if neither is.
+    pub fn label(&self) -> Option<String> {
+        let parts: Vec<&str> = [&self.program, &self.version]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        (!parts.is_empty()).then(|| parts.join(" "))
+    }
+}
+
+/// `Some` for a non-blank `s`, trimmed.
+pub fn non_empty(s: &str) -> Option<String> {
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+/// `$XDG_CONFIG_HOME/bath/config.toml`, or `~/.config/bath/config.toml` when
+/// `XDG_CONFIG_HOME` is unset.
+pub fn config_path() -> Option<PathBuf> {
+    let base = if let Ok(xdg) = std::env::var("XDG_CONFIG_HOME") {
+        PathBuf::from(xdg)
+    } else if let Ok(home) = std::env::var("HOME") {
+        PathBuf::from(home).join(".config")
+    } else {
+        return None;
+    };
+
+    Some(base.join("bath").join("config.toml"))
+}
+
+/// The file keeping the command palette history, next to config.toml.
+pub fn command_history_path() -> Option<PathBuf> {
+    Some(config_path()?.with_file_name("command_history"))
+}
+
+/// Reads an optional string where older versions wrote "" for "not set".
+fn empty_as_none<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    Ok(value.as_deref().and_then(non_empty))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -14,6 +78,206 @@ pub enum VarKind {
     List,
 }
 
+/// How the TUI edits the parts of a variable.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EditorStyle {
+    Single,
+    PathPart,
+    PartsList,
+}
+
+/// A variable bath knows without a definition.
+#[derive(Debug)]
+pub struct BuiltinVar {
+    pub name: &'static str,
+    pub kind: VarKind,
+    /// Separator between parts of a list; empty for scalars.
+    pub separator: &'static str,
+    pub editor: EditorStyle,
+}
+
+const fn builtin(
+    name: &'static str,
+    kind: VarKind,
+    separator: &'static str,
+    editor: EditorStyle,
+) -> BuiltinVar {
+    BuiltinVar {
+        name,
+        kind,
+        separator,
+        editor,
+    }
+}
+
+/// Every builtin variable, in the order the TUI and `bath snapshot` list them.
+///
+/// Adding one takes a row here and, if its parts get an `Entry` variant of their
+/// own, the variant and a line in `string_builtins!`. Lists without a variant are
+/// stored as custom parts.
+pub const BUILTIN_VARS: &[BuiltinVar] = {
+    use EditorStyle::{PartsList, PathPart, Single};
+    use VarKind::{List, Scalar};
+    &[
+        builtin("PATH", List, ":", PathPart),
+        // colon-separated lists
+        builtin("CPATH", List, ":", PartsList),
+        builtin("C_INCLUDE_PATH", List, ":", PartsList),
+        builtin("CPLUS_INCLUDE_PATH", List, ":", PartsList),
+        builtin("OBJC_INCLUDE_PATH", List, ":", PartsList),
+        builtin("LIBRARY_PATH", List, ":", PartsList),
+        builtin("LD_LIBRARY_PATH", List, ":", PartsList),
+        builtin("LD_RUN_PATH", List, ":", PartsList),
+        // Builtin lists without a variant of their own; stored as custom parts.
+        builtin("PKG_CONFIG_PATH", List, ":", PartsList),
+        builtin("MANPATH", List, ":", PartsList),
+        builtin("CMAKE_PREFIX_PATH", List, ":", PartsList),
+        builtin("ACLOCAL_PATH", List, ":", PartsList),
+        builtin("LD_PRELOAD", List, ":", PartsList),
+        // space-separated lists
+        builtin("CPPFLAGS", List, " ", PartsList),
+        builtin("CFLAGS", List, " ", PartsList),
+        builtin("CXXFLAGS", List, " ", PartsList),
+        builtin("LDFLAGS", List, " ", PartsList),
+        // scalars
+        builtin("RANLIB", Scalar, "", Single),
+        builtin("CC", Scalar, "", Single),
+        builtin("CXX", Scalar, "", Single),
+        builtin("AR", Scalar, "", Single),
+        builtin("STRIP", Scalar, "", Single),
+        builtin("GCC_EXEC_PREFIX", Scalar, "", Single),
+        builtin("COLLECT_GCC_OPTIONS", Scalar, "", Single),
+        builtin("LANG", Scalar, "", Single),
+    ]
+};
+
+/// The builtin variable called `name`.
+pub fn builtin_var(name: &str) -> Option<&'static BuiltinVar> {
+    BUILTIN_VARS.iter().find(|v| v.name == name)
+}
+
+/// Separators the user chose for builtin list vars, by var name; see
+/// `db::load_separator_overrides`. Stored parts keep the builtin separator,
+/// the override only changes how they are joined.
+pub type SeparatorOverrides = HashMap<String, String>;
+
+/// A var as the editors and exports see it: a builtin or a custom definition.
+#[derive(Clone, Debug)]
+pub struct VarTypeOption {
+    pub name: String,
+    pub kind: VarKind,
+    pub separator: String,
+    pub editor: EditorStyle,
+}
+
+impl VarTypeOption {
+    /// The option of `var`, joined with its separator in `overrides` if it is
+    /// a list with one.
+    fn builtin(var: &BuiltinVar, overrides: &SeparatorOverrides) -> Self {
+        let separator = match overrides.get(var.name) {
+            Some(sep) if var.kind == VarKind::List => sep.clone(),
+            _ => var.separator.to_string(),
+        };
+        VarTypeOption {
+            name: var.name.to_string(),
+            kind: var.kind.clone(),
+            separator,
+            editor: var.editor,
+        }
+    }
+
+    fn custom(def: &CustomVarDef) -> Self {
+        VarTypeOption {
+            name: def.name.clone(),
+            kind: def.kind.clone(),
+            separator: def.separator.clone(),
+            editor: match def.kind {
+                VarKind::Scalar => EditorStyle::Single,
+                VarKind::List => EditorStyle::PartsList,
+            },
+        }
+    }
+}
+
+/// `BUILTIN_VARS` as options, with the separators in `overrides`.
+pub fn builtin_var_options(overrides: &SeparatorOverrides) -> Vec<VarTypeOption> {
+    BUILTIN_VARS
+        .iter()
+        .map(|var| VarTypeOption::builtin(var, overrides))
+        .collect()
+}
+
+/// The builtin options followed by `custom_defs`. Definitions stored before a
+/// builtin of the same name existed are shadowed by it, as in `lookup_var_option`.
+pub fn var_options(
+    custom_defs: &[CustomVarDef],
+    overrides: &SeparatorOverrides,
+) -> Vec<VarTypeOption> {
+    let mut opts = builtin_var_options(overrides);
+    for d in custom_defs {
+        if builtin_var(&d.name).is_none() {
+            opts.push(VarTypeOption::custom(d));
+        }
+    }
+    opts
+}
+
+/// Looks up the definition of a var: builtins first, then the given custom defs.
+/// Builtins come with their own separator, the one their parts are stored with.
+pub fn lookup_var_option(name: &str, custom_defs: &[CustomVarDef]) -> Option<VarTypeOption> {
+    if let Some(var) = builtin_var(name) {
+        return Some(VarTypeOption::builtin(var, &SeparatorOverrides::new()));
+    }
+    custom_defs
+        .iter()
+        .find(|d| d.name == name)
+        .map(VarTypeOption::custom)
+}
+
+/// Calls `$m!` with the builtins whose entries hold just the value, each as
+/// `Variant => "NAME"`. PATH, whose entries carry metadata, is not among them.
+macro_rules! string_builtins {
+    ($($m:ident)::+) => {
+        $($m)::+! {
+            CPath => "CPATH",
+            CInclude => "C_INCLUDE_PATH",
+            CPlusInclude => "CPLUS_INCLUDE_PATH",
+            OBJCInclude => "OBJC_INCLUDE_PATH",
+            CPPFlag => "CPPFLAGS",
+            CFlag => "CFLAGS",
+            CXXFlag => "CXXFLAGS",
+            LDFlag => "LDFLAGS",
+            LibraryPath => "LIBRARY_PATH",
+            LDLibraryPath => "LD_LIBRARY_PATH",
+            LDRunPath => "LD_RUN_PATH",
+            RanLib => "RANLIB",
+            CC => "CC",
+            CXX => "CXX",
+            AR => "AR",
+            Strip => "STRIP",
+            GCCExecPrefix => "GCC_EXEC_PREFIX",
+            CollectGCCOptions => "COLLECT_GCC_OPTIONS",
+            Lang => "LANG",
+        }
+    };
+}
+
+macro_rules! string_builtin_pattern {
+    ($($variant:ident => $name:literal,)*) => {
+        $($crate::config::Entry::$variant(_))|*
+    };
+}
+
+/// A pattern matching the entries of the `string_builtins!`, so that matches
+/// on entries can list every variant without a catch-all arm.
+macro_rules! string_builtin_entry {
+    () => {
+        crate::config::string_builtins!(crate::config::string_builtin_pattern)
+    };
+}
+
+pub(crate) use {string_builtin_entry, string_builtin_pattern, string_builtins};
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct CustomVarDef {
     pub name: String,
@@ -21,6 +285,21 @@ pub struct CustomVarDef {
     /// Separator between parts when `kind` is `List`.
     /// For `Scalar`, this is ignored and may be empty.
     pub separator: String,
+    /// Exported for profiles that neither set nor unset the var.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_value: Option<String>,
+}
+
+impl CustomVarDef {
+    /// A definition without a default value.
+    pub fn new(name: &str, kind: VarKind, separator: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            separator: separator.to_string(),
+            default_value: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -37,10 +316,15 @@ pub struct CatalogItem {
     pub program: Option<String>,
     pub version: Option<String>,
     pub tags: Vec<String>,
+    /// How often the item was dropped into a profile, and when last (unix seconds).
+    #[serde(default)]
+    pub use_count: u32,
+    #[serde(default)]
+    pub last_used_at: Option<i64>,
 }
 
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Entry {
     // Paths & Programs
     Path(PathEntry),
@@ -86,7 +370,136 @@ pub enum Entry {
         name: String,
         value: String,
         separator: String,
+        /// Mode pinned for this part; `None` inherits the variable's mode.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<OperationMode>,
+    },
+
+    // Part settings
+    //
+    // Wraps another entry with settings the plain variants cannot carry. Entries
+    // with default settings are always stored plain, so old profiles load as-is.
+    Annotated {
+        entry: Box<Entry>,
+        /// Disabled parts stay in the profile but are left out of every export.
+        #[serde(
+            default = "enabled_default",
+            skip_serializing_if = "is_enabled_default"
+        )]
+        enabled: bool,
+        /// A note on why the part is there; never part of the value.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        comment: Option<String>,
+        /// Where the part goes among the parts of its variable on export;
+        /// see `export::in_priority_order`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        priority: Option<i64>,
+    },
+
+    // Explicit unset
+    //
+    // Clears the variable instead of setting it. Other entries of the same
+    // variable are not exported alongside it.
+    Unset {
+        name: String,
     },
+
+    // Entries from a newer bath
+    //
+    // A variant this version does not know is kept exactly as stored, so saving
+    // the profile does not drop it. It is shown but never exported or edited.
+    #[serde(untagged)]
+    Unknown(#[serde(deserialize_with = "unknown_entry")] serde_json::Value),
+}
+
+macro_rules! entry_variants {
+    ($($variant:ident => $name:literal,)*) => {
+        &[
+            "Path",
+            $(stringify!($variant),)*
+            "CustomScalar",
+            "CustomPart",
+            "Annotated",
+            "Unset",
+        ]
+    };
+}
+
+/// Names of the variants above, stored as the single key of each entry.
+pub(crate) const ENTRY_VARIANTS: &[&str] = string_builtins!(entry_variants);
+
+macro_rules! builtin_entry_impls {
+    ($($variant:ident => $name:literal,)*) => {
+        impl Entry {
+            /// The variable and value of a builtin entry, `None` for custom
+            /// ones and the other variants.
+            pub fn builtin(&self) -> Option<(&'static str, &String)> {
+                match self {
+                    Entry::Path(pe) => Some(("PATH", &pe.path)),
+                    $(Entry::$variant(value) => Some(($name, value)),)*
+                    _ => None,
+                }
+            }
+
+            /// Mutable value of `builtin`.
+            fn builtin_value_mut(&mut self) -> Option<&mut String> {
+                match self {
+                    Entry::Path(pe) => Some(&mut pe.path),
+                    $(Entry::$variant(value) => Some(value),)*
+                    _ => None,
+                }
+            }
+
+            /// The builtin entry holding `value` for `var_name`, `None` if the
+            /// var has no variant of its own.
+            pub fn for_builtin(var_name: &str, value: &str) -> Option<Entry> {
+                let value = value.to_string();
+                match var_name {
+                    "PATH" => Some(Entry::Path(PathEntry {
+                        path: value,
+                        program: None,
+                        version: None,
+                        mode: None,
+                    })),
+                    $($name => Some(Entry::$variant(value)),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+string_builtins!(builtin_entry_impls);
+
+/// Accepts `{"<Variant>": ...}` for variants this version does not know. Known
+/// ones only get here when their payload is malformed, which stays an error.
+fn unknown_entry<'de, D>(deserializer: D) -> Result<serde_json::Value, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    let raw = serde_json::Value::deserialize(deserializer)?;
+    match raw.as_object() {
+        Some(map) if map.len() == 1 => {
+            let kind = map.keys().next().expect("one key");
+            if ENTRY_VARIANTS.contains(&kind.as_str()) {
+                Err(D::Error::custom(format!("invalid {kind} entry")))
+            } else {
+                Ok(raw)
+            }
+        }
+        _ => Err(D::Error::custom(
+            "invalid entry: expected an object with a single variant name",
+        )),
+    }
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+fn is_enabled_default(enabled: &bool) -> bool {
+    *enabled
 }
 
 impl Entry {
@@ -94,46 +507,202 @@ impl Entry {
     pub fn var_name(&self) -> Cow<'static, str> {
         match self {
             Entry::Path(_) => Cow::Borrowed("PATH"),
-            Entry::CPath(_) => Cow::Borrowed("CPATH"),
-            Entry::CInclude(_) => Cow::Borrowed("C_INCLUDE_PATH"),
-            Entry::CPlusInclude(_) => Cow::Borrowed("CPLUS_INCLUDE_PATH"),
-            Entry::OBJCInclude(_) => Cow::Borrowed("OBJC_INCLUDE_PATH"),
-            Entry::CPPFlag(_) => Cow::Borrowed("CPPFLAGS"),
-            Entry::CFlag(_) => Cow::Borrowed("CFLAGS"),
-            Entry::CXXFlag(_) => Cow::Borrowed("CXXFLAGS"),
-            Entry::LDFlag(_) => Cow::Borrowed("LDFLAGS"),
-            Entry::LibraryPath(_) => Cow::Borrowed("LIBRARY_PATH"),
-            Entry::LDLibraryPath(_) => Cow::Borrowed("LD_LIBRARY_PATH"),
-            Entry::LDRunPath(_) => Cow::Borrowed("LD_RUN_PATH"),
-            Entry::RanLib(_) => Cow::Borrowed("RANLIB"),
-            Entry::CC(_) => Cow::Borrowed("CC"),
-            Entry::CXX(_) => Cow::Borrowed("CXX"),
-            Entry::AR(_) => Cow::Borrowed("AR"),
-            Entry::Strip(_) => Cow::Borrowed("STRIP"),
-            Entry::GCCExecPrefix(_) => Cow::Borrowed("GCC_EXEC_PREFIX"),
-            Entry::CollectGCCOptions(_) => Cow::Borrowed("COLLECT_GCC_OPTIONS"),
-            Entry::Lang(_) => Cow::Borrowed("LANG"),
-
-            Entry::CustomScalar { name, .. } | Entry::CustomPart { name, .. } => {
-                Cow::Owned(name.clone())
+            string_builtin_entry!() => {
+                let (name, _) = self.builtin().expect("builtin entries have a builtin var");
+                Cow::Borrowed(name)
+            }
+            Entry::CustomScalar { name, .. }
+            | Entry::CustomPart { name, .. }
+            | Entry::Unset { name } => Cow::Owned(name.clone()),
+            Entry::Annotated { entry, .. } => entry.var_name(),
+            Entry::Unknown(_) => {
+                Cow::Owned(self.unknown_field("name").unwrap_or_default().to_string())
+            }
+        }
+    }
+
+    /// The variant name of an entry from a newer bath.
+    pub fn unknown_kind(&self) -> Option<&str> {
+        match self.plain() {
+            Entry::Unknown(raw) => raw.as_object()?.keys().next().map(String::as_str),
+            _ => None,
+        }
+    }
+
+    /// A string field of an entry from a newer bath. A payload that is a bare
+    /// string is its `value`.
+    pub fn unknown_field(&self, key: &str) -> Option<&str> {
+        let Entry::Unknown(raw) = self.plain() else {
+            return None;
+        };
+        let payload = raw.as_object()?.values().next()?;
+        match payload {
+            serde_json::Value::String(s) if key == "value" => Some(s),
+            _ => payload.get(key)?.as_str(),
+        }
+    }
+
+    /// Whether the part ends up in exports: it is enabled, this version knows
+    /// what it means and it has a value (i.e. it is not an `Unset`).
+    pub fn is_exported(&self) -> bool {
+        self.is_enabled() && !matches!(self.plain(), Entry::Unknown(_) | Entry::Unset { .. })
+    }
+
+    /// The variable an enabled `Unset` entry clears.
+    pub fn unset_var(&self) -> Option<&str> {
+        match self.plain() {
+            Entry::Unset { name } if self.is_enabled() => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The entry without its part settings.
+    pub fn plain(&self) -> &Entry {
+        match self {
+            Entry::Annotated { entry, .. } => entry.plain(),
+            _ => self,
+        }
+    }
+
+    /// Mutable `plain`.
+    pub fn plain_mut(&mut self) -> &mut Entry {
+        match self {
+            Entry::Annotated { entry, .. } => entry.plain_mut(),
+            _ => self,
+        }
+    }
+
+    /// Whether the part is exported. Parts are enabled unless switched off.
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            Entry::Annotated { enabled, .. } => *enabled,
+            _ => true,
+        }
+    }
+
+    /// The note attached to the part, if any.
+    pub fn comment(&self) -> Option<&str> {
+        match self {
+            Entry::Annotated { comment, .. } => comment.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The export priority of the part, if it has one.
+    pub fn priority(&self) -> Option<i64> {
+        match self {
+            Entry::Annotated { priority, .. } => *priority,
+            _ => None,
+        }
+    }
+
+    /// This entry switched on or off.
+    pub fn with_enabled(self, enabled: bool) -> Entry {
+        let (plain, _, comment, priority) = self.into_parts();
+        Entry::annotated(plain, enabled, comment, priority)
+    }
+
+    /// This entry with `comment` attached; a blank one removes the note.
+    pub fn with_comment(self, comment: Option<String>) -> Entry {
+        let (plain, enabled, _, priority) = self.into_parts();
+        let comment = comment
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty());
+        Entry::annotated(plain, enabled, comment, priority)
+    }
+
+    /// This entry with export priority `priority`; `None` removes it.
+    pub fn with_priority(self, priority: Option<i64>) -> Entry {
+        let (plain, enabled, comment, _) = self.into_parts();
+        Entry::annotated(plain, enabled, comment, priority)
+    }
+
+    /// The plain entry and its settings.
+    fn into_parts(self) -> (Entry, bool, Option<String>, Option<i64>) {
+        match self {
+            Entry::Annotated {
+                entry,
+                enabled,
+                comment,
+                priority,
+            } => {
+                let (plain, ..) = entry.into_parts();
+                (plain, enabled, comment, priority)
+            }
+            other => (other, true, None, None),
+        }
+    }
+
+    /// Wraps `plain` with the given settings, or returns it as-is for the defaults.
+    fn annotated(
+        plain: Entry,
+        enabled: bool,
+        comment: Option<String>,
+        priority: Option<i64>,
+    ) -> Entry {
+        if enabled && comment.is_none() && priority.is_none() {
+            plain
+        } else {
+            Entry::Annotated {
+                entry: Box::new(plain),
+                enabled,
+                comment,
+                priority,
             }
         }
     }
 
+    /// The stored value of this entry (the path for PATH entries). Entries from
+    /// a newer bath have none that can be changed safely.
+    pub fn value_mut(&mut self) -> Option<&mut String> {
+        if self.builtin().is_some() {
+            return self.builtin_value_mut();
+        }
+        match self {
+            Entry::CustomScalar { value, .. } | Entry::CustomPart { value, .. } => Some(value),
+            Entry::Annotated { entry, .. } => entry.value_mut(),
+            _ => None,
+        }
+    }
+
+    /// The mode pinned on this part, if any. Only PATH and custom list parts
+    /// can pin one.
+    pub fn pinned_mode(&self) -> Option<OperationMode> {
+        match self {
+            Entry::Path(pe) => pe.mode,
+            Entry::CustomPart { mode, .. } => *mode,
+            Entry::Annotated { entry, .. } => entry.pinned_mode(),
+            _ => None,
+        }
+    }
+
+    /// Pins `mode` on this part. Returns false if the entry cannot pin a mode.
+    pub fn set_pinned_mode(&mut self, mode: Option<OperationMode>) -> bool {
+        match self {
+            Entry::Path(pe) => pe.mode = mode,
+            Entry::CustomPart { mode: m, .. } => *m = mode,
+            Entry::Annotated { entry, .. } => return entry.set_pinned_mode(mode),
+            _ => return false,
+        }
+        true
+    }
+
     /// Returns the default separator used when joining multiple entries.
     pub fn separator(&self) -> Cow<'static, str> {
+        if let Some((name, _)) = self.builtin() {
+            return match builtin_var(name) {
+                Some(var) if var.kind == VarKind::List => Cow::Borrowed(var.separator),
+                _ => Cow::Borrowed(" "),
+            };
+        }
         match self {
-            // PATH and library paths are colon separated.
-            Entry::Path(_)
-            | Entry::CPath(_)
-            | Entry::CInclude(_)
-            | Entry::CPlusInclude(_)
-            | Entry::OBJCInclude(_)
-            | Entry::LibraryPath(_)
-            | Entry::LDLibraryPath(_)
-            | Entry::LDRunPath(_) => Cow::Borrowed(":"),
             Entry::CustomPart { separator, .. } => Cow::Owned(separator.clone()),
-            // Other flags are space separated.
+            Entry::Annotated { entry, .. } => entry.separator(),
+            Entry::Unknown(_) => match self.unknown_field("separator") {
+                Some(sep) => Cow::Owned(sep.to_string()),
+                None => Cow::Borrowed(":"),
+            },
+            // Scalars have no parts to separate.
             _ => Cow::Borrowed(" "),
         }
     }
@@ -148,8 +717,9 @@ mod tests {
         assert_eq!(
             Entry::Path(PathEntry {
                 path: "/opt/bin".to_string(),
-                program: "tool".to_string(),
-                version: "1".to_string(),
+                program: Some("tool".to_string()),
+                version: Some("1".to_string()),
+                mode: None
             })
             .separator()
             .as_ref(),
@@ -195,6 +765,255 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pinned_mode_is_optional_in_stored_json() {
+        let old: Entry =
+            serde_json::from_str(r#"{"Path":{"path":"/opt/bin","program":"","version":""}}"#)
+                .unwrap();
+        assert_eq!(old.pinned_mode(), None);
+        assert!(!serde_json::to_string(&old).unwrap().contains("mode"));
+
+        let mut part: Entry = serde_json::from_str(
+            r#"{"CustomPart":{"name":"L","value":"a","separator":",","mode":"Append"}}"#,
+        )
+        .unwrap();
+        assert_eq!(part.pinned_mode(), Some(OperationMode::Append));
+        assert_eq!(part.to_string(), "L: a [append]");
+        assert!(part.set_pinned_mode(None));
+        assert!(!Entry::CFlag("-O2".to_string()).set_pinned_mode(Some(OperationMode::Append)));
+    }
+
+    #[test]
+    fn disabled_parts_round_trip_and_enabled_ones_stay_plain() {
+        let old = r#"{"CFlag":"-O2"}"#;
+        let part: Entry = serde_json::from_str(old).unwrap();
+        assert!(part.is_enabled());
+
+        let off = part.with_enabled(false);
+        assert!(!off.is_enabled());
+        assert_eq!(off.var_name(), "CFLAGS");
+        assert_eq!(off.to_string(), "CFLAGS: -O2 [disabled]");
+        let json = serde_json::to_string(&off).unwrap();
+        assert_eq!(
+            json,
+            r#"{"Annotated":{"entry":{"CFlag":"-O2"},"enabled":false}}"#
+        );
+        let back: Entry = serde_json::from_str(&json).unwrap();
+        assert!(!back.is_enabled());
+        assert_eq!(
+            serde_json::to_string(&back.with_enabled(true)).unwrap(),
+            old
+        );
+    }
+
+    #[test]
+    fn comments_round_trip_next_to_the_enabled_flag() {
+        let part = Entry::LDFlag("-Wl,--no-as-needed".to_string())
+            .with_comment(Some(" libfoo registers plugins at load time ".to_string()));
+        assert_eq!(
+            part.comment(),
+            Some("libfoo registers plugins at load time")
+        );
+        assert!(part.is_enabled());
+        let json = serde_json::to_string(&part).unwrap();
+        assert_eq!(
+            json,
+            r#"{"Annotated":{"entry":{"LDFlag":"-Wl,--no-as-needed"},"comment":"libfoo registers plugins at load time"}}"#
+        );
+
+        // Toggling keeps the note, removing the note keeps the toggle.
+        let off = part.with_enabled(false);
+        assert_eq!(off.comment(), Some("libfoo registers plugins at load time"));
+        let off = off.with_comment(Some("  ".to_string()));
+        assert_eq!(off.comment(), None);
+        assert!(!off.is_enabled());
+        assert_eq!(
+            serde_json::to_string(&off.with_enabled(true)).unwrap(),
+            r#"{"LDFlag":"-Wl,--no-as-needed"}"#
+        );
+    }
+
+    #[test]
+    fn unknown_variants_are_kept_as_stored() {
+        let json = r#"{"Wrap":{"flavor":"lto","name":"CFLAGS","value":"-O2"}}"#;
+        let entry: Entry = serde_json::from_str(json).unwrap();
+        assert_eq!(entry.unknown_kind(), Some("Wrap"));
+        assert_eq!(entry.var_name(), "CFLAGS");
+        assert_eq!(entry.to_string(), "CFLAGS: (unsupported entry)");
+        assert!(entry.is_enabled() && !entry.is_exported());
+        assert_eq!(serde_json::to_string(&entry).unwrap(), json);
+
+        let off = entry.with_enabled(false);
+        let back: Entry = serde_json::from_str(&serde_json::to_string(&off).unwrap()).unwrap();
+        assert_eq!(back.unknown_kind(), Some("Wrap"));
+        assert!(!back.is_enabled());
+
+        // Broken known variants are still errors, so repair can drop them.
+        assert!(serde_json::from_str::<Entry>(r#"{"CFlag":5}"#).is_err());
+        assert!(serde_json::from_str::<Entry>(r#""CFlag""#).is_err());
+    }
+
+    #[test]
+    fn stored_entries_keep_their_names_separators_and_json() {
+        let fixture = include_str!("testdata/entries.json");
+        let entries: Vec<Entry> = serde_json::from_str(fixture).unwrap();
+        let seen: Vec<(String, String, String)> = entries
+            .iter()
+            .map(|e| {
+                (
+                    e.var_name().into_owned(),
+                    e.separator().into_owned(),
+                    e.to_string(),
+                )
+            })
+            .collect();
+        let expected = [
+            ("PATH", ":", "PATH: /opt/gcc-13/bin (gcc 13)"),
+            ("PATH", ":", "PATH: ~/bin [append]"),
+            ("CPATH", ":", "CPATH: /opt/include"),
+            ("C_INCLUDE_PATH", ":", "C_INCLUDE_PATH: /opt/c/include"),
+            (
+                "CPLUS_INCLUDE_PATH",
+                ":",
+                "CPLUS_INCLUDE_PATH: /opt/c++/include",
+            ),
+            (
+                "OBJC_INCLUDE_PATH",
+                ":",
+                "OBJC_INCLUDE_PATH: /opt/objc/include",
+            ),
+            ("CPPFLAGS", " ", "CPPFLAGS: -DNDEBUG"),
+            ("CFLAGS", " ", "CFLAGS: -O2"),
+            ("CXXFLAGS", " ", "CXXFLAGS: -std=c++20"),
+            ("LDFLAGS", " ", "LDFLAGS: -Wl,--as-needed"),
+            ("LIBRARY_PATH", ":", "LIBRARY_PATH: /opt/lib"),
+            ("LD_LIBRARY_PATH", ":", "LD_LIBRARY_PATH: /opt/lib64"),
+            ("LD_RUN_PATH", ":", "LD_RUN_PATH: /opt/rpath"),
+            ("RANLIB", " ", "RANLIB: gcc-ranlib"),
+            ("CC", " ", "CC: gcc"),
+            ("CXX", " ", "CXX: g++"),
+            ("AR", " ", "AR: gcc-ar"),
+            ("STRIP", " ", "STRIP: strip"),
+            ("GCC_EXEC_PREFIX", " ", "GCC_EXEC_PREFIX: /opt/gcc/lib/"),
+            ("COLLECT_GCC_OPTIONS", " ", "COLLECT_GCC_OPTIONS: -v"),
+            ("LANG", " ", "LANG: C.UTF-8"),
+            ("SYSROOT", " ", "SYSROOT: /srv/arm"),
+            ("PKG_CONFIG_PATH", ":", "PKG_CONFIG_PATH: /opt/pc"),
+            ("TOOLS", ",", "TOOLS: b [replace]"),
+            ("CFLAGS", " ", "CFLAGS: -g [disabled]"),
+            ("CCACHE_DIR", " ", "unset CCACHE_DIR"),
+        ];
+        let expected: Vec<(String, String, String)> = expected
+            .iter()
+            .map(|(v, s, d)| (v.to_string(), s.to_string(), d.to_string()))
+            .collect();
+        assert_eq!(seen, expected);
+
+        // Saving writes back exactly what was read.
+        let stored: serde_json::Value = serde_json::from_str(fixture).unwrap();
+        assert_eq!(serde_json::to_value(&entries).unwrap(), stored);
+    }
+
+    #[test]
+    fn builtins_build_the_entries_they_always_did() {
+        let fixture: Vec<serde_json::Value> =
+            serde_json::from_str(include_str!("testdata/entries.json")).unwrap();
+        // One plain entry per builtin var with a variant, after the two PATH ones.
+        for stored in &fixture[2..21] {
+            let entry: Entry = serde_json::from_value(stored.clone()).unwrap();
+            let value = crate::export::entry_value(&entry);
+            let built = make_entry(&entry.var_name(), value, &[]);
+            assert_eq!(&serde_json::to_value(&built).unwrap(), stored);
+        }
+        let pkg = make_entry("PKG_CONFIG_PATH", "/opt/pc".to_string(), &[]);
+        assert_eq!(serde_json::to_value(&pkg).unwrap(), fixture[22]);
+        for var in BUILTIN_VARS {
+            let entry = make_entry(var.name, "x".to_string(), &[]);
+            assert_eq!(entry.var_name(), var.name);
+            if var.kind == VarKind::List {
+                assert_eq!(entry.separator(), var.separator);
+            }
+        }
+    }
+
+    #[test]
+    fn builtins_shadow_custom_definitions_of_the_same_name() {
+        let def = |name: &str| CustomVarDef::new(name, VarKind::Scalar, "");
+        let defs = [def("MANPATH"), def("TOOLCHAIN")];
+        let opts = var_options(&defs, &SeparatorOverrides::new());
+        assert_eq!(opts.len(), BUILTIN_VARS.len() + 1);
+        assert_eq!(opts.last().map(|o| o.name.as_str()), Some("TOOLCHAIN"));
+
+        let manpath = lookup_var_option("MANPATH", &defs).unwrap();
+        assert_eq!(
+            (manpath.kind, manpath.separator.as_str()),
+            (VarKind::List, ":")
+        );
+        let custom = lookup_var_option("TOOLCHAIN", &defs).unwrap();
+        assert_eq!(custom.editor, EditorStyle::Single);
+        assert!(lookup_var_option("TOOLCHAIN", &[]).is_none());
+    }
+
+    #[test]
+    fn variant_list_names_every_variant() {
+        let mut entries: Vec<Entry> = builtin_var_options(&SeparatorOverrides::new())
+            .iter()
+            .map(|o| make_entry(&o.name, "x".to_string(), &[]))
+            .collect();
+        entries.push(Entry::CustomScalar {
+            name: "X".to_string(),
+            value: "x".to_string(),
+        });
+        entries.push(Entry::CFlag("x".to_string()).with_enabled(false));
+        entries.push(Entry::Unset {
+            name: "X".to_string(),
+        });
+        let mut kinds: Vec<String> = entries
+            .iter()
+            .map(|e| match serde_json::to_value(e).unwrap() {
+                serde_json::Value::Object(map) => map.keys().next().unwrap().clone(),
+                other => panic!("unexpected {other}"),
+            })
+            .collect();
+        kinds.sort();
+        kinds.dedup();
+        let mut known: Vec<&str> = ENTRY_VARIANTS.to_vec();
+        known.sort();
+        assert_eq!(kinds, known);
+    }
+
+    #[test]
+    fn path_metadata_is_optional_and_old_empty_strings_load_as_unset() {
+        let old: Entry =
+            serde_json::from_str(r#"{"Path":{"path":"/opt/x/bin","program":"","version":""}}"#)
+                .unwrap();
+        let Entry::Path(pe) = &old else {
+            panic!("expected a PATH entry");
+        };
+        assert_eq!((pe.program.as_deref(), pe.version.as_deref()), (None, None));
+        assert_eq!(old.to_string(), "PATH: /opt/x/bin");
+        assert_eq!(
+            serde_json::to_string(&old).unwrap(),
+            r#"{"Path":{"path":"/opt/x/bin"}}"#
+        );
+
+        let bare: Entry = serde_json::from_str(r#"{"Path":{"path":"/opt/x/bin"}}"#).unwrap();
+        assert_eq!(bare.to_string(), "PATH: /opt/x/bin");
+
+        let tool = |program: Option<&str>, version: Option<&str>| {
+            Entry::Path(PathEntry {
+                path: "/opt/gcc/bin".to_string(),
+                program: program.map(str::to_string),
+                version: version.map(str::to_string),
+                mode: None,
+            })
+            .to_string()
+        };
+        assert_eq!(tool(Some("gcc"), Some("13")), "PATH: /opt/gcc/bin (gcc 13)");
+        assert_eq!(tool(Some("gcc"), None), "PATH: /opt/gcc/bin (gcc)");
+        assert_eq!(tool(None, Some("13")), "PATH: /opt/gcc/bin (13)");
+    }
+
     #[test]
     fn separator_is_space_for_flags() {
         assert_eq!(
@@ -214,42 +1033,100 @@ mod tests {
             " "
         );
     }
+
+    #[test]
+    fn time_ago_picks_the_largest_unit() {
+        assert_eq!(time_ago(1000, 1030), "just now");
+        assert_eq!(time_ago(0, 60), "1 minute ago");
+        assert_eq!(time_ago(0, 7300), "2 hours ago");
+        assert_eq!(time_ago(0, 3 * 86_400 + 5), "3 days ago");
+        // Clock skew never produces negative ages.
+        assert_eq!(time_ago(100, 0), "just now");
+    }
 }
 
 impl std::fmt::Display for Entry {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Entry::Path(pe) => write!(f, "PATH: {} ({} {})", pe.path, pe.program, pe.version),
-            Entry::CPath(s) => write!(f, "CPATH: {}", s),
-            Entry::CInclude(s) => write!(f, "C_INCLUDE_PATH: {}", s),
-            Entry::CPlusInclude(s) => write!(f, "CPLUS_INCLUDE_PATH: {}", s),
-            Entry::OBJCInclude(s) => write!(f, "OBJC_INCLUDE_PATH: {}", s),
-            Entry::CPPFlag(s) => write!(f, "CPPFLAGS: {}", s),
-            Entry::CFlag(s) => write!(f, "CFLAGS: {}", s),
-            Entry::CXXFlag(s) => write!(f, "CXXFLAGS: {}", s),
-            Entry::LDFlag(s) => write!(f, "LDFLAGS: {}", s),
-            Entry::LibraryPath(s) => write!(f, "LIBRARY_PATH: {}", s),
-            Entry::LDLibraryPath(s) => write!(f, "LD_LIBRARY_PATH: {}", s),
-            Entry::LDRunPath(s) => write!(f, "LD_RUN_PATH: {}", s),
-            Entry::RanLib(s) => write!(f, "RANLIB: {}", s),
-            Entry::CC(s) => write!(f, "CC: {}", s),
-            Entry::CXX(s) => write!(f, "CXX: {}", s),
-            Entry::AR(s) => write!(f, "AR: {}", s),
-            Entry::Strip(s) => write!(f, "STRIP: {}", s),
-            Entry::GCCExecPrefix(s) => write!(f, "GCC_EXEC_PREFIX: {}", s),
-            Entry::CollectGCCOptions(s) => write!(f, "COLLECT_GCC_OPTIONS: {}", s),
-            Entry::Lang(s) => write!(f, "LANG: {}", s),
+        self.plain().fmt_value(f)?;
+        if let Some(mode) = self.pinned_mode() {
+            write!(f, " [{}]", mode.name())?;
+        }
+        if !self.is_enabled() {
+            write!(f, " [disabled]")?;
+        }
+        Ok(())
+    }
+}
 
+impl Entry {
+    fn fmt_value(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Entry::Path(pe) => match pe.label() {
+                Some(label) => write!(f, "PATH: {} ({label})", pe.path),
+                None => write!(f, "PATH: {}", pe.path),
+            },
             Entry::CustomScalar { name, value } => write!(f, "{}: {}", name, value),
             Entry::CustomPart { name, value, .. } => write!(f, "{}: {}", name, value),
+            Entry::Annotated { entry, .. } => entry.fmt_value(f),
+            Entry::Unset { name } => write!(f, "unset {}", name),
+            Entry::Unknown(_) => write!(f, "{}: (unsupported entry)", self.var_name()),
+            string_builtin_entry!() => {
+                let (name, value) = self.builtin().expect("builtin entries have a builtin var");
+                write!(f, "{}: {}", name, value)
+            }
         }
     }
 }
 
-/// An environment profile holds a name and a list of entries.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Builds the entry for one part (or the whole value, for scalars) of `var_name`.
+///
+/// Builtin names map onto their dedicated variants; other vars are looked up among
+/// the builtin definitions, then in `custom_defs`, and unknown names are treated
+/// as list parts joined with ':'.
+pub fn make_entry(var_name: &str, value: String, custom_defs: &[CustomVarDef]) -> Entry {
+    if let Some(entry) = Entry::for_builtin(var_name, &value) {
+        return entry;
+    }
+
+    if let Some(def) = lookup_var_option(var_name, custom_defs) {
+        return match def.kind {
+            VarKind::Scalar => Entry::CustomScalar {
+                name: def.name.clone(),
+                value,
+            },
+            VarKind::List => Entry::CustomPart {
+                name: def.name.clone(),
+                value,
+                separator: def.separator.clone(),
+                mode: None,
+            },
+        };
+    }
+
+    Entry::CustomPart {
+        name: var_name.to_string(),
+        value,
+        separator: ":".to_string(),
+        mode: None,
+    }
+}
+
+/// An environment profile holds a name, an optional note on what it is for, the
+/// export mode used when none is asked for and a list of entries.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct EnvProfile {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_mode: Option<OperationMode>,
+    /// The profile this one inherits from; its entries come before this
+    /// profile's own on export.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// Free-form labels to group and filter profiles by.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
     pub entries: Vec<Entry>,
 }
 
@@ -257,7 +1134,96 @@ impl EnvProfile {
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
+            description: None,
+            default_mode: None,
+            extends: None,
+            tags: Vec::new(),
             entries: Vec::new(),
         }
     }
+
+    /// Renames every custom entry of `old` to `new`. Returns the number of
+    /// entries changed.
+    pub fn rename_custom_var(&mut self, old: &str, new: &str) -> usize {
+        let mut touched = 0;
+        for entry in &mut self.entries {
+            if let Entry::CustomScalar { name, .. } | Entry::CustomPart { name, .. } =
+                entry.plain_mut()
+            {
+                if name == old {
+                    *name = new.to_string();
+                    touched += 1;
+                }
+            }
+        }
+        touched
+    }
+}
+
+/// Bookkeeping stored next to a profile. Times are unix seconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileMeta {
+    pub name: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub entry_count: usize,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// A profile part that was created by dropping catalog item `item_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemLink {
+    pub item_id: i64,
+    pub profile: String,
+    pub var: String,
+    pub value: String,
+}
+
+/// One recorded change of a profile: what was done, when, and the profile before
+/// and after (`None` where it did not exist).
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub at: i64,
+    pub action: String,
+    pub before: Option<EnvProfile>,
+    pub after: Option<EnvProfile>,
+}
+
+/// A stored profile whose entries cannot be read, with the reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokenProfile {
+    pub name: String,
+    pub error: String,
+}
+
+/// The UTC date and time of `unix_secs` as (year, month, day, hour, minute,
+/// second).
+pub fn utc_date_time(unix_secs: i64) -> (i64, i64, i64, i64, i64, i64) {
+    let (days, secs) = (unix_secs.div_euclid(86_400), unix_secs.rem_euclid(86_400));
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// `then` relative to `now` in words, e.g. "3 days ago".
+pub fn time_ago(then: i64, now: i64) -> String {
+    let secs = (now - then).max(0);
+    let (n, unit) = match secs {
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86_399 => (secs / 3600, "hour"),
+        _ => (secs / 86_400, "day"),
+    };
+    let plural = if n == 1 { "" } else { "s" };
+    format!("{n} {unit}{plural} ago")
 }
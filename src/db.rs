@@ -1,20 +1,190 @@
-use crate::config::{CatalogItem, CustomVarDef, Entry, EnvProfile, ItemKind, VarKind};
-use anyhow::Result;
-use rusqlite::{params, types::Type, Connection};
+use crate::config::{
+    BrokenProfile, CatalogItem, CustomVarDef, Entry, EnvProfile, HistoryEntry, ItemKind, ItemLink,
+    PathEntry, ProfileMeta, SeparatorOverrides, VarKind,
+};
+use crate::error::{suggest, BathError, Result};
+use crate::export::OperationMode;
+use rusqlite::backup::Backup;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::collections::HashMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
 
-/// Open (or create) the SQLite database.
+/// Env var overriding the database location (the `--db` flag takes precedence).
+pub const DB_ENV: &str = "BATH_DB";
+
+static DB_PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Makes every later `establish_connection` use `path` (set from `--db`).
+pub fn set_db_path(path: PathBuf) {
+    let _ = DB_PATH_OVERRIDE.set(path);
+}
+
+/// Picks the database path: flag > `BATH_DB` > the XDG data location if it
+/// exists > a legacy `~/.bath.db` if that exists > the XDG data location.
+///
+/// A legacy database keeps being used until `bath migrate-db` copies it over.
+pub fn resolve_db_path(
+    flag: Option<&Path>,
+    env_db: Option<&str>,
+    home: Option<&str>,
+    xdg_data_home: Option<&str>,
+) -> Result<PathBuf> {
+    if let Some(path) = flag {
+        return Ok(path.to_path_buf());
+    }
+    if let Some(path) = env_db.filter(|p| !p.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+    let xdg = xdg_db_path(home, xdg_data_home);
+    if let Some(path) = xdg.as_ref().filter(|p| p.exists()) {
+        return Ok(path.clone());
+    }
+    if let Some(legacy) = legacy_db_path(home).filter(|p| p.exists()) {
+        return Ok(legacy);
+    }
+    xdg.ok_or(BathError::NoDatabasePath)
+}
+
+/// `~/.bath.db`, where bath kept its database before using the XDG location.
+pub fn legacy_db_path(home: Option<&str>) -> Option<PathBuf> {
+    home.filter(|h| !h.is_empty())
+        .map(|h| PathBuf::from(h).join(".bath.db"))
+}
+
+/// `$XDG_DATA_HOME/bath/bath.db`, or `~/.local/share/bath/bath.db` when
+/// `XDG_DATA_HOME` is unset or not absolute.
+pub fn xdg_db_path(home: Option<&str>, xdg_data_home: Option<&str>) -> Option<PathBuf> {
+    let data = match xdg_data_home.filter(|d| Path::new(d).is_absolute()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(home.filter(|h| !h.is_empty())?).join(".local/share"),
+    };
+    Some(data.join("bath").join("bath.db"))
+}
+
+/// Path of the database this process uses.
+pub fn db_path() -> Result<PathBuf> {
+    resolve_db_path(
+        DB_PATH_OVERRIDE.get().map(PathBuf::as_path),
+        env::var(DB_ENV).ok().as_deref(),
+        env::var("HOME").ok().as_deref(),
+        env::var("XDG_DATA_HOME").ok().as_deref(),
+    )
+}
+
+/// Open (or create) the SQLite database, creating its directory if needed.
 pub fn establish_connection() -> Result<Connection> {
-    let home = env::var("HOME").expect("HOME environment variable not set");
+    let path = db_path()?;
+    create_parent_dir(&path)?;
+    establish_connection_at(&path)
+}
+
+fn create_parent_dir(path: &Path) -> Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !dir.exists() => {
+            std::fs::create_dir_all(dir).map_err(|e| BathError::file("create", dir, e))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Copies the legacy database at `legacy` to `dest` and leaves a
+/// `<legacy>.moved` note next to it. The legacy file is kept, but once `dest`
+/// exists it is no longer used.
+pub fn migrate_legacy_db(legacy: &Path, dest: &Path) -> Result<()> {
+    create_parent_dir(dest)?;
+    let conn = establish_connection_at(legacy)?;
+    backup_to(&conn, dest)?;
+    let note = PathBuf::from(format!("{}.moved", legacy.display()));
+    let text = format!(
+        "bath now keeps its database at {}.\n{} is no longer used and can be removed.\n",
+        dest.display(),
+        legacy.display()
+    );
+    std::fs::write(&note, text).map_err(|e| BathError::file("write", &note, e))
+}
+
+/// How long a connection waits for another bath process to finish writing before
+/// giving up with `DatabaseLocked`.
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
 
-    let conn = Connection::open(PathBuf::from(home).join(".bath.db"))?;
+/// Open (or create) the SQLite database at `path`.
+///
+/// The database is switched to WAL mode so readers (e.g. `bath export`) never block
+/// on a writer (e.g. the TUI), and writers wait up to `BUSY_TIMEOUT` for each other.
+pub fn establish_connection_at(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
     initialize_db(&conn)?;
     Ok(conn)
 }
 
-/// Create the profiles table if it does not exist.
+/// Brings the schema up to date, see `migrate`.
 pub fn initialize_db(conn: &Connection) -> Result<()> {
+    migrate(conn)
+}
+
+/// A schema upgrade, run inside the transaction that also bumps `user_version`.
+type Migration = fn(&Connection) -> Result<()>;
+
+/// Schema upgrades in order: migration `n` (1-based) takes a database from
+/// `PRAGMA user_version` `n - 1` to `n`. Only ever append to this list.
+const MIGRATIONS: &[Migration] = &[
+    migration_1_initial_schema,
+    migration_2_profile_entries,
+    migration_3_profile_timestamps,
+    migration_4_profile_descriptions,
+    migration_5_profile_positions,
+    migration_6_soft_delete,
+    migration_7_items_by_value,
+    migration_8_item_usage,
+    migration_9_item_search,
+    migration_10_item_links,
+    migration_11_profile_default_mode,
+    migration_12_history,
+    migration_13_entry_enabled,
+    migration_14_entry_comments,
+    migration_15_profile_extends,
+    migration_16_profile_tags,
+    migration_17_custom_var_defaults,
+    migration_18_entry_priority,
+    migration_19_separator_overrides,
+];
+
+/// The schema version this binary writes.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// The stored schema version (0 for databases that predate migrations).
+pub fn schema_version(conn: &Connection) -> Result<u32> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+/// Applies every migration newer than the stored schema version, each in its
+/// own transaction. A database written by a newer bath is refused rather than
+/// read with a schema we do not understand.
+pub fn migrate(conn: &Connection) -> Result<()> {
+    let version = schema_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(BathError::SchemaTooNew {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.unchecked_transaction()?;
+        migration(&tx)?;
+        tx.pragma_update(None, "user_version", i as u32 + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// The tables as they existed before migrations. `IF NOT EXISTS` lets databases
+/// created back then (version 0, tables present) pass through unchanged.
+fn migration_1_initial_schema(conn: &Connection) -> Result<()> {
     conn.execute(
         "CREATE TABLE IF NOT EXISTS profiles (
             id INTEGER PRIMARY KEY,
@@ -45,64 +215,776 @@ pub fn initialize_db(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
-pub fn save_item(conn: &Connection, item: &mut CatalogItem) -> Result<()> {
-    let kind = match item.kind {
+/// One row per profile entry, so a part edit rewrites only its own rows and SQL
+/// can search values. The JSON `entries` column is still written as a fallback
+/// for older binaries.
+fn migration_2_profile_entries(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE profile_entries (
+            profile_id INTEGER NOT NULL REFERENCES profiles(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            var_name TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            value TEXT NOT NULL,
+            program TEXT,
+            version TEXT,
+            separator TEXT,
+            mode TEXT,
+            PRIMARY KEY (profile_id, position)
+        );
+        CREATE INDEX profile_entries_value ON profile_entries (value);",
+    )?;
+    let mut stmt = conn.prepare("SELECT id, entries FROM profiles")?;
+    let profiles: Vec<(i64, Option<String>)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    // Inserted with this migration's columns only: `sync_entry_rows` writes the
+    // latest schema, which later migrations have yet to add.
+    for (id, json) in profiles {
+        let entries: Vec<Entry> = serde_json::from_str(json.as_deref().unwrap_or("[]"))?;
+        for (i, entry) in entries.iter().enumerate() {
+            let row = EntryRow::from_entry(entry)?;
+            conn.execute(
+                "INSERT INTO profile_entries
+                    (profile_id, position, var_name, kind, value, program, version, separator,
+                     mode)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    id,
+                    (i as i64 + 1) * POSITION_GAP,
+                    row.var_name,
+                    row.kind,
+                    row.value,
+                    row.program,
+                    row.version,
+                    row.separator,
+                    row.mode
+                ],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// `created_at` / `updated_at` (unix seconds) on profiles. Existing profiles get
+/// the time of the upgrade, the best we know.
+fn migration_3_profile_timestamps(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE profiles ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE profiles ADD COLUMN updated_at INTEGER NOT NULL DEFAULT 0;",
+    )?;
+    let now = unix_now();
+    conn.execute(
+        "UPDATE profiles SET created_at = ?1, updated_at = ?1",
+        [now],
+    )?;
+    Ok(())
+}
+
+/// Free-form note on what a profile is for.
+fn migration_4_profile_descriptions(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE profiles ADD COLUMN description TEXT;")?;
+    Ok(())
+}
+
+/// Manual ordering of profiles. Existing profiles keep their alphabetical order.
+fn migration_5_profile_positions(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE profiles ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+         UPDATE profiles SET position =
+            (SELECT COUNT(*) FROM profiles p WHERE p.name < profiles.name);",
+    )?;
+    Ok(())
+}
+
+/// `deleted_at` marks profiles in the trash. Names only need to be unique among
+/// live profiles, so the table is rebuilt without the column-level UNIQUE.
+fn migration_6_soft_delete(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE profiles_new (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            entries TEXT,
+            created_at INTEGER NOT NULL DEFAULT 0,
+            updated_at INTEGER NOT NULL DEFAULT 0,
+            description TEXT,
+            position INTEGER NOT NULL DEFAULT 0,
+            deleted_at INTEGER
+         );
+         INSERT INTO profiles_new (id, name, entries, created_at, updated_at, description, position)
+            SELECT id, name, entries, created_at, updated_at, description, position FROM profiles;
+         DROP TABLE profiles;
+         ALTER TABLE profiles_new RENAME TO profiles;
+         CREATE UNIQUE INDEX profiles_live_name ON profiles (name) WHERE deleted_at IS NULL;",
+    )?;
+    Ok(())
+}
+
+/// Lookup of items by value, used to merge duplicates on save.
+fn migration_7_items_by_value(conn: &Connection) -> Result<()> {
+    conn.execute_batch("CREATE INDEX IF NOT EXISTS items_kind_value ON items (kind, value);")?;
+    Ok(())
+}
+
+/// How often and when items were last dropped into a profile.
+fn migration_8_item_usage(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE items ADD COLUMN use_count INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE items ADD COLUMN last_used_at INTEGER;",
+    )?;
+    Ok(())
+}
+
+/// Full-text index over the item columns people search by, kept in sync by
+/// triggers. Usage updates do not touch it.
+fn migration_9_item_search(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE items_fts USING fts5(
+             value, program, version, tags, content = 'items', content_rowid = 'id'
+         );
+         CREATE TRIGGER items_fts_insert AFTER INSERT ON items BEGIN
+             INSERT INTO items_fts (rowid, value, program, version, tags)
+             VALUES (new.id, new.value, new.program, new.version, new.tags);
+         END;
+         CREATE TRIGGER items_fts_delete AFTER DELETE ON items BEGIN
+             INSERT INTO items_fts (items_fts, rowid, value, program, version, tags)
+             VALUES ('delete', old.id, old.value, old.program, old.version, old.tags);
+         END;
+         CREATE TRIGGER items_fts_update AFTER UPDATE OF value, program, version, tags ON items
+         BEGIN
+             INSERT INTO items_fts (items_fts, rowid, value, program, version, tags)
+             VALUES ('delete', old.id, old.value, old.program, old.version, old.tags);
+             INSERT INTO items_fts (rowid, value, program, version, tags)
+             VALUES (new.id, new.value, new.program, new.version, new.tags);
+         END;
+         INSERT INTO items_fts (items_fts) VALUES ('rebuild');",
+    )?;
+    Ok(())
+}
+
+/// Which profile parts came from which catalog item.
+fn migration_10_item_links(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE item_links (
+             item_id INTEGER NOT NULL,
+             profile_name TEXT NOT NULL,
+             var_name TEXT NOT NULL,
+             value TEXT NOT NULL,
+             UNIQUE (item_id, profile_name, var_name, value)
+         );
+         CREATE INDEX item_links_profile ON item_links (profile_name);",
+    )?;
+    Ok(())
+}
+
+/// The export mode a profile uses when none is passed; NULL means prepend.
+fn migration_11_profile_default_mode(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE profiles ADD COLUMN default_mode TEXT;")?;
+    Ok(())
+}
+
+/// Snapshots of profiles around each change made in the TUI, see `record_history`.
+fn migration_12_history(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE history (
+             id INTEGER PRIMARY KEY,
+             profile_name TEXT NOT NULL,
+             at INTEGER NOT NULL,
+             action TEXT NOT NULL,
+             before_json TEXT,
+             after_json TEXT
+         );
+         CREATE INDEX history_profile ON history (profile_name, id);",
+    )?;
+    Ok(())
+}
+
+/// Parts switched off in the TUI stay stored, flagged with `enabled = 0`.
+fn migration_13_entry_enabled(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE profile_entries ADD COLUMN enabled INTEGER NOT NULL DEFAULT 1;",
+    )?;
+    Ok(())
+}
+
+/// The note attached to a part, NULL for none.
+fn migration_14_entry_comments(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE profile_entries ADD COLUMN comment TEXT;")?;
+    Ok(())
+}
+
+/// The name of the profile a profile inherits from, NULL for none.
+fn migration_15_profile_extends(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE profiles ADD COLUMN extends TEXT;")?;
+    Ok(())
+}
+
+/// The tags of a profile as a JSON array, NULL for none.
+fn migration_16_profile_tags(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE profiles ADD COLUMN tags TEXT;")?;
+    Ok(())
+}
+
+/// The value a custom var falls back to, NULL for none.
+fn migration_17_custom_var_defaults(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE custom_vars ADD COLUMN default_value TEXT;")?;
+    Ok(())
+}
+
+/// The export priority of a part, NULL for none.
+fn migration_18_entry_priority(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE profile_entries ADD COLUMN priority INTEGER;")?;
+    Ok(())
+}
+
+/// Separators the user chose for builtin list vars.
+fn migration_19_separator_overrides(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE separator_overrides (
+            name TEXT PRIMARY KEY,
+            separator TEXT NOT NULL
+        );",
+    )?;
+    Ok(())
+}
+
+/// The `tags` column for `tags`: NULL when there are none, so saving an
+/// untagged profile does not count as a change.
+fn tags_column(tags: &[String]) -> Result<Option<String>> {
+    Ok(match tags {
+        [] => None,
+        tags => Some(serde_json::to_string(tags)?),
+    })
+}
+
+/// Stored profile tags. Only bath writes the column, so anything it cannot
+/// parse counts as no tags.
+fn stored_tags(value: Option<String>) -> Vec<String> {
+    value
+        .and_then(|t| serde_json::from_str(&t).ok())
+        .unwrap_or_default()
+}
+
+/// A stored `default_mode`. Only bath writes the column, so anything it cannot
+/// parse is treated as unset.
+fn stored_mode(value: Option<String>) -> Option<OperationMode> {
+    value.and_then(|m| m.parse().ok())
+}
+
+/// Current time in unix seconds.
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Distance between the positions of consecutive entries, leaving room to insert
+/// entries without renumbering their neighbours.
+const POSITION_GAP: i64 = 1024;
+
+/// An entry flattened into the columns of `profile_entries`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct EntryRow {
+    var_name: String,
+    /// The serde variant name, e.g. `Path` or `CFlag`.
+    kind: String,
+    value: String,
+    program: Option<String>,
+    version: Option<String>,
+    separator: Option<String>,
+    mode: Option<String>,
+    enabled: bool,
+    comment: Option<String>,
+    priority: Option<i64>,
+}
+
+impl EntryRow {
+    fn from_entry(entry: &Entry) -> Result<Self> {
+        let (enabled, comment) = (entry.is_enabled(), entry.comment().map(str::to_string));
+        let priority = entry.priority();
+        let entry = entry.plain();
+        let kind = match serde_json::to_value(entry)? {
+            serde_json::Value::Object(map) => map.keys().next().cloned().unwrap_or_default(),
+            _ => String::new(),
+        };
+        let mut row = EntryRow {
+            var_name: entry.var_name().into_owned(),
+            kind,
+            value: crate::export::entry_value(entry),
+            program: None,
+            version: None,
+            separator: None,
+            mode: entry.pinned_mode().map(|m| m.name().to_string()),
+            enabled,
+            comment,
+            priority,
+        };
+        match entry {
+            Entry::Path(pe) => {
+                row.program = pe.program.clone();
+                row.version = pe.version.clone();
+            }
+            Entry::CustomPart { separator, .. } => row.separator = Some(separator.clone()),
+            // Entries from a newer bath are normally rows it wrote itself; other
+            // payloads are kept whole as JSON, with no `var_name` to tell them apart.
+            Entry::Unknown(raw) => {
+                let payload = raw.as_object().and_then(|m| m.values().next());
+                if payload.is_some_and(fits_entry_columns) {
+                    let field = |key| entry.unknown_field(key).map(str::to_string);
+                    row.program = field("program");
+                    row.version = field("version");
+                    row.separator = field("separator");
+                    row.mode = field("mode");
+                } else {
+                    row.var_name = String::new();
+                    row.value = payload.map(|p| p.to_string()).unwrap_or_default();
+                }
+            }
+            _ => {}
+        }
+        Ok(row)
+    }
+
+    fn into_entry(self) -> Result<Entry> {
+        if !crate::config::ENTRY_VARIANTS.contains(&self.kind.as_str()) {
+            return Ok(self.into_unknown_entry());
+        }
+        let mode = self.mode.map(|m| m.parse()).transpose()?;
+        let (enabled, comment, priority) = (self.enabled, self.comment, self.priority);
+        let entry: Entry = match self.kind.as_str() {
+            "Path" => Entry::Path(PathEntry {
+                path: self.value,
+                program: self.program.as_deref().and_then(crate::config::non_empty),
+                version: self.version.as_deref().and_then(crate::config::non_empty),
+                mode,
+            }),
+            "CustomScalar" => Entry::CustomScalar {
+                name: self.var_name,
+                value: self.value,
+            },
+            "CustomPart" => Entry::CustomPart {
+                name: self.var_name,
+                value: self.value,
+                separator: self.separator.unwrap_or_default(),
+                mode,
+            },
+            "Unset" => Entry::Unset {
+                name: self.var_name,
+            },
+            kind => serde_json::from_value(serde_json::json!({ kind: self.value }))?,
+        };
+        Ok(entry
+            .with_enabled(enabled)
+            .with_comment(comment)
+            .with_priority(priority))
+    }
+
+    /// The reverse of `from_entry` for a kind this version does not know.
+    fn into_unknown_entry(self) -> Entry {
+        let extras = [
+            ("program", self.program),
+            ("version", self.version),
+            ("separator", self.separator),
+            ("mode", self.mode),
+        ];
+        let whole = (self.var_name.is_empty() && extras.iter().all(|(_, v)| v.is_none()))
+            .then(|| serde_json::from_str(&self.value).ok())
+            .flatten();
+        let payload = whole.unwrap_or_else(|| {
+            let mut fields = serde_json::Map::new();
+            fields.insert("name".to_string(), self.var_name.into());
+            fields.insert("value".to_string(), self.value.into());
+            for (key, value) in extras {
+                if let Some(value) = value {
+                    fields.insert(key.to_string(), value.into());
+                }
+            }
+            serde_json::Value::Object(fields)
+        });
+        Entry::Unknown(serde_json::json!({ self.kind: payload }))
+            .with_enabled(self.enabled)
+            .with_comment(self.comment)
+            .with_priority(self.priority)
+    }
+}
+
+/// Whether the payload of an entry from a newer bath can be stored in the
+/// columns of a row: a `name` and a `value`, plus only the other string columns.
+fn fits_entry_columns(payload: &serde_json::Value) -> bool {
+    const COLUMNS: [&str; 6] = ["name", "value", "program", "version", "separator", "mode"];
+    let Some(fields) = payload.as_object() else {
+        return false;
+    };
+    let named = fields
+        .get("name")
+        .and_then(serde_json::Value::as_str)
+        .is_some_and(|name| !name.is_empty());
+    named
+        && fields.contains_key("value")
+        && fields
+            .iter()
+            .all(|(key, value)| COLUMNS.contains(&key.as_str()) && value.is_string())
+}
+
+/// The stored rows of a profile with their positions, in order.
+fn load_entry_rows(conn: &Connection, profile_id: i64) -> Result<Vec<(i64, EntryRow)>> {
+    let mut stmt = conn.prepare(
+        "SELECT position, var_name, kind, value, program, version, separator, mode, enabled,
+                comment, priority
+         FROM profile_entries WHERE profile_id = ?1 ORDER BY position",
+    )?;
+    let rows = stmt.query_map([profile_id], |row| {
+        Ok((
+            row.get(0)?,
+            EntryRow {
+                var_name: row.get(1)?,
+                kind: row.get(2)?,
+                value: row.get(3)?,
+                program: row.get(4)?,
+                version: row.get(5)?,
+                separator: row.get(6)?,
+                mode: row.get(7)?,
+                enabled: row.get(8)?,
+                comment: row.get(9)?,
+                priority: row.get(10)?,
+            },
+        ))
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+fn insert_entry_row(
+    conn: &Connection,
+    profile_id: i64,
+    position: i64,
+    row: &EntryRow,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO profile_entries
+            (profile_id, position, var_name, kind, value, program, version, separator, mode,
+             enabled, comment, priority)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            profile_id,
+            position,
+            row.var_name,
+            row.kind,
+            row.value,
+            row.program,
+            row.version,
+            row.separator,
+            row.mode,
+            row.enabled,
+            row.comment,
+            row.priority
+        ],
+    )?;
+    Ok(())
+}
+
+/// `count` increasing positions strictly between `lo` and `hi` (either may be
+/// open), or None if they do not fit.
+fn positions_between(lo: Option<i64>, hi: Option<i64>, count: usize) -> Option<Vec<i64>> {
+    let count = count as i64;
+    match (lo, hi) {
+        (None, None) => Some((1..=count).map(|j| j * POSITION_GAP).collect()),
+        (Some(lo), None) => Some((1..=count).map(|j| lo + j * POSITION_GAP).collect()),
+        (None, Some(hi)) => Some(
+            (1..=count)
+                .map(|j| hi - (count + 1 - j) * POSITION_GAP)
+                .collect(),
+        ),
+        (Some(lo), Some(hi)) if hi - lo > count => Some(
+            (1..=count)
+                .map(|j| lo + (hi - lo) * j / (count + 1))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// Makes the stored rows of a profile match `entries`, touching only what
+/// changed: the unchanged rows before and after the edited stretch keep their
+/// positions, edited rows are updated in place and added ones are slotted in
+/// between. Only when there is no room left is the profile renumbered.
+fn sync_entry_rows(conn: &Connection, profile_id: i64, entries: &[Entry]) -> Result<()> {
+    let old = load_entry_rows(conn, profile_id)?;
+    let new: Vec<EntryRow> = entries
+        .iter()
+        .map(EntryRow::from_entry)
+        .collect::<Result<_>>()?;
+
+    let prefix = old
+        .iter()
+        .zip(&new)
+        .take_while(|((_, o), n)| o == *n)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|((_, o), n)| o == *n)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    for ((position, o), n) in old_mid.iter().zip(new_mid) {
+        if o != n {
+            conn.execute(
+                "UPDATE profile_entries SET var_name = ?3, kind = ?4, value = ?5, program = ?6,
+                    version = ?7, separator = ?8, mode = ?9, enabled = ?10,
+                    comment = ?11, priority = ?12
+                 WHERE profile_id = ?1 AND position = ?2",
+                params![
+                    profile_id,
+                    position,
+                    n.var_name,
+                    n.kind,
+                    n.value,
+                    n.program,
+                    n.version,
+                    n.separator,
+                    n.mode,
+                    n.enabled,
+                    n.comment,
+                    n.priority
+                ],
+            )?;
+        }
+    }
+    for (position, _) in old_mid.iter().skip(new_mid.len()) {
+        conn.execute(
+            "DELETE FROM profile_entries WHERE profile_id = ?1 AND position = ?2",
+            params![profile_id, position],
+        )?;
+    }
+    let added = new_mid.get(old_mid.len()..).unwrap_or_default();
+    if added.is_empty() {
+        return Ok(());
+    }
+    let lo = old[..prefix + old_mid.len()].last().map(|(p, _)| *p);
+    let hi = old.get(old.len() - suffix).map(|(p, _)| *p);
+    match positions_between(lo, hi, added.len()) {
+        Some(positions) => {
+            for (position, row) in positions.into_iter().zip(added) {
+                insert_entry_row(conn, profile_id, position, row)?;
+            }
+        }
+        None => {
+            conn.execute(
+                "DELETE FROM profile_entries WHERE profile_id = ?1",
+                [profile_id],
+            )?;
+            for (i, row) in new.iter().enumerate() {
+                insert_entry_row(conn, profile_id, (i as i64 + 1) * POSITION_GAP, row)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The entries of a profile from its rows. A profile without rows falls back to
+/// the JSON column, which is all an older bath writes.
+fn load_entries(conn: &Connection, profile_id: i64, json: Option<&str>) -> Result<Vec<Entry>> {
+    let rows = load_entry_rows(conn, profile_id)?;
+    if rows.is_empty() {
+        return Ok(serde_json::from_str(json.unwrap_or("[]"))?);
+    }
+    rows.into_iter().map(|(_, row)| row.into_entry()).collect()
+}
+
+fn item_kind_name(kind: &ItemKind) -> &'static str {
+    match kind {
         ItemKind::Text => "text",
         ItemKind::Path => "path",
-    };
-    let tags_json = serde_json::to_string(&item.tags)?;
+    }
+}
+
+/// Adds the tags of `from` missing in `into`, keeping the order of `into`.
+fn union_tags(into: &mut Vec<String>, from: &[String]) {
+    for tag in from {
+        if !into.contains(tag) {
+            into.push(tag.clone());
+        }
+    }
+}
+
+/// Saves an item. A new item with the kind and value of an existing one is merged
+/// into it (its tags are added, the program/version fill gaps) unless
+/// `allow_duplicate` is set; `item` then becomes the merged row and true is
+/// returned.
+pub fn save_item(conn: &Connection, item: &mut CatalogItem, allow_duplicate: bool) -> Result<bool> {
+    let kind = item_kind_name(&item.kind);
     if let Some(id) = item.id {
+        let tags_json = serde_json::to_string(&item.tags)?;
         conn.execute(
             "UPDATE items SET kind = ?1, value = ?2, program = ?3, version = ?4, tags = ?5 WHERE id = ?6",
             params![kind, item.value, item.program, item.version, tags_json, id],
         )?;
-        return Ok(());
+        return Ok(false);
+    }
+
+    if !allow_duplicate {
+        let existing = conn
+            .query_row(
+                &format!(
+                    "SELECT {ITEM_COLUMNS} FROM items WHERE kind = ?1 AND value = ?2 ORDER BY id LIMIT 1"
+                ),
+                params![kind, item.value],
+                item_from_row,
+            )
+            .optional()?;
+        if let Some(mut existing) = existing {
+            union_tags(&mut existing.tags, &item.tags);
+            existing.program = existing.program.or(item.program.take());
+            existing.version = existing.version.or(item.version.take());
+            save_item(conn, &mut existing, false)?;
+            *item = existing;
+            return Ok(true);
+        }
     }
 
+    let tags_json = serde_json::to_string(&item.tags)?;
     conn.execute(
         "INSERT INTO items (kind, value, program, version, tags) VALUES (?1, ?2, ?3, ?4, ?5)",
         params![kind, item.value, item.program, item.version, tags_json],
     )?;
     item.id = Some(conn.last_insert_rowid());
-    Ok(())
+    Ok(false)
+}
+
+/// Collapses items sharing a kind and value into the one with the lowest id,
+/// which gets the union of their tags and their combined usage. Returns how many
+/// rows were removed.
+pub fn dedup_items(conn: &Connection) -> Result<usize> {
+    let mut kept: Vec<CatalogItem> = Vec::new();
+    let mut dropped = Vec::new();
+    for item in load_items(conn)? {
+        match kept.iter_mut().find(|k| {
+            item_kind_name(&k.kind) == item_kind_name(&item.kind) && k.value == item.value
+        }) {
+            Some(first) => {
+                union_tags(&mut first.tags, &item.tags);
+                first.program = first.program.take().or(item.program);
+                first.version = first.version.take().or(item.version);
+                first.use_count += item.use_count;
+                first.last_used_at = first.last_used_at.max(item.last_used_at);
+                dropped.extend(item.id.map(|id| (id, first.id)));
+            }
+            None => kept.push(item),
+        }
+    }
+    if dropped.is_empty() {
+        return Ok(0);
+    }
+    atomically(conn, |tx| {
+        for (id, kept_id) in &dropped {
+            tx.execute(
+                "UPDATE OR IGNORE item_links SET item_id = ?2 WHERE item_id = ?1",
+                params![id, kept_id],
+            )?;
+            delete_item(tx, *id)?;
+        }
+        for item in &mut kept {
+            save_item(tx, item, true)?;
+            tx.execute(
+                "UPDATE items SET use_count = ?2, last_used_at = ?3 WHERE id = ?1",
+                params![item.id, item.use_count, item.last_used_at],
+            )?;
+        }
+        Ok(dropped.len())
+    })
+}
+
+/// Records that item `id` was just used (dropped into a profile). Returns the
+/// time stored as its `last_used_at`.
+pub fn touch_item(conn: &Connection, id: i64) -> Result<i64> {
+    let now = unix_now();
+    conn.execute(
+        "UPDATE items SET use_count = use_count + 1, last_used_at = ?2 WHERE id = ?1",
+        params![id, now],
+    )?;
+    Ok(now)
 }
 
+/// Deletes an item and its links.
 pub fn delete_item(conn: &Connection, id: i64) -> Result<()> {
-    conn.execute("DELETE FROM items WHERE id = ?1", params![id])?;
+    atomically(conn, |tx| {
+        tx.execute("DELETE FROM item_links WHERE item_id = ?1", params![id])?;
+        tx.execute("DELETE FROM items WHERE id = ?1", params![id])
+    })?;
     Ok(())
 }
 
+const ITEM_COLUMNS: &str = "items.id, items.kind, items.value, items.program, items.version, \
+                            items.tags, items.use_count, items.last_used_at";
+
 pub fn load_items(conn: &Connection) -> Result<Vec<CatalogItem>> {
-    let mut stmt =
-        conn.prepare("SELECT id, kind, value, program, version, tags FROM items ORDER BY id")?;
-    let rows = stmt.query_map([], |row| {
-        let id: i64 = row.get(0)?;
-        let kind_s: String = row.get(1)?;
-        let value: String = row.get(2)?;
-        let program: Option<String> = row.get(3)?;
-        let version: Option<String> = row.get(4)?;
-        let tags_json: String = row.get(5)?;
-        let kind = match kind_s.as_str() {
-            "text" => ItemKind::Text,
-            "path" => ItemKind::Path,
-            _ => ItemKind::Text,
-        };
-        let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
-        Ok(CatalogItem {
-            id: Some(id),
-            kind,
-            value,
-            program,
-            version,
-            tags,
-        })
-    })?;
+    let mut stmt = conn.prepare(&format!("SELECT {ITEM_COLUMNS} FROM items ORDER BY id"))?;
+    let rows = stmt.query_map([], item_from_row)?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
 
-    let mut out = Vec::new();
-    for r in rows {
-        out.push(r?);
+/// Items matching every word of `query` as a prefix of a word in their value,
+/// program, version or tags, best match first. An empty query matches nothing.
+pub fn search_items(conn: &Connection, query: &str) -> Result<Vec<CatalogItem>> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|w| format!("\"{}\"*", w.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ITEM_COLUMNS} FROM items_fts JOIN items ON items.id = items_fts.rowid
+         WHERE items_fts MATCH ?1 ORDER BY rank, items.id"
+    ))?;
+    let rows = stmt.query_map([terms.join(" ")], item_from_row)?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+fn item_from_row(row: &rusqlite::Row) -> rusqlite::Result<CatalogItem> {
+    let id: i64 = row.get(0)?;
+    let kind_s: String = row.get(1)?;
+    let value: String = row.get(2)?;
+    let program: Option<String> = row.get(3)?;
+    let version: Option<String> = row.get(4)?;
+    let tags_json: String = row.get(5)?;
+    let kind = match kind_s.as_str() {
+        "text" => ItemKind::Text,
+        "path" => ItemKind::Path,
+        _ => ItemKind::Text,
+    };
+    let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
+    Ok(CatalogItem {
+        id: Some(id),
+        kind,
+        value,
+        program,
+        version,
+        tags,
+        use_count: row.get(6)?,
+        last_used_at: row.get(7)?,
+    })
+}
+
+/// Runs `f` as one unit: if it fails, nothing it wrote is kept. Uses a savepoint,
+/// so calls nest (the db functions that write several statements use it too).
+pub fn atomically<T, E: From<rusqlite::Error>>(
+    conn: &Connection,
+    f: impl FnOnce(&Connection) -> std::result::Result<T, E>,
+) -> std::result::Result<T, E> {
+    conn.execute_batch("SAVEPOINT bath_atomically")?;
+    match f(conn) {
+        Ok(value) => {
+            conn.execute_batch("RELEASE bath_atomically")?;
+            Ok(value)
+        }
+        Err(e) => {
+            conn.execute_batch("ROLLBACK TO bath_atomically; RELEASE bath_atomically")?;
+            Err(e)
+        }
     }
-    Ok(out)
 }
 
 pub fn save_custom_var_def(conn: &Connection, def: &CustomVarDef) -> Result<()> {
@@ -111,18 +993,22 @@ pub fn save_custom_var_def(conn: &Connection, def: &CustomVarDef) -> Result<()>
         VarKind::List => "list",
     };
     conn.execute(
-        "INSERT OR REPLACE INTO custom_vars (name, kind, separator) VALUES (?1, ?2, ?3)",
-        params![def.name, kind, def.separator],
+        "INSERT INTO custom_vars (name, kind, separator, default_value) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (name) DO UPDATE SET kind = excluded.kind, separator = excluded.separator,
+            default_value = excluded.default_value",
+        params![def.name, kind, def.separator, def.default_value],
     )?;
     Ok(())
 }
 
 pub fn load_custom_var_defs(_conn: &Connection) -> Result<Vec<CustomVarDef>> {
-    let mut stmt = _conn.prepare("SELECT name, kind, separator FROM custom_vars ORDER BY name")?;
+    let mut stmt = _conn
+        .prepare("SELECT name, kind, separator, default_value FROM custom_vars ORDER BY name")?;
     let rows = stmt.query_map([], |row| {
         let name: String = row.get(0)?;
         let kind_s: String = row.get(1)?;
         let separator: String = row.get(2)?;
+        let default_value: Option<String> = row.get(3)?;
         let kind = match kind_s.as_str() {
             "scalar" => VarKind::Scalar,
             "list" => VarKind::List,
@@ -132,6 +1018,7 @@ pub fn load_custom_var_defs(_conn: &Connection) -> Result<Vec<CustomVarDef>> {
             name,
             kind,
             separator,
+            default_value,
         })
     })?;
 
@@ -142,71 +1029,1486 @@ pub fn load_custom_var_defs(_conn: &Connection) -> Result<Vec<CustomVarDef>> {
     Ok(defs)
 }
 
-/// Save (or update) a profile.
+/// The separators of builtin list vars the user overrode, by var name.
+pub fn load_separator_overrides(conn: &Connection) -> Result<SeparatorOverrides> {
+    let mut stmt = conn.prepare("SELECT name, separator FROM separator_overrides")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Joins the builtin list var `name` with `separator`, or with its own
+/// separator again for `None`. Returns false if that changed nothing.
+pub fn set_separator_override(
+    conn: &Connection,
+    name: &str,
+    separator: Option<&str>,
+) -> Result<bool> {
+    let changed = match separator {
+        Some(sep) => conn.execute(
+            "INSERT INTO separator_overrides (name, separator) VALUES (?1, ?2)
+             ON CONFLICT (name) DO UPDATE SET separator = excluded.separator
+             WHERE separator != excluded.separator",
+            params![name, sep],
+        )?,
+        None => conn.execute(
+            "DELETE FROM separator_overrides WHERE name = ?1",
+            params![name],
+        )?,
+    };
+    Ok(changed > 0)
+}
+
+/// Delete a custom var definition. Returns false if no such definition existed.
+/// Profile entries using the var are kept; they show up as undefined.
+pub fn delete_custom_var_def(conn: &Connection, name: &str) -> Result<bool> {
+    let deleted = conn.execute("DELETE FROM custom_vars WHERE name = ?1", params![name])?;
+    Ok(deleted > 0)
+}
+
+/// Rename a custom var definition along with the entries of every profile using
+/// it, those in the trash included, in one transaction. Returns false (and
+/// changes nothing) if no definition named `old_name` exists; fails if
+/// `new_name` is already defined.
+pub fn rename_custom_var_def(conn: &Connection, old_name: &str, new_name: &str) -> Result<bool> {
+    let taken: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM custom_vars WHERE name = ?1",
+        [new_name],
+        |row| row.get(0),
+    )?;
+    if taken > 0 && old_name != new_name {
+        return Err(BathError::InvalidArgument(format!(
+            "custom var {new_name} is already defined"
+        )));
+    }
+    atomically(conn, |tx| {
+        let updated = tx.execute(
+            "UPDATE custom_vars SET name = ?1 WHERE name = ?2",
+            params![new_name, old_name],
+        )?;
+        if updated == 0 {
+            return Ok(false);
+        }
+        tx.execute(
+            "UPDATE item_links SET var_name = ?1 WHERE var_name = ?2",
+            params![new_name, old_name],
+        )?;
+        for name in profiles_using_custom_var(tx, old_name)? {
+            let mut profile = load_profile(tx, &name)?;
+            profile.rename_custom_var(old_name, new_name);
+            save_profile(tx, &profile)?;
+        }
+        // Deleted profiles keep their `updated_at`, so they are renamed in place.
+        tx.execute(
+            "UPDATE profile_entries SET var_name = ?1
+             WHERE var_name = ?2 AND kind IN ('CustomPart', 'CustomScalar')
+                AND profile_id IN (SELECT id FROM profiles WHERE deleted_at IS NOT NULL)",
+            params![new_name, old_name],
+        )?;
+        Ok(true)
+    })
+}
+
+/// Names of the profiles that have at least one custom entry for `var_name`.
+pub fn profiles_using_custom_var(conn: &Connection, var_name: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT p.name FROM profiles p JOIN profile_entries e ON e.profile_id = p.id
+         WHERE e.kind IN ('CustomPart', 'CustomScalar') AND e.var_name = ?1
+            AND p.deleted_at IS NULL
+         ORDER BY p.name",
+    )?;
+    let rows = stmt.query_map(params![var_name], |row| row.get(0))?;
+    let mut names = Vec::new();
+    for r in rows {
+        names.push(r?);
+    }
+    Ok(names)
+}
+
+/// Save (or update) a profile. New profiles go after all others. Only the entry
+/// rows that changed are written, and `updated_at` only moves if the entries, the
+/// description, the default mode, the parent or the tags did. The row is updated in place, so
+/// its id never changes.
 pub fn save_profile(conn: &Connection, profile: &EnvProfile) -> Result<()> {
     let entries_json = serde_json::to_string(&profile.entries)?;
+    atomically(conn, |tx| {
+        let id: i64 = tx.query_row(
+            "INSERT INTO profiles
+            (name, entries, created_at, updated_at, description, default_mode, extends,
+             tags, position)
+         VALUES (?1, ?2, ?3, ?3, ?4, ?5, ?6, ?7,
+                 (SELECT COALESCE(MAX(position) + 1, 0) FROM profiles))
+         ON CONFLICT (name) WHERE deleted_at IS NULL DO UPDATE SET
+            entries = excluded.entries,
+            description = excluded.description,
+            default_mode = excluded.default_mode,
+            extends = excluded.extends,
+            tags = excluded.tags,
+            updated_at = CASE
+                WHEN profiles.entries IS excluded.entries
+                    AND profiles.description IS excluded.description
+                    AND profiles.default_mode IS excluded.default_mode
+                    AND profiles.extends IS excluded.extends
+                    AND profiles.tags IS excluded.tags
+                THEN profiles.updated_at ELSE excluded.updated_at END
+         RETURNING id",
+            params![
+                profile.name,
+                entries_json,
+                unix_now(),
+                profile.description,
+                profile.default_mode.map(OperationMode::name),
+                profile.extends,
+                tags_column(&profile.tags)?
+            ],
+            |row| row.get(0),
+        )?;
+        sync_entry_rows(tx, id, &profile.entries)?;
+        drop_stale_item_links(tx, id, &profile.name)
+    })
+}
+
+/// Forgets the item links of `profile_name` whose part is gone. Links carry no
+/// entry id, so a part counts as still there while the profile has an entry with
+/// the link's var and value; a part that was edited loses its link.
+fn drop_stale_item_links(conn: &Connection, profile_id: i64, profile_name: &str) -> Result<()> {
     conn.execute(
-        "INSERT OR REPLACE INTO profiles (name, entries) VALUES (?1, ?2)",
-        params![profile.name, entries_json],
+        "DELETE FROM item_links WHERE profile_name = ?2 AND NOT EXISTS (
+             SELECT 1 FROM profile_entries e
+             WHERE e.profile_id = ?1 AND e.var_name = item_links.var_name
+                AND e.value = item_links.value
+         )",
+        params![profile_id, profile_name],
     )?;
     Ok(())
 }
 
-/// Rename a profile without leaving stale rows behind.
-pub fn rename_profile(conn: &Connection, old_name: &str, new_name: &str) -> Result<()> {
-    let updated = conn.execute(
-        "UPDATE profiles SET name = ?1 WHERE name = ?2",
-        params![new_name, old_name],
+/// Records that the part `value` of `var` in `profile` came from item `item_id`.
+pub fn link_item(
+    conn: &Connection,
+    item_id: i64,
+    profile: &str,
+    var: &str,
+    value: &str,
+) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO item_links (item_id, profile_name, var_name, value)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![item_id, profile, var, value],
     )?;
-    if updated == 0 {
-        anyhow::bail!("profile not found: {old_name}");
-    }
     Ok(())
 }
 
-/// Load a profile by name.
-pub fn load_profile(conn: &Connection, name: &str) -> Result<EnvProfile> {
-    let mut stmt = conn.prepare("SELECT name, entries FROM profiles WHERE name = ?1")?;
-    let profile = stmt.query_row([name], |row| {
-        let name: String = row.get(0)?;
-        let entries_json: String = row.get(1)?;
-        let entries: Vec<Entry> = serde_json::from_str(&entries_json)
-            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(1, Type::Text, Box::new(e)))?;
-        Ok(EnvProfile { name, entries })
+/// The profile parts created from item `item_id`, by profile and var.
+pub fn item_links(conn: &Connection, item_id: i64) -> Result<Vec<ItemLink>> {
+    let mut stmt = conn.prepare(
+        "SELECT item_id, profile_name, var_name, value FROM item_links
+         WHERE item_id = ?1 ORDER BY profile_name, var_name, value",
+    )?;
+    let rows = stmt.query_map([item_id], |row| {
+        Ok(ItemLink {
+            item_id: row.get(0)?,
+            profile: row.get(1)?,
+            var: row.get(2)?,
+            value: row.get(3)?,
+        })
     })?;
-    Ok(profile)
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
 }
 
-/// Load all profiles from the database.
-pub fn load_all_profiles(conn: &Connection) -> Result<Vec<EnvProfile>> {
-    let mut stmt = conn.prepare("SELECT name, entries FROM profiles")?;
-    let profile_iter = stmt.query_map([], |row| {
-        let name: String = row.get(0)?;
-        let entries_json: String = row.get(1)?;
-        let entries: Vec<Entry> = serde_json::from_str(&entries_json)
-            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))?;
-        Ok(EnvProfile { name, entries })
-    })?;
-    let mut profiles = Vec::new();
-    for profile in profile_iter {
-        profiles.push(profile?);
+/// How many changes `record_history` keeps per profile; older ones are pruned.
+pub const HISTORY_LIMIT: i64 = 50;
+
+/// Appends a change of `profile` to its history and prunes it to the newest
+/// `HISTORY_LIMIT` entries. Changes that leave the profile as it was are skipped.
+pub fn record_history(
+    conn: &Connection,
+    profile: &str,
+    action: &str,
+    before: Option<&EnvProfile>,
+    after: Option<&EnvProfile>,
+) -> Result<()> {
+    let before = before.map(serde_json::to_string).transpose()?;
+    let after = after.map(serde_json::to_string).transpose()?;
+    if before == after {
+        return Ok(());
     }
-    Ok(profiles)
+    conn.execute(
+        "INSERT INTO history (profile_name, at, action, before_json, after_json)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![profile, unix_now(), action, before, after],
+    )?;
+    conn.execute(
+        "DELETE FROM history WHERE profile_name = ?1 AND id NOT IN (
+             SELECT id FROM history WHERE profile_name = ?1 ORDER BY id DESC LIMIT ?2
+         )",
+        params![profile, HISTORY_LIMIT],
+    )?;
+    Ok(())
 }
 
-/// Delete a profile by name.
-pub fn delete_profile(conn: &Connection, name: &str) -> Result<()> {
-    conn.execute("DELETE FROM profiles WHERE name = ?1", params![name])?;
-    Ok(())
+/// `save_profile`, recording the change from the stored version (if any) in the
+/// profile's history under `action`.
+pub fn save_profile_logged(conn: &Connection, profile: &EnvProfile, action: &str) -> Result<()> {
+    atomically(conn, |tx| {
+        let before = match load_profile(tx, &profile.name) {
+            Ok(p) => Some(p),
+            Err(BathError::ProfileNotFound { .. }) => None,
+            Err(e) => return Err(e),
+        };
+        save_profile(tx, profile)?;
+        record_history(tx, &profile.name, action, before.as_ref(), Some(profile))
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// The recorded changes of `profile`, newest first.
+pub fn load_history(conn: &Connection, profile: &str) -> Result<Vec<HistoryEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, at, action, before_json, after_json FROM history
+         WHERE profile_name = ?1 ORDER BY id DESC",
+    )?;
+    type Row = (i64, i64, String, Option<String>, Option<String>);
+    let rows: Vec<Row> = stmt
+        .query_map([profile], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    let snapshot = |json: Option<String>| -> Result<Option<EnvProfile>> {
+        Ok(json.map(|j| serde_json::from_str(&j)).transpose()?)
+    };
+    rows.into_iter()
+        .map(|(id, at, action, before, after)| {
+            Ok(HistoryEntry {
+                id,
+                at,
+                action,
+                before: snapshot(before)?,
+                after: snapshot(after)?,
+            })
+        })
+        .collect()
+}
 
-    #[test]
-    fn items_roundtrip_insert_load_update_delete() -> Result<()> {
-        let conn = Connection::open_in_memory()?;
+/// Puts `profile` back the way it was before change `id` of its history (undoing
+/// that change and every later one), recreating it if it was deleted. The
+/// rollback is recorded as a change itself, so it can be undone the same way.
+pub fn restore_from_history(conn: &Connection, profile: &str, id: i64) -> Result<EnvProfile> {
+    let entry = load_history(conn, profile)?
+        .into_iter()
+        .find(|e| e.id == id)
+        .ok_or_else(|| {
+            BathError::InvalidArgument(format!("profile '{profile}' has no change #{id}"))
+        })?;
+    let Some(mut restored) = entry.before else {
+        return Err(BathError::InvalidArgument(format!(
+            "change #{id} created profile '{profile}', there is nothing before it to restore"
+        )));
+    };
+    restored.name = profile.to_string();
+    save_profile_logged(conn, &restored, &format!("restore #{id}"))?;
+    Ok(restored)
+}
+
+/// Rename a profile without leaving stale rows behind. Profiles extending it
+/// follow the rename.
+pub fn rename_profile(conn: &Connection, old_name: &str, new_name: &str) -> Result<()> {
+    atomically(conn, |tx| {
+        let updated = tx.execute(
+            "UPDATE profiles SET name = ?1, updated_at = ?3 WHERE name = ?2 AND deleted_at IS NULL",
+            params![new_name, old_name, unix_now()],
+        )?;
+        if updated == 0 {
+            return Err(profile_not_found(tx, old_name));
+        }
+        tx.execute(
+            "UPDATE item_links SET profile_name = ?1 WHERE profile_name = ?2",
+            params![new_name, old_name],
+        )?;
+        tx.execute(
+            "UPDATE history SET profile_name = ?1 WHERE profile_name = ?2",
+            params![new_name, old_name],
+        )?;
+        tx.execute(
+            "UPDATE profiles SET extends = ?1 WHERE extends = ?2",
+            params![new_name, old_name],
+        )?;
+        Ok(())
+    })
+}
+
+/// Load a profile by name.
+pub fn load_profile(conn: &Connection, name: &str) -> Result<EnvProfile> {
+    let row = conn.query_row(
+        "SELECT id, name, entries, description, default_mode, extends, tags FROM profiles
+         WHERE name = ?1 AND deleted_at IS NULL",
+        [name],
+        |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, Option<String>>(5)?,
+                row.get::<_, Option<String>>(6)?,
+            ))
+        },
+    );
+    let (id, name, json, description, mode, extends, tags) = match row {
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(profile_not_found(conn, name)),
+        other => other?,
+    };
+    let entries = load_entries(conn, id, json.as_deref())?;
+    Ok(EnvProfile {
+        name,
+        description,
+        default_mode: stored_mode(mode),
+        extends,
+        tags: stored_tags(tags),
+        entries,
+    })
+}
+
+/// A `ProfileNotFound` error carrying the closest existing profile names.
+fn profile_not_found(conn: &Connection, name: &str) -> BathError {
+    let names: Vec<String> = conn
+        .prepare("SELECT name FROM profiles WHERE deleted_at IS NULL ORDER BY name")
+        .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+        .unwrap_or_default();
+    BathError::ProfileNotFound {
+        name: name.to_string(),
+        suggestions: suggest(name, &names),
+    }
+}
+
+/// Names, descriptions, entry counts and timestamps of all profiles, in their
+/// manual order.
+pub fn load_profile_metas(conn: &Connection) -> Result<Vec<ProfileMeta>> {
+    let mut stmt = conn.prepare(
+        "SELECT p.name, COUNT(e.position), p.created_at, p.updated_at, p.description, p.tags
+         FROM profiles p LEFT JOIN profile_entries e ON e.profile_id = p.id
+         WHERE p.deleted_at IS NULL
+         GROUP BY p.id ORDER BY p.position, p.name",
+    )?;
+    let metas = stmt.query_map([], |row| {
+        Ok(ProfileMeta {
+            name: row.get(0)?,
+            entry_count: row.get(1)?,
+            created_at: row.get(2)?,
+            updated_at: row.get(3)?,
+            description: row.get(4)?,
+            tags: stored_tags(row.get(5)?),
+        })
+    })?;
+    Ok(metas.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Bookkeeping of one profile, see `load_profile_metas`.
+#[cfg(test)]
+pub fn load_profile_meta(conn: &Connection, name: &str) -> Result<ProfileMeta> {
+    load_profile_metas(conn)?
+        .into_iter()
+        .find(|m| m.name == name)
+        .ok_or_else(|| profile_not_found(conn, name))
+}
+
+/// Returns true if a profile with the given name exists.
+pub fn profile_exists(conn: &Connection, name: &str) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM profiles WHERE name = ?1 AND deleted_at IS NULL",
+        [name],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+/// All live profiles whose entries can be read, in their manual order. The others
+/// are skipped; see `load_profiles_checked`.
+pub fn load_all_profiles(conn: &Connection) -> Result<Vec<EnvProfile>> {
+    Ok(load_profiles_checked(conn)?.0)
+}
+
+/// All profiles in their manual order, split into the readable ones and those
+/// whose entries fail to deserialize. Only database errors fail the whole load.
+pub fn load_profiles_checked(conn: &Connection) -> Result<(Vec<EnvProfile>, Vec<BrokenProfile>)> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, entries, description, default_mode, extends, tags FROM profiles
+         WHERE deleted_at IS NULL ORDER BY position, name",
+    )?;
+    type Row = (
+        i64,
+        String,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+    );
+    let rows: Vec<Row> = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+                row.get(6)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut profiles = Vec::new();
+    let mut broken = Vec::new();
+    for (id, name, json, description, mode, extends, tags) in rows {
+        match load_entries(conn, id, json.as_deref()) {
+            Ok(entries) => profiles.push(EnvProfile {
+                name,
+                description,
+                default_mode: stored_mode(mode),
+                extends,
+                tags: stored_tags(tags),
+                entries,
+            }),
+            Err(e @ (BathError::Json(_) | BathError::InvalidArgument(_))) => {
+                broken.push(BrokenProfile {
+                    name,
+                    error: e.to_string(),
+                })
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((profiles, broken))
+}
+
+/// Re-saves a profile whose entries cannot be read with the entries that still
+/// parse on their own (each stored row, or each element of the JSON array).
+/// Returns how many entries were kept and how many were dropped.
+pub fn repair_profile(conn: &Connection, name: &str) -> Result<(usize, usize)> {
+    let row = conn.query_row(
+        "SELECT id, entries, description, default_mode, extends, tags FROM profiles
+         WHERE name = ?1 AND deleted_at IS NULL",
+        [name],
+        |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, Option<String>>(5)?,
+            ))
+        },
+    );
+    let (id, json, description, mode, extends, tags) = match row {
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(profile_not_found(conn, name)),
+        other => other?,
+    };
+    if load_entries(conn, id, json.as_deref()).is_ok() {
+        return Err(BathError::InvalidArgument(format!(
+            "profile '{name}' is readable, nothing to repair"
+        )));
+    }
+
+    let rows = load_entry_rows(conn, id)?;
+    let parsed: Vec<Result<Entry>> = if rows.is_empty() {
+        serde_json::from_str::<Vec<serde_json::Value>>(json.as_deref().unwrap_or("[]"))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|v| Ok(serde_json::from_value(v)?))
+            .collect()
+    } else {
+        rows.into_iter().map(|(_, row)| row.into_entry()).collect()
+    };
+    let total = parsed.len();
+    let entries: Vec<Entry> = parsed.into_iter().filter_map(|e| e.ok()).collect();
+    let kept = entries.len();
+    save_profile(
+        conn,
+        &EnvProfile {
+            name: name.to_string(),
+            description,
+            default_mode: stored_mode(mode),
+            extends,
+            tags: stored_tags(tags),
+            entries,
+        },
+    )?;
+    Ok((kept, total - kept))
+}
+
+/// Exchanges the places of two profiles in the manual order.
+pub fn swap_profile_positions(conn: &Connection, a: &str, b: &str) -> Result<()> {
+    atomically(conn, |tx| {
+        let position = |name: &str| -> Result<i64> {
+            match tx.query_row(
+                "SELECT position FROM profiles WHERE name = ?1 AND deleted_at IS NULL",
+                [name],
+                |row| row.get(0),
+            ) {
+                Err(rusqlite::Error::QueryReturnedNoRows) => Err(profile_not_found(conn, name)),
+                other => Ok(other?),
+            }
+        };
+        let (pa, pb) = (position(a)?, position(b)?);
+        tx.execute(
+            "UPDATE profiles SET position = ?1 WHERE name = ?2 AND deleted_at IS NULL",
+            params![pb, a],
+        )?;
+        tx.execute(
+            "UPDATE profiles SET position = ?1 WHERE name = ?2 AND deleted_at IS NULL",
+            params![pa, b],
+        )?;
+        Ok(())
+    })
+}
+
+/// Stores `names` as the manual order; profiles not listed keep their place after
+/// them.
+pub fn set_profile_order(conn: &Connection, names: &[String]) -> Result<()> {
+    atomically(conn, |tx| {
+        tx.execute(
+            "UPDATE profiles SET position = position + ?1",
+            [names.len() as i64],
+        )?;
+        for (position, name) in names.iter().enumerate() {
+            tx.execute(
+                "UPDATE profiles SET position = ?1 WHERE name = ?2 AND deleted_at IS NULL",
+                params![position as i64, name],
+            )?;
+        }
+        Ok(())
+    })
+}
+
+/// Copy the entries of `src` into a new profile named `dst` and return it.
+///
+/// Fails if `dst` already exists, unless `overwrite` is set.
+pub fn copy_profile(
+    conn: &Connection,
+    src: &str,
+    dst: &str,
+    overwrite: bool,
+) -> Result<EnvProfile> {
+    if src == dst {
+        return Err(BathError::InvalidArgument(format!(
+            "cannot copy profile '{src}' onto itself"
+        )));
+    }
+    let source = load_profile(conn, src)?;
+    if !overwrite && profile_exists(conn, dst)? {
+        return Err(BathError::ProfileExists(dst.to_string()));
+    }
+    let copy = EnvProfile {
+        name: dst.to_string(),
+        description: source.description,
+        default_mode: source.default_mode,
+        extends: source.extends,
+        tags: source.tags,
+        entries: source.entries,
+    };
+    save_profile(conn, &copy)?;
+    Ok(copy)
+}
+
+/// Move a profile to the trash. It keeps its entries and can be brought back with
+/// `recover_profile` until purged. Fails while other profiles extend it.
+pub fn delete_profile(conn: &Connection, name: &str) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM profiles WHERE extends = ?1 AND deleted_at IS NULL ORDER BY name",
+    )?;
+    let children: Vec<String> = stmt
+        .query_map([name], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    if !children.is_empty() {
+        return Err(BathError::InvalidArgument(format!(
+            "profile '{name}' is extended by {}; give them another parent first",
+            children.join(", ")
+        )));
+    }
+    conn.execute(
+        "UPDATE profiles SET deleted_at = ?2 WHERE name = ?1 AND deleted_at IS NULL",
+        params![name, unix_now()],
+    )?;
+    Ok(())
+}
+
+/// Names in the trash, most recently deleted first.
+pub fn deleted_profile_names(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM profiles WHERE deleted_at IS NOT NULL
+         ORDER BY deleted_at DESC, id DESC",
+    )?;
+    let names = stmt.query_map([], |row| row.get(0))?;
+    Ok(names.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Takes the most recently deleted profile called `name` out of the trash, as
+/// `new_name` if given. Fails if a live profile already has the name.
+pub fn recover_profile(conn: &Connection, name: &str, new_name: Option<&str>) -> Result<()> {
+    let target = new_name.unwrap_or(name);
+    let id: i64 = match conn.query_row(
+        "SELECT id FROM profiles WHERE name = ?1 AND deleted_at IS NOT NULL
+         ORDER BY deleted_at DESC, id DESC LIMIT 1",
+        [name],
+        |row| row.get(0),
+    ) {
+        Err(rusqlite::Error::QueryReturnedNoRows) if profile_exists(conn, name)? => {
+            return Err(BathError::InvalidArgument(format!(
+                "profile '{name}' is not deleted"
+            )))
+        }
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            return Err(BathError::ProfileNotFound {
+                name: name.to_string(),
+                suggestions: suggest(name, &deleted_profile_names(conn)?),
+            })
+        }
+        other => other?,
+    };
+    if profile_exists(conn, target)? {
+        return Err(BathError::ProfileExists(target.to_string()));
+    }
+    conn.execute(
+        "UPDATE profiles SET deleted_at = NULL, name = ?2, updated_at = ?3 WHERE id = ?1",
+        params![id, target, unix_now()],
+    )?;
+    Ok(())
+}
+
+/// Removes deleted profiles for good: all of them, or those deleted at least
+/// `older_than` seconds ago. Returns the purged names.
+pub fn purge_deleted_profiles(conn: &Connection, older_than: Option<i64>) -> Result<Vec<String>> {
+    let cutoff = older_than.map_or(i64::MAX, |age| unix_now() - age);
+    atomically(conn, |tx| {
+        let mut stmt = tx.prepare(
+            "DELETE FROM profiles WHERE deleted_at IS NOT NULL AND deleted_at <= ?1
+             RETURNING id, name",
+        )?;
+        let purged: Vec<(i64, String)> = stmt
+            .query_map([cutoff], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        for (id, name) in &purged {
+            tx.execute("DELETE FROM profile_entries WHERE profile_id = ?1", [id])?;
+            if !profile_exists(tx, name)? {
+                tx.execute("DELETE FROM item_links WHERE profile_name = ?1", [name])?;
+                tx.execute("DELETE FROM history WHERE profile_name = ?1", [name])?;
+            }
+        }
+        Ok(purged.into_iter().map(|(_, name)| name).collect())
+    })
+}
+
+/// Copies the live database to `dest` page by page, which is safe while other
+/// connections (e.g. the TUI) have it open. An existing `dest` is not overwritten.
+pub fn backup_to(conn: &Connection, dest: &Path) -> Result<()> {
+    if dest.exists() {
+        return Err(BathError::InvalidArgument(format!(
+            "{} already exists",
+            dest.display()
+        )));
+    }
+    let mut target = Connection::open(dest)?;
+    Backup::new(conn, &mut target)?.run_to_completion(256, Duration::from_millis(20), None)?;
+    Ok(())
+}
+
+/// Default backup file next to the database: `bath-backup-YYYYMMDD-HHMMSS.db` (UTC).
+pub fn default_backup_path(db: &Path, unix_secs: i64) -> PathBuf {
+    let (year, month, day, hour, minute, second) = crate::config::utc_date_time(unix_secs);
+    db.with_file_name(format!(
+        "bath-backup-{year:04}{month:02}{day:02}-{hour:02}{minute:02}{second:02}.db"
+    ))
+}
+
+/// Opens a backup read-only and copies it into memory, brought up to the current
+/// schema, without touching the file. Fails unless it is a bath database this
+/// version can read.
+pub fn open_backup(src: &Path) -> Result<Connection> {
+    let not_bath =
+        || BathError::InvalidArgument(format!("{} is not a bath database", src.display()));
+    if !src.is_file() {
+        return Err(BathError::InvalidArgument(format!(
+            "{} does not exist",
+            src.display()
+        )));
+    }
+    let source = Connection::open_with_flags(src, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let has_profiles: bool = source
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'profiles'",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .map_err(|_| not_bath())?
+        > 0;
+    if !has_profiles {
+        return Err(not_bath());
+    }
+    let found = schema_version(&source)?;
+    if found > SCHEMA_VERSION {
+        return Err(BathError::SchemaTooNew {
+            found,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    let mut copy = Connection::open_in_memory()?;
+    Backup::new(&source, &mut copy)?.run_to_completion(256, Duration::ZERO, None)?;
+    migrate(&copy)?;
+    Ok(copy)
+}
+
+/// Replaces everything in `conn` with the contents of the backup at `src`.
+pub fn restore_from(conn: &mut Connection, src: &Path) -> Result<()> {
+    let backup = open_backup(src)?;
+    Backup::new(&backup, conn)?.run_to_completion(256, Duration::from_millis(20), None)?;
+    Ok(())
+}
+
+/// Imports the profiles of the backup at `src` next to the existing ones, plus the
+/// custom var definitions missing here. A profile whose name is taken is imported
+/// as `name-imported` (or `name-imported-2`, ...). Returns (backup name, new name)
+/// per imported profile. Fails, importing nothing, if any backup profile's
+/// entries cannot be read.
+pub fn merge_from(conn: &Connection, src: &Path) -> Result<Vec<(String, String)>> {
+    let backup = open_backup(src)?;
+    let (profiles, broken) = load_profiles_checked(&backup)?;
+    if !broken.is_empty() {
+        let names: Vec<&str> = broken.iter().map(|b| b.name.as_str()).collect();
+        return Err(BathError::InvalidArgument(format!(
+            "the entries of profile(s) {} in the backup cannot be read",
+            names.join(", ")
+        )));
+    }
+    import_profiles(conn, profiles, &load_custom_var_defs(&backup)?)
+}
+
+/// Saves `profiles` next to the existing ones, renaming taken names as
+/// `merge_from` does, and adds the `defs` not defined here yet. Profiles
+/// extending one of the others follow its new name. Returns (original name,
+/// new name) per profile.
+pub fn import_profiles(
+    conn: &Connection,
+    profiles: Vec<EnvProfile>,
+    defs: &[CustomVarDef],
+) -> Result<Vec<(String, String)>> {
+    atomically(conn, |conn| {
+        let existing: Vec<String> = load_custom_var_defs(conn)?
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        for def in defs.iter().filter(|d| !existing.contains(&d.name)) {
+            save_custom_var_def(conn, def)?;
+        }
+        let mut imported: Vec<(String, String)> = Vec::new();
+        for profile in &profiles {
+            let original = profile.name.clone();
+            let mut candidate = original.clone();
+            let mut n = 1;
+            while profile_exists(conn, &candidate)?
+                || imported.iter().any(|(_, stored)| *stored == candidate)
+            {
+                candidate = match n {
+                    1 => format!("{original}-imported"),
+                    _ => format!("{original}-imported-{n}"),
+                };
+                n += 1;
+            }
+            imported.push((original, candidate));
+        }
+        let stored_as: HashMap<&str, &str> = imported
+            .iter()
+            .map(|(original, stored)| (original.as_str(), stored.as_str()))
+            .collect();
+        for (mut profile, (_, stored)) in profiles.into_iter().zip(&imported) {
+            profile.name = stored.clone();
+            if let Some(parent) = &mut profile.extends {
+                if let Some(new) = stored_as.get(parent.as_str()) {
+                    *parent = new.to_string();
+                }
+            }
+            save_profile(conn, &profile)?;
+        }
+        Ok(imported)
+    })
+}
+
+/// Deletes every profile (including deleted ones and their history), custom var
+/// definition and item. Separator overrides belong to the machine and stay.
+pub fn clear_database(conn: &Connection) -> Result<()> {
+    atomically(conn, |tx| {
+        tx.execute_batch(
+            "DELETE FROM profile_entries;
+             DELETE FROM item_links;
+             DELETE FROM history;
+             DELETE FROM profiles;
+             DELETE FROM custom_vars;
+             DELETE FROM items;",
+        )
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::entry_value;
+
+    #[test]
+    fn version_0_database_is_migrated_with_rows_kept() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("bath-v0-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            // What an older bath left behind: the tables, but no user_version.
+            let conn = Connection::open(&path)?;
+            conn.execute_batch(
+                "CREATE TABLE profiles (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, entries TEXT);
+                 INSERT INTO profiles (name, entries) VALUES ('old', '[{\"CC\":\"gcc\"}]');",
+            )?;
+        }
+
+        let conn = establish_connection_at(&path)?;
+        assert_eq!(schema_version(&conn)?, SCHEMA_VERSION);
+        let tables: Vec<String> = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        assert_eq!(
+            tables,
+            vec![
+                "custom_vars",
+                "history",
+                "item_links",
+                "items",
+                "items_fts",
+                "items_fts_config",
+                "items_fts_data",
+                "items_fts_docsize",
+                "items_fts_idx",
+                "profile_entries",
+                "profiles",
+                "separator_overrides"
+            ]
+        );
+        let rows: i64 =
+            conn.query_row("SELECT COUNT(*) FROM profile_entries", [], |row| row.get(0))?;
+        assert_eq!(rows, 1);
+        assert_eq!(load_profile(&conn, "old")?.entries.len(), 1);
+
+        // Opening again is a no-op.
+        drop(conn);
+        let conn = establish_connection_at(&path)?;
+        assert_eq!(load_all_profiles(&conn)?.len(), 1);
+        drop(conn);
+        std::fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn newer_schema_is_refused() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)?;
+        let err = initialize_db(&conn).unwrap_err();
+        assert!(matches!(err, BathError::SchemaTooNew { .. }));
+        assert!(err.to_string().contains("upgrade bath"));
+        Ok(())
+    }
+
+    fn entries_json(p: &EnvProfile) -> String {
+        serde_json::to_string(&p.entries).unwrap()
+    }
+
+    #[test]
+    fn entry_rows_round_trip_in_order() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        profile.entries = vec![
+            Entry::CFlag("-O2".to_string()),
+            Entry::Path(PathEntry {
+                path: "/opt/gcc-12/bin".to_string(),
+                program: Some("gcc".to_string()),
+                version: Some("12".to_string()),
+                mode: Some(crate::export::OperationMode::Append),
+            }),
+            Entry::CustomScalar {
+                name: "TARGET".to_string(),
+                value: "arm".to_string(),
+            },
+            Entry::CFlag("-g".to_string()),
+            Entry::CustomPart {
+                name: "MYLIST".to_string(),
+                value: "a".to_string(),
+                separator: ",".to_string(),
+                mode: None,
+            },
+        ];
+        save_profile(&conn, &profile)?;
+        assert_eq!(
+            entries_json(&load_profile(&conn, "p")?),
+            entries_json(&profile)
+        );
+
+        // Moving and inserting entries keeps the order.
+        profile.entries.swap(0, 3);
+        profile.entries.insert(1, Entry::CC("gcc".to_string()));
+        profile.entries.insert(0, Entry::CC("clang".to_string()));
+        save_profile(&conn, &profile)?;
+        assert_eq!(
+            entries_json(&load_profile(&conn, "p")?),
+            entries_json(&profile)
+        );
+
+        let using: String = conn.query_row(
+            "SELECT p.name FROM profiles p JOIN profile_entries e ON e.profile_id = p.id
+             WHERE e.value = '/opt/gcc-12/bin'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(using, "p");
+        Ok(())
+    }
+
+    #[test]
+    fn editing_one_var_of_a_large_profile_touches_only_its_rows() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("big");
+        for var in 0..10 {
+            for part in 0..100 {
+                profile.entries.push(Entry::CustomPart {
+                    name: format!("VAR{var}"),
+                    value: format!("v{part}"),
+                    separator: ":".to_string(),
+                    mode: None,
+                });
+            }
+        }
+        save_profile(&conn, &profile)?;
+
+        // Rewrite VAR4 (entries 400..500) with one more part, as replace_var_parts does.
+        let parts: Vec<Entry> = (0..101)
+            .map(|part| Entry::CustomPart {
+                name: "VAR4".to_string(),
+                value: format!("new{part}"),
+                separator: ":".to_string(),
+                mode: None,
+            })
+            .collect();
+        profile.entries.splice(400..500, parts);
+
+        let before = conn.total_changes();
+        save_profile(&conn, &profile)?;
+        // 100 updated rows, 1 inserted row and the profile row itself.
+        assert_eq!(conn.total_changes() - before, 102);
+        assert_eq!(
+            entries_json(&load_profile(&conn, "big")?),
+            entries_json(&profile)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn timestamps_move_only_on_change() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        profile.entries.push(Entry::CC("gcc".to_string()));
+        save_profile(&conn, &profile)?;
+        conn.execute("UPDATE profiles SET created_at = 100, updated_at = 200", [])?;
+        let times = |name: &str| -> Result<(i64, i64)> {
+            let m = load_profile_meta(&conn, name)?;
+            Ok((m.created_at, m.updated_at))
+        };
+
+        save_profile(&conn, &profile)?;
+        assert_eq!(times("p")?, (100, 200));
+
+        profile.entries.push(Entry::CFlag("-O2".to_string()));
+        save_profile(&conn, &profile)?;
+        let (created, updated) = times("p")?;
+        assert_eq!(created, 100);
+        assert!(updated > 200);
+
+        conn.execute("UPDATE profiles SET updated_at = 200", [])?;
+        rename_profile(&conn, "p", "q")?;
+        let (created, updated) = times("q")?;
+        assert_eq!(created, 100);
+        assert!(updated > 200);
+        assert_eq!(load_profile_metas(&conn)?[0].entry_count, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn descriptions_round_trip() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("x-aarch64-musl-dbg");
+        profile.description = Some("static debug builds for the router".to_string());
+        save_profile(&conn, &profile)?;
+        save_profile(&conn, &EnvProfile::new("plain"))?;
+
+        let loaded = load_profile(&conn, "x-aarch64-musl-dbg")?;
+        assert_eq!(loaded.description, profile.description);
+        let all = load_all_profiles(&conn)?;
+        assert!(all
+            .iter()
+            .any(|p| p.name == "plain" && p.description.is_none()));
+        let copy = copy_profile(&conn, "x-aarch64-musl-dbg", "copy", false)?;
+        assert_eq!(copy.description, profile.description);
+        assert_eq!(
+            load_profile_meta(&conn, "copy")?.description,
+            profile.description
+        );
+
+        conn.execute("UPDATE profiles SET updated_at = 0", [])?;
+        profile.description = None;
+        save_profile(&conn, &profile)?;
+        let meta = load_profile_meta(&conn, "x-aarch64-musl-dbg")?;
+        assert_eq!(meta.description, None);
+        assert!(meta.updated_at > 0);
+
+        // Blobs written before descriptions existed still load.
+        let old: EnvProfile = serde_json::from_str(r#"{"name":"old","entries":[]}"#)?;
+        assert_eq!(old.description, None);
+        Ok(())
+    }
+
+    #[test]
+    fn history_keeps_the_newest_changes_per_profile() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        save_profile_logged(&conn, &profile, "create")?;
+        for i in 0..HISTORY_LIMIT + 5 {
+            profile.entries.push(Entry::CFlag(format!("-D{i}")));
+            save_profile_logged(&conn, &profile, "add CFLAGS")?;
+        }
+        // Saving an unchanged profile records nothing.
+        save_profile_logged(&conn, &profile, "edit CFLAGS")?;
+        save_profile_logged(&conn, &EnvProfile::new("other"), "create")?;
+
+        let history = load_history(&conn, "p")?;
+        assert_eq!(history.len(), HISTORY_LIMIT as usize);
+        assert!(history.iter().all(|h| h.action == "add CFLAGS"));
+        assert_eq!(
+            history[0].after.as_ref().map(|p| p.entries.len()),
+            Some(HISTORY_LIMIT as usize + 5)
+        );
+        assert!(history.windows(2).all(|w| w[0].id > w[1].id));
+        assert_eq!(load_history(&conn, "other")?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn restoring_undoes_a_change_and_everything_after_it() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        profile.description = Some("kept".to_string());
+        save_profile_logged(&conn, &profile, "create")?;
+        let path = |p: &str| {
+            Entry::Path(PathEntry {
+                path: p.to_string(),
+                program: None,
+                version: None,
+                mode: None,
+            })
+        };
+        profile.entries = vec![path("/a"), path("/b"), path("/c")];
+        save_profile_logged(&conn, &profile, "edit PATH")?;
+        profile.entries.clear();
+        save_profile_logged(&conn, &profile, "edit PATH")?;
+        profile.entries.push(Entry::CC("gcc".to_string()));
+        save_profile_logged(&conn, &profile, "add CC")?;
+
+        let history = load_history(&conn, "p")?;
+        let wipe = history[1].id;
+        let restored = restore_from_history(&conn, "p", wipe)?;
+        let values: Vec<String> = restored.entries.iter().map(entry_value).collect();
+        assert_eq!(values, ["/a", "/b", "/c"]);
+        let loaded = load_profile(&conn, "p")?;
+        assert_eq!(loaded.entries.len(), 3);
+        assert_eq!(loaded.description.as_deref(), Some("kept"));
+        // The restore is a change too, undoing it brings CC back.
+        let latest = &load_history(&conn, "p")?[0];
+        assert_eq!(latest.action, format!("restore #{wipe}"));
+        restore_from_history(&conn, "p", latest.id)?;
+        assert!(matches!(
+            load_profile(&conn, "p")?.entries[..],
+            [Entry::CC(_)]
+        ));
+
+        let created = history.last().map(|h| h.id).unwrap_or_default();
+        assert!(restore_from_history(&conn, "p", created).is_err());
+        assert!(restore_from_history(&conn, "other", wipe).is_err());
+
+        // Deleted profiles come back, and history follows renames.
+        let before = load_profile(&conn, "p")?;
+        delete_profile(&conn, "p")?;
+        record_history(&conn, "p", "delete", Some(&before), None)?;
+        let deleted = load_history(&conn, "p")?[0].id;
+        restore_from_history(&conn, "p", deleted)?;
+        assert!(profile_exists(&conn, "p")?);
+        rename_profile(&conn, "p", "q")?;
+        assert!(load_history(&conn, "p")?.is_empty());
+        assert!(!load_history(&conn, "q")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn part_settings_round_trip_through_rows() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        profile.entries = vec![
+            Entry::CFlag("-O2".to_string()),
+            Entry::CustomPart {
+                name: "L".to_string(),
+                value: "a".to_string(),
+                separator: ",".to_string(),
+                mode: Some(OperationMode::Append),
+            }
+            .with_enabled(false)
+            .with_comment(Some("off until the vendor fixes it".to_string()))
+            .with_priority(Some(-3)),
+        ];
+        save_profile(&conn, &profile)?;
+        let loaded = load_profile(&conn, "p")?;
+        assert!(loaded.entries[0].is_enabled());
+        assert_eq!(
+            loaded.entries[1].comment(),
+            Some("off until the vendor fixes it")
+        );
+        assert!(!loaded.entries[1].is_enabled());
+        assert_eq!(loaded.entries[1].pinned_mode(), Some(OperationMode::Append));
+        assert_eq!(loaded.entries[1].separator(), ",");
+
+        profile.entries[1] = profile.entries[1].clone().with_enabled(true);
+        save_profile(&conn, &profile)?;
+        let loaded = load_profile(&conn, "p")?;
+        assert!(loaded.entries.iter().all(Entry::is_enabled));
+        assert!(loaded.entries[1].comment().is_some());
+        assert_eq!(loaded.entries[1].priority(), Some(-3));
+        assert_eq!(loaded.entries[0].priority(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn entries_from_a_newer_bath_survive_saves() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        profile.entries = vec![Entry::CFlag("-O2".to_string())];
+        save_profile(&conn, &profile)?;
+        let id: i64 =
+            conn.query_row("SELECT id FROM profiles WHERE name = 'p'", [], |r| r.get(0))?;
+        conn.execute(
+            "INSERT INTO profile_entries
+                (profile_id, position, var_name, kind, value, separator, mode, enabled, comment)
+             VALUES (?1, 5000, 'CFLAGS', 'Frobnicate', '-flto', ' ', 'Weave', 0, 'later')",
+            [id],
+        )?;
+        let stored = |conn: &Connection| -> Result<Vec<EntryRow>> {
+            Ok(load_entry_rows(conn, id)?
+                .into_iter()
+                .map(|(_, r)| r)
+                .collect())
+        };
+        let before = stored(&conn)?;
+
+        let mut loaded = load_profile(&conn, "p")?;
+        assert_eq!(loaded.entries[1].unknown_kind(), Some("Frobnicate"));
+        assert_eq!(loaded.entries[1].var_name(), "CFLAGS");
+        assert_eq!(loaded.entries[1].comment(), Some("later"));
+        assert!(!crate::export::generate_full_export(
+            &crate::dialect::Posix::default(),
+            &loaded,
+            OperationMode::Prepend,
+            crate::export::VarOrder::default(),
+            &SeparatorOverrides::new(),
+        )
+        .contains("-flto"));
+
+        loaded.entries.insert(0, Entry::CC("gcc".to_string()));
+        save_profile(&conn, &loaded)?;
+        let after = stored(&conn)?;
+        assert_eq!(after[1..], before[..]);
+
+        // Profiles only stored as JSON keep the entry whole as well.
+        let future = r#"{"Frobnicate":{"tiers":[1,2]}}"#;
+        conn.execute(
+            "INSERT INTO profiles (name, entries, position) VALUES ('old', ?1, 9)",
+            [format!("[{future}]")],
+        )?;
+        let old = load_profile(&conn, "old")?;
+        save_profile(&conn, &old)?;
+        let again = load_profile(&conn, "old")?;
+        assert_eq!(serde_json::to_string(&again.entries[0])?, future);
+        Ok(())
+    }
+
+    #[test]
+    fn entries_from_a_newer_bath_keep_fields_without_a_column() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let payloads = [
+            r#"{"Frobnicate":{"name":"CFLAGS","separator":" ","value":"-flto"}}"#,
+            r#"{"Frobnicate":{"name":"CFLAGS","value":"-flto","weight":3}}"#,
+            r#"{"Frobnicate":{"name":"CFLAGS","tier":"hot","value":"-flto"}}"#,
+            r#"{"Frobnicate":{"value":"{}"}}"#,
+            r#"{"Frobnicate":"-flto"}"#,
+        ];
+        let mut profile = EnvProfile::new("p");
+        for payload in payloads {
+            profile.entries.push(serde_json::from_str(payload)?);
+        }
+        save_profile(&conn, &profile)?;
+
+        let loaded = load_profile(&conn, "p")?;
+        let stored: Vec<String> = loaded
+            .entries
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<serde_json::Result<_>>()?;
+        assert_eq!(stored, payloads);
+        assert_eq!(loaded.entries[1].var_name(), "CFLAGS");
+        Ok(())
+    }
+
+    #[test]
+    fn default_modes_round_trip() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("sysroot");
+        profile.default_mode = Some(OperationMode::Append);
+        save_profile(&conn, &profile)?;
+        save_profile(&conn, &EnvProfile::new("plain"))?;
+
+        assert_eq!(
+            load_profile(&conn, "sysroot")?.default_mode,
+            Some(OperationMode::Append)
+        );
+        assert_eq!(load_profile(&conn, "plain")?.default_mode, None);
+        let copy = copy_profile(&conn, "sysroot", "copy", false)?;
+        assert_eq!(copy.default_mode, Some(OperationMode::Append));
+
+        conn.execute("UPDATE profiles SET updated_at = 0", [])?;
+        profile.default_mode = None;
+        save_profile(&conn, &profile)?;
+        assert!(load_profile_meta(&conn, "sysroot")?.updated_at > 0);
+        assert!(load_all_profiles(&conn)?
+            .iter()
+            .all(|p| p.name == "copy" || p.default_mode.is_none()));
+
+        conn.execute(
+            "UPDATE profiles SET default_mode = 'sideways' WHERE name = 'copy'",
+            [],
+        )?;
+        assert_eq!(load_profile(&conn, "copy")?.default_mode, None);
+        Ok(())
+    }
+
+    #[test]
+    fn parents_round_trip_and_follow_renames() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        save_profile(&conn, &EnvProfile::new("base"))?;
+        let mut child = EnvProfile::new("base-asan");
+        child.extends = Some("base".to_string());
+        save_profile(&conn, &child)?;
+
+        rename_profile(&conn, "base", "core")?;
+        assert_eq!(
+            load_profile(&conn, "base-asan")?.extends.as_deref(),
+            Some("core")
+        );
+        let copy = copy_profile(&conn, "base-asan", "base-ubsan", false)?;
+        assert_eq!(copy.extends.as_deref(), Some("core"));
+        assert_eq!(load_profile(&conn, "core")?.extends, None);
+
+        // A parent stays until nothing extends it.
+        assert!(delete_profile(&conn, "core").is_err());
+        assert!(profile_exists(&conn, "core")?);
+        delete_profile(&conn, "base-asan")?;
+        delete_profile(&conn, "base-ubsan")?;
+        delete_profile(&conn, "core")?;
+        assert!(!profile_exists(&conn, "core")?);
+        Ok(())
+    }
+
+    #[test]
+    fn tags_round_trip_without_touching_untagged_profiles() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        save_profile(&conn, &EnvProfile::new("plain"))?;
+        let mut tagged = EnvProfile::new("cross");
+        tagged.tags = vec!["arm".to_string(), "musl".to_string()];
+        save_profile(&conn, &tagged)?;
+
+        assert_eq!(load_profile(&conn, "cross")?.tags, ["arm", "musl"]);
+        assert_eq!(copy_profile(&conn, "cross", "cross2", false)?.tags.len(), 2);
+        let stored: Option<String> = conn.query_row(
+            "SELECT tags FROM profiles WHERE name = 'plain'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(stored, None);
+        let metas = load_profile_metas(&conn)?;
+        assert_eq!(metas[1].tags, ["arm", "musl"]);
+        assert!(metas[0].tags.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn manual_profile_order_persists() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        for name in ["b", "a", "c"] {
+            save_profile(&conn, &EnvProfile::new(name))?;
+        }
+        let names = |conn: &Connection| -> Result<Vec<String>> {
+            Ok(load_all_profiles(conn)?
+                .into_iter()
+                .map(|p| p.name)
+                .collect())
+        };
+        assert_eq!(names(&conn)?, ["b", "a", "c"]);
+
+        swap_profile_positions(&conn, "b", "c")?;
+        // Re-saving keeps the place.
+        save_profile(&conn, &EnvProfile::new("c"))?;
+        assert_eq!(names(&conn)?, ["c", "a", "b"]);
+
+        set_profile_order(&conn, &["a".to_string()])?;
+        save_profile(&conn, &EnvProfile::new("d"))?;
+        assert_eq!(names(&conn)?, ["a", "c", "b", "d"]);
+        let metas: Vec<String> = load_profile_metas(&conn)?
+            .into_iter()
+            .map(|m| m.name)
+            .collect();
+        assert_eq!(metas, names(&conn)?);
+        assert!(swap_profile_positions(&conn, "a", "zz").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn saving_keeps_the_profile_id() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let id = |conn: &Connection| -> Result<i64> {
+            Ok(conn.query_row("SELECT id FROM profiles WHERE name = 'p'", [], |r| r.get(0))?)
+        };
+        let mut profile = EnvProfile::new("p");
+        save_profile(&conn, &profile)?;
+        let first = id(&conn)?;
+        for value in ["gcc", "clang", "tcc"] {
+            profile.entries = vec![Entry::CC(value.to_string())];
+            save_profile(&conn, &profile)?;
+            assert_eq!(id(&conn)?, first);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn failed_atomic_block_keeps_the_previous_entries() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        profile.entries = vec![Entry::CFlag("-O2".to_string())];
+        save_profile(&conn, &profile)?;
+
+        let result: Result<()> = atomically(&conn, |tx| {
+            profile.entries = vec![
+                Entry::CFlag("-O0".to_string()),
+                Entry::CFlag("-g".to_string()),
+            ];
+            save_profile(tx, &profile)?;
+            save_profile(tx, &EnvProfile::new("half-written"))?;
+            Err(BathError::InvalidArgument("simulated crash".to_string()))
+        });
+        assert!(result.is_err());
+
+        let loaded = load_profile(&conn, "p")?;
+        let values: Vec<String> = loaded.entries.iter().map(entry_value).collect();
+        assert_eq!(values, ["-O2"]);
+        assert!(!profile_exists(&conn, "half-written")?);
+        let rows: i64 = conn.query_row("SELECT COUNT(*) FROM profile_entries", [], |r| r.get(0))?;
+        assert_eq!(rows, 1);
+
+        // A successful block keeps everything.
+        atomically(&conn, |tx| save_profile(tx, &profile))?;
+        assert_eq!(load_profile(&conn, "p")?.entries.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_profiles_can_be_recovered_until_purged() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut gcc = EnvProfile::new("gcc");
+        gcc.entries.push(Entry::CC("gcc-13".to_string()));
+        save_profile(&conn, &gcc)?;
+        save_profile(&conn, &EnvProfile::new("clang"))?;
+
+        delete_profile(&conn, "gcc")?;
+        assert!(load_profile(&conn, "gcc").is_err());
+        let names: Vec<String> = load_all_profiles(&conn)?
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, ["clang"]);
+        assert_eq!(deleted_profile_names(&conn)?, ["gcc"]);
+
+        recover_profile(&conn, "gcc", None)?;
+        let back = load_profile(&conn, "gcc")?;
+        assert_eq!(entry_value(&back.entries[0]), "gcc-13");
+        let names: Vec<String> = load_all_profiles(&conn)?
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, ["gcc", "clang"]);
+        assert!(deleted_profile_names(&conn)?.is_empty());
+
+        // Deleted, then a new profile takes the name: recovering needs another name.
+        delete_profile(&conn, "gcc")?;
+        save_profile(&conn, &EnvProfile::new("gcc"))?;
+        assert!(matches!(
+            recover_profile(&conn, "gcc", None),
+            Err(BathError::ProfileExists(_))
+        ));
+        recover_profile(&conn, "gcc", Some("gcc-old"))?;
+        assert_eq!(
+            entry_value(&load_profile(&conn, "gcc-old")?.entries[0]),
+            "gcc-13"
+        );
+        assert!(load_profile(&conn, "gcc")?.entries.is_empty());
+        assert_eq!(
+            recover_profile(&conn, "gcc", None).unwrap_err().to_string(),
+            "profile 'gcc' is not deleted"
+        );
+        assert!(matches!(
+            recover_profile(&conn, "gone", None),
+            Err(BathError::ProfileNotFound { .. })
+        ));
+
+        delete_profile(&conn, "gcc-old")?;
+        assert!(purge_deleted_profiles(&conn, Some(3600))?.is_empty());
+        assert_eq!(purge_deleted_profiles(&conn, None)?, ["gcc-old"]);
+        assert!(recover_profile(&conn, "gcc-old", None).is_err());
+        let rows: i64 = conn.query_row("SELECT COUNT(*) FROM profile_entries", [], |r| r.get(0))?;
+        assert_eq!(rows, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn items_roundtrip_insert_load_update_delete() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
         initialize_db(&conn)?;
 
         let mut item = CatalogItem {
@@ -216,8 +2518,10 @@ mod tests {
             program: None,
             version: None,
             tags: vec!["core".to_string()],
+            use_count: 0,
+            last_used_at: None,
         };
-        save_item(&conn, &mut item)?;
+        save_item(&conn, &mut item, false)?;
         let id = item.id.expect("id should be set");
 
         let items = load_items(&conn)?;
@@ -227,7 +2531,7 @@ mod tests {
 
         item.value = "/usr/local/bin".to_string();
         item.tags.push("updated".to_string());
-        save_item(&conn, &mut item)?;
+        save_item(&conn, &mut item, false)?;
 
         let items = load_items(&conn)?;
         assert_eq!(items[0].value, "/usr/local/bin");
@@ -239,16 +2543,146 @@ mod tests {
         Ok(())
     }
 
+    fn text_item(value: &str, tags: &[&str]) -> CatalogItem {
+        CatalogItem {
+            id: None,
+            kind: ItemKind::Text,
+            value: value.to_string(),
+            program: None,
+            version: None,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            use_count: 0,
+            last_used_at: None,
+        }
+    }
+
     #[test]
-    fn custom_var_defs_roundtrip() -> Result<()> {
+    fn adding_an_existing_value_merges_tags_unless_duplicating() -> Result<()> {
         let conn = Connection::open_in_memory()?;
         initialize_db(&conn)?;
+        let mut first = text_item("-O2", &["opt"]);
+        assert!(!save_item(&conn, &mut first, false)?);
 
-        let def = CustomVarDef {
-            name: "MY_PATH".to_string(),
-            kind: VarKind::List,
-            separator: ";".to_string(),
+        let mut again = text_item("-O2", &["release", "opt"]);
+        again.program = Some("gcc".to_string());
+        assert!(save_item(&conn, &mut again, false)?);
+        assert_eq!(again.id, first.id);
+        let items = load_items(&conn)?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].tags, ["opt", "release"]);
+        assert_eq!(items[0].program.as_deref(), Some("gcc"));
+
+        // Same value, other kind: a separate item.
+        let mut path = text_item("-O2", &[]);
+        path.kind = ItemKind::Path;
+        assert!(!save_item(&conn, &mut path, false)?);
+
+        let mut dup = text_item("-O2", &["copy"]);
+        assert!(!save_item(&conn, &mut dup, true)?);
+        assert_ne!(dup.id, first.id);
+        assert_eq!(load_items(&conn)?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn dedup_keeps_the_lowest_id_with_all_tags() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        for (value, tags) in [
+            ("/opt/bin", vec!["a"]),
+            ("-g", vec![]),
+            ("/opt/bin", vec!["b", "a"]),
+            ("/opt/bin", vec!["c"]),
+        ] {
+            save_item(&conn, &mut text_item(value, &tags), true)?;
+        }
+        assert_eq!(dedup_items(&conn)?, 2);
+        let items = load_items(&conn)?;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].id, Some(1));
+        assert_eq!(items[0].tags, ["a", "b", "c"]);
+        assert_eq!(items[1].value, "-g");
+        assert_eq!(dedup_items(&conn)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn search_matches_word_prefixes_and_tags() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut gcc = text_item("/opt/gcc-13.2/bin", &["toolchain", "core"]);
+        gcc.program = Some("gcc".to_string());
+        save_item(&conn, &mut gcc, false)?;
+        save_item(
+            &conn,
+            &mut text_item("/opt/llvm-17/bin", &["toolchain"]),
+            false,
+        )?;
+        save_item(
+            &conn,
+            &mut text_item("-fsanitize=address", &["debug"]),
+            false,
+        )?;
+
+        let values = |q: &str| -> Result<Vec<String>> {
+            Ok(search_items(&conn, q)?
+                .into_iter()
+                .map(|i| i.value)
+                .collect())
         };
+        assert_eq!(values("gc")?, ["/opt/gcc-13.2/bin"]);
+        assert_eq!(values("fsan")?, ["-fsanitize=address"]);
+        assert_eq!(values("tool")?.len(), 2);
+        assert_eq!(values("toolchain core")?, ["/opt/gcc-13.2/bin"]);
+        assert_eq!(values("deb")?, ["-fsanitize=address"]);
+        assert_eq!(values("\"llvm")?, ["/opt/llvm-17/bin"]);
+        assert!(values("")?.is_empty());
+
+        // The index follows edits and deletes.
+        gcc.tags = vec!["legacy".to_string()];
+        save_item(&conn, &mut gcc, false)?;
+        assert!(values("core")?.is_empty());
+        assert_eq!(values("legacy")?, ["/opt/gcc-13.2/bin"]);
+        delete_item(&conn, gcc.id.unwrap())?;
+        assert!(values("legacy")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn touching_items_counts_uses_and_dedup_adds_them_up() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        save_item(&conn, &mut text_item("/opt/bin", &[]), true)?;
+        save_item(&conn, &mut text_item("/opt/bin", &[]), true)?;
+        touch_item(&conn, 1)?;
+        touch_item(&conn, 2)?;
+        touch_item(&conn, 2)?;
+        let items = load_items(&conn)?;
+        assert_eq!(items[1].use_count, 2);
+        assert!(items[1].last_used_at.is_some());
+
+        // Editing an item leaves its usage alone.
+        let mut edited = items[1].clone();
+        edited.value = "/opt/other".to_string();
+        edited.use_count = 0;
+        save_item(&conn, &mut edited, false)?;
+        assert_eq!(load_items(&conn)?[1].use_count, 2);
+
+        let mut back = load_items(&conn)?[1].clone();
+        back.value = "/opt/bin".to_string();
+        save_item(&conn, &mut back, false)?;
+        assert_eq!(dedup_items(&conn)?, 1);
+        let items = load_items(&conn)?;
+        assert_eq!(items[0].use_count, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn custom_var_defs_roundtrip() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+
+        let def = CustomVarDef::new("MY_PATH", VarKind::List, ";");
         save_custom_var_def(&conn, &def)?;
 
         let defs = load_custom_var_defs(&conn)?;
@@ -256,14 +2690,62 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn separator_overrides_roundtrip() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        assert!(load_separator_overrides(&conn)?.is_empty());
+
+        assert!(set_separator_override(&conn, "CPATH", Some(";"))?);
+        assert!(!set_separator_override(&conn, "CPATH", Some(";"))?);
+        assert!(set_separator_override(&conn, "CPATH", Some(","))?);
+        assert_eq!(
+            load_separator_overrides(&conn)?,
+            SeparatorOverrides::from([("CPATH".to_string(), ",".to_string())])
+        );
+
+        assert!(set_separator_override(&conn, "CPATH", None)?);
+        assert!(!set_separator_override(&conn, "CPATH", None)?);
+        assert!(load_separator_overrides(&conn)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn custom_var_defaults_roundtrip_and_drop_without_touching_profiles() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut def = CustomVarDef {
+            name: "SYSROOT".to_string(),
+            kind: VarKind::Scalar,
+            separator: String::new(),
+            default_value: Some("/opt/sysroot".to_string()),
+        };
+        save_custom_var_def(&conn, &def)?;
+        assert_eq!(load_custom_var_defs(&conn)?, [def.clone()]);
+        let mut profile = EnvProfile::new("arm");
+        profile.entries.push(Entry::CustomScalar {
+            name: "SYSROOT".to_string(),
+            value: "/srv/arm".to_string(),
+        });
+        save_profile(&conn, &profile)?;
+
+        def.default_value = None;
+        save_custom_var_def(&conn, &def)?;
+        assert_eq!(load_custom_var_defs(&conn)?[0].default_value, None);
+        let entries = load_profile(&conn, "arm")?.entries;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].to_string(), profile.entries[0].to_string());
+        Ok(())
+    }
+
     #[test]
     fn rename_profile_updates_row_in_place() -> Result<()> {
         let conn = Connection::open_in_memory()?;
         initialize_db(&conn)?;
 
         let p = EnvProfile {
-            name: "old".to_string(),
             entries: vec![Entry::CFlag("-O2".to_string())],
+            ..EnvProfile::new("old")
         };
         save_profile(&conn, &p)?;
 
@@ -280,4 +2762,498 @@ mod tests {
         assert!(load_profile(&conn, "old").is_err());
         Ok(())
     }
+
+    #[test]
+    fn save_waits_for_another_writer() -> Result<()> {
+        let path = temp_db("busy");
+        let holder = establish_connection_at(&path)?;
+        let waiter = establish_connection_at(&path)?;
+        let journal: String = waiter.query_row("PRAGMA journal_mode", [], |r| r.get(0))?;
+        assert_eq!(journal, "wal");
+
+        holder.execute_batch("BEGIN IMMEDIATE")?;
+        save_profile(&holder, &EnvProfile::new("first"))?;
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            holder.execute_batch("COMMIT")
+        });
+        // Blocks until the other transaction commits, well within BUSY_TIMEOUT.
+        save_profile(&waiter, &EnvProfile::new("second"))?;
+        release.join().expect("holder thread")?;
+        assert!(profile_exists(&waiter, "first")? && profile_exists(&waiter, "second")?);
+
+        // A writer that never lets go ends in the friendly error.
+        waiter.busy_timeout(Duration::from_millis(50))?;
+        let holder = establish_connection_at(&path)?;
+        holder.execute_batch("BEGIN IMMEDIATE")?;
+        let err = save_profile(&waiter, &EnvProfile::new("third")).unwrap_err();
+        assert!(matches!(err, BathError::DatabaseLocked), "{err}");
+        // Readers are not blocked by the writer.
+        assert!(load_profile(&waiter, "first").is_ok());
+        holder.execute_batch("ROLLBACK")?;
+        Ok(())
+    }
+
+    #[test]
+    fn backup_file_name_is_a_utc_timestamp() {
+        assert_eq!(
+            default_backup_path(Path::new("/home/u/.bath.db"), 1_792_160_701),
+            PathBuf::from("/home/u/bath-backup-20261016-142501.db")
+        );
+        assert_eq!(
+            default_backup_path(Path::new("x.db"), 951_782_400),
+            PathBuf::from("bath-backup-20000229-000000.db")
+        );
+    }
+
+    #[test]
+    fn backup_then_restore_or_merge() -> anyhow::Result<()> {
+        let dir = env::temp_dir().join(format!("bath-backup-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+
+        let mut live = establish_connection_at(&dir.join("live.db"))?;
+        let mut gcc = EnvProfile::new("gcc");
+        gcc.entries.push(Entry::CC("gcc".to_string()));
+        save_profile(&live, &gcc)?;
+        save_custom_var_def(&live, &CustomVarDef::new("XTOOLS", VarKind::List, ":"))?;
+        // A second connection stands in for a TUI holding the file open.
+        let _tui = establish_connection_at(&dir.join("live.db"))?;
+        let backup = dir.join("snap.db");
+        backup_to(&live, &backup)?;
+        assert!(backup_to(&live, &backup).is_err());
+
+        gcc.entries = vec![Entry::CC("gcc-14".to_string())];
+        save_profile(&live, &gcc)?;
+        save_profile(&live, &EnvProfile::new("later"))?;
+
+        let imported = merge_from(&live, &backup)?;
+        assert_eq!(imported, [("gcc".to_string(), "gcc-imported".to_string())]);
+        assert_eq!(
+            entry_value(&load_profile(&live, "gcc-imported")?.entries[0]),
+            "gcc"
+        );
+        assert_eq!(
+            entry_value(&load_profile(&live, "gcc")?.entries[0]),
+            "gcc-14"
+        );
+        let again = merge_from(&live, &backup)?;
+        assert_eq!(again[0].1, "gcc-imported-2");
+
+        restore_from(&mut live, &backup)?;
+        let names: Vec<String> = load_all_profiles(&live)?
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, ["gcc"]);
+        assert_eq!(entry_value(&load_profile(&live, "gcc")?.entries[0]), "gcc");
+        assert_eq!(load_custom_var_defs(&live)?.len(), 1);
+
+        // A backup with an unreadable profile is not merged at all.
+        live.execute(
+            "INSERT INTO profiles (name, entries, position) VALUES ('garbled', '{oops', 9)",
+            [],
+        )?;
+        let broken = dir.join("broken.db");
+        backup_to(&live, &broken)?;
+        assert!(merge_from(&live, &broken).is_err());
+        assert!(!profile_exists(&live, "gcc-imported")?);
+
+        let junk = dir.join("junk.db");
+        std::fs::write(
+            &junk,
+            "not sqlite at all, just text padding it out to a page",
+        )?;
+        assert!(matches!(
+            open_backup(&junk),
+            Err(BathError::InvalidArgument(_))
+        ));
+        let empty = dir.join("empty.db");
+        Connection::open(&empty)?.execute_batch("CREATE TABLE t (x)")?;
+        assert!(restore_from(&mut live, &empty).is_err());
+        let newer = dir.join("newer.db");
+        {
+            let c = Connection::open(&newer)?;
+            c.execute_batch("CREATE TABLE profiles (id INTEGER); PRAGMA user_version = 999;")?;
+        }
+        assert!(matches!(
+            open_backup(&newer),
+            Err(BathError::SchemaTooNew { found: 999, .. })
+        ));
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    fn temp_db(tag: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("bath-test-{}-{tag}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn db_path_prefers_flag_then_env_then_data_dir() -> Result<()> {
+        let flag = PathBuf::from("/tmp/flag.db");
+        assert_eq!(
+            resolve_db_path(Some(&flag), Some("/tmp/env.db"), Some("/home/u"), None)?,
+            flag
+        );
+        assert_eq!(
+            resolve_db_path(None, Some("/tmp/env.db"), Some("/home/u"), None)?,
+            PathBuf::from("/tmp/env.db")
+        );
+        assert_eq!(
+            resolve_db_path(None, None, Some("/home/u"), None)?,
+            PathBuf::from("/home/u/.local/share/bath/bath.db")
+        );
+        assert_eq!(
+            resolve_db_path(None, None, Some("/home/u"), Some("/data"))?,
+            PathBuf::from("/data/bath/bath.db")
+        );
+        assert_eq!(
+            resolve_db_path(None, None, Some("/home/u"), Some("relative"))?,
+            PathBuf::from("/home/u/.local/share/bath/bath.db")
+        );
+        assert_eq!(
+            resolve_db_path(None, None, None, Some("/data"))?,
+            PathBuf::from("/data/bath/bath.db")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_database_is_used_until_migrated() -> anyhow::Result<()> {
+        let home = env::temp_dir().join(format!("bath-test-{}-home", std::process::id()));
+        let _ = std::fs::remove_dir_all(&home);
+        std::fs::create_dir_all(&home)?;
+        let home_s = home.to_str().unwrap();
+        let xdg = home.join("xdg");
+        let xdg_s = xdg.to_str().unwrap();
+        let resolve = || resolve_db_path(None, None, Some(home_s), Some(xdg_s));
+
+        let fresh = xdg.join("bath/bath.db");
+        assert_eq!(resolve()?, fresh);
+
+        let legacy = home.join(".bath.db");
+        save_profile(&establish_connection_at(&legacy)?, &EnvProfile::new("old"))?;
+        assert_eq!(resolve()?, legacy);
+        assert_eq!(
+            resolve_db_path(None, Some("/tmp/env.db"), Some(home_s), Some(xdg_s))?,
+            PathBuf::from("/tmp/env.db")
+        );
+
+        migrate_legacy_db(&legacy, &fresh)?;
+        assert_eq!(resolve()?, fresh);
+        assert!(profile_exists(&establish_connection_at(&fresh)?, "old")?);
+        assert!(legacy.exists());
+        let note = std::fs::read_to_string(home.join(".bath.db.moved"))?;
+        assert!(note.contains(fresh.to_str().unwrap()));
+        assert!(migrate_legacy_db(&legacy, &fresh).is_err());
+
+        std::fs::remove_dir_all(&home)?;
+        Ok(())
+    }
+
+    #[test]
+    fn missing_home_is_an_error_mentioning_db_flag() {
+        let err = resolve_db_path(None, None, None, None).unwrap_err();
+        assert!(err.to_string().contains("--db"));
+    }
+
+    #[test]
+    fn databases_at_different_paths_are_isolated() -> anyhow::Result<()> {
+        let a = temp_db("iso-a");
+        let b = temp_db("iso-b");
+
+        let conn = establish_connection_at(&a)?;
+        save_profile(&conn, &EnvProfile::new("only-in-a"))?;
+        drop(conn);
+
+        assert!(profile_exists(&establish_connection_at(&a)?, "only-in-a")?);
+        assert!(load_all_profiles(&establish_connection_at(&b)?)?.is_empty());
+
+        std::fs::remove_file(a)?;
+        std::fs::remove_file(b)?;
+        Ok(())
+    }
+
+    #[test]
+    fn delete_custom_var_def_removes_only_that_def() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        for name in ["A_PATH", "B_PATH"] {
+            save_custom_var_def(&conn, &CustomVarDef::new(name, VarKind::List, ":"))?;
+        }
+
+        assert!(delete_custom_var_def(&conn, "A_PATH")?);
+        assert!(!delete_custom_var_def(&conn, "A_PATH")?);
+        let names: Vec<String> = load_custom_var_defs(&conn)?
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        assert_eq!(names, vec!["B_PATH"]);
+        Ok(())
+    }
+
+    #[test]
+    fn profiles_using_custom_var_matches_parts_and_scalars() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        save_profile(
+            &conn,
+            &EnvProfile {
+                entries: vec![Entry::CustomPart {
+                    name: "MY_PATH".to_string(),
+                    value: "/x".to_string(),
+                    separator: ":".to_string(),
+                    mode: None,
+                }],
+                ..EnvProfile::new("b")
+            },
+        )?;
+        save_profile(
+            &conn,
+            &EnvProfile {
+                entries: vec![
+                    Entry::CFlag("-O2".to_string()),
+                    Entry::CustomScalar {
+                        name: "MY_PATH".to_string(),
+                        value: "/y".to_string(),
+                    },
+                ],
+                ..EnvProfile::new("a")
+            },
+        )?;
+        save_profile(
+            &conn,
+            &EnvProfile {
+                entries: vec![Entry::CustomScalar {
+                    name: "OTHER".to_string(),
+                    value: "MY_PATH".to_string(),
+                }],
+                ..EnvProfile::new("c")
+            },
+        )?;
+
+        assert_eq!(profiles_using_custom_var(&conn, "MY_PATH")?, vec!["a", "b"]);
+        assert!(profiles_using_custom_var(&conn, "NOPE")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn copy_profile_clones_entries_under_new_name() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        save_profile(
+            &conn,
+            &EnvProfile {
+                entries: vec![
+                    Entry::CC("gcc".to_string()),
+                    Entry::CFlag("-O2".to_string()),
+                ],
+                ..EnvProfile::new("gcc")
+            },
+        )?;
+
+        let copy = copy_profile(&conn, "gcc", "gcc-debug", false)?;
+        assert_eq!(copy.name, "gcc-debug");
+        assert_eq!(load_profile(&conn, "gcc-debug")?.entries.len(), 2);
+        assert_eq!(load_profile(&conn, "gcc")?.entries.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn copy_profile_refuses_existing_target_unless_overwriting() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        save_profile(
+            &conn,
+            &EnvProfile {
+                entries: vec![Entry::CC("gcc".to_string())],
+                ..EnvProfile::new("a")
+            },
+        )?;
+        save_profile(&conn, &EnvProfile::new("b"))?;
+
+        assert!(copy_profile(&conn, "a", "b", false).is_err());
+        assert!(load_profile(&conn, "b")?.entries.is_empty());
+        assert!(copy_profile(&conn, "a", "a", true).is_err());
+        assert!(copy_profile(&conn, "missing", "c", false).is_err());
+
+        copy_profile(&conn, "a", "b", true)?;
+        assert_eq!(load_profile(&conn, "b")?.entries.len(), 1);
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM profiles", [], |row| row.get(0))?;
+        assert_eq!(count, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_profile_suggests_close_names() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        save_profile(&conn, &EnvProfile::new("native"))?;
+        save_profile(&conn, &EnvProfile::new("cross-arm"))?;
+
+        match load_profile(&conn, "nativ") {
+            Err(BathError::ProfileNotFound { name, suggestions }) => {
+                assert_eq!(name, "nativ");
+                assert_eq!(suggestions, vec!["native"]);
+            }
+            other => panic!("expected ProfileNotFound, got {other:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rename_custom_var_def_moves_definition() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        for name in ["TOOLCHAIN_BIN", "OTHER"] {
+            save_custom_var_def(&conn, &CustomVarDef::new(name, VarKind::List, ":"))?;
+        }
+
+        assert!(rename_custom_var_def(&conn, "TOOLCHAIN_BIN", "XTOOL_BIN")?);
+        let names: Vec<String> = load_custom_var_defs(&conn)?
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        assert_eq!(names, vec!["OTHER", "XTOOL_BIN"]);
+
+        assert!(!rename_custom_var_def(&conn, "MISSING", "NEW")?);
+        assert!(rename_custom_var_def(&conn, "XTOOL_BIN", "OTHER").is_err());
+
+        // A rename that fails halfway keeps nothing.
+        let result: Result<()> = atomically(&conn, |tx| {
+            rename_custom_var_def(tx, "XTOOL_BIN", "RENAMED")?;
+            Err(BathError::InvalidArgument("simulated crash".to_string()))
+        });
+        assert!(result.is_err());
+        let names: Vec<String> = load_custom_var_defs(&conn)?
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        assert_eq!(names, vec!["OTHER", "XTOOL_BIN"]);
+        Ok(())
+    }
+
+    #[test]
+    fn entries_follow_a_def_rename_and_survive_a_delete() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        save_custom_var_def(&conn, &CustomVarDef::new("TOOLS", VarKind::List, ":"))?;
+        for name in ["a", "b"] {
+            let mut p = EnvProfile::new(name);
+            p.entries = vec![
+                Entry::CustomPart {
+                    name: "TOOLS".to_string(),
+                    value: format!("/opt/{name}"),
+                    separator: ":".to_string(),
+                    mode: None,
+                },
+                Entry::CFlag("-O2".to_string()),
+            ];
+            save_profile(&conn, &p)?;
+        }
+        save_profile(&conn, &EnvProfile::new("unrelated"))?;
+        delete_profile(&conn, "b")?;
+
+        assert!(rename_custom_var_def(&conn, "TOOLS", "XTOOLS")?);
+        assert!(profiles_using_custom_var(&conn, "TOOLS")?.is_empty());
+        assert_eq!(profiles_using_custom_var(&conn, "XTOOLS")?, ["a"]);
+        // A profile in the trash comes back with the new name.
+        recover_profile(&conn, "b", None)?;
+        let b = load_profile(&conn, "b")?;
+        assert_eq!(b.entries[0].var_name(), "XTOOLS");
+        assert_eq!(entry_value(&b.entries[0]), "/opt/b");
+
+        assert!(delete_custom_var_def(&conn, "XTOOLS")?);
+        assert!(load_custom_var_defs(&conn)?.is_empty());
+        assert_eq!(profiles_using_custom_var(&conn, "XTOOLS")?, ["a", "b"]);
+        assert_eq!(load_profile(&conn, "a")?.entries.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn corrupt_entries_only_break_their_profile() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut good = EnvProfile::new("good");
+        good.entries = vec![Entry::CC("gcc".to_string())];
+        save_profile(&conn, &good)?;
+        conn.execute(
+            "INSERT INTO profiles (name, entries, position) VALUES ('garbled', '{oops', 1)",
+            [],
+        )?;
+        conn.execute(
+            "INSERT INTO profiles (name, entries, position)
+             VALUES ('partial', '[{\"CFlag\": \"-O2\"}, {\"CFlag\": 5}, {\"CC\": \"clang\"}]', 2)",
+            [],
+        )?;
+
+        let (profiles, broken) = load_profiles_checked(&conn)?;
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].name, "good");
+        let names: Vec<&str> = broken.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["garbled", "partial"]);
+        assert!(broken[0].error.starts_with("invalid profile data"));
+        assert_eq!(load_all_profiles(&conn)?.len(), 1);
+
+        assert_eq!(repair_profile(&conn, "partial")?, (2, 1));
+        assert_eq!(load_profile(&conn, "partial")?.entries.len(), 2);
+        assert_eq!(repair_profile(&conn, "garbled")?, (0, 0));
+        assert!(load_profile(&conn, "garbled")?.entries.is_empty());
+        assert!(load_profiles_checked(&conn)?.1.is_empty());
+        assert!(repair_profile(&conn, "good").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn item_links_follow_profiles_and_parts() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        initialize_db(&conn)?;
+        let mut item = text_item("/opt/gcc/bin", &[]);
+        save_item(&conn, &mut item, true)?;
+        let id = item.id.unwrap();
+        let mut p = EnvProfile::new("tc");
+        p.entries = vec![
+            Entry::Path(PathEntry {
+                path: "/opt/gcc/bin".to_string(),
+                program: None,
+                version: None,
+                mode: None,
+            }),
+            Entry::CFlag("-O2".to_string()),
+        ];
+        save_profile(&conn, &p)?;
+        link_item(&conn, id, "tc", "PATH", "/opt/gcc/bin")?;
+        link_item(&conn, id, "tc", "PATH", "/opt/gcc/bin")?;
+        let where_used = |conn: &Connection| -> Result<Vec<(String, String)>> {
+            Ok(item_links(conn, id)?
+                .into_iter()
+                .map(|l| (l.profile, l.var))
+                .collect())
+        };
+        assert_eq!(where_used(&conn)?, [("tc".to_string(), "PATH".to_string())]);
+
+        // Unrelated edits keep the link, a rename carries it along.
+        p.entries.pop();
+        save_profile(&conn, &p)?;
+        rename_profile(&conn, "tc", "gcc")?;
+        assert_eq!(
+            where_used(&conn)?,
+            [("gcc".to_string(), "PATH".to_string())]
+        );
+
+        // Removing the part drops the link.
+        p.name = "gcc".to_string();
+        p.entries.clear();
+        save_profile(&conn, &p)?;
+        assert!(where_used(&conn)?.is_empty());
+
+        // Deleting the item drops its links too.
+        link_item(&conn, id, "gcc", "PATH", "/gone")?;
+        delete_item(&conn, id)?;
+        let left: i64 = conn.query_row("SELECT COUNT(*) FROM item_links", [], |r| r.get(0))?;
+        assert_eq!(left, 0);
+        Ok(())
+    }
 }
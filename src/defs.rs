@@ -0,0 +1,194 @@
+// src/defs.rs
+
+use crate::config::{builtin_var, CustomVarDef, VarKind};
+use crate::db;
+use crate::error::BathError;
+use crate::profiles::{check_var_rename, warn_unreadable};
+use anyhow::Result;
+
+/// True for names usable as shell variables: `[A-Za-z_][A-Za-z0-9_]*`.
+pub fn is_posix_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Builds a custom var definition, rejecting builtin names and names the shell
+/// could not export.
+pub fn build_def(name: &str, kind: VarKind, separator: &str) -> Result<CustomVarDef> {
+    if !is_posix_identifier(name) {
+        return Err(BathError::InvalidVarName(name.to_string()).into());
+    }
+    if builtin_var(name).is_some() {
+        anyhow::bail!("{name} is a builtin variable and cannot be redefined");
+    }
+    let separator = match kind {
+        VarKind::List if separator.is_empty() => anyhow::bail!("list vars need a separator"),
+        VarKind::List => separator.to_string(),
+        VarKind::Scalar => String::new(),
+    };
+    Ok(CustomVarDef::new(name, kind, &separator))
+}
+
+pub fn parse_kind(s: &str) -> Result<VarKind> {
+    match s {
+        "list" => Ok(VarKind::List),
+        "scalar" => Ok(VarKind::Scalar),
+        _ => anyhow::bail!("unknown var kind: {s} (expected list or scalar)"),
+    }
+}
+
+fn format_def(def: &CustomVarDef) -> String {
+    let line = match def.kind {
+        VarKind::List => format!("{}  list  {:?}", def.name, def.separator),
+        VarKind::Scalar => format!("{}  scalar", def.name),
+    };
+    match &def.default_value {
+        Some(value) => format!("{line}  default {value:?}"),
+        None => line,
+    }
+}
+
+/// Rejects separator overrides for anything but builtin list vars, and empty
+/// separators.
+pub fn check_separator_override(name: &str, separator: Option<&str>) -> Result<()> {
+    match builtin_var(name) {
+        Some(var) if var.kind == VarKind::List => {}
+        Some(_) => anyhow::bail!("{name} is a scalar and has no separator"),
+        None => anyhow::bail!(
+            "{name} is not a builtin variable; custom ones get their separator from `bath defs add`"
+        ),
+    }
+    if separator == Some("") {
+        anyhow::bail!("the separator cannot be empty");
+    }
+    Ok(())
+}
+
+/// Lists the custom definitions, then the builtin lists joined with a
+/// separator of the user's. A definition stored before a builtin of the same
+/// name existed is marked, since the builtin wins.
+pub fn list_command() -> Result<()> {
+    let conn = db::establish_connection()?;
+    let overrides = db::load_separator_overrides(&conn)?;
+    for var in crate::config::BUILTIN_VARS {
+        if let Some(sep) = overrides.get(var.name) {
+            println!(
+                "{}  list  {sep:?}  (builtin, overrides {:?})",
+                var.name, var.separator
+            );
+        }
+    }
+    for def in db::load_custom_var_defs(&conn)? {
+        if builtin_var(&def.name).is_some() {
+            println!("{}  (shadowed by the builtin)", format_def(&def));
+        } else {
+            println!("{}", format_def(&def));
+        }
+    }
+    Ok(())
+}
+
+pub fn add_command(def: CustomVarDef) -> Result<()> {
+    let conn = db::establish_connection()?;
+    db::save_custom_var_def(&conn, &def)?;
+    println!("{}", format_def(&def));
+    Ok(())
+}
+
+/// Removes a definition. Refuses while profiles still use the var unless `force`.
+pub fn rm_command(name: &str, force: bool) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let users = db::profiles_using_custom_var(&conn, name)?;
+    if !users.is_empty() {
+        if !force {
+            anyhow::bail!(
+                "{name} is still used by profile(s): {} (pass --force to remove it anyway)",
+                users.join(", ")
+            );
+        }
+        eprintln!(
+            "warning: {name} is still used by profile(s): {} (their entries are kept)",
+            users.join(", ")
+        );
+    }
+    if !db::delete_custom_var_def(&conn, name)? {
+        anyhow::bail!("no custom var named {name}");
+    }
+    println!("Removed custom var {name}.");
+    Ok(())
+}
+
+/// Joins the builtin list var `name` with `separator` from now on, or with its
+/// own separator again for `None`. Stored parts of the var are left as they
+/// are; only exports join them differently.
+pub fn override_command(name: &str, separator: Option<&str>) -> Result<()> {
+    check_separator_override(name, separator)?;
+    let conn = db::establish_connection()?;
+    db::set_separator_override(&conn, name, separator)?;
+    match separator {
+        Some(sep) => println!("{name} is now joined with {sep:?}."),
+        None => println!(
+            "{name} is joined with its builtin separator {:?} again.",
+            builtin_var(name).map_or("", |v| v.separator)
+        ),
+    }
+    Ok(())
+}
+
+/// Renames a definition; every profile's entries follow.
+pub fn rename_command(old: &str, new: &str) -> Result<()> {
+    check_var_rename(old, new)?;
+    let conn = db::establish_connection()?;
+    let users = db::profiles_using_custom_var(&conn, old)?;
+    // Unreadable profiles keep the old name; `:repair` brings them back.
+    for b in db::load_profiles_checked(&conn)?.1 {
+        warn_unreadable(&b.name);
+    }
+    if !db::rename_custom_var_def(&conn, old, new)? {
+        anyhow::bail!("no custom var named {old}");
+    }
+    println!("Renamed custom var {old} to {new}.");
+    if !users.is_empty() {
+        println!("Updated profile(s): {}", users.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_only_posix_identifiers() {
+        assert!(is_posix_identifier("MY_PATH"));
+        assert!(is_posix_identifier("_x1"));
+        assert!(!is_posix_identifier("1X"));
+        assert!(!is_posix_identifier("MY-PATH"));
+        assert!(!is_posix_identifier(""));
+    }
+
+    #[test]
+    fn build_def_rejects_builtins_and_invalid_names() -> Result<()> {
+        assert!(build_def("CFLAGS", VarKind::List, " ").is_err());
+        assert!(build_def("PKG_CONFIG_PATH", VarKind::List, ";").is_err());
+        assert!(build_def("my var", VarKind::Scalar, "").is_err());
+        assert!(build_def("MY_PATH", VarKind::List, "").is_err());
+
+        let def = build_def("MY_TOOL", VarKind::Scalar, ";")?;
+        assert_eq!(def.separator, "");
+        Ok(())
+    }
+
+    #[test]
+    fn only_builtin_lists_take_a_separator_override() {
+        assert!(check_separator_override("CPATH", Some(";")).is_ok());
+        assert!(check_separator_override("PKG_CONFIG_PATH", None).is_ok());
+        assert!(check_separator_override("CPATH", Some("")).is_err());
+        assert!(check_separator_override("CC", Some(";")).is_err());
+        assert!(check_separator_override("MY_PATH", Some(";")).is_err());
+    }
+}
@@ -0,0 +1,564 @@
+// src/dialect.rs
+
+use crate::error::{BathError, Result};
+use crate::export::OperationMode;
+
+/// Which `$` constructs in stored values the shell may expand when the output is
+/// run. Backticks and `$(` run commands, so they are only left alone with `Full`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Expand {
+    /// Everything is literal, including `$HOME`.
+    None,
+    /// `$VAR` and `${VAR}` expand; command substitution is escaped.
+    #[default]
+    Vars,
+    /// Values are passed through as written.
+    Full,
+}
+
+impl std::str::FromStr for Expand {
+    type Err = BathError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Expand::None),
+            "vars" => Ok(Expand::Vars),
+            "full" => Ok(Expand::Full),
+            _ => Err(BathError::InvalidArgument(format!(
+                "unknown expansion: {s} (expected none, vars or full)"
+            ))),
+        }
+    }
+}
+
+/// How `Posix` quotes values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Quote {
+    /// `"..."`, with `$` handled according to `Expand`.
+    #[default]
+    Double,
+    /// `'...'`, where nothing expands.
+    Single,
+}
+
+impl std::str::FromStr for Quote {
+    type Err = BathError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "double" => Ok(Quote::Double),
+            "single" => Ok(Quote::Single),
+            _ => Err(BathError::InvalidArgument(format!(
+                "unknown quoting: {s} (expected double or single)"
+            ))),
+        }
+    }
+}
+
+/// Wraps `s` in single quotes, spelling embedded quotes as `'\\''`.
+pub fn single_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Whether the `$` at `i` has to be escaped under `expand`.
+fn escape_dollar(chars: &[char], i: usize, expand: Expand) -> bool {
+    match expand {
+        Expand::None => true,
+        Expand::Vars => chars.get(i + 1) == Some(&'('),
+        Expand::Full => false,
+    }
+}
+
+/// How a shell spells variable assignments.
+///
+/// Values and separators are passed unquoted; implementations quote them with
+/// `quote_literal`, leaving `$VAR` references to expand when the output is run.
+pub trait ShellDialect {
+    /// Escapes `s` for use inside the dialect's double quotes.
+    fn quote_literal(&self, s: &str) -> String;
+
+    /// Sets `var` to `value`.
+    fn assign_replace(&self, var: &str, value: &str) -> String;
+
+    /// Removes `var` from the environment.
+    fn unset(&self, var: &str) -> String;
+
+    /// Puts `value` in front of the current value of `var`, adding `sep` only if
+    /// `var` is non-empty.
+    fn assign_prepend(&self, var: &str, value: &str, sep: &str) -> String;
+
+    /// Puts `value` after the current value of `var`, adding `sep` only if `var`
+    /// is non-empty.
+    fn assign_append(&self, var: &str, value: &str, sep: &str) -> String;
+
+    /// Puts `before` in front of and `after` behind the current value of `var`,
+    /// for variables whose parts pin different modes.
+    fn assign_surround(&self, var: &str, before: &str, after: &str, sep: &str) -> String;
+
+    /// Wraps `statement` so it is skipped when `var`, delimited by `sep`, already
+    /// contains the `parts` in order (see `presence_patterns`).
+    fn unless_present(&self, var: &str, parts: &[String], sep: &str, statement: &str) -> String;
+
+    /// Whether `$VAR` references in values expand when the output is run.
+    fn expands_vars(&self) -> bool;
+
+    /// What separates the directories of `PATH`-like lists in the environment
+    /// this shell passes on.
+    fn path_separator(&self) -> &'static str;
+
+    /// Dispatches on `mode`.
+    fn assign(&self, var: &str, value: &str, sep: &str, mode: OperationMode) -> String {
+        match mode {
+            OperationMode::Prepend => self.assign_prepend(var, value, sep),
+            OperationMode::Append => self.assign_append(var, value, sep),
+            OperationMode::Replace => self.assign_replace(var, value),
+        }
+    }
+}
+
+/// Literal segments that `sep + $VAR + sep` contains, in order and separated
+/// by anything, once `parts` were added. Each part is matched as a whole
+/// between separators; consecutive parts may be adjacent (the variable was
+/// empty) or have the inherited value between them, hence one pattern per
+/// combination.
+pub fn presence_patterns(parts: &[String], sep: &str) -> Vec<Vec<String>> {
+    let Some((first, rest)) = parts.split_first() else {
+        return Vec::new();
+    };
+    let mut patterns = vec![vec![format!("{sep}{first}")]];
+    for part in rest {
+        patterns = patterns
+            .into_iter()
+            .flat_map(|segments| {
+                let mut adjacent = segments.clone();
+                if let Some(last) = adjacent.last_mut() {
+                    last.push_str(&format!("{sep}{part}"));
+                }
+                let mut apart = segments;
+                if let Some(last) = apart.last_mut() {
+                    last.push_str(sep);
+                }
+                apart.push(format!("{sep}{part}"));
+                [adjacent, apart]
+            })
+            .collect();
+    }
+    for segments in &mut patterns {
+        if let Some(last) = segments.last_mut() {
+            last.push_str(sep);
+        }
+    }
+    patterns
+}
+
+/// sh, bash and zsh.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Posix {
+    pub expand: Expand,
+    pub quote: Quote,
+}
+
+impl Posix {
+    /// `value` as a quoted shell word.
+    fn quoted(&self, value: &str) -> String {
+        match self.quote {
+            Quote::Double => format!("\"{}\"", self.quote_literal(value)),
+            Quote::Single => single_quote(value),
+        }
+    }
+}
+
+impl ShellDialect for Posix {
+    fn expands_vars(&self) -> bool {
+        self.quote == Quote::Double && self.expand != Expand::None
+    }
+
+    fn path_separator(&self) -> &'static str {
+        ":"
+    }
+
+    fn quote_literal(&self, s: &str) -> String {
+        // '$' is only escaped as far as `expand` asks, so by default $HOME and
+        // ${VAR} expand at eval-time as requested.
+        let chars: Vec<char> = s.chars().collect();
+        let mut out = String::with_capacity(s.len());
+        for (i, &ch) in chars.iter().enumerate() {
+            match ch {
+                '\\' => out.push_str("\\\\"),
+                '"' => out.push_str("\\\""),
+                '`' if self.expand != Expand::Full => out.push_str("\\`"),
+                '$' if escape_dollar(&chars, i, self.expand) => out.push_str("\\$"),
+                _ => out.push(ch),
+            }
+        }
+        out
+    }
+
+    fn assign_replace(&self, var: &str, value: &str) -> String {
+        format!("export {}={};", var, self.quoted(value))
+    }
+
+    fn unset(&self, var: &str) -> String {
+        format!("unset {var};")
+    }
+
+    fn assign_prepend(&self, var: &str, value: &str, sep: &str) -> String {
+        // Only insert the separator + existing var if it is non-empty:
+        // VAR="<new>${VAR:+<sep>}${VAR}"
+        //
+        // This is functionally equivalent to `${VAR:+<sep>${VAR}}` but reads clearer.
+        let tail = format!("${{{}:+{}}}${{{}}}", var, sep, var);
+        match self.quote {
+            Quote::Double => format!("export {}=\"{}{}\";", var, self.quote_literal(value), tail),
+            // The reference stays outside the literal: VAR='<new>'"${VAR:+<sep>}${VAR}"
+            Quote::Single => format!("export {}={}\"{}\";", var, single_quote(value), tail),
+        }
+    }
+
+    fn assign_append(&self, var: &str, value: &str, sep: &str) -> String {
+        // Only insert the existing var + separator if it is non-empty:
+        // VAR="${VAR:+${VAR}<sep>}<new>"
+        let head = format!("${{{}:+${{{}}}{}}}", var, var, sep);
+        match self.quote {
+            Quote::Double => format!("export {}=\"{}{}\";", var, head, self.quote_literal(value)),
+            Quote::Single => format!("export {}=\"{}\"{};", var, head, single_quote(value)),
+        }
+    }
+
+    fn unless_present(&self, var: &str, parts: &[String], sep: &str, statement: &str) -> String {
+        // case "<sep>${VAR}<sep>" in *"<sep><part><sep>"*) ;; *) <statement> ;; esac;
+        let patterns: Vec<String> = presence_patterns(parts, sep)
+            .iter()
+            .map(|segments| {
+                let quoted: Vec<String> = segments.iter().map(|s| self.quoted(s)).collect();
+                format!("*{}*", quoted.join("*"))
+            })
+            .collect();
+        let sep = self.quote_literal(sep);
+        format!(
+            "case \"{sep}${{{var}}}{sep}\" in {}) ;; *) {statement} ;; esac;",
+            patterns.join(" | ")
+        )
+    }
+
+    fn assign_surround(&self, var: &str, before: &str, after: &str, sep: &str) -> String {
+        // VAR="<before>${VAR:+<sep>${VAR}}<sep><after>"
+        let middle = format!("${{{}:+{}${{{}}}}}{}", var, sep, var, sep);
+        match self.quote {
+            Quote::Double => format!(
+                "export {}=\"{}{}{}\";",
+                var,
+                self.quote_literal(before),
+                middle,
+                self.quote_literal(after)
+            ),
+            Quote::Single => format!(
+                "export {}={}\"{}\"{};",
+                var,
+                single_quote(before),
+                middle,
+                single_quote(after)
+            ),
+        }
+    }
+}
+
+/// fish, where variables are lists and `string join` rebuilds the old value.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fish {
+    pub expand: Expand,
+}
+
+impl ShellDialect for Fish {
+    fn expands_vars(&self) -> bool {
+        self.expand != Expand::None
+    }
+
+    fn path_separator(&self) -> &'static str {
+        // fish keeps PATH as a list but exports it ':'-joined.
+        ":"
+    }
+
+    fn quote_literal(&self, s: &str) -> String {
+        // Backticks are plain characters in fish; `$(` substitutes commands
+        // inside double quotes since fish 3.4.
+        let chars: Vec<char> = s.chars().collect();
+        let mut out = String::with_capacity(s.len());
+        for (i, &ch) in chars.iter().enumerate() {
+            match ch {
+                '\\' => out.push_str("\\\\"),
+                '"' => out.push_str("\\\""),
+                '$' if escape_dollar(&chars, i, self.expand) => out.push_str("\\$"),
+                _ => out.push(ch),
+            }
+        }
+        out
+    }
+
+    fn assign_replace(&self, var: &str, value: &str) -> String {
+        format!("set -gx {var} \"{}\";", self.quote_literal(value))
+    }
+
+    fn unset(&self, var: &str) -> String {
+        format!("set -e {var};")
+    }
+
+    fn assign_prepend(&self, var: &str, value: &str, sep: &str) -> String {
+        let value = self.quote_literal(value);
+        let sep = self.quote_literal(sep);
+        format!(
+            "if test -n \"${var}\"; set -gx {var} \"{value}{sep}\"(string join -- \"{sep}\" ${var}); else; set -gx {var} \"{value}\"; end;"
+        )
+    }
+
+    fn assign_append(&self, var: &str, value: &str, sep: &str) -> String {
+        let value = self.quote_literal(value);
+        let sep = self.quote_literal(sep);
+        format!(
+            "if test -n \"${var}\"; set -gx {var} (string join -- \"{sep}\" ${var})\"{sep}{value}\"; else; set -gx {var} \"{value}\"; end;"
+        )
+    }
+
+    fn unless_present(&self, var: &str, parts: &[String], sep: &str, statement: &str) -> String {
+        // An unset list joins to no arguments at all, so `string match` fails.
+        let value = {
+            let sep = self.quote_literal(sep);
+            format!("\"{sep}\"(string join -- \"{sep}\" ${var})\"{sep}\"")
+        };
+        let tests: Vec<String> = presence_patterns(parts, sep)
+            .iter()
+            .map(|segments| {
+                let quoted: Vec<String> = segments.iter().map(|s| self.quote_literal(s)).collect();
+                format!("not string match -q -- \"*{}*\" {value}", quoted.join("*"))
+            })
+            .collect();
+        format!("if {}; {statement} end;", tests.join("; and "))
+    }
+
+    fn assign_surround(&self, var: &str, before: &str, after: &str, sep: &str) -> String {
+        let before = self.quote_literal(before);
+        let after = self.quote_literal(after);
+        let sep = self.quote_literal(sep);
+        format!(
+            "if test -n \"${var}\"; set -gx {var} \"{before}{sep}\"(string join -- \"{sep}\" ${var})\"{sep}{after}\"; else; set -gx {var} \"{before}{sep}{after}\"; end;"
+        )
+    }
+}
+
+/// The `--shell` choices of `bath export`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShellKind {
+    #[default]
+    Posix,
+    Fish,
+}
+
+impl std::str::FromStr for ShellKind {
+    type Err = BathError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "posix" | "sh" | "bash" | "zsh" => Ok(ShellKind::Posix),
+            "fish" => Ok(ShellKind::Fish),
+            _ => Err(BathError::InvalidArgument(format!(
+                "unsupported shell: {s} (expected posix or fish)"
+            ))),
+        }
+    }
+}
+
+impl ShellKind {
+    /// The dialect for this shell. `quote` only applies to posix shells.
+    pub fn dialect(self, expand: Expand, quote: Quote) -> Box<dyn ShellDialect> {
+        match self {
+            ShellKind::Posix => Box::new(Posix { expand, quote }),
+            ShellKind::Fish => Box::new(Fish { expand }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIALECTS: &[ShellKind] = &[ShellKind::Posix, ShellKind::Fish];
+
+    /// (raw value, expected quote_literal output) shared by every dialect.
+    const QUOTING_CASES: &[(&str, &str)] = &[
+        ("plain", "plain"),
+        ("with space", "with space"),
+        ("say \"hi\"", "say \\\"hi\\\""),
+        ("C:\\dir", "C:\\\\dir"),
+        ("$HOME/bin", "$HOME/bin"),
+        ("", ""),
+    ];
+
+    #[test]
+    fn every_dialect_quotes_edge_cases_the_same_way() {
+        for kind in DIALECTS {
+            let d = kind.dialect(Expand::Vars, Quote::Double);
+            for (raw, quoted) in QUOTING_CASES {
+                assert_eq!(d.quote_literal(raw), *quoted, "{kind:?}: {raw:?}");
+                assert!(
+                    d.assign_replace("V", raw)
+                        .contains(&format!("\"{quoted}\"")),
+                    "{kind:?}: {raw:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_dialect_passes_on_colon_separated_paths() {
+        for kind in DIALECTS {
+            let d = kind.dialect(Expand::Vars, Quote::Double);
+            assert_eq!(d.path_separator(), ":", "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn unknown_choices_are_invalid_arguments() {
+        let err = "csh".parse::<ShellKind>().unwrap_err();
+        assert_eq!(err.exit_code(), 2);
+        assert!(err.to_string().contains("posix or fish"));
+        assert!(matches!(
+            "some".parse::<Expand>(),
+            Err(BathError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            "back".parse::<Quote>(),
+            Err(BathError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn only_replace_ignores_the_old_value() {
+        for kind in DIALECTS {
+            let d = kind.dialect(Expand::Vars, Quote::Double);
+            for mode in [OperationMode::Prepend, OperationMode::Append] {
+                let line = d.assign("PATH", "/opt/bin", ":", mode);
+                assert!(
+                    line.contains("$PATH") || line.contains("${PATH}"),
+                    "{kind:?}: {line}"
+                );
+                assert!(line.contains("/opt/bin"), "{kind:?}: {line}");
+            }
+            let line = d.assign("CC", "gcc", " ", OperationMode::Replace);
+            assert!(
+                !line.contains("$CC") && !line.contains("${CC}"),
+                "{kind:?}: {line}"
+            );
+        }
+    }
+
+    #[test]
+    fn posix_assignments_match_previous_output() {
+        assert_eq!(
+            Posix::default().assign("CPATH", "/opt/inc", ":", OperationMode::Prepend),
+            "export CPATH=\"/opt/inc${CPATH:+:}${CPATH}\";"
+        );
+        assert_eq!(
+            Posix::default().assign("CPATH", "/opt/inc", ":", OperationMode::Append),
+            "export CPATH=\"${CPATH:+${CPATH}:}/opt/inc\";"
+        );
+    }
+
+    #[test]
+    fn expand_policy_controls_substitutions() {
+        let raw = "`rm -rf ~` $(id) $HOME ${X}";
+        let posix = |expand| {
+            Posix {
+                expand,
+                ..Posix::default()
+            }
+            .quote_literal(raw)
+        };
+        assert_eq!(posix(Expand::Vars), "\\`rm -rf ~\\` \\$(id) $HOME ${X}");
+        assert_eq!(posix(Expand::None), "\\`rm -rf ~\\` \\$(id) \\$HOME \\${X}");
+        assert_eq!(posix(Expand::Full), raw);
+
+        let fish = |expand| Fish { expand }.quote_literal(raw);
+        assert_eq!(fish(Expand::Vars), "`rm -rf ~` \\$(id) $HOME ${X}");
+        assert_eq!(fish(Expand::None), "`rm -rf ~` \\$(id) \\$HOME \\${X}");
+    }
+
+    #[test]
+    fn posix_eval_runs_no_commands_unless_full() {
+        let raw = "a`echo x`b$(echo y)c:$HOME";
+        for (expand, expected) in [
+            (Expand::Vars, "a`echo x`b$(echo y)c:/h"),
+            (Expand::None, "a`echo x`b$(echo y)c:$HOME"),
+            (Expand::Full, "axbyc:/h"),
+        ] {
+            let script = format!(
+                "{}\nprintf '%s' \"$V\"",
+                Posix {
+                    expand,
+                    ..Posix::default()
+                }
+                .assign_replace("V", raw)
+            );
+            let out = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(&script)
+                .env("HOME", "/h")
+                .output()
+                .expect("run sh");
+            assert_eq!(String::from_utf8_lossy(&out.stdout), expected, "{expand:?}");
+        }
+    }
+
+    #[test]
+    fn single_quotes_keep_quotes_and_dollars_literal() {
+        let single = Posix {
+            quote: Quote::Single,
+            ..Posix::default()
+        };
+        let raw = "it's $HOME";
+        assert_eq!(single_quote(raw), "'it'\\''s $HOME'");
+        assert_eq!(
+            single.assign("V", raw, ":", OperationMode::Prepend),
+            "export V='it'\\''s $HOME'\"${V:+:}${V}\";"
+        );
+        let script = [
+            single.assign_replace("A", raw),
+            single.assign("B", raw, ":", OperationMode::Prepend),
+            single.assign("C", raw, ":", OperationMode::Append),
+            "printf '%s|%s|%s' \"$A\" \"$B\" \"$C\"".to_string(),
+        ]
+        .join("\n");
+        let out = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&script)
+            .env("HOME", "/h")
+            .env("B", "/old")
+            .env_remove("C")
+            .output()
+            .expect("run sh");
+        assert_eq!(
+            String::from_utf8_lossy(&out.stdout),
+            "it's $HOME|it's $HOME:/old|it's $HOME"
+        );
+    }
+
+    #[test]
+    fn presence_patterns_allow_the_old_value_between_parts() {
+        let parts = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(presence_patterns(&parts(&["/a"]), ":"), vec![vec![":/a:"]]);
+        assert_eq!(
+            presence_patterns(&parts(&["/a", "/b"]), ":"),
+            vec![
+                vec![":/a:/b:".to_string()],
+                vec![":/a:".into(), ":/b:".into()]
+            ]
+        );
+        assert_eq!(
+            Posix::default().unless_present("V", &parts(&["-O2"]), " ", "export V=x;"),
+            "case \" ${V} \" in *\" -O2 \"*) ;; *) export V=x; ;; esac;"
+        );
+        assert_eq!(
+            Fish::default().unless_present("V", &parts(&["/a"]), ":", "set -gx V x;"),
+            "if not string match -q -- \"*:/a:*\" \":\"(string join -- \":\" $V)\":\"; set -gx V x; end;"
+        );
+    }
+}
@@ -0,0 +1,260 @@
+// src/diff.rs
+
+use crate::config::EnvProfile;
+use crate::db;
+use crate::export::{group_entries, VarGroup};
+use anyhow::Result;
+use serde::Serialize;
+
+/// How a single variable differs between two profiles.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum VarDiff {
+    OnlyInA {
+        name: String,
+        parts: Vec<String>,
+    },
+    OnlyInB {
+        name: String,
+        parts: Vec<String>,
+    },
+    Changed {
+        name: String,
+        removed: Vec<String>,
+        added: Vec<String>,
+        /// True if the parts both profiles share appear in a different order.
+        reordered: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProfileDiff {
+    pub a: String,
+    pub b: String,
+    pub vars: Vec<VarDiff>,
+}
+
+impl ProfileDiff {
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+}
+
+/// Removes the first occurrence of each element of `other` from `parts`
+/// (multiset difference), keeping the remaining order.
+fn subtract(parts: &[String], other: &[String]) -> Vec<String> {
+    let mut pool: Vec<&String> = other.iter().collect();
+    let mut out = Vec::new();
+    for p in parts {
+        if let Some(i) = pool.iter().position(|o| *o == p) {
+            pool.remove(i);
+        } else {
+            out.push(p.clone());
+        }
+    }
+    out
+}
+
+fn diff_group(a: &VarGroup, b: &VarGroup) -> Option<VarDiff> {
+    if a.parts == b.parts {
+        return None;
+    }
+    let removed = subtract(&a.parts, &b.parts);
+    let added = subtract(&b.parts, &a.parts);
+    // The shared parts, in each profile's own order.
+    let common_a = subtract(&a.parts, &removed);
+    let common_b = subtract(&b.parts, &added);
+    Some(VarDiff::Changed {
+        name: a.name.clone(),
+        removed,
+        added,
+        reordered: common_a != common_b,
+    })
+}
+
+/// Compares two profiles variable by variable.
+///
+/// Variables are reported in A's order first, followed by those only present in B.
+pub fn diff_profiles(a: &EnvProfile, b: &EnvProfile) -> ProfileDiff {
+    let groups_a = group_entries(&a.entries);
+    let groups_b = group_entries(&b.entries);
+
+    let mut vars = Vec::new();
+    for ga in &groups_a {
+        match groups_b.iter().find(|gb| gb.name == ga.name) {
+            Some(gb) => vars.extend(diff_group(ga, gb)),
+            None => vars.push(VarDiff::OnlyInA {
+                name: ga.name.clone(),
+                parts: ga.parts.clone(),
+            }),
+        }
+    }
+    for gb in &groups_b {
+        if !groups_a.iter().any(|ga| ga.name == gb.name) {
+            vars.push(VarDiff::OnlyInB {
+                name: gb.name.clone(),
+                parts: gb.parts.clone(),
+            });
+        }
+    }
+
+    ProfileDiff {
+        a: a.name.clone(),
+        b: b.name.clone(),
+        vars,
+    }
+}
+
+/// Renders a diff as human readable text with `+`/`-` prefixes.
+pub fn render_diff(diff: &ProfileDiff) -> String {
+    let mut lines = vec![format!("--- {}", diff.a), format!("+++ {}", diff.b)];
+    for v in &diff.vars {
+        match v {
+            VarDiff::OnlyInA { name, parts } => {
+                lines.push(format!("{name} (only in {})", diff.a));
+                lines.extend(parts.iter().map(|p| format!("  - {p}")));
+            }
+            VarDiff::OnlyInB { name, parts } => {
+                lines.push(format!("{name} (only in {})", diff.b));
+                lines.extend(parts.iter().map(|p| format!("  + {p}")));
+            }
+            VarDiff::Changed {
+                name,
+                removed,
+                added,
+                reordered,
+            } => {
+                lines.push(name.clone());
+                lines.extend(removed.iter().map(|p| format!("  - {p}")));
+                lines.extend(added.iter().map(|p| format!("  + {p}")));
+                if *reordered {
+                    lines.push("  ~ parts reordered".to_string());
+                }
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+/// Prints the diff between two stored profiles.
+///
+/// Returns true if the profiles differ.
+pub fn diff_command(a: &str, b: &str, json: bool) -> Result<bool> {
+    let conn = db::establish_connection()?;
+    let pa = db::load_profile(&conn, a)?;
+    let pb = db::load_profile(&conn, b)?;
+    let diff = diff_profiles(&pa, &pb);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else if !diff.is_empty() {
+        println!("{}", render_diff(&diff));
+    }
+    Ok(!diff.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Entry;
+
+    fn profile(name: &str, entries: Vec<Entry>) -> EnvProfile {
+        EnvProfile {
+            entries,
+            ..EnvProfile::new(name)
+        }
+    }
+
+    #[test]
+    fn identical_profiles_have_an_empty_diff() {
+        let a = profile("a", vec![Entry::CFlag("-O2".to_string())]);
+        let b = profile("b", vec![Entry::CFlag("-O2".to_string())]);
+        assert!(diff_profiles(&a, &b).is_empty());
+    }
+
+    #[test]
+    fn reports_vars_only_in_one_profile() {
+        let a = profile("a", vec![Entry::CC("gcc".to_string())]);
+        let b = profile("b", vec![Entry::CXX("g++".to_string())]);
+        let d = diff_profiles(&a, &b);
+        assert_eq!(
+            d.vars,
+            vec![
+                VarDiff::OnlyInA {
+                    name: "CC".to_string(),
+                    parts: vec!["gcc".to_string()],
+                },
+                VarDiff::OnlyInB {
+                    name: "CXX".to_string(),
+                    parts: vec!["g++".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_added_removed_and_reordered_parts() {
+        let a = profile(
+            "a",
+            vec![
+                Entry::CFlag("-O2".to_string()),
+                Entry::CFlag("-g".to_string()),
+                Entry::CFlag("-Wall".to_string()),
+            ],
+        );
+        let b = profile(
+            "b",
+            vec![
+                Entry::CFlag("-Wall".to_string()),
+                Entry::CFlag("-O2".to_string()),
+                Entry::CFlag("-fsanitize=address".to_string()),
+            ],
+        );
+        let d = diff_profiles(&a, &b);
+        assert_eq!(
+            d.vars,
+            vec![VarDiff::Changed {
+                name: "CFLAGS".to_string(),
+                removed: vec!["-g".to_string()],
+                added: vec!["-fsanitize=address".to_string()],
+                reordered: true,
+            }]
+        );
+
+        let text = render_diff(&d);
+        assert!(text.contains("  - -g"));
+        assert!(text.contains("  + -fsanitize=address"));
+        assert!(text.contains("~ parts reordered"));
+    }
+
+    #[test]
+    fn duplicate_parts_are_compared_as_a_multiset() {
+        let a = profile(
+            "a",
+            vec![
+                Entry::CPath("/x".to_string()),
+                Entry::CPath("/x".to_string()),
+            ],
+        );
+        let b = profile("b", vec![Entry::CPath("/x".to_string())]);
+        let d = diff_profiles(&a, &b);
+        assert_eq!(
+            d.vars,
+            vec![VarDiff::Changed {
+                name: "CPATH".to_string(),
+                removed: vec!["/x".to_string()],
+                added: vec![],
+                reordered: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn json_output_is_tagged_by_change_kind() -> Result<()> {
+        let a = profile("a", vec![Entry::CC("gcc".to_string())]);
+        let b = profile("b", vec![]);
+        let v = serde_json::to_value(diff_profiles(&a, &b))?;
+        assert_eq!(v["vars"][0]["change"], "only_in_a");
+        assert_eq!(v["vars"][0]["name"], "CC");
+        Ok(())
+    }
+}
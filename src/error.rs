@@ -0,0 +1,223 @@
+// src/error.rs
+
+use std::path::PathBuf;
+use thiserror::Error;
+
+pub type Result<T, E = BathError> = std::result::Result<T, E>;
+
+/// Errors the CLI reports to users. Each maps to an exit code in `main`.
+#[derive(Debug, Error)]
+pub enum BathError {
+    #[error("profile '{name}' not found{}", did_you_mean(suggestions))]
+    ProfileNotFound {
+        name: String,
+        suggestions: Vec<String>,
+    },
+
+    #[error("profile '{0}' already exists")]
+    ProfileExists(String),
+
+    #[error("{var} has no entries in profile '{profile}'")]
+    VarNotInProfile { var: String, profile: String },
+
+    #[error(
+        "invalid variable name: {0:?} (use letters, digits and '_', not starting with a digit)"
+    )]
+    InvalidVarName(String),
+
+    #[error("{0}")]
+    InvalidArgument(String),
+
+    #[error("variables reference each other in a cycle: {}", .0.join(" -> "))]
+    ReferenceCycle(Vec<String>),
+
+    #[error("profiles extend each other in a cycle: {}", .0.join(" -> "))]
+    InheritanceCycle(Vec<String>),
+
+    #[error("the database is locked by another bath process, try again")]
+    DatabaseLocked,
+
+    #[error("neither HOME nor XDG_DATA_HOME is set, so the default database cannot be located; pass --db <path> or set {}", crate::db::DB_ENV)]
+    NoDatabasePath,
+
+    #[error("the database uses schema version {found}, but this bath only knows up to {supported}; upgrade bath to open it")]
+    SchemaTooNew { found: u32, supported: u32 },
+
+    #[error("database error: {0}")]
+    Database(rusqlite::Error),
+
+    // The causes of these are part of the message, not `source()`, so that
+    // `report_error` does not print them a second time.
+    #[error("invalid profile data: {0}")]
+    Json(serde_json::Error),
+
+    #[error("failed to {action} {}: {error}", path.display())]
+    File {
+        action: &'static str,
+        path: PathBuf,
+        error: std::io::Error,
+    },
+
+    #[error("terminal error: {0}")]
+    Terminal(std::io::Error),
+}
+
+impl From<rusqlite::Error> for BathError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e.sqlite_error_code() {
+            Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked) => {
+                BathError::DatabaseLocked
+            }
+            _ => BathError::Database(e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for BathError {
+    fn from(e: serde_json::Error) -> Self {
+        BathError::Json(e)
+    }
+}
+
+impl BathError {
+    /// Process exit code: 2 for bad input, 3 for missing things, 4 for a busy
+    /// database and 5 for other storage or I/O failures.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BathError::InvalidVarName(_)
+            | BathError::InvalidArgument(_)
+            | BathError::ReferenceCycle(_)
+            | BathError::InheritanceCycle(_)
+            | BathError::ProfileExists(_) => 2,
+            BathError::ProfileNotFound { .. } | BathError::VarNotInProfile { .. } => 3,
+            BathError::DatabaseLocked => 4,
+            BathError::NoDatabasePath
+            | BathError::SchemaTooNew { .. }
+            | BathError::Database(_)
+            | BathError::Json(_)
+            | BathError::File { .. }
+            | BathError::Terminal(_) => 5,
+        }
+    }
+
+    /// Shorthand for `File { action, path, error }`.
+    pub fn file(action: &'static str, path: impl Into<PathBuf>, error: std::io::Error) -> Self {
+        BathError::File {
+            action,
+            path: path.into(),
+            error,
+        }
+    }
+}
+
+/// Exit code for any error reaching `main`: the code of the first `BathError` in
+/// the chain, 1 otherwise.
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    err.chain()
+        .find_map(|e| e.downcast_ref::<BathError>())
+        .map_or(1, BathError::exit_code)
+}
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    match suggestions {
+        [] => String::new(),
+        [one] => format!(" (did you mean '{one}'?)"),
+        many => format!(
+            " (did you mean one of: {}?)",
+            many.iter()
+                .map(|s| format!("'{s}'"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Levenshtein distance over chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Up to three candidates close to `name`, best first. A candidate qualifies if
+/// it contains `name` (ignoring case) or is within a third of its length in edits.
+pub fn suggest(name: &str, candidates: &[String]) -> Vec<String> {
+    let lower = name.to_lowercase();
+    let max = (name.chars().count() / 3).max(1);
+    let mut scored: Vec<(usize, &String)> = candidates
+        .iter()
+        .filter_map(|c| {
+            let c_lower = c.to_lowercase();
+            let d = edit_distance(&lower, &c_lower);
+            (d <= max || c_lower.contains(&lower)).then_some((d, c))
+        })
+        .collect();
+    scored.sort();
+    scored.into_iter().take(3).map(|(_, c)| c.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn suggests_close_profile_names_best_first() {
+        let candidates = names(&["cross-arm", "cross-arm-debug", "native", "llvm"]);
+        assert_eq!(suggest("cross-amr", &candidates), vec!["cross-arm"]);
+        assert_eq!(
+            suggest("arm", &candidates),
+            vec!["cross-arm", "cross-arm-debug"]
+        );
+        assert_eq!(suggest("Native", &candidates), vec!["native"]);
+        assert!(suggest("gcc", &candidates).is_empty());
+    }
+
+    #[test]
+    fn not_found_message_and_exit_codes() {
+        let err = BathError::ProfileNotFound {
+            name: "nativ".to_string(),
+            suggestions: names(&["native"]),
+        };
+        assert_eq!(
+            err.to_string(),
+            "profile 'nativ' not found (did you mean 'native'?)"
+        );
+        assert_eq!(err.exit_code(), 3);
+        assert_eq!(BathError::DatabaseLocked.exit_code(), 4);
+        assert_eq!(BathError::InvalidVarName("1X".into()).exit_code(), 2);
+
+        let wrapped = anyhow::Error::from(err).context("while exporting");
+        assert_eq!(exit_code(&wrapped), 3);
+        assert_eq!(exit_code(&anyhow::anyhow!("other")), 1);
+    }
+
+    #[test]
+    fn causes_are_reported_once() {
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        let err = anyhow::Error::from(BathError::file("read", "/tmp/x.json", missing));
+        assert_eq!(
+            format!("{err:#}"),
+            "failed to read /tmp/x.json: entity not found"
+        );
+
+        let json = serde_json::from_str::<u32>("x").unwrap_err();
+        let cause = json.to_string();
+        let err = anyhow::Error::from(BathError::from(json)).context("loading p");
+        assert_eq!(
+            format!("{err:#}"),
+            format!("loading p: invalid profile data: {cause}")
+        );
+    }
+}
@@ -1,318 +1,4185 @@
 // src/export.rs
 
-use crate::config::{Entry, EnvProfile};
+use crate::config::{
+    make_entry, string_builtin_entry, CustomVarDef, Entry, EnvProfile, SeparatorOverrides, VarKind,
+};
 use crate::db;
-use anyhow::Result;
+use crate::dialect::{Expand, Posix, Quote, ShellDialect, ShellKind};
+use crate::error::{BathError, Result};
+use crate::template;
+use crate::validate::normalize_separators;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OperationMode {
     Prepend,
     Append,
     Replace,
 }
 
-fn shell_double_quote_literal(s: &str) -> String {
-    // Escape for inside double quotes.
-    //
-    // Intentionally does NOT escape '$' so things like $HOME and ${VAR}
-    // expand at eval-time as requested.
-    let mut out = String::with_capacity(s.len());
-    for ch in s.chars() {
-        match ch {
-            '\\' => out.push_str("\\\\"),
-            '"' => out.push_str("\\\""),
-            _ => out.push(ch),
+impl std::str::FromStr for OperationMode {
+    type Err = BathError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "prepend" => Ok(OperationMode::Prepend),
+            "append" => Ok(OperationMode::Append),
+            "replace" => Ok(OperationMode::Replace),
+            _ => Err(invalid(format!(
+                "unknown mode: {s} (expected prepend, append or replace)"
+            ))),
         }
     }
-    out
 }
 
-fn export_assignment(var_name: &str, value: &str, sep: &str, mode: OperationMode) -> String {
-    let escaped_value = shell_double_quote_literal(value);
-    match mode {
-        OperationMode::Prepend => {
-            // Only insert the separator + existing var if it is non-empty:
-            // VAR="<new>${VAR:+<sep>}${VAR}"
-            //
-            // This is functionally equivalent to `${VAR:+<sep>${VAR}}` but reads clearer.
-            let tail = format!("${{{}:+{}}}${{{}}}", var_name, sep, var_name);
-            format!("export {}=\"{}{}\";", var_name, escaped_value, tail)
+impl OperationMode {
+    /// The CLI spelling of the mode.
+    pub fn name(self) -> &'static str {
+        match self {
+            OperationMode::Prepend => "prepend",
+            OperationMode::Append => "append",
+            OperationMode::Replace => "replace",
         }
-        OperationMode::Append => {
-            // Only insert the existing var + separator if it is non-empty:
-            // VAR="${VAR:+${VAR}<sep>}<new>"
-            let head = format!("${{{}:+${{{}}}{}}}", var_name, var_name, sep);
-            format!("export {}=\"{}{}\";", var_name, head, escaped_value)
-        }
-        OperationMode::Replace => format!("export {}=\"{}\";", var_name, escaped_value),
     }
 }
 
-fn entry_value(entry: &Entry) -> String {
+fn invalid(message: impl Into<String>) -> BathError {
+    BathError::InvalidArgument(message.into())
+}
+
+pub(crate) fn shell_double_quote_literal(s: &str) -> String {
+    Posix::default().quote_literal(s)
+}
+
+pub(crate) fn entry_value(entry: &Entry) -> String {
     match entry {
         Entry::Path(pe) => pe.path.clone(),
-        Entry::CPath(s)
-        | Entry::CInclude(s)
-        | Entry::CPlusInclude(s)
-        | Entry::OBJCInclude(s)
-        | Entry::CPPFlag(s)
-        | Entry::CFlag(s)
-        | Entry::CXXFlag(s)
-        | Entry::LDFlag(s)
-        | Entry::LibraryPath(s)
-        | Entry::LDLibraryPath(s)
-        | Entry::LDRunPath(s)
-        | Entry::RanLib(s)
-        | Entry::CC(s)
-        | Entry::CXX(s)
-        | Entry::AR(s)
-        | Entry::Strip(s)
-        | Entry::GCCExecPrefix(s)
-        | Entry::CollectGCCOptions(s)
-        | Entry::Lang(s) => s.clone(),
+        string_builtin_entry!() => {
+            let (_, value) = entry.builtin().expect("builtin entries have a builtin var");
+            value.clone()
+        }
         Entry::CustomScalar { value, .. } => value.clone(),
         Entry::CustomPart { value, .. } => value.clone(),
+        Entry::Annotated { entry, .. } => entry_value(entry),
+        Entry::Unset { .. } => String::new(),
+        Entry::Unknown(_) => entry.unknown_field("value").unwrap_or_default().to_string(),
     }
 }
 
 /// Generates an export command for a single Entry (treated as the new value).
-pub fn generate_export_line(entry: &Entry, mode: OperationMode) -> String {
+pub fn generate_export_line(
+    dialect: &dyn ShellDialect,
+    entry: &Entry,
+    mode: OperationMode,
+) -> String {
     let var_name = entry.var_name();
-    let value = entry_value(entry);
+    let value = home_part(dialect, entry_value(entry));
     let sep = entry.separator();
-    export_assignment(var_name.as_ref(), &value, sep.as_ref(), mode)
+    dialect.assign(var_name.as_ref(), &value, sep.as_ref(), mode)
 }
 
-/// Generates the full export commands for a given profile.
-pub fn generate_full_export(profile: &EnvProfile, mode: OperationMode) -> String {
-    // One export line per variable, with parts joined in the order they were added.
-    //
-    // This keeps editing at the parts level in storage/UI, but export happens at the
-    // variable level (e.g. one PATH assignment).
-    let mut order: Vec<String> = Vec::new();
-    let mut groups: HashMap<String, (String, Vec<String>)> = HashMap::new();
+/// What follows a leading `~` the shell would expand at the start of a word: the
+/// whole value or `~/...`. `~user` forms are left alone.
+fn tilde_rest(value: &str) -> Option<&str> {
+    value
+        .strip_prefix('~')
+        .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Spells a leading `~` as `$HOME`, which expands inside double quotes where a
+/// tilde does not.
+pub fn tilde_to_home(value: &str) -> String {
+    match tilde_rest(value) {
+        Some(rest) => format!("$HOME{rest}"),
+        None => value.to_string(),
+    }
+}
+
+/// Replaces a leading `~` with `home`, i.e. the value the shell ends up with
+/// for `tilde_to_home(value)`.
+pub fn expand_tilde(value: &str, home: &str) -> String {
+    match tilde_rest(value) {
+        Some(rest) => format!("{home}{rest}"),
+        None => value.to_string(),
+    }
+}
+
+/// One variable of a profile with its parts in the order they were added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarGroup {
+    pub name: String,
+    pub separator: String,
+    pub parts: Vec<String>,
+    /// The mode pinned by each part, parallel to `parts`.
+    pub pinned: Vec<Option<OperationMode>>,
+    /// The comment of each part, parallel to `parts`.
+    pub comments: Vec<Option<String>>,
+}
+
+/// Where a variable's parts go relative to the inherited value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placement {
+    /// The inherited value is dropped.
+    Replace(String),
+    /// Joined parts before and after the inherited value, `None` if there are none.
+    Around {
+        before: Option<String>,
+        after: Option<String>,
+    },
+}
+
+impl VarGroup {
+    /// Splits the parts by their effective mode: the pinned one, else `mode`.
+    /// A single part pinned to Replace replaces the whole variable.
+    pub fn placement(&self, mode: OperationMode) -> Placement {
+        let modes: Vec<OperationMode> = self.pinned.iter().map(|p| p.unwrap_or(mode)).collect();
+        if modes.contains(&OperationMode::Replace) {
+            return Placement::Replace(self.parts.join(&self.separator));
+        }
+        let join = |wanted: OperationMode| {
+            let parts: Vec<&str> = self
+                .parts
+                .iter()
+                .zip(&modes)
+                .filter(|(_, m)| **m == wanted)
+                .map(|(p, _)| p.as_str())
+                .collect();
+            (!parts.is_empty()).then(|| parts.join(&self.separator))
+        };
+        Placement::Around {
+            before: join(OperationMode::Prepend),
+            after: join(OperationMode::Append),
+        }
+    }
+}
+
+/// Groups a profile's entries per variable, in order of first appearance.
+/// Disabled parts are skipped as if they were not in the profile.
+///
+/// The separator of a variable is taken from its first entry. Scalar variables
+/// keep only their last value. Empty and whitespace-only parts of list variables
+/// are left out, so no empty segment (the current directory, in PATH) is exported.
+/// Variables with an `Unset` entry get no group at all; see `unset_vars`.
+pub fn group_entries(entries: &[Entry]) -> Vec<VarGroup> {
+    group_entries_with(entries, false, &SeparatorOverrides::new())
+}
+
+/// `group_entries`, keeping empty list parts if `keep_empty` is set and joining
+/// the builtin list vars in `separators` with their override.
+///
+/// `@{VAR}` in a part is replaced by the joined value of VAR in the same entries;
+/// see `resolve_references`.
+pub fn group_entries_with(
+    entries: &[Entry],
+    keep_empty: bool,
+    separators: &SeparatorOverrides,
+) -> Vec<VarGroup> {
+    let mut groups = collect_groups(entries, keep_empty, separators);
+    resolve_references(&mut groups);
+    groups
+}
+
+/// `entries` in the order their parts are exported: the parts of each variable
+/// by priority, lowest first, with a part without a priority counting as 0. The
+/// sort is stable, so parts with the same priority (and all parts, if none has
+/// one) keep the order they have in the profile. Parts only trade places with
+/// parts of the same variable, so the order of the variables does not change.
+pub fn in_priority_order(entries: &[Entry]) -> Vec<&Entry> {
+    let mut ordered: Vec<&Entry> = entries.iter().collect();
+    if entries.iter().all(|e| e.priority().is_none()) {
+        return ordered;
+    }
+    let mut vars: Vec<Cow<str>> = Vec::new();
+    for entry in entries {
+        let var = entry.var_name();
+        if !vars.contains(&var) {
+            vars.push(var);
+        }
+    }
+    for var in vars {
+        let slots: Vec<usize> = (0..entries.len())
+            .filter(|i| entries[*i].var_name() == var)
+            .collect();
+        let mut parts: Vec<&Entry> = slots.iter().map(|i| &entries[*i]).collect();
+        parts.sort_by_key(|e| e.priority().unwrap_or(0));
+        for (slot, part) in slots.into_iter().zip(parts) {
+            ordered[slot] = part;
+        }
+    }
+    ordered
+}
+
+/// The groups of `group_entries_with`, with references left as written.
+fn collect_groups(
+    entries: &[Entry],
+    keep_empty: bool,
+    separators: &SeparatorOverrides,
+) -> Vec<VarGroup> {
+    let mut groups: Vec<VarGroup> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
 
-    for entry in &profile.entries {
+    for entry in in_priority_order(entries) {
+        if !entry.is_exported() {
+            continue;
+        }
+        if !keep_empty
+            && entry_value(entry).trim().is_empty()
+            && !is_scalar_var(&entry.var_name(), entries)
+        {
+            continue;
+        }
         let var = entry.var_name().into_owned();
-        let sep = entry.separator().into_owned();
-        if !groups.contains_key(&var) {
-            order.push(var.clone());
-            groups.insert(var.clone(), (sep, Vec::new()));
+        let i = *index.entry(var.clone()).or_insert_with(|| {
+            groups.push(VarGroup {
+                separator: separators
+                    .get(&var)
+                    .cloned()
+                    .unwrap_or_else(|| entry.separator().into_owned()),
+                name: var,
+                parts: Vec::new(),
+                pinned: Vec::new(),
+                comments: Vec::new(),
+            });
+            groups.len() - 1
+        });
+        groups[i].parts.push(entry_value(entry));
+        groups[i].pinned.push(entry.pinned_mode());
+        groups[i].comments.push(entry.comment().map(str::to_string));
+    }
+    for g in &mut groups {
+        if g.parts.len() > 1 && is_scalar_var(&g.name, entries) {
+            g.parts.drain(..g.parts.len() - 1);
+            g.pinned.drain(..g.pinned.len() - 1);
+            g.comments.drain(..g.comments.len() - 1);
         }
-        if let Some((_sep, parts)) = groups.get_mut(&var) {
-            parts.push(entry_value(entry));
+    }
+    let unset = unset_vars(entries);
+    groups.retain(|g| !unset.contains(&g.name));
+    groups
+}
+
+/// Variables cleared by an enabled `Unset` entry, in order of first appearance.
+pub fn unset_vars(entries: &[Entry]) -> Vec<String> {
+    let mut vars: Vec<String> = Vec::new();
+    for var in entries.iter().filter_map(Entry::unset_var) {
+        if !vars.iter().any(|v| v == var) {
+            vars.push(var.to_string());
         }
     }
+    vars
+}
 
-    let mut lines = Vec::new();
-    for var in order {
-        if let Some((sep, parts)) = groups.remove(&var) {
-            let joined = parts.join(&sep);
-            lines.push(export_assignment(&var, &joined, &sep, mode));
+/// Variables that are both set and unset in `entries`. The unset wins, so
+/// none of their parts are exported.
+pub fn unset_conflicts(entries: &[Entry]) -> Vec<String> {
+    let mut unset = unset_vars(entries);
+    unset.retain(|var| {
+        entries
+            .iter()
+            .any(|e| e.is_exported() && e.var_name() == *var)
+    });
+    unset
+}
+
+/// Splits `value` at its `@{VAR}` references, calling `f` for each with the
+/// variable name; `f` returns the replacement or `None` to keep it as written.
+fn rewrite_references(value: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(at) = rest.find("@{") {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let name = rest[2..]
+            .find('}')
+            .map(|end| &rest[2..2 + end])
+            .filter(|name| crate::defs::is_posix_identifier(name));
+        match name {
+            Some(name) => {
+                let len = name.len() + 3;
+                match f(name) {
+                    Some(replacement) => out.push_str(&replacement),
+                    None => out.push_str(&rest[..len]),
+                }
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('@');
+                rest = &rest[1..];
+            }
         }
     }
-    lines.join("\n")
+    out.push_str(rest);
+    out
 }
 
-/// Exports the given profile as export commands (without a shebang)
-/// so you can eval the commands in your shell.
-pub fn export_profile(profile_name: &str, mode: OperationMode) -> Result<()> {
-    let conn = db::establish_connection()?;
-    let profile: EnvProfile = db::load_profile(&conn, profile_name)?;
-    let out = generate_full_export(&profile, mode);
-    if !out.is_empty() {
-        println!("{out}");
+/// Names of the variables referenced by `@{VAR}` in `value`, in order.
+fn references(value: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    rewrite_references(value, |name| {
+        names.push(name.to_string());
+        None
+    });
+    names
+}
+
+/// Replaces `@{VAR}` references with the joined profile value of VAR (not the
+/// value in the shell). Variables are resolved once everything they reference
+/// is, so chains work; references in a cycle and to variables the entries do
+/// not set are left as written. `reference_cycles` reports the cycles.
+fn resolve_references(groups: &mut [VarGroup]) {
+    let names: Vec<String> = groups.iter().map(|g| g.name.clone()).collect();
+    let pending = |g: &VarGroup| {
+        g.parts
+            .iter()
+            .flat_map(|p| references(p))
+            .any(|r| names.contains(&r))
+    };
+    loop {
+        let ready: HashMap<String, String> = groups
+            .iter()
+            .filter(|g| !pending(g))
+            .map(|g| (g.name.clone(), g.parts.join(&g.separator)))
+            .collect();
+        let mut changed = false;
+        for g in groups.iter_mut() {
+            for part in &mut g.parts {
+                let resolved = rewrite_references(part, |name| ready.get(name).cloned());
+                if resolved != *part {
+                    *part = resolved;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
     }
-    Ok(())
 }
 
-/// Launches an interactive ratatui TUI to select a profile to export.
-/// When a profile is selected, its export commands (according to the given mode)
-/// are printed to stdout.
-pub fn interactive_export(mode: OperationMode) -> Result<()> {
-    use crossterm::event::{poll, read, Event, KeyCode};
-    use crossterm::execute;
-    use crossterm::terminal::{
-        disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+/// Cycles of `@{VAR}` references between the variables of `entries`, each as
+/// the path through it back to its first variable, e.g. `[A, B, A]`.
+pub fn reference_cycles(entries: &[Entry]) -> Vec<Vec<String>> {
+    let groups = collect_groups(entries, true, &SeparatorOverrides::new());
+    let edges: HashMap<&str, Vec<String>> = groups
+        .iter()
+        .map(|g| {
+            let mut refs: Vec<String> = g.parts.iter().flat_map(|p| references(p)).collect();
+            refs.retain(|r| groups.iter().any(|o| o.name == *r));
+            refs.dedup();
+            (g.name.as_str(), refs)
+        })
+        .collect();
+
+    fn visit<'a>(
+        var: &'a str,
+        edges: &'a HashMap<&str, Vec<String>>,
+        path: &mut Vec<&'a str>,
+        done: &mut Vec<&'a str>,
+        cycles: &mut Vec<Vec<String>>,
+    ) {
+        if let Some(start) = path.iter().position(|v| *v == var) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|v| v.to_string()).collect();
+            cycle.push(var.to_string());
+            cycles.push(cycle);
+            return;
+        }
+        if done.contains(&var) {
+            return;
+        }
+        path.push(var);
+        for next in edges.get(var).into_iter().flatten() {
+            visit(next, edges, path, done, cycles);
+        }
+        path.pop();
+        done.push(var);
+    }
+
+    let mut cycles = Vec::new();
+    let mut done = Vec::new();
+    for g in &groups {
+        visit(&g.name, &edges, &mut Vec::new(), &mut done, &mut cycles);
+    }
+    cycles
+}
+
+/// Fails with the first reference cycle among the entries of `profile`.
+pub fn check_references(profile: &EnvProfile) -> Result<()> {
+    match reference_cycles(&profile.entries).into_iter().next() {
+        Some(cycle) => Err(BathError::ReferenceCycle(cycle)),
+        None => Ok(()),
+    }
+}
+
+/// Drops entries whose value equals an earlier part of the same variable after
+/// trimming, keeping the first occurrence and the order of everything else.
+/// Disabled parts are kept and never count as an earlier occurrence.
+/// Returns the remaining entries and how many were dropped. Only for what gets
+/// exported; settings such as comments do not tell parts apart here.
+pub fn dedup_entries(entries: &[Entry]) -> (Vec<Entry>, usize) {
+    let mut seen = std::collections::HashSet::new();
+    let kept: Vec<Entry> = entries
+        .iter()
+        .filter(|e| {
+            !e.is_exported() || seen.insert((e.var_name(), entry_value(e).trim().to_string()))
+        })
+        .cloned()
+        .collect();
+    let dropped = entries.len() - kept.len();
+    (kept, dropped)
+}
+
+/// Names of the variables a profile sets, in export order, followed by the
+/// ones it unsets.
+pub fn profile_var_names(profile: &EnvProfile) -> Vec<String> {
+    group_entries(&profile.entries)
+        .into_iter()
+        .map(|g| g.name)
+        .chain(unset_vars(&profile.entries))
+        .collect()
+}
+
+/// A variable ready for output: (name, parts joined with the separator, separator).
+pub type JoinedVar = (String, String, String);
+
+/// Joins the parts of each group with its separator.
+pub fn join_groups(groups: Vec<VarGroup>) -> Vec<JoinedVar> {
+    groups
+        .into_iter()
+        .map(|g| {
+            let joined = g.parts.join(&g.separator);
+            (g.name, joined, g.separator)
+        })
+        .collect()
+}
+
+/// Groups a profile's entries per variable and joins their parts, in export order.
+///
+/// Every output format (shell, JSON, env file) is built from this.
+pub fn grouped_vars(entries: &[Entry]) -> Vec<JoinedVar> {
+    join_groups(group_entries(entries))
+}
+
+/// Per-variable modes taking precedence over the default mode.
+pub type ModeOverrides = HashMap<String, OperationMode>;
+
+/// Parses `VAR=mode` specs (from `--mode-for`) into overrides.
+pub fn parse_mode_overrides(specs: &[String]) -> Result<ModeOverrides> {
+    let mut overrides = ModeOverrides::new();
+    for spec in specs {
+        let Some((var, mode)) = spec.split_once('=') else {
+            return Err(invalid(format!(
+                "invalid --mode-for {spec:?} (expected VAR=prepend|append|replace)"
+            )));
+        };
+        if var.is_empty() {
+            return Err(invalid(format!(
+                "invalid --mode-for {spec:?}: missing variable name"
+            )));
+        }
+        overrides.insert(var.to_string(), mode.parse()?);
+    }
+    Ok(overrides)
+}
+
+/// Override names that do not match any variable of the profile.
+pub fn unknown_override_vars(profile: &EnvProfile, overrides: &ModeOverrides) -> Vec<String> {
+    let vars = profile_var_names(profile);
+    let mut unknown: Vec<String> = overrides
+        .keys()
+        .filter(|k| !vars.contains(k))
+        .cloned()
+        .collect();
+    unknown.sort();
+    unknown
+}
+
+fn mode_for(var: &str, default_mode: OperationMode, overrides: &ModeOverrides) -> OperationMode {
+    overrides.get(var).copied().unwrap_or(default_mode)
+}
+
+/// `overrides` plus Replace for every scalar var without an explicit override, so
+/// e.g. CC is never prepended to an inherited compiler name.
+pub fn with_scalar_modes(entries: &[Entry], overrides: &ModeOverrides) -> ModeOverrides {
+    let mut all = overrides.clone();
+    for g in group_entries(entries) {
+        if is_scalar_var(&g.name, entries) {
+            all.entry(g.name).or_insert(OperationMode::Replace);
+        }
+    }
+    all
+}
+
+/// Scalar vars set more than once in a profile; only the last value is exported.
+pub fn scalar_conflicts(profile: &EnvProfile) -> Vec<String> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for entry in profile.entries.iter().filter(|e| e.is_exported()) {
+        let var = entry.var_name();
+        if !is_scalar_var(&var, &profile.entries) {
+            continue;
+        }
+        match counts.iter_mut().find(|(v, _)| *v == var) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((var.into_owned(), 1)),
+        }
+    }
+    counts
+        .into_iter()
+        .filter(|(_, n)| *n > 1)
+        .map(|(v, _)| v)
+        .collect()
+}
+
+/// Generates the full export commands for a given profile, with variables in `order`
+/// and the builtin list variables in `separators` joined with their override.
+pub fn generate_full_export(
+    dialect: &dyn ShellDialect,
+    profile: &EnvProfile,
+    mode: OperationMode,
+    order: VarOrder,
+    separators: &SeparatorOverrides,
+) -> String {
+    generate_full_export_with_overrides(
+        dialect,
+        profile,
+        mode,
+        &ModeOverrides::new(),
+        order,
+        separators,
+    )
+}
+
+/// Like `generate_full_export`, but variables listed in `overrides` use their own mode.
+///
+/// This is what `bath export` prints for the shell format without further
+/// options, and what the TUI Export view shows.
+pub fn generate_full_export_with_overrides(
+    dialect: &dyn ShellDialect,
+    profile: &EnvProfile,
+    default_mode: OperationMode,
+    overrides: &ModeOverrides,
+    order: VarOrder,
+    separators: &SeparatorOverrides,
+) -> String {
+    let opts = ExportOptions {
+        mode: Some(default_mode),
+        overrides: overrides.clone(),
+        order,
+        separators: separators.clone(),
+        ..ExportOptions::default()
     };
-    use ratatui::backend::CrosstermBackend;
-    use ratatui::layout::{Constraint, Direction, Layout};
-    use ratatui::style::Style;
-    use ratatui::widgets::{Block, Borders, List, ListItem, ListState};
-    use ratatui::Terminal;
-    use std::io::stdout;
+    shell_export(dialect, profile, &opts)
+}
 
-    let conn = db::establish_connection()?;
-    let profiles = db::load_all_profiles(&conn)?;
-    if profiles.is_empty() {
-        println!("No profiles available to export.");
-        return Ok(());
+/// The export statements of `profile` in `dialect` according to `opts`; scalar
+/// vars are replaced unless `opts.overrides` says otherwise.
+fn shell_export(dialect: &dyn ShellDialect, profile: &EnvProfile, opts: &ExportOptions) -> String {
+    let vars = resolve_groups(
+        selected_groups(profile, opts),
+        opts.mode(),
+        &with_scalar_modes(&profile.entries, &opts.overrides),
+        &HashMap::new(),
+    );
+    let mut lines = export_lines(dialect, &vars, opts.idempotent, opts.comments);
+    push_unset_lines(&mut lines, dialect, &selected_unsets(profile, opts));
+    lines
+}
+
+/// Appends one unset statement per variable in `vars` to the export `lines`.
+/// Unsets always come after the assignments.
+fn push_unset_lines(lines: &mut String, dialect: &dyn ShellDialect, vars: &[String]) {
+    for var in vars {
+        if !lines.is_empty() {
+            lines.push('\n');
+        }
+        lines.push_str(&dialect.unset(var));
     }
+}
 
-    enable_raw_mode()?;
-    let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+/// `value` with a leading `~` spelled as `$HOME` if `dialect` lets it expand.
+fn home_part(dialect: &dyn ShellDialect, value: String) -> String {
+    if dialect.expands_vars() {
+        tilde_to_home(&value)
+    } else {
+        value
+    }
+}
 
-    let mut list_state = ListState::default();
-    list_state.select(Some(0));
-    let items: Vec<ListItem> = profiles
+/// `g` with `home_part` applied to every part.
+fn with_home(dialect: &dyn ShellDialect, g: &VarGroup) -> VarGroup {
+    VarGroup {
+        parts: g
+            .parts
+            .iter()
+            .map(|p| home_part(dialect, p.clone()))
+            .collect(),
+        ..g.clone()
+    }
+}
+
+/// The comments of a group's parts as one trailing `# ...` comment, or "" if none
+/// has one. Line breaks are flattened so a comment cannot end early.
+fn trailing_comment(g: &VarGroup) -> String {
+    let comments: Vec<String> = g
+        .comments
         .iter()
-        .map(|p| ListItem::new(p.name.clone()))
+        .flatten()
+        .map(|c| c.split_whitespace().collect::<Vec<_>>().join(" "))
         .collect();
+    if comments.is_empty() {
+        String::new()
+    } else {
+        format!(" # {}", comments.join("; "))
+    }
+}
 
-    loop {
-        terminal.draw(|f| {
-            let size = f.size();
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
-                .split(size);
-            let block = Block::default()
-                .borders(Borders::ALL)
-                .title("Select a profile to export (Enter: select, Esc: cancel)");
-            let list = List::new(items.clone())
-                .block(block)
-                .highlight_style(Style::default().bg(ratatui::style::Color::Blue));
-            f.render_stateful_widget(list, chunks[1], &mut list_state);
-        })?;
-
-        if poll(std::time::Duration::from_millis(200))? {
-            if let Event::Key(key) = read()? {
-                match key.code {
-                    KeyCode::Esc => {
-                        disable_raw_mode()?;
-                        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-                        terminal.show_cursor()?;
-                        return Ok(());
-                    }
-                    KeyCode::Down => {
-                        let i = match list_state.selected() {
-                            Some(i) if i >= profiles.len() - 1 => 0,
-                            Some(i) => i + 1,
-                            None => 0,
-                        };
-                        list_state.select(Some(i));
-                    }
-                    KeyCode::Up => {
-                        let i = match list_state.selected() {
-                            Some(0) | None => profiles.len() - 1,
-                            Some(i) => i - 1,
-                        };
-                        list_state.select(Some(i));
-                    }
-                    KeyCode::Enter => {
-                        if let Some(i) = list_state.selected() {
-                            let selected = &profiles[i];
-                            disable_raw_mode()?;
-                            execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-                            terminal.show_cursor()?;
-                            export_profile(&selected.name, mode)?;
-                            return Ok(());
+/// One export line per variable. With `idempotent`, prepend/append lines are
+/// guarded so they do nothing if the joined parts are already in the variable;
+/// with `comments`, each line ends with the comments of its parts.
+fn export_lines(
+    dialect: &dyn ShellDialect,
+    vars: &[ResolvedVar],
+    idempotent: bool,
+    comments: bool,
+) -> String {
+    // One export line per variable, with parts joined in the order they were added.
+    //
+    // This keeps editing at the parts level in storage/UI, but export happens at the
+    // variable level (e.g. one PATH assignment).
+    vars.iter()
+        .map(|v| {
+            let (name, sep) = (v.name.as_str(), v.separator.as_str());
+            let (statement, added) = match with_home(dialect, &v.group).placement(v.mode) {
+                Placement::Replace(value) => (dialect.assign_replace(name, &value), Vec::new()),
+                Placement::Around { before, after } => {
+                    let statement = match (&before, &after) {
+                        (Some(before), Some(after)) => {
+                            dialect.assign_surround(name, before, after, sep)
                         }
-                    }
-                    _ => {}
+                        (Some(before), None) => dialect.assign_prepend(name, before, sep),
+                        (None, Some(after)) => dialect.assign_append(name, after, sep),
+                        // Only possible for a group without parts.
+                        (None, None) => dialect.assign_replace(name, ""),
+                    };
+                    (statement, [before, after].into_iter().flatten().collect())
                 }
+            };
+            let statement = if idempotent && !added.is_empty() {
+                dialect.unless_present(name, &added, sep, &statement)
+            } else {
+                statement
+            };
+            if comments {
+                format!("{statement}{}", trailing_comment(&v.group))
+            } else {
+                statement
             }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Matches a variable name against a pattern where `*` matches any run of characters.
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let Some((head, rest)) = pattern.split_once('*') else {
+        return pattern == name;
+    };
+    let Some(mut tail) = name.strip_prefix(head) else {
+        return false;
+    };
+    if rest.is_empty() {
+        return true;
+    }
+    loop {
+        if glob_matches(rest, tail) {
+            return true;
+        }
+        let mut chars = tail.chars();
+        if chars.next().is_none() {
+            return false;
         }
+        tail = chars.as_str();
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::config::{Entry, PathEntry};
+/// Which variables of a profile to export (`--only` / `--exclude`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum VarFilter {
+    #[default]
+    All,
+    Only(Vec<String>),
+    Exclude(Vec<String>),
+}
 
-    #[test]
-    fn replace_mode_uses_double_quotes_and_escapes_inner_double_quotes() {
-        let e = Entry::CC("O\"Reilly".to_string());
-        assert_eq!(
-            generate_export_line(&e, OperationMode::Replace),
-            "export CC=\"O\\\"Reilly\";"
-        );
+impl VarFilter {
+    /// Builds a filter from the `--only` / `--exclude` lists (at most one non-empty).
+    pub fn new(only: Vec<String>, exclude: Vec<String>) -> Self {
+        if !only.is_empty() {
+            VarFilter::Only(only)
+        } else if !exclude.is_empty() {
+            VarFilter::Exclude(exclude)
+        } else {
+            VarFilter::All
+        }
     }
 
-    #[test]
-    fn replace_mode_allows_shell_expansion_of_dollar_vars() {
-        let e = Entry::CC("/opt/$HOME/bin".to_string());
-        assert_eq!(
-            generate_export_line(&e, OperationMode::Replace),
-            "export CC=\"/opt/$HOME/bin\";"
-        );
+    pub fn allows(&self, name: &str) -> bool {
+        match self {
+            VarFilter::All => true,
+            VarFilter::Only(patterns) => patterns.iter().any(|p| glob_matches(p, name)),
+            VarFilter::Exclude(patterns) => !patterns.iter().any(|p| glob_matches(p, name)),
+        }
     }
 
-    #[test]
-    fn prepend_mode_generates_single_export_per_var_with_all_parts_in_order() {
-        let profile = EnvProfile {
-            name: "p".to_string(),
-            entries: vec![
-                Entry::Path(PathEntry {
-                    path: "/p1".to_string(),
-                    program: "tool".to_string(),
-                    version: "1".to_string(),
-                }),
-                Entry::Path(PathEntry {
-                    path: "/p2".to_string(),
-                    program: "tool".to_string(),
-                    version: "2".to_string(),
-                }),
-                Entry::CFlag("-O2 -Wall".to_string()),
-            ],
-        };
+    /// Keeps whole variables only, so a list is never emitted partially.
+    pub fn apply(&self, groups: Vec<VarGroup>) -> Vec<VarGroup> {
+        groups
+            .into_iter()
+            .filter(|g| self.allows(&g.name))
+            .collect()
+    }
+}
 
-        let out = generate_full_export(&profile, OperationMode::Prepend);
+/// Order of the variables in export output (`--sort-vars` / `--path-first`).
+///
+/// The default keeps the order in which variables first appear in the profile.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VarOrder {
+    /// Sort variables by name.
+    pub alphabetical: bool,
+    /// Put PATH before every other variable.
+    pub path_first: bool,
+}
 
-        let path_lines: Vec<&str> = out
-            .lines()
-            .filter(|l| l.starts_with("export PATH="))
-            .collect();
-        assert_eq!(path_lines.len(), 1, "expected a single PATH export line");
-        assert_eq!(path_lines[0], "export PATH=\"/p1:/p2${PATH:+:}${PATH}\";");
+impl VarOrder {
+    /// Reorders `groups`; the sort is stable, so ties keep appearance order.
+    pub fn apply(self, mut groups: Vec<VarGroup>) -> Vec<VarGroup> {
+        if self.alphabetical {
+            groups.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        if self.path_first {
+            groups.sort_by_key(|g| g.name != "PATH");
+        }
+        groups
+    }
+}
 
-        let cflag_lines: Vec<&str> = out
-            .lines()
-            .filter(|l| l.starts_with("export CFLAGS="))
-            .collect();
-        assert_eq!(cflag_lines.len(), 1, "expected a single CFLAGS export line");
-        assert_eq!(
-            cflag_lines[0],
-            "export CFLAGS=\"-O2 -Wall${CFLAGS:+ }${CFLAGS}\";"
-        );
+/// Output format of `bath export`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// `export VAR=...;` statements to eval.
+    #[default]
+    Shell,
+    /// A JSON object keyed by variable name.
+    Json,
+    /// `VAR=value` lines, e.g. for `docker run --env-file`.
+    Env,
+    /// `.env` file lines, quoted where dotenv parsers need it.
+    Dotenv,
+    /// Dockerfile `ENV` instructions.
+    Dockerfile,
+    /// Lines for GitHub Actions' `$GITHUB_PATH` and `$GITHUB_ENV` files.
+    GithubActions,
+    /// A Makefile fragment of `export VAR := ...` assignments.
+    Make,
+}
+
+impl ExportFormat {
+    /// Whether `--resolve` (composing with the current environment) makes sense.
+    fn supports_resolve(self) -> bool {
+        !matches!(
+            self,
+            ExportFormat::Shell | ExportFormat::Dockerfile | ExportFormat::Make
+        )
     }
 
-    #[test]
-    fn append_mode_uses_parameter_expansion_to_avoid_leading_separators() {
-        let profile = EnvProfile {
-            name: "p".to_string(),
-            entries: vec![
+    /// The `--format` spelling.
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportFormat::Shell => "shell",
+            ExportFormat::Json => "json",
+            ExportFormat::Env => "env",
+            ExportFormat::Dotenv => "dotenv",
+            ExportFormat::Dockerfile => "dockerfile",
+            ExportFormat::GithubActions => "github-actions",
+            ExportFormat::Make => "make",
+        }
+    }
+
+    /// Whether the format can express an unset variable.
+    fn supports_unset(self) -> bool {
+        matches!(
+            self,
+            ExportFormat::Shell | ExportFormat::Json | ExportFormat::Make
+        )
+    }
+
+    /// Whether the format has `#` comments.
+    fn has_comments(self) -> bool {
+        self != ExportFormat::Json
+    }
+
+    /// Header comment for files written with `-o`, if the format has comments.
+    pub fn file_header(self, profile_name: &str) -> Option<String> {
+        self.has_comments().then(|| {
+            format!("# Managed by bath (profile: {profile_name}). Changes may be overwritten.")
+        })
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = BathError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "shell" => Ok(ExportFormat::Shell),
+            "json" => Ok(ExportFormat::Json),
+            "env" => Ok(ExportFormat::Env),
+            "dotenv" => Ok(ExportFormat::Dotenv),
+            "dockerfile" => Ok(ExportFormat::Dockerfile),
+            "github-actions" => Ok(ExportFormat::GithubActions),
+            "make" => Ok(ExportFormat::Make),
+            _ => Err(invalid(format!(
+                "unknown format: {s} (expected shell, json, env, dotenv, dockerfile, github-actions or make)"
+            ))),
+        }
+    }
+}
+
+/// Maps an `-o` argument to the file to write; `-` means stdout, like no `-o`.
+pub fn output_file(path: Option<PathBuf>) -> Option<PathBuf> {
+    path.filter(|p| p.as_os_str() != "-")
+}
+
+/// Everything that shapes the output of `bath export`.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// The `--mode` flag; without one the profile's default mode applies, see
+    /// `mode`.
+    pub mode: Option<OperationMode>,
+    pub overrides: ModeOverrides,
+    pub filter: VarFilter,
+    pub order: VarOrder,
+    /// Emit the statements reverting an export instead (shell format only).
+    pub undo: bool,
+    /// Shell the statements are written for (shell format only).
+    pub shell: ShellKind,
+    /// Drop repeated parts within a variable.
+    pub dedup: bool,
+    /// Keep empty and whitespace-only parts of list variables.
+    pub keep_empty: bool,
+    /// Skip prepends/appends whose parts the variable already contains, so the
+    /// output can be eval'd repeatedly (shell format only).
+    pub idempotent: bool,
+    /// Only emit variables the base environment does not already have the
+    /// profile applied to (shell format only).
+    pub delta: bool,
+    /// Which `$`/backtick constructs in values the shell may expand (shell format only).
+    pub expand: Expand,
+    /// Double- or single-quoted values (posix shell format only).
+    pub quote: Quote,
+    pub format: ExportFormat,
+    /// Combine values with the current environment (not for shell output).
+    pub resolve: bool,
+    /// Write to this file (with a header comment) instead of stdout.
+    pub output: Option<PathBuf>,
+    /// Start the output with comments naming the profile, bath version, time and mode.
+    pub header: bool,
+    /// End each export line with the comments of its parts (shell format only).
+    pub comments: bool,
+    /// Fold all Dockerfile assignments into one `ENV` instruction.
+    pub single_instruction: bool,
+    /// Append to the files named by `$GITHUB_PATH`/`$GITHUB_ENV` (github-actions format).
+    pub write: bool,
+    /// Separators replacing those of builtin list vars; see `db::load_separator_overrides`.
+    pub separators: SeparatorOverrides,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            mode: None,
+            overrides: ModeOverrides::new(),
+            filter: VarFilter::All,
+            order: VarOrder::default(),
+            undo: false,
+            shell: ShellKind::Posix,
+            dedup: false,
+            keep_empty: false,
+            idempotent: false,
+            delta: false,
+            expand: Expand::Vars,
+            quote: Quote::Double,
+            format: ExportFormat::Shell,
+            resolve: false,
+            output: None,
+            header: true,
+            comments: false,
+            single_instruction: false,
+            write: false,
+            separators: SeparatorOverrides::new(),
+        }
+    }
+}
+
+impl ExportOptions {
+    /// The mode variables are exported with. `export_profile` and
+    /// `render_export` resolve a missing `--mode` against the profile's
+    /// default first; otherwise it is prepend.
+    pub fn mode(&self) -> OperationMode {
+        self.mode.unwrap_or(OperationMode::Prepend)
+    }
+}
+
+fn selected_groups(profile: &EnvProfile, opts: &ExportOptions) -> Vec<VarGroup> {
+    let groups = if opts.dedup {
+        group_entries_with(
+            &dedup_entries(&profile.entries).0,
+            opts.keep_empty,
+            &opts.separators,
+        )
+    } else {
+        group_entries_with(&profile.entries, opts.keep_empty, &opts.separators)
+    };
+    opts.order.apply(opts.filter.apply(groups))
+}
+
+fn selected_vars(profile: &EnvProfile, opts: &ExportOptions) -> Vec<JoinedVar> {
+    join_groups(selected_groups(profile, opts))
+}
+
+/// The unset variables that pass `opts.filter`, sorted with `--sort-vars`.
+fn selected_unsets(profile: &EnvProfile, opts: &ExportOptions) -> Vec<String> {
+    let mut vars = unset_vars(&profile.entries);
+    vars.retain(|v| opts.filter.allows(v));
+    if opts.order.alphabetical {
+        vars.sort();
+    }
+    vars
+}
+
+/// Generates the export (or undo) script for a profile according to `opts`.
+pub fn generate_export(profile: &EnvProfile, opts: &ExportOptions) -> String {
+    if opts.undo {
+        // Single-quoted exports are literal, so the patterns must be too.
+        let expand = match opts.quote {
+            Quote::Double => opts.expand,
+            Quote::Single => Expand::None,
+        };
+        unexport_lines(
+            Posix {
+                expand,
+                quote: Quote::Double,
+            },
+            &selected_groups(profile, opts),
+            &profile.entries,
+            opts.mode(),
+            &opts.overrides,
+        )
+    } else {
+        shell_export(&*opts.shell.dialect(opts.expand, opts.quote), profile, opts)
+    }
+}
+
+/// Like `generate_export`, but only for the variables `base` does not already
+/// have the profile applied to. The others are named in a trailing
+/// `# unchanged: ...` comment.
+pub fn generate_delta_export(
+    profile: &EnvProfile,
+    opts: &ExportOptions,
+    base: &HashMap<String, String>,
+) -> String {
+    let vars = resolve_groups(
+        selected_groups(profile, opts),
+        opts.mode(),
+        &opts.overrides,
+        base,
+    );
+    let (applied, changed): (Vec<ResolvedVar>, Vec<ResolvedVar>) =
+        vars.into_iter().partition(|v| v.is_applied(base));
+    let (unset, already_unset): (Vec<String>, Vec<String>) = selected_unsets(profile, opts)
+        .into_iter()
+        .partition(|v| base.contains_key(v));
+    let dialect = opts.shell.dialect(opts.expand, opts.quote);
+    let mut exports = export_lines(&*dialect, &changed, opts.idempotent, opts.comments);
+    push_unset_lines(&mut exports, &*dialect, &unset);
+    let mut lines = Vec::new();
+    if !exports.is_empty() {
+        lines.push(exports);
+    }
+    let names: Vec<&str> = applied
+        .iter()
+        .map(|v| v.name.as_str())
+        .chain(already_unset.iter().map(String::as_str))
+        .collect();
+    if !names.is_empty() {
+        lines.push(format!("# unchanged: {}", names.join(" ")));
+    }
+    lines.join("\n")
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ` for `time`, in UTC.
+fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let (year, month, day, hour, minute, second) = crate::config::utc_date_time(secs);
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+}
+
+/// `#` comments recording which profile, bath version, time and mode produced an
+/// export, so a checked-in script can be traced back.
+pub fn provenance_header(
+    profile_name: &str,
+    opts: &ExportOptions,
+    generated_at: SystemTime,
+) -> String {
+    let mut mode = opts.mode().name().to_string();
+    if !opts.overrides.is_empty() {
+        let mut overrides: Vec<String> = opts
+            .overrides
+            .iter()
+            .map(|(var, m)| format!("{var}={}", m.name()))
+            .collect();
+        overrides.sort();
+        mode.push_str(&format!(" ({})", overrides.join(", ")));
+    }
+    if opts.undo {
+        mode.push_str(", undo");
+    }
+    [
+        format!("# Generated by bath {}", env!("CARGO_PKG_VERSION")),
+        format!("# profile: {profile_name}"),
+        format!("# generated: {}", utc_timestamp(generated_at)),
+        format!("# mode: {mode}"),
+    ]
+    .join("\n")
+}
+
+/// `render_export` preceded by the provenance header, if `opts.header` asks for
+/// it and the format has comments. Only the CLI adds the header. Nothing is
+/// rendered, header included, if no variable is left to export.
+fn render_with_header(
+    profile: &EnvProfile,
+    opts: &ExportOptions,
+    base: &HashMap<String, String>,
+    generated_at: SystemTime,
+) -> Result<String> {
+    let out = render_export(profile, opts, base)?;
+    if out.is_empty() || !opts.header || !opts.format.has_comments() {
+        return Ok(out);
+    }
+    let header = provenance_header(&profile.name, opts, generated_at);
+    Ok(format!("{header}\n{out}"))
+}
+
+/// Values as they would be after eval'ing the shell export on top of `base`.
+fn resolved_vars(
+    groups: Vec<VarGroup>,
+    opts: &ExportOptions,
+    base: &HashMap<String, String>,
+) -> Vec<(String, String)> {
+    resolve_groups(groups, opts.mode(), &opts.overrides, base)
+        .into_iter()
+        .map(|v| (v.name, v.composed))
+        .collect()
+}
+
+/// Each variable of `selected_groups` with its separator and where its parts go
+/// under the mode `opts` gives it, see `VarGroup::placement`.
+fn selected_placements(
+    profile: &EnvProfile,
+    opts: &ExportOptions,
+) -> Vec<(String, String, Placement)> {
+    selected_groups(profile, opts)
+        .into_iter()
+        .map(|g| {
+            let placement = g.placement(mode_for(&g.name, opts.mode(), &opts.overrides));
+            (g.name, g.separator, placement)
+        })
+        .collect()
+}
+
+/// The JSON value of a variable placed as `placement`, see `generate_json_export`.
+fn json_value(placement: Placement, sep: &str) -> serde_json::Value {
+    let (value, mode) = match placement {
+        Placement::Replace(value) => return value.into(),
+        Placement::Around {
+            before: Some(before),
+            after: Some(after),
+        } => {
+            return serde_json::json!({
+                "prepend": before,
+                "append": after,
+                "separator": sep,
+            })
+        }
+        Placement::Around {
+            before: None,
+            after: Some(after),
+        } => (after, OperationMode::Append),
+        Placement::Around {
+            before,
+            after: None,
+        } => (before.unwrap_or_default(), OperationMode::Prepend),
+    };
+    serde_json::json!({
+        "value": value,
+        "mode": mode.name(),
+        "separator": sep,
+    })
+}
+
+/// Renders the profile as a JSON object.
+///
+/// Variables that replace their inherited value map to the value itself; prepended
+/// or appended ones map to `{"value", "mode", "separator"}`, and those with parts
+/// pinned to both sides to `{"prepend", "append", "separator"}`, unless
+/// `opts.resolve` composes them against `base`. Unset variables map to `null`.
+pub fn generate_json_export(
+    profile: &EnvProfile,
+    opts: &ExportOptions,
+    base: &HashMap<String, String>,
+) -> Result<String> {
+    let mut map = serde_json::Map::new();
+    if opts.resolve {
+        for (name, value) in resolved_vars(selected_groups(profile, opts), opts, base) {
+            map.insert(name, value.into());
+        }
+    } else {
+        for (name, sep, placement) in selected_placements(profile, opts) {
+            map.insert(name, json_value(placement, &sep));
+        }
+    }
+    for name in selected_unsets(profile, opts) {
+        map.insert(name, serde_json::Value::Null);
+    }
+    Ok(serde_json::to_string_pretty(&map)?)
+}
+
+/// The (name, value) pairs written by the env-style formats.
+///
+/// Without `opts.resolve` these are the profile's own values, i.e. prepend/append
+/// against an empty base.
+fn value_pairs(
+    profile: &EnvProfile,
+    opts: &ExportOptions,
+    base: &HashMap<String, String>,
+) -> Vec<(String, String)> {
+    if opts.resolve {
+        resolved_vars(selected_groups(profile, opts), opts, base)
+    } else {
+        selected_vars(profile, opts)
+            .into_iter()
+            .map(|(name, joined, _)| (name, joined))
+            .collect()
+    }
+}
+
+/// Renders `VAR=value` lines (no quoting, as `--env-file` expects).
+pub fn generate_env_export(
+    profile: &EnvProfile,
+    opts: &ExportOptions,
+    base: &HashMap<String, String>,
+) -> String {
+    value_pairs(profile, opts, base)
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Quotes a dotenv value if needed: values with whitespace, quotes, `#` or
+/// backslashes are double quoted, with `\`, `"` and newlines escaped.
+///
+/// Readers expand `$VAR` outside single quotes, so a value with a `$` is single
+/// quoted, which every reader takes literally. Single quotes cannot hold a `'` or
+/// a newline; such values are double quoted with the `$` escaped instead.
+fn dotenv_value(value: &str) -> String {
+    if value.contains('$') && !value.contains(['\'', '\n', '\r']) {
+        return format!("'{value}'");
+    }
+    let needs_quotes = value
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, '#' | '"' | '\'' | '\\' | '$'));
+    if !needs_quotes {
+        return value.to_string();
+    }
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '$' => out.push_str("\\$"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Renders a `.env` file body: one `KEY=value` line per variable.
+pub fn generate_dotenv_export(
+    profile: &EnvProfile,
+    opts: &ExportOptions,
+    base: &HashMap<String, String>,
+) -> String {
+    value_pairs(profile, opts, base)
+        .iter()
+        .map(|(name, value)| format!("{name}={}", dotenv_value(value)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The value of a variable placed as `placement`, with `old` standing for the
+/// inherited value and every literal passed through `literal`.
+fn placed_value(
+    placement: Placement,
+    sep: &str,
+    old: String,
+    literal: impl Fn(&str) -> String,
+) -> String {
+    match placement {
+        Placement::Replace(value) => literal(&value),
+        Placement::Around { before, after } => [
+            before.map(|b| literal(&b)),
+            Some(old),
+            after.map(|a| literal(&a)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(&literal(sep)),
+    }
+}
+
+/// One `NAME="value"` pair of an `ENV` instruction.
+///
+/// Docker substitutes `${VAR}` but has no `${VAR:+...}`, so prepend/append always
+/// insert the separator, even if the variable turns out to be empty.
+fn dockerfile_assignment(name: &str, placement: Placement, sep: &str) -> String {
+    let value = placed_value(
+        placement,
+        sep,
+        format!("${{{name}}}"),
+        shell_double_quote_literal,
+    );
+    format!("{name}=\"{value}\"")
+}
+
+/// Renders Dockerfile `ENV` instructions, one per variable or folded into a single
+/// multi-line instruction with `opts.single_instruction`.
+pub fn generate_dockerfile_export(profile: &EnvProfile, opts: &ExportOptions) -> String {
+    let assignments: Vec<String> = selected_placements(profile, opts)
+        .into_iter()
+        .map(|(name, sep, placement)| dockerfile_assignment(&name, placement, &sep))
+        .collect();
+    if assignments.is_empty() {
+        return String::new();
+    }
+    if opts.single_instruction {
+        format!("ENV {}", assignments.join(" \\\n    "))
+    } else {
+        assignments
+            .iter()
+            .map(|a| format!("ENV {a}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Escapes a literal for make: `$` is doubled so it reaches the shell, `#` would
+/// start a comment.
+fn make_literal(s: &str) -> String {
+    s.replace('$', "$$").replace('#', "\\#")
+}
+
+/// One `export VAR := ...` line. Make has no `${VAR:+...}`, so prepend/append
+/// reference `$(VAR)` with an unconditional separator.
+fn make_assignment(name: &str, placement: Placement, sep: &str) -> String {
+    let value = placed_value(placement, sep, format!("$({name})"), make_literal);
+    format!("export {name} := {value}")
+}
+
+/// Renders a Makefile fragment meant to be `include`d. Unset variables are
+/// `unexport`ed, so recipes do not see them.
+pub fn generate_make_export(profile: &EnvProfile, opts: &ExportOptions) -> String {
+    selected_placements(profile, opts)
+        .into_iter()
+        .map(|(name, sep, placement)| make_assignment(&name, placement, &sep))
+        .chain(
+            selected_unsets(profile, opts)
+                .into_iter()
+                .map(|name| format!("unexport {name}")),
+        )
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// What a profile adds to the GitHub Actions environment files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GithubActionsExport {
+    /// Contents for `$GITHUB_PATH`, one directory per line.
+    pub path: String,
+    /// Contents for `$GITHUB_ENV`.
+    pub env: String,
+}
+
+/// A heredoc delimiter that does not occur as a line of `value`.
+fn heredoc_delimiter(value: &str) -> String {
+    let mut delimiter = "BATH_EOF".to_string();
+    let mut n = 0;
+    while value.lines().any(|l| l == delimiter) {
+        n += 1;
+        delimiter = format!("BATH_EOF_{n}");
+    }
+    delimiter
+}
+
+fn github_env_entry(name: &str, value: &str) -> String {
+    if value.contains('\n') {
+        let delimiter = heredoc_delimiter(value);
+        format!("{name}<<{delimiter}\n{value}\n{delimiter}\n")
+    } else {
+        format!("{name}={value}\n")
+    }
+}
+
+/// Splits a profile into `$GITHUB_PATH` and `$GITHUB_ENV` contents.
+///
+/// A PATH whose parts are all prepended goes to `$GITHUB_PATH`; the runner prepends
+/// every line in turn, so the parts are written last-to-first. Everything else,
+/// PATH with parts pinned to append included, becomes `NAME=value`.
+/// `$GITHUB_ENV` only sets whole values, so variables that prepend or append are
+/// combined with their value in `base` (the runner's environment), as with
+/// `--resolve`; otherwise they would replace it.
+pub fn generate_github_actions(
+    profile: &EnvProfile,
+    opts: &ExportOptions,
+    base: &HashMap<String, String>,
+) -> GithubActionsExport {
+    let opts = &with_profile_modes(profile, opts);
+    let mut out = GithubActionsExport::default();
+    let mut rest = Vec::new();
+    for g in selected_groups(profile, opts) {
+        let path_mode = mode_for(&g.name, opts.mode(), &opts.overrides);
+        let prepended = matches!(
+            g.placement(path_mode),
+            Placement::Around {
+                before: Some(_),
+                after: None
+            }
+        );
+        if g.name == "PATH" && prepended {
+            for part in g.parts.iter().rev().filter(|p| !p.is_empty()) {
+                out.path.push_str(part);
+                out.path.push('\n');
+            }
+        } else {
+            rest.push(g);
+        }
+    }
+    for (name, value) in resolved_vars(rest, opts, base) {
+        out.env.push_str(&github_env_entry(&name, &value));
+    }
+    out
+}
+
+impl GithubActionsExport {
+    /// Shell snippet appending the contents to the files, for use in a `run:` step.
+    pub fn to_script(&self) -> String {
+        let mut lines = Vec::new();
+        for (file, contents) in [("GITHUB_PATH", &self.path), ("GITHUB_ENV", &self.env)] {
+            if contents.is_empty() {
+                continue;
+            }
+            let delimiter = heredoc_delimiter(contents);
+            lines.push(format!(
+                "cat >> \"${file}\" <<'{delimiter}'\n{contents}{delimiter}"
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Appends the contents to the files named by `$GITHUB_PATH` and `$GITHUB_ENV`.
+    pub fn write_to_runner_files(&self) -> Result<()> {
+        use std::io::Write;
+
+        for (var, contents) in [("GITHUB_PATH", &self.path), ("GITHUB_ENV", &self.env)] {
+            if contents.is_empty() {
+                continue;
+            }
+            let path = std::env::var(var).map_err(|_| {
+                invalid(format!(
+                    "--write needs ${var} (only set inside GitHub Actions)"
+                ))
+            })?;
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| BathError::file("open", &path, e))?;
+            file.write_all(contents.as_bytes())
+                .map_err(|e| BathError::file("write", &path, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// `opts` with scalar vars switched to Replace (see `with_scalar_modes`).
+fn with_profile_modes(profile: &EnvProfile, opts: &ExportOptions) -> ExportOptions {
+    ExportOptions {
+        mode: Some(effective_mode(opts.mode, profile)),
+        overrides: with_scalar_modes(&profile.entries, &opts.overrides),
+        ..opts.clone()
+    }
+}
+
+/// Renders the profile in `opts.format`, resolving against `base` if requested.
+pub fn render_export(
+    profile: &EnvProfile,
+    opts: &ExportOptions,
+    base: &HashMap<String, String>,
+) -> Result<String> {
+    if opts.resolve && !opts.format.supports_resolve() {
+        return Err(invalid("--resolve does not apply to --format shell"));
+    }
+    if opts.undo && opts.format != ExportFormat::Shell {
+        return Err(invalid("--undo only applies to --format shell"));
+    }
+    if opts.undo && opts.shell != ShellKind::Posix {
+        return Err(invalid("--undo only supports posix shells"));
+    }
+    if opts.shell != ShellKind::Posix && opts.format != ExportFormat::Shell {
+        return Err(invalid("--shell only applies to --format shell"));
+    }
+    if opts.expand != Expand::Vars && opts.format != ExportFormat::Shell {
+        return Err(invalid("--expand only applies to --format shell"));
+    }
+    if opts.idempotent && (opts.format != ExportFormat::Shell || opts.undo) {
+        return Err(invalid(
+            "--idempotent only applies to --format shell without --undo",
+        ));
+    }
+    if opts.comments && (opts.format != ExportFormat::Shell || opts.undo) {
+        return Err(invalid(
+            "--comments only applies to --format shell without --undo",
+        ));
+    }
+    if opts.delta && (opts.format != ExportFormat::Shell || opts.undo) {
+        return Err(invalid(
+            "--delta only applies to --format shell without --undo",
+        ));
+    }
+    if opts.quote == Quote::Single {
+        if opts.format != ExportFormat::Shell || opts.shell != ShellKind::Posix {
+            return Err(invalid(
+                "--quote only applies to --format shell with posix shells",
+            ));
+        }
+        if opts.expand != Expand::Vars {
+            return Err(invalid(
+                "--expand does not apply to --quote single, which keeps values literal",
+            ));
+        }
+    }
+    if opts.single_instruction && opts.format != ExportFormat::Dockerfile {
+        return Err(invalid(
+            "--single-instruction only applies to --format dockerfile",
+        ));
+    }
+    if opts.write && opts.format != ExportFormat::GithubActions {
+        return Err(invalid("--write only applies to --format github-actions"));
+    }
+    let opts = &with_profile_modes(profile, opts);
+    match opts.format {
+        ExportFormat::Shell if opts.delta => Ok(generate_delta_export(profile, opts, base)),
+        ExportFormat::Shell => Ok(generate_export(profile, opts)),
+        ExportFormat::Json => generate_json_export(profile, opts, base),
+        ExportFormat::Env => Ok(generate_env_export(profile, opts, base)),
+        ExportFormat::Dotenv => Ok(generate_dotenv_export(profile, opts, base)),
+        ExportFormat::Dockerfile => Ok(generate_dockerfile_export(profile, opts)),
+        ExportFormat::GithubActions => Ok(generate_github_actions(profile, opts, base).to_script()),
+        ExportFormat::Make => Ok(generate_make_export(profile, opts)),
+    }
+}
+
+/// True if `name` is a scalar var: a builtin scalar or a var set via `CustomScalar`.
+fn is_scalar_var(name: &str, entries: &[Entry]) -> bool {
+    if let Some(o) = crate::config::builtin_var(name) {
+        return o.kind == VarKind::Scalar;
+    }
+    entries
+        .iter()
+        .any(|e| matches!(e.plain(), Entry::CustomScalar { name: n, .. } if n == name))
+}
+
+/// Generates a statement that removes exactly what `Posix::assign` added.
+///
+/// If nothing but the profile's value is left the variable is unset, since we
+/// cannot tell an originally empty variable from an unset one.
+fn unexport_assignment(
+    dialect: Posix,
+    var_name: &str,
+    value: &str,
+    sep: &str,
+    mode: OperationMode,
+) -> String {
+    let v = dialect.quote_literal(value);
+    let sep = dialect.quote_literal(sep);
+    match mode {
+        OperationMode::Prepend => format!(
+            "case \"${{{var_name}}}\" in \"{v}\") unset {var_name};; \"{v}{sep}\"*) export {var_name}=\"${{{var_name}#\"{v}{sep}\"}}\";; esac;"
+        ),
+        OperationMode::Append => format!(
+            "case \"${{{var_name}}}\" in \"{v}\") unset {var_name};; *\"{sep}{v}\") export {var_name}=\"${{{var_name}%\"{sep}{v}\"}}\";; esac;"
+        ),
+        OperationMode::Replace => format!("unset {var_name};"),
+    }
+}
+
+/// Generates statements that undo `generate_full_export` for the same profile and mode,
+/// with the builtin list variables in `separators` joined with their override.
+///
+/// List variables get the profile's parts stripped again; scalars are unset.
+pub fn generate_full_unexport(
+    profile: &EnvProfile,
+    mode: OperationMode,
+    separators: &SeparatorOverrides,
+) -> String {
+    unexport_lines(
+        Posix::default(),
+        &group_entries_with(&profile.entries, false, separators),
+        &profile.entries,
+        mode,
+        &ModeOverrides::new(),
+    )
+}
+
+fn unexport_lines(
+    dialect: Posix,
+    groups: &[VarGroup],
+    entries: &[Entry],
+    default_mode: OperationMode,
+    overrides: &ModeOverrides,
+) -> String {
+    groups
+        .iter()
+        .map(|g| {
+            let (name, sep) = (g.name.as_str(), g.separator.as_str());
+            if is_scalar_var(name, entries) {
+                return format!("unset {name};");
+            }
+            let mode = mode_for(name, default_mode, overrides);
+            match with_home(&dialect, g).placement(mode) {
+                Placement::Replace(_) => format!("unset {name};"),
+                Placement::Around { before, after } => {
+                    // Strip the prefix first, then the suffix, undoing `assign_surround`.
+                    let strip = [
+                        before.map(|b| {
+                            unexport_assignment(dialect, name, &b, sep, OperationMode::Prepend)
+                        }),
+                        after.map(|a| {
+                            unexport_assignment(dialect, name, &a, sep, OperationMode::Append)
+                        }),
+                    ];
+                    strip.into_iter().flatten().collect::<Vec<_>>().join(" ")
+                }
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `g` with a leading `~` in its parts replaced by the HOME of `base`.
+fn home_expanded(g: &VarGroup, base: &HashMap<String, String>) -> VarGroup {
+    match base.get("HOME") {
+        Some(home) => VarGroup {
+            parts: g.parts.iter().map(|p| expand_tilde(p, home)).collect(),
+            ..g.clone()
+        },
+        None => g.clone(),
+    }
+}
+
+/// The value of `g` on top of `base`, honouring pinned part modes.
+///
+/// Mirrors the `${VAR:+...}` logic of the shell output: an unset or empty
+/// inherited value never produces a dangling separator.
+fn compose_group(g: &VarGroup, mode: OperationMode, base: &HashMap<String, String>) -> String {
+    let g = home_expanded(g, base);
+    match g.placement(mode) {
+        Placement::Replace(value) => value,
+        Placement::Around { before, after } => {
+            let old = base.get(&g.name).filter(|o| !o.is_empty()).cloned();
+            [before, old, after]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(&g.separator)
+        }
+    }
+}
+
+/// A variable of a profile together with the value it ends up with on top of a
+/// base environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedVar {
+    pub name: String,
+    /// The profile's parts joined with `separator`.
+    pub value: String,
+    pub separator: String,
+    /// The final value once the profile is applied on top of the base.
+    pub composed: String,
+    /// The mode the variable is applied with.
+    pub mode: OperationMode,
+    /// The parts behind `value`, with their pinned modes.
+    pub group: VarGroup,
+}
+
+impl ResolvedVar {
+    /// Whether `base` already has what this variable contributes: the same value
+    /// if it replaces, otherwise every prepended/appended run of parts somewhere
+    /// in the inherited value, bounded by separators.
+    pub fn is_applied(&self, base: &HashMap<String, String>) -> bool {
+        let Some(old) = base.get(&self.name) else {
+            return false;
+        };
+        match home_expanded(&self.group, base).placement(self.mode) {
+            Placement::Replace(_) => *old == self.composed,
+            Placement::Around { before, after } => {
+                let sep = &self.separator;
+                let old = format!("{sep}{old}{sep}");
+                [before, after]
+                    .into_iter()
+                    .flatten()
+                    .all(|added| old.contains(&format!("{sep}{added}{sep}")))
+            }
+        }
+    }
+}
+
+/// Resolves `groups` against `base`, each with its mode from `overrides` or else
+/// `default_mode`.
+fn resolve_groups(
+    groups: Vec<VarGroup>,
+    default_mode: OperationMode,
+    overrides: &ModeOverrides,
+    base: &HashMap<String, String>,
+) -> Vec<ResolvedVar> {
+    groups
+        .into_iter()
+        .map(|group| {
+            let mode = mode_for(&group.name, default_mode, overrides);
+            ResolvedVar {
+                name: group.name.clone(),
+                value: group.parts.join(&group.separator),
+                separator: group.separator.clone(),
+                composed: compose_group(&group, mode, base),
+                mode,
+                group,
+            }
+        })
+        .collect()
+}
+
+/// The environment of this process, see `lossy_env`.
+pub fn current_env() -> HashMap<String, String> {
+    lossy_env(std::env::vars_os())
+}
+
+/// `vars` as strings. Names and values that are not UTF-8 are converted lossily
+/// instead of panicking like `std::env::vars`.
+fn lossy_env(vars: impl IntoIterator<Item = (OsString, OsString)>) -> HashMap<String, String> {
+    vars.into_iter()
+        .map(|(k, v)| {
+            (
+                k.to_string_lossy().into_owned(),
+                v.to_string_lossy().into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Every variable of the profile in export order, resolved against `base`.
+/// Scalar variables always replace; everything else uses `mode`. Builtin list
+/// variables in `separators` are joined with their override.
+///
+/// The shell exports are rendered from this, and `composed` is what eval'ing
+/// them on top of `base` yields.
+pub fn resolve_profile(
+    profile: &EnvProfile,
+    mode: OperationMode,
+    base: &HashMap<String, String>,
+    separators: &SeparatorOverrides,
+) -> Vec<ResolvedVar> {
+    resolve_groups(
+        group_entries_with(&profile.entries, false, separators),
+        mode,
+        &with_scalar_modes(&profile.entries, &ModeOverrides::new()),
+        base,
+    )
+}
+
+/// Computes the final value of every variable in the profile, applied on top of `base`.
+///
+/// This is the in-process equivalent of eval'ing `generate_full_export`.
+pub fn compose_env(
+    profile: &EnvProfile,
+    mode: OperationMode,
+    base: &HashMap<String, String>,
+    separators: &SeparatorOverrides,
+) -> Vec<(String, String)> {
+    resolve_profile(profile, mode, base, separators)
+        .into_iter()
+        .map(|v| (v.name, v.composed))
+        .collect()
+}
+
+/// Computes the final value of a single variable, or None if the profile has no
+/// entries for it. Same resolution as `compose_env`.
+pub fn compose_var(
+    profile: &EnvProfile,
+    var: &str,
+    mode: OperationMode,
+    base: &HashMap<String, String>,
+    separators: &SeparatorOverrides,
+) -> Option<String> {
+    resolve_profile(profile, mode, base, separators)
+        .into_iter()
+        .find(|v| v.name == var)
+        .map(|v| v.composed)
+}
+
+/// First line of the block `bath direnv` manages inside an `.envrc`.
+pub const DIRENV_BLOCK_START: &str = "# >>> bath <<<";
+/// Last line of the managed `.envrc` block.
+pub const DIRENV_BLOCK_END: &str = "# <<< bath >>>";
+
+/// Renders a profile with direnv idioms: `PATH_add` for PATH parts, `path_add` for
+/// other ':' lists and plain exports for scalars and flag vars.
+///
+/// `PATH_add`/`path_add` prepend, so parts are emitted last-to-first to keep the
+/// profile's order.
+pub fn generate_direnv(profile: &EnvProfile) -> String {
+    let shell = Posix::default();
+    let path_sep = shell.path_separator();
+    let mut lines = Vec::new();
+    for (name, joined, sep) in grouped_vars(&profile.entries) {
+        let is_list = sep == path_sep && !is_scalar_var(&name, &profile.entries);
+        if !is_list {
+            lines.push(shell.assign_replace(&name, &joined));
+            continue;
+        }
+        for part in joined.rsplit(path_sep) {
+            let part = shell_double_quote_literal(part);
+            if name == "PATH" {
+                lines.push(format!("PATH_add \"{part}\""));
+            } else {
+                lines.push(format!("path_add {name} \"{part}\""));
+            }
+        }
+    }
+    for name in unset_vars(&profile.entries) {
+        lines.push(shell.unset(&name));
+    }
+    lines.join("\n")
+}
+
+/// Wraps the direnv lines of a profile in the managed block markers.
+pub fn direnv_block(profile: &EnvProfile) -> String {
+    let body = generate_direnv(profile);
+    let mut block = format!("{DIRENV_BLOCK_START}\n# profile: {}\n", profile.name);
+    if !body.is_empty() {
+        block.push_str(&body);
+        block.push('\n');
+    }
+    block.push_str(DIRENV_BLOCK_END);
+    block.push('\n');
+    block
+}
+
+/// Replaces the managed block in `existing` with `block`, or appends `block` if
+/// there is none. Everything outside the block is kept verbatim.
+pub fn splice_direnv_block(existing: &str, block: &str) -> String {
+    if let Some(start) = existing.find(DIRENV_BLOCK_START) {
+        if let Some(end_rel) = existing[start..].find(DIRENV_BLOCK_END) {
+            let mut end = start + end_rel + DIRENV_BLOCK_END.len();
+            if existing[end..].starts_with('\n') {
+                end += 1;
+            }
+            return format!("{}{block}{}", &existing[..start], &existing[end..]);
+        }
+    }
+    let mut out = existing.to_string();
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(block);
+    out
+}
+
+/// Prints the managed `.envrc` block for a profile, or splices it into `output`.
+pub fn direnv_command(profile_name: &str, output: Option<&Path>) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let env = current_env();
+    let profile = load_inherited(&conn, profile_name, &env)?;
+    check_references(&profile)?;
+    let block = direnv_block(&profile);
+    match output {
+        Some(path) => {
+            let existing = match std::fs::read_to_string(path) {
+                Ok(s) => s,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(BathError::file("read", path, e)),
+            };
+            std::fs::write(path, splice_direnv_block(&existing, &block))
+                .map_err(|e| BathError::file("write", path, e))?;
+            eprintln!("Updated {} (run `direnv allow` to apply).", path.display());
+        }
+        None => print!("{block}"),
+    }
+    Ok(())
+}
+
+/// The mode to export `profile` with: the `--mode` flag if given, else the
+/// profile's default mode, else prepend.
+pub fn effective_mode(flag: Option<OperationMode>, profile: &EnvProfile) -> OperationMode {
+    flag.or(profile.default_mode)
+        .unwrap_or(OperationMode::Prepend)
+}
+
+/// Exports the given profile as export commands (without a shebang)
+/// so you can eval the commands in your shell.
+///
+/// The mode is `opts.mode` (the `--mode` flag) if given, else the default mode
+/// of the layered profiles.
+/// With `opts.undo`, prints the statements reverting a previous export instead.
+/// Overrides naming variables the profile does not set only produce a warning.
+pub fn export_profile(profile_names: &[String], opts: &ExportOptions) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let custom_defs = db::load_custom_var_defs(&conn)?;
+    let env = current_env();
+    let mut layers = Vec::with_capacity(profile_names.len());
+    for name in profile_names {
+        let chain: Vec<EnvProfile> = load_chain(&conn, name)?
+            .into_iter()
+            .map(|p| template::expand_profile(p, &env))
+            .collect();
+        let mut profile = inherit(&chain);
+        let unsupported = profile
+            .entries
+            .iter()
+            .filter(|e| e.unknown_kind().is_some())
+            .count();
+        if unsupported > 0 {
+            eprintln!(
+                "warning: profile '{name}' has {unsupported} entries from a newer bath; they are not exported"
+            );
+        }
+        // Conflicts are about the profile's own parts; overriding an inherited
+        // scalar is what inheritance is for.
+        let own = chain.last().expect("a chain ends with the profile itself");
+        for var in scalar_conflicts(own) {
+            eprintln!(
+                "warning: {var} is set more than once in profile '{name}'; using the last value"
+            );
+        }
+        for var in unset_conflicts(&own.entries) {
+            eprintln!("warning: {var} is both set and unset in profile '{name}'; it is unset");
+        }
+        for (var, sep) in normalize_separators(&mut profile, &custom_defs) {
+            eprintln!(
+                "warning: parts of {var} in profile '{name}' disagree on the separator; using {sep:?} (`bath doctor --fix --yes` stores this)"
+            );
+        }
+        layers.push(profile);
+    }
+    let profile = with_defaults(layer_profiles(&layers), &custom_defs, &env);
+    check_references(&profile)?;
+    let opts = &ExportOptions {
+        mode: Some(effective_mode(opts.mode, &profile)),
+        ..opts.clone()
+    };
+    let profile_name = profile.name.as_str();
+    for var in unknown_override_vars(&profile, &opts.overrides) {
+        eprintln!("warning: --mode-for {var}: profile '{profile_name}' does not set {var}");
+    }
+    if !opts.format.supports_unset() {
+        for var in selected_unsets(&profile, opts) {
+            eprintln!(
+                "warning: --format {} cannot unset {var}; it is left out",
+                opts.format.name()
+            );
+        }
+    }
+    for (var, seps) in separator_conflicts(&profile.entries) {
+        let seps: Vec<String> = seps.iter().map(|s| format!("{s:?}")).collect();
+        eprintln!(
+            "warning: {var} is joined with {} across the layered profiles; using {}",
+            seps.join(" and "),
+            seps[0]
+        );
+    }
+    let base = current_env();
+    let out = render_with_header(&profile, opts, &base, SystemTime::now())?;
+    if opts.write {
+        return generate_github_actions(&profile, &with_profile_modes(&profile, opts), &base)
+            .write_to_runner_files();
+    }
+    if let Some(path) = &opts.output {
+        let mut contents = String::new();
+        if let Some(header) = opts.format.file_header(profile_name) {
+            contents.push_str(&header);
+            contents.push('\n');
+        }
+        if !out.is_empty() {
+            contents.push_str(&out);
+            contents.push('\n');
+        }
+        std::fs::write(path, contents).map_err(|e| BathError::file("write", path, e))?;
+    } else if !out.is_empty() {
+        println!("{out}");
+    }
+    Ok(())
+}
+
+/// Applies `profiles` in order as one profile named `a,b,...`: parts of later
+/// profiles follow earlier ones within a variable, and scalars set by a later
+/// profile win. Likewise the default mode of the last profile that has one wins.
+pub fn layer_profiles(profiles: &[EnvProfile]) -> EnvProfile {
+    let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+    EnvProfile {
+        default_mode: profiles.iter().rev().find_map(|p| p.default_mode),
+        entries: profiles
+            .iter()
+            .flat_map(|p| p.entries.iter().cloned())
+            .collect(),
+        ..EnvProfile::new(&names.join(","))
+    }
+}
+
+/// The profiles `name` inherits from through `extends`, root first and ending
+/// with the profile itself. `load` fetches a profile by name.
+pub fn inheritance_chain(
+    name: &str,
+    mut load: impl FnMut(&str) -> Result<EnvProfile>,
+) -> Result<Vec<EnvProfile>> {
+    let mut chain = vec![load(name)?];
+    while let Some(parent) = chain.last().and_then(|p| p.extends.clone()) {
+        if let Some(at) = chain.iter().position(|p| p.name == parent) {
+            let mut cycle: Vec<String> = chain[at..].iter().map(|p| p.name.clone()).collect();
+            cycle.push(parent);
+            return Err(BathError::InheritanceCycle(cycle));
+        }
+        chain.push(load(&parent)?);
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+/// Applies an `inheritance_chain` as the profile at its end: like
+/// `layer_profiles`, parent parts come first within a variable and scalars set
+/// by the child win, but the result keeps the child's name, description and tags.
+pub fn inherit(chain: &[EnvProfile]) -> EnvProfile {
+    let child = chain.last().expect("a chain ends with the profile itself");
+    EnvProfile {
+        name: child.name.clone(),
+        description: child.description.clone(),
+        extends: child.extends.clone(),
+        tags: child.tags.clone(),
+        ..layer_profiles(chain)
+    }
+}
+
+/// The stored `inheritance_chain` of `name`.
+pub fn load_chain(conn: &Connection, name: &str) -> Result<Vec<EnvProfile>> {
+    inheritance_chain(name, |n| db::load_profile(conn, n))
+}
+
+/// Loads `name` with everything it inherits and the defaults it falls back to.
+/// Each profile's placeholders are expanded against `env` first, so `{PROFILE}`
+/// names the profile that owns the part.
+pub fn load_inherited(
+    conn: &Connection,
+    name: &str,
+    env: &HashMap<String, String>,
+) -> Result<EnvProfile> {
+    let chain: Vec<EnvProfile> = load_chain(conn, name)?
+        .into_iter()
+        .map(|p| template::expand_profile(p, env))
+        .collect();
+    let defs = db::load_custom_var_defs(conn)?;
+    Ok(with_defaults(inherit(&chain), &defs, env))
+}
+
+/// Custom vars with a default value that `entries` neither set (with an
+/// enabled part) nor unset, each with its default.
+pub fn defaulted_vars<'a>(entries: &[Entry], defs: &'a [CustomVarDef]) -> Vec<(&'a str, &'a str)> {
+    let unset = unset_vars(entries);
+    defs.iter()
+        .filter_map(|d| Some((d.name.as_str(), d.default_value.as_deref()?)))
+        .filter(|(var, _)| {
+            !unset.iter().any(|u| u == var)
+                && !entries
+                    .iter()
+                    .any(|e| e.is_exported() && e.var_name() == *var)
+        })
+        .collect()
+}
+
+/// `profile` with an entry for each of its `defaulted_vars`, placeholders
+/// expanded against `env`. The defaults come after the profile's own entries.
+pub fn with_defaults(
+    mut profile: EnvProfile,
+    defs: &[CustomVarDef],
+    env: &HashMap<String, String>,
+) -> EnvProfile {
+    let defaults: Vec<Entry> = defaulted_vars(&profile.entries, defs)
+        .into_iter()
+        .map(|(var, value)| make_entry(var, template::expand(value, &profile.name, env), defs))
+        .collect();
+    profile.entries.extend(defaults);
+    profile
+}
+
+/// Variables whose parts disagree on the separator, with the distinct
+/// separators in order of appearance. Export uses the first one.
+pub fn separator_conflicts(entries: &[Entry]) -> Vec<(String, Vec<String>)> {
+    let mut seps: Vec<(String, Vec<String>)> = Vec::new();
+    for entry in entries.iter().filter(|e| e.is_exported()) {
+        if is_scalar_var(&entry.var_name(), entries) {
+            continue;
+        }
+        let (var, sep) = (entry.var_name(), entry.separator().into_owned());
+        match seps.iter_mut().find(|(v, _)| *v == var) {
+            Some((_, list)) if !list.contains(&sep) => list.push(sep),
+            Some(_) => {}
+            None => seps.push((var.into_owned(), vec![sep])),
+        }
+    }
+    seps.retain(|(_, list)| list.len() > 1);
+    seps
+}
+
+/// Launches an interactive ratatui TUI to select a profile to export.
+/// When a profile is selected, its export commands (according to the given mode)
+/// are printed to stdout.
+pub fn interactive_export(opts: &ExportOptions) -> Result<()> {
+    use crate::tui::util::key_press;
+    use crossterm::event::{poll, read, KeyCode};
+    use crossterm::execute;
+    use crossterm::terminal::{
+        disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+    };
+    use ratatui::backend::CrosstermBackend;
+    use ratatui::layout::{Constraint, Direction, Layout};
+    use ratatui::style::Style;
+    use ratatui::widgets::{Block, Borders, List, ListItem, ListState};
+    use ratatui::Terminal;
+    use std::io::stdout;
+
+    let conn = db::establish_connection()?;
+    let profiles = crate::profiles::readable_profiles(&conn)?;
+    if profiles.is_empty() {
+        println!("No profiles available to export.");
+        return Ok(());
+    }
+
+    enable_raw_mode().map_err(BathError::Terminal)?;
+    let mut stdout = stdout();
+    execute!(stdout, EnterAlternateScreen).map_err(BathError::Terminal)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend).map_err(BathError::Terminal)?;
+
+    let mut list_state = ListState::default();
+    list_state.select(Some(0));
+    let items: Vec<ListItem> = profiles
+        .iter()
+        .map(|p| ListItem::new(p.name.clone()))
+        .collect();
+
+    loop {
+        terminal
+            .draw(|f| {
+                let size = f.size();
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+                    .split(size);
+                let block = Block::default()
+                    .borders(Borders::ALL)
+                    .title("Select a profile to export (Enter: select, Esc: cancel)");
+                let list = List::new(items.clone())
+                    .block(block)
+                    .highlight_style(Style::default().bg(ratatui::style::Color::Blue));
+                f.render_stateful_widget(list, chunks[1], &mut list_state);
+            })
+            .map_err(BathError::Terminal)?;
+
+        if poll(std::time::Duration::from_millis(200)).map_err(BathError::Terminal)? {
+            if let Some(key) = key_press(read().map_err(BathError::Terminal)?) {
+                match key.code {
+                    KeyCode::Esc => {
+                        disable_raw_mode().map_err(BathError::Terminal)?;
+                        execute!(terminal.backend_mut(), LeaveAlternateScreen)
+                            .map_err(BathError::Terminal)?;
+                        terminal.show_cursor().map_err(BathError::Terminal)?;
+                        return Ok(());
+                    }
+                    KeyCode::Down => {
+                        let i = match list_state.selected() {
+                            Some(i) if i >= profiles.len() - 1 => 0,
+                            Some(i) => i + 1,
+                            None => 0,
+                        };
+                        list_state.select(Some(i));
+                    }
+                    KeyCode::Up => {
+                        let i = match list_state.selected() {
+                            Some(0) | None => profiles.len() - 1,
+                            Some(i) => i - 1,
+                        };
+                        list_state.select(Some(i));
+                    }
+                    KeyCode::Enter => {
+                        if let Some(i) = list_state.selected() {
+                            let selected = &profiles[i];
+                            disable_raw_mode().map_err(BathError::Terminal)?;
+                            execute!(terminal.backend_mut(), LeaveAlternateScreen)
+                                .map_err(BathError::Terminal)?;
+                            terminal.show_cursor().map_err(BathError::Terminal)?;
+                            export_profile(std::slice::from_ref(&selected.name), opts)?;
+                            return Ok(());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Entry, PathEntry};
+
+    #[test]
+    fn replace_mode_uses_double_quotes_and_escapes_inner_double_quotes() {
+        let e = Entry::CC("O\"Reilly".to_string());
+        assert_eq!(
+            generate_export_line(&Posix::default(), &e, OperationMode::Replace),
+            "export CC=\"O\\\"Reilly\";"
+        );
+    }
+
+    #[test]
+    fn replace_mode_allows_shell_expansion_of_dollar_vars() {
+        let e = Entry::CC("/opt/$HOME/bin".to_string());
+        assert_eq!(
+            generate_export_line(&Posix::default(), &e, OperationMode::Replace),
+            "export CC=\"/opt/$HOME/bin\";"
+        );
+    }
+
+    #[test]
+    fn prepend_mode_generates_single_export_per_var_with_all_parts_in_order() {
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::Path(PathEntry {
+                    path: "/p1".to_string(),
+                    program: Some("tool".to_string()),
+                    version: Some("1".to_string()),
+                    mode: None,
+                }),
+                Entry::Path(PathEntry {
+                    path: "/p2".to_string(),
+                    program: Some("tool".to_string()),
+                    version: Some("2".to_string()),
+                    mode: None,
+                }),
+                Entry::CFlag("-O2 -Wall".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+
+        let out = generate_full_export(
+            &Posix::default(),
+            &profile,
+            OperationMode::Prepend,
+            VarOrder::default(),
+            &SeparatorOverrides::new(),
+        );
+
+        let path_lines: Vec<&str> = out
+            .lines()
+            .filter(|l| l.starts_with("export PATH="))
+            .collect();
+        assert_eq!(path_lines.len(), 1, "expected a single PATH export line");
+        assert_eq!(path_lines[0], "export PATH=\"/p1:/p2${PATH:+:}${PATH}\";");
+
+        let cflag_lines: Vec<&str> = out
+            .lines()
+            .filter(|l| l.starts_with("export CFLAGS="))
+            .collect();
+        assert_eq!(cflag_lines.len(), 1, "expected a single CFLAGS export line");
+        assert_eq!(
+            cflag_lines[0],
+            "export CFLAGS=\"-O2 -Wall${CFLAGS:+ }${CFLAGS}\";"
+        );
+    }
+
+    fn joined(entries: &[Entry]) -> Vec<(String, String)> {
+        grouped_vars(entries)
+            .into_iter()
+            .map(|(name, joined, _)| (name, joined))
+            .collect()
+    }
+
+    fn owned(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parts_are_sorted_by_priority_within_their_variable() {
+        let flag = |value: &str, priority| Entry::CFlag(value.to_string()).with_priority(priority);
+        let entries = vec![
+            flag("-a", None),
+            flag("-b", Some(10)),
+            Entry::LDFlag("-s".to_string()),
+            Entry::LDFlag("-x".to_string()).with_priority(Some(-5)),
+            flag("-c", Some(-1)),
+            flag("-d", None),
+            flag("-e", Some(10)),
+            flag("-f", Some(0)),
+        ];
+        // Missing priorities count as 0 and ties keep the profile order; LDFLAGS
+        // stays after CFLAGS although one of its parts sorts first.
+        assert_eq!(
+            joined(&entries),
+            owned(&[("CFLAGS", "-c -a -d -f -b -e"), ("LDFLAGS", "-x -s")])
+        );
+        let plain: Vec<Entry> = entries.iter().map(|e| e.plain().clone()).collect();
+        assert_eq!(
+            joined(&plain),
+            owned(&[("CFLAGS", "-a -b -c -d -e -f"), ("LDFLAGS", "-s -x")])
+        );
+    }
+
+    #[test]
+    fn references_expand_to_the_joined_profile_value() {
+        let entries = vec![
+            Entry::LDFlag("-L@{SYSROOT}/lib".to_string()),
+            Entry::CustomScalar {
+                name: "SYSROOT".to_string(),
+                value: "/opt/sysroot".to_string(),
+            },
+            Entry::CFlag("--sysroot=@{SYSROOT} -I@{NOT_SET} me@{host}".to_string()),
+        ];
+        assert_eq!(
+            joined(&entries),
+            owned(&[
+                ("LDFLAGS", "-L/opt/sysroot/lib"),
+                ("SYSROOT", "/opt/sysroot"),
+                ("CFLAGS", "--sysroot=/opt/sysroot -I@{NOT_SET} me@{host}"),
+            ])
+        );
+        assert!(reference_cycles(&entries).is_empty());
+    }
+
+    #[test]
+    fn chained_references_resolve_in_any_order() {
+        let entries = vec![
+            Entry::CPath("@{TOOLS}/include".to_string()),
+            Entry::CustomScalar {
+                name: "TOOLS".to_string(),
+                value: "@{ROOT}/tools".to_string(),
+            },
+            Entry::CustomScalar {
+                name: "ROOT".to_string(),
+                value: "/opt".to_string(),
+            },
+            Entry::CPath("/usr/local/include".to_string()),
+        ];
+        assert_eq!(
+            joined(&entries),
+            owned(&[
+                ("CPATH", "/opt/tools/include:/usr/local/include"),
+                ("TOOLS", "/opt/tools"),
+                ("ROOT", "/opt"),
+            ])
+        );
+    }
+
+    #[test]
+    fn reference_cycles_are_reported_and_left_unexpanded() {
+        let scalar = |name: &str, value: &str| Entry::CustomScalar {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        let profile = EnvProfile {
+            entries: vec![
+                scalar("A", "x@{B}"),
+                scalar("B", "y@{A}"),
+                scalar("C", "@{A}"),
+            ],
+            ..EnvProfile::new("p")
+        };
+        assert_eq!(reference_cycles(&profile.entries), [["A", "B", "A"]]);
+        assert_eq!(
+            check_references(&profile).unwrap_err().to_string(),
+            "variables reference each other in a cycle: A -> B -> A"
+        );
+        assert_eq!(
+            joined(&profile.entries),
+            owned(&[("A", "x@{B}"), ("B", "y@{A}"), ("C", "@{A}")])
+        );
+        assert_eq!(reference_cycles(&[scalar("A", "@{A}")]), [["A", "A"]]);
+    }
+
+    #[test]
+    fn builtin_search_path_lists_join_with_colons() {
+        // A stale custom definition with another separator is shadowed.
+        let stale = [crate::config::CustomVarDef::new(
+            "MANPATH",
+            crate::config::VarKind::List,
+            ";",
+        )];
+        let mut entries = Vec::new();
+        for var in [
+            "PKG_CONFIG_PATH",
+            "MANPATH",
+            "CMAKE_PREFIX_PATH",
+            "ACLOCAL_PATH",
+            "LD_PRELOAD",
+        ] {
+            for value in ["/opt/a", "/opt/b"] {
+                entries.push(crate::config::make_entry(var, value.to_string(), &stale));
+            }
+        }
+        entries.rotate_left(1);
+        let joined: Vec<(String, String)> = grouped_vars(&entries)
+            .into_iter()
+            .map(|(name, joined, _)| (name, joined))
+            .collect();
+        assert_eq!(
+            joined,
+            [
+                ("PKG_CONFIG_PATH", "/opt/b:/opt/a"),
+                ("MANPATH", "/opt/a:/opt/b"),
+                ("CMAKE_PREFIX_PATH", "/opt/a:/opt/b"),
+                ("ACLOCAL_PATH", "/opt/a:/opt/b"),
+                ("LD_PRELOAD", "/opt/a:/opt/b"),
+            ]
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+        );
+    }
+
+    #[test]
+    fn append_mode_uses_parameter_expansion_to_avoid_leading_separators() {
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::Path(PathEntry {
+                    path: "/p1".to_string(),
+                    program: Some("tool".to_string()),
+                    version: Some("1".to_string()),
+                    mode: None,
+                }),
+                Entry::Path(PathEntry {
+                    path: "/p2".to_string(),
+                    program: Some("tool".to_string()),
+                    version: Some("2".to_string()),
+                    mode: None,
+                }),
+            ],
+            ..EnvProfile::new("p")
+        };
+
+        let out = generate_full_export(
+            &Posix::default(),
+            &profile,
+            OperationMode::Append,
+            VarOrder::default(),
+            &SeparatorOverrides::new(),
+        );
+        let path_lines: Vec<&str> = out
+            .lines()
+            .filter(|l| l.starts_with("export PATH="))
+            .collect();
+        assert_eq!(path_lines.len(), 1, "expected a single PATH export line");
+        assert_eq!(path_lines[0], "export PATH=\"${PATH:+${PATH}:}/p1:/p2\";");
+    }
+
+    #[test]
+    fn builtin_lists_are_joined_with_their_override() {
+        let mut profile = EnvProfile::new("p");
+        profile.entries = vec![
+            Entry::CPath("/a".to_string()),
+            Entry::CPath("/b".to_string()),
+            Entry::CustomPart {
+                name: "PKG_CONFIG_PATH".to_string(),
+                value: "/x".to_string(),
+                separator: ":".to_string(),
+                mode: None,
+            },
+            Entry::CustomPart {
+                name: "PKG_CONFIG_PATH".to_string(),
+                value: "/y".to_string(),
+                separator: ":".to_string(),
+                mode: None,
+            },
+            Entry::LDLibraryPath("/l1".to_string()),
+            Entry::LDLibraryPath("/l2".to_string()),
+        ];
+        let export = |separators: &SeparatorOverrides| {
+            generate_full_export(
+                &Posix::default(),
+                &profile,
+                OperationMode::Replace,
+                VarOrder::default(),
+                separators,
+            )
+        };
+        let builtin = export(&SeparatorOverrides::new());
+        assert!(builtin.contains("export CPATH=\"/a:/b\";"));
+        assert!(builtin.contains("export PKG_CONFIG_PATH=\"/x:/y\";"));
+
+        let separators = SeparatorOverrides::from([
+            ("CPATH".to_string(), ";".to_string()),
+            ("PKG_CONFIG_PATH".to_string(), ",".to_string()),
+        ]);
+        let out = export(&separators);
+        assert!(out.contains("export CPATH=\"/a;/b\";"));
+        assert!(out.contains("export PKG_CONFIG_PATH=\"/x,/y\";"));
+        assert!(out.contains("export LD_LIBRARY_PATH=\"/l1:/l2\";"));
+        let opts = ExportOptions {
+            separators: separators.clone(),
+            ..ExportOptions::default()
+        };
+        let json = generate_json_export(&profile, &opts, &HashMap::new());
+        assert!(json.unwrap().contains("/a;/b"));
+        assert_eq!(
+            compose_var(
+                &profile,
+                "CPATH",
+                OperationMode::Replace,
+                &HashMap::new(),
+                &separators
+            )
+            .as_deref(),
+            Some("/a;/b")
+        );
+        // The stored parts are not touched.
+        assert!(profile.entries.iter().all(|e| e.separator() == ":"));
+    }
+
+    #[test]
+    fn compose_env_applies_modes_against_base() {
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::CPath("/a".to_string()),
+                Entry::CPath("/b".to_string()),
+                Entry::CFlag("-O2".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        let base: HashMap<String, String> = [
+            ("CPATH".to_string(), "/sys".to_string()),
+            ("CFLAGS".to_string(), String::new()),
+        ]
+        .into_iter()
+        .collect();
+
+        let pre = compose_env(
+            &profile,
+            OperationMode::Prepend,
+            &base,
+            &SeparatorOverrides::new(),
+        );
+        assert_eq!(
+            pre,
+            vec![
+                ("CPATH".to_string(), "/a:/b:/sys".to_string()),
+                ("CFLAGS".to_string(), "-O2".to_string()),
+            ]
+        );
+
+        let app = compose_env(
+            &profile,
+            OperationMode::Append,
+            &base,
+            &SeparatorOverrides::new(),
+        );
+        assert_eq!(app[0].1, "/sys:/a:/b");
+
+        let rep = compose_env(
+            &profile,
+            OperationMode::Replace,
+            &base,
+            &SeparatorOverrides::new(),
+        );
+        assert_eq!(rep[0].1, "/a:/b");
+    }
+
+    fn eval_in_sh(script: &str, var: &str, initial: Option<&str>) -> String {
+        let mut cmd = std::process::Command::new("sh");
+        cmd.arg("-c")
+            .arg(format!("{script}\nprintf '%s' \"${{{var}-<unset>}}\""));
+        match initial {
+            Some(v) => cmd.env(var, v),
+            None => cmd.env_remove(var),
+        };
+        let out = cmd.output().expect("run sh");
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    }
+
+    #[test]
+    fn unexport_restores_list_vars_for_prepend_and_append() {
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::CPath("/a".to_string()),
+                Entry::CPath("/b".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        for mode in [OperationMode::Prepend, OperationMode::Append] {
+            let script = format!(
+                "{}\n{}",
+                generate_full_export(
+                    &Posix::default(),
+                    &profile,
+                    mode,
+                    VarOrder::default(),
+                    &SeparatorOverrides::new()
+                ),
+                generate_full_unexport(&profile, mode, &SeparatorOverrides::new())
+            );
+            assert_eq!(
+                eval_in_sh(&script, "CPATH", Some("/usr/include:/x")),
+                "/usr/include:/x"
+            );
+            assert_eq!(eval_in_sh(&script, "CPATH", None), "<unset>");
+        }
+    }
+
+    #[test]
+    fn unexport_replace_and_scalars_emit_unset() {
+        let profile = EnvProfile {
+            entries: vec![Entry::CPath("/a".to_string()), Entry::CC("gcc".to_string())],
+            ..EnvProfile::new("p")
+        };
+        assert_eq!(
+            generate_full_unexport(&profile, OperationMode::Replace, &SeparatorOverrides::new()),
+            "unset CPATH;\nunset CC;"
+        );
+        assert!(generate_full_unexport(
+            &profile,
+            OperationMode::Prepend,
+            &SeparatorOverrides::new()
+        )
+        .ends_with("unset CC;"));
+    }
+
+    #[test]
+    fn statements_end_with_semicolon() {
+        let e = Entry::CFlag("-O2 -Wall".to_string());
+        let line = generate_export_line(&Posix::default(), &e, OperationMode::Replace);
+        assert!(line.ends_with(';'), "line did not end with ';': {line}");
+    }
+
+    #[test]
+    fn mode_overrides_apply_per_variable() -> Result<()> {
+        let profile = EnvProfile {
+            entries: vec![
                 Entry::Path(PathEntry {
-                    path: "/p1".to_string(),
-                    program: "tool".to_string(),
-                    version: "1".to_string(),
+                    path: "/opt/bin".to_string(),
+                    program: None,
+                    version: None,
+                    mode: None,
+                }),
+                Entry::LDLibraryPath("/opt/lib".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        let overrides = parse_mode_overrides(&[
+            "LD_LIBRARY_PATH=replace".to_string(),
+            "NOT_SET=append".to_string(),
+        ])?;
+
+        let out = generate_full_export_with_overrides(
+            &Posix::default(),
+            &profile,
+            OperationMode::Prepend,
+            &overrides,
+            VarOrder::default(),
+            &SeparatorOverrides::new(),
+        );
+        assert_eq!(
+            out,
+            "export PATH=\"/opt/bin${PATH:+:}${PATH}\";\nexport LD_LIBRARY_PATH=\"/opt/lib\";"
+        );
+        assert_eq!(unknown_override_vars(&profile, &overrides), vec!["NOT_SET"]);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_mode_overrides_are_rejected() {
+        assert!(parse_mode_overrides(&["PATH".to_string()]).is_err());
+        assert!(parse_mode_overrides(&["=append".to_string()]).is_err());
+        assert!(parse_mode_overrides(&["PATH=sideways".to_string()]).is_err());
+    }
+
+    fn flags_and_paths_profile() -> EnvProfile {
+        EnvProfile {
+            entries: vec![
+                Entry::CPath("/opt/inc".to_string()),
+                Entry::CFlag("-O2".to_string()),
+                Entry::CXXFlag("-O2".to_string()),
+                Entry::CPath("/opt/inc2".to_string()),
+                Entry::LDFlag("-L/opt/lib".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        }
+    }
+
+    #[test]
+    fn cli_shell_export_matches_the_shared_generator() -> Result<()> {
+        let mut profile = flags_and_paths_profile();
+        profile.entries.extend([
+            Entry::CC("gcc".to_string()),
+            Entry::CPath("  ".to_string()),
+            Entry::Unset {
+                name: "LANG".to_string(),
+            },
+        ]);
+        for mode in [OperationMode::Prepend, OperationMode::Append] {
+            let mut overrides = ModeOverrides::new();
+            overrides.insert("CFLAGS".to_string(), OperationMode::Replace);
+            let opts = ExportOptions {
+                mode: Some(mode),
+                overrides: overrides.clone(),
+                header: false,
+                ..ExportOptions::default()
+            };
+            assert_eq!(
+                render_with_header(&profile, &opts, &HashMap::new(), SystemTime::now())?,
+                generate_full_export_with_overrides(
+                    &Posix::default(),
+                    &profile,
+                    mode,
+                    &overrides,
+                    VarOrder::default(),
+                    &SeparatorOverrides::new()
+                )
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn cli_export_prints_nothing_when_the_filter_leaves_no_vars() -> Result<()> {
+        let profile = flags_and_paths_profile();
+        let mut opts = ExportOptions {
+            filter: VarFilter::new(vec!["NOPE*".to_string()], vec![]),
+            ..ExportOptions::default()
+        };
+        assert!(opts.header);
+        for format in [ExportFormat::Shell, ExportFormat::Env, ExportFormat::Make] {
+            opts.format = format;
+            assert_eq!(
+                render_with_header(&profile, &opts, &HashMap::new(), SystemTime::now())?,
+                ""
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn glob_patterns_match_any_run_of_characters() {
+        assert!(glob_matches("CFLAGS", "CFLAGS"));
+        assert!(!glob_matches("CFLAGS", "CXXFLAGS"));
+        assert!(glob_matches("C*", "CPATH"));
+        assert!(glob_matches("C*FLAGS", "CXXFLAGS"));
+        assert!(glob_matches("C*FLAGS", "CFLAGS"));
+        assert!(!glob_matches("C*FLAGS", "LDFLAGS"));
+        assert!(!glob_matches("c*", "CFLAGS"));
+    }
+
+    #[test]
+    fn only_and_exclude_filter_whole_variables() {
+        let profile = flags_and_paths_profile();
+        let only = ExportOptions {
+            mode: Some(OperationMode::Replace),
+            filter: VarFilter::new(vec!["C*FLAGS".to_string()], vec![]),
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_export(&profile, &only),
+            "export CFLAGS=\"-O2\";\nexport CXXFLAGS=\"-O2\";"
+        );
+
+        let exclude = ExportOptions {
+            mode: Some(OperationMode::Replace),
+            filter: VarFilter::new(vec![], vec!["*FLAGS".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_export(&profile, &exclude),
+            "export CPATH=\"/opt/inc:/opt/inc2\";"
+        );
+    }
+
+    #[test]
+    fn filter_matching_nothing_exports_nothing() {
+        let opts = ExportOptions {
+            filter: VarFilter::new(vec!["RUSTFLAGS".to_string()], vec![]),
+            ..Default::default()
+        };
+        assert_eq!(generate_export(&flags_and_paths_profile(), &opts), "");
+    }
+
+    #[test]
+    fn json_export_includes_mode_unless_replaced_or_resolved() -> Result<()> {
+        let profile = flags_and_paths_profile();
+        let base = HashMap::from([("CPATH".to_string(), "/usr/include".to_string())]);
+        let mut opts = ExportOptions {
+            format: ExportFormat::Json,
+            filter: VarFilter::new(vec!["CPATH".to_string(), "CFLAGS".to_string()], vec![]),
+            overrides: parse_mode_overrides(&["CFLAGS=replace".to_string()])?,
+            ..Default::default()
+        };
+
+        let v: serde_json::Value = serde_json::from_str(&render_export(&profile, &opts, &base)?)?;
+        assert_eq!(
+            v,
+            serde_json::json!({
+                "CPATH": {"value": "/opt/inc:/opt/inc2", "mode": "prepend", "separator": ":"},
+                "CFLAGS": "-O2",
+            })
+        );
+
+        opts.resolve = true;
+        let v: serde_json::Value = serde_json::from_str(&render_export(&profile, &opts, &base)?)?;
+        assert_eq!(
+            v,
+            serde_json::json!({"CPATH": "/opt/inc:/opt/inc2:/usr/include", "CFLAGS": "-O2"})
+        );
+        Ok(())
+    }
+
+    /// PATH with `/pre` prepended and `/post` pinned to append.
+    fn mixed_path_profile() -> EnvProfile {
+        EnvProfile {
+            entries: vec![
+                path("/pre", None),
+                path("/post", Some(OperationMode::Append)),
+            ],
+            ..EnvProfile::new("p")
+        }
+    }
+
+    #[test]
+    fn json_export_splits_parts_pinned_to_another_mode() -> Result<()> {
+        let mut opts = ExportOptions {
+            format: ExportFormat::Json,
+            ..Default::default()
+        };
+        let v: serde_json::Value = serde_json::from_str(&render_export(
+            &mixed_path_profile(),
+            &opts,
+            &HashMap::new(),
+        )?)?;
+        assert_eq!(
+            v,
+            serde_json::json!({"PATH": {"prepend": "/pre", "append": "/post", "separator": ":"}})
+        );
+
+        opts.mode = Some(OperationMode::Append);
+        let v: serde_json::Value = serde_json::from_str(&render_export(
+            &mixed_path_profile(),
+            &opts,
+            &HashMap::new(),
+        )?)?;
+        assert_eq!(
+            v,
+            serde_json::json!({"PATH": {"value": "/pre:/post", "mode": "append", "separator": ":"}})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn env_export_prints_plain_assignments() -> Result<()> {
+        let profile = flags_and_paths_profile();
+        let mut opts = ExportOptions {
+            format: ExportFormat::Env,
+            ..Default::default()
+        };
+        assert_eq!(
+            render_export(&profile, &opts, &HashMap::new())?,
+            "CPATH=/opt/inc:/opt/inc2\nCFLAGS=-O2\nCXXFLAGS=-O2\nLDFLAGS=-L/opt/lib"
+        );
+
+        opts.undo = true;
+        assert!(render_export(&profile, &opts, &HashMap::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn dotenv_quotes_only_when_needed() {
+        assert_eq!(dotenv_value("/opt/bin:/usr/bin"), "/opt/bin:/usr/bin");
+        assert_eq!(dotenv_value("-O2 -Wall"), "\"-O2 -Wall\"");
+        assert_eq!(dotenv_value("a#b"), "\"a#b\"");
+        assert_eq!(dotenv_value("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(dotenv_value("line1\nline2"), "\"line1\\nline2\"");
+        assert_eq!(dotenv_value("C:\\x"), "\"C:\\\\x\"");
+        assert_eq!(dotenv_value("$HOME/bin"), "'$HOME/bin'");
+        assert_eq!(dotenv_value("a $b \"c\""), "'a $b \"c\"'");
+        assert_eq!(dotenv_value("it's $5"), "\"it's \\$5\"");
+    }
+
+    #[test]
+    fn dotenv_export_uses_profile_values_without_conditionals() -> Result<()> {
+        let opts = ExportOptions {
+            format: ExportFormat::Dotenv,
+            ..Default::default()
+        };
+        let out = render_export(&flags_and_paths_profile(), &opts, &HashMap::new())?;
+        assert_eq!(
+            out,
+            "CPATH=/opt/inc:/opt/inc2\nCFLAGS=-O2\nCXXFLAGS=-O2\nLDFLAGS=-L/opt/lib"
+        );
+        assert!(!out.contains("${"));
+        Ok(())
+    }
+
+    #[test]
+    fn direnv_maps_path_lists_and_scalars() {
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::Path(PathEntry {
+                    path: "/opt/gcc/bin".to_string(),
+                    program: None,
+                    version: None,
+                    mode: None,
+                }),
+                Entry::Path(PathEntry {
+                    path: "/opt/tools/bin".to_string(),
+                    program: None,
+                    version: None,
+                    mode: None,
                 }),
+                Entry::LDLibraryPath("/opt/gcc/lib".to_string()),
+                Entry::CC("gcc-13".to_string()),
+                Entry::CFlag("-O2".to_string()),
+                Entry::CFlag("-g".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        assert_eq!(
+            generate_direnv(&profile),
+            [
+                "PATH_add \"/opt/tools/bin\"",
+                "PATH_add \"/opt/gcc/bin\"",
+                "path_add LD_LIBRARY_PATH \"/opt/gcc/lib\"",
+                "export CC=\"gcc-13\";",
+                "export CFLAGS=\"-O2 -g\";",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn direnv_block_is_appended_after_user_content() {
+        let block = "# >>> bath <<<\nexport CC=\"gcc\";\n# <<< bath >>>\n";
+        assert_eq!(splice_direnv_block("", block), block);
+        assert_eq!(
+            splice_direnv_block("dotenv", block),
+            format!("dotenv\n\n{block}")
+        );
+    }
+
+    #[test]
+    fn direnv_block_is_replaced_in_place() {
+        let existing =
+            "layout python\n# >>> bath <<<\nexport CC=\"old\";\n# <<< bath >>>\nwatch_file x\n";
+        let block = "# >>> bath <<<\nexport CC=\"new\";\n# <<< bath >>>\n";
+        assert_eq!(
+            splice_direnv_block(existing, block),
+            "layout python\n# >>> bath <<<\nexport CC=\"new\";\n# <<< bath >>>\nwatch_file x\n"
+        );
+    }
+
+    #[test]
+    fn dockerfile_export_has_no_conditional_expansion() -> Result<()> {
+        let profile = flags_and_paths_profile();
+        let mut opts = ExportOptions {
+            format: ExportFormat::Dockerfile,
+            filter: VarFilter::new(vec!["CPATH".to_string(), "CFLAGS".to_string()], vec![]),
+            overrides: parse_mode_overrides(&["CFLAGS=replace".to_string()])?,
+            ..Default::default()
+        };
+        let out = render_export(&profile, &opts, &HashMap::new())?;
+        assert_eq!(
+            out,
+            "ENV CPATH=\"/opt/inc:/opt/inc2:${CPATH}\"\nENV CFLAGS=\"-O2\""
+        );
+        assert!(!out.contains(":+"));
+
+        opts.mode = Some(OperationMode::Append);
+        opts.overrides.clear();
+        opts.single_instruction = true;
+        let out = render_export(&profile, &opts, &HashMap::new())?;
+        assert_eq!(
+            out,
+            "ENV CPATH=\"${CPATH}:/opt/inc:/opt/inc2\" \\\n    CFLAGS=\"${CFLAGS} -O2\""
+        );
+        assert!(!out.contains(":+"));
+        Ok(())
+    }
+
+    #[test]
+    fn dockerfile_export_splits_parts_pinned_to_another_mode() -> Result<()> {
+        let opts = ExportOptions {
+            format: ExportFormat::Dockerfile,
+            ..Default::default()
+        };
+        assert_eq!(
+            render_export(&mixed_path_profile(), &opts, &HashMap::new())?,
+            "ENV PATH=\"/pre:${PATH}:/post\""
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn github_actions_sends_path_parts_to_github_path() {
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::Path(PathEntry {
+                    path: "/opt/gcc/bin".to_string(),
+                    program: None,
+                    version: None,
+                    mode: None,
+                }),
+                Entry::Path(PathEntry {
+                    path: "/opt/tools/bin".to_string(),
+                    program: None,
+                    version: None,
+                    mode: None,
+                }),
+                Entry::CC("gcc-13".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        let out = generate_github_actions(&profile, &ExportOptions::default(), &HashMap::new());
+        assert_eq!(out.path, "/opt/tools/bin\n/opt/gcc/bin\n");
+        assert_eq!(out.env, "CC=gcc-13\n");
+        assert_eq!(
+            out.to_script(),
+            "cat >> \"$GITHUB_PATH\" <<'BATH_EOF'\n/opt/tools/bin\n/opt/gcc/bin\nBATH_EOF\ncat >> \"$GITHUB_ENV\" <<'BATH_EOF'\nCC=gcc-13\nBATH_EOF"
+        );
+
+        let opts = ExportOptions {
+            overrides: parse_mode_overrides(&["PATH=replace".to_string()]).unwrap(),
+            ..Default::default()
+        };
+        let out = generate_github_actions(&profile, &opts, &HashMap::new());
+        assert_eq!(out.path, "");
+        assert_eq!(out.env, "PATH=/opt/gcc/bin:/opt/tools/bin\nCC=gcc-13\n");
+    }
+
+    #[test]
+    fn github_actions_keeps_the_runner_value_of_prepended_vars() {
+        let mut profile = EnvProfile::new("p");
+        profile.entries = vec![
+            Entry::Path(PathEntry {
+                path: "/opt/gcc/bin".to_string(),
+                program: None,
+                version: None,
+                mode: None,
+            }),
+            Entry::LDLibraryPath("/opt/gcc/lib".to_string()),
+            Entry::CC("gcc-13".to_string()),
+        ];
+        let base: HashMap<String, String> = [
+            ("PATH", "/usr/bin"),
+            ("LD_LIBRARY_PATH", "/usr/lib"),
+            ("CC", "cc"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let out = generate_github_actions(&profile, &ExportOptions::default(), &base);
+        assert_eq!(out.path, "/opt/gcc/bin\n");
+        assert_eq!(
+            out.env,
+            "LD_LIBRARY_PATH=/opt/gcc/lib:/usr/lib\nCC=gcc-13\n"
+        );
+        let unset = generate_github_actions(&profile, &ExportOptions::default(), &HashMap::new());
+        assert_eq!(unset.env, "LD_LIBRARY_PATH=/opt/gcc/lib\nCC=gcc-13\n");
+
+        // Appending to PATH is not something $GITHUB_PATH can do.
+        let opts = ExportOptions {
+            mode: Some(OperationMode::Append),
+            ..Default::default()
+        };
+        let out = generate_github_actions(&profile, &opts, &base);
+        assert_eq!(out.path, "");
+        assert_eq!(
+            out.env,
+            "PATH=/usr/bin:/opt/gcc/bin\nLD_LIBRARY_PATH=/usr/lib:/opt/gcc/lib\nCC=gcc-13\n"
+        );
+    }
+
+    #[test]
+    fn github_actions_keeps_parts_pinned_to_append_behind_the_runner_path() {
+        let base = HashMap::from([("PATH".to_string(), "/usr/bin".to_string())]);
+        let out = generate_github_actions(&mixed_path_profile(), &ExportOptions::default(), &base);
+        assert_eq!(out.path, "");
+        assert_eq!(out.env, "PATH=/pre:/usr/bin:/post\n");
+    }
+
+    #[test]
+    fn github_actions_uses_heredoc_for_multiline_values() {
+        assert_eq!(github_env_entry("A", "x"), "A=x\n");
+        assert_eq!(
+            github_env_entry("MSG", "line1\nline2"),
+            "MSG<<BATH_EOF\nline1\nline2\nBATH_EOF\n"
+        );
+        assert_eq!(
+            github_env_entry("MSG", "BATH_EOF\nx"),
+            "MSG<<BATH_EOF_1\nBATH_EOF\nx\nBATH_EOF_1\n"
+        );
+    }
+
+    fn make_profile() -> EnvProfile {
+        EnvProfile {
+            entries: vec![
+                Entry::Path(PathEntry {
+                    path: "/opt/x/bin".to_string(),
+                    program: None,
+                    version: None,
+                    mode: None,
+                }),
+                Entry::CFlag("-DVERSION=$V#1".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        }
+    }
+
+    fn make_output(mode: OperationMode) -> String {
+        let opts = ExportOptions {
+            mode: Some(mode),
+            format: ExportFormat::Make,
+            ..Default::default()
+        };
+        render_export(&make_profile(), &opts, &HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn make_prepend_golden() {
+        assert_eq!(
+            make_output(OperationMode::Prepend),
+            "export PATH := /opt/x/bin:$(PATH)\nexport CFLAGS := -DVERSION=$$V\\#1 $(CFLAGS)"
+        );
+    }
+
+    #[test]
+    fn make_append_golden() {
+        assert_eq!(
+            make_output(OperationMode::Append),
+            "export PATH := $(PATH):/opt/x/bin\nexport CFLAGS := $(CFLAGS) -DVERSION=$$V\\#1"
+        );
+    }
+
+    #[test]
+    fn make_replace_golden() {
+        let out = make_output(OperationMode::Replace);
+        assert_eq!(
+            out,
+            "export PATH := /opt/x/bin\nexport CFLAGS := -DVERSION=$$V\\#1"
+        );
+        assert!(!out.contains("${"));
+    }
+
+    #[test]
+    fn make_export_splits_parts_pinned_to_another_mode() -> Result<()> {
+        let opts = ExportOptions {
+            format: ExportFormat::Make,
+            ..Default::default()
+        };
+        assert_eq!(
+            render_export(&mixed_path_profile(), &opts, &HashMap::new())?,
+            "export PATH := /pre:$(PATH):/post"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn mode_flag_wins_over_the_profile_default() -> Result<()> {
+        let profile = EnvProfile {
+            default_mode: Some(OperationMode::Append),
+            entries: vec![Entry::CPath("/opt/inc".to_string())],
+            ..EnvProfile::new("p")
+        };
+        let opts = ExportOptions {
+            header: false,
+            ..Default::default()
+        };
+        let out = render_export(&profile, &opts, &HashMap::new())?;
+        assert_eq!(out, "export CPATH=\"${CPATH:+${CPATH}:}/opt/inc\";");
+        let opts = ExportOptions {
+            mode: Some(OperationMode::Replace),
+            ..opts
+        };
+        let out = render_export(&profile, &opts, &HashMap::new())?;
+        assert_eq!(out, "export CPATH=\"/opt/inc\";");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn env_reads_non_utf8_values_lossily() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let env = lossy_env([(
+            OsString::from("P"),
+            OsStr::from_bytes(b"/opt/\xff/bin").to_owned(),
+        )]);
+        assert_eq!(env["P"], "/opt/\u{fffd}/bin");
+    }
+
+    #[test]
+    fn dash_output_means_stdout() {
+        assert_eq!(output_file(Some(PathBuf::from("-"))), None);
+        assert_eq!(output_file(None), None);
+        assert_eq!(
+            output_file(Some(PathBuf::from("out.env"))),
+            Some(PathBuf::from("out.env"))
+        );
+    }
+
+    #[test]
+    fn scalars_are_replaced_in_every_mode() {
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::CC("gcc-11".to_string()),
+                Entry::CFlag("-O2".to_string()),
+                Entry::CC("gcc-12".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        assert_eq!(scalar_conflicts(&profile), vec!["CC"]);
+        let base = HashMap::from([("CC".to_string(), "cc".to_string())]);
+        for mode in [
+            OperationMode::Prepend,
+            OperationMode::Append,
+            OperationMode::Replace,
+        ] {
+            let out = generate_full_export(
+                &Posix::default(),
+                &profile,
+                mode,
+                VarOrder::default(),
+                &SeparatorOverrides::new(),
+            );
+            assert_eq!(
+                out.lines().next(),
+                Some("export CC=\"gcc-12\";"),
+                "{mode:?}"
+            );
+            assert_eq!(
+                compose_var(&profile, "CC", mode, &base, &SeparatorOverrides::new()).as_deref(),
+                Some("gcc-12")
+            );
+        }
+
+        let overrides = ModeOverrides::from([("CC".to_string(), OperationMode::Append)]);
+        let out = generate_full_export_with_overrides(
+            &Posix::default(),
+            &profile,
+            OperationMode::Prepend,
+            &overrides,
+            VarOrder::default(),
+            &SeparatorOverrides::new(),
+        );
+        assert!(out.starts_with("export CC=\"${CC:+${CC} }gcc-12\";"));
+    }
+
+    #[test]
+    fn disabled_parts_are_left_out_of_exports() {
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::CFlag("-O2".to_string()).with_enabled(false),
+                Entry::CFlag("-g".to_string()),
+                Entry::CFlag("-O2".to_string()),
+                Entry::CC("clang".to_string()).with_enabled(false),
+                Entry::LDFlag("-s".to_string()).with_enabled(false),
+            ],
+            ..EnvProfile::new("p")
+        };
+        assert_eq!(
+            generate_full_export(
+                &Posix::default(),
+                &profile,
+                OperationMode::Replace,
+                VarOrder::default(),
+                &SeparatorOverrides::new()
+            ),
+            "export CFLAGS=\"-g -O2\";"
+        );
+        assert_eq!(profile_var_names(&profile), vec!["CFLAGS"]);
+        let base = HashMap::from([("CC".to_string(), "gcc".to_string())]);
+        let resolved = resolve_profile(
+            &profile,
+            OperationMode::Replace,
+            &base,
+            &SeparatorOverrides::new(),
+        );
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].composed, "-g -O2");
+
+        // A disabled part never shadows an enabled duplicate.
+        let (kept, dropped) = dedup_entries(&profile.entries);
+        assert_eq!((kept.len(), dropped), (5, 0));
+    }
+
+    #[test]
+    fn dedup_keeps_first_occurrence_and_order() {
+        let path = |p: &str| {
+            Entry::Path(PathEntry {
+                path: p.to_string(),
+                program: None,
+                version: None,
+                mode: None,
+            })
+        };
+        let entries = vec![
+            path("/usr/local/bin"),
+            path("/opt/bin"),
+            Entry::LibraryPath("/opt/bin".to_string()),
+            path(" /usr/local/bin "),
+            path("/usr/local/bin2"),
+            path("/opt/bin"),
+        ];
+        let (kept, dropped) = dedup_entries(&entries);
+        assert_eq!(dropped, 2);
+        let values: Vec<String> = kept.iter().map(entry_value).collect();
+        assert_eq!(
+            values,
+            vec!["/usr/local/bin", "/opt/bin", "/opt/bin", "/usr/local/bin2"]
+        );
+
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::CFlag("-O2".to_string()),
+                Entry::CFlag("-O".to_string()),
+                Entry::CFlag("-O2".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        let opts = ExportOptions {
+            mode: Some(OperationMode::Replace),
+            dedup: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_export(&profile, &opts),
+            "export CFLAGS=\"-O2 -O\";"
+        );
+    }
+
+    #[test]
+    fn utc_timestamps_are_civil_dates() {
+        let at = |secs| utc_timestamp(UNIX_EPOCH + std::time::Duration::from_secs(secs));
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(951_825_600), "2000-02-29T12:00:00Z");
+        assert_eq!(at(1_791_763_199), "2026-10-11T23:59:59Z");
+    }
+
+    #[test]
+    fn cli_export_has_provenance_header_unless_disabled() -> Result<()> {
+        let profile = flags_and_paths_profile();
+        let at = UNIX_EPOCH + std::time::Duration::from_secs(86_400);
+        let mut opts = ExportOptions::default();
+        opts.overrides
+            .insert("CFLAGS".to_string(), OperationMode::Append);
+        let out = render_with_header(&profile, &opts, &HashMap::new(), at)?;
+        let header = format!(
+            "# Generated by bath {}\n# profile: {}\n# generated: 1970-01-02T00:00:00Z\n# mode: prepend (CFLAGS=append)\n",
+            env!("CARGO_PKG_VERSION"),
+            profile.name
+        );
+        assert!(out.starts_with(&header), "{out}");
+        assert!(out.ends_with(&render_export(&profile, &opts, &HashMap::new())?));
+
+        opts.header = false;
+        assert!(!render_with_header(&profile, &opts, &HashMap::new(), at)?.contains('#'));
+        opts.header = true;
+        opts.format = ExportFormat::Json;
+        assert!(!render_with_header(&profile, &opts, &HashMap::new(), at)?.contains("# "));
+
+        let tui = generate_full_export(
+            &Posix::default(),
+            &profile,
+            OperationMode::Prepend,
+            VarOrder::default(),
+            &SeparatorOverrides::new(),
+        );
+        assert!(!tui.contains("Generated by bath"));
+        Ok(())
+    }
+
+    #[test]
+    fn var_order_sorts_and_pins_path() {
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::LDFlag("-L/opt/lib".to_string()),
+                Entry::CC("gcc".to_string()),
                 Entry::Path(PathEntry {
-                    path: "/p2".to_string(),
-                    program: "tool".to_string(),
-                    version: "2".to_string(),
+                    path: "/opt/bin".to_string(),
+                    program: None,
+                    version: None,
+                    mode: None,
                 }),
+                Entry::CFlag("-O2".to_string()),
             ],
+            ..EnvProfile::new("p")
         };
-
-        let out = generate_full_export(&profile, OperationMode::Append);
-        let path_lines: Vec<&str> = out
+        let names = |order| -> Vec<String> {
+            generate_full_export(
+                &Posix::default(),
+                &profile,
+                OperationMode::Prepend,
+                order,
+                &SeparatorOverrides::new(),
+            )
             .lines()
-            .filter(|l| l.starts_with("export PATH="))
+            .map(|l| l["export ".len()..l.find('=').unwrap()].to_string())
+            .collect()
+        };
+        assert_eq!(
+            names(VarOrder::default()),
+            ["LDFLAGS", "CC", "PATH", "CFLAGS"]
+        );
+        let sorted = VarOrder {
+            alphabetical: true,
+            path_first: false,
+        };
+        assert_eq!(names(sorted), ["CC", "CFLAGS", "LDFLAGS", "PATH"]);
+        let pinned = VarOrder {
+            alphabetical: true,
+            path_first: true,
+        };
+        assert_eq!(names(pinned), ["PATH", "CC", "CFLAGS", "LDFLAGS"]);
+        let path_only = VarOrder {
+            alphabetical: false,
+            path_first: true,
+        };
+        assert_eq!(names(path_only), ["PATH", "LDFLAGS", "CC", "CFLAGS"]);
+    }
+
+    #[test]
+    fn sorted_output_is_stable_when_parts_move() {
+        let mut profile = flags_and_paths_profile();
+        let opts = ExportOptions {
+            order: VarOrder {
+                alphabetical: true,
+                path_first: true,
+            },
+            ..Default::default()
+        };
+        let before: Vec<String> = selected_vars(&profile, &opts)
+            .into_iter()
+            .map(|(name, _, _)| name)
             .collect();
-        assert_eq!(path_lines.len(), 1, "expected a single PATH export line");
-        assert_eq!(path_lines[0], "export PATH=\"${PATH:+${PATH}:}/p1:/p2\";");
+        profile.entries.rotate_left(2);
+        let after: Vec<String> = selected_vars(&profile, &opts)
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .collect();
+        assert_eq!(before, after);
+        assert_eq!(before, ["CFLAGS", "CPATH", "CXXFLAGS", "LDFLAGS"]);
+    }
+
+    fn path(dir: &str, mode: Option<OperationMode>) -> Entry {
+        Entry::Path(PathEntry {
+            path: dir.to_string(),
+            program: None,
+            version: None,
+            mode,
+        })
     }
 
     #[test]
-    fn statements_end_with_semicolon() {
-        let e = Entry::CFlag("-O2 -Wall".to_string());
-        let line = generate_export_line(&e, OperationMode::Replace);
-        assert!(line.ends_with(';'), "line did not end with ';': {line}");
+    fn pinned_parts_split_around_the_inherited_value() {
+        let profile = EnvProfile {
+            entries: vec![
+                path("/opt/a", None),
+                path("/opt/late", Some(OperationMode::Append)),
+                path("/opt/b", None),
+            ],
+            ..EnvProfile::new("p")
+        };
+        let out = generate_full_export(
+            &Posix::default(),
+            &profile,
+            OperationMode::Prepend,
+            VarOrder::default(),
+            &SeparatorOverrides::new(),
+        );
+        assert_eq!(
+            out,
+            "export PATH=\"/opt/a:/opt/b${PATH:+:${PATH}}:/opt/late\";"
+        );
+
+        let undo =
+            generate_full_unexport(&profile, OperationMode::Prepend, &SeparatorOverrides::new());
+        let script =
+            format!("{out}\nprintf '%s|' \"$PATH\"\n{undo}\nprintf '%s' \"${{PATH-unset}}\"");
+        // sh makes up a PATH if it is unset, so an empty one stands in for that.
+        for (base, expected) in [
+            ("/usr/bin", "/opt/a:/opt/b:/usr/bin:/opt/late|/usr/bin"),
+            ("", "/opt/a:/opt/b:/opt/late|unset"),
+        ] {
+            let out = std::process::Command::new("/bin/sh")
+                .arg("-c")
+                .arg(&script)
+                .env("PATH", base)
+                .output()
+                .expect("run sh");
+            assert_eq!(String::from_utf8_lossy(&out.stdout), expected);
+        }
+
+        let base = HashMap::from([("PATH".to_string(), "/usr/bin".to_string())]);
+        assert_eq!(
+            compose_var(
+                &profile,
+                "PATH",
+                OperationMode::Prepend,
+                &base,
+                &SeparatorOverrides::new()
+            )
+            .as_deref(),
+            Some("/opt/a:/opt/b:/usr/bin:/opt/late")
+        );
+        // With append as the default, the unpinned parts move behind the old value.
+        assert_eq!(
+            compose_var(
+                &profile,
+                "PATH",
+                OperationMode::Append,
+                &base,
+                &SeparatorOverrides::new()
+            )
+            .as_deref(),
+            Some("/usr/bin:/opt/a:/opt/late:/opt/b")
+        );
+    }
+
+    #[test]
+    fn a_part_pinned_to_replace_drops_the_inherited_value() {
+        let profile = EnvProfile {
+            entries: vec![
+                path("/opt/a", Some(OperationMode::Replace)),
+                path("/opt/b", None),
+            ],
+            ..EnvProfile::new("p")
+        };
+        let base = HashMap::from([("PATH".to_string(), "/usr/bin".to_string())]);
+        assert_eq!(
+            compose_var(
+                &profile,
+                "PATH",
+                OperationMode::Prepend,
+                &base,
+                &SeparatorOverrides::new()
+            )
+            .as_deref(),
+            Some("/opt/a:/opt/b")
+        );
+        assert_eq!(
+            generate_full_export(
+                &Posix::default(),
+                &profile,
+                OperationMode::Prepend,
+                VarOrder::default(),
+                &SeparatorOverrides::new()
+            ),
+            "export PATH=\"/opt/a:/opt/b\";"
+        );
+    }
+
+    #[test]
+    fn layered_profiles_append_parts_and_let_later_scalars_win() {
+        let base = EnvProfile {
+            entries: vec![
+                Entry::CFlag("-O2".to_string()),
+                Entry::CC("gcc".to_string()),
+                Entry::CustomPart {
+                    name: "L".to_string(),
+                    value: "a".to_string(),
+                    separator: ":".to_string(),
+                    mode: None,
+                },
+            ],
+            ..EnvProfile::new("base")
+        };
+        let ubsan = EnvProfile {
+            entries: vec![
+                Entry::CFlag("-fsanitize=undefined".to_string()),
+                Entry::CC("clang".to_string()),
+                Entry::CustomPart {
+                    name: "L".to_string(),
+                    value: "b".to_string(),
+                    separator: ",".to_string(),
+                    mode: None,
+                },
+            ],
+            ..EnvProfile::new("ubsan")
+        };
+        let layered = layer_profiles(&[base, ubsan]);
+        assert_eq!(layered.name, "base,ubsan");
+        assert_eq!(
+            generate_full_export(
+                &Posix::default(),
+                &layered,
+                OperationMode::Prepend,
+                VarOrder::default(),
+                &SeparatorOverrides::new()
+            ),
+            [
+                "export CFLAGS=\"-O2 -fsanitize=undefined${CFLAGS:+ }${CFLAGS}\";",
+                "export CC=\"clang\";",
+                "export L=\"a:b${L:+:}${L}\";",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            separator_conflicts(&layered.entries),
+            vec![("L".to_string(), vec![":".to_string(), ",".to_string()])]
+        );
+    }
+
+    #[test]
+    fn mode_flag_beats_profile_default_beats_prepend() {
+        let with_mode = |name: &str, mode| EnvProfile {
+            default_mode: mode,
+            ..EnvProfile::new(name)
+        };
+        let plain = with_mode("plain", None);
+        let sysroot = with_mode("sysroot", Some(OperationMode::Append));
+        let pinned = with_mode("pinned", Some(OperationMode::Replace));
+
+        assert_eq!(effective_mode(None, &plain), OperationMode::Prepend);
+        assert_eq!(effective_mode(None, &sysroot), OperationMode::Append);
+        // An explicit `--mode prepend` is not the same as no flag.
+        assert_eq!(
+            effective_mode(Some(OperationMode::Prepend), &sysroot),
+            OperationMode::Prepend
+        );
+
+        // The last layer with a default wins; layers without one do not reset it.
+        let layered = layer_profiles(&[pinned.clone(), sysroot.clone(), plain.clone()]);
+        assert_eq!(effective_mode(None, &layered), OperationMode::Append);
+        let layered = layer_profiles(&[sysroot, pinned]);
+        assert_eq!(effective_mode(None, &layered), OperationMode::Replace);
+        assert_eq!(layer_profiles(&[plain]).default_mode, None);
+    }
+
+    /// Evals `script` twice on top of `env` and prints `vars` after each round.
+    fn eval_twice(script: &str, env: &[(&str, &str)], vars: &[&str]) -> (String, String) {
+        let show: Vec<String> = vars.iter().map(|v| format!("${{{v}-unset}}")).collect();
+        let show = format!("printf '%s\\n' \"{}\"", show.join("|"));
+        let full = format!("{script}\n{show}\n{script}\n{show}");
+        let mut cmd = std::process::Command::new("/bin/sh");
+        cmd.arg("-c").arg(&full);
+        for (k, v) in env {
+            cmd.env(k, v);
+        }
+        let out = String::from_utf8_lossy(&cmd.output().expect("run sh").stdout).into_owned();
+        let mut lines = out.lines().map(str::to_string);
+        (lines.next().unwrap(), lines.next().unwrap())
+    }
+
+    #[test]
+    fn idempotent_exports_survive_a_second_eval() {
+        let profile = EnvProfile {
+            entries: vec![
+                path("/opt/a", None),
+                path("/opt/late", Some(OperationMode::Append)),
+                Entry::CFlag("-O2".to_string()),
+                Entry::CFlag("-g".to_string()),
+                Entry::CC("gcc".to_string()),
+                Entry::LDFlag("-L/opt/lib".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        let mut opts = ExportOptions {
+            idempotent: true,
+            ..Default::default()
+        };
+        opts.overrides
+            .insert("LDFLAGS".to_string(), OperationMode::Append);
+        let script = generate_export(&profile, &with_profile_modes(&profile, &opts));
+        assert!(script.contains("case \" ${CFLAGS} \" in *\" -O2 -g \"*) ;;"));
+        let vars = ["PATH", "CFLAGS", "CC", "LDFLAGS"];
+
+        for env in [
+            vec![
+                ("PATH", "/usr/bin:/bin"),
+                ("CFLAGS", "-Wall"),
+                ("LDFLAGS", "-s"),
+            ],
+            // sh makes up a PATH if it is unset, so an empty one stands in for that.
+            vec![("PATH", ""), ("CFLAGS", "")],
+        ] {
+            let (first, second) = eval_twice(&script, &env, &vars);
+            assert_eq!(first, second, "{env:?}");
+        }
+        let (first, _) = eval_twice(&script, &[("PATH", "/bin"), ("CFLAGS", "-O2")], &vars);
+        // A single matching flag is not the whole group, so the group is added.
+        assert_eq!(first, "/opt/a:/bin:/opt/late|-O2 -g -O2|gcc|-L/opt/lib");
+
+        // Without the guard the second eval doubles the parts.
+        let plain = generate_export(&profile, &ExportOptions::default());
+        let (first, second) = eval_twice(&plain, &[("PATH", "/bin")], &["PATH"]);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn idempotent_is_shell_only() {
+        let opts = ExportOptions {
+            idempotent: true,
+            format: ExportFormat::Json,
+            ..Default::default()
+        };
+        assert!(render_export(&flags_and_paths_profile(), &opts, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn comments_trail_the_export_line_only_when_asked() {
+        let note = |e: Entry, c: &str| e.with_comment(Some(c.to_string()));
+        let profile = EnvProfile {
+            entries: vec![
+                note(
+                    Entry::LDFlag("-Wl,--no-as-needed".to_string()),
+                    "plugins\nload late",
+                ),
+                Entry::LDFlag("-s".to_string()),
+                note(Entry::LDFlag("-lfoo".to_string()), "vendor lib"),
+                note(Entry::CC("gcc".to_string()), "old"),
+                Entry::CC("clang".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        let opts = ExportOptions {
+            mode: Some(OperationMode::Replace),
+            ..Default::default()
+        };
+        let plain = generate_export(&profile, &opts);
+        assert!(!plain.contains('#'));
+        assert_eq!(
+            generate_export(
+                &profile,
+                &ExportOptions {
+                    comments: true,
+                    ..opts.clone()
+                }
+            ),
+            [
+                "export LDFLAGS=\"-Wl,--no-as-needed -s -lfoo\"; # plugins load late; vendor lib",
+                // Only the comment of the scalar value that is exported.
+                "export CC=\"clang\";",
+            ]
+            .join("\n")
+        );
+
+        let json = ExportOptions {
+            comments: true,
+            format: ExportFormat::Json,
+            ..Default::default()
+        };
+        assert!(render_export(&profile, &json, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn leading_tilde_exports_as_home() {
+        let profile = EnvProfile {
+            entries: vec![
+                path("~/tools/bin", None),
+                path("~user/bin", None),
+                Entry::CC("~".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        let script = generate_full_export(
+            &Posix::default(),
+            &profile,
+            OperationMode::Prepend,
+            VarOrder::default(),
+            &SeparatorOverrides::new(),
+        );
+        assert_eq!(
+            script,
+            "export PATH=\"$HOME/tools/bin:~user/bin${PATH:+:}${PATH}\";\nexport CC=\"$HOME\";"
+        );
+        let home = [("HOME", "/home/me"), ("PATH", "/bin")];
+        let (first, _) = eval_twice(&script, &home, &["PATH", "CC"]);
+        assert_eq!(first, "/home/me/tools/bin:~user/bin:/bin|/home/me");
+
+        // Undo matches the rewritten value.
+        let undo = format!(
+            "{script}\n{}",
+            generate_full_unexport(&profile, OperationMode::Prepend, &SeparatorOverrides::new())
+        );
+        assert_eq!(eval_twice(&undo, &home, &["PATH"]).0, "/bin");
+
+        // Where nothing expands the value stays as written.
+        let literal = Posix {
+            quote: Quote::Single,
+            ..Posix::default()
+        };
+        let script = generate_full_export(
+            &literal,
+            &profile,
+            OperationMode::Replace,
+            VarOrder::default(),
+            &SeparatorOverrides::new(),
+        );
+        assert!(script.starts_with("export PATH='~/tools/bin:~user/bin';"));
+
+        let base = HashMap::from([("HOME".to_string(), "/home/me".to_string())]);
+        assert_eq!(
+            compose_var(
+                &profile,
+                "PATH",
+                OperationMode::Replace,
+                &base,
+                &SeparatorOverrides::new()
+            )
+            .as_deref(),
+            Some("/home/me/tools/bin:~user/bin")
+        );
+    }
+
+    #[test]
+    fn empty_list_parts_are_dropped_unless_kept() {
+        let profile = EnvProfile {
+            entries: vec![
+                path("", None),
+                path("/opt/a", None),
+                path("  ", None),
+                path("/opt/b", None),
+                path("", None),
+                Entry::CFlag(String::new()),
+                Entry::CC(String::new()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        let script = generate_export(
+            &profile,
+            &with_profile_modes(&profile, &ExportOptions::default()),
+        );
+        assert_eq!(
+            script,
+            "export PATH=\"/opt/a:/opt/b${PATH:+:}${PATH}\";\nexport CC=\"\";"
+        );
+        let (first, _) = eval_twice(&script, &[("PATH", "/bin")], &["PATH"]);
+        assert_eq!(first, "/opt/a:/opt/b:/bin");
+        for format in [ExportFormat::Json, ExportFormat::Dotenv] {
+            let opts = ExportOptions {
+                format,
+                ..Default::default()
+            };
+            let out = render_export(&profile, &opts, &HashMap::new()).unwrap();
+            assert!(
+                out.contains("/opt/a:/opt/b") && !out.contains("::"),
+                "{out}"
+            );
+            assert!(
+                !out.contains(":/opt/a") && !out.contains("/opt/b:"),
+                "{out}"
+            );
+        }
+
+        let kept = ExportOptions {
+            keep_empty: true,
+            mode: Some(OperationMode::Replace),
+            ..Default::default()
+        };
+        assert!(generate_export(&profile, &kept).starts_with("export PATH=\":/opt/a:  :/opt/b:\";"));
+    }
+
+    fn resolve_fixture() -> EnvProfile {
+        EnvProfile {
+            entries: vec![
+                Entry::CPath("/a".to_string()),
+                Entry::CPath("/b".to_string()),
+                Entry::CC("gcc".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        }
+    }
+
+    #[test]
+    fn resolve_against_an_empty_base_is_the_profile_value() {
+        let vars = resolve_profile(
+            &resolve_fixture(),
+            OperationMode::Prepend,
+            &HashMap::new(),
+            &SeparatorOverrides::new(),
+        );
+        let cpath = &vars[0];
+        assert_eq!(
+            (
+                cpath.name.as_str(),
+                cpath.value.as_str(),
+                cpath.separator.as_str()
+            ),
+            ("CPATH", "/a:/b", ":")
+        );
+        assert_eq!(cpath.composed, "/a:/b");
+        assert_eq!(cpath.mode, OperationMode::Prepend);
+        assert_eq!(
+            (vars[1].composed.as_str(), vars[1].mode),
+            ("gcc", OperationMode::Replace)
+        );
+    }
+
+    #[test]
+    fn resolve_against_a_base_in_every_mode() {
+        let base = HashMap::from([
+            ("CPATH".to_string(), "/sys".to_string()),
+            ("CC".to_string(), "cc".to_string()),
+        ]);
+        for (mode, cpath) in [
+            (OperationMode::Prepend, "/a:/b:/sys"),
+            (OperationMode::Append, "/sys:/a:/b"),
+            (OperationMode::Replace, "/a:/b"),
+        ] {
+            let vars = resolve_profile(&resolve_fixture(), mode, &base, &SeparatorOverrides::new());
+            let composed: Vec<&str> = vars.iter().map(|v| v.composed.as_str()).collect();
+            assert_eq!(composed, vec![cpath, "gcc"], "{mode:?}");
+
+            // The rendered export evals to the same value.
+            let script = generate_full_export(
+                &Posix::default(),
+                &resolve_fixture(),
+                mode,
+                VarOrder::default(),
+                &SeparatorOverrides::new(),
+            );
+            assert_eq!(eval_in_sh(&script, "CPATH", Some("/sys")), cpath);
+        }
+    }
+
+    #[test]
+    fn delta_skips_variables_already_applied() {
+        let profile = EnvProfile {
+            entries: vec![
+                path("/opt/a", None),
+                Entry::CFlag("-O2".to_string()),
+                Entry::CC("gcc".to_string()),
+                Entry::LDFlag("-s".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        let opts = ExportOptions {
+            delta: true,
+            ..Default::default()
+        };
+        let base = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        let seeded = base(&[
+            ("PATH", "/usr/bin:/opt/a:/bin"),
+            ("CFLAGS", "-O2x"),
+            ("CC", "gcc"),
+        ]);
+        assert_eq!(
+            render_export(&profile, &opts, &seeded).unwrap(),
+            [
+                "export CFLAGS=\"-O2${CFLAGS:+ }${CFLAGS}\";",
+                "export LDFLAGS=\"-s${LDFLAGS:+ }${LDFLAGS}\";",
+                "# unchanged: PATH CC",
+            ]
+            .join("\n")
+        );
+
+        // A different compiler or a PATH with /opt/a only as a prefix of a part changes.
+        let other = base(&[
+            ("PATH", "/opt/ab"),
+            ("CC", "clang"),
+            ("CFLAGS", "-g -O2"),
+            ("LDFLAGS", "-s"),
+        ]);
+        assert_eq!(
+            render_export(&profile, &opts, &other).unwrap(),
+            [
+                "export PATH=\"/opt/a${PATH:+:}${PATH}\";",
+                "export CC=\"gcc\";",
+                "# unchanged: CFLAGS LDFLAGS",
+            ]
+            .join("\n")
+        );
+
+        let undo = ExportOptions { undo: true, ..opts };
+        assert!(render_export(&profile, &undo, &seeded).is_err());
+    }
+
+    fn unset(name: &str) -> Entry {
+        Entry::Unset {
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn unset_entries_render_as_unset_statements() -> Result<()> {
+        let profile = EnvProfile {
+            entries: vec![unset("CC"), Entry::CFlag("-O2".to_string())],
+            ..EnvProfile::new("p")
+        };
+        for mode in [OperationMode::Prepend, OperationMode::Replace] {
+            let out = generate_full_export(
+                &Posix::default(),
+                &profile,
+                mode,
+                VarOrder::default(),
+                &SeparatorOverrides::new(),
+            );
+            let lines: Vec<&str> = out.lines().collect();
+            assert_eq!(lines.len(), 2);
+            assert!(lines[0].starts_with("export CFLAGS="));
+            assert_eq!(lines[1], "unset CC;");
+        }
+        let fish = ExportOptions {
+            shell: ShellKind::Fish,
+            ..ExportOptions::default()
+        };
+        assert!(generate_export(&profile, &fish).ends_with("\nset -e CC;"));
+
+        let base = HashMap::new();
+        let json = ExportOptions {
+            format: ExportFormat::Json,
+            ..ExportOptions::default()
+        };
+        let value: serde_json::Value =
+            serde_json::from_str(&render_export(&profile, &json, &base)?)?;
+        assert_eq!(value["CC"], serde_json::Value::Null);
+        let make = ExportOptions {
+            format: ExportFormat::Make,
+            ..ExportOptions::default()
+        };
+        assert!(render_export(&profile, &make, &base)?.ends_with("\nunexport CC"));
+        assert!(generate_direnv(&profile).ends_with("\nunset CC;"));
+        assert_eq!(profile_var_names(&profile), ["CFLAGS", "CC"]);
+        Ok(())
+    }
+
+    #[test]
+    fn unset_wins_over_parts_of_the_same_var() {
+        let mut profile = EnvProfile {
+            entries: vec![
+                Entry::CPath("/a".to_string()),
+                unset("CPATH"),
+                Entry::CPath("/b".to_string()),
+                unset("LANG").with_enabled(false),
+                Entry::Lang("C".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        assert_eq!(unset_conflicts(&profile.entries), ["CPATH"]);
+        let out = generate_full_export(
+            &Posix::default(),
+            &profile,
+            OperationMode::Prepend,
+            VarOrder::default(),
+            &SeparatorOverrides::new(),
+        );
+        assert_eq!(out, "export LANG=\"C\";\nunset CPATH;");
+
+        // Only set in one layer, unset in the next: not a conflict of either.
+        profile.entries.retain(|e| e.var_name() != "CPATH");
+        let overlay = EnvProfile {
+            entries: vec![unset("LANG")],
+            ..EnvProfile::new("q")
+        };
+        assert!(unset_conflicts(&profile.entries).is_empty());
+        let layered = layer_profiles(&[profile, overlay]);
+        let base = HashMap::from([("LANG".to_string(), "C".to_string())]);
+        assert!(compose_env(
+            &layered,
+            OperationMode::Prepend,
+            &base,
+            &SeparatorOverrides::new()
+        )
+        .is_empty());
+        assert_eq!(
+            generate_delta_export(&layered, &ExportOptions::default(), &base),
+            "unset LANG;"
+        );
+        assert_eq!(
+            generate_delta_export(&layered, &ExportOptions::default(), &HashMap::new()),
+            "# unchanged: LANG"
+        );
+    }
+
+    fn child_of(name: &str, parent: Option<&str>, entries: Vec<Entry>) -> EnvProfile {
+        EnvProfile {
+            extends: parent.map(str::to_string),
+            entries,
+            ..EnvProfile::new(name)
+        }
+    }
+
+    fn chain_in(profiles: &[EnvProfile], name: &str) -> Result<Vec<EnvProfile>> {
+        inheritance_chain(name, |n| {
+            profiles
+                .iter()
+                .find(|p| p.name == n)
+                .cloned()
+                .ok_or_else(|| BathError::ProfileNotFound {
+                    name: n.to_string(),
+                    suggestions: Vec::new(),
+                })
+        })
+    }
+
+    #[test]
+    fn children_inherit_the_parts_of_the_whole_chain() -> Result<()> {
+        let profiles = [
+            child_of(
+                "base",
+                None,
+                vec![
+                    Entry::CFlag("-O2".to_string()),
+                    Entry::CC("gcc".to_string()),
+                ],
+            ),
+            child_of(
+                "base-debug",
+                Some("base"),
+                vec![Entry::CFlag("-g".to_string())],
+            ),
+            child_of(
+                "base-asan",
+                Some("base-debug"),
+                vec![
+                    Entry::CFlag("-fsanitize=address".to_string()),
+                    Entry::CC("clang".to_string()),
+                ],
+            ),
+        ];
+        let chain = chain_in(&profiles, "base-asan")?;
+        let names: Vec<&str> = chain.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["base", "base-debug", "base-asan"]);
+
+        let profile = inherit(&chain);
+        assert_eq!(profile.name, "base-asan");
+        assert_eq!(profile.extends.as_deref(), Some("base-debug"));
+        // Parent parts come first; the child's scalar wins.
+        assert_eq!(
+            joined(&profile.entries),
+            owned(&[("CFLAGS", "-O2 -g -fsanitize=address"), ("CC", "clang")])
+        );
+        assert_eq!(
+            joined(&inherit(&chain_in(&profiles, "base-debug")?).entries),
+            owned(&[("CFLAGS", "-O2 -g"), ("CC", "gcc")])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn inheritance_cycles_and_missing_parents_are_errors() {
+        let profiles = [
+            child_of("a", Some("b"), Vec::new()),
+            child_of("b", Some("c"), Vec::new()),
+            child_of("c", Some("a"), Vec::new()),
+            child_of("self", Some("self"), Vec::new()),
+            child_of("orphan", Some("gone"), Vec::new()),
+        ];
+        match chain_in(&profiles, "a") {
+            Err(BathError::InheritanceCycle(cycle)) => assert_eq!(cycle, ["a", "b", "c", "a"]),
+            other => panic!("expected a cycle, got {other:?}"),
+        }
+        match chain_in(&profiles, "self") {
+            Err(e @ BathError::InheritanceCycle(_)) => assert_eq!(
+                e.to_string(),
+                "profiles extend each other in a cycle: self -> self"
+            ),
+            other => panic!("expected a cycle, got {other:?}"),
+        }
+        assert!(matches!(
+            chain_in(&profiles, "orphan"),
+            Err(BathError::ProfileNotFound { name, .. }) if name == "gone"
+        ));
+    }
+
+    #[test]
+    fn custom_vars_fall_back_to_their_default() {
+        let defs = [
+            CustomVarDef {
+                name: "SYSROOT".to_string(),
+                kind: VarKind::Scalar,
+                separator: String::new(),
+                default_value: Some("{HOME}/sysroots/{PROFILE}".to_string()),
+            },
+            CustomVarDef::new("TOOLS", VarKind::List, ":"),
+        ];
+        let env = HashMap::from([("HOME".to_string(), "/home/me".to_string())]);
+        let sysroot = |value: &str| Entry::CustomScalar {
+            name: "SYSROOT".to_string(),
+            value: value.to_string(),
+        };
+        let export = |entries: Vec<Entry>| {
+            let profile = with_defaults(child_of("arm", None, entries), &defs, &env);
+            generate_full_export(
+                &Posix::default(),
+                &profile,
+                OperationMode::Prepend,
+                VarOrder::default(),
+                &SeparatorOverrides::new(),
+            )
+        };
+
+        assert_eq!(
+            export(vec![Entry::CC("gcc".to_string())]),
+            "export CC=\"gcc\";\nexport SYSROOT=\"/home/me/sysroots/arm\";"
+        );
+        // A disabled part does not count; an own value or an unset does.
+        assert_eq!(
+            export(vec![sysroot("/opt/x").with_enabled(false)]),
+            "export SYSROOT=\"/home/me/sysroots/arm\";"
+        );
+        assert_eq!(
+            export(vec![sysroot("/opt/x")]),
+            "export SYSROOT=\"/opt/x\";"
+        );
+        assert_eq!(
+            export(vec![Entry::Unset {
+                name: "SYSROOT".to_string()
+            }]),
+            "unset SYSROOT;"
+        );
     }
 }
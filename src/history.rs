@@ -0,0 +1,75 @@
+// src/history.rs
+
+use crate::config::{time_ago, EnvProfile, HistoryEntry};
+use crate::db;
+use anyhow::Result;
+
+/// One line per recorded change: its id, age, action and the entry counts of
+/// the profile before and after ("-" where it did not exist).
+pub fn history_line(entry: &HistoryEntry, now: i64) -> String {
+    let count = |p: &Option<EnvProfile>| {
+        p.as_ref()
+            .map_or("-".to_string(), |p| p.entries.len().to_string())
+    };
+    format!(
+        "#{:<4} {:<16} {}  ({} -> {} entries)",
+        entry.id,
+        time_ago(entry.at, now),
+        entry.action,
+        count(&entry.before),
+        count(&entry.after)
+    )
+}
+
+/// Lists the recorded changes of a profile, newest first. Deleted profiles keep
+/// theirs until purged.
+pub fn list_command(profile: &str) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let history = db::load_history(&conn, profile)?;
+    if history.is_empty() {
+        println!("No recorded changes for '{profile}'.");
+        return Ok(());
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    for entry in &history {
+        println!("{}", history_line(entry, now));
+    }
+    Ok(())
+}
+
+/// Rolls a profile back to how it was before change `id`.
+pub fn restore_command(profile: &str, id: i64) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let restored = db::restore_from_history(&conn, profile, id)?;
+    println!(
+        "Restored '{profile}' to before change #{id} ({} entries).",
+        restored.entries.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_show_age_action_and_entry_counts() {
+        let mut before = EnvProfile::new("p");
+        before
+            .entries
+            .push(crate::config::Entry::CC("gcc".to_string()));
+        let entry = HistoryEntry {
+            id: 7,
+            at: 1_000,
+            action: "delete".to_string(),
+            before: Some(before),
+            after: None,
+        };
+        assert_eq!(
+            history_line(&entry, 1_000 + 180),
+            "#7    3 minutes ago    delete  (1 -> - entries)"
+        );
+    }
+}
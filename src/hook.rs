@@ -0,0 +1,287 @@
+// src/hook.rs
+
+use crate::config::{EnvProfile, SeparatorOverrides};
+use crate::db;
+use crate::dialect::{Fish, Posix, ShellDialect};
+use crate::export::{self, shell_double_quote_literal, OperationMode, VarOrder};
+use anyhow::Result;
+
+/// Env var listing the variables touched by the active profile (space separated).
+pub const ACTIVE_VARS: &str = "BATH_ACTIVE_VARS";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl std::str::FromStr for HookShell {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "bash" => Ok(HookShell::Bash),
+            "zsh" => Ok(HookShell::Zsh),
+            "fish" => Ok(HookShell::Fish),
+            _ => anyhow::bail!("unsupported shell: {s} (expected bash, zsh or fish)"),
+        }
+    }
+}
+
+impl HookShell {
+    fn name(self) -> &'static str {
+        match self {
+            HookShell::Bash => "bash",
+            HookShell::Zsh => "zsh",
+            HookShell::Fish => "fish",
+        }
+    }
+}
+
+/// Shell function wrapping the binary so `bath use` / `bath deactivate` affect the
+/// current shell. Everything else is passed through to the real binary.
+pub fn init_script(shell: HookShell) -> String {
+    let name = shell.name();
+    match shell {
+        HookShell::Bash | HookShell::Zsh => format!(
+            r#"bath() {{
+    case "$1" in
+        use)
+            shift
+            eval "$(command bath hook {name} use "$@")"
+            ;;
+        deactivate)
+            eval "$(command bath hook {name} deactivate)"
+            ;;
+        *)
+            command bath "$@"
+            ;;
+    esac
+}}
+"#
+        ),
+        HookShell::Fish => format!(
+            r#"function bath
+    switch "$argv[1]"
+        case use
+            command bath hook {name} use $argv[2..-1] | source
+        case deactivate
+            command bath hook {name} deactivate | source
+        case '*'
+            command bath $argv
+    end
+end
+"#
+        ),
+    }
+}
+
+fn saved_name(var: &str) -> String {
+    format!("BATH_SAVED_{var}")
+}
+
+fn save_statement(shell: HookShell, var: &str) -> String {
+    let saved = saved_name(var);
+    match shell {
+        HookShell::Bash | HookShell::Zsh => format!(
+            "if [ -n \"${{{var}+x}}\" ]; then export {saved}=\"${var}\"; else unset {saved}; fi;"
+        ),
+        HookShell::Fish => {
+            format!("if set -q {var}; set -gx {saved} ${var}; else; set -e {saved}; end;")
+        }
+    }
+}
+
+fn restore_statement(shell: HookShell, var: &str) -> String {
+    let saved = saved_name(var);
+    match shell {
+        HookShell::Bash | HookShell::Zsh => format!(
+            "if [ -n \"${{{saved}+x}}\" ]; then export {var}=\"${saved}\"; else unset {var}; fi; unset {saved};"
+        ),
+        HookShell::Fish => format!(
+            "if set -q {saved}; set -gx {var} ${saved}; else; set -e {var}; end; set -e {saved};"
+        ),
+    }
+}
+
+/// Restores every variable in `vars` to the value saved at activation time.
+pub fn deactivation_script(shell: HookShell, vars: &[String]) -> String {
+    let mut lines: Vec<String> = vars.iter().map(|v| restore_statement(shell, v)).collect();
+    lines.push(match shell {
+        HookShell::Bash | HookShell::Zsh => format!("unset {ACTIVE_VARS} BATH_PROFILE;"),
+        HookShell::Fish => format!("set -e {ACTIVE_VARS}; set -e BATH_PROFILE;"),
+    });
+    lines.join("\n")
+}
+
+/// Saves the current values of the profile's variables, then applies the profile
+/// with the builtin list variables in `separators` joined with their override.
+pub fn activation_script(
+    shell: HookShell,
+    profile: &EnvProfile,
+    mode: OperationMode,
+    separators: &SeparatorOverrides,
+) -> String {
+    let vars = export::profile_var_names(profile);
+    let mut lines: Vec<String> = vars.iter().map(|v| save_statement(shell, v)).collect();
+
+    let dialect: &dyn ShellDialect = match shell {
+        HookShell::Bash | HookShell::Zsh => &Posix::default(),
+        HookShell::Fish => &Fish::default(),
+    };
+    let body =
+        export::generate_full_export(dialect, profile, mode, VarOrder::default(), separators);
+    if !body.is_empty() {
+        lines.push(body);
+    }
+
+    let active = vars.join(" ");
+    let name = shell_double_quote_literal(&profile.name);
+    lines.push(match shell {
+        HookShell::Bash | HookShell::Zsh => {
+            format!("export {ACTIVE_VARS}=\"{active}\"; export BATH_PROFILE=\"{name}\";")
+        }
+        HookShell::Fish => {
+            format!("set -gx {ACTIVE_VARS} \"{active}\"; set -gx BATH_PROFILE \"{name}\";")
+        }
+    });
+    lines.join("\n")
+}
+
+fn active_vars_from_env() -> Vec<String> {
+    std::env::var(ACTIVE_VARS)
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// Prints the script the `bath use` shell function evals.
+///
+/// A previously activated profile is deactivated first so switching is clean.
+pub fn print_use(shell: HookShell, profile_name: &str, mode: OperationMode) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let env = export::current_env();
+    let profile = export::load_inherited(&conn, profile_name, &env)?;
+    export::check_references(&profile)?;
+    let previous = active_vars_from_env();
+    if !previous.is_empty() {
+        println!("{}", deactivation_script(shell, &previous));
+    }
+    let separators = db::load_separator_overrides(&conn)?;
+    println!("{}", activation_script(shell, &profile, mode, &separators));
+    Ok(())
+}
+
+/// Prints the script the `bath deactivate` shell function evals.
+pub fn print_deactivate(shell: HookShell) -> Result<()> {
+    let vars = active_vars_from_env();
+    println!("{}", deactivation_script(shell, &vars));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Entry;
+    use std::process::Command;
+
+    fn profile() -> EnvProfile {
+        EnvProfile {
+            entries: vec![
+                Entry::CPath("/opt/inc".to_string()),
+                Entry::CC("gcc-13".to_string()),
+            ],
+            ..EnvProfile::new("tc")
+        }
+    }
+
+    #[test]
+    fn bash_init_function_snapshot() {
+        assert_eq!(
+            init_script(HookShell::Bash),
+            r#"bath() {
+    case "$1" in
+        use)
+            shift
+            eval "$(command bath hook bash use "$@")"
+            ;;
+        deactivate)
+            eval "$(command bath hook bash deactivate)"
+            ;;
+        *)
+            command bath "$@"
+            ;;
+    esac
+}
+"#
+        );
+        assert!(init_script(HookShell::Zsh).contains("command bath hook zsh use"));
+    }
+
+    #[test]
+    fn fish_init_function_snapshot() {
+        assert_eq!(
+            init_script(HookShell::Fish),
+            r#"function bath
+    switch "$argv[1]"
+        case use
+            command bath hook fish use $argv[2..-1] | source
+        case deactivate
+            command bath hook fish deactivate | source
+        case '*'
+            command bath $argv
+    end
+end
+"#
+        );
+    }
+
+    #[test]
+    fn fish_activation_uses_set_syntax() {
+        let script = activation_script(
+            HookShell::Fish,
+            &profile(),
+            OperationMode::Prepend,
+            &SeparatorOverrides::new(),
+        );
+        assert_eq!(
+            script,
+            [
+                "if set -q CPATH; set -gx BATH_SAVED_CPATH $CPATH; else; set -e BATH_SAVED_CPATH; end;",
+                "if set -q CC; set -gx BATH_SAVED_CC $CC; else; set -e BATH_SAVED_CC; end;",
+                "if test -n \"$CPATH\"; set -gx CPATH \"/opt/inc:\"(string join -- \":\" $CPATH); else; set -gx CPATH \"/opt/inc\"; end;",
+                "set -gx CC \"gcc-13\";",
+                "set -gx BATH_ACTIVE_VARS \"CPATH CC\"; set -gx BATH_PROFILE \"tc\";",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn posix_activate_then_deactivate_restores_original_values() {
+        let activate = activation_script(
+            HookShell::Bash,
+            &profile(),
+            OperationMode::Prepend,
+            &SeparatorOverrides::new(),
+        );
+        let deactivate =
+            deactivation_script(HookShell::Bash, &["CPATH".to_string(), "CC".to_string()]);
+        let script = format!(
+            "{activate}\nprintf '%s|%s\\n' \"$CPATH\" \"$BATH_PROFILE\"\n{deactivate}\nprintf '%s|%s|%s\\n' \"$CPATH\" \"${{CC-unset}}\" \"${{BATH_SAVED_CPATH-gone}}\""
+        );
+        let out = Command::new("sh")
+            .arg("-c")
+            .arg(&script)
+            .env("CPATH", "/usr/include")
+            .env_remove("CC")
+            .output()
+            .expect("run sh");
+        assert_eq!(
+            String::from_utf8_lossy(&out.stdout),
+            "/opt/inc:/usr/include|tc\n/usr/include|unset|gone\n"
+        );
+    }
+}
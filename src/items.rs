@@ -0,0 +1,472 @@
+// src/items.rs
+
+use crate::config::{CatalogItem, ItemKind};
+use crate::db;
+use crate::error::BathError;
+use anyhow::Result;
+use rusqlite::Connection;
+use std::path::Path;
+
+/// How many directory levels below its root `scan_dir` looks by default.
+pub const SCAN_DEPTH: usize = 4;
+
+/// Splits a comma separated tag list, trimming whitespace and dropping empty tags.
+pub fn parse_tags(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_string())
+        .collect()
+}
+
+pub fn parse_kind(s: &str) -> Result<ItemKind> {
+    match s {
+        "path" => Ok(ItemKind::Path),
+        "text" => Ok(ItemKind::Text),
+        _ => anyhow::bail!("unknown item kind: {s} (expected path or text)"),
+    }
+}
+
+fn kind_name(kind: &ItemKind) -> &'static str {
+    match kind {
+        ItemKind::Path => "path",
+        ItemKind::Text => "text",
+    }
+}
+
+fn non_empty(s: Option<&str>) -> Option<String> {
+    s.map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+}
+
+/// Builds a new catalog item, enforcing the same rules as the item dialog:
+/// the value must not be empty and program/version only apply to path items.
+pub fn build_item(
+    value: &str,
+    kind: ItemKind,
+    program: Option<&str>,
+    version: Option<&str>,
+    tags: &str,
+) -> Result<CatalogItem> {
+    let value = value.trim();
+    if value.is_empty() {
+        anyhow::bail!("item value must not be empty");
+    }
+    if kind != ItemKind::Path && (program.is_some() || version.is_some()) {
+        anyhow::bail!("--program and --version are only allowed with --kind path");
+    }
+    Ok(CatalogItem {
+        id: None,
+        kind,
+        value: value.to_string(),
+        program: non_empty(program),
+        version: non_empty(version),
+        tags: parse_tags(tags),
+        use_count: 0,
+        last_used_at: None,
+    })
+}
+
+/// Fields to change on an existing item; `None` keeps the current value.
+#[derive(Debug, Default)]
+pub struct ItemEdit<'a> {
+    pub value: Option<&'a str>,
+    pub kind: Option<ItemKind>,
+    pub program: Option<&'a str>,
+    pub version: Option<&'a str>,
+    pub tags: Option<&'a str>,
+}
+
+/// Applies `edit` to `item`. Switching an item to text drops its program/version.
+pub fn apply_edit(item: &mut CatalogItem, edit: ItemEdit) -> Result<()> {
+    let kind = edit.kind.unwrap_or_else(|| item.kind.clone());
+    if kind != ItemKind::Path && (edit.program.is_some() || edit.version.is_some()) {
+        anyhow::bail!("--program and --version are only allowed on path items");
+    }
+    if let Some(value) = edit.value {
+        let value = value.trim();
+        if value.is_empty() {
+            anyhow::bail!("item value must not be empty");
+        }
+        item.value = value.to_string();
+    }
+    if let Some(program) = edit.program {
+        item.program = non_empty(Some(program));
+    }
+    if let Some(version) = edit.version {
+        item.version = non_empty(Some(version));
+    }
+    if let Some(tags) = edit.tags {
+        item.tags = parse_tags(tags);
+    }
+    if kind != ItemKind::Path {
+        item.program = None;
+        item.version = None;
+    }
+    item.kind = kind;
+    Ok(())
+}
+
+fn format_item(item: &CatalogItem) -> String {
+    let mut line = format!(
+        "{:>4}  {:<4}  {}",
+        item.id.unwrap_or_default(),
+        kind_name(&item.kind),
+        item.value
+    );
+    let meta: Vec<&str> = [item.program.as_deref(), item.version.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect();
+    if !meta.is_empty() {
+        line.push_str(&format!("  ({})", meta.join(" ")));
+    }
+    if !item.tags.is_empty() {
+        line.push_str(&format!("  [{}]", item.tags.join(", ")));
+    }
+    line
+}
+
+fn find_item(items: Vec<CatalogItem>, id: i64) -> Result<CatalogItem> {
+    items
+        .into_iter()
+        .find(|i| i.id == Some(id))
+        .ok_or_else(|| anyhow::anyhow!("no item with id {id}"))
+}
+
+/// Prints the catalog, optionally only items carrying `tag` and only those
+/// matching a full-text `search` (best match first).
+pub fn list_command(tag: Option<&str>, search: Option<&str>, json: bool) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let items = match search {
+        Some(q) => db::search_items(&conn, q)?,
+        None => db::load_items(&conn)?,
+    };
+    let items: Vec<CatalogItem> = items
+        .into_iter()
+        .filter(|i| tag.is_none_or(|t| i.tags.iter().any(|it| it == t)))
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&items)?);
+    } else {
+        for item in &items {
+            println!("{}", format_item(item));
+        }
+    }
+    Ok(())
+}
+
+/// Adds an item; one with the same kind and value gets the new tags instead,
+/// unless `allow_duplicate`.
+pub fn add_command(mut item: CatalogItem, allow_duplicate: bool) -> Result<()> {
+    let conn = db::establish_connection()?;
+    if db::save_item(&conn, &mut item, allow_duplicate)? {
+        println!("Merged into the existing item:");
+    }
+    println!("{}", format_item(&item));
+    Ok(())
+}
+
+/// Collapses duplicate items, see `db::dedup_items`.
+pub fn dedup_command() -> Result<()> {
+    let conn = db::establish_connection()?;
+    match db::dedup_items(&conn)? {
+        0 => println!("No duplicate items."),
+        n => println!("Removed {n} duplicate items."),
+    }
+    Ok(())
+}
+
+pub fn rm_command(id: i64) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let item = find_item(db::load_items(&conn)?, id)?;
+    let links = db::item_links(&conn, id)?;
+    if !links.is_empty() {
+        eprintln!(
+            "warning: {} profile part(s) came from this item; they are kept (see `bath items where-used {id}`)",
+            links.len()
+        );
+    }
+    db::delete_item(&conn, id)?;
+    println!("Removed item {id} ({}).", item.value);
+    Ok(())
+}
+
+/// Prints the profile parts that were created from item `id`.
+pub fn where_used_command(id: i64) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let item = find_item(db::load_items(&conn)?, id)?;
+    let links = db::item_links(&conn, id)?;
+    if links.is_empty() {
+        println!("Item {id} ({}) is not used by any profile.", item.value);
+    }
+    for link in links {
+        println!("{}  {}  {}", link.profile, link.var, link.value);
+    }
+    Ok(())
+}
+
+pub fn edit_command(id: i64, edit: ItemEdit) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let mut item = find_item(db::load_items(&conn)?, id)?;
+    apply_edit(&mut item, edit)?;
+    db::save_item(&conn, &mut item, false)?;
+    println!("{}", format_item(&item));
+    Ok(())
+}
+
+/// The tag of a directory `scan_dir` picks up by its name.
+fn scan_tag(name: &str) -> Option<&'static str> {
+    match name {
+        "bin" => Some("bin"),
+        "include" => Some("include"),
+        "lib" | "lib64" => Some("lib"),
+        _ => None,
+    }
+}
+
+/// Path items for the `bin`, `include`, `lib` and `lib64` directories in
+/// `root`, at most `depth` levels down, sorted by path. Hidden directories and
+/// the contents of the ones found are not searched, and symlinks are not
+/// followed. Each item is tagged with what it holds and the name of the
+/// directory right below `root` it is in, or of `root` itself.
+pub fn scan_dir(root: &Path, depth: usize) -> Result<Vec<CatalogItem>> {
+    let root = std::path::absolute(root).map_err(|e| BathError::file("read", root, e))?;
+    if !root.is_dir() {
+        anyhow::bail!("not a directory: {}", root.display());
+    }
+    let root_name = root
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "/".to_string());
+    let mut found = Vec::new();
+    // Directories still to read, with their depth and the tag naming them.
+    let mut pending = vec![(root.clone(), 0, root_name)];
+    while let Some((dir, level, top)) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if dir == root => return Err(BathError::file("read", &dir, e).into()),
+            // Unreadable subdirectories are skipped.
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = entry.path();
+            if name.starts_with('.') {
+                continue;
+            }
+            if let Some(tag) = scan_tag(&name).filter(|_| path.is_dir()) {
+                let mut item =
+                    build_item(&path.to_string_lossy(), ItemKind::Path, None, None, tag)?;
+                if top != tag {
+                    item.tags.push(top.clone());
+                }
+                found.push(item);
+            } else if level + 1 < depth && entry.file_type().is_ok_and(|t| t.is_dir()) {
+                let top = if level == 0 { name } else { top.clone() };
+                pending.push((path, level + 1, top));
+            }
+        }
+    }
+    found.sort_by(|a, b| a.value.cmp(&b.value));
+    Ok(found)
+}
+
+/// `scanned` without the items whose value some item in `existing` already
+/// has, and how many those were.
+pub fn new_scanned_items(
+    scanned: Vec<CatalogItem>,
+    existing: &[CatalogItem],
+) -> (Vec<CatalogItem>, usize) {
+    let total = scanned.len();
+    let new: Vec<CatalogItem> = scanned
+        .into_iter()
+        .filter(|s| !existing.iter().any(|e| e.value == s.value))
+        .collect();
+    let skipped = total - new.len();
+    (new, skipped)
+}
+
+/// Saves the items of a scan together.
+pub fn save_scanned_items(conn: &Connection, items: &mut [CatalogItem]) -> Result<()> {
+    db::atomically(conn, |tx| -> Result<()> {
+        for item in items.iter_mut() {
+            db::save_item(tx, item, false)?;
+        }
+        Ok(())
+    })
+}
+
+/// "created 12 items, skipped 3 duplicates" for a scan.
+pub fn scan_summary(created: usize, skipped: usize) -> String {
+    let plural = |n: usize, word: &str| format!("{n} {word}{}", if n == 1 { "" } else { "s" });
+    format!(
+        "created {}, skipped {}",
+        plural(created, "item"),
+        plural(skipped, "duplicate")
+    )
+}
+
+/// Adds a path item for every `bin`, `include` and `lib` directory under
+/// `root` (see `scan_dir`) that is not in the catalog yet.
+pub fn scan_command(root: &Path, depth: usize) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let (mut items, skipped) = new_scanned_items(scan_dir(root, depth)?, &db::load_items(&conn)?);
+    save_scanned_items(&conn, &mut items)?;
+    for item in &items {
+        println!("{}", format_item(item));
+    }
+    println!(
+        "Scanned {}: {}.",
+        root.display(),
+        scan_summary(items.len(), skipped)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tags_trims_and_drops_empty_tags() {
+        assert_eq!(parse_tags(" core, gcc ,,x86 "), vec!["core", "gcc", "x86"]);
+        assert!(parse_tags("").is_empty());
+        assert!(parse_tags(" , ").is_empty());
+    }
+
+    #[test]
+    fn program_and_version_require_path_kind() -> Result<()> {
+        assert!(build_item("-O2", ItemKind::Text, Some("gcc"), None, "").is_err());
+        assert!(build_item("-O2", ItemKind::Text, None, Some("13"), "").is_err());
+        assert!(build_item("  ", ItemKind::Text, None, None, "").is_err());
+
+        let item = build_item(
+            "/opt/gcc/bin",
+            ItemKind::Path,
+            Some("gcc"),
+            Some("13"),
+            "a,b",
+        )?;
+        assert_eq!(item.program.as_deref(), Some("gcc"));
+        assert_eq!(item.version.as_deref(), Some("13"));
+        assert_eq!(item.tags, vec!["a", "b"]);
+        Ok(())
+    }
+
+    #[test]
+    fn edit_to_text_clears_path_fields() -> Result<()> {
+        let mut item = build_item("/opt/gcc/bin", ItemKind::Path, Some("gcc"), Some("13"), "")?;
+        assert!(apply_edit(
+            &mut item,
+            ItemEdit {
+                kind: Some(ItemKind::Text),
+                program: Some("clang"),
+                ..Default::default()
+            }
+        )
+        .is_err());
+
+        apply_edit(
+            &mut item,
+            ItemEdit {
+                value: Some("-O2"),
+                kind: Some(ItemKind::Text),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(item.value, "-O2");
+        assert_eq!(item.kind, ItemKind::Text);
+        assert_eq!(item.program, None);
+        assert_eq!(item.version, None);
+        Ok(())
+    }
+
+    /// A directory tree under the temp dir, made of `dirs` and empty `files`.
+    fn fixture(name: &str, dirs: &[&str], files: &[&str]) -> Result<std::path::PathBuf> {
+        let root = std::env::temp_dir().join(format!("bath-scan-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for dir in dirs {
+            std::fs::create_dir_all(root.join(dir))?;
+        }
+        for file in files {
+            std::fs::write(root.join(file), "")?;
+        }
+        Ok(root)
+    }
+
+    #[test]
+    fn scan_finds_bin_include_and_lib_dirs() -> Result<()> {
+        let root = fixture(
+            "tree",
+            &[
+                "bin",
+                "gcc-13/bin",
+                "gcc-13/include",
+                "gcc-13/lib64",
+                "gcc-13/lib/gcc/x86_64/include",
+                "gcc-13/share/man",
+                "llvm/17/bin",
+                ".cache/bin",
+                "deep/a/b/c/bin",
+                "tools",
+            ],
+            &["tools/include"],
+        )?;
+        let top = root.file_name().unwrap().to_string_lossy().into_owned();
+        let found = scan_dir(&root, SCAN_DEPTH)?;
+        let at = |rel: &str| root.join(rel).to_string_lossy().into_owned();
+        let values: Vec<&str> = found.iter().map(|i| i.value.as_str()).collect();
+        assert_eq!(
+            values,
+            [
+                at("bin"),
+                at("gcc-13/bin"),
+                at("gcc-13/include"),
+                at("gcc-13/lib"),
+                at("gcc-13/lib64"),
+                at("llvm/17/bin"),
+            ]
+        );
+        assert!(found.iter().all(|i| i.kind == ItemKind::Path));
+        assert_eq!(found[0].tags, ["bin".to_string(), top]);
+        assert_eq!(found[2].tags, ["include", "gcc-13"]);
+        assert_eq!(found[4].tags, ["lib", "gcc-13"]);
+        assert_eq!(found[5].tags, ["bin", "llvm"]);
+
+        // Two levels miss llvm/17/bin; five levels reach deep/a/b/c/bin.
+        assert_eq!(scan_dir(&root, 2)?.len(), 5);
+        assert_eq!(scan_dir(&root, 5)?.len(), 7);
+        assert!(scan_dir(&root.join("tools/include"), SCAN_DEPTH).is_err());
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn scan_skips_items_already_in_the_catalog() -> Result<()> {
+        let root = fixture("dedup", &["a/bin", "a/lib", "b/bin"], &[])?;
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut known = build_item(
+            &root.join("a/bin").to_string_lossy(),
+            ItemKind::Text,
+            None,
+            None,
+            "",
+        )?;
+        db::save_item(&conn, &mut known, false)?;
+
+        let (mut items, skipped) = new_scanned_items(scan_dir(&root, 2)?, &db::load_items(&conn)?);
+        assert_eq!((items.len(), skipped), (2, 1));
+        save_scanned_items(&conn, &mut items)?;
+        assert!(items.iter().all(|i| i.id.is_some()));
+        assert_eq!(db::load_items(&conn)?.len(), 3);
+        assert_eq!(scan_summary(2, 1), "created 2 items, skipped 1 duplicate");
+
+        let (again, skipped) = new_scanned_items(scan_dir(&root, 2)?, &db::load_items(&conn)?);
+        assert_eq!((again.len(), skipped), (0, 3));
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
@@ -3,12 +3,27 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 
+mod archive;
+mod cmake;
 mod config;
 mod db;
+mod defs;
+mod dialect;
+mod diff;
+mod error;
 mod export;
+mod history;
+mod hook;
+mod items;
 mod profile_editor;
+mod profiles;
+mod run;
+mod snapshot;
+mod template;
 mod tui;
 mod tui_app;
+mod validate;
+mod which;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -16,6 +31,16 @@ mod tui_app;
     about = "Manage GCC-related environment variable configurations"
 )]
 struct Cli {
+    /// Database file to use (default: $BATH_DB, then $XDG_DATA_HOME/bath/bath.db,
+    /// or ~/.bath.db until `migrate-db` was run)
+    #[arg(long, global = true, value_name = "PATH")]
+    db: Option<std::path::PathBuf>,
+
+    /// In the interactive editor, store a leading `~` in values as the home
+    /// directory instead of leaving it to export time
+    #[arg(long)]
+    expand_home_at_save: bool,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -24,10 +49,406 @@ struct Cli {
 enum Commands {
     /// Export a profile as export commands (which you can eval in your shell)
     Export {
-        /// Profile name to export. If omitted, an interactive view lets you select one.
+        /// Profiles to export, applied in order (comma-separated or repeated). If
+        /// omitted, an interactive view lets you select one.
+        #[arg(value_delimiter = ',')]
+        profiles: Vec<String>,
+
+        /// Operation mode: prepend, append, or replace (default is the profile's
+        /// mode set with `profile set-mode`, else prepend)
+        #[arg(short, long, value_parser = ["prepend", "append", "replace"])]
+        mode: Option<String>,
+
+        /// Use a different mode for one variable, e.g. `LD_LIBRARY_PATH=replace` (repeatable)
+        #[arg(long = "mode-for", value_name = "VAR=MODE")]
+        mode_for: Vec<String>,
+
+        /// Only export these variables (comma-separated, `*` matches any characters)
+        #[arg(long, value_delimiter = ',', conflicts_with = "exclude")]
+        only: Vec<String>,
+
+        /// Export every variable except these (comma-separated, `*` matches any characters)
+        #[arg(long, value_delimiter = ',')]
+        exclude: Vec<String>,
+
+        /// Emit variables sorted by name instead of in order of first appearance
+        #[arg(long)]
+        sort_vars: bool,
+
+        /// Emit PATH before every other variable
+        #[arg(long)]
+        path_first: bool,
+
+        /// Print statements that undo a previous export of the profile instead
+        #[arg(long)]
+        undo: bool,
+
+        /// Drop parts repeated within a variable (first occurrence wins)
+        #[arg(long)]
+        dedup: bool,
+
+        /// Keep empty parts of list variables (they are dropped by default, since
+        /// an empty PATH segment means the current directory)
+        #[arg(long)]
+        keep_empty: bool,
+
+        /// Guard prepends/appends so eval'ing the output again changes nothing
+        #[arg(long)]
+        idempotent: bool,
+
+        /// End each export line with the comments of its parts, as `# ...`
+        #[arg(long)]
+        comments: bool,
+
+        /// Only print variables the current environment does not already have the
+        /// profile applied to (the rest are listed in a comment)
+        #[arg(long)]
+        delta: bool,
+
+        /// What the shell may expand in values: nothing, $VAR references, or
+        /// everything including command substitution (shell format)
+        #[arg(long, default_value = "vars", value_parser = ["none", "vars", "full"])]
+        expand: String,
+
+        /// Leave out the comments naming the profile, bath version, time and mode
+        #[arg(long)]
+        no_header: bool,
+
+        /// Quote values with double quotes or literal single quotes (posix shell format)
+        #[arg(long, default_value = "double", value_parser = ["double", "single"])]
+        quote: String,
+
+        /// Shell syntax of the statements (shell format)
+        #[arg(long, default_value = "posix", value_parser = ["posix", "fish"])]
+        shell: String,
+
+        /// Output format (default: shell statements to eval)
+        #[arg(
+            long,
+            default_value = "shell",
+            value_parser = [
+                "shell",
+                "json",
+                "env",
+                "dotenv",
+                "dockerfile",
+                "github-actions",
+                "make",
+            ]
+        )]
+        format: String,
+
+        /// Combine values with the current environment (not for shell output)
+        #[arg(long)]
+        resolve: bool,
+
+        /// Write to this file (with a "managed by bath" header) instead of stdout; `-` is stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<std::path::PathBuf>,
+
+        /// Fold all variables into a single multi-line ENV instruction (dockerfile format)
+        #[arg(long)]
+        single_instruction: bool,
+
+        /// Append to $GITHUB_PATH / $GITHUB_ENV instead of printing (github-actions format)
+        #[arg(long)]
+        write: bool,
+    },
+
+    /// Generate a CMake toolchain file from a profile
+    CmakeToolchain {
+        /// Profile to translate.
+        profile: String,
+
+        /// Write the toolchain file here instead of stdout (`-` is stdout)
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Generate a direnv `.envrc` block for a profile
+    Direnv {
+        /// Profile to render.
+        profile: String,
+
+        /// Update the bath block in this file (e.g. `.envrc`), keeping everything else
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Capture variables from the current environment into a profile
+    Snapshot {
+        /// Name of the new profile to create.
+        #[arg(required_unless_present = "merge_into", conflicts_with = "merge_into")]
+        name: Option<String>,
+
+        /// Comma-separated variables to capture (default: every builtin var that is set)
+        #[arg(long, value_delimiter = ',')]
+        vars: Vec<String>,
+
+        /// Append the captured entries to this existing profile instead
+        #[arg(long)]
+        merge_into: Option<String>,
+    },
+
+    /// List profiles
+    List {
+        /// Also show entry counts and when each profile was created and last changed
+        #[arg(short, long)]
+        verbose: bool,
+
+        /// Only show profiles with this tag
+        #[arg(long)]
+        tag: Option<String>,
+    },
+
+    /// Duplicate a profile under a new name
+    Copy {
+        /// Profile to copy.
+        src: String,
+
+        /// Name of the new profile.
+        dst: String,
+
+        /// Replace `dst` if it already exists
+        #[arg(long)]
+        overwrite: bool,
+    },
+
+    /// Combine two profiles into a new one (base parts first, overlay scalars win)
+    Merge {
+        /// Profile whose parts come first.
+        base: String,
+
+        /// Profile layered on top.
+        overlay: String,
+
+        /// Name of the merged profile
+        #[arg(short, long, value_name = "PROFILE")]
+        output: String,
+
+        /// Keep parts present in both profiles twice
+        #[arg(long)]
+        keep_duplicates: bool,
+
+        /// Replace the result profile if it already exists
+        #[arg(long)]
+        overwrite: bool,
+    },
+
+    /// Rename a custom variable in a profile (or all profiles) and its definition
+    RenameVar {
+        /// Profile to change.
+        #[arg(long, required_unless_present = "all_profiles")]
+        profile: Option<String>,
+
+        /// Change every profile
+        #[arg(long, conflicts_with = "profile")]
+        all_profiles: bool,
+
+        /// Current variable name.
+        old: String,
+
+        /// New variable name.
+        new: String,
+    },
+
+    /// Compare two profiles variable by variable (exits 1 if they differ)
+    Diff {
+        /// First profile.
+        a: String,
+
+        /// Second profile.
+        b: String,
+
+        /// Print the differences as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Run a command with a profile applied to its environment
+    Run {
+        /// Profile to apply.
+        profile: String,
+
+        /// How list variables combine with the inherited values
+        #[arg(
+            short,
+            long,
+            default_value = "prepend",
+            value_parser = ["prepend", "append", "replace"]
+        )]
+        mode: String,
+
+        /// The command to run, after `--`.
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Spawn an interactive subshell with a profile active
+    Shell {
+        /// Profile to activate.
+        profile: String,
+
+        /// How list variables combine with the inherited values
+        #[arg(
+            short,
+            long,
+            default_value = "prepend",
+            value_parser = ["prepend", "append", "replace"]
+        )]
+        mode: String,
+
+        /// Start the shell even if already inside a bath shell
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Print the value a variable ends up with when a profile is applied
+    ///
+    /// Exits with status 1 if the profile has no entries for the variable.
+    Which {
+        /// Profile to apply.
+        profile: String,
+
+        /// Variable to resolve.
+        var: String,
+
+        /// How list variables combine with the inherited values
+        #[arg(
+            short,
+            long,
+            default_value = "prepend",
+            value_parser = ["prepend", "append", "replace"]
+        )]
+        mode: String,
+
+        /// Also list every entry contributing to the value
+        #[arg(long)]
+        explain: bool,
+    },
+
+    /// Check profiles for missing paths, duplicates and inconsistent entries
+    ///
+    /// Exits with status 1 if any problems remain.
+    Doctor {
+        /// Only check this profile (default: all profiles)
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// List the fixes: drop missing paths and duplicate parts
+        #[arg(long)]
+        fix: bool,
+
+        /// Apply the fixes listed by --fix and save the profiles
+        #[arg(long, requires = "fix")]
+        yes: bool,
+    },
+
+    /// Remove entries of path variables that point at nonexistent paths
+    Prune {
+        /// Only prune this profile (default: all profiles)
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Print what would be removed without saving
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Copy the database to a file (default: a timestamped file next to it)
+    Backup {
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Copy a legacy ~/.bath.db to the XDG data directory, which is used from then on
+    MigrateDb,
+
+    /// Replace the database with a backup
+    Restore {
+        path: std::path::PathBuf,
+
+        /// Import the backup's profiles next to the current ones instead;
+        /// a taken name gets an `-imported` suffix
+        #[arg(long)]
+        merge: bool,
+    },
+
+    /// Write the whole database as a JSON archive (stdout unless -o is given; `-` is stdout)
+    Dump {
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Load a JSON archive written by `dump`; fails on taken profile names unless
+    /// --merge or --replace is given
+    Load {
+        /// Archive to read; `-` reads stdin
+        path: std::path::PathBuf,
+
+        /// Load next to the existing profiles, suffixing taken names with `-imported`
+        #[arg(long, conflicts_with = "replace")]
+        merge: bool,
+
+        /// Delete all profiles, custom var definitions and items first
+        #[arg(long)]
+        replace: bool,
+    },
+
+    /// List the recorded TUI changes of a profile, newest first, or roll one back
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    History {
+        /// Profile whose changes to list
+        #[arg(required = true)]
         profile: Option<String>,
 
-        /// Operation mode: prepend, append, or replace (default is prepend)
+        #[command(subcommand)]
+        action: Option<HistoryAction>,
+    },
+
+    /// Manage profile metadata
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+
+    /// Manage catalog items (the values offered for dropping into profiles)
+    Items {
+        #[command(subcommand)]
+        action: ItemsAction,
+    },
+
+    /// Manage custom variable definitions
+    Defs {
+        #[command(subcommand)]
+        action: DefsAction,
+    },
+
+    /// Print a shell function enabling `bath use <profile>` and `bath deactivate`
+    ///
+    /// Add `eval "$(bath init bash)"` to your shell rc (fish: `bath init fish | source`).
+    Init {
+        #[arg(value_parser = ["bash", "zsh", "fish"])]
+        shell: String,
+    },
+
+    /// Scripts evaluated by the `bath init` shell function
+    #[command(hide = true)]
+    Hook {
+        #[arg(value_parser = ["bash", "zsh", "fish"])]
+        shell: String,
+
+        #[command(subcommand)]
+        action: HookAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HookAction {
+    /// Save the current values and apply a profile
+    Use {
+        profile: String,
+
         #[arg(
             short,
             long,
@@ -36,29 +457,496 @@ enum Commands {
         )]
         mode: String,
     },
+    /// Restore the values saved by `use`
+    Deactivate,
+}
+
+#[derive(Subcommand, Debug)]
+enum ProfileAction {
+    /// Note what a profile is for (shown by `list --verbose` and the TUI)
+    Describe {
+        name: String,
+
+        /// The description; an empty string clears it
+        #[arg(allow_hyphen_values = true)]
+        text: String,
+    },
+    /// Set the mode `export` uses for a profile when `--mode` is not given
+    SetMode {
+        name: String,
+
+        /// prepend, append or replace; omit to go back to prepend
+        #[arg(value_parser = ["prepend", "append", "replace"])]
+        mode: Option<String>,
+    },
+    /// Make a profile inherit the parts of another (its parent) on export
+    SetParent {
+        name: String,
+
+        /// The profile to extend; omit to stop extending
+        parent: Option<String>,
+    },
+    /// Move a profile to a place in the list order (0 is the top)
+    Move { name: String, index: usize },
+    /// Bring back a deleted profile
+    Recover {
+        name: String,
+
+        /// Recover it under this name (e.g. when the old name is taken again)
+        #[arg(long = "as", value_name = "NEW_NAME")]
+        new_name: Option<String>,
+    },
+    /// Remove deleted profiles for good
+    Purge {
+        /// Only those deleted at least this long ago, e.g. 30d, 12h
+        #[arg(long)]
+        older_than: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ItemsAction {
+    /// List items with their ids
+    List {
+        /// Only show items with this tag
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Only show items matching these words (prefixes of words in the value,
+        /// program, version or tags), best match first
+        #[arg(long)]
+        search: Option<String>,
+
+        /// Print the items as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Add an item
+    Add {
+        #[arg(allow_hyphen_values = true)]
+        value: String,
+
+        #[arg(long, default_value = "text", value_parser = ["path", "text"])]
+        kind: String,
+
+        /// Program the path belongs to (path items only)
+        #[arg(long)]
+        program: Option<String>,
+
+        /// Program version (path items only)
+        #[arg(long)]
+        version: Option<String>,
+
+        /// Comma-separated tags
+        #[arg(long, default_value = "")]
+        tags: String,
+
+        /// Add it even if an item with the same kind and value exists (by
+        /// default the tags are merged into that one)
+        #[arg(long)]
+        duplicate: bool,
+    },
+    /// Remove an item by id
+    Rm { id: i64 },
+    /// Merge items with the same kind and value into the oldest one
+    Dedup,
+    /// Add path items for the bin, include and lib directories under a
+    /// directory, tagged with what they hold and the directory they are in
+    Scan {
+        dir: std::path::PathBuf,
+
+        /// How many levels below the directory to look
+        #[arg(long, default_value_t = items::SCAN_DEPTH)]
+        depth: usize,
+    },
+    /// List the profile parts created by dropping an item in the TUI
+    WhereUsed { id: i64 },
+    /// Change fields of an item by id
+    Edit {
+        id: i64,
+
+        #[arg(long, allow_hyphen_values = true)]
+        value: Option<String>,
+
+        #[arg(long, value_parser = ["path", "text"])]
+        kind: Option<String>,
+
+        /// Program the path belongs to (path items only, empty to clear)
+        #[arg(long)]
+        program: Option<String>,
+
+        /// Program version (path items only, empty to clear)
+        #[arg(long)]
+        version: Option<String>,
+
+        /// Comma-separated tags, replacing the current ones
+        #[arg(long)]
+        tags: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryAction {
+    /// Put a profile back the way it was before change <id> (see `bath history <profile>`)
+    Restore { profile: String, id: i64 },
 }
 
-fn main() -> Result<()> {
+#[derive(Subcommand, Debug)]
+enum DefsAction {
+    /// List custom variable definitions
+    List,
+    /// Define a custom variable
+    Add {
+        name: String,
+
+        #[arg(long, default_value = "list", value_parser = ["list", "scalar"])]
+        kind: String,
+
+        /// Separator joining the parts of a list var
+        #[arg(long, default_value = ":")]
+        separator: String,
+
+        /// Value exported for profiles that have no parts of the var
+        #[arg(long)]
+        default: Option<String>,
+    },
+    /// Remove a custom variable definition
+    Rm {
+        name: String,
+
+        /// Remove it even if profiles still have entries for it
+        #[arg(long)]
+        force: bool,
+    },
+    /// Rename a custom variable definition and its entries in every profile
+    Rename { old: String, new: String },
+    /// Join a builtin list variable with another separator; without one, go
+    /// back to the builtin separator
+    Override {
+        name: String,
+        separator: Option<String>,
+    },
+}
+
+fn migrate_db_command() -> Result<()> {
+    let home = std::env::var("HOME").ok();
+    let xdg = std::env::var("XDG_DATA_HOME").ok();
+    let Some(legacy) = db::legacy_db_path(home.as_deref()).filter(|p| p.exists()) else {
+        println!("Nothing to migrate: there is no ~/.bath.db.");
+        return Ok(());
+    };
+    let dest =
+        db::xdg_db_path(home.as_deref(), xdg.as_deref()).ok_or(error::BathError::NoDatabasePath)?;
+    if dest.exists() {
+        anyhow::bail!(
+            "{} already exists; remove it to migrate {} again",
+            dest.display(),
+            legacy.display()
+        );
+    }
+    db::migrate_legacy_db(&legacy, &dest)?;
+    println!("Copied {} to {}.", legacy.display(), dest.display());
+    println!(
+        "The old file is kept but no longer used (see {}.moved).",
+        legacy.display()
+    );
+    Ok(())
+}
+
+fn backup_command(output: Option<std::path::PathBuf>) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let dest = match output {
+        Some(path) => path,
+        None => db::default_backup_path(&db::db_path()?, now),
+    };
+    db::backup_to(&conn, &dest)?;
+    println!("Backed up to {}.", dest.display());
+    Ok(())
+}
+
+fn restore_command(path: &std::path::Path, merge: bool) -> Result<()> {
+    let mut conn = db::establish_connection()?;
+    if merge {
+        for (original, name) in db::merge_from(&conn, path)? {
+            if original == name {
+                println!("Imported profile '{name}'.");
+            } else {
+                println!("Imported profile '{original}' as '{name}'.");
+            }
+        }
+    } else {
+        db::restore_from(&mut conn, path)?;
+        println!("Restored the database from {}.", path.display());
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run_cli() {
+        report_error(&err);
+        std::process::exit(error::exit_code(&err));
+    }
+}
+
+/// Prints `err` to stderr, with a red "error:" prefix when stderr is a terminal.
+fn report_error(err: &anyhow::Error) {
+    use crossterm::style::Stylize;
+    use std::io::IsTerminal;
+
+    let prefix = if std::io::stderr().is_terminal() {
+        "error:".red().bold().to_string()
+    } else {
+        "error:".to_string()
+    };
+    eprintln!("{prefix} {err:#}");
+}
+
+fn run_cli() -> Result<()> {
     let cli = Cli::parse();
+    if let Some(path) = cli.db {
+        db::set_db_path(path);
+    }
 
     match cli.command {
-        Some(Commands::Export { profile, mode }) => {
-            let op_mode = match mode.as_str() {
-                "prepend" => export::OperationMode::Prepend,
-                "append" => export::OperationMode::Append,
-                "replace" => export::OperationMode::Replace,
-                _ => export::OperationMode::Prepend, // fallback
+        Some(Commands::Export {
+            profiles,
+            mode,
+            mode_for,
+            only,
+            exclude,
+            sort_vars,
+            path_first,
+            undo,
+            dedup,
+            keep_empty,
+            idempotent,
+            comments,
+            delta,
+            expand,
+            no_header,
+            quote,
+            shell,
+            format,
+            resolve,
+            output,
+            single_instruction,
+            write,
+        }) => {
+            let opts = export::ExportOptions {
+                mode: mode.map(|m| m.parse()).transpose()?,
+                overrides: export::parse_mode_overrides(&mode_for)?,
+                filter: export::VarFilter::new(only, exclude),
+                order: export::VarOrder {
+                    alphabetical: sort_vars,
+                    path_first,
+                },
+                undo,
+                shell: shell.parse()?,
+                dedup,
+                keep_empty,
+                idempotent,
+                delta,
+                expand: expand.parse()?,
+                quote: quote.parse()?,
+                format: format.parse()?,
+                resolve,
+                output: export::output_file(output),
+                header: !no_header,
+                comments,
+                single_instruction,
+                write,
+                separators: db::load_separator_overrides(&db::establish_connection()?)?,
             };
-            if let Some(profile_name) = profile {
-                export::export_profile(&profile_name, op_mode)?;
-            } else {
+            if profiles.is_empty() {
                 // Launch interactive export selection if no profile was provided.
-                export::interactive_export(op_mode)?;
+                export::interactive_export(&opts)?;
+            } else {
+                export::export_profile(&profiles, &opts)?;
+            }
+        }
+        Some(Commands::CmakeToolchain { profile, output }) => {
+            cmake::cmake_toolchain_command(&profile, export::output_file(output).as_deref())?
+        }
+        Some(Commands::Direnv { profile, output }) => {
+            export::direnv_command(&profile, export::output_file(output).as_deref())?
+        }
+        Some(Commands::Snapshot {
+            name,
+            vars,
+            merge_into,
+        }) => {
+            snapshot::snapshot_profile(name.as_deref(), &vars, merge_into.as_deref())?;
+        }
+        Some(Commands::List { verbose, tag }) => profiles::list_command(verbose, tag.as_deref())?,
+        Some(Commands::Copy {
+            src,
+            dst,
+            overwrite,
+        }) => profiles::copy_command(&src, &dst, overwrite)?,
+        Some(Commands::Merge {
+            base,
+            overlay,
+            output,
+            keep_duplicates,
+            overwrite,
+        }) => profiles::merge_command(&base, &overlay, &output, keep_duplicates, overwrite)?,
+        Some(Commands::RenameVar {
+            profile,
+            all_profiles: _,
+            old,
+            new,
+        }) => profiles::rename_var_command(profile.as_deref(), &old, &new)?,
+        Some(Commands::Diff { a, b, json }) => {
+            if diff::diff_command(&a, &b, json)? {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Run {
+            profile,
+            mode,
+            command,
+        }) => {
+            let code = run::run_command(&profile, mode.parse()?, &command)?;
+            std::process::exit(code);
+        }
+        Some(Commands::Shell {
+            profile,
+            mode,
+            force,
+        }) => {
+            let code = run::shell_command(&profile, mode.parse()?, force)?;
+            std::process::exit(code);
+        }
+        Some(Commands::Which {
+            profile,
+            var,
+            mode,
+            explain,
+        }) => which::which_command(&profile, &var, mode.parse()?, explain)?,
+        Some(Commands::Prune { profile, dry_run }) => {
+            validate::prune_command(profile.as_deref(), dry_run)?
+        }
+        Some(Commands::Doctor { profile, fix, yes }) => {
+            if validate::doctor_command(profile.as_deref(), fix, yes)? {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Backup { output }) => backup_command(output)?,
+        Some(Commands::MigrateDb) => migrate_db_command()?,
+        Some(Commands::Dump { output }) => {
+            archive::dump_command(export::output_file(output).as_deref())?
+        }
+        Some(Commands::Load {
+            path,
+            merge,
+            replace,
+        }) => {
+            let mode = match (merge, replace) {
+                (true, _) => archive::LoadMode::Merge,
+                (_, true) => archive::LoadMode::Replace,
+                _ => archive::LoadMode::Strict,
+            };
+            archive::load_command(&path, mode)?
+        }
+        Some(Commands::Restore { path, merge }) => restore_command(&path, merge)?,
+        Some(Commands::History { profile, action }) => match (action, profile) {
+            (Some(HistoryAction::Restore { profile, id }), _) => {
+                history::restore_command(&profile, id)?
+            }
+            (None, profile) => history::list_command(&profile.unwrap_or_default())?,
+        },
+        Some(Commands::Profile { action }) => match action {
+            ProfileAction::Describe { name, text } => profiles::describe_command(&name, &text)?,
+            ProfileAction::SetMode { name, mode } => {
+                profiles::set_mode_command(&name, mode.map(|m| m.parse()).transpose()?)?
+            }
+            ProfileAction::SetParent { name, parent } => {
+                profiles::set_parent_command(&name, parent.as_deref())?
+            }
+            ProfileAction::Move { name, index } => profiles::move_command(&name, index)?,
+            ProfileAction::Recover { name, new_name } => {
+                profiles::recover_command(&name, new_name.as_deref())?
+            }
+            ProfileAction::Purge { older_than } => profiles::purge_command(older_than.as_deref())?,
+        },
+        Some(Commands::Items { action }) => match action {
+            ItemsAction::List { tag, search, json } => {
+                items::list_command(tag.as_deref(), search.as_deref(), json)?
+            }
+            ItemsAction::Add {
+                value,
+                kind,
+                program,
+                version,
+                tags,
+                duplicate,
+            } => items::add_command(
+                items::build_item(
+                    &value,
+                    items::parse_kind(&kind)?,
+                    program.as_deref(),
+                    version.as_deref(),
+                    &tags,
+                )?,
+                duplicate,
+            )?,
+            ItemsAction::Rm { id } => items::rm_command(id)?,
+            ItemsAction::Dedup => items::dedup_command()?,
+            ItemsAction::Scan { dir, depth } => items::scan_command(&dir, depth)?,
+            ItemsAction::WhereUsed { id } => items::where_used_command(id)?,
+            ItemsAction::Edit {
+                id,
+                value,
+                kind,
+                program,
+                version,
+                tags,
+            } => items::edit_command(
+                id,
+                items::ItemEdit {
+                    value: value.as_deref(),
+                    kind: kind.as_deref().map(items::parse_kind).transpose()?,
+                    program: program.as_deref(),
+                    version: version.as_deref(),
+                    tags: tags.as_deref(),
+                },
+            )?,
+        },
+        Some(Commands::Defs { action }) => match action {
+            DefsAction::List => defs::list_command()?,
+            DefsAction::Add {
+                name,
+                kind,
+                separator,
+                default,
+            } => defs::add_command(config::CustomVarDef {
+                default_value: default,
+                ..defs::build_def(&name, defs::parse_kind(&kind)?, &separator)?
+            })?,
+            DefsAction::Rm { name, force } => defs::rm_command(&name, force)?,
+            DefsAction::Rename { old, new } => defs::rename_command(&old, &new)?,
+            DefsAction::Override { name, separator } => {
+                defs::override_command(&name, separator.as_deref())?
+            }
+        },
+        Some(Commands::Init { shell }) => {
+            print!("{}", hook::init_script(shell.parse()?));
+        }
+        Some(Commands::Hook { shell, action }) => {
+            let shell: hook::HookShell = shell.parse()?;
+            match action {
+                HookAction::Use { profile, mode } => {
+                    hook::print_use(shell, &profile, mode.parse()?)?
+                }
+                HookAction::Deactivate => hook::print_deactivate(shell)?,
             }
         }
         None => {
             // If no subcommand is provided, run the interactive TUI.
-            tui_app::run()?;
+            tui_app::run(cli.expand_home_at_save)?;
         }
     }
 
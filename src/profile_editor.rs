@@ -1,3 +1,5 @@
+use crate::tui::line_input::LineInput;
+use crate::tui::util::{key_press, set_input_cursor};
 use anyhow::Result;
 use crossterm::event::{poll, read, Event, KeyCode};
 use ratatui::{
@@ -13,7 +15,7 @@ pub fn edit_profile_name_dialog<B: Backend>(
     terminal: &mut Terminal<B>,
     initial: Option<&str>,
 ) -> Result<Option<String>> {
-    let mut name = initial.unwrap_or("").to_string();
+    let mut name = LineInput::new(initial.unwrap_or(""));
     loop {
         terminal.draw(|f| {
             let area = centered_rect(50, 20, f.size());
@@ -24,19 +26,19 @@ pub fn edit_profile_name_dialog<B: Backend>(
                 .block(block)
                 .style(Style::default());
             f.render_widget(paragraph, area);
+            set_input_cursor(f, area, 0, "Profile Name: ", &name);
         })?;
         if poll(Duration::from_millis(100))? {
-            if let Event::Key(key) = read()? {
+            let event = read()?;
+            if let Event::Paste(text) = &event {
+                name.paste(text);
+            } else if let Some(key) = key_press(event) {
                 match key.code {
-                    KeyCode::Enter => return Ok(Some(name)),
+                    KeyCode::Enter => return Ok(Some(name.to_string())),
                     KeyCode::Esc => return Ok(None),
-                    KeyCode::Backspace => {
-                        name.pop();
+                    _ => {
+                        name.handle_key(key);
                     }
-                    KeyCode::Char(c) => {
-                        name.push(c);
-                    }
-                    _ => {}
                 }
             }
         }
@@ -85,7 +87,7 @@ pub fn confirm_dialog<B: Backend>(terminal: &mut Terminal<B>, message: &str) ->
             f.render_widget(paragraph, area);
         })?;
         if poll(Duration::from_millis(100))? {
-            if let Event::Key(key) = read()? {
+            if let Some(key) = key_press(read()?) {
                 match key.code {
                     KeyCode::Char('y') | KeyCode::Char('Y') => return Ok(true),
                     KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => return Ok(false),
@@ -0,0 +1,446 @@
+// src/profiles.rs
+
+use crate::config::{self, time_ago, CustomVarDef, Entry, EnvProfile};
+use crate::db;
+use crate::defs::is_posix_identifier;
+use crate::error::BathError;
+use crate::export::{entry_value, inheritance_chain, OperationMode};
+use crate::validate::is_scalar_var;
+use anyhow::Result;
+
+/// The profiles whose entries can be read, warning about each one that is
+/// skipped because they cannot.
+pub fn readable_profiles(conn: &rusqlite::Connection) -> crate::error::Result<Vec<EnvProfile>> {
+    let (profiles, broken) = db::load_profiles_checked(conn)?;
+    for b in &broken {
+        warn_unreadable(&b.name);
+    }
+    Ok(profiles)
+}
+
+/// Tells that profile `name` is left out because its entries cannot be read.
+pub fn warn_unreadable(name: &str) {
+    eprintln!("warning: skipped profile '{name}', its entries cannot be read (see `bath doctor`)");
+}
+
+/// Prints the profile names, with `verbose` also their size, age and tags.
+/// `tag` keeps only the profiles carrying it.
+pub fn list_command(verbose: bool, tag: Option<&str>) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let metas = db::load_profile_metas(&conn)?
+        .into_iter()
+        .filter(|m| tag.is_none_or(|t| m.tags.iter().any(|mt| mt == t)));
+    for meta in metas {
+        if verbose {
+            println!(
+                "{}  {} entries, updated {}, created {}",
+                meta.name,
+                meta.entry_count,
+                time_ago(meta.updated_at, now),
+                time_ago(meta.created_at, now)
+            );
+            if let Some(description) = &meta.description {
+                println!("    {description}");
+            }
+            if !meta.tags.is_empty() {
+                let tags: Vec<String> = meta.tags.iter().map(|t| format!("#{t}")).collect();
+                println!("    {}", tags.join(" "));
+            }
+        } else {
+            println!("{}", meta.name);
+        }
+    }
+    Ok(())
+}
+
+/// Sets the description of a profile; empty text clears it.
+pub fn describe_command(name: &str, text: &str) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let mut profile = db::load_profile(&conn, name)?;
+    let text = text.trim();
+    profile.description = (!text.is_empty()).then(|| text.to_string());
+    db::save_profile(&conn, &profile)?;
+    match &profile.description {
+        Some(_) => println!("Described profile '{name}'."),
+        None => println!("Cleared the description of '{name}'."),
+    }
+    Ok(())
+}
+
+/// Sets the mode `bath export` uses for a profile without `--mode`; `None`
+/// goes back to prepend.
+pub fn set_mode_command(name: &str, mode: Option<OperationMode>) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let mut profile = db::load_profile(&conn, name)?;
+    profile.default_mode = mode;
+    db::save_profile(&conn, &profile)?;
+    match mode {
+        Some(mode) => println!("Profile '{name}' now exports in {} mode.", mode.name()),
+        None => println!("Profile '{name}' now exports in prepend mode (the default)."),
+    }
+    Ok(())
+}
+
+/// Makes profile `name` extend `parent`; `None` stops extending. Fails if the
+/// parent does not exist or already extends `name`.
+pub fn set_parent_command(name: &str, parent: Option<&str>) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let mut profile = db::load_profile(&conn, name)?;
+    profile.extends = parent.map(str::to_string);
+    inheritance_chain(name, |n| {
+        if n == name {
+            Ok(profile.clone())
+        } else {
+            db::load_profile(&conn, n)
+        }
+    })?;
+    db::save_profile(&conn, &profile)?;
+    match parent {
+        Some(parent) => println!("Profile '{name}' now extends '{parent}'."),
+        None => println!("Profile '{name}' no longer extends another profile."),
+    }
+    Ok(())
+}
+
+/// Moves profile `name` to `index` (0-based) in the manual order; indexes past
+/// the end move it last.
+pub fn move_command(name: &str, index: usize) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let mut names: Vec<String> = db::load_all_profiles(&conn)?
+        .into_iter()
+        .map(|p| p.name)
+        .filter(|n| n != name)
+        .collect();
+    db::load_profile(&conn, name)?;
+    let index = index.min(names.len());
+    names.insert(index, name.to_string());
+    db::set_profile_order(&conn, &names)?;
+    println!("Moved profile '{name}' to position {index}.");
+    Ok(())
+}
+
+/// Brings a deleted profile back, optionally under another name.
+pub fn recover_command(name: &str, new_name: Option<&str>) -> Result<()> {
+    let conn = db::establish_connection()?;
+    db::recover_profile(&conn, name, new_name).map_err(|e| match e {
+        BathError::ProfileExists(_) => {
+            anyhow::Error::from(e).context("recover it under another name with --as <new name>")
+        }
+        e => e.into(),
+    })?;
+    match new_name {
+        Some(new_name) => println!("Recovered profile '{name}' as '{new_name}'."),
+        None => println!("Recovered profile '{name}'."),
+    }
+    Ok(())
+}
+
+/// Parses an age like `30d`, `12h`, `90m` or `45s` into seconds.
+pub fn parse_age(s: &str) -> Result<i64, BathError> {
+    let invalid = || {
+        BathError::InvalidArgument(format!(
+            "invalid age: {s:?} (expected a number followed by s, m, h, d or w, e.g. 30d)"
+        ))
+    };
+    let unit = s.chars().last().ok_or_else(invalid)?;
+    let scale = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86_400,
+        'w' => 7 * 86_400,
+        _ => return Err(invalid()),
+    };
+    let n: i64 = s[..s.len() - 1].parse().map_err(|_| invalid())?;
+    n.checked_mul(scale).filter(|v| *v >= 0).ok_or_else(invalid)
+}
+
+/// Removes deleted profiles for good (only those deleted at least `older_than`
+/// ago, if given).
+pub fn purge_command(older_than: Option<&str>) -> Result<()> {
+    let older_than = older_than.map(parse_age).transpose()?;
+    let conn = db::establish_connection()?;
+    let purged = db::purge_deleted_profiles(&conn, older_than)?;
+    if purged.is_empty() {
+        println!("Nothing to purge.");
+    }
+    for name in purged {
+        println!("Purged profile '{name}'.");
+    }
+    Ok(())
+}
+
+/// Duplicates profile `src` as `dst`.
+pub fn copy_command(src: &str, dst: &str, overwrite: bool) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let copy = db::copy_profile(&conn, src, dst, overwrite)?;
+    println!(
+        "Copied profile '{src}' to '{dst}' ({} entries).",
+        copy.entries.len()
+    );
+    Ok(())
+}
+
+/// Checks that a custom var can be renamed from `old` to `new`: neither side may
+/// be a builtin, and `new` must be a valid variable name.
+pub fn check_var_rename(old: &str, new: &str) -> Result<()> {
+    if config::builtin_var(old).is_some() {
+        anyhow::bail!("{old} is a builtin variable and cannot be renamed");
+    }
+    if !is_posix_identifier(new) {
+        return Err(BathError::InvalidVarName(new.to_string()).into());
+    }
+    if config::builtin_var(new).is_some() {
+        anyhow::bail!("{new} is a builtin variable");
+    }
+    Ok(())
+}
+
+/// Renames a custom var in one profile (or all of them). The definition follows
+/// once no other profile uses the old name.
+///
+/// Each profile is saved once, after all of its entries were renamed, and all of
+/// it is one transaction.
+pub fn rename_var_command(profile: Option<&str>, old: &str, new: &str) -> Result<()> {
+    check_var_rename(old, new)?;
+    let conn = db::establish_connection()?;
+    let profiles = match profile {
+        Some(name) => vec![db::load_profile(&conn, name)?],
+        None => readable_profiles(&conn)?,
+    };
+
+    db::atomically(&conn, |conn| {
+        let mut total = 0;
+        for mut p in profiles {
+            let touched = p.rename_custom_var(old, new);
+            if touched > 0 {
+                db::save_profile(conn, &p)?;
+                println!("{}: renamed {touched} entries", p.name);
+                total += touched;
+            }
+        }
+        let others = db::profiles_using_custom_var(conn, old)?;
+        if !others.is_empty() {
+            if db::load_custom_var_defs(conn)?
+                .iter()
+                .any(|d| d.name == old)
+            {
+                println!(
+                    "Kept the definition of {old}, still used by: {}",
+                    others.join(", ")
+                );
+            }
+        } else if db::rename_custom_var_def(conn, old, new)? {
+            println!("Renamed custom var definition {old} to {new}.");
+        }
+        if total == 0 {
+            println!("No entries for {old} found.");
+        }
+        Ok(())
+    })
+}
+
+/// Outcome of `merge_profiles`: the merged profile plus one notice per scalar the
+/// overlay overrode.
+#[derive(Debug)]
+pub struct Merged {
+    pub profile: EnvProfile,
+    pub notices: Vec<String>,
+}
+
+/// Combines `base` and `overlay` into a new profile named `name`.
+///
+/// Variables keep the order in which they first appear (base first). List vars get
+/// the base parts followed by the overlay parts, with identical parts dropped
+/// unless `keep_duplicates`. For scalars set by both, the overlay wins.
+pub fn merge_profiles(
+    base: &EnvProfile,
+    overlay: &EnvProfile,
+    name: &str,
+    custom_defs: &[CustomVarDef],
+    keep_duplicates: bool,
+) -> Merged {
+    let all: Vec<Entry> = base
+        .entries
+        .iter()
+        .chain(&overlay.entries)
+        .cloned()
+        .collect();
+    // (var, entries so far, whether the overlay already replaced a scalar)
+    let mut vars: Vec<(String, Vec<Entry>, bool)> = Vec::new();
+    let mut notices = Vec::new();
+
+    for (i, entry) in all.iter().enumerate() {
+        let var = entry.var_name().into_owned();
+        let pos = match vars.iter().position(|(v, _, _)| *v == var) {
+            Some(pos) => pos,
+            None => {
+                vars.push((var.clone(), Vec::new(), false));
+                vars.len() - 1
+            }
+        };
+        let (_, slot, replaced) = &mut vars[pos];
+
+        if is_scalar_var(&var, &all, custom_defs) {
+            let from_overlay = i >= base.entries.len();
+            if from_overlay && !*replaced {
+                *replaced = true;
+                if let Some(old) = slot.last().map(entry_value) {
+                    let new = entry_value(entry);
+                    if old != new {
+                        notices.push(format!("{var}: using '{new}' from overlay (base: '{old}')"));
+                    }
+                }
+                slot.clear();
+            }
+            slot.push(entry.clone());
+            continue;
+        }
+
+        let value = entry_value(entry);
+        if keep_duplicates || !slot.iter().any(|e| entry_value(e) == value) {
+            slot.push(entry.clone());
+        }
+    }
+
+    Merged {
+        profile: EnvProfile {
+            entries: vars
+                .into_iter()
+                .flat_map(|(_, entries, _)| entries)
+                .collect(),
+            ..EnvProfile::new(name)
+        },
+        notices,
+    }
+}
+
+/// Merges `base` and `overlay` into a new stored profile `result`.
+pub fn merge_command(
+    base: &str,
+    overlay: &str,
+    result: &str,
+    keep_duplicates: bool,
+    overwrite: bool,
+) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let base = db::load_profile(&conn, base)?;
+    let overlay = db::load_profile(&conn, overlay)?;
+    if !overwrite && db::profile_exists(&conn, result)? {
+        return Err(BathError::ProfileExists(result.to_string()).into());
+    }
+    let custom_defs = db::load_custom_var_defs(&conn)?;
+    let merged = merge_profiles(&base, &overlay, result, &custom_defs, keep_duplicates);
+    for notice in &merged.notices {
+        eprintln!("notice: {notice}");
+    }
+    db::save_profile(&conn, &merged.profile)?;
+    println!(
+        "Merged '{}' and '{}' into '{result}' ({} entries).",
+        base.name,
+        overlay.name,
+        merged.profile.entries.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_vars_cannot_be_renamed() {
+        assert!(check_var_rename("CFLAGS", "MY_FLAGS").is_err());
+        assert!(check_var_rename("MY_FLAGS", "CFLAGS").is_err());
+        assert!(check_var_rename("MY_FLAGS", "my-flags").is_err());
+        assert!(check_var_rename("TOOLCHAIN_BIN", "XTOOL_BIN").is_ok());
+    }
+
+    #[test]
+    fn rename_touches_only_matching_custom_entries() {
+        let mut p = EnvProfile {
+            entries: vec![
+                Entry::CustomPart {
+                    name: "TOOLCHAIN_BIN".to_string(),
+                    value: "/opt/a".to_string(),
+                    separator: ":".to_string(),
+                    mode: None,
+                },
+                Entry::CFlag("-O2".to_string()),
+                Entry::CustomScalar {
+                    name: "OTHER".to_string(),
+                    value: "x".to_string(),
+                },
+                Entry::CustomPart {
+                    name: "TOOLCHAIN_BIN".to_string(),
+                    value: "/opt/b".to_string(),
+                    separator: ":".to_string(),
+                    mode: None,
+                },
+            ],
+            ..EnvProfile::new("p")
+        };
+        assert_eq!(p.rename_custom_var("TOOLCHAIN_BIN", "XTOOL_BIN"), 2);
+        let names: Vec<String> = p
+            .entries
+            .iter()
+            .map(|e| e.var_name().into_owned())
+            .collect();
+        assert_eq!(names, vec!["XTOOL_BIN", "CFLAGS", "OTHER", "XTOOL_BIN"]);
+    }
+
+    #[test]
+    fn merge_puts_base_parts_first_and_overlay_scalars_win() {
+        let base = EnvProfile {
+            entries: vec![
+                Entry::CFlag("-O2".to_string()),
+                Entry::CC("gcc".to_string()),
+                Entry::CFlag("-g".to_string()),
+            ],
+            ..EnvProfile::new("base")
+        };
+        let overlay = EnvProfile {
+            entries: vec![
+                Entry::LDFlag("-fsanitize=address".to_string()),
+                Entry::CFlag("-g".to_string()),
+                Entry::CFlag("-fsanitize=address".to_string()),
+                Entry::CC("clang".to_string()),
+            ],
+            ..EnvProfile::new("sanitizers")
+        };
+
+        let merged = merge_profiles(&base, &overlay, "asan", &[], false);
+        let values: Vec<String> = merged.profile.entries.iter().map(entry_value).collect();
+        assert_eq!(merged.profile.name, "asan");
+        assert_eq!(
+            values,
+            vec![
+                "-O2",
+                "-g",
+                "-fsanitize=address",
+                "clang",
+                "-fsanitize=address"
+            ]
+        );
+        assert_eq!(
+            merged.notices,
+            vec!["CC: using 'clang' from overlay (base: 'gcc')"]
+        );
+
+        let kept = merge_profiles(&base, &overlay, "asan", &[], true);
+        assert_eq!(kept.profile.entries.len(), 6);
+    }
+
+    #[test]
+    fn ages_take_a_unit() {
+        assert_eq!(parse_age("30d").unwrap(), 30 * 86_400);
+        assert_eq!(parse_age("2w").unwrap(), 14 * 86_400);
+        assert_eq!(parse_age("90m").unwrap(), 5400);
+        for bad in ["", "30", "d", "-1d", "3x"] {
+            assert!(parse_age(bad).is_err(), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,185 @@
+// src/run.rs
+
+use crate::db;
+use crate::export::{self, OperationMode};
+use anyhow::{Context, Result};
+use std::process::{Command, ExitStatus};
+
+/// Marker variable set inside `bath shell` so nested invocations can be detected.
+pub const PROFILE_MARKER: &str = "BATH_PROFILE";
+
+/// The variables a profile sets, composed on the current environment, and the
+/// ones it unsets.
+struct ProfileEnv {
+    vars: Vec<(String, String)>,
+    unset: Vec<String>,
+}
+
+/// Builds a command for `argv` with the given variables set in its environment
+/// and the `unset` ones removed from it.
+pub fn command_with_env(
+    argv: &[String],
+    vars: &[(String, String)],
+    unset: &[String],
+) -> Result<Command> {
+    let (program, args) = argv.split_first().context("no command given to run")?;
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+    cmd.envs(vars.iter().map(|(k, v)| (k, v)));
+    for var in unset {
+        cmd.env_remove(var);
+    }
+    Ok(cmd)
+}
+
+/// Maps a child's exit status onto our own exit code.
+///
+/// Children killed by a signal report `128 + signal`, like shells do.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    if let Some(code) = status.code() {
+        return code;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(sig) = status.signal() {
+            return 128 + sig;
+        }
+    }
+    1
+}
+
+/// Loads a profile and composes its variables on top of the current environment.
+fn profile_env(profile_name: &str, mode: OperationMode) -> Result<ProfileEnv> {
+    let conn = db::establish_connection()?;
+    let base = export::current_env();
+    let profile = export::load_inherited(&conn, profile_name, &base)?;
+    export::check_references(&profile)?;
+    let separators = db::load_separator_overrides(&conn)?;
+    Ok(ProfileEnv {
+        vars: export::compose_env(&profile, mode, &base, &separators),
+        unset: export::unset_vars(&profile.entries),
+    })
+}
+
+/// Runs `argv` with the profile applied on top of the current environment.
+///
+/// Returns the exit code of the child.
+pub fn run_command(profile_name: &str, mode: OperationMode, argv: &[String]) -> Result<i32> {
+    let env = profile_env(profile_name, mode)?;
+    let status = command_with_env(argv, &env.vars, &env.unset)?
+        .status()
+        .with_context(|| format!("failed to run {}", argv[0]))?;
+    Ok(exit_code(status))
+}
+
+/// One-line description of an activated profile, printed before the subshell starts.
+pub fn shell_banner(profile_name: &str, vars: &[(String, String)]) -> String {
+    let names: Vec<&str> = vars.iter().map(|(k, _)| k.as_str()).collect();
+    if names.is_empty() {
+        format!("bath: entering shell for profile '{profile_name}' (no variables modified)")
+    } else {
+        format!(
+            "bath: entering shell for profile '{profile_name}' (modified: {})",
+            names.join(", ")
+        )
+    }
+}
+
+/// Spawns an interactive `$SHELL` (or /bin/sh) with the profile applied.
+///
+/// Refuses to nest inside another bath shell unless `force` is set. Returns
+/// the exit code of the shell.
+pub fn shell_command(profile_name: &str, mode: OperationMode, force: bool) -> Result<i32> {
+    if let Ok(active) = std::env::var(PROFILE_MARKER) {
+        if !force {
+            anyhow::bail!(
+                "already inside a bath shell for profile '{active}' (use --force to nest)"
+            );
+        }
+    }
+
+    let ProfileEnv { mut vars, unset } = profile_env(profile_name, mode)?;
+    eprintln!("{}", shell_banner(profile_name, &vars));
+    vars.push((PROFILE_MARKER.to_string(), profile_name.to_string()));
+
+    let shell = std::env::var("SHELL")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "/bin/sh".to_string());
+    let status = command_with_env(std::slice::from_ref(&shell), &vars, &unset)?
+        .status()
+        .with_context(|| format!("failed to start {shell}"))?;
+    Ok(exit_code(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Entry, EnvProfile, SeparatorOverrides};
+    use std::collections::HashMap;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["sh".to_string(), "-c".to_string(), script.to_string()]
+    }
+
+    #[test]
+    fn child_sees_the_composed_value() -> Result<()> {
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::CPath("/opt/a".to_string()),
+                Entry::CPath("/opt/b".to_string()),
+            ],
+            ..EnvProfile::new("p")
+        };
+        let base: HashMap<String, String> = [("CPATH".to_string(), "/usr/include".to_string())]
+            .into_iter()
+            .collect();
+        let vars = export::compose_env(
+            &profile,
+            OperationMode::Append,
+            &base,
+            &SeparatorOverrides::new(),
+        );
+
+        let out = command_with_env(&sh("printf %s \"$CPATH\""), &vars, &[])?.output()?;
+        assert_eq!(
+            String::from_utf8_lossy(&out.stdout),
+            "/usr/include:/opt/a:/opt/b"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unset_vars_are_removed_from_the_child() -> Result<()> {
+        let unset = ["HOME".to_string()];
+        let out = command_with_env(&sh("printf %s \"${HOME-gone}\""), &[], &unset)?.output()?;
+        assert_eq!(String::from_utf8_lossy(&out.stdout), "gone");
+        Ok(())
+    }
+
+    #[test]
+    fn exit_status_is_propagated() -> Result<()> {
+        let status = command_with_env(&sh("exit 7"), &[], &[])?.status()?;
+        assert_eq!(exit_code(status), 7);
+        Ok(())
+    }
+
+    #[test]
+    fn banner_lists_modified_vars() {
+        let vars = vec![
+            ("PATH".to_string(), "/x".to_string()),
+            ("CC".to_string(), "gcc".to_string()),
+        ];
+        assert_eq!(
+            shell_banner("arm", &vars),
+            "bath: entering shell for profile 'arm' (modified: PATH, CC)"
+        );
+        assert!(shell_banner("empty", &[]).contains("no variables modified"));
+    }
+
+    #[test]
+    fn empty_command_is_an_error() {
+        assert!(command_with_env(&[], &[], &[]).is_err());
+    }
+}
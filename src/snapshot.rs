@@ -0,0 +1,174 @@
+// src/snapshot.rs
+
+use crate::config::{
+    self, lookup_var_option, CustomVarDef, Entry, EnvProfile, VarKind, BUILTIN_VARS,
+};
+use crate::db;
+use crate::export;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Returns (kind, separator) for a var, preferring builtins over custom defs.
+///
+/// Unknown vars are treated like list vars joined with ':' (same as the TUI).
+fn var_layout(var_name: &str, custom_defs: &[CustomVarDef]) -> (VarKind, String) {
+    match lookup_var_option(var_name, custom_defs) {
+        Some(o) => (o.kind, o.separator),
+        None => (VarKind::List, ":".to_string()),
+    }
+}
+
+/// Names of every builtin var that is set (and non-empty) in `env`.
+pub fn default_snapshot_vars(env: &HashMap<String, String>) -> Vec<String> {
+    BUILTIN_VARS
+        .iter()
+        .map(|v| v.name.to_string())
+        .filter(|name| env.get(name).is_some_and(|v| !v.is_empty()))
+        .collect()
+}
+
+/// Converts the given variables of `env` into profile entries.
+///
+/// List vars are split on their separator (one entry per non-empty segment),
+/// scalars become a single entry, and unset or empty vars are skipped.
+pub fn snapshot_entries(
+    env: &HashMap<String, String>,
+    vars: &[String],
+    custom_defs: &[CustomVarDef],
+) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for var in vars {
+        let Some(value) = env.get(var) else {
+            continue;
+        };
+        if value.is_empty() {
+            continue;
+        }
+        let (kind, sep) = var_layout(var, custom_defs);
+        match kind {
+            VarKind::Scalar => {
+                entries.push(config::make_entry(var, value.clone(), custom_defs));
+            }
+            VarKind::List => {
+                for part in value.split(sep.as_str()).filter(|p| !p.is_empty()) {
+                    entries.push(config::make_entry(var, part.to_string(), custom_defs));
+                }
+            }
+        }
+    }
+    entries
+}
+
+/// Captures variables from the current process environment into a profile.
+///
+/// With `merge_into`, the entries are appended to that existing profile instead
+/// of creating a new one named `name`.
+pub fn snapshot_profile(
+    name: Option<&str>,
+    vars: &[String],
+    merge_into: Option<&str>,
+) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let custom_defs = db::load_custom_var_defs(&conn)?;
+    let env = export::current_env();
+
+    let vars = if vars.is_empty() {
+        default_snapshot_vars(&env)
+    } else {
+        vars.to_vec()
+    };
+    let entries = snapshot_entries(&env, &vars, &custom_defs);
+    let count = entries.len();
+
+    let profile = if let Some(target) = merge_into {
+        let mut profile = db::load_profile(&conn, target)?;
+        profile.entries.extend(entries);
+        profile
+    } else {
+        let Some(name) = name else {
+            anyhow::bail!("a profile name is required unless --merge-into is given");
+        };
+        if db::profile_exists(&conn, name)? {
+            anyhow::bail!("profile already exists: {name} (use --merge-into to append)");
+        }
+        let mut profile = EnvProfile::new(name);
+        profile.entries = entries;
+        profile
+    };
+
+    db::save_profile(&conn, &profile)?;
+    println!("Saved {count} entries into profile '{}'.", profile.name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn list_vars_are_split_on_their_builtin_separator() {
+        let env = env(&[
+            ("PATH", "/a/bin:/b/bin"),
+            ("CFLAGS", "-O2  -Wall"),
+            ("CC", "gcc-13"),
+        ]);
+        let vars = vec!["PATH".to_string(), "CFLAGS".to_string(), "CC".to_string()];
+
+        let entries = snapshot_entries(&env, &vars, &[]);
+        let rendered: Vec<String> = entries.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            rendered,
+            vec![
+                "PATH: /a/bin",
+                "PATH: /b/bin",
+                "CFLAGS: -O2",
+                "CFLAGS: -Wall",
+                "CC: gcc-13",
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_defs_control_separator_and_kind() {
+        let defs = vec![
+            CustomVarDef::new("MY_LIST", VarKind::List, ";"),
+            CustomVarDef::new("SYSROOT", VarKind::Scalar, ""),
+        ];
+        let env = env(&[("MY_LIST", "a;b"), ("SYSROOT", "/x:/y")]);
+        let vars = vec!["MY_LIST".to_string(), "SYSROOT".to_string()];
+
+        let entries = snapshot_entries(&env, &vars, &defs);
+        assert_eq!(entries.len(), 3);
+        assert!(matches!(
+            &entries[0],
+            Entry::CustomPart { value, separator, .. } if value == "a" && separator == ";"
+        ));
+        assert!(matches!(
+            &entries[2],
+            Entry::CustomScalar { value, .. } if value == "/x:/y"
+        ));
+    }
+
+    #[test]
+    fn unset_and_empty_vars_are_skipped() {
+        let env = env(&[("CPATH", ""), ("CC", "cc")]);
+        let vars = vec!["CPATH".to_string(), "CXX".to_string(), "CC".to_string()];
+
+        let entries = snapshot_entries(&env, &vars, &[]);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].var_name(), "CC");
+    }
+
+    #[test]
+    fn default_vars_are_the_set_builtins() {
+        let env = env(&[("PATH", "/bin"), ("CC", ""), ("HOME", "/home/me")]);
+        assert_eq!(default_snapshot_vars(&env), vec!["PATH".to_string()]);
+    }
+}
@@ -0,0 +1,177 @@
+// src/template.rs
+
+use crate::config::{Entry, EnvProfile};
+use std::collections::HashMap;
+
+/// One `{NAME}` or `{ENV:NAME}` placeholder found in a value.
+enum Token<'a> {
+    /// `{{`, a literal brace.
+    Brace,
+    Placeholder(&'a str),
+}
+
+/// Finds the token starting at `rest`, which begins with '{', and its length.
+/// Placeholder names are upper case, optionally followed by `:` and a variable
+/// name, so shell syntax like `{a,b}` is left alone.
+fn token(rest: &str) -> Option<(Token<'_>, usize)> {
+    if rest.starts_with("{{") {
+        return Some((Token::Brace, 2));
+    }
+    let end = rest.find('}')?;
+    let name = &rest[1..end];
+    let (head, arg) = match name.split_once(':') {
+        Some((head, arg)) => (head, Some(arg)),
+        None => (name, None),
+    };
+    let upper = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+    };
+    let var_name = |s: &str| {
+        !s.is_empty()
+            && !s.starts_with(|c: char| c.is_ascii_digit())
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    (upper(head) && arg.is_none_or(var_name)).then_some((Token::Placeholder(name), end + 1))
+}
+
+/// The value of a known placeholder, `None` for unknown ones.
+fn lookup(name: &str, profile: &str, env: &HashMap<String, String>) -> Option<String> {
+    match name {
+        "HOME" => env.get("HOME").cloned(),
+        "PROFILE" => Some(profile.to_string()),
+        "ARCH" => Some(std::env::consts::ARCH.to_string()),
+        _ => {
+            let var = name.strip_prefix("ENV:")?;
+            Some(env.get(var).cloned().unwrap_or_default())
+        }
+    }
+}
+
+/// Walks `value`, handing every placeholder to `f`, which returns its
+/// replacement or `None` to keep it as written. `${VAR}` (shell syntax) and
+/// `@{VAR}` (a reference to another variable of the profile) are never
+/// placeholders.
+fn rewrite(value: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(at) = rest.find('{') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let after_sigil = out.ends_with(['$', '@']);
+        match token(rest) {
+            Some((Token::Brace, len)) => {
+                out.push('{');
+                rest = &rest[len..];
+            }
+            Some((Token::Placeholder(name), len)) if !after_sigil => {
+                match f(name) {
+                    Some(replacement) => out.push_str(&replacement),
+                    None => out.push_str(&rest[..len]),
+                }
+                rest = &rest[len..];
+            }
+            _ => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Replaces `{HOME}`, `{PROFILE}` (the owning profile's name), `{ARCH}` and
+/// `{ENV:NAME}` in `value`. `{{` is a literal brace; unknown placeholders are
+/// kept as written.
+///
+/// `{HOME}` stays as it is if HOME is unset; an unset `{ENV:NAME}` is empty.
+pub fn expand(value: &str, profile: &str, env: &HashMap<String, String>) -> String {
+    rewrite(value, |name| lookup(name, profile, env))
+}
+
+/// Placeholders in `value` that `expand` does not know, as written.
+pub fn unknown_placeholders(value: &str) -> Vec<String> {
+    let mut unknown = Vec::new();
+    rewrite(value, |name| {
+        let known = matches!(name, "HOME" | "PROFILE" | "ARCH") || name.starts_with("ENV:");
+        if !known {
+            unknown.push(format!("{{{name}}}"));
+        }
+        None
+    });
+    unknown
+}
+
+/// `entry` with the placeholders in its value expanded for `profile`.
+pub fn expand_entry(entry: &Entry, profile: &str, env: &HashMap<String, String>) -> Entry {
+    let mut entry = entry.clone();
+    if let Some(value) = entry.value_mut() {
+        *value = expand(value, profile, env);
+    }
+    entry
+}
+
+/// `profile` with every part value expanded, as it is exported. Profiles are
+/// expanded before they are layered so `{PROFILE}` names the owning profile.
+pub fn expand_profile(mut profile: EnvProfile, env: &HashMap<String, String>) -> EnvProfile {
+    for entry in &mut profile.entries {
+        *entry = expand_entry(entry, &profile.name, env);
+    }
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> HashMap<String, String> {
+        HashMap::from([
+            ("HOME".to_string(), "/home/me".to_string()),
+            ("SDK_ROOT".to_string(), "/opt/sdk".to_string()),
+        ])
+    }
+
+    #[test]
+    fn home_profile_and_arch_are_replaced() {
+        assert_eq!(
+            expand("{HOME}/toolchains/{PROFILE}/bin", "gcc-13", &env()),
+            "/home/me/toolchains/gcc-13/bin"
+        );
+        assert_eq!(
+            expand("/opt/{ARCH}/lib", "p", &env()),
+            format!("/opt/{}/lib", std::env::consts::ARCH)
+        );
+        assert_eq!(expand("{HOME}/bin", "p", &HashMap::new()), "{HOME}/bin");
+    }
+
+    #[test]
+    fn env_placeholders_read_the_environment() {
+        assert_eq!(
+            expand("{ENV:SDK_ROOT}/include", "p", &env()),
+            "/opt/sdk/include"
+        );
+        assert_eq!(expand("-I{ENV:MISSING}/x", "p", &env()), "-I/x");
+    }
+
+    #[test]
+    fn double_braces_are_literal_and_shell_syntax_is_kept() {
+        assert_eq!(expand("{{HOME}", "p", &env()), "{HOME}");
+        assert_eq!(expand("a{{b", "p", &env()), "a{b");
+        assert_eq!(
+            expand("${HOME}/{a,b} {lower}", "p", &env()),
+            "${HOME}/{a,b} {lower}"
+        );
+        assert!(unknown_placeholders("${HOME} @{CFLAGS} {{NOPE}").is_empty());
+    }
+
+    #[test]
+    fn unknown_placeholders_are_kept_and_reported() {
+        assert_eq!(expand("{NOPE}{HOME}", "p", &env()), "{NOPE}/home/me");
+        assert_eq!(
+            unknown_placeholders("{NOPE}/{HOME}/{ENV:X}/{OS}"),
+            ["{NOPE}", "{OS}"]
+        );
+    }
+}
@@ -2,7 +2,10 @@ use crate::tui::input;
 use crate::tui::state::AppState;
 use crate::tui::ui;
 use anyhow::Result;
-use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event};
+use crossterm::event::{
+    self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+    Event,
+};
 use crossterm::execute;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
@@ -11,23 +14,39 @@ use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 use std::io::stdout;
 
-pub fn run() -> Result<()> {
+pub fn run(expand_home_at_save: bool) -> Result<()> {
     enable_raw_mode()?;
     let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableBracketedPaste
+    )?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     let mut app = AppState::new()?;
+    app.expand_home_at_save = expand_home_at_save;
 
     loop {
         terminal.draw(|f| ui::draw_main_ui(f, &mut app))?;
 
         if event::poll(std::time::Duration::from_millis(200))? {
-            if let Event::Key(key) = event::read()? {
-                if input::handle_key_event(&mut terminal, &mut app, key.code)? {
-                    break;
-                };
+            match event::read()? {
+                Event::Key(key) => {
+                    if input::handle_key_event(&mut terminal, &mut app, key)? {
+                        break;
+                    };
+                }
+                Event::Paste(text) => input::handle_paste(&mut terminal, &mut app, &text)?,
+                Event::Mouse(mouse) => {
+                    if input::handle_mouse_event(&mut terminal, &mut app, mouse)? {
+                        break;
+                    }
+                }
+                _ => continue,
             }
+            app.note_expansion_problems();
         }
     }
 
@@ -35,7 +54,8 @@ pub fn run() -> Result<()> {
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableBracketedPaste
     )?;
     terminal.show_cursor()?;
     Ok(())
@@ -0,0 +1,137 @@
+use anyhow::Result;
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// Where copied text goes. The TUI copies through this so tests can see what
+/// would have reached the clipboard.
+pub trait Clipboard {
+    fn copy(&mut self, text: &str) -> Result<()>;
+}
+
+/// The clipboard of the machine the user sits at. Locally that is whichever
+/// of pbcopy, wl-copy, xclip or xsel works; over SSH, or when none does, the
+/// text is handed to the terminal with an OSC 52 escape sequence.
+pub struct SystemClipboard;
+
+impl Clipboard for SystemClipboard {
+    fn copy(&mut self, text: &str) -> Result<()> {
+        let remote = ["SSH_CONNECTION", "SSH_TTY"]
+            .iter()
+            .any(|v| std::env::var_os(v).is_some());
+        if !remote && local_tools().iter().any(|tool| pipe_to(tool, text)) {
+            return Ok(());
+        }
+        let mut stdout = std::io::stdout();
+        if !stdout.is_terminal() {
+            anyhow::bail!("no clipboard available");
+        }
+        stdout.write_all(osc52(text, std::env::var_os("TMUX").is_some()).as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Clipboard commands worth trying on this system, best first.
+fn local_tools() -> Vec<&'static [&'static str]> {
+    let mut tools: Vec<&'static [&'static str]> = Vec::new();
+    if cfg!(target_os = "macos") {
+        tools.push(&["pbcopy"]);
+    }
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push(&["wl-copy"]);
+    }
+    if std::env::var_os("DISPLAY").is_some() {
+        tools.push(&["xclip", "-selection", "clipboard"]);
+        tools.push(&["xsel", "--clipboard", "--input"]);
+    }
+    tools
+}
+
+/// Runs `tool` with `text` on its stdin; false if it is missing or fails.
+fn pipe_to(tool: &[&str], text: &str) -> bool {
+    let Ok(mut child) = Command::new(tool[0])
+        .args(&tool[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return false;
+    };
+    let written = child
+        .stdin
+        .take()
+        .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+    child.wait().is_ok_and(|status| status.success()) && written
+}
+
+/// The OSC 52 sequence setting the clipboard to `text`, wrapped for tmux to
+/// pass it on to the outer terminal.
+fn osc52(text: &str, tmux: bool) -> String {
+    let sequence = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    if tmux {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// A clipboard keeping what is copied to it, for tests.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct Captured(std::rc::Rc<std::cell::RefCell<Vec<String>>>);
+
+#[cfg(test)]
+impl Captured {
+    /// Everything copied so far, oldest first.
+    pub fn copied(&self) -> Vec<String> {
+        self.0.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+impl Clipboard for Captured {
+    fn copy(&mut self, text: &str) -> Result<()> {
+        self.0.borrow_mut().push(text.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads_the_last_group() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64("PATH=/ü".as_bytes()), "UEFUSD0vw7w=");
+    }
+
+    #[test]
+    fn osc52_is_wrapped_for_tmux() {
+        assert_eq!(osc52("hi", false), "\x1b]52;c;aGk=\x07");
+        assert_eq!(osc52("hi", true), "\x1bPtmux;\x1b\x1b]52;c;aGk=\x07\x1b\\");
+    }
+}
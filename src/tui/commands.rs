@@ -1,9 +1,15 @@
+use crate::config::{CatalogItem, CustomVarDef, Entry, EnvProfile, ItemKind, VarKind};
 use crate::db;
+use crate::defs::{self, is_posix_identifier};
+use crate::export::OperationMode;
+use crate::items;
+use crate::profile_editor::confirm_dialog;
 use crate::tui::daisyui_themes;
 use crate::tui::dialogs::{create_custom_var_dialog, create_or_edit_item_dialog};
-use crate::tui::state::AppState;
+use crate::tui::select;
+use crate::tui::state::{AppState, Holding, InputMode, PartOrder};
 use crate::tui::view::View;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use ratatui::backend::Backend;
 use ratatui::Terminal;
 
@@ -17,24 +23,167 @@ fn all_commands() -> Vec<String> {
         "items".to_string(),
         "preview".to_string(),
         "export".to_string(),
+        "copy-export".to_string(),
+        "history".to_string(),
         "use".to_string(),
+        "copy".to_string(),
+        "new-profile".to_string(),
+        "rename-profile".to_string(),
+        "delete-profile".to_string(),
+        "describe".to_string(),
+        "extends".to_string(),
+        "tag add".to_string(),
+        "tag remove".to_string(),
+        "recover".to_string(),
+        "repair".to_string(),
+        "fix-separators".to_string(),
+        "dedup-items".to_string(),
+        "scan".to_string(),
         "themes".to_string(),
         "theme".to_string(),
+        "mode".to_string(),
+        "set-mode".to_string(),
         "new-var".to_string(),
         "new-item".to_string(),
+        "unset".to_string(),
+        "sort".to_string(),
+        "dedup".to_string(),
+        "export-dedup".to_string(),
         "help".to_string(),
     ]
 }
 
+/// The arguments the command being typed takes, shown while typing them.
+fn argument_hint(input: &str) -> Option<&'static str> {
+    match input.split_whitespace().next()? {
+        "new-item" => Some("new-item <value> [#tag ...], or nothing for the dialog"),
+        "new-var" => Some("new-var <NAME> [list [SEPARATOR] | scalar], or nothing for the dialog"),
+        "sort" => Some("sort [--by value|program|version]"),
+        "copy-export" => Some("copy-export [VAR], the whole script without one"),
+        "new-profile" => Some("new-profile <name>"),
+        "rename-profile" => Some("rename-profile <new name>"),
+        "delete-profile" => Some("delete-profile [name] [--yes]"),
+        "scan" => Some("scan <dir> [--yes], adding its bin, include and lib dirs as items"),
+        _ => None,
+    }
+}
+
+/// The item `:new-item <value> [#tag ...]` creates. Words starting with `#`
+/// are tags wherever they are; the others make up the value. A value
+/// starting with `/` or `~` is a path item.
+pub fn parse_new_item(args: &str) -> Result<CatalogItem> {
+    let (tags, value): (Vec<&str>, Vec<&str>) =
+        args.split_whitespace().partition(|w| w.starts_with('#'));
+    let tags: Vec<&str> = tags.iter().map(|t| &t[1..]).collect();
+    if tags.contains(&"") {
+        bail!("empty tag: write #name");
+    }
+    let value = value.join(" ");
+    let kind = if value.starts_with(['/', '~']) {
+        ItemKind::Path
+    } else {
+        ItemKind::Text
+    };
+    items::build_item(&value, kind, None, None, &tags.join(","))
+}
+
+/// The definition `:new-var <NAME> [list [SEPARATOR] | scalar]` creates. Vars
+/// are lists separated by `:` unless told otherwise; a separator with spaces
+/// is quoted, as in `list " "`.
+pub fn parse_new_var(args: &str) -> Result<CustomVarDef> {
+    let (name, rest) = split_word(args);
+    let (kind, separator) = split_word(rest);
+    let separator = match separator {
+        "" => None,
+        s => Some(unquote_separator(s)?),
+    };
+    let (name, kind, separator) = match (name, kind, separator) {
+        ("", ..) => bail!("usage: new-var <NAME> [list [SEPARATOR] | scalar]"),
+        (name, "", None) => (name, VarKind::List, ":"),
+        (name, kind, None) => (name, defs::parse_kind(kind)?, ":"),
+        (name, "list", Some(separator)) => (name, VarKind::List, separator),
+        (_, "scalar", Some(_)) => bail!("scalar vars take no separator"),
+        (_, kind, Some(_)) => bail!("unknown var kind: {kind} (expected list or scalar)"),
+    };
+    defs::build_def(name, kind, separator)
+}
+
+/// The first word of `s` and the trimmed rest.
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    match s.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (s, ""),
+    }
+}
+
+/// A separator as typed, without the `"` or `'` around it.
+fn unquote_separator(s: &str) -> Result<&str> {
+    for quote in ['"', '\''] {
+        if let Some(inner) = s.strip_prefix(quote).and_then(|s| s.strip_suffix(quote)) {
+            return Ok(inner);
+        }
+    }
+    if s.contains(char::is_whitespace) {
+        bail!("quote a separator with spaces, e.g. \" \"");
+    }
+    Ok(s)
+}
+
+/// The selected var, for `:sort` and `:dedup`, or why its parts cannot be
+/// rearranged.
+fn parts_to_rearrange(app: &AppState) -> Result<String, String> {
+    let var = app
+        .selected_var_name
+        .clone()
+        .unwrap_or_else(|| "PATH".to_string());
+    if select::var_option_for(app, &var).kind != VarKind::List {
+        return Err(format!(
+            "{var} is a scalar var, it has no parts to rearrange"
+        ));
+    }
+    if matches!(app.holding, Some(Holding::Parts { .. })) {
+        return Err("drop or cancel the parts being moved first".to_string());
+    }
+    Ok(var)
+}
+
+/// Shows `message` and keeps the palette open on the command, to fix it.
+fn reject(app: &mut AppState, message: String) {
+    app.status = message;
+    app.input_mode = InputMode::Command;
+}
+
 pub fn refresh_command_suggestions(app: &mut AppState) {
-    let input = app.command_input.trim_start();
+    let input = app.command_input.as_str().trim_start();
+    app.command_hint = argument_hint(input);
     let mut suggestions = Vec::new();
 
-    if input.starts_with("use ") {
-        let q = input.trim_start_matches("use ").trim().to_lowercase();
+    if let Some((cmd @ ("use" | "extends" | "delete-profile"), q)) = input.split_once(' ') {
+        let q = q.trim().to_lowercase();
         for p in &app.profiles {
             if q.is_empty() || p.name.to_lowercase().contains(&q) {
-                suggestions.push(format!("use {}", p.name));
+                suggestions.push(format!("{cmd} {}", p.name));
+            }
+        }
+    } else if let Some(q) = input.strip_prefix("sort ") {
+        for by in ["value", "program", "version"] {
+            let s = format!("--by {by}");
+            if s.starts_with(q.trim()) {
+                suggestions.push(format!("sort {s}"));
+            }
+        }
+    } else if let Some((cmd @ ("mode" | "set-mode"), q)) = input.split_once(' ') {
+        for m in ["prepend", "append", "replace"] {
+            if m.starts_with(q.trim()) {
+                suggestions.push(format!("{cmd} {m}"));
+            }
+        }
+    } else if let Some(q) = input.strip_prefix("tag remove ") {
+        let q = q.trim().to_lowercase();
+        for tag in &app.profiles[app.active_profile_index].tags {
+            if tag.to_lowercase().contains(&q) {
+                suggestions.push(format!("tag remove {tag}"));
             }
         }
     } else if input.starts_with("theme ") {
@@ -67,7 +216,7 @@ pub fn refresh_command_suggestions(app: &mut AppState) {
 }
 
 pub fn pick_command_to_execute(app: &AppState) -> String {
-    let typed = app.command_input.trim().to_string();
+    let typed = app.command_input.as_str().trim().to_string();
     if typed.is_empty() {
         return app
             .command_suggestions
@@ -88,6 +237,15 @@ pub fn pick_command_to_execute(app: &AppState) -> String {
         .unwrap_or(typed)
 }
 
+/// Status after saving a new item, see `db::save_item`.
+pub fn item_saved_status(item: &CatalogItem, merged: bool) -> String {
+    if merged {
+        format!("merged into existing item: {}", item.value)
+    } else {
+        format!("saved item: {}", item.value)
+    }
+}
+
 pub fn execute_command<B: Backend>(
     terminal: &mut Terminal<B>,
     app: &mut AppState,
@@ -99,6 +257,8 @@ pub fn execute_command<B: Backend>(
     }
 
     if cmd == "quit" || cmd == "q" || cmd == "exit" {
+        // Parts being moved go back where they came from rather than vanish.
+        app.put_back_held_parts()?;
         return Ok(true);
     }
     if cmd == "profiles" {
@@ -129,6 +289,12 @@ pub fn execute_command<B: Backend>(
         app.active_view = View::Export;
         return Ok(false);
     }
+    if cmd == "history" {
+        app.refresh_history()?;
+        app.history_list_state.select(Some(0));
+        app.active_view = View::History;
+        return Ok(false);
+    }
     if cmd == "themes" {
         app.active_view = View::Help;
         app.status = format!(
@@ -142,25 +308,140 @@ pub fn execute_command<B: Backend>(
         return Ok(false);
     }
     if cmd == "new-var" {
-        if let Some(def) = create_custom_var_dialog(terminal)? {
+        if let Some(def) = create_custom_var_dialog(terminal, None)? {
             db::save_custom_var_def(&app.conn, &def)?;
             app.refresh_var_options()?;
             app.status = format!("saved var def: {}", def.name);
+            app.normalize_separators()?;
+        }
+        return Ok(false);
+    }
+    if let Some(args) = cmd.strip_prefix("new-var ") {
+        match parse_new_var(args) {
+            Ok(def) if app.var_options.iter().any(|o| o.name == def.name) => {
+                reject(app, format!("{} is already defined", def.name));
+            }
+            Ok(def) => {
+                db::save_custom_var_def(&app.conn, &def)?;
+                app.refresh_var_options()?;
+                app.status = format!("saved var def: {}", def.name);
+                app.normalize_separators()?;
+            }
+            Err(e) => reject(app, e.to_string()),
         }
         return Ok(false);
     }
     if cmd == "new-item" {
         if let Some(mut item) = create_or_edit_item_dialog(terminal, None)? {
-            db::save_item(&app.conn, &mut item)?;
+            let merged = db::save_item(&app.conn, &mut item, false)?;
             app.refresh_items()?;
-            app.status = format!("saved item: {}", item.value);
+            app.status = item_saved_status(&item, merged);
+        }
+        return Ok(false);
+    }
+    if let Some(args) = cmd.strip_prefix("new-item ") {
+        match parse_new_item(args) {
+            Ok(mut item) => {
+                let merged = db::save_item(&app.conn, &mut item, false)?;
+                app.refresh_items()?;
+                app.status = item_saved_status(&item, merged);
+            }
+            Err(e) => reject(app, e.to_string()),
         }
         return Ok(false);
     }
+    if let Some(rest) = cmd.strip_prefix("sort") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            let order = match rest.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => Ok(PartOrder::Value),
+                ["--by", by] => PartOrder::parse(by),
+                _ => Err(anyhow::anyhow!("usage: sort [--by value|program|version]")),
+            };
+            match (order, parts_to_rearrange(app)) {
+                (Err(e), _) => reject(app, e.to_string()),
+                (_, Err(message)) => app.status = message,
+                (Ok(order), Ok(var)) if order != PartOrder::Value && var != "PATH" => {
+                    app.status = format!("{var} parts have no program or version to sort by");
+                }
+                (Ok(order), Ok(var)) => {
+                    app.status = if app.sort_var_parts(&var, order)? {
+                        format!("sorted the parts of {var}")
+                    } else {
+                        format!("the parts of {var} are already sorted")
+                    };
+                }
+            }
+            return Ok(false);
+        }
+    }
+    if cmd == "dedup" {
+        match parts_to_rearrange(app) {
+            Ok(var) => {
+                let dropped = app.dedup_var_parts(&var)?;
+                app.status = match dropped {
+                    0 => format!("{var} has no duplicate parts"),
+                    1 => format!("dropped 1 duplicate part of {var}"),
+                    n => format!("dropped {n} duplicate parts of {var}"),
+                };
+            }
+            Err(message) => app.status = message,
+        }
+        return Ok(false);
+    }
+    if let Some(rest) = cmd.strip_prefix("copy-export") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            let var = rest.trim();
+            app.copy_export((!var.is_empty()).then_some(var));
+            return Ok(false);
+        }
+    }
+    if cmd == "dedup-items" {
+        let removed = db::dedup_items(&app.conn)?;
+        app.refresh_items()?;
+        app.status = format!("removed {removed} duplicate items");
+        return Ok(false);
+    }
     if cmd == "help" {
         app.active_view = View::Help;
         return Ok(false);
     }
+    if let Some(rest) = cmd.strip_prefix("scan") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            let (flags, words): (Vec<&str>, Vec<&str>) =
+                rest.split_whitespace().partition(|w| *w == "--yes");
+            if words.is_empty() {
+                reject(app, "usage: scan <dir> [--yes]".to_string());
+                return Ok(false);
+            }
+            let home = std::env::var("HOME").unwrap_or_default();
+            let dir = crate::export::expand_tilde(&words.join(" "), &home);
+            let found = match items::scan_dir(std::path::Path::new(&dir), items::SCAN_DEPTH) {
+                Ok(found) => found,
+                Err(e) => {
+                    reject(app, e.to_string());
+                    return Ok(false);
+                }
+            };
+            let (mut new, skipped) = items::new_scanned_items(found, &app.items);
+            if new.is_empty() {
+                app.status = format!(
+                    "nothing new under {dir}: {}",
+                    items::scan_summary(0, skipped)
+                );
+                return Ok(false);
+            }
+            let question = format!(
+                "Add {} item(s) from {dir}, skipping {skipped} duplicate(s)?",
+                new.len()
+            );
+            if !flags.is_empty() || confirm_dialog(terminal, &question)? {
+                items::save_scanned_items(&app.conn, &mut new)?;
+                app.refresh_items()?;
+                app.status = items::scan_summary(new.len(), skipped);
+            }
+            return Ok(false);
+        }
+    }
 
     if let Some(rest) = cmd.strip_prefix("use ") {
         let name = rest.trim();
@@ -170,14 +451,284 @@ pub fn execute_command<B: Backend>(
             .enumerate()
             .find(|(_, p)| p.name == name)
         {
+            // A move does not follow to another profile this way; it is undone.
+            let put_back = app.put_back_held_parts()?;
             app.active_profile_index = idx;
-            app.status = format!("profile: {name}");
+            app.status = match put_back {
+                0 => format!("profile: {name}"),
+                n => format!(
+                    "profile: {name} (put the moved {} back)",
+                    crate::tui::input::count_parts(n)
+                ),
+            };
         } else {
             app.status = format!("profile not found: {name}");
         }
         return Ok(false);
     }
 
+    if let Some(rest) = cmd.strip_prefix("new-profile") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            let name = rest.trim();
+            if name.is_empty() {
+                reject(app, "usage: new-profile <name>".to_string());
+            } else if app.profile_name_taken(name) {
+                reject(app, format!("profile already exists: {name}"));
+            } else {
+                app.add_profile(EnvProfile::new(name))?;
+                app.active_profile_index = app.profiles.len() - 1;
+                app.profile_list_state
+                    .select(Some(app.active_profile_index));
+                app.status = format!("added profile: {name}");
+            }
+            return Ok(false);
+        }
+    }
+    if let Some(rest) = cmd.strip_prefix("rename-profile") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            let new_name = rest.trim();
+            let current_name = app.profiles[app.active_profile_index].name.clone();
+            if new_name.is_empty() {
+                reject(app, "usage: rename-profile <new name>".to_string());
+            } else if new_name == current_name {
+                app.status = format!("profile is already named {new_name}");
+            } else if app.profile_name_taken(new_name) {
+                reject(app, format!("profile already exists: {new_name}"));
+            } else {
+                app.update_profile(app.active_profile_index, new_name.to_string())?;
+                app.status = format!("renamed profile: {current_name} -> {new_name}");
+            }
+            return Ok(false);
+        }
+    }
+    if let Some(rest) = cmd.strip_prefix("delete-profile") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            let (flags, names): (Vec<&str>, Vec<&str>) =
+                rest.split_whitespace().partition(|w| *w == "--yes");
+            let name = match names[..] {
+                [] => app.profiles[app.active_profile_index].name.clone(),
+                [name] => name.to_string(),
+                _ => {
+                    reject(app, "usage: delete-profile [name] [--yes]".to_string());
+                    return Ok(false);
+                }
+            };
+            match app.profile_index(&name) {
+                None => reject(app, format!("profile not found: {name}")),
+                Some(_) if app.profiles.len() <= 1 => {
+                    app.status = format!("cannot delete {name}, the last profile");
+                }
+                Some(i) => {
+                    let question = format!("Delete profile {name}?");
+                    if !flags.is_empty() || confirm_dialog(terminal, &question)? {
+                        // Another profile than the active one may go; stay on it.
+                        let active = app.profiles[app.active_profile_index].name.clone();
+                        app.delete_profile(i)?;
+                        if let Some(j) = app.profile_index(&active) {
+                            app.active_profile_index = j;
+                        }
+                        app.status =
+                            format!("deleted profile: {name} (:recover {name} brings it back)");
+                    }
+                }
+            }
+            return Ok(false);
+        }
+    }
+
+    if cmd == "copy" {
+        app.status = "Usage: copy <new profile name>".to_string();
+        return Ok(false);
+    }
+    if let Some(rest) = cmd.strip_prefix("copy ") {
+        let dst = rest.trim();
+        let src = app.profiles[app.active_profile_index].name.clone();
+        match db::copy_profile(&app.conn, &src, dst, false) {
+            Ok(copy) => {
+                app.profiles.push(copy);
+                app.active_profile_index = app.profiles.len() - 1;
+                app.profile_list_state
+                    .select(Some(app.active_profile_index));
+                app.status = format!("copied {src} -> {dst}");
+            }
+            Err(e) => app.status = format!("copy failed: {e}"),
+        }
+        return Ok(false);
+    }
+
+    if let Some(rest) = cmd.strip_prefix("describe") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            let text = rest.trim();
+            app.describe_profile((!text.is_empty()).then(|| text.to_string()))?;
+            let name = &app.profiles[app.active_profile_index].name;
+            app.status = if text.is_empty() {
+                format!("cleared description of {name}")
+            } else {
+                format!("described {name}")
+            };
+            return Ok(false);
+        }
+    }
+
+    if let Some(rest) = cmd.strip_prefix("extends") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            let parent = rest.trim();
+            let parent = (!parent.is_empty()).then(|| parent.to_string());
+            let name = app.profiles[app.active_profile_index].name.clone();
+            app.status = match app.set_profile_parent(parent.clone()) {
+                Ok(()) => match parent {
+                    Some(parent) => format!("{name} extends {parent}"),
+                    None => format!("{name} no longer extends another profile"),
+                },
+                Err(e) => format!("extends failed: {e}"),
+            };
+            return Ok(false);
+        }
+    }
+
+    if let Some(rest) = cmd.strip_prefix("tag") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            let name = app.profiles[app.active_profile_index].name.clone();
+            let mut words = rest.split_whitespace();
+            let (action, tag) = (words.next(), words.next());
+            let tag = tag.map(|t| t.trim_start_matches('#')).unwrap_or_default();
+            app.status = match (action, tag, words.next()) {
+                (Some(action @ ("add" | "remove")), tag, None) if !tag.is_empty() => {
+                    match app.tag_profile(tag, action == "add") {
+                        Ok(true) if action == "add" => format!("tagged {name} #{tag}"),
+                        Ok(true) => format!("removed #{tag} from {name}"),
+                        Ok(false) if action == "add" => format!("{name} is already tagged #{tag}"),
+                        Ok(false) => format!("{name} is not tagged #{tag}"),
+                        Err(e) => format!("tag failed: {e}"),
+                    }
+                }
+                _ => "usage: tag add|remove <tag>".to_string(),
+            };
+            return Ok(false);
+        }
+    }
+
+    if cmd == "fix-separators" {
+        app.status = "no separator mismatches".to_string();
+        app.normalize_separators()?;
+        return Ok(false);
+    }
+    if cmd == "repair" {
+        app.status = if app.broken_profiles.is_empty() {
+            "no broken profiles".to_string()
+        } else {
+            let names: Vec<&str> = app
+                .broken_profiles
+                .iter()
+                .map(|b| b.name.as_str())
+                .collect();
+            format!("broken: {} (usage: repair <name>)", names.join(", "))
+        };
+        return Ok(false);
+    }
+    if let Some(name) = cmd.strip_prefix("repair ") {
+        let name = name.trim();
+        match app.repair_profile(name) {
+            Ok((kept, dropped)) => {
+                app.status =
+                    format!("repaired profile: {name} (kept {kept} entries, dropped {dropped})")
+            }
+            Err(e) => app.status = format!("repair failed: {e}"),
+        }
+        return Ok(false);
+    }
+    if cmd == "recover" {
+        let deleted = db::deleted_profile_names(&app.conn)?;
+        app.status = if deleted.is_empty() {
+            "no deleted profiles".to_string()
+        } else {
+            format!(
+                "deleted: {} (usage: recover <name> [new name])",
+                deleted.join(", ")
+            )
+        };
+        return Ok(false);
+    }
+    if let Some(rest) = cmd.strip_prefix("recover ") {
+        let mut args = rest.split_whitespace();
+        let name = args.next().unwrap_or_default();
+        let new_name = args.next();
+        match app.recover_profile(name, new_name) {
+            Ok(()) => app.status = format!("recovered profile: {}", new_name.unwrap_or(name)),
+            Err(e) => app.status = format!("recover failed: {e}"),
+        }
+        return Ok(false);
+    }
+
+    if cmd == "mode" {
+        app.status = format!(
+            "mode: {} (usage: mode prepend|append|replace)",
+            app.mode_label()
+        );
+        return Ok(false);
+    }
+    if cmd == "export-dedup" {
+        app.export_dedup = !app.export_dedup;
+        app.status = if app.export_dedup {
+            "preview and export drop repeated parts".to_string()
+        } else {
+            "preview and export keep repeated parts".to_string()
+        };
+        return Ok(false);
+    }
+    if let Some(rest) = cmd.strip_prefix("mode ") {
+        match rest.trim().parse() {
+            Ok(mode) => {
+                app.export_mode = Some(mode);
+                app.status = format!("mode: {}", app.mode_label());
+            }
+            Err(e) => app.status = e.to_string(),
+        }
+        return Ok(false);
+    }
+
+    if let Some(rest) = cmd.strip_prefix("set-mode") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            let rest = rest.trim();
+            let mode = if rest.is_empty() {
+                None
+            } else {
+                match rest.parse::<OperationMode>() {
+                    Ok(mode) => Some(mode),
+                    Err(e) => {
+                        app.status = e.to_string();
+                        return Ok(false);
+                    }
+                }
+            };
+            app.set_profile_mode(mode)?;
+            let name = &app.profiles[app.active_profile_index].name;
+            app.status = match mode {
+                Some(mode) => format!("{name} exports in {} mode by default", mode.name()),
+                None => format!("{name} exports in prepend mode by default"),
+            };
+            return Ok(false);
+        }
+    }
+
+    if cmd == "unset" {
+        app.status = "Usage: unset <VAR>".to_string();
+        return Ok(false);
+    }
+    if let Some(rest) = cmd.strip_prefix("unset ") {
+        let name = rest.trim();
+        if !is_posix_identifier(name) {
+            app.status = format!("invalid variable name: {name}");
+            return Ok(false);
+        }
+        app.add_env_var(Entry::Unset {
+            name: name.to_string(),
+        })?;
+        let profile = &app.profiles[app.active_profile_index].name;
+        app.status = format!("{profile} unsets {name}");
+        return Ok(false);
+    }
+
     if let Some(rest) = cmd.strip_prefix("theme ") {
         let name = rest.trim();
         if name.is_empty() {
@@ -191,3 +742,200 @@ pub fn execute_command<B: Backend>(
 
     Ok(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BrokenProfile;
+    use ratatui::backend::TestBackend;
+    use rusqlite::Connection;
+
+    fn run(app: &mut AppState, cmd: &str) -> Result<()> {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24))?;
+        app.input_mode = InputMode::Normal;
+        execute_command(&mut terminal, app, cmd)?;
+        Ok(())
+    }
+
+    #[test]
+    fn scan_adds_the_new_dirs_as_items() -> Result<()> {
+        let root = std::env::temp_dir().join(format!("bath-tui-scan-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for dir in ["gcc/bin", "gcc/include"] {
+            std::fs::create_dir_all(root.join(dir))?;
+        }
+        let mut app = profiles_app(&["p"])?;
+        run(&mut app, "scan")?;
+        assert!(matches!(app.input_mode, InputMode::Command));
+        run(&mut app, "scan /no/such/bath/dir")?;
+        assert!(app.status.contains("/no/such/bath/dir"));
+
+        let cmd = format!("scan {} --yes", root.display());
+        run(&mut app, &cmd)?;
+        assert_eq!(app.status, "created 2 items, skipped 0 duplicates");
+        assert_eq!(app.items.len(), 2);
+        assert_eq!(app.items[1].tags, ["include", "gcc"]);
+        run(&mut app, &cmd)?;
+        assert_eq!(
+            app.status,
+            format!(
+                "nothing new under {}: created 0 items, skipped 2 duplicates",
+                root.display()
+            )
+        );
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    fn profiles_app(names: &[&str]) -> Result<AppState> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let profiles: Vec<EnvProfile> = names.iter().map(|n| EnvProfile::new(n)).collect();
+        for p in &profiles {
+            db::save_profile(&conn, p)?;
+        }
+        AppState::for_tests(conn, profiles)
+    }
+
+    fn names(app: &AppState) -> Vec<&str> {
+        app.profiles.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[test]
+    fn profile_commands_refuse_taken_names() -> Result<()> {
+        let mut app = profiles_app(&["gcc-13", "clang"])?;
+        run(&mut app, "new-profile clang")?;
+        assert_eq!(app.status, "profile already exists: clang");
+        assert!(matches!(app.input_mode, InputMode::Command));
+        run(&mut app, "rename-profile clang")?;
+        assert_eq!(app.status, "profile already exists: clang");
+        assert_eq!(names(&app), ["gcc-13", "clang"]);
+
+        // An unreadable profile keeps its name too.
+        app.broken_profiles.push(BrokenProfile {
+            name: "musl".to_string(),
+            error: "unknown entry".to_string(),
+        });
+        run(&mut app, "new-profile musl")?;
+        assert_eq!(app.status, "profile already exists: musl");
+        app.broken_profiles.clear();
+
+        run(&mut app, "new-profile musl")?;
+        assert_eq!(names(&app), ["gcc-13", "clang", "musl"]);
+        assert_eq!(app.active_profile_index, 2);
+        assert!(matches!(app.input_mode, InputMode::Normal));
+        run(&mut app, "rename-profile musl-cross")?;
+        assert_eq!(app.status, "renamed profile: musl -> musl-cross");
+        assert_eq!(
+            db::load_profile(&app.conn, "musl-cross")?.name,
+            "musl-cross"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn delete_profile_keeps_the_last_one_and_the_active_one() -> Result<()> {
+        let mut app = profiles_app(&["gcc-13", "clang"])?;
+        app.active_profile_index = 1;
+        run(&mut app, "delete-profile gcc --yes")?;
+        assert_eq!(app.status, "profile not found: gcc");
+        run(&mut app, "delete-profile --yes gcc-13")?;
+        assert_eq!(names(&app), ["clang"]);
+        assert_eq!(app.active_profile_index, 0);
+
+        run(&mut app, "delete-profile --yes")?;
+        assert_eq!(app.status, "cannot delete clang, the last profile");
+        assert_eq!(names(&app), ["clang"]);
+        Ok(())
+    }
+
+    #[test]
+    fn delete_profile_completes_names() -> Result<()> {
+        let mut app = profiles_app(&["gcc-13", "clang"])?;
+        app.command_input.set("delete-profile cl");
+        refresh_command_suggestions(&mut app);
+        assert_eq!(app.command_suggestions, ["delete-profile clang"]);
+        assert_eq!(app.command_hint, Some("delete-profile [name] [--yes]"));
+        Ok(())
+    }
+
+    #[test]
+    fn new_item_args_split_into_value_and_tags() -> Result<()> {
+        let item = parse_new_item("/opt/gcc-13/bin #toolchain #gcc")?;
+        assert_eq!(item.value, "/opt/gcc-13/bin");
+        assert_eq!(item.kind, ItemKind::Path);
+        assert_eq!(item.tags, ["toolchain", "gcc"]);
+
+        let item = parse_new_item("#opt -O2   -pipe")?;
+        assert_eq!(item.value, "-O2 -pipe");
+        assert_eq!(item.kind, ItemKind::Text);
+        assert_eq!(item.tags, ["opt"]);
+        assert_eq!(parse_new_item("~/bin")?.kind, ItemKind::Path);
+
+        assert!(parse_new_item("#only #tags").is_err());
+        assert!(parse_new_item("/opt/bin # gcc").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn new_var_args_give_name_kind_and_separator() -> Result<()> {
+        let def = parse_new_var("SYSROOT scalar")?;
+        assert_eq!((def.kind, def.separator.as_str()), (VarKind::Scalar, ""));
+        let def = parse_new_var("MY_PATH")?;
+        assert_eq!((def.kind, def.separator.as_str()), (VarKind::List, ":"));
+        let def = parse_new_var("MY_FLAGS list ,")?;
+        assert_eq!(
+            (def.name.as_str(), def.separator.as_str()),
+            ("MY_FLAGS", ",")
+        );
+        assert_eq!(parse_new_var("MY_FLAGS list \" \"")?.separator, " ");
+        assert_eq!(parse_new_var("MY_FLAGS list ', '")?.separator, ", ");
+
+        assert!(parse_new_var("SYSROOT scalar :").is_err());
+        assert!(parse_new_var("SYSROOT map").is_err());
+        assert!(parse_new_var("SYSROOT list : extra").is_err());
+        assert!(parse_new_var("my-var").is_err());
+        assert!(parse_new_var("CFLAGS list").is_err());
+        assert!(parse_new_var("").is_err());
+        assert!(parse_new_var("MY_FLAGS list \"\"").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn a_rejected_new_var_keeps_the_palette_open() -> Result<()> {
+        let mut app = profiles_app(&["p"])?;
+        run(&mut app, "new-var MY_FLAGS list , ;")?;
+        assert_eq!(app.status, "quote a separator with spaces, e.g. \" \"");
+        assert!(matches!(app.input_mode, InputMode::Command));
+        run(&mut app, "new-var CFLAGS")?;
+        assert_eq!(
+            app.status,
+            "CFLAGS is a builtin variable and cannot be redefined"
+        );
+        assert!(matches!(app.input_mode, InputMode::Command));
+        assert!(db::load_custom_var_defs(&app.conn)?.is_empty());
+
+        run(&mut app, "new-var MY_FLAGS list \" \"")?;
+        assert_eq!(app.status, "saved var def: MY_FLAGS");
+        assert!(matches!(app.input_mode, InputMode::Normal));
+        Ok(())
+    }
+
+    #[test]
+    fn export_dedup_toggles_repeated_parts_in_the_export() -> Result<()> {
+        let mut app = profiles_app(&["p"])?;
+        let part = Entry::CPath("/opt/inc".to_string());
+        app.profiles[0].entries = vec![part.clone(), part];
+        let repeated = "export CPATH=\"/opt/inc:/opt/inc${CPATH:+:}${CPATH}\";";
+        assert!(app.export_script().contains(repeated));
+
+        run(&mut app, "export-dedup")?;
+        assert!(app.export_dedup);
+        assert!(app
+            .export_script()
+            .contains("export CPATH=\"/opt/inc${CPATH:+:}${CPATH}\";"));
+        run(&mut app, "export-dedup")?;
+        assert!(app.export_script().contains(repeated));
+        Ok(())
+    }
+}
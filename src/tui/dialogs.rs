@@ -1,5 +1,7 @@
 use crate::config::{CatalogItem, CustomVarDef, ItemKind, VarKind};
-use crate::tui::util::centered_rect;
+use crate::tui::line_input::LineInput;
+use crate::tui::mouse::clicked_line;
+use crate::tui::util::{centered_rect, key_press, set_input_cursor};
 use anyhow::Result;
 use crossterm::event;
 use crossterm::event::{Event, KeyCode};
@@ -9,25 +11,39 @@ use ratatui::{
     Terminal,
 };
 
+/// Asks for a custom var definition, starting from `initial` when editing one.
 pub fn create_custom_var_dialog<B: Backend>(
     terminal: &mut Terminal<B>,
+    initial: Option<&CustomVarDef>,
 ) -> Result<Option<CustomVarDef>> {
     #[derive(Clone, Copy, PartialEq, Eq)]
     enum Field {
         Name,
         Kind,
         Separator,
+        Default,
     }
 
-    let mut name = String::new();
-    let mut kind = VarKind::List;
-    let mut separator = ":".to_string();
+    let mut name = LineInput::new(initial.map(|d| d.name.clone()).unwrap_or_default());
+    let mut kind = initial.map(|d| d.kind.clone()).unwrap_or(VarKind::List);
+    let mut separator = LineInput::new(match initial {
+        Some(d) if d.kind == VarKind::List => d.separator.clone(),
+        _ => ":".to_string(),
+    });
+    let mut default_value = LineInput::new(
+        initial
+            .and_then(|d| d.default_value.clone())
+            .unwrap_or_default(),
+    );
     let mut field = Field::Name;
+    let action = if initial.is_some() { "Edit" } else { "Create" };
 
     loop {
         terminal.draw(|f| {
             let area = centered_rect(70, 35, f.size());
-            let title = "Create custom env var (Tab: next, t: toggle kind, Enter: save, Esc: cancel)";
+            let title = format!(
+                "{action} custom env var (Tab: next, t: toggle kind, Enter: save, Esc: cancel)"
+            );
             let block = Block::default().borders(Borders::ALL).title(title);
 
             let kind_s = match kind {
@@ -38,6 +54,7 @@ pub fn create_custom_var_dialog<B: Backend>(
             let name_prefix = if field == Field::Name { "> " } else { "  " };
             let kind_prefix = if field == Field::Kind { "> " } else { "  " };
             let sep_prefix = if field == Field::Separator { "> " } else { "  " };
+            let default_prefix = if field == Field::Default { "> " } else { "  " };
 
             let sep_line = if kind == VarKind::List {
                 format!("{sep_prefix}Separator: {separator}")
@@ -46,18 +63,49 @@ pub fn create_custom_var_dialog<B: Backend>(
             };
 
             let text = format!(
-                "{name_prefix}Name: {name}\n{kind_prefix}Kind: {kind_s}\n{sep_line}\n\nNote: list vars are edited as parts; export joins parts using Separator."
+                "{name_prefix}Name: {name}\n{kind_prefix}Kind: {kind_s}\n{sep_line}\n{default_prefix}Default: {default_value}\n\nNote: list vars are edited as parts; export joins parts using Separator.\nProfiles without parts of the var export the Default (leave it empty for none)."
             );
             let p = Paragraph::new(text).block(block);
             f.render_widget(p, area);
+            match field {
+                Field::Name => set_input_cursor(f, area, 0, "> Name: ", &name),
+                Field::Kind => {}
+                Field::Separator => {
+                    if kind == VarKind::List {
+                        set_input_cursor(f, area, 2, "> Separator: ", &separator);
+                    }
+                }
+                Field::Default => set_input_cursor(f, area, 3, "> Default: ", &default_value),
+            }
         })?;
 
         if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
+            let event = event::read()?;
+            let area = centered_rect(70, 35, terminal.size()?);
+            if let Some(line) = clicked_line(&event, area) {
+                field = match line {
+                    0 => Field::Name,
+                    1 => Field::Kind,
+                    2 => Field::Separator,
+                    3 => Field::Default,
+                    _ => field,
+                };
+            }
+            let focused = match field {
+                Field::Name => Some(&mut name),
+                Field::Separator if kind == VarKind::List => Some(&mut separator),
+                Field::Kind | Field::Separator => None,
+                Field::Default => Some(&mut default_value),
+            };
+            if let Event::Paste(text) = &event {
+                if let Some(input) = focused {
+                    input.paste(text);
+                }
+            } else if let Some(key) = key_press(event) {
                 match key.code {
                     KeyCode::Esc => return Ok(None),
                     KeyCode::Enter => {
-                        let trimmed = name.trim();
+                        let trimmed = name.as_str().trim();
                         if trimmed.is_empty() {
                             return Ok(None);
                         }
@@ -65,10 +113,12 @@ pub fn create_custom_var_dialog<B: Backend>(
                             name: trimmed.to_string(),
                             kind: kind.clone(),
                             separator: if kind == VarKind::List {
-                                separator.clone()
+                                separator.to_string()
                             } else {
                                 String::new()
                             },
+                            default_value: (!default_value.is_empty())
+                                .then(|| default_value.to_string()),
                         };
                         return Ok(Some(def));
                     }
@@ -76,39 +126,86 @@ pub fn create_custom_var_dialog<B: Backend>(
                         field = match field {
                             Field::Name => Field::Kind,
                             Field::Kind => Field::Separator,
-                            Field::Separator => Field::Name,
+                            Field::Separator => Field::Default,
+                            Field::Default => Field::Name,
                         };
                     }
-                    KeyCode::Char('t') | KeyCode::Char('T') => {
+                    KeyCode::Char('t') | KeyCode::Char('T') if field != Field::Default => {
                         kind = match kind {
                             VarKind::Scalar => VarKind::List,
                             VarKind::List => VarKind::Scalar,
                         };
                         if kind == VarKind::List && separator.is_empty() {
-                            separator = ":".to_string();
+                            separator.set(":");
                         }
                     }
-                    KeyCode::Backspace => match field {
-                        Field::Name => {
-                            name.pop();
-                        }
-                        Field::Kind => {}
-                        Field::Separator => {
-                            if kind == VarKind::List {
-                                separator.pop();
-                            }
+                    _ => {
+                        if let Some(input) = focused {
+                            input.handle_key(key);
                         }
-                    },
-                    KeyCode::Char(c) => match field {
-                        Field::Name => name.push(c),
-                        Field::Kind => {}
-                        Field::Separator => {
-                            if kind == VarKind::List {
-                                separator.push(c);
-                            }
-                        }
-                    },
-                    _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Asks for a new name for the custom var `current`.
+pub fn rename_var_dialog<B: Backend>(
+    terminal: &mut Terminal<B>,
+    current: &str,
+) -> Result<Option<String>> {
+    let name = line_dialog(
+        terminal,
+        &format!("Rename {current}"),
+        "New name: ",
+        current,
+    )?;
+    Ok(name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()))
+}
+
+/// Asks for the separator of the builtin list var `var`, starting from
+/// `current`. An empty answer stands for the builtin separator.
+pub fn separator_dialog<B: Backend>(
+    terminal: &mut Terminal<B>,
+    var: &str,
+    current: &str,
+) -> Result<Option<String>> {
+    let title = format!("Separator of {var}, empty for the builtin one");
+    line_dialog(terminal, &title, "Separator: ", current)
+}
+
+/// One line of input labelled `label`, starting from `initial`; `None` if
+/// cancelled.
+fn line_dialog<B: Backend>(
+    terminal: &mut Terminal<B>,
+    title: &str,
+    label: &str,
+    initial: &str,
+) -> Result<Option<String>> {
+    let mut input = LineInput::new(initial);
+    loop {
+        terminal.draw(|f| {
+            let area = centered_rect(50, 20, f.size());
+            let block = Block::default()
+                .borders(Borders::ALL)
+                .title(format!("{title} (Enter: confirm, Esc: cancel)"));
+            f.render_widget(Clear, area);
+            f.render_widget(Paragraph::new(format!("{label}{input}")).block(block), area);
+            set_input_cursor(f, area, 0, label, &input);
+        })?;
+
+        if event::poll(std::time::Duration::from_millis(100))? {
+            let event = event::read()?;
+            if let Event::Paste(text) = &event {
+                input.paste(text);
+            } else if let Some(key) = key_press(event) {
+                match key.code {
+                    KeyCode::Esc => return Ok(None),
+                    KeyCode::Enter => return Ok(Some(input.to_string())),
+                    _ => {
+                        input.handle_key(key);
+                    }
                 }
             }
         }
@@ -129,10 +226,10 @@ pub fn create_or_edit_item_dialog<B: Backend>(
     }
 
     let mut kind = initial.map(|i| i.kind.clone()).unwrap_or(ItemKind::Text);
-    let mut value = initial.map(|i| i.value.clone()).unwrap_or_default();
-    let mut program = initial.and_then(|i| i.program.clone()).unwrap_or_default();
-    let mut version = initial.and_then(|i| i.version.clone()).unwrap_or_default();
-    let mut tags = initial.map(|i| i.tags.join(",")).unwrap_or_default();
+    let mut value = LineInput::new(initial.map(|i| i.value.clone()).unwrap_or_default());
+    let mut program = LineInput::new(initial.and_then(|i| i.program.clone()).unwrap_or_default());
+    let mut version = LineInput::new(initial.and_then(|i| i.version.clone()).unwrap_or_default());
+    let mut tags = LineInput::new(initial.map(|i| i.tags.join(",")).unwrap_or_default());
 
     let mut field = Field::Value;
     let id = initial.and_then(|i| i.id);
@@ -171,38 +268,75 @@ pub fn create_or_edit_item_dialog<B: Backend>(
             );
             let p = Paragraph::new(text).block(block);
             f.render_widget(p, area);
+            let focused = match field {
+                Field::Kind => None,
+                Field::Value => Some((1, "> Value: ", &value)),
+                Field::Program | Field::Version if kind == ItemKind::Text => None,
+                Field::Program => Some((2, "> Program: ", &program)),
+                Field::Version => Some((3, "> Version: ", &version)),
+                Field::Tags => Some((4, "> Tags: ", &tags)),
+            };
+            if let Some((row, prefix, input)) = focused {
+                set_input_cursor(f, area, row, prefix, input);
+            }
         })?;
 
         if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
+            let event = event::read()?;
+            let area = centered_rect(80, 45, terminal.size()?);
+            if let Some(line) = clicked_line(&event, area) {
+                field = match line {
+                    0 => Field::Kind,
+                    1 => Field::Value,
+                    2 | 3 if kind == ItemKind::Text => field,
+                    2 => Field::Program,
+                    3 => Field::Version,
+                    4 => Field::Tags,
+                    _ => field,
+                };
+            }
+            let focused = match field {
+                Field::Kind => None,
+                Field::Value => Some(&mut value),
+                Field::Program | Field::Version if kind == ItemKind::Text => None,
+                Field::Program => Some(&mut program),
+                Field::Version => Some(&mut version),
+                Field::Tags => Some(&mut tags),
+            };
+            if let Event::Paste(text) = &event {
+                if let Some(input) = focused {
+                    input.paste(text);
+                }
+            } else if let Some(key) = key_press(event) {
                 match key.code {
                     KeyCode::Esc => return Ok(None),
                     KeyCode::Enter => {
-                        let trimmed = value.trim();
+                        let trimmed = value.as_str().trim();
                         if trimmed.is_empty() {
                             return Ok(None);
                         }
-                        let tags_vec = tags
-                            .split(',')
-                            .map(|t| t.trim())
-                            .filter(|t| !t.is_empty())
-                            .map(|t| t.to_string())
-                            .collect::<Vec<_>>();
+                        let tags_vec = crate::items::parse_tags(tags.as_str());
                         let out = CatalogItem {
                             id,
                             kind: kind.clone(),
                             value: trimmed.to_string(),
-                            program: if kind == ItemKind::Path && !program.trim().is_empty() {
-                                Some(program.trim().to_string())
+                            program: if kind == ItemKind::Path
+                                && !program.as_str().trim().is_empty()
+                            {
+                                Some(program.as_str().trim().to_string())
                             } else {
                                 None
                             },
-                            version: if kind == ItemKind::Path && !version.trim().is_empty() {
-                                Some(version.trim().to_string())
+                            version: if kind == ItemKind::Path
+                                && !version.as_str().trim().is_empty()
+                            {
+                                Some(version.as_str().trim().to_string())
                             } else {
                                 None
                             },
                             tags: tags_vec,
+                            use_count: 0,
+                            last_used_at: None,
                         };
                         return Ok(Some(out));
                     }
@@ -233,41 +367,11 @@ pub fn create_or_edit_item_dialog<B: Backend>(
                             }
                         }
                     }
-                    KeyCode::Backspace => match field {
-                        Field::Kind => {}
-                        Field::Value => {
-                            value.pop();
-                        }
-                        Field::Program => {
-                            if kind == ItemKind::Path {
-                                program.pop();
-                            }
-                        }
-                        Field::Version => {
-                            if kind == ItemKind::Path {
-                                version.pop();
-                            }
+                    _ => {
+                        if let Some(input) = focused {
+                            input.handle_key(key);
                         }
-                        Field::Tags => {
-                            tags.pop();
-                        }
-                    },
-                    KeyCode::Char(c) => match field {
-                        Field::Kind => {}
-                        Field::Value => value.push(c),
-                        Field::Program => {
-                            if kind == ItemKind::Path {
-                                program.push(c);
-                            }
-                        }
-                        Field::Version => {
-                            if kind == ItemKind::Path {
-                                version.push(c);
-                            }
-                        }
-                        Field::Tags => tags.push(c),
-                    },
-                    _ => {}
+                    }
                 }
             }
         }
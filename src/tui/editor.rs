@@ -1,26 +1,47 @@
-use crate::config::{Entry, PathEntry, VarKind};
+use crate::config::{non_empty, CustomVarDef, Entry, PathEntry, VarKind};
+use crate::dialect::Posix;
 use crate::export::{self, OperationMode};
-use crate::tui::util::centered_rect;
+use crate::tui::fuzzy;
+use crate::tui::line_input::LineInput;
+use crate::tui::mouse::{line_at, ListArea};
+use crate::tui::theme::Theme;
+use crate::tui::util::{centered_rect, highlight_matches, key_press, set_input_cursor};
+use crate::validate::{self, Severity};
 use anyhow::Result;
 use crossterm::event;
-use crossterm::event::{Event, KeyCode};
+use crossterm::event::{Event, KeyCode, MouseButton, MouseEventKind};
 use ratatui::{
     backend::Backend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style},
+    text::Spans,
     widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
     Terminal,
 };
+use std::collections::HashMap;
+use std::rc::Rc;
 
-fn is_path_part(opt: &crate::tui::state::VarTypeOption) -> bool {
-    opt.editor == crate::tui::state::EditorStyle::PathPart
+fn is_path_part(opt: &crate::config::VarTypeOption) -> bool {
+    opt.editor == crate::config::EditorStyle::PathPart
+}
+
+/// Next pinned mode for a part: inherit -> prepend -> append -> replace -> inherit.
+pub fn cycle_pinned_mode(mode: Option<OperationMode>) -> Option<OperationMode> {
+    match mode {
+        None => Some(OperationMode::Prepend),
+        Some(OperationMode::Prepend) => Some(OperationMode::Append),
+        Some(OperationMode::Append) => Some(OperationMode::Replace),
+        Some(OperationMode::Replace) => None,
+    }
 }
 
 #[allow(dead_code)]
 pub fn edit_var_parts_dialog<B: Backend>(
     terminal: &mut Terminal<B>,
-    var: &crate::tui::state::VarTypeOption,
+    var: &crate::config::VarTypeOption,
     initial_parts: &[Entry],
+    mode: OperationMode,
+    theme: &Theme,
 ) -> Result<Option<Vec<Entry>>> {
     let mut parts: Vec<Entry> = initial_parts.to_vec();
     let mut selected: usize = 0;
@@ -45,7 +66,7 @@ pub fn edit_var_parts_dialog<B: Backend>(
             }
 
             let title = format!(
-                "{} parts (a:add, e:edit, d:delete, J/K:move, Enter:save, Esc:cancel)",
+                "{} parts (a:add, e:edit, d:delete, J/K:move, m:mode, Enter:save, Esc:cancel)",
                 var.name
             );
             let list = List::new(items)
@@ -62,7 +83,7 @@ pub fn edit_var_parts_dialog<B: Backend>(
         })?;
 
         if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
+            if let Some(key) = key_press(event::read()?) {
                 match key.code {
                     KeyCode::Esc => return Ok(None),
                     KeyCode::Enter => return Ok(Some(parts)),
@@ -94,9 +115,17 @@ pub fn edit_var_parts_dialog<B: Backend>(
                             }
                         }
                     }
+                    KeyCode::Char('m') => {
+                        if selected < parts.len() {
+                            let next = cycle_pinned_mode(parts[selected].pinned_mode());
+                            parts[selected].set_pinned_mode(next);
+                        }
+                    }
                     KeyCode::Char('a') => {
                         let one = vec![var.clone()];
-                        if let Some(new_entry) = edit_env_var_dialog(terminal, &one, None)? {
+                        if let Some(new_entry) =
+                            edit_env_var_dialog(terminal, &one, None, mode, theme)?
+                        {
                             parts.push(new_entry);
                             selected = parts.len().saturating_sub(1);
                         }
@@ -105,8 +134,12 @@ pub fn edit_var_parts_dialog<B: Backend>(
                         if selected < parts.len() {
                             let one = vec![var.clone()];
                             let current = parts.get(selected);
-                            if let Some(new_entry) = edit_env_var_dialog(terminal, &one, current)? {
-                                parts[selected] = new_entry;
+                            if let Some(mut new_entry) =
+                                edit_env_var_dialog(terminal, &one, current, mode, theme)?
+                            {
+                                new_entry.set_pinned_mode(parts[selected].pinned_mode());
+                                parts[selected] =
+                                    new_entry.with_enabled(parts[selected].is_enabled());
                             }
                         }
                     }
@@ -127,32 +160,41 @@ pub enum FocusArea {
 
 #[derive(Default)]
 pub struct EnvVarEditorState {
-    pub all_options: Vec<crate::tui::state::VarTypeOption>,
-    pub search: String,
-    pub filtered: Vec<crate::tui::state::VarTypeOption>,
+    pub all_options: Vec<crate::config::VarTypeOption>,
+    pub search: LineInput,
+    pub filtered: Vec<crate::config::VarTypeOption>,
     pub selected: usize,
-    pub input: String,
-    pub path: String,
-    pub version: String,
-    pub tool: String,
+    pub input: LineInput,
+    pub path: LineInput,
+    pub version: LineInput,
+    pub tool: LineInput,
+    /// Export priority of the part as typed, see `Entry::with_priority`.
+    pub priority: LineInput,
+    /// Note saved with the part, see `Entry::with_comment`.
+    pub comment: LineInput,
     pub active_input_field: usize,
     pub focus: FocusArea,
+    /// Shown above the preview when Enter was refused.
+    pub message: Option<String>,
     last_search: String,
 }
 
 impl EnvVarEditorState {
-    pub fn new(options: &[crate::tui::state::VarTypeOption], initial: Option<&Entry>) -> Self {
+    pub fn new(options: &[crate::config::VarTypeOption], initial: Option<&Entry>) -> Self {
         let mut s = Self {
             all_options: options.to_vec(),
-            search: String::new(),
+            search: LineInput::default(),
             filtered: options.to_vec(),
             selected: 0,
-            input: String::new(),
-            path: String::new(),
-            version: String::new(),
-            tool: String::new(),
+            input: LineInput::default(),
+            path: LineInput::default(),
+            version: LineInput::default(),
+            tool: LineInput::default(),
+            priority: LineInput::default(),
+            comment: LineInput::default(),
             active_input_field: 0,
             focus: FocusArea::Search,
+            message: None,
             last_search: String::new(),
         };
 
@@ -161,37 +203,22 @@ impl EnvVarEditorState {
             if let Some(pos) = s.all_options.iter().position(|o| o.name == initial_name) {
                 s.selected = pos;
             }
-            match e {
+            s.comment.set(e.comment().unwrap_or_default());
+            s.priority
+                .set(e.priority().map(|p| p.to_string()).unwrap_or_default());
+            match e.plain() {
                 Entry::Path(pe) => {
-                    s.path = pe.path.clone();
-                    s.version = pe.version.clone();
-                    s.tool = pe.program.clone();
-                    s.focus = FocusArea::Input;
-                }
-                Entry::CPath(v)
-                | Entry::CInclude(v)
-                | Entry::CPlusInclude(v)
-                | Entry::OBJCInclude(v)
-                | Entry::CPPFlag(v)
-                | Entry::CFlag(v)
-                | Entry::CXXFlag(v)
-                | Entry::LDFlag(v)
-                | Entry::LibraryPath(v)
-                | Entry::LDLibraryPath(v)
-                | Entry::LDRunPath(v)
-                | Entry::RanLib(v)
-                | Entry::CC(v)
-                | Entry::CXX(v)
-                | Entry::AR(v)
-                | Entry::Strip(v)
-                | Entry::GCCExecPrefix(v)
-                | Entry::CollectGCCOptions(v)
-                | Entry::Lang(v) => {
-                    s.input = v.clone();
+                    s.path.set(pe.path.clone());
+                    s.version.set(pe.version.clone().unwrap_or_default());
+                    s.tool.set(pe.program.clone().unwrap_or_default());
                     s.focus = FocusArea::Input;
                 }
-                Entry::CustomScalar { value, .. } | Entry::CustomPart { value, .. } => {
-                    s.input = value.clone();
+                // `plain` never returns a wrapper, and unsets and entries from
+                // a newer bath are not opened in the editor.
+                Entry::Annotated { .. } | Entry::Unset { .. } | Entry::Unknown(_) => {}
+                // Builtin and custom values.
+                plain => {
+                    s.input.set(export::entry_value(plain));
                     s.focus = FocusArea::Input;
                 }
             }
@@ -201,130 +228,253 @@ impl EnvVarEditorState {
     }
 
     pub fn update_filter(&mut self) {
-        if self.search == self.last_search {
+        if self.search.as_str() == self.last_search {
             return;
         }
-        self.last_search = self.search.clone();
-        self.filtered = self
-            .all_options
-            .iter()
-            .filter(|opt| {
-                opt.name
-                    .to_lowercase()
-                    .contains(&self.search.to_lowercase())
-            })
-            .cloned()
-            .collect();
+        self.last_search = self.search.to_string();
+        self.filtered =
+            fuzzy::filter_sorted(self.all_options.clone(), self.search.as_str(), |opt| {
+                opt.name.clone()
+            });
         if self.filtered.is_empty() {
             self.filtered = self.all_options.clone();
         }
         self.selected = 0;
     }
+
+    /// Puts pasted `text` into the focused input.
+    pub fn paste(&mut self, text: &str) {
+        match self.focus {
+            FocusArea::Search => {
+                self.search.paste(text);
+                self.update_filter();
+            }
+            FocusArea::Options => {}
+            FocusArea::Input => self.active_field_mut().paste(text),
+        }
+    }
+
+    /// The entry Enter would save, or the reason it cannot be saved: the first
+    /// error `validate_entry` finds, e.g. an empty list part, since an empty
+    /// segment means something else entirely (the current directory, in PATH).
+    pub fn entry_to_save(&self) -> std::result::Result<Entry, String> {
+        let opt = self
+            .filtered
+            .get(self.selected)
+            .ok_or_else(|| "no variable selected".to_string())?;
+        let entry = entry_from_state(opt, self);
+        let defs = [CustomVarDef::new(
+            &opt.name,
+            opt.kind.clone(),
+            &opt.separator,
+        )];
+        let issues = validate::validate_entry(&entry, "", &defs, &HashMap::new());
+        if let Some(error) = issues.iter().find(|i| i.severity == Severity::Error) {
+            return Err(error.message.clone());
+        }
+        let priority = match self.priority.as_str().trim() {
+            "" => None,
+            p => Some(
+                p.parse::<i64>()
+                    .map_err(|_| format!("priority must be a whole number, not {p:?}"))?,
+            ),
+        };
+        Ok(entry
+            .with_comment(Some(self.comment.to_string()))
+            .with_priority(priority))
+    }
+
+    /// Whether the selected type is edited with the PATH fields.
+    fn is_path(&self) -> bool {
+        self.filtered
+            .get(self.selected)
+            .map(is_path_part)
+            .unwrap_or(false)
+    }
+
+    /// The input fields: path, version and tool for PATH, else the value, then
+    /// the priority and the comment.
+    fn field_count(&self) -> usize {
+        if self.is_path() {
+            5
+        } else {
+            3
+        }
+    }
+
+    /// The input field typing goes to; the last one is always the comment.
+    fn active_field_mut(&mut self) -> &mut LineInput {
+        match (self.is_path(), self.active_input_field) {
+            (true, 0) => &mut self.path,
+            (true, 1) => &mut self.version,
+            (true, 2) => &mut self.tool,
+            (true, 3) | (false, 1) => &mut self.priority,
+            (false, 0) => &mut self.input,
+            _ => &mut self.comment,
+        }
+    }
 }
 
-fn entry_from_state(opt: &crate::tui::state::VarTypeOption, state: &EnvVarEditorState) -> Entry {
+fn entry_from_state(opt: &crate::config::VarTypeOption, state: &EnvVarEditorState) -> Entry {
     if is_path_part(opt) {
         return Entry::Path(PathEntry {
-            path: state.path.clone(),
-            version: state.version.clone(),
-            program: state.tool.clone(),
+            path: state.path.to_string(),
+            version: non_empty(state.version.as_str()),
+            program: non_empty(state.tool.as_str()),
+            mode: None,
         });
     }
 
-    // Builtins
-    match opt.name.as_str() {
-        "CPATH" => return Entry::CPath(state.input.clone()),
-        "C_INCLUDE_PATH" => return Entry::CInclude(state.input.clone()),
-        "CPLUS_INCLUDE_PATH" => return Entry::CPlusInclude(state.input.clone()),
-        "OBJC_INCLUDE_PATH" => return Entry::OBJCInclude(state.input.clone()),
-        "CPPFLAGS" => return Entry::CPPFlag(state.input.clone()),
-        "CFLAGS" => return Entry::CFlag(state.input.clone()),
-        "CXXFLAGS" => return Entry::CXXFlag(state.input.clone()),
-        "LDFLAGS" => return Entry::LDFlag(state.input.clone()),
-        "LIBRARY_PATH" => return Entry::LibraryPath(state.input.clone()),
-        "LD_LIBRARY_PATH" => return Entry::LDLibraryPath(state.input.clone()),
-        "LD_RUN_PATH" => return Entry::LDRunPath(state.input.clone()),
-        "RANLIB" => return Entry::RanLib(state.input.clone()),
-        "CC" => return Entry::CC(state.input.clone()),
-        "CXX" => return Entry::CXX(state.input.clone()),
-        "AR" => return Entry::AR(state.input.clone()),
-        "STRIP" => return Entry::Strip(state.input.clone()),
-        "GCC_EXEC_PREFIX" => return Entry::GCCExecPrefix(state.input.clone()),
-        "COLLECT_GCC_OPTIONS" => return Entry::CollectGCCOptions(state.input.clone()),
-        "LANG" => return Entry::Lang(state.input.clone()),
-        _ => {}
+    if let Some(entry) = Entry::for_builtin(&opt.name, state.input.as_str()) {
+        return entry;
     }
 
     // Custom vars
     match opt.kind {
         VarKind::Scalar => Entry::CustomScalar {
             name: opt.name.clone(),
-            value: state.input.clone(),
+            value: state.input.to_string(),
         },
         VarKind::List => Entry::CustomPart {
             name: opt.name.clone(),
-            value: state.input.clone(),
+            value: state.input.to_string(),
             separator: opt.separator.clone(),
+            mode: None,
         },
     }
 }
 
+/// Where the editor draws its panes inside the terminal's `size`: the search
+/// box and the options on the left, the input fields and the preview on the
+/// right.
+struct EditorAreas {
+    search: Rect,
+    options: Rect,
+    fields: Rect,
+    preview: Rect,
+}
+
+impl EditorAreas {
+    fn new(size: Rect) -> Self {
+        // Split into left (40%) for fuzzy search and right (60%) for input and preview.
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+            .split(centered_rect(80, 60, size));
+        let left = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .split(chunks[0]);
+        let right = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(9), Constraint::Min(0)].as_ref())
+            .split(chunks[1]);
+        Self {
+            search: left[0],
+            options: left[1],
+            fields: right[0],
+            preview: right[1],
+        }
+    }
+
+    /// The value, priority and comment boxes of a type without PATH fields.
+    fn single_fields(&self) -> Rc<[Rect]> {
+        Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3); 3].as_ref())
+            .split(self.fields)
+    }
+}
+
+impl EnvVarEditorState {
+    /// The options list drawn into `area`. It scrolls afresh every frame, just
+    /// far enough to show the selected option.
+    fn options_list(&self, area: Rect) -> ListArea {
+        let mut list = ListArea::default();
+        list.update(area, Some(self.selected), self.filtered.len());
+        list
+    }
+
+    /// Focuses what a click at `column`, `row` landed on: the search box, an
+    /// option (selecting it) or an input field.
+    fn click(&mut self, areas: &EditorAreas, column: u16, row: u16) {
+        if line_at(areas.search, column, row).is_some() {
+            self.focus = FocusArea::Search;
+        } else if let Some(line) = line_at(areas.options, column, row) {
+            self.focus = FocusArea::Options;
+            let index = self.options_list(areas.options).offset + line as usize;
+            if index < self.filtered.len() {
+                self.selected = index;
+            }
+        } else if self.is_path() {
+            if let Some(line) = line_at(areas.fields, column, row) {
+                self.focus = FocusArea::Input;
+                self.active_input_field = (line as usize).min(self.field_count() - 1);
+            }
+        } else if let Some(i) = areas
+            .single_fields()
+            .iter()
+            .position(|&field| line_at(field, column, row).is_some())
+        {
+            self.focus = FocusArea::Input;
+            self.active_input_field = i;
+        }
+    }
+}
+
 /// Launches the edit/create env var widget.
 /// Displays fuzzy search on the left and input fields on the right,
-/// with an integrated preview (using default Prepend mode) of the export command for the current variable.
+/// with an integrated preview (using `mode`) of the export command for the current variable.
+/// Search matches are styled by `theme`.
 pub fn edit_env_var_dialog<B: Backend>(
     terminal: &mut Terminal<B>,
-    options: &[crate::tui::state::VarTypeOption],
+    options: &[crate::config::VarTypeOption],
     initial: Option<&Entry>,
+    mode: OperationMode,
+    theme: &Theme,
 ) -> Result<Option<Entry>> {
     let mut state = EnvVarEditorState::new(options, initial);
 
     loop {
         terminal.draw(|f| {
-            let size = f.size();
-            // Split into left (40%) for fuzzy search and right (60%) for input and preview.
-            let chunks = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
-                .split(centered_rect(80, 60, size));
-
-            // Left pane: fuzzy search and options.
-            let left_chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
-                .split(chunks[0]);
+            let areas = EditorAreas::new(f.size());
 
             let search_style = if state.focus == FocusArea::Search {
                 Style::default().fg(Color::Yellow)
             } else {
                 Style::default()
             };
-            let search_para = Paragraph::new(state.search.as_ref()).block(
+            let search_para = Paragraph::new(state.search.as_str()).block(
                 Block::default()
                     .borders(Borders::ALL)
                     .style(search_style)
                     .title("Search Type"),
             );
-            f.render_widget(search_para, left_chunks[0]);
+            f.render_widget(search_para, areas.search);
+            if state.focus == FocusArea::Search {
+                set_input_cursor(f, areas.search, 0, "", &state.search);
+            }
 
             state.update_filter();
             let items: Vec<ListItem> = state
                 .filtered
                 .iter()
-                .map(|opt| ListItem::new(opt.name.clone()))
+                .map(|opt| {
+                    ListItem::new(Spans::from(highlight_matches(
+                        state.search.as_str(),
+                        &opt.name,
+                        theme.filter_match(),
+                    )))
+                })
                 .collect();
+            // Scrolled as `click` expects, see `options_list`.
+            let offset = state.options_list(areas.options).offset;
             let mut list_state = ListState::default();
-            list_state.select(Some(state.selected));
-            let list = List::new(items)
+            list_state.select(state.selected.checked_sub(offset));
+            let list = List::new(items.into_iter().skip(offset).collect::<Vec<_>>())
                 .block(Block::default().borders(Borders::ALL).title("Options"))
                 .highlight_style(Style::default().bg(Color::Blue));
-            f.render_stateful_widget(list, left_chunks[1], &mut list_state);
-
-            // Right pane: input fields and preview.
-            let right_chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Length(7), Constraint::Min(0)].as_ref())
-                .split(chunks[1]);
+            f.render_stateful_widget(list, areas.options, &mut list_state);
 
             let input_style = if state.focus == FocusArea::Input {
                 Style::default().fg(Color::Yellow)
@@ -339,11 +489,19 @@ pub fn edit_env_var_dialog<B: Backend>(
                 .unwrap_or(false)
             {
                 // Multi-field input for types like PATH.
-                let field_titles = ["Path", "Version", "Tool Name"];
+                let field_titles = [
+                    "Path",
+                    "Version (optional)",
+                    "Tool Name (optional)",
+                    "Priority (optional)",
+                    "Comment (optional)",
+                ];
                 let values = [
-                    state.path.clone(),
-                    state.version.clone(),
-                    state.tool.clone(),
+                    &state.path,
+                    &state.version,
+                    &state.tool,
+                    &state.priority,
+                    &state.comment,
                 ];
                 let field_items: Vec<ListItem> = field_titles
                     .iter()
@@ -364,7 +522,12 @@ pub fn edit_env_var_dialog<B: Backend>(
                         .style(input_style)
                         .title("Multi-field Input"),
                 );
-                f.render_widget(fields_list, right_chunks[0]);
+                f.render_widget(fields_list, areas.fields);
+                if state.focus == FocusArea::Input {
+                    let i = state.active_input_field.min(field_titles.len() - 1);
+                    let prefix = format!("> {}: ", field_titles[i]);
+                    set_input_cursor(f, areas.fields, i as u16, &prefix, values[i]);
+                }
             } else {
                 // Single-field input.
                 let current_type = state
@@ -373,13 +536,40 @@ pub fn edit_env_var_dialog<B: Backend>(
                     .map(|opt| opt.name.clone())
                     .unwrap_or_else(|| "...".to_string());
                 let title = format!("Enter value for {}", current_type);
-                let para = Paragraph::new(state.input.as_ref()).block(
+                let fields = areas.single_fields();
+                let field_style = |i: usize| {
+                    if state.active_input_field.min(2) == i {
+                        input_style
+                    } else {
+                        Style::default()
+                    }
+                };
+                let para = Paragraph::new(state.input.as_str()).block(
                     Block::default()
                         .borders(Borders::ALL)
-                        .style(input_style)
+                        .style(field_style(0))
                         .title(title),
                 );
-                f.render_widget(para, right_chunks[0]);
+                f.render_widget(para, fields[0]);
+                let priority = Paragraph::new(state.priority.as_str()).block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .style(field_style(1))
+                        .title("Priority (optional, lower goes first)"),
+                );
+                f.render_widget(priority, fields[1]);
+                let comment = Paragraph::new(state.comment.as_str()).block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .style(field_style(2))
+                        .title("Comment (optional)"),
+                );
+                f.render_widget(comment, fields[2]);
+                if state.focus == FocusArea::Input {
+                    let i = state.active_input_field.min(2);
+                    let input = [&state.input, &state.priority, &state.comment][i];
+                    set_input_cursor(f, fields[i], 0, "", input);
+                }
             }
 
             // Bottom right: preview of export command for the current variable.
@@ -390,43 +580,57 @@ pub fn edit_env_var_dialog<B: Backend>(
                 .unwrap_or(false)
             {
                 let pe = PathEntry {
-                    path: state.path.clone(),
-                    version: state.version.clone(),
-                    program: state.tool.clone(),
+                    path: state.path.to_string(),
+                    version: non_empty(state.version.as_str()),
+                    program: non_empty(state.tool.as_str()),
+                    mode: None,
                 };
                 let entry = Entry::Path(pe);
-                export::generate_export_line(&entry, OperationMode::Prepend)
+                export::generate_export_line(&Posix::default(), &entry, mode)
             } else {
                 let opt = state
                     .filtered
                     .get(state.selected)
                     .cloned()
-                    .unwrap_or_else(|| crate::tui::state::VarTypeOption {
+                    .unwrap_or_else(|| crate::config::VarTypeOption {
                         name: "CFLAGS".to_string(),
                         kind: VarKind::List,
                         separator: " ".to_string(),
-                        editor: crate::tui::state::EditorStyle::Single,
+                        editor: crate::config::EditorStyle::Single,
                     });
                 let entry = entry_from_state(&opt, &state);
-                export::generate_export_line(&entry, OperationMode::Prepend)
+                export::generate_export_line(&Posix::default(), &entry, mode)
             };
-            let preview_para = Paragraph::new(preview).block(
-                Block::default()
+            let preview_block = match &state.message {
+                Some(message) => Block::default()
+                    .borders(Borders::ALL)
+                    .title(message.as_str())
+                    .border_style(Style::default().fg(Color::Red)),
+                None => Block::default()
                     .borders(Borders::ALL)
                     .title("Export Preview for this Variable"),
-            );
-            f.render_widget(preview_para, right_chunks[1]);
+            };
+            let preview_para = Paragraph::new(preview).block(preview_block);
+            f.render_widget(preview_para, areas.preview);
         })?;
 
         if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
+            let event = event::read()?;
+            if let Event::Mouse(mouse) = event {
+                if mouse.kind == MouseEventKind::Down(MouseButton::Left) {
+                    let areas = EditorAreas::new(terminal.size()?);
+                    state.click(&areas, mouse.column, mouse.row);
+                }
+            } else if let Event::Paste(text) = &event {
+                state.paste(text);
+            } else if let Some(key) = key_press(event) {
+                state.message = None;
                 match key.code {
                     KeyCode::Esc => return Ok(None),
-                    KeyCode::Enter => {
-                        let selected_opt = &state.filtered[state.selected];
-                        let entry = entry_from_state(selected_opt, &state);
-                        return Ok(Some(entry));
-                    }
+                    KeyCode::Enter => match state.entry_to_save() {
+                        Ok(entry) => return Ok(Some(entry)),
+                        Err(message) => state.message = Some(message),
+                    },
                     KeyCode::Tab => {
                         // Cycle focus among Search -> Options -> Input.
                         state.focus = match state.focus {
@@ -435,60 +639,6 @@ pub fn edit_env_var_dialog<B: Backend>(
                             FocusArea::Input => FocusArea::Search,
                         };
                     }
-                    KeyCode::Backspace => match state.focus {
-                        FocusArea::Search => {
-                            state.search.pop();
-                            state.update_filter();
-                        }
-                        FocusArea::Options => {}
-                        FocusArea::Input => {
-                            if state
-                                .filtered
-                                .get(state.selected)
-                                .map(is_path_part)
-                                .unwrap_or(false)
-                            {
-                                match state.active_input_field {
-                                    0 => {
-                                        state.path.pop();
-                                    }
-                                    1 => {
-                                        state.version.pop();
-                                    }
-                                    2 => {
-                                        state.tool.pop();
-                                    }
-                                    _ => {}
-                                }
-                            } else {
-                                state.input.pop();
-                            }
-                        }
-                    },
-                    KeyCode::Char(c) => match state.focus {
-                        FocusArea::Search => {
-                            state.search.push(c);
-                            state.update_filter();
-                        }
-                        FocusArea::Options => {}
-                        FocusArea::Input => {
-                            if state
-                                .filtered
-                                .get(state.selected)
-                                .map(is_path_part)
-                                .unwrap_or(false)
-                            {
-                                match state.active_input_field {
-                                    0 => state.path.push(c),
-                                    1 => state.version.push(c),
-                                    2 => state.tool.push(c),
-                                    _ => {}
-                                }
-                            } else {
-                                state.input.push(c);
-                            }
-                        }
-                    },
                     KeyCode::Up => match state.focus {
                         FocusArea::Options => {
                             if state.selected > 0 {
@@ -496,14 +646,9 @@ pub fn edit_env_var_dialog<B: Backend>(
                             }
                         }
                         FocusArea::Input => {
-                            if state
-                                .filtered
-                                .get(state.selected)
-                                .map(is_path_part)
-                                .unwrap_or(false)
-                                && state.active_input_field > 0
-                            {
-                                state.active_input_field -= 1;
+                            if state.active_input_field > 0 {
+                                state.active_input_field =
+                                    state.active_input_field.min(state.field_count() - 1) - 1;
                             }
                         }
                         _ => {}
@@ -515,19 +660,23 @@ pub fn edit_env_var_dialog<B: Backend>(
                             }
                         }
                         FocusArea::Input => {
-                            if state
-                                .filtered
-                                .get(state.selected)
-                                .map(is_path_part)
-                                .unwrap_or(false)
-                                && state.active_input_field < 2
-                            {
+                            if state.active_input_field + 1 < state.field_count() {
                                 state.active_input_field += 1;
                             }
                         }
                         _ => {}
                     },
-                    _ => {}
+                    _ => match state.focus {
+                        FocusArea::Search => {
+                            if state.search.handle_key(key) {
+                                state.update_filter();
+                            }
+                        }
+                        FocusArea::Options => {}
+                        FocusArea::Input => {
+                            state.active_field_mut().handle_key(key);
+                        }
+                    },
                 }
             }
         }
@@ -540,11 +689,11 @@ mod tests {
 
     #[test]
     fn update_filter_does_not_reset_selected_when_search_is_unchanged() {
-        let options = crate::tui::state::builtin_var_options();
+        let options = crate::config::builtin_var_options(&crate::config::SeparatorOverrides::new());
         let mut s = EnvVarEditorState::new(&options, None);
 
         // Force one filter refresh.
-        s.search.push('c');
+        s.search.insert('c');
         s.update_filter();
         assert!(
             s.filtered.len() > 2,
@@ -558,4 +707,107 @@ mod tests {
         s.update_filter();
         assert_eq!(s.selected, 2);
     }
+
+    #[test]
+    fn empty_list_parts_are_refused_but_empty_scalars_are_not() {
+        let options = crate::config::builtin_var_options(&crate::config::SeparatorOverrides::new());
+        let pick = |name: &str, input: &str| {
+            let mut s = EnvVarEditorState::new(&options, None);
+            s.selected = options.iter().position(|o| o.name == name).unwrap();
+            s.input.set(input);
+            s.path.set(input);
+            s.entry_to_save()
+        };
+        assert_eq!(
+            pick("PATH", "  ").err().as_deref(),
+            Some("PATH parts cannot be empty")
+        );
+        assert_eq!(
+            pick("CFLAGS", "").err().as_deref(),
+            Some("CFLAGS parts cannot be empty")
+        );
+        assert!(pick("PATH", "/opt/bin").is_ok());
+        assert!(pick("CC", "").is_ok());
+    }
+
+    #[test]
+    fn the_last_input_field_edits_the_comment() {
+        let options = crate::config::builtin_var_options(&crate::config::SeparatorOverrides::new());
+        let initial = Entry::LDFlag("-s".to_string()).with_comment(Some("small".to_string()));
+        let mut s = EnvVarEditorState::new(&options, Some(&initial));
+        assert_eq!(s.comment.as_str(), "small");
+
+        s.active_input_field = s.field_count() - 1;
+        s.active_field_mut().insert('e');
+        s.active_field_mut().insert('r');
+        let saved = s.entry_to_save().unwrap();
+        assert_eq!(saved.comment(), Some("smaller"));
+        assert_eq!(export::entry_value(&saved), "-s");
+
+        s.comment.clear();
+        assert_eq!(s.entry_to_save().unwrap().comment(), None);
+    }
+
+    #[test]
+    fn priorities_must_be_whole_numbers() {
+        let options = crate::config::builtin_var_options(&crate::config::SeparatorOverrides::new());
+        let initial = Entry::CFlag("-O2".to_string()).with_priority(Some(10));
+        let mut s = EnvVarEditorState::new(&options, Some(&initial));
+        assert_eq!(s.priority.as_str(), "10");
+
+        s.active_input_field = 1;
+        s.active_field_mut().move_home();
+        s.active_field_mut().insert('-');
+        assert_eq!(s.entry_to_save().unwrap().priority(), Some(-10));
+        s.priority.set("high");
+        assert!(s.entry_to_save().is_err());
+        s.priority.clear();
+        assert_eq!(s.entry_to_save().unwrap().priority(), None);
+    }
+
+    #[test]
+    fn pastes_go_to_the_focused_field() {
+        let options = crate::config::builtin_var_options(&crate::config::SeparatorOverrides::new());
+        let mut s = EnvVarEditorState::new(&options, None);
+        s.paste("ldfl");
+        assert_eq!(s.filtered[0].name, "LDFLAGS");
+
+        s.focus = FocusArea::Options;
+        s.paste("ignored");
+        assert_eq!(s.search.as_str(), "ldfl");
+
+        s.focus = FocusArea::Input;
+        s.paste("-Wl,--as-needed\n");
+        s.active_input_field = 2;
+        s.paste("from the\nwiki");
+        let saved = s.entry_to_save().unwrap();
+        assert_eq!(saved.to_string(), "LDFLAGS: -Wl,--as-needed");
+        assert_eq!(saved.comment(), Some("from the wiki"));
+    }
+
+    #[test]
+    fn clicks_focus_the_pane_under_them() {
+        let options = crate::config::builtin_var_options(&crate::config::SeparatorOverrides::new());
+        let mut s = EnvVarEditorState::new(&options, None);
+        let areas = EditorAreas::new(Rect::new(0, 0, 100, 50));
+        let options_area = areas.options;
+        s.click(&areas, options_area.x + 2, options_area.y + 3);
+        assert_eq!(s.focus, FocusArea::Options);
+        assert_eq!(s.selected, 2);
+
+        // PATH has a line per field.
+        s.selected = 0;
+        s.click(&areas, areas.fields.x + 2, areas.fields.y + 3);
+        assert_eq!((s.focus, s.active_input_field), (FocusArea::Input, 2));
+
+        s.selected = 2;
+        let comment = areas.single_fields()[2];
+        s.click(&areas, comment.x + 2, comment.y + 1);
+        assert_eq!(s.active_input_field, 2);
+        s.click(&areas, areas.search.x + 2, areas.search.y + 1);
+        assert_eq!(s.focus, FocusArea::Search);
+        // Borders are not inside anything.
+        s.click(&areas, areas.fields.x, areas.fields.y + 1);
+        assert_eq!(s.focus, FocusArea::Search);
+    }
 }
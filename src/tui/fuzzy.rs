@@ -0,0 +1,208 @@
+use std::cmp::Reverse;
+
+/// Bonus for a character right after the previous match.
+const ADJACENT: i64 = 5;
+/// Bonus for a character starting a word: the first one, one after `_`, `/`
+/// or another separator, or an upper case letter after a lower case one.
+const WORD_START: i64 = 8;
+/// Characters skipped between two matches cost one each, up to this much.
+const MAX_GAP_PENALTY: i64 = 10;
+
+/// Where a filter matched some text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// How well it matched, higher is better.
+    pub score: i64,
+    /// Byte offsets of the matched characters, ascending.
+    pub positions: Vec<usize>,
+}
+
+/// Matches the filter `query` against `text`, `None` if it does not match.
+///
+/// The characters of every whitespace-separated word of the query have to
+/// appear in `text` in order, though not necessarily adjacent, so `ldlib` and
+/// `ld lib` both match LD_LIBRARY_PATH. Case is ignored.
+pub fn find(query: &str, text: &str) -> Option<Match> {
+    let text: Vec<(usize, char)> = text.char_indices().collect();
+    let mut found = Match {
+        score: 0,
+        positions: Vec::new(),
+    };
+    for word in query.split_whitespace() {
+        let (score, positions) = word_match(word, &text)?;
+        found.score += score;
+        found.positions.extend(positions);
+    }
+    found.positions.sort_unstable();
+    found.positions.dedup();
+    Some(found)
+}
+
+/// The score of `find`.
+pub fn score(query: &str, text: &str) -> Option<i64> {
+    find(query, text).map(|m| m.score)
+}
+
+/// The `rows` whose `text` matches `query`, best match first. Rows scoring
+/// the same keep their order.
+pub fn filter_sorted<T, S: AsRef<str>>(
+    rows: Vec<T>,
+    query: &str,
+    text: impl Fn(&T) -> S,
+) -> Vec<T> {
+    let mut scored: Vec<(i64, T)> = rows
+        .into_iter()
+        .filter_map(|row| Some((score(query, text(&row).as_ref())?, row)))
+        .collect();
+    scored.sort_by_key(|(score, _)| Reverse(*score));
+    scored.into_iter().map(|(_, row)| row).collect()
+}
+
+/// `text` cut into runs of characters at `positions` (`true`) and runs of
+/// the others, for drawing a match. Runs always end on character boundaries.
+pub fn segments<'a>(text: &'a str, positions: &[usize]) -> Vec<(&'a str, bool)> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut matched = false;
+    for (i, _) in text.char_indices() {
+        let here = positions.binary_search(&i).is_ok();
+        if i == 0 {
+            matched = here;
+        } else if here != matched {
+            segments.push((&text[start..i], matched));
+            (start, matched) = (i, here);
+        }
+    }
+    if start < text.len() {
+        segments.push((&text[start..], matched));
+    }
+    segments
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn is_word_start(text: &[(usize, char)], i: usize) -> bool {
+    let Some(&(_, prev)) = i.checked_sub(1).map(|p| &text[p]) else {
+        return true;
+    };
+    !prev.is_alphanumeric() || (prev.is_lowercase() && text[i].1.is_uppercase())
+}
+
+/// The best match of `word` over every place its first character occurs,
+/// the earliest of equally good ones.
+fn word_match(word: &str, text: &[(usize, char)]) -> Option<(i64, Vec<usize>)> {
+    let word: Vec<char> = word.chars().map(fold).collect();
+    let mut best: Option<(i64, Vec<usize>)> = None;
+    for start in (0..text.len()).filter(|&start| fold(text[start].1) == word[0]) {
+        if let Some(found) = match_from(&word, text, start) {
+            if best.as_ref().is_none_or(|(score, _)| found.0 > *score) {
+                best = Some(found);
+            }
+        }
+    }
+    best
+}
+
+/// Matches `word` from `start` on, taking each character as early as possible.
+fn match_from(word: &[char], text: &[(usize, char)], start: usize) -> Option<(i64, Vec<usize>)> {
+    let mut score = 0;
+    let mut positions = Vec::with_capacity(word.len());
+    let mut prev: Option<usize> = None;
+    let mut from = start;
+    for &c in word {
+        let i = (from..text.len()).find(|&i| fold(text[i].1) == c)?;
+        score += 1;
+        if let Some(prev) = prev {
+            let gap = (i - prev - 1) as i64;
+            score += if gap == 0 {
+                ADJACENT
+            } else {
+                -gap.min(MAX_GAP_PENALTY)
+            };
+        }
+        if is_word_start(text, i) {
+            score += WORD_START;
+        }
+        positions.push(text[i].0);
+        prev = Some(i);
+        from = i + 1;
+    }
+    Some((score, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked<'a>(query: &str, texts: &[&'a str]) -> Vec<&'a str> {
+        filter_sorted(texts.to_vec(), query, |t| *t)
+    }
+
+    #[test]
+    fn cpp_ranks_cppflags_first() {
+        let vars = ["CPATH", "CPLUS_INCLUDE_PATH", "CPPFLAGS", "CC"];
+        assert_eq!(ranked("cpp", &vars), ["CPPFLAGS", "CPLUS_INCLUDE_PATH"]);
+    }
+
+    #[test]
+    fn words_match_separately_and_out_of_order() {
+        assert!(score("ld lib", "LD_LIBRARY_PATH").is_some());
+        assert!(score("ldlib", "LD_LIBRARY_PATH").is_some());
+        assert!(score("lib ld", "LD_LIBRARY_PATH").is_some());
+        assert_eq!(score("ld lib", "LDFLAGS"), None);
+        assert_eq!(
+            ranked("ld lib", &["LIBRARY_PATH", "LDFLAGS", "LD_LIBRARY_PATH"]),
+            ["LD_LIBRARY_PATH"]
+        );
+    }
+
+    #[test]
+    fn inc_prefers_a_word_over_scattered_letters() {
+        let parts = ["/opt/bin/cc", "/usr/include", "-I/src/inc"];
+        assert_eq!(
+            ranked("inc", &parts),
+            ["/usr/include", "-I/src/inc", "/opt/bin/cc"]
+        );
+        assert_eq!(score("INC", "/usr/include"), score("inc", "/usr/include"));
+        assert_eq!(score("incz", "/usr/include"), None);
+    }
+
+    #[test]
+    fn camel_case_humps_start_words() {
+        assert!(score("ts", "toolSet") > score("ts", "tests"));
+        assert_eq!(score("", "anything"), Some(0));
+    }
+
+    #[test]
+    fn positions_are_byte_offsets_of_the_best_match() {
+        let found = find("inc", "/usr/include").unwrap();
+        assert_eq!(found.positions, [5, 6, 7]);
+        // The word start beats the earlier scattered letters.
+        assert_eq!(find("lib", "/lxixb/lib").unwrap().positions, [7, 8, 9]);
+        let found = find("ld lib", "LD_LIBRARY_PATH").unwrap();
+        assert_eq!(found.positions, [0, 1, 3, 4, 5]);
+    }
+
+    #[test]
+    fn multi_byte_characters_are_kept_whole() {
+        let text = "/home/jürgen/bin";
+        let found = find("ürb", text).unwrap();
+        assert_eq!(found.positions, [7, 9, 14]);
+        assert_eq!(
+            segments(text, &found.positions),
+            [
+                ("/home/j", false),
+                ("ür", true),
+                ("gen/", false),
+                ("b", true),
+                ("in", false)
+            ]
+        );
+        // Offsets inside a character are not boundaries and are ignored.
+        assert_eq!(segments("ü", &[1]), [("ü", false)]);
+        assert_eq!(segments("ab", &[0, 1]), [("ab", true)]);
+        assert!(segments("", &[]).is_empty());
+    }
+}
@@ -1,23 +1,269 @@
-use crate::config::{EnvProfile, VarKind};
+use crate::config::VarTypeOption;
+use crate::config::{make_entry, CatalogItem, CustomVarDef, Entry, EnvProfile, VarKind};
 use crate::db;
+use crate::error::BathError;
+use crate::export::{entry_value, OperationMode};
 use crate::profile_editor::{confirm_dialog, edit_profile_name_dialog};
-use crate::tui::state::{AppState, Holding, InputMode};
+use crate::profiles;
+use crate::tui::line_input::single_line;
+use crate::tui::state::{AppState, Holding, InputMode, TagEdit};
+use crate::tui::util::is_ctrl_c;
 use crate::tui::view::View;
-use crate::tui::{commands, dialogs, editor, select};
+use crate::tui::{commands, dialogs, editor, mouse, select};
 use anyhow::Result;
-use crossterm::event::KeyCode;
+use crossterm::event::{
+    KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
 use ratatui::backend::Backend;
 use ratatui::Terminal;
+use std::time::{Duration, Instant};
 
+/// Handles one key. A failed database write (e.g. the database is locked by
+/// another bath) is shown in the status line instead of ending the TUI.
 pub fn handle_key_event<B: Backend>(
     terminal: &mut Terminal<B>,
     app: &mut AppState,
-    code: KeyCode,
+    key: KeyEvent,
 ) -> Result<bool> {
+    // Terminals with the kitty keyboard protocol (and Windows) also report
+    // releases; acting on them would repeat every key.
+    if key.kind == KeyEventKind::Release {
+        return Ok(false);
+    }
+    let result = match app.input_mode {
+        InputMode::Normal => handle_normal_key(terminal, app, key),
+        InputMode::Command => handle_command_key(terminal, app, key),
+        InputMode::Search => handle_search_key(app, key),
+        InputMode::QuickAdd => handle_quick_add_key(app, key),
+        InputMode::Tags => handle_tag_key(app, key),
+    };
+    report_storage_error(app, result)
+}
+
+/// Handles a bracketed paste: the text goes into the command or filter line
+/// as a whole, and in the Parts view it becomes new parts of the selected var.
+/// Storage errors are reported like `handle_key_event` does.
+pub fn handle_paste<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut AppState,
+    text: &str,
+) -> Result<()> {
+    let result = paste(terminal, app, text).map(|()| false);
+    report_storage_error(app, result).map(|_| ())
+}
+
+/// The second key of `gg` has to follow the first within this time.
+const KEY_SEQUENCE: Duration = Duration::from_secs(1);
+
+/// Two left clicks on the same cell within this time are a double-click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Handles a mouse event on the main screen: the wheel moves the selection, a
+/// left click selects the list row or header tab under it, and a double-click
+/// on a row acts like Enter. Storage errors are reported like
+/// `handle_key_event` does.
+pub fn handle_mouse_event<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut AppState,
+    mouse: MouseEvent,
+) -> Result<bool> {
+    if !matches!(app.input_mode, InputMode::Normal) {
+        return Ok(false);
+    }
+    let result = match mouse.kind {
+        MouseEventKind::ScrollUp => {
+            move_selection(app, -1);
+            Ok(false)
+        }
+        MouseEventKind::ScrollDown => {
+            move_selection(app, 1);
+            Ok(false)
+        }
+        MouseEventKind::Down(MouseButton::Left) => click(terminal, app, mouse.column, mouse.row),
+        _ => Ok(false),
+    };
+    report_storage_error(app, result)
+}
+
+fn click<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut AppState,
+    column: u16,
+    row: u16,
+) -> Result<bool> {
+    let now = Instant::now();
+    let double = app
+        .last_click
+        .is_some_and(|(at, c, r)| (c, r) == (column, row) && now.duration_since(at) < DOUBLE_CLICK);
+    // A third click starts over.
+    app.last_click = (!double).then_some((now, column, row));
+
+    if let Some(view) = mouse::view_at(app.header_area, column, row) {
+        app.active_view = view;
+        return Ok(false);
+    }
+    let Some(index) = app
+        .list_areas
+        .get(&app.active_view)
+        .and_then(|list| list.row_at(column, row))
+        .filter(|&index| index < list_len(app))
+    else {
+        return Ok(false);
+    };
+    let current = app
+        .list_state_mut(app.active_view)
+        .and_then(|s| s.selected())
+        .unwrap_or(0);
+    move_selection(app, index as isize - current as isize);
+    if double {
+        return handle_normal_key(terminal, app, KeyEvent::from(KeyCode::Enter));
+    }
+    Ok(false)
+}
+
+fn report_storage_error(app: &mut AppState, result: Result<bool>) -> Result<bool> {
+    match result {
+        Err(e) => match storage_error_status(&e) {
+            Some(status) => {
+                let _ = app.reload_profiles();
+                app.status = status;
+                Ok(false)
+            }
+            None => Err(e),
+        },
+        ok => ok,
+    }
+}
+
+fn paste<B: Backend>(terminal: &mut Terminal<B>, app: &mut AppState, text: &str) -> Result<()> {
     match app.input_mode {
-        InputMode::Normal => handle_normal_key(terminal, app, code),
-        InputMode::Command => handle_command_key(terminal, app, code),
-        InputMode::Search => handle_search_key(app, code),
+        InputMode::Command => {
+            app.command_input.paste(text);
+            commands::refresh_command_suggestions(app);
+        }
+        InputMode::Search => {
+            app.command_input.paste(text);
+            let q = app.command_input.to_string();
+            apply_live_filter(app, &q);
+        }
+        InputMode::QuickAdd | InputMode::Tags => app.command_input.paste(text),
+        InputMode::Normal if app.active_view == View::Parts => paste_parts(terminal, app, text)?,
+        InputMode::Normal => {}
+    }
+    Ok(())
+}
+
+/// The parts a value pasted into the Parts view of `opt` holds: one per line
+/// and, for list vars, one per piece between separators. Scalars take the
+/// whole paste as their value.
+fn pasted_parts(text: &str, opt: &VarTypeOption) -> Vec<String> {
+    if opt.kind == VarKind::Scalar {
+        return vec![single_line(text)];
+    }
+    let sep = opt.separator.as_str();
+    text.lines()
+        .flat_map(|line| -> Vec<&str> {
+            if sep.trim().is_empty() {
+                line.split_whitespace().collect()
+            } else {
+                line.split(sep).collect()
+            }
+        })
+        .map(|piece| single_line(piece.trim()))
+        .filter(|piece| !piece.is_empty())
+        .collect()
+}
+
+/// Offers to add every piece of a pasted separator-joined value as its own
+/// part; otherwise the paste is opened in the editor as one part.
+fn paste_parts<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut AppState,
+    text: &str,
+) -> Result<()> {
+    let var = app
+        .selected_var_name
+        .clone()
+        .unwrap_or_else(|| "PATH".to_string());
+    let opt = select::var_option_for(app, &var);
+    let pieces = pasted_parts(text, &opt);
+    if pieces.len() > 1 {
+        let question = format!(
+            "Add {} parts to {var}, split on {:?}? (N edits the paste as one part)",
+            pieces.len(),
+            opt.separator
+        );
+        if confirm_dialog(terminal, &question)? {
+            let added = pieces.len();
+            let mut parts = select::current_var_parts(app, &var);
+            for piece in pieces {
+                let entry = make_entry(&var, piece, &app.custom_var_defs);
+                parts.push(app.normalize_saved(entry));
+            }
+            app.replace_var_parts(&var, parts)?;
+            app.status = format!("added {added} parts to {var}");
+            return Ok(());
+        }
+    }
+    let value = match pieces.len() {
+        0 => return Ok(()),
+        1 => pieces[0].clone(),
+        _ => single_line(text),
+    };
+    let initial = make_entry(&var, value, &app.custom_var_defs);
+    add_part(terminal, app, Some(&initial))
+}
+
+/// Opens the editor for a new part of the selected var, filled in from
+/// `initial`, and saves it. A scalar's part replaces its value.
+fn add_part<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut AppState,
+    initial: Option<&Entry>,
+) -> Result<()> {
+    let var = app
+        .selected_var_name
+        .clone()
+        .unwrap_or_else(|| "PATH".to_string());
+    let opt = select::var_option_for(app, &var);
+    if let Some(new_entry) = editor::edit_env_var_dialog(
+        terminal,
+        std::slice::from_ref(&opt),
+        initial,
+        app.effective_mode(),
+        &app.theme,
+    )? {
+        let new_entry = app.normalize_saved(new_entry);
+        if opt.kind == VarKind::Scalar {
+            app.replace_var_parts(&var, vec![new_entry])?;
+        } else {
+            app.add_env_var(new_entry)?;
+        }
+        app.status = format!("added part to {var}");
+    }
+    Ok(())
+}
+
+/// The status line for a `BathError` in `err`'s chain: "save failed" only
+/// when writing failed, the error itself when the change was refused. `None`
+/// for terminal and other errors, which end the TUI.
+fn storage_error_status(err: &anyhow::Error) -> Option<String> {
+    let bath = err.chain().find_map(|e| e.downcast_ref::<BathError>())?;
+    match bath {
+        BathError::Terminal(_) => None,
+        BathError::Database(_)
+        | BathError::Json(_)
+        | BathError::File { .. }
+        | BathError::NoDatabasePath
+        | BathError::SchemaTooNew { .. } => Some(format!("save failed: {err}")),
+        BathError::DatabaseLocked
+        | BathError::ProfileNotFound { .. }
+        | BathError::ProfileExists(_)
+        | BathError::VarNotInProfile { .. }
+        | BathError::InvalidVarName(_)
+        | BathError::InvalidArgument(_)
+        | BathError::ReferenceCycle(_)
+        | BathError::InheritanceCycle(_) => Some(err.to_string()),
     }
 }
 
@@ -29,7 +275,8 @@ fn cycle_view(app: &mut AppState) {
         View::Items => View::Defs,
         View::Defs => View::Preview,
         View::Preview => View::Export,
-        View::Export => View::Help,
+        View::Export => View::History,
+        View::History => View::Help,
         View::Help => View::Profiles,
     };
 }
@@ -37,28 +284,56 @@ fn cycle_view(app: &mut AppState) {
 fn handle_normal_key<B: Backend>(
     terminal: &mut Terminal<B>,
     app: &mut AppState,
-    code: KeyCode,
+    key: KeyEvent,
 ) -> Result<bool> {
-    match code {
+    if is_ctrl_c(&key) {
+        return if confirm_dialog(terminal, "Quit bath?")? {
+            quit(app)
+        } else {
+            Ok(false)
+        };
+    }
+    // Any other key, or a second `g` too late, ends a `gg` in progress.
+    let after_g = app
+        .pending_g
+        .take()
+        .is_some_and(|at| at.elapsed() < KEY_SEQUENCE);
+    // Other Ctrl chords are not bound here; never act on them as plain letters.
+    if key.modifiers.contains(KeyModifiers::CONTROL) && matches!(key.code, KeyCode::Char(_)) {
+        match key.code {
+            KeyCode::Char('d') => move_half_page(app, 1),
+            KeyCode::Char('u') => move_half_page(app, -1),
+            _ => {}
+        }
+        return Ok(false);
+    }
+    match key.code {
         // Quit
-        KeyCode::Char('q') => return Ok(true),
+        KeyCode::Char('q') => return quit(app),
 
         // Vim-ish movement keys
         KeyCode::Char('j') => move_selection(app, 1),
         KeyCode::Char('k') => move_selection(app, -1),
-        KeyCode::Char('G') | KeyCode::Home => jump_to_top(app),
-        KeyCode::Char('g') | KeyCode::End => jump_to_bottom(app),
+        KeyCode::Char('g') if after_g => jump_to_top(app),
+        KeyCode::Char('g') => app.pending_g = Some(Instant::now()),
+        KeyCode::Home => jump_to_top(app),
+        KeyCode::Char('G') | KeyCode::End => jump_to_bottom(app),
         KeyCode::PageUp => move_selection(app, -10),
         KeyCode::PageDown => move_selection(app, 10),
 
         KeyCode::Esc => {
             // Global cancel for in-progress part move.
-            if let Some(Holding::Part { var, from, entry }) = app.holding.take() {
-                let mut parts = select::current_var_parts(app, &var);
-                let insert_at = from.min(parts.len());
-                parts.insert(insert_at, entry);
-                app.replace_var_parts(&var, parts)?;
+            let moved = app.put_back_held_parts()?;
+            // Picked up items are just let go of.
+            app.holding = None;
+            if moved > 0 {
                 app.status = "cancelled move".to_string();
+            } else if app.active_view == View::Parts && !app.marked_parts().is_empty() {
+                app.part_selection_mut().clear();
+                app.status = "cleared marks".to_string();
+            } else if app.active_view == View::Items && !app.item_marks.is_empty() {
+                app.item_marks.clear();
+                app.status = "cleared marks".to_string();
             } else {
                 app.status.clear();
             }
@@ -67,6 +342,18 @@ fn handle_normal_key<B: Backend>(
         KeyCode::Tab => cycle_view(app),
 
         KeyCode::Char('?') => app.active_view = View::Help,
+        KeyCode::Char('y') if app.active_view == View::Export => {
+            let var = app
+                .selected_var_name
+                .clone()
+                .unwrap_or_else(|| "PATH".to_string());
+            app.copy_export(Some(&var));
+        }
+        KeyCode::Char('Y') if app.active_view == View::Export => app.copy_export(None),
+        KeyCode::Char('o') if matches!(app.active_view, View::Preview | View::Export) => {
+            app.cycle_export_mode();
+            app.status = format!("mode: {}", app.mode_label());
+        }
 
         KeyCode::Char(':') => {
             app.input_mode = InputMode::Command;
@@ -75,6 +362,7 @@ fn handle_normal_key<B: Backend>(
             commands::refresh_command_suggestions(app);
         }
 
+        KeyCode::Char('i') if app.active_view == View::Parts => start_quick_add(app),
         KeyCode::Char('/') => {
             if app.active_view.is_filterable() {
                 app.input_mode = InputMode::Search;
@@ -106,40 +394,140 @@ fn handle_normal_key<B: Backend>(
                 }
             }
         }
+        KeyCode::Char('K') if app.active_view == View::Profiles => {
+            app.move_selected_profile(-1)?;
+        }
+        KeyCode::Char('J') if app.active_view == View::Profiles => {
+            app.move_selected_profile(1)?;
+        }
         KeyCode::Char('D') if app.active_view == View::Profiles => {
             if let Some(i) = select::selected_profile_index(app) {
                 if confirm_dialog(terminal, "Delete profile?")? {
                     let name = app.profiles[i].name.clone();
                     app.delete_profile(i)?;
-                    app.status = format!("deleted profile: {name}");
+                    app.status =
+                        format!("deleted profile: {name} (:recover {name} brings it back)");
+                }
+            }
+        }
+
+        // History view actions
+        KeyCode::Char('R') if app.active_view == View::History => {
+            let selected = app.history_list_state.selected();
+            if let Some(entry) = selected.and_then(|i| app.history.get(i)) {
+                let (id, action) = (entry.id, entry.action.clone());
+                let question = format!("Undo #{id} ({action}) and every later change?");
+                if confirm_dialog(terminal, &question)? {
+                    app.restore_from_history(id)?;
+                    app.history_list_state.select(Some(0));
+                    app.status = format!("restored to before #{id}");
                 }
             }
         }
 
         // Defs view actions
         KeyCode::Char('C') if app.active_view == View::Defs => {
-            if let Some(def) = dialogs::create_custom_var_dialog(terminal)? {
+            if let Some(def) = dialogs::create_custom_var_dialog(terminal, None)? {
                 db::save_custom_var_def(&app.conn, &def)?;
                 app.refresh_var_options()?;
                 app.status = format!("saved var def: {}", def.name);
+                app.normalize_separators()?;
+            }
+        }
+        KeyCode::Char('e') if app.active_view == View::Defs => {
+            if let Some(old) = selected_def_for(app, "edited") {
+                if let Some(def) = dialogs::create_custom_var_dialog(terminal, Some(&old))? {
+                    let users = db::profiles_using_custom_var(&app.conn, &old.name)?;
+                    if def.name != old.name {
+                        app.status = format!("use E to rename {}", old.name);
+                    } else if def.kind != old.kind && !users.is_empty() {
+                        app.status = format!(
+                            "{} is used by {} profile(s), its kind cannot change",
+                            old.name,
+                            users.len()
+                        );
+                    } else {
+                        app.status = format!("saved var def: {}", def.name);
+                        app.update_custom_var_def(&def)?;
+                    }
+                }
+            }
+        }
+        KeyCode::Char('s') if app.active_view == View::Defs => {
+            if select::selected_custom_def(app).is_some() {
+                app.status = "custom var defs change their separator with e".to_string();
+            } else if let Some(opt) = app
+                .defs_list_state
+                .selected()
+                .and_then(|i| select::visible_defs(app).get(i).cloned())
+            {
+                if let Err(e) = crate::defs::check_separator_override(&opt.name, None) {
+                    app.status = e.to_string();
+                } else if let Some(sep) =
+                    dialogs::separator_dialog(terminal, &opt.name, &opt.separator)?
+                {
+                    let sep = (!sep.is_empty()).then_some(sep);
+                    app.status = match &sep {
+                        Some(sep) => format!("{} is now joined with {sep:?}", opt.name),
+                        None => format!("{} uses its builtin separator again", opt.name),
+                    };
+                    app.set_separator_override(&opt.name, sep.as_deref())?;
+                }
+            }
+        }
+        KeyCode::Char('d') | KeyCode::Char('D') if app.active_view == View::Defs => {
+            if let Some(name) = selected_def_for(app, "deleted").map(|d| d.name) {
+                let users = db::profiles_using_custom_var(&app.conn, &name)?;
+                let question = if users.is_empty() {
+                    format!("Delete var def {name}?")
+                } else {
+                    format!(
+                        "Delete var def {name}? Its entries in {} profile(s) are kept as undefined.",
+                        users.len()
+                    )
+                };
+                if confirm_dialog(terminal, &question)? {
+                    app.delete_custom_var_def(&name)?;
+                    app.status = format!("deleted var def: {name}");
+                }
+            }
+        }
+        KeyCode::Char('E') if app.active_view == View::Defs => {
+            if let Some(old) = selected_def_for(app, "renamed").map(|d| d.name) {
+                if let Some(new) = dialogs::rename_var_dialog(terminal, &old)? {
+                    if let Err(e) = profiles::check_var_rename(&old, &new) {
+                        app.status = e.to_string();
+                    } else if new != old && app.var_options.iter().any(|o| o.name == new) {
+                        app.status = format!("{new} is already defined");
+                    } else if new != old {
+                        let users = db::profiles_using_custom_var(&app.conn, &old)?;
+                        let question =
+                            format!("Rename {old} to {new} in {} profile(s)?", users.len());
+                        if confirm_dialog(terminal, &question)? {
+                            app.rename_custom_var_def(&old, &new)?;
+                            app.status = format!("renamed var def: {old} -> {new}");
+                        }
+                    }
+                }
             }
         }
 
         // Items view actions
         KeyCode::Char('a') if app.active_view == View::Items => {
             if let Some(mut item) = dialogs::create_or_edit_item_dialog(terminal, None)? {
-                db::save_item(&app.conn, &mut item)?;
+                let merged = db::save_item(&app.conn, &mut item, false)?;
                 app.refresh_items()?;
-                app.status = format!("saved item: {}", item.value);
+                app.status = commands::item_saved_status(&item, merged);
             }
         }
+        KeyCode::Char('t') if app.active_view == View::Items => start_tag_edit(app),
         KeyCode::Char('e') if app.active_view == View::Items => {
             if let Some(i) = select::selected_item_index(app) {
                 if let Some(initial) = app.items.get(i).cloned() {
                     if let Some(mut edited) =
                         dialogs::create_or_edit_item_dialog(terminal, Some(&initial))?
                     {
-                        db::save_item(&app.conn, &mut edited)?;
+                        db::save_item(&app.conn, &mut edited, false)?;
                         app.refresh_items()?;
                         app.status = format!("updated item: {}", edited.value);
                     }
@@ -149,7 +537,13 @@ fn handle_normal_key<B: Backend>(
         KeyCode::Char('d') if app.active_view == View::Items => {
             if let Some(i) = select::selected_item_index(app) {
                 if let Some(id) = app.items.get(i).and_then(|it| it.id) {
-                    if confirm_dialog(terminal, "Delete item?")? {
+                    let links = db::item_links(&app.conn, id)?.len();
+                    let question = if links == 0 {
+                        "Delete item?".to_string()
+                    } else {
+                        format!("Delete item? {links} profile part(s) came from it (they stay).")
+                    };
+                    if confirm_dialog(terminal, &question)? {
                         db::delete_item(&app.conn, id)?;
                         app.refresh_items()?;
                         app.status = "deleted item".to_string();
@@ -162,55 +556,82 @@ fn handle_normal_key<B: Backend>(
                 if let Some(orig) = app.items.get(i).cloned() {
                     let mut dup = orig.clone();
                     dup.id = None;
-                    db::save_item(&app.conn, &mut dup)?;
+                    db::save_item(&app.conn, &mut dup, true)?;
                     app.refresh_items()?;
                     app.status = "duplicated item".to_string();
                 }
             }
         }
+        KeyCode::Char(' ') if app.active_view == View::Items => {
+            if let Some(id) = highlighted_item(app) {
+                app.item_marks.toggle(id);
+                app.status = format!("{} marked", count_items(marked_items(app).len()));
+            }
+        }
+        KeyCode::Char('v') if app.active_view == View::Items => {
+            if app.item_marks.visual_anchor().is_some() {
+                app.item_marks.end_visual();
+                app.status = format!("{} marked", count_items(marked_items(app).len()));
+            } else if let Some(id) = highlighted_item(app) {
+                app.item_marks.start_visual(id);
+                app.status = "marking a range (j/k:extend, v:done, Esc:clear)".to_string();
+            }
+        }
         KeyCode::Char('m') if app.active_view == View::Items => {
-            if let Some(i) = select::selected_item_index(app) {
-                if let Some(it) = app.items.get(i).cloned() {
-                    app.holding = Some(Holding::Item(it));
-                    app.status = "picked item".to_string();
-                }
+            let items = target_items(app);
+            if !items.is_empty() {
+                app.status = format!("picked {}", count_items(items.len()));
+                app.holding = Some(Holding::Items(items));
             }
         }
+        KeyCode::Char('w') if app.active_view == View::Items => {
+            app.show_item_links = !app.show_item_links;
+        }
+        KeyCode::Char('s') if app.active_view == View::Items => {
+            // The highlight stays on the same item.
+            let highlighted = highlighted_item(app);
+            app.item_sort = app.item_sort.next();
+            let rows = select::visible_item_indices(app);
+            let at =
+                highlighted.and_then(|id| rows.iter().position(|i| app.items[*i].id == Some(id)));
+            app.items_list_state.select(Some(at.unwrap_or(0)));
+            app.status = format!("items sorted by: {}", app.item_sort.name());
+        }
         KeyCode::Char('p') if app.active_view == View::Items => {
-            // Drop selected item into current var context.
+            // Drop the marked (or selected) items into current var context.
             let var = app
                 .selected_var_name
                 .clone()
                 .unwrap_or_else(|| "PATH".to_string());
-            let opt = select::var_option_for(app, &var);
-            if opt.kind != VarKind::List {
-                app.status = "cannot drop into scalar var".to_string();
-            } else if let Some(i) = select::selected_item_index(app) {
-                if let Some(it) = app.items.get(i).cloned() {
-                    if let Some(e) = select::make_part_entry(app, &var, it.value) {
-                        app.add_env_var(e)?;
-                        app.status = format!("dropped into {var}");
-                    }
-                }
+            let items = target_items(app);
+            if items.is_empty() {
+                return Ok(false);
+            }
+            if select::var_option_for(app, &var).kind == VarKind::Scalar {
+                drop_onto_scalar(terminal, app, &var, &items)?;
+            } else {
+                let (dropped, skipped) = drop_items(app, &var, &items, None)?;
+                app.status = dropped_status(&var, dropped, skipped);
             }
         }
 
         // Vars view actions
         KeyCode::Char('p') if app.active_view == View::Vars => {
             // Drop held item into selected var (append).
-            if let Some(Holding::Item(it)) = app.holding.clone() {
+            if let Some(Holding::Items(items)) = app.holding.clone() {
                 let rows = select::compute_var_rows(app);
                 if let Some(i) = app.vars_list_state.selected() {
                     if let Some(row) = rows.get(i) {
-                        if row.kind != VarKind::List {
-                            app.status = "cannot drop into scalar var".to_string();
-                        } else if let Some(e) =
-                            select::make_part_entry(app, &row.name, it.value.clone())
-                        {
-                            app.add_env_var(e)?;
+                        let dropped = if row.kind == VarKind::Scalar {
+                            usize::from(drop_onto_scalar(terminal, app, &row.name, &items)?)
+                        } else {
+                            let (dropped, skipped) = drop_items(app, &row.name, &items, None)?;
+                            app.status = dropped_status(&row.name, dropped, skipped);
+                            dropped
+                        };
+                        if dropped > 0 {
                             app.holding = None;
                             app.selected_var_name = Some(row.name.clone());
-                            app.status = format!("dropped into {}", row.name);
                         }
                     }
                 }
@@ -218,23 +639,7 @@ fn handle_normal_key<B: Backend>(
         }
 
         // Parts view actions
-        KeyCode::Char('a') if app.active_view == View::Parts => {
-            let var = app
-                .selected_var_name
-                .clone()
-                .unwrap_or_else(|| "PATH".to_string());
-            let opt = select::var_option_for(app, &var);
-            if let Some(new_entry) =
-                editor::edit_env_var_dialog(terminal, std::slice::from_ref(&opt), None)?
-            {
-                if opt.kind == VarKind::Scalar {
-                    app.replace_var_parts(&var, vec![new_entry])?;
-                } else {
-                    app.add_env_var(new_entry)?;
-                }
-                app.status = format!("added part to {var}");
-            }
-        }
+        KeyCode::Char('a') if app.active_view == View::Parts => add_part(terminal, app, None)?,
         KeyCode::Char('e') if app.active_view == View::Parts => {
             let var = app
                 .selected_var_name
@@ -246,12 +651,22 @@ fn handle_normal_key<B: Backend>(
             if let Some(sel) = app.parts_list_state.selected() {
                 if let Some(part_i) = visible.get(sel).copied() {
                     if let Some(initial) = parts.get(part_i).cloned() {
-                        if let Some(new_entry) = editor::edit_env_var_dialog(
+                        if initial.unknown_kind().is_some() {
+                            app.status = "this part comes from a newer bath and cannot be edited"
+                                .to_string();
+                        } else if let Entry::Unset { name } = initial.plain() {
+                            app.status = format!("unset {name} has no value to edit");
+                        } else if let Some(mut new_entry) = editor::edit_env_var_dialog(
                             terminal,
                             std::slice::from_ref(&opt),
                             Some(&initial),
+                            app.effective_mode(),
+                            &app.theme,
                         )? {
-                            parts[part_i] = new_entry;
+                            new_entry.set_pinned_mode(initial.pinned_mode());
+                            parts[part_i] = app
+                                .normalize_saved(new_entry)
+                                .with_enabled(initial.is_enabled());
                             app.replace_var_parts(&var, parts)?;
                             app.status = format!("edited part in {var}");
                         }
@@ -266,12 +681,14 @@ fn handle_normal_key<B: Backend>(
                 .unwrap_or_else(|| "PATH".to_string());
             let mut parts = select::current_var_parts(app, &var);
             let visible = select::visible_part_indices(app, &parts);
-            if let Some(sel) = app.parts_list_state.selected() {
-                if let Some(part_i) = visible.get(sel).copied() {
-                    parts.remove(part_i);
-                    app.replace_var_parts(&var, parts)?;
-                    app.status = format!("deleted part from {var}");
+            let targets = target_parts(app, &visible);
+            if !targets.is_empty() {
+                for &i in targets.iter().rev() {
+                    parts.remove(i);
                 }
+                app.replace_var_parts(&var, parts)?;
+                app.part_selection_mut().removed(&targets);
+                app.status = format!("deleted {} from {var}", count_parts(targets.len()));
             }
         }
         KeyCode::Char('y') if app.active_view == View::Parts => {
@@ -281,13 +698,20 @@ fn handle_normal_key<B: Backend>(
                 .unwrap_or_else(|| "PATH".to_string());
             let mut parts = select::current_var_parts(app, &var);
             let visible = select::visible_part_indices(app, &parts);
-            if let Some(sel) = app.parts_list_state.selected() {
-                if let Some(part_i) = visible.get(sel).copied() {
-                    let dup = parts[part_i].clone();
-                    parts.insert(part_i + 1, dup);
-                    app.replace_var_parts(&var, parts)?;
-                    app.status = format!("duplicated part in {var}");
+            let targets = target_parts(app, &visible);
+            if !targets.is_empty() {
+                // Each copy goes right after its original.
+                for &i in targets.iter().rev() {
+                    let dup = parts[i].clone();
+                    parts.insert(i + 1, dup);
+                }
+                app.replace_var_parts(&var, parts)?;
+                let selection = app.part_selection_mut();
+                selection.unmark(&targets);
+                for &i in targets.iter().rev() {
+                    selection.inserted(i + 1, 1);
                 }
+                app.status = format!("duplicated {} in {var}", count_parts(targets.len()));
             }
         }
         KeyCode::Char('K') if app.active_view == View::Parts => {
@@ -302,6 +726,7 @@ fn handle_normal_key<B: Backend>(
                     if part_i > 0 {
                         parts.swap(part_i - 1, part_i);
                         app.replace_var_parts(&var, parts)?;
+                        app.part_selection_mut().swapped(part_i - 1, part_i);
                         app.parts_list_state.select(Some(sel.saturating_sub(1)));
                         app.status = format!("moved part up in {var}");
                     }
@@ -320,13 +745,49 @@ fn handle_normal_key<B: Backend>(
                     if part_i + 1 < parts.len() {
                         parts.swap(part_i, part_i + 1);
                         app.replace_var_parts(&var, parts)?;
+                        app.part_selection_mut().swapped(part_i, part_i + 1);
                         app.parts_list_state.select(Some(sel + 1));
                         app.status = format!("moved part down in {var}");
                     }
                 }
             }
         }
-        KeyCode::Char('m') if app.active_view == View::Parts => {
+        KeyCode::Char('M') if app.active_view == View::Parts => {
+            let var = app
+                .selected_var_name
+                .clone()
+                .unwrap_or_else(|| "PATH".to_string());
+            let mut parts = select::current_var_parts(app, &var);
+            let visible = select::visible_part_indices(app, &parts);
+            if let Some(sel) = app.parts_list_state.selected() {
+                if let Some(part_i) = visible.get(sel).copied() {
+                    let next = editor::cycle_pinned_mode(parts[part_i].pinned_mode());
+                    if parts[part_i].set_pinned_mode(next) {
+                        app.replace_var_parts(&var, parts)?;
+                        app.status =
+                            format!("part mode: {}", next.map_or("inherit", OperationMode::name));
+                    } else {
+                        app.status = "only PATH and custom list parts can pin a mode".to_string();
+                    }
+                }
+            }
+        }
+        KeyCode::Char('x') if app.active_view == View::Parts => {
+            if let Some(part_i) = highlighted_part(app) {
+                app.part_selection_mut().toggle(part_i);
+                app.status = format!("{} marked", count_parts(app.marked_parts().len()));
+            }
+        }
+        KeyCode::Char('v') if app.active_view == View::Parts => {
+            if app.part_selection_mut().visual_anchor().is_some() {
+                app.part_selection_mut().end_visual();
+                app.status = format!("{} marked", count_parts(app.marked_parts().len()));
+            } else if let Some(part_i) = highlighted_part(app) {
+                app.part_selection_mut().start_visual(part_i);
+                app.status = "marking a range (j/k:extend, v:done, Esc:clear)".to_string();
+            }
+        }
+        KeyCode::Char(' ') if app.active_view == View::Parts => {
             let var = app
                 .selected_var_name
                 .clone()
@@ -335,15 +796,41 @@ fn handle_normal_key<B: Backend>(
             let visible = select::visible_part_indices(app, &parts);
             if let Some(sel) = app.parts_list_state.selected() {
                 if let Some(part_i) = visible.get(sel).copied() {
-                    let entry = parts.remove(part_i);
+                    let enabled = !parts[part_i].is_enabled();
+                    parts[part_i] = parts[part_i].clone().with_enabled(enabled);
                     app.replace_var_parts(&var, parts)?;
-                    app.holding = Some(Holding::Part {
-                        var: var.clone(),
-                        from: part_i,
-                        entry,
-                    });
-                    app.status = "moving part (navigate, p:drop, Esc:cancel)".to_string();
+                    app.status = format!(
+                        "{} part in {var}",
+                        if enabled { "enabled" } else { "disabled" }
+                    );
+                }
+            }
+        }
+        KeyCode::Char('m') if app.active_view == View::Parts => {
+            let var = app
+                .selected_var_name
+                .clone()
+                .unwrap_or_else(|| "PATH".to_string());
+            let mut parts = select::current_var_parts(app, &var);
+            let visible = select::visible_part_indices(app, &parts);
+            let targets = target_parts(app, &visible);
+            if !targets.is_empty() {
+                let mut picked = Vec::new();
+                for &i in targets.iter().rev() {
+                    picked.push((i, parts.remove(i)));
                 }
+                picked.reverse();
+                app.splice_var_parts(app.active_profile_index, &var, parts);
+                app.part_selection_mut().removed(&targets);
+                app.holding = Some(Holding::Parts {
+                    profile: app.profiles[app.active_profile_index].name.clone(),
+                    var: var.clone(),
+                    parts: picked,
+                });
+                app.status = format!(
+                    "moving {} (navigate, p:drop, Esc:cancel)",
+                    count_parts(targets.len())
+                );
             }
         }
         KeyCode::Char('p') if app.active_view == View::Parts => {
@@ -353,7 +840,12 @@ fn handle_normal_key<B: Backend>(
                 .clone()
                 .unwrap_or_else(|| "PATH".to_string());
             let opt = select::var_option_for(app, &var);
-            if opt.kind != VarKind::List {
+            if let (VarKind::Scalar, Some(Holding::Items(items))) = (&opt.kind, app.holding.clone())
+            {
+                if drop_onto_scalar(terminal, app, &var, &items)? {
+                    app.holding = None;
+                }
+            } else if opt.kind != VarKind::List {
                 app.status = "cannot drop into scalar var".to_string();
             } else if let Some(holding) = app.holding.clone() {
                 let mut parts = select::current_var_parts(app, &var);
@@ -365,22 +857,40 @@ fn handle_normal_key<B: Backend>(
                     .unwrap_or(parts.len());
 
                 match holding {
-                    Holding::Item(it) => {
-                        if let Some(e) = select::make_part_entry(app, &var, it.value.clone()) {
-                            parts.insert(insert_at, e);
-                            app.replace_var_parts(&var, parts)?;
+                    Holding::Items(items) => {
+                        let (dropped, skipped) = drop_items(app, &var, &items, Some(insert_at))?;
+                        if dropped > 0 {
                             app.holding = None;
-                            app.status = format!("dropped into {var}");
                         }
+                        app.status = dropped_status(&var, dropped, skipped);
                     }
-                    Holding::Part { entry, .. } => {
+                    Holding::Parts {
+                        profile,
+                        parts: picked,
+                        ..
+                    } => {
                         // If moving between different vars, convert by value.
-                        let value = select::preview_value(&entry);
-                        if let Some(e) = select::make_part_entry(app, &var, value) {
-                            parts.insert(insert_at, e);
-                            app.replace_var_parts(&var, parts)?;
+                        let moved: Option<Vec<Entry>> = picked
+                            .iter()
+                            .map(|(_, entry)| {
+                                let value = select::preview_value(entry);
+                                let mut e = select::make_part_entry(app, &var, value)?;
+                                e.set_pinned_mode(entry.pinned_mode());
+                                Some(e)
+                            })
+                            .collect();
+                        if let Some(moved) = moved {
+                            let count = moved.len();
+                            parts.splice(insert_at..insert_at, moved);
+                            let target = app.active_profile_index;
+                            app.splice_var_parts(target, &var, parts);
+                            // The pickup is saved here too, with the drop.
+                            let mut touched = vec![target];
+                            touched.extend(app.profile_index(&profile).filter(|p| *p != target));
+                            app.save_profiles(&touched)?;
+                            app.part_selection_mut().inserted(insert_at, count);
                             app.holding = None;
-                            app.status = format!("moved part into {var}");
+                            app.status = format!("moved {} into {var}", count_parts(count));
                         } else {
                             app.status = "cannot drop into target var".to_string();
                         }
@@ -395,46 +905,240 @@ fn handle_normal_key<B: Backend>(
     Ok(false)
 }
 
-fn move_selection(app: &mut AppState, delta: isize) {
-    // Clamp selection to visible list bounds at input-time. Otherwise the selection index can grow
-    // unbounded (e.g. holding Down at end), making it take many Up presses to get back in range.
-    let len = {
-        let a: &AppState = &*app;
-        match a.active_view {
-            View::Profiles => select::visible_profile_indices(a).len(),
-            View::Vars => select::compute_var_rows(a).len(),
-            View::Parts => {
-                let var = a
-                    .selected_var_name
-                    .clone()
-                    .unwrap_or_else(|| "PATH".to_string());
-                let parts = select::current_var_parts(a, &var);
-                select::visible_part_indices(a, &parts).len()
-            }
-            View::Items => select::visible_item_indices(a).len(),
-            View::Defs => {
-                let mut defs = a.var_options.clone();
-                defs.sort_by(|x, y| x.name.cmp(&y.name));
-                if !a.defs_filter.is_empty() {
-                    let q = a.defs_filter.to_lowercase();
-                    defs.retain(|d| d.name.to_lowercase().contains(&q));
-                }
-                defs.len()
-            }
-            View::Preview | View::Export | View::Help => 0,
+/// Number of selectable rows in the active view's list.
+fn list_len(app: &AppState) -> usize {
+    match app.active_view {
+        View::Profiles => select::visible_profile_indices(app).len(),
+        View::Vars => select::compute_var_rows(app).len(),
+        View::Parts => {
+            let var = app
+                .selected_var_name
+                .clone()
+                .unwrap_or_else(|| "PATH".to_string());
+            let parts = select::current_var_parts(app, &var);
+            select::visible_part_indices(app, &parts).len()
         }
+        View::Items => select::visible_item_indices(app).len(),
+        View::Defs => select::visible_defs(app).len(),
+        View::History => app.history.len(),
+        View::Preview | View::Export | View::Help => 0,
+    }
+}
+
+/// Index of the highlighted part among all parts of the selected var.
+fn highlighted_part(app: &AppState) -> Option<usize> {
+    let var = app.selected_var_name.as_deref().unwrap_or("PATH");
+    let parts = select::current_var_parts(app, var);
+    let visible = select::visible_part_indices(app, &parts);
+    let sel = app.parts_list_state.selected()?;
+    visible.get(sel).copied()
+}
+
+/// The parts `d`, `y` and `m` act on, ascending: the marked ones among the
+/// `visible` rows, or else the highlighted one.
+fn target_parts(app: &AppState, visible: &[usize]) -> Vec<usize> {
+    let marked = app.marked_parts();
+    let targets: Vec<usize> = visible
+        .iter()
+        .copied()
+        .filter(|i| marked.contains(i))
+        .collect();
+    if targets.is_empty() {
+        highlighted_part(app).into_iter().collect()
+    } else {
+        targets
+    }
+}
+
+/// Ends the TUI, first putting back parts being moved so they are not lost.
+fn quit(app: &mut AppState) -> Result<bool> {
+    app.put_back_held_parts()?;
+    Ok(true)
+}
+
+pub(crate) fn count_parts(n: usize) -> String {
+    if n == 1 {
+        "part".to_string()
+    } else {
+        format!("{n} parts")
+    }
+}
+
+/// Id of the highlighted item.
+fn highlighted_item(app: &AppState) -> Option<i64> {
+    let i = select::selected_item_index(app)?;
+    app.items[i].id
+}
+
+/// Ids of the marked items the filter shows, in display order.
+fn marked_items(app: &AppState) -> Vec<i64> {
+    select::visible_item_indices(app)
+        .into_iter()
+        .filter_map(|i| app.items[i].id)
+        .filter(|id| app.item_marks.contains(id))
+        .collect()
+}
+
+/// The items `m` and `p` act on, in display order: the marked ones the filter
+/// shows, or else the highlighted one.
+fn target_items(app: &AppState) -> Vec<CatalogItem> {
+    let marked = marked_items(app);
+    if marked.is_empty() {
+        select::selected_item_index(app)
+            .map(|i| app.items[i].clone())
+            .into_iter()
+            .collect()
+    } else {
+        marked
+            .iter()
+            .filter_map(|id| app.items.iter().find(|it| it.id == Some(*id)).cloned())
+            .collect()
+    }
+}
+
+fn count_items(n: usize) -> String {
+    if n == 1 {
+        "1 item".to_string()
+    } else {
+        format!("{n} items")
+    }
+}
+
+/// Inserts `items` as parts of `var` in a row at part index `at` (appended for
+/// `None`) with one write, linking each part to its item. Items that cannot
+/// become a part of `var` are skipped. Clears the item marks once something
+/// was dropped. Returns how many items were dropped and how many skipped.
+fn drop_items(
+    app: &mut AppState,
+    var: &str,
+    items: &[CatalogItem],
+    at: Option<usize>,
+) -> Result<(usize, usize)> {
+    let converted: Vec<(Option<i64>, Entry)> = items
+        .iter()
+        .filter_map(|it| Some((it.id, select::make_part_entry_from_item(app, var, it)?)))
+        .collect();
+    let dropped = converted.len();
+    if dropped == 0 {
+        return Ok((0, items.len()));
+    }
+    let mut parts = select::current_var_parts(app, var);
+    let at = at.unwrap_or(parts.len()).min(parts.len());
+    let links: Vec<(Option<i64>, String)> = converted
+        .iter()
+        .map(|(id, e)| (*id, entry_value(e)))
+        .collect();
+    parts.splice(at..at, converted.into_iter().map(|(_, e)| e));
+    app.replace_var_parts(var, parts)?;
+    let profile = app.profiles[app.active_profile_index].name.clone();
+    if app.shows_parts_of(&profile, var) {
+        app.part_selection_mut().inserted(at, dropped);
+    }
+    for (id, value) in links {
+        app.item_dropped(id, var, &value)?;
+    }
+    app.item_marks.clear();
+    Ok((dropped, items.len() - dropped))
+}
+
+/// Replaces the value of the scalar `var` with the single item in `items`,
+/// after asking. Returns whether it did.
+fn drop_onto_scalar<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut AppState,
+    var: &str,
+    items: &[CatalogItem],
+) -> Result<bool> {
+    let [item] = items else {
+        app.status = format!("{var} holds one value, pick a single item for it");
+        return Ok(false);
     };
+    let question = format!("Replace {var} with {}?", item.value);
+    if !confirm_dialog(terminal, &question)? {
+        return Ok(false);
+    }
+    app.replace_scalar_with_item(var, item)?;
+    app.item_marks.clear();
+    app.status = format!("replaced {var} with {}", item.value);
+    Ok(true)
+}
+
+/// Status after `dropped` items went into `var` and `skipped` did not fit.
+fn dropped_status(var: &str, dropped: usize, skipped: usize) -> String {
+    match (dropped, skipped) {
+        (0, _) => "cannot drop into scalar var".to_string(),
+        (n, 0) => format!("dropped {} into {var}", count_items(n)),
+        (n, s) => format!(
+            "dropped {} into {var}, skipped {s} that cannot be parts of it",
+            count_items(n)
+        ),
+    }
+}
 
-    let state = match app.active_view {
-        View::Profiles => Some(&mut app.profile_list_state),
-        View::Vars => Some(&mut app.vars_list_state),
-        View::Defs => Some(&mut app.defs_list_state),
-        View::Parts => Some(&mut app.parts_list_state),
-        View::Items => Some(&mut app.items_list_state),
-        View::Preview | View::Export | View::Help => None,
+/// Grows a `v` range of the Parts or Items view to the highlighted row. Rows
+/// the filter hides are not marked.
+fn extend_range(app: &mut AppState) {
+    match app.active_view {
+        View::Parts => extend_part_range(app),
+        View::Items => extend_item_range(app),
+        _ => {}
+    }
+}
+
+fn extend_item_range(app: &mut AppState) {
+    let (Some(anchor), Some(cursor)) = (
+        app.item_marks.visual_anchor(),
+        app.items_list_state.selected(),
+    ) else {
+        return;
     };
+    let ids: Vec<Option<i64>> = select::visible_item_indices(app)
+        .into_iter()
+        .map(|i| app.items[i].id)
+        .collect();
+    // A hidden anchor leaves only the highlighted row in the range.
+    let from = ids
+        .iter()
+        .position(|id| *id == Some(anchor))
+        .unwrap_or(cursor);
+    let (low, high) = (from.min(cursor), from.max(cursor));
+    let range: Vec<i64> = ids
+        .get(low..=high)
+        .unwrap_or_default()
+        .iter()
+        .flatten()
+        .copied()
+        .collect();
+    app.item_marks.extend_visual(range);
+}
+
+fn extend_part_range(app: &mut AppState) {
+    let Some(anchor) = app.part_selection_mut().visual_anchor() else {
+        return;
+    };
+    let Some(cursor) = highlighted_part(app) else {
+        return;
+    };
+    let var = app.selected_var_name.as_deref().unwrap_or("PATH");
+    let parts = select::current_var_parts(app, var);
+    let (low, high) = (anchor.min(cursor), anchor.max(cursor));
+    let range: Vec<usize> = select::visible_part_indices(app, &parts)
+        .into_iter()
+        .filter(|i| (low..=high).contains(i))
+        .collect();
+    app.part_selection_mut().extend_visual(range);
+}
 
-    let Some(state) = state else {
+fn move_selection(app: &mut AppState, delta: isize) {
+    // Clamp selection to visible list bounds at input-time. Otherwise the selection index can grow
+    // unbounded (e.g. holding Down at end), making it take many Up presses to get back in range.
+    // The text views scroll instead.
+    if let Some(scroll) = app.text_scroll.get_mut(&app.active_view) {
+        scroll.scroll(delta);
+        return;
+    }
+    let len = list_len(app);
+    let Some(state) = app.list_state_mut(app.active_view) else {
         return;
     };
 
@@ -456,6 +1160,30 @@ fn move_selection(app: &mut AppState, delta: isize) {
             }
         }
     }
+    extend_range(app);
+}
+
+/// Moves the selection by half the rows the list showed last, down for
+/// `direction` 1 and up for -1.
+fn move_half_page(app: &mut AppState, direction: isize) {
+    let view = app.active_view;
+    let rows = match app.text_scroll.get(&view) {
+        Some(scroll) => scroll.height(),
+        None => app.list_areas.get(&view).map_or(0, mouse::ListArea::rows),
+    };
+    move_selection(app, direction * (rows / 2).max(1) as isize);
+}
+
+/// The custom definition selected in the Defs view; for a builtin one the
+/// status says it cannot be `done`.
+fn selected_def_for(app: &mut AppState, done: &str) -> Option<CustomVarDef> {
+    if let Some(name) = select::selected_custom_def(app) {
+        return app.custom_var_defs.iter().find(|d| d.name == name).cloned();
+    }
+    let sel = app.defs_list_state.selected()?;
+    let name = select::visible_defs(app).get(sel)?.name.clone();
+    app.status = format!("{name} is builtin and cannot be {done}");
+    None
 }
 
 fn activate_selection(app: &mut AppState) {
@@ -476,6 +1204,14 @@ fn activate_selection(app: &mut AppState) {
                 }
             }
         }
+        View::Defs => {
+            let defs = select::visible_defs(app);
+            if let Some(def) = app.defs_list_state.selected().and_then(|i| defs.get(i)) {
+                app.selected_var_name = Some(def.name.clone());
+                app.active_view = View::Parts;
+                app.status = format!("selected var: {}", def.name);
+            }
+        }
         _ => {}
     }
 }
@@ -483,26 +1219,61 @@ fn activate_selection(app: &mut AppState) {
 fn handle_command_key<B: Backend>(
     terminal: &mut Terminal<B>,
     app: &mut AppState,
-    code: KeyCode,
+    key: KeyEvent,
 ) -> Result<bool> {
+    // Ctrl+C cancels, like Esc.
+    let code = if is_ctrl_c(&key) {
+        KeyCode::Esc
+    } else {
+        key.code
+    };
+    // Up/Down go through the history from an empty prompt, and keep doing so
+    // once they did; otherwise they pick a suggestion. Ctrl+P/Ctrl+N always
+    // go through the history.
+    let ctrl = |c| key.modifiers.contains(KeyModifiers::CONTROL) && code == KeyCode::Char(c);
+    let recalling = app.command_history.is_recalling();
+    let older = ctrl('p') || (code == KeyCode::Up && (recalling || app.command_input.is_empty()));
+    let newer = recalling && (ctrl('n') || code == KeyCode::Down);
     match code {
         KeyCode::Esc => {
+            app.command_history.stop_recalling();
             app.input_mode = InputMode::Normal;
         }
         KeyCode::Enter => {
-            let exec = commands::pick_command_to_execute(app);
-            let quit = commands::execute_command(terminal, app, &exec)?;
+            // A recalled command runs as it was, not as a suggestion for it.
+            let exec = if app.command_history.is_recalling() {
+                app.command_input.to_string()
+            } else {
+                commands::pick_command_to_execute(app)
+            };
+            // A command the palette cannot run as typed opens it again.
             app.input_mode = InputMode::Normal;
+            let quit = commands::execute_command(terminal, app, &exec)?;
+            // Only commands that ran are recorded; a failed one never gets here.
+            if app.input_mode == InputMode::Command {
+                app.command_history.stop_recalling();
+            } else if let Err(e) = app.command_history.push(&exec) {
+                app.status = format!("could not save the command history: {e}");
+            }
             if quit {
                 return Ok(true);
             }
         }
         KeyCode::Tab => {
             if let Some(s) = app.command_suggestions.get(app.command_selected).cloned() {
-                app.command_input = s;
+                app.command_input.set(s);
                 commands::refresh_command_suggestions(app);
             }
         }
+        _ if older => {
+            if let Some(cmd) = app.command_history.older().map(str::to_string) {
+                recall(app, cmd);
+            }
+        }
+        _ if newer => {
+            let cmd = app.command_history.newer().unwrap_or_default().to_string();
+            recall(app, cmd);
+        }
         KeyCode::Up => {
             app.command_selected = app.command_selected.saturating_sub(1);
         }
@@ -512,20 +1283,30 @@ fn handle_command_key<B: Backend>(
                     (app.command_selected + 1).min(app.command_suggestions.len() - 1);
             }
         }
-        KeyCode::Backspace => {
-            app.command_input.pop();
-            commands::refresh_command_suggestions(app);
-        }
-        KeyCode::Char(c) => {
-            app.command_input.push(c);
-            commands::refresh_command_suggestions(app);
+        _ => {
+            if app.command_input.handle_key(key) {
+                app.command_history.stop_recalling();
+                commands::refresh_command_suggestions(app);
+            }
         }
-        _ => {}
     }
     Ok(false)
 }
 
-fn handle_search_key(app: &mut AppState, code: KeyCode) -> Result<bool> {
+/// Puts the recalled `cmd` into the command palette.
+fn recall(app: &mut AppState, cmd: String) {
+    app.command_input.set(cmd);
+    app.command_selected = 0;
+    commands::refresh_command_suggestions(app);
+}
+
+fn handle_search_key(app: &mut AppState, key: KeyEvent) -> Result<bool> {
+    // Ctrl+C cancels, like Esc.
+    let code = if is_ctrl_c(&key) {
+        KeyCode::Esc
+    } else {
+        key.code
+    };
     match code {
         KeyCode::Esc => {
             app.command_input.clear();
@@ -536,21 +1317,205 @@ fn handle_search_key(app: &mut AppState, code: KeyCode) -> Result<bool> {
             app.command_input.clear();
             app.input_mode = InputMode::Normal;
         }
-        KeyCode::Backspace => {
-            app.command_input.pop();
-            let q = app.command_input.clone();
-            apply_live_filter(app, &q);
+        _ => {
+            if app.command_input.handle_key(key) {
+                let q = app.command_input.to_string();
+                apply_live_filter(app, &q);
+            }
         }
-        KeyCode::Char(c) => {
-            app.command_input.push(c);
-            let q = app.command_input.clone();
-            apply_live_filter(app, &q);
+    }
+    Ok(false)
+}
+
+/// Opens the quick-add line for the selected var; a scalar's starts out
+/// with its value.
+fn start_quick_add(app: &mut AppState) {
+    let var = app
+        .selected_var_name
+        .clone()
+        .unwrap_or_else(|| "PATH".to_string());
+    app.command_input.clear();
+    if select::var_option_for(app, &var).kind == VarKind::Scalar {
+        if let Some(entry) = select::current_var_parts(app, &var).first() {
+            app.command_input.set(entry_value(entry));
+        }
+    }
+    app.input_mode = InputMode::QuickAdd;
+}
+
+fn handle_quick_add_key(app: &mut AppState, key: KeyEvent) -> Result<bool> {
+    // Ctrl+C cancels, like Esc.
+    let code = if is_ctrl_c(&key) {
+        KeyCode::Esc
+    } else {
+        key.code
+    };
+    match code {
+        KeyCode::Esc => {
+            app.command_input.clear();
+            app.input_mode = InputMode::Normal;
+        }
+        KeyCode::Enter => {
+            let value = app.command_input.to_string();
+            if quick_add(app, &value)? {
+                app.command_input.clear();
+                app.input_mode = InputMode::Normal;
+            }
+        }
+        _ => {
+            app.command_input.handle_key(key);
+        }
+    }
+    Ok(false)
+}
+
+/// Opens the tag editor for the highlighted item.
+fn start_tag_edit(app: &mut AppState) {
+    let Some(i) = select::selected_item_index(app) else {
+        return;
+    };
+    let Some(id) = app.items[i].id else {
+        return;
+    };
+    app.tag_edit = Some(TagEdit {
+        item: id,
+        tags: app.items[i].tags.clone(),
+        completing: None,
+    });
+    app.command_input.clear();
+    app.input_mode = InputMode::Tags;
+}
+
+fn handle_tag_key(app: &mut AppState, key: KeyEvent) -> Result<bool> {
+    // Ctrl+C cancels, like Esc.
+    let code = if is_ctrl_c(&key) {
+        KeyCode::Esc
+    } else {
+        key.code
+    };
+    let Some(mut edit) = app.tag_edit.take() else {
+        app.input_mode = InputMode::Normal;
+        return Ok(false);
+    };
+    let completing = edit.completing.take();
+    match code {
+        KeyCode::Esc => {
+            app.command_input.clear();
+            app.input_mode = InputMode::Normal;
+            return Ok(false);
+        }
+        // Enter adds the typed tag; with nothing typed it saves.
+        KeyCode::Enter | KeyCode::Char(',') => {
+            let typed = app.command_input.to_string();
+            app.command_input.clear();
+            if !edit.add(&typed) && code == KeyCode::Enter {
+                save_tags(app, &edit)?;
+                app.input_mode = InputMode::Normal;
+                return Ok(false);
+            }
+        }
+        KeyCode::Backspace if app.command_input.is_empty() => {
+            edit.tags.pop();
+        }
+        KeyCode::Tab => {
+            let (prefix, at) = match completing {
+                Some((prefix, at)) => (prefix, at + 1),
+                None => (app.command_input.to_string(), 0),
+            };
+            let found = edit.completions(&prefix, &app.items);
+            if found.is_empty() {
+                app.status = format!("no other tag starts with {prefix:?}");
+                app.command_input.set(prefix);
+            } else {
+                let at = at % found.len();
+                app.command_input.set(found[at].clone());
+                edit.completing = Some((prefix, at));
+            }
+        }
+        _ => {
+            app.command_input.handle_key(key);
         }
-        _ => {}
     }
+    app.tag_edit = Some(edit);
     Ok(false)
 }
 
+/// Saves the tags of the item `edit` is for.
+fn save_tags(app: &mut AppState, edit: &TagEdit) -> Result<()> {
+    let Some(mut item) = app
+        .items
+        .iter()
+        .find(|it| it.id == Some(edit.item))
+        .cloned()
+    else {
+        return Ok(());
+    };
+    if item.tags == edit.tags {
+        return Ok(());
+    }
+    item.tags = edit.tags.clone();
+    db::save_item(&app.conn, &mut item, false)?;
+    app.refresh_items()?;
+    app.status = if item.tags.is_empty() {
+        format!("removed the tags of {}", item.value)
+    } else {
+        format!("tagged {}: {}", item.value, item.tags.join(", "))
+    };
+    Ok(())
+}
+
+/// The entry the quick-add line makes of `value` for `var`: a part of a list
+/// var, `None` while the value is blank, or the new value of a scalar.
+pub fn quick_add_entry(app: &AppState, var: &str, value: &str) -> Option<Entry> {
+    let value = value.trim().to_string();
+    match select::var_option_for(app, var).kind {
+        VarKind::List if value.is_empty() => None,
+        VarKind::List => select::make_part_entry(app, var, value),
+        VarKind::Scalar => Some(make_entry(var, value, &app.custom_var_defs)),
+    }
+}
+
+/// Adds `value` as a part of the selected var right after the highlighted
+/// one, or makes it the value of a scalar var, keeping the settings of the
+/// part it replaces. Returns false, with the reason in the status line, if
+/// there is nothing to add.
+fn quick_add(app: &mut AppState, value: &str) -> Result<bool> {
+    let var = app
+        .selected_var_name
+        .clone()
+        .unwrap_or_else(|| "PATH".to_string());
+    let Some(entry) = quick_add_entry(app, &var, value) else {
+        app.status = format!("type a value to add to {var}");
+        return Ok(false);
+    };
+    let entry = app.normalize_saved(entry);
+    if select::var_option_for(app, &var).kind == VarKind::Scalar {
+        app.set_scalar(&var, entry)?;
+        app.status = format!("set {var}");
+        return Ok(true);
+    }
+    let mut parts = select::current_var_parts(app, &var);
+    let visible = select::visible_part_indices(app, &parts);
+    let insert_at = app
+        .parts_list_state
+        .selected()
+        .and_then(|sel| visible.get(sel))
+        .map_or(parts.len(), |i| i + 1);
+    parts.insert(insert_at, entry);
+    app.replace_var_parts(&var, parts)?;
+    app.part_selection_mut().inserted(insert_at, 1);
+    // Highlight the new part, if the filter shows it.
+    let parts = select::current_var_parts(app, &var);
+    if let Some(row) = select::visible_part_indices(app, &parts)
+        .iter()
+        .position(|&i| i == insert_at)
+    {
+        app.parts_list_state.select(Some(row));
+    }
+    app.status = format!("added part to {var}");
+    Ok(true)
+}
+
 fn apply_live_filter(app: &mut AppState, q: &str) {
     let q = q.to_string();
     match app.search_target {
@@ -559,62 +1524,782 @@ fn apply_live_filter(app: &mut AppState, q: &str) {
         View::Defs => app.defs_filter = q,
         View::Parts => app.parts_filter = q,
         View::Items => app.items_filter = q,
-        View::Preview | View::Export | View::Help => {}
+        View::Preview | View::Export | View::History | View::Help => {}
     }
 }
 
 fn jump_to_top(app: &mut AppState) {
-    let state = match app.active_view {
-        View::Profiles => Some(&mut app.profile_list_state),
-        View::Vars => Some(&mut app.vars_list_state),
-        View::Defs => Some(&mut app.defs_list_state),
-        View::Parts => Some(&mut app.parts_list_state),
-        View::Items => Some(&mut app.items_list_state),
-        View::Preview | View::Export | View::Help => None,
-    };
-    if let Some(state) = state {
+    if let Some(scroll) = app.text_scroll.get_mut(&app.active_view) {
+        scroll.scroll(isize::MIN);
+    }
+    if let Some(state) = app.list_state_mut(app.active_view) {
         state.select(Some(0));
     }
+    extend_range(app);
 }
 
 fn jump_to_bottom(app: &mut AppState) {
-    let (len, state) = match app.active_view {
-        View::Profiles => {
-            let len = select::visible_profile_indices(app).len();
-            (len, Some(&mut app.profile_list_state))
+    if let Some(scroll) = app.text_scroll.get_mut(&app.active_view) {
+        scroll.scroll(isize::MAX);
+    }
+    let len = list_len(app);
+    if let Some(state) = app.list_state_mut(app.active_view) {
+        if len > 0 {
+            state.select(Some(len - 1));
         }
-        View::Vars => {
-            let len = select::compute_var_rows(app).len();
-            (len, Some(&mut app.vars_list_state))
+    }
+    extend_range(app);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use rusqlite::Connection;
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    fn typed(text: &str) -> Vec<KeyEvent> {
+        text.chars()
+            .map(|c| KeyEvent::from(KeyCode::Char(c)))
+            .collect()
+    }
+
+    fn press(app: &mut AppState, keys: impl IntoIterator<Item = KeyEvent>) -> Result<()> {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24))?;
+        for key in keys {
+            handle_key_event(&mut terminal, app, key)?;
         }
-        View::Parts => {
-            let var = app
-                .selected_var_name
-                .clone()
-                .unwrap_or_else(|| "PATH".to_string());
-            let parts = select::current_var_parts(app, &var);
-            let len = select::visible_part_indices(app, &parts).len();
-            (len, Some(&mut app.parts_list_state))
+        Ok(())
+    }
+
+    fn test_app() -> Result<AppState> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        AppState::for_tests(conn, vec![EnvProfile::new("gcc-13")])
+    }
+
+    #[test]
+    fn readline_shortcuts_edit_the_command_line() -> Result<()> {
+        let mut app = test_app()?;
+        press(&mut app, typed(":use gcc-13"))?;
+        assert!(matches!(app.input_mode, InputMode::Command));
+
+        press(&mut app, [ctrl('w')])?;
+        assert_eq!(app.command_input.as_str(), "use ");
+        press(&mut app, typed("clang"))?;
+        press(&mut app, [ctrl('a'), KeyEvent::from(KeyCode::Char('x'))])?;
+        press(&mut app, [ctrl('e'), KeyEvent::from(KeyCode::Char('!'))])?;
+        assert_eq!(app.command_input.as_str(), "xuse clang!");
+
+        press(
+            &mut app,
+            [ctrl('a'), KeyEvent::from(KeyCode::Right), ctrl('k')],
+        )?;
+        assert_eq!(app.command_input.as_str(), "x");
+        // Unbound chords are not typed.
+        press(&mut app, [ctrl('x'), ctrl('u')])?;
+        assert_eq!(app.command_input.as_str(), "");
+        assert!(app.command_suggestions.contains(&"quit".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn only_failed_writes_are_labelled_save_failed() {
+        let status = |e: BathError| storage_error_status(&anyhow::Error::new(e));
+        assert_eq!(
+            status(BathError::Database(rusqlite::Error::InvalidQuery)).as_deref(),
+            Some("save failed: database error: Query is not read-only")
+        );
+        assert_eq!(
+            status(BathError::ProfileExists("gcc".to_string())).as_deref(),
+            Some("profile 'gcc' already exists")
+        );
+        assert_eq!(
+            status(BathError::DatabaseLocked).as_deref(),
+            Some("the database is locked by another bath process, try again")
+        );
+        let terminal = std::io::Error::other("gone");
+        assert_eq!(status(BathError::Terminal(terminal)), None);
+        assert_eq!(storage_error_status(&anyhow::anyhow!("plain")), None);
+    }
+
+    #[test]
+    fn ctrl_u_clears_the_live_filter() -> Result<()> {
+        let mut app = test_app()?;
+        press(&mut app, typed("/cfl"))?;
+        assert_eq!(app.vars_filter, "cfl");
+        press(&mut app, [ctrl('u')])?;
+        assert_eq!(app.vars_filter, "");
+        assert!(matches!(app.input_mode, InputMode::Search));
+        Ok(())
+    }
+
+    #[test]
+    fn ctrl_c_cancels_without_typing_a_c() -> Result<()> {
+        let mut app = test_app()?;
+        press(&mut app, typed(":use"))?;
+        press(&mut app, [ctrl('c')])?;
+        assert_eq!(app.command_input.as_str(), "use");
+        assert!(matches!(app.input_mode, InputMode::Normal));
+
+        press(&mut app, typed("/cfl"))?;
+        press(&mut app, [ctrl('c')])?;
+        assert_eq!(app.command_input.as_str(), "");
+        assert_eq!(app.vars_filter, "");
+        assert!(matches!(app.input_mode, InputMode::Normal));
+        Ok(())
+    }
+
+    #[test]
+    fn releases_and_ctrl_letters_are_not_keys() -> Result<()> {
+        let mut app = test_app()?;
+        let mut terminal = Terminal::new(TestBackend::new(80, 24))?;
+        assert!(!handle_key_event(&mut terminal, &mut app, ctrl('q'))?);
+        assert!(handle_key_event(
+            &mut terminal,
+            &mut app,
+            KeyEvent::from(KeyCode::Char('q'))
+        )?);
+
+        press(&mut app, typed(":"))?;
+        let mut release = KeyEvent::from(KeyCode::Char('x'));
+        release.kind = KeyEventKind::Release;
+        press(&mut app, [KeyEvent::from(KeyCode::Char('x')), release])?;
+        assert_eq!(app.command_input.as_str(), "x");
+        Ok(())
+    }
+
+    #[test]
+    fn pastes_go_into_the_focused_line() -> Result<()> {
+        let mut app = test_app()?;
+        let mut terminal = Terminal::new(TestBackend::new(80, 24))?;
+        // Nothing to paste into outside the Parts view.
+        handle_paste(&mut terminal, &mut app, "use x")?;
+        assert!(matches!(app.input_mode, InputMode::Normal));
+        assert_eq!(app.command_input.as_str(), "");
+
+        press(&mut app, typed(":use "))?;
+        handle_paste(&mut terminal, &mut app, "gcc:13\n")?;
+        assert_eq!(app.command_input.as_str(), "use gcc:13");
+        assert!(matches!(app.input_mode, InputMode::Command));
+
+        press(&mut app, [KeyEvent::from(KeyCode::Esc)])?;
+        press(&mut app, typed("/"))?;
+        handle_paste(&mut terminal, &mut app, "ld\tflags")?;
+        assert_eq!(app.vars_filter, "ld flags");
+        Ok(())
+    }
+
+    #[test]
+    fn pasted_values_split_on_the_var_separator() {
+        let option = |name: &str| crate::config::lookup_var_option(name, &[]).unwrap();
+        assert_eq!(
+            pasted_parts("/opt/a/bin::/opt/b/bin\n/opt/c/bin\n", &option("PATH")),
+            ["/opt/a/bin", "/opt/b/bin", "/opt/c/bin"]
+        );
+        assert_eq!(
+            pasted_parts(" -O2  -g\t-pipe ", &option("CFLAGS")),
+            ["-O2", "-g", "-pipe"]
+        );
+        assert_eq!(
+            pasted_parts("clang\n--target x\n", &option("CC")),
+            ["clang --target x"]
+        );
+        assert!(pasted_parts("\n ::\n", &option("PATH")).is_empty());
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
         }
-        View::Items => {
-            let len = select::visible_item_indices(app).len();
-            (len, Some(&mut app.items_list_state))
+    }
+
+    #[test]
+    fn export_view_copies_the_line_or_the_script() -> Result<()> {
+        let mut app = test_app()?;
+        let clipboard = crate::tui::clipboard::Captured::default();
+        app.clipboard = Box::new(clipboard.clone());
+        app.add_env_var(Entry::CFlag("-O2".to_string()))?;
+        app.add_env_var(Entry::CC("gcc-13".to_string()))?;
+        app.active_view = View::Export;
+        app.selected_var_name = Some("CFLAGS".to_string());
+
+        press(&mut app, typed("yY"))?;
+        let copied = clipboard.copied();
+        assert_eq!(copied[0], "export CFLAGS=\"-O2${CFLAGS:+ }${CFLAGS}\";");
+        assert_eq!(copied[1], app.export_script());
+        assert!(copied[1].contains(&copied[0]) && copied[1].contains("export CC="));
+        assert_eq!(app.status, "copied the export script of gcc-13");
+
+        press(&mut app, typed(":copy-export LDFLAGS"))?;
+        press(&mut app, [KeyEvent::from(KeyCode::Enter)])?;
+        assert_eq!(app.status, "LDFLAGS is not exported, nothing to copy");
+        assert_eq!(clipboard.copied().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn clicks_select_rows_and_switch_views() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let profiles = vec![EnvProfile::new("gcc-13"), EnvProfile::new("clang")];
+        let mut app = AppState::for_tests(conn, profiles)?;
+        let mut terminal = Terminal::new(TestBackend::new(120, 30))?;
+        let left = MouseEventKind::Down(MouseButton::Left);
+        terminal.draw(|f| crate::tui::ui::draw_main_ui(f, &mut app))?;
+
+        let vars = app.list_areas[&View::Vars].area;
+        handle_mouse_event(&mut terminal, &mut app, mouse(left, vars.x + 3, vars.y + 4))?;
+        let rows = select::compute_var_rows(&app);
+        assert_eq!(app.vars_list_state.selected(), Some(3));
+        assert_eq!(
+            app.selected_var_name.as_deref(),
+            Some(rows[3].name.as_str())
+        );
+        let scroll = mouse(MouseEventKind::ScrollDown, 0, 0);
+        handle_mouse_event(&mut terminal, &mut app, scroll)?;
+        assert_eq!(app.vars_list_state.selected(), Some(4));
+
+        let (_, tab) = mouse::view_tabs(app.header_area)[0];
+        handle_mouse_event(&mut terminal, &mut app, mouse(left, tab.x + 1, tab.y))?;
+        assert_eq!(app.active_view, View::Profiles);
+        terminal.draw(|f| crate::tui::ui::draw_main_ui(f, &mut app))?;
+
+        // A double-click uses the profile.
+        let list = app.list_areas[&View::Profiles].area;
+        let second = mouse(left, list.x + 3, list.y + 2);
+        handle_mouse_event(&mut terminal, &mut app, second)?;
+        assert_eq!(app.active_profile_index, 0);
+        handle_mouse_event(&mut terminal, &mut app, second)?;
+        assert_eq!(app.active_profile_index, 1);
+        // Rows past the end select nothing.
+        handle_mouse_event(&mut terminal, &mut app, mouse(left, list.x + 3, list.y + 5))?;
+        assert_eq!(app.profile_list_state.selected(), Some(1));
+        Ok(())
+    }
+
+    fn cpath_app(values: &[&str]) -> Result<AppState> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("gcc-13");
+        profile.entries = values.iter().map(|v| Entry::CPath(v.to_string())).collect();
+        db::save_profile(&conn, &profile)?;
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+        app.selected_var_name = Some("CPATH".to_string());
+        app.active_view = View::Parts;
+        Ok(app)
+    }
+
+    fn cpath(app: &AppState) -> Vec<String> {
+        select::current_var_parts(app, "CPATH")
+            .iter()
+            .map(entry_value)
+            .collect()
+    }
+
+    #[test]
+    fn defs_open_their_parts_and_builtins_stay_read_only() -> Result<()> {
+        let mut app = test_app()?;
+        app.active_view = View::Defs;
+        let at = select::visible_defs(&app)
+            .iter()
+            .position(|d| d.name == "CPATH")
+            .unwrap();
+        app.defs_list_state.select(Some(at));
+        for c in ['e', 'd', 'E'] {
+            press(&mut app, [KeyEvent::from(KeyCode::Char(c))])?;
+            assert!(app.status.starts_with("CPATH is builtin and cannot be"));
         }
-        View::Defs => {
-            let mut defs = app.var_options.clone();
-            defs.sort_by(|a, b| a.name.cmp(&b.name));
-            if !app.defs_filter.is_empty() {
-                let q = app.defs_filter.to_lowercase();
-                defs.retain(|d| d.name.to_lowercase().contains(&q));
-            }
-            (defs.len(), Some(&mut app.defs_list_state))
+        assert_eq!(app.active_view, View::Defs);
+        press(&mut app, [KeyEvent::from(KeyCode::Enter)])?;
+        assert_eq!(app.active_view, View::Parts);
+        assert_eq!(app.selected_var_name.as_deref(), Some("CPATH"));
+        Ok(())
+    }
+
+    #[test]
+    fn resorting_items_keeps_the_highlighted_item() -> Result<()> {
+        let mut app = test_app()?;
+        add_items(&mut app, &["/c", "/a", "/b"])?;
+        app.active_view = View::Items;
+        app.items_list_state.select(Some(2));
+        let highlighted =
+            |app: &AppState| select::selected_item_index(app).map(|i| app.items[i].value.clone());
+        assert_eq!(highlighted(&app).as_deref(), Some("/b"));
+
+        press(&mut app, typed("s"))?;
+        assert_eq!(app.status, "items sorted by: value");
+        assert_eq!(app.items_list_state.selected(), Some(1));
+        assert_eq!(highlighted(&app).as_deref(), Some("/b"));
+
+        // Back at the top when the item is filtered out.
+        app.items_filter = "a".to_string();
+        app.items_list_state.select(None);
+        press(&mut app, typed("s"))?;
+        assert_eq!(app.items_list_state.selected(), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn tag_editor_adds_removes_and_completes_tags() -> Result<()> {
+        let mut app = test_app()?;
+        add_items(&mut app, &["/opt/gcc-13/bin", "/opt/clang/bin"])?;
+        let mut other = app.items[1].clone();
+        other.tags = vec!["x86".to_string(), "gcc-arm".to_string(), "gcc".to_string()];
+        db::save_item(&app.conn, &mut other, false)?;
+        app.refresh_items()?;
+        app.active_view = View::Items;
+        app.items_list_state.select(Some(0));
+        let key = |code| KeyEvent::from(code);
+        let tags = |app: &AppState| app.tag_edit.as_ref().map(|e| e.tags.clone());
+
+        press(&mut app, typed("t"))?;
+        assert!(matches!(app.input_mode, InputMode::Tags));
+        // Commas and Enter add tags, repeats are dropped whatever their case.
+        press(&mut app, typed("core, x86,core,CORE,"))?;
+        assert_eq!(tags(&app).unwrap(), ["core", "x86"]);
+        press(&mut app, typed("tmp"))?;
+        press(&mut app, [key(KeyCode::Enter)])?;
+        assert_eq!(tags(&app).unwrap(), ["core", "x86", "tmp"]);
+        press(&mut app, [key(KeyCode::Backspace)])?;
+        assert_eq!(tags(&app).unwrap(), ["core", "x86"]);
+
+        // Tab cycles through the other tags starting with what is typed.
+        press(&mut app, typed("g"))?;
+        press(&mut app, [key(KeyCode::Tab)])?;
+        assert_eq!(app.command_input.as_str(), "gcc");
+        press(&mut app, [key(KeyCode::Tab)])?;
+        assert_eq!(app.command_input.as_str(), "gcc-arm");
+        press(&mut app, [key(KeyCode::Tab)])?;
+        assert_eq!(app.command_input.as_str(), "gcc");
+
+        press(&mut app, [key(KeyCode::Enter), key(KeyCode::Enter)])?;
+        assert!(matches!(app.input_mode, InputMode::Normal));
+        assert_eq!(app.status, "tagged /opt/gcc-13/bin: core, x86, gcc");
+        let saved = db::load_items(&app.conn)?;
+        assert_eq!(saved[0].tags, ["core", "x86", "gcc"]);
+
+        // Esc drops the changes.
+        press(&mut app, typed("t"))?;
+        press(&mut app, [key(KeyCode::Backspace), key(KeyCode::Esc)])?;
+        assert_eq!(db::load_items(&app.conn)?[0].tags.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn quitting_or_switching_profiles_puts_moved_parts_back() -> Result<()> {
+        let mut app = cpath_app(&["/a", "/b", "/c"])?;
+        let mut terminal = Terminal::new(TestBackend::new(80, 24))?;
+        press(&mut app, typed("jm"))?;
+        assert_eq!(cpath(&app), ["/a", "/c"]);
+        assert!(handle_key_event(
+            &mut terminal,
+            &mut app,
+            KeyEvent::from(KeyCode::Char('q'))
+        )?);
+        assert_eq!(cpath(&app), ["/a", "/b", "/c"]);
+        assert!(app.holding.is_none());
+
+        app.add_profile(EnvProfile::new("clang"))?;
+        press(&mut app, typed("jm:use clang"))?;
+        press(&mut app, [KeyEvent::from(KeyCode::Enter)])?;
+        assert_eq!(app.status, "profile: clang (put the moved part back)");
+        app.active_profile_index = 0;
+        assert_eq!(cpath(&app), ["/a", "/b", "/c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn quick_add_inserts_after_the_highlighted_part() -> Result<()> {
+        let mut app = cpath_app(&["/usr/a", "/opt/b", "/usr/c"])?;
+        let enter = KeyEvent::from(KeyCode::Enter);
+        press(&mut app, typed("ji  "))?;
+        assert!(matches!(app.input_mode, InputMode::QuickAdd));
+        press(&mut app, [enter])?;
+        assert_eq!(app.status, "type a value to add to CPATH");
+        assert!(matches!(app.input_mode, InputMode::QuickAdd));
+
+        press(&mut app, typed("/opt/new"))?;
+        press(&mut app, [enter])?;
+        assert!(matches!(app.input_mode, InputMode::Normal));
+        assert_eq!(cpath(&app), ["/usr/a", "/opt/b", "/opt/new", "/usr/c"]);
+        assert_eq!(app.parts_list_state.selected(), Some(2));
+
+        // Behind a filter the position is the one in the whole list.
+        app.parts_filter = "usr".to_string();
+        app.parts_list_state.select(Some(0));
+        press(&mut app, typed("i/usr/x"))?;
+        press(&mut app, [enter])?;
+        assert_eq!(
+            cpath(&app),
+            ["/usr/a", "/usr/x", "/opt/b", "/opt/new", "/usr/c"]
+        );
+        assert_eq!(app.parts_list_state.selected(), Some(1));
+
+        press(&mut app, typed("inope"))?;
+        press(&mut app, [KeyEvent::from(KeyCode::Esc)])?;
+        assert!(matches!(app.input_mode, InputMode::Normal));
+        assert_eq!(cpath(&app).len(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn quick_add_replaces_a_scalar_value_keeping_its_note() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("gcc-13");
+        profile.entries =
+            vec![Entry::CC("gcc".to_string()).with_comment(Some("pinned".to_string()))];
+        db::save_profile(&conn, &profile)?;
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+        app.selected_var_name = Some("CC".to_string());
+        app.active_view = View::Parts;
+
+        press(&mut app, typed("i"))?;
+        assert_eq!(app.command_input.as_str(), "gcc");
+        press(&mut app, [ctrl('u')])?;
+        press(&mut app, typed("gcc-13"))?;
+        press(&mut app, [KeyEvent::from(KeyCode::Enter)])?;
+        assert_eq!(app.status, "set CC");
+        let parts = select::current_var_parts(&app, "CC");
+        assert_eq!(parts.len(), 1);
+        assert_eq!(entry_value(&parts[0]), "gcc-13");
+        assert_eq!(parts[0].comment(), Some("pinned"));
+        Ok(())
+    }
+
+    #[test]
+    fn gg_needs_two_quick_gs_in_a_row() -> Result<()> {
+        let values: Vec<String> = (0..30).map(|i| format!("/opt/{i}")).collect();
+        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+        let mut app = cpath_app(&values)?;
+        let selected = |app: &AppState| app.parts_list_state.selected();
+
+        press(&mut app, typed("G"))?;
+        assert_eq!(selected(&app), Some(29));
+        press(&mut app, typed("gkg"))?;
+        assert_eq!(selected(&app), Some(28));
+        assert!(app.pending_g.is_some());
+        press(&mut app, typed("g"))?;
+        assert_eq!(selected(&app), Some(0));
+        assert!(app.pending_g.is_none());
+
+        press(&mut app, [KeyEvent::from(KeyCode::End)])?;
+        press(&mut app, typed("g"))?;
+        app.pending_g = Instant::now().checked_sub(KEY_SEQUENCE * 2);
+        press(&mut app, typed("g"))?;
+        assert_eq!(selected(&app), Some(29));
+        // The late `g` starts a new sequence.
+        press(&mut app, typed("g"))?;
+        assert_eq!(selected(&app), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn export_view_scrolls_through_the_whole_script() -> Result<()> {
+        let values: Vec<String> = (0..40)
+            .map(|i| format!("/opt/toolchain-{i}/include"))
+            .collect();
+        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+        let mut app = cpath_app(&values)?;
+        app.add_env_var(Entry::CC("gcc-13".to_string()))?;
+        app.active_view = View::Export;
+        let mut terminal = Terminal::new(TestBackend::new(80, 24))?;
+        terminal.draw(|f| crate::tui::ui::draw_main_ui(f, &mut app))?;
+        let scroll = app.text_scroll[&View::Export];
+        assert_eq!(scroll.offset, 0);
+        assert!(scroll.indicator().starts_with(" (1/"));
+
+        press(&mut app, typed("jj"))?;
+        assert_eq!(app.text_scroll[&View::Export].offset, 2);
+        press(&mut app, typed("G"))?;
+        terminal.draw(|f| crate::tui::ui::draw_main_ui(f, &mut app))?;
+        let bottom = app.text_scroll[&View::Export];
+        assert!(bottom.offset > 2);
+        press(&mut app, typed("j"))?;
+        assert_eq!(app.text_scroll[&View::Export], bottom);
+        press(&mut app, typed("gg"))?;
+        assert_eq!(app.text_scroll[&View::Export].offset, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn export_view_scrolls_to_the_selected_var_once() -> Result<()> {
+        let values: Vec<String> = (0..80)
+            .map(|i| format!("/opt/toolchain-{i}/include"))
+            .collect();
+        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+        let mut app = cpath_app(&values)?;
+        for (i, flag) in ["-O2", "-g", "-Wall"].into_iter().enumerate() {
+            app.add_env_var(Entry::CustomScalar {
+                name: format!("MY_FLAG_{i}"),
+                value: flag.to_string(),
+            })?;
         }
-        View::Preview | View::Export | View::Help => (0, None),
-    };
+        app.active_view = View::Export;
+        let mut terminal = Terminal::new(TestBackend::new(80, 30))?;
+        let shown = |terminal: &Terminal<TestBackend>, text: &str| {
+            let buffer = terminal.backend().buffer();
+            (0..buffer.area.height).any(|y| {
+                let row: String = (0..buffer.area.width)
+                    .map(|x| buffer.get(x, y).symbol.as_str())
+                    .collect();
+                row.contains(text)
+            })
+        };
+        terminal.draw(|f| crate::tui::ui::draw_main_ui(f, &mut app))?;
+        assert!(shown(&terminal, "CPATH=\"/opt/toolchain-0"));
+        assert!(!shown(&terminal, "MY_FLAG_2="));
 
-    if let Some(state) = state {
-        if len > 0 {
-            state.select(Some(len - 1));
+        app.selected_var_name = Some("MY_FLAG_2".to_string());
+        terminal.draw(|f| crate::tui::ui::draw_main_ui(f, &mut app))?;
+        assert!(shown(&terminal, "MY_FLAG_2="));
+        // Scrolling away is not undone by the next frame.
+        press(&mut app, typed("gg"))?;
+        terminal.draw(|f| crate::tui::ui::draw_main_ui(f, &mut app))?;
+        assert!(shown(&terminal, "CPATH=\"/opt/toolchain-0"));
+        Ok(())
+    }
+
+    #[test]
+    fn ctrl_d_and_ctrl_u_move_half_the_drawn_rows() -> Result<()> {
+        let values: Vec<String> = (0..30).map(|i| format!("/opt/{i}")).collect();
+        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+        let mut app = cpath_app(&values)?;
+        app.parts_list_state.select(Some(0));
+        let area = ratatui::layout::Rect::new(0, 0, 40, 12);
+        app.list_areas
+            .entry(View::Parts)
+            .or_default()
+            .update(area, Some(0), 30);
+
+        press(&mut app, [ctrl('d'), ctrl('d')])?;
+        assert_eq!(app.parts_list_state.selected(), Some(10));
+        press(&mut app, [ctrl('u')])?;
+        assert_eq!(app.parts_list_state.selected(), Some(5));
+        press(&mut app, [ctrl('u'), ctrl('u')])?;
+        assert_eq!(app.parts_list_state.selected(), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn space_switches_a_part_off_and_x_marks_it() -> Result<()> {
+        let mut app = cpath_app(&["/usr/a", "/opt/b"])?;
+        app.parts_list_state.select(Some(0));
+        let key = |c| KeyEvent::from(KeyCode::Char(c));
+        press(&mut app, [key(' ')])?;
+        let parts = select::current_var_parts(&app, "CPATH");
+        assert!(!parts[0].is_enabled());
+        assert!(app.marked_parts().is_empty());
+
+        press(&mut app, [key(' '), key('x')])?;
+        assert!(select::current_var_parts(&app, "CPATH")[0].is_enabled());
+        assert_eq!(app.marked_parts().into_iter().collect::<Vec<_>>(), [0]);
+        Ok(())
+    }
+
+    #[test]
+    fn marks_hidden_by_the_filter_keep_their_parts() -> Result<()> {
+        let mut app = cpath_app(&["/usr/a", "/opt/b", "/usr/c", "/opt/d", "/usr/e"])?;
+        let key = |c| KeyEvent::from(KeyCode::Char(c));
+        press(&mut app, [key('j'), key('j'), key('x')])?;
+        app.parts_filter = "opt".to_string();
+        app.parts_list_state.select(Some(0));
+        press(&mut app, [key('x'), key('j'), key('x')])?;
+        assert_eq!(
+            app.marked_parts().into_iter().collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+
+        // Only the marked parts on screen are deleted; /usr/c stays marked.
+        press(&mut app, [key('d')])?;
+        app.parts_filter.clear();
+        assert_eq!(cpath(&app), ["/usr/a", "/usr/c", "/usr/e"]);
+        assert_eq!(app.marked_parts().into_iter().collect::<Vec<_>>(), [1]);
+        assert_eq!(db::load_profile(&app.conn, "gcc-13")?.entries.len(), 3);
+
+        press(&mut app, [key('y')])?;
+        assert_eq!(cpath(&app), ["/usr/a", "/usr/c", "/usr/c", "/usr/e"]);
+        assert!(app.marked_parts().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn a_marked_range_is_moved_as_one_block() -> Result<()> {
+        let mut app = cpath_app(&["/a", "/b", "/c", "/d", "/e", "/f"])?;
+        let key = |c| KeyEvent::from(KeyCode::Char(c));
+        press(&mut app, [key('j'), key('v'), key('j'), key('j'), key('v')])?;
+        assert_eq!(
+            app.marked_parts().into_iter().collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+
+        press(&mut app, [key('m'), KeyEvent::from(KeyCode::Esc)])?;
+        assert_eq!(cpath(&app), ["/a", "/b", "/c", "/d", "/e", "/f"]);
+        assert!(app.marked_parts().is_empty());
+
+        press(&mut app, [key('k'), key('k'), key('v'), key('j')])?;
+        press(&mut app, [key('j'), key('m')])?;
+        assert_eq!(cpath(&app), ["/a", "/e", "/f"]);
+        app.parts_list_state.select(Some(2));
+        press(&mut app, [key('p')])?;
+        assert_eq!(cpath(&app), ["/a", "/e", "/b", "/c", "/d", "/f"]);
+        assert!(app.holding.is_none());
+        let saved = db::load_profile(&app.conn, "gcc-13")?;
+        assert_eq!(saved.entries.len(), 6);
+        Ok(())
+    }
+
+    fn add_items(app: &mut AppState, values: &[&str]) -> Result<()> {
+        for value in values {
+            let mut item = CatalogItem {
+                id: None,
+                kind: crate::config::ItemKind::Path,
+                value: value.to_string(),
+                program: None,
+                version: None,
+                tags: Vec::new(),
+                use_count: 0,
+                last_used_at: None,
+            };
+            db::save_item(&app.conn, &mut item, false)?;
         }
+        app.refresh_items()
+    }
+
+    #[test]
+    fn marked_items_are_dropped_in_display_order() -> Result<()> {
+        let mut app = cpath_app(&["/x", "/y"])?;
+        add_items(&mut app, &["/a", "/b", "/c", "/d", "/e"])?;
+        app.active_view = View::Items;
+        app.items_list_state.select(Some(2));
+        let key = |c| KeyEvent::from(KeyCode::Char(c));
+        // /c, then /a, then the range /d../e.
+        press(&mut app, [key(' '), key('k'), key('k'), key(' ')])?;
+        press(&mut app, [key('j'), key('j'), key('j'), key('v'), key('j')])?;
+        press(&mut app, [key('v'), key('m')])?;
+
+        app.active_view = View::Parts;
+        app.parts_list_state.select(Some(1));
+        press(&mut app, [key('p')])?;
+        assert_eq!(cpath(&app), ["/x", "/a", "/c", "/d", "/e", "/y"]);
+        assert_eq!(app.status, "dropped 4 items into CPATH");
+        assert!(app.holding.is_none());
+        assert!(app.item_marks.is_empty());
+        assert_eq!(db::load_profile(&app.conn, "gcc-13")?.entries.len(), 6);
+        assert_eq!(app.items.iter().filter(|it| it.use_count == 1).count(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn several_items_cannot_drop_into_a_scalar() -> Result<()> {
+        let mut app = cpath_app(&[])?;
+        add_items(&mut app, &["gcc", "clang"])?;
+        app.selected_var_name = Some("CC".to_string());
+        app.active_view = View::Items;
+        let key = |c| KeyEvent::from(KeyCode::Char(c));
+        app.items_list_state.select(Some(0));
+        press(&mut app, [key(' '), key('j'), key(' '), key('p')])?;
+        assert_eq!(app.status, "CC holds one value, pick a single item for it");
+        assert!(select::current_var_parts(&app, "CC").is_empty());
+        assert!(!app.item_marks.is_empty());
+
+        assert_eq!(
+            dropped_status("CPATH", 3, 2),
+            "dropped 3 items into CPATH, skipped 2 that cannot be parts of it"
+        );
+        assert_eq!(dropped_status("CPATH", 1, 0), "dropped 1 item into CPATH");
+        Ok(())
+    }
+
+    #[test]
+    fn filters_match_fuzzily_best_first() -> Result<()> {
+        let mut app = cpath_app(&["/usr/include", "/opt/bin/cc", "/src/inc"])?;
+        app.active_view = View::Vars;
+        press(&mut app, typed("/ldlib"))?;
+        let rows = select::compute_var_rows(&app);
+        assert_eq!(rows[0].name, "LD_LIBRARY_PATH");
+
+        // Parts keep their order, scattered matches included.
+        app.parts_filter = "inc".to_string();
+        let parts = select::current_var_parts(&app, "CPATH");
+        assert_eq!(select::visible_part_indices(&app, &parts), [0, 1, 2]);
+        app.parts_filter = "incl".to_string();
+        assert_eq!(select::visible_part_indices(&app, &parts), [0]);
+        Ok(())
+    }
+
+    #[test]
+    fn up_and_down_recall_commands_from_an_empty_prompt() -> Result<()> {
+        let mut app = test_app()?;
+        let enter = KeyEvent::from(KeyCode::Enter);
+        let (up, down) = (KeyEvent::from(KeyCode::Up), KeyEvent::from(KeyCode::Down));
+        for cmd in [":vars", ":parts", ":parts"] {
+            press(&mut app, typed(cmd))?;
+            press(&mut app, [enter])?;
+        }
+        press(&mut app, typed(":"))?;
+        press(&mut app, [up])?;
+        assert_eq!(app.command_input.as_str(), "parts");
+        press(&mut app, [up, up])?;
+        assert_eq!(app.command_input.as_str(), "vars");
+        press(&mut app, [down])?;
+        assert_eq!(app.command_input.as_str(), "parts");
+        press(&mut app, [down])?;
+        assert_eq!(app.command_input.as_str(), "");
+
+        // A recalled command runs as it was.
+        press(&mut app, [up, up, enter])?;
+        assert_eq!(app.active_view, View::Vars);
+        Ok(())
+    }
+
+    #[test]
+    fn rejected_commands_are_not_recalled() -> Result<()> {
+        let mut app = test_app()?;
+        let enter = KeyEvent::from(KeyCode::Enter);
+        press(&mut app, typed(":vars"))?;
+        press(&mut app, [enter])?;
+        press(&mut app, typed(":new-profile"))?;
+        press(&mut app, [enter])?;
+        assert!(matches!(app.input_mode, InputMode::Command));
+
+        app.command_input.set(String::new());
+        press(&mut app, [KeyEvent::from(KeyCode::Up)])?;
+        assert_eq!(app.command_input.as_str(), "vars");
+        Ok(())
+    }
+
+    #[test]
+    fn typed_text_keeps_up_for_suggestions_and_ctrl_p_for_history() -> Result<()> {
+        let mut app = test_app()?;
+        press(&mut app, typed(":vars"))?;
+        press(&mut app, [KeyEvent::from(KeyCode::Enter)])?;
+
+        press(&mut app, typed(":t"))?;
+        assert!(app.command_suggestions.len() > 1);
+        press(&mut app, [KeyEvent::from(KeyCode::Down)])?;
+        assert_eq!(app.command_selected, 1);
+        press(&mut app, [KeyEvent::from(KeyCode::Up)])?;
+        assert_eq!((app.command_input.as_str(), app.command_selected), ("t", 0));
+        // Ctrl+N has nothing to go forward to yet.
+        press(&mut app, [ctrl('n')])?;
+        assert_eq!(app.command_input.as_str(), "t");
+
+        press(&mut app, [ctrl('p')])?;
+        assert_eq!(app.command_input.as_str(), "vars");
+        // Editing the recalled command hands Up back to the suggestions.
+        press(&mut app, [KeyEvent::from(KeyCode::Backspace)])?;
+        press(&mut app, [KeyEvent::from(KeyCode::Up)])?;
+        assert_eq!(app.command_input.as_str(), "var");
+        press(&mut app, [ctrl('n')])?;
+        assert_eq!(app.command_input.as_str(), "var");
+        Ok(())
     }
 }
@@ -0,0 +1,284 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fmt;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// A single line of editable text with a cursor, used by every text input of
+/// the TUI. The cursor moves by grapheme, so `é` written as `e` plus a
+/// combining accent is stepped over (and deleted) as one character.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineInput {
+    text: String,
+    /// Byte offset into `text`, always on a grapheme boundary.
+    cursor: usize,
+}
+
+impl LineInput {
+    /// `text` with the cursor at its end.
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        let cursor = text.len();
+        Self { text, cursor }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Replaces the text and puts the cursor at its end.
+    pub fn set(&mut self, text: impl Into<String>) {
+        *self = Self::new(text);
+    }
+
+    pub fn clear(&mut self) {
+        self.set(String::new());
+    }
+
+    /// Terminal columns between the start of the text and the cursor, wide
+    /// characters counting twice.
+    pub fn cursor_column(&self) -> u16 {
+        self.text[..self.cursor].width() as u16
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+        // A combining mark joins the grapheme before it.
+        self.cursor = self.boundary_at_or_after(self.cursor);
+    }
+
+    /// Inserts pasted `text` at the cursor as one line, see `single_line`.
+    pub fn paste(&mut self, text: &str) {
+        let text = single_line(text);
+        self.text.insert_str(self.cursor, &text);
+        self.cursor = self.boundary_at_or_after(self.cursor + text.len());
+    }
+
+    /// Deletes the grapheme before the cursor.
+    pub fn backspace(&mut self) {
+        let start = self.prev_boundary();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Deletes the grapheme under the cursor.
+    pub fn delete(&mut self) {
+        let end = self.next_boundary();
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    /// Deletes the word before the cursor and the blanks after it, like
+    /// Ctrl+W in a shell.
+    pub fn delete_word(&mut self) {
+        let start = self.text[..self.cursor]
+            .trim_end()
+            .trim_end_matches(|c: char| !c.is_whitespace())
+            .len();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Deletes everything from the cursor to the end.
+    pub fn kill_to_end(&mut self) {
+        self.text.truncate(self.cursor);
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.prev_boundary();
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = self.next_boundary();
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    /// Applies an editing key: a character, Backspace, Delete, Left, Right,
+    /// Home or End, or one of the readline shortcuts Ctrl+A/E (home/end),
+    /// Ctrl+U (clear), Ctrl+W (delete word) and Ctrl+K (kill to end). Returns
+    /// false for any other key, which the caller handles; other Ctrl chords
+    /// are never typed as letters.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL)
+            && !key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Char(c) if ctrl => match c.to_ascii_lowercase() {
+                'a' => self.move_home(),
+                'e' => self.move_end(),
+                'u' => self.clear(),
+                'w' => self.delete_word(),
+                'k' => self.kill_to_end(),
+                _ => return false,
+            },
+            KeyCode::Char(c) => self.insert(c),
+            KeyCode::Backspace => self.backspace(),
+            KeyCode::Delete => self.delete(),
+            KeyCode::Left => self.move_left(),
+            KeyCode::Right => self.move_right(),
+            KeyCode::Home => self.move_home(),
+            KeyCode::End => self.move_end(),
+            _ => return false,
+        }
+        true
+    }
+
+    fn prev_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |g| self.cursor + g.len())
+    }
+
+    fn boundary_at_or_after(&self, at: usize) -> usize {
+        self.text
+            .grapheme_indices(true)
+            .map(|(i, _)| i)
+            .chain([self.text.len()])
+            .find(|&i| i >= at)
+            .unwrap_or(self.text.len())
+    }
+}
+
+/// `text` made fit for a single-line input: trailing line breaks are dropped,
+/// the ones in between and tabs become spaces, other control characters are
+/// removed.
+pub fn single_line(text: &str) -> String {
+    text.trim_end_matches(['\r', '\n'])
+        .lines()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .filter_map(|c| match c {
+            '\t' => Some(' '),
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect()
+}
+
+impl fmt::Display for LineInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl From<&str> for LineInput {
+    fn from(text: &str) -> Self {
+        Self::new(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typing_goes_in_at_the_cursor() {
+        let mut input = LineInput::new("/opt/bin");
+        input.move_home();
+        input.move_right();
+        for c in "usr/".chars() {
+            input.insert(c);
+        }
+        assert_eq!(input.as_str(), "/usr/opt/bin");
+        assert_eq!(input.cursor, 5);
+        input.move_end();
+        input.insert('/');
+        assert_eq!(input.as_str(), "/usr/opt/bin/");
+    }
+
+    #[test]
+    fn the_cursor_steps_over_multi_byte_characters() {
+        let mut input = LineInput::new("día😀x");
+        assert_eq!(input.cursor, input.as_str().len());
+        input.move_left();
+        input.move_left();
+        assert_eq!(&input.as_str()[input.cursor..], "😀x");
+        // The emoji is two columns wide, "día" three.
+        assert_eq!(input.cursor_column(), 3);
+        input.move_right();
+        assert_eq!(input.cursor_column(), 5);
+        input.backspace();
+        assert_eq!(input.as_str(), "díax");
+        input.move_home();
+        input.move_right();
+        input.delete();
+        assert_eq!(input.as_str(), "dax");
+        assert_eq!(input.cursor, 1);
+    }
+
+    #[test]
+    fn combining_marks_are_edited_with_their_base_character() {
+        let mut input = LineInput::new("e\u{301}");
+        input.move_left();
+        assert_eq!(input.cursor, 0);
+        input.move_end();
+        input.insert('a');
+        input.insert('\u{301}');
+        assert_eq!(input.cursor, input.as_str().len());
+        input.backspace();
+        assert_eq!(input.as_str(), "e\u{301}");
+        assert_eq!(input.cursor_column(), 1);
+    }
+
+    #[test]
+    fn edits_at_the_ends_are_no_ops() {
+        let mut input = LineInput::new("ab");
+        input.delete();
+        input.move_right();
+        assert_eq!((input.as_str(), input.cursor), ("ab", 2));
+        input.move_home();
+        input.backspace();
+        input.move_left();
+        assert_eq!((input.as_str(), input.cursor), ("ab", 0));
+        assert!(!input.handle_key(KeyEvent::from(KeyCode::Enter)));
+        assert!(input.handle_key(KeyEvent::from(KeyCode::End)));
+        assert_eq!(input.cursor, 2);
+    }
+
+    #[test]
+    fn pastes_are_inserted_as_one_line() {
+        let mut input = LineInput::new("/opt/bin");
+        input.move_home();
+        input.paste("/usr/local\x1b[0m/bin:\r\n");
+        assert_eq!(input.as_str(), "/usr/local[0m/bin:/opt/bin");
+        assert_eq!(input.cursor, "/usr/local[0m/bin:".len());
+        assert_eq!(single_line("-O2\n-g\t-pipe\n\n"), "-O2 -g -pipe");
+    }
+
+    #[test]
+    fn ctrl_w_deletes_the_word_before_the_cursor() {
+        let mut input = LineInput::new("use  gcc-13  ");
+        input.delete_word();
+        assert_eq!(input.as_str(), "use  ");
+        input.delete_word();
+        assert_eq!(input.as_str(), "");
+        input.delete_word();
+
+        let mut input = LineInput::new("theme dracula");
+        for _ in 0..4 {
+            input.move_left();
+        }
+        input.delete_word();
+        assert_eq!((input.as_str(), input.cursor), ("theme cula", 6));
+        input.kill_to_end();
+        assert_eq!(input.as_str(), "theme ");
+    }
+}
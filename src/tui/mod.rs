@@ -1,9 +1,13 @@
 pub mod app;
+pub mod clipboard;
 pub mod commands;
 pub mod daisyui_themes;
 pub mod dialogs;
 pub mod editor;
+pub mod fuzzy;
 pub mod input;
+pub mod line_input;
+pub mod mouse;
 pub mod select;
 pub mod state;
 pub mod theme;
@@ -11,6 +15,6 @@ pub mod ui;
 pub mod util;
 pub mod view;
 
-pub fn run() -> anyhow::Result<()> {
-    app::run()
+pub fn run(expand_home_at_save: bool) -> anyhow::Result<()> {
+    app::run(expand_home_at_save)
 }
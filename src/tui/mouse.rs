@@ -0,0 +1,167 @@
+use crate::tui::view::View;
+use crossterm::event::{Event, MouseButton, MouseEventKind};
+use ratatui::layout::Rect;
+use unicode_width::UnicodeWidthStr;
+
+/// A list as drawn by the last frame, to map clicks to rows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ListArea {
+    /// The list's area, borders included.
+    pub area: Rect,
+    /// Index of the first row shown.
+    pub offset: usize,
+}
+
+impl ListArea {
+    /// Scrolls the list for `len` one-line rows drawn into the bordered `area`
+    /// with `selected` highlighted: rows stay put until the selection leaves
+    /// them, then scroll just far enough to show it. The list is drawn from
+    /// `offset` on, so clicks always map to the rows on screen.
+    pub fn update(&mut self, area: Rect, selected: Option<usize>, len: usize) {
+        self.area = area;
+        let height = area.height.saturating_sub(2) as usize;
+        if height == 0 || len == 0 || area.width < 3 {
+            return;
+        }
+        let offset = self.offset.min(len - 1);
+        let end = (offset + height).min(len);
+        let selected = selected.unwrap_or(0).min(len - 1);
+        self.offset = if selected >= end {
+            offset.max(selected + 1 - height)
+        } else {
+            offset.min(selected)
+        };
+    }
+
+    /// How many rows fit between the borders.
+    pub fn rows(&self) -> usize {
+        self.area.height.saturating_sub(2) as usize
+    }
+
+    /// The row under the cell at `column`, `row`, which may be past the last
+    /// one.
+    pub fn row_at(&self, column: u16, row: u16) -> Option<usize> {
+        line_at(self.area, column, row).map(|line| self.offset + line as usize)
+    }
+}
+
+/// `area` without its borders.
+fn inner(area: Rect) -> Rect {
+    Rect {
+        x: area.x.saturating_add(1),
+        y: area.y.saturating_add(1),
+        width: area.width.saturating_sub(2),
+        height: area.height.saturating_sub(2),
+    }
+}
+
+fn contains(area: Rect, column: u16, row: u16) -> bool {
+    (area.x..area.x + area.width).contains(&column) && (area.y..area.y + area.height).contains(&row)
+}
+
+/// The header tab of every view, as drawn on the first line of `area`.
+pub fn view_tabs(area: Rect) -> Vec<(View, Rect)> {
+    let mut x = area.x;
+    View::ALL
+        .iter()
+        .map(|&view| {
+            let width = tab_label(view).width() as u16;
+            let tab = Rect {
+                x,
+                y: area.y,
+                width: width.min((area.x + area.width).saturating_sub(x)),
+                height: area.height.min(1),
+            };
+            x = x.saturating_add(width);
+            (view, tab)
+        })
+        .collect()
+}
+
+pub fn tab_label(view: View) -> String {
+    format!(" {} ", view.title())
+}
+
+/// The view whose header tab is at `column`, `row`.
+pub fn view_at(header: Rect, column: u16, row: u16) -> Option<View> {
+    view_tabs(header)
+        .into_iter()
+        .find(|(_, tab)| contains(*tab, column, row))
+        .map(|(view, _)| view)
+}
+
+/// The line inside the bordered `area` holding the cell at `column`, `row`.
+pub fn line_at(area: Rect, column: u16, row: u16) -> Option<u16> {
+    let inner = inner(area);
+    contains(inner, column, row).then(|| row - inner.y)
+}
+
+/// The line inside the bordered `area` a left click in `event` landed on.
+pub fn clicked_line(event: &Event, area: Rect) -> Option<u16> {
+    match event {
+        Event::Mouse(m) if m.kind == MouseEventKind::Down(MouseButton::Left) => {
+            line_at(area, m.column, m.row)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_scroll_only_as_far_as_the_selection_needs() {
+        let mut area = ListArea::default();
+        let len = 20;
+        // Five rows fit. Down past the bottom, back up inside, then above the
+        // top, and jumps.
+        for (selected, offset) in [
+            (0, 0),
+            (4, 0),
+            (5, 1),
+            (9, 5),
+            (7, 5),
+            (4, 4),
+            (0, 0),
+            (19, 15),
+            (10, 10),
+            (15, 11),
+            (14, 11),
+        ] {
+            area.update(Rect::new(0, 0, 10, 7), Some(selected), len);
+            assert_eq!(area.offset, offset, "selected {selected}");
+        }
+        // A shorter list after a long one.
+        area.update(Rect::new(0, 0, 10, 7), Some(2), 3);
+        assert_eq!(area.offset, 2);
+    }
+
+    #[test]
+    fn clicks_map_to_rows_of_scrolled_lists() {
+        let mut list = ListArea::default();
+        list.update(Rect::new(2, 5, 20, 6), Some(9), 30);
+        assert_eq!(list.offset, 6);
+        assert_eq!(list.row_at(3, 6), Some(6));
+        assert_eq!(list.row_at(20, 9), Some(9));
+        // Borders and cells outside are not rows.
+        assert_eq!(list.row_at(3, 5), None);
+        assert_eq!(list.row_at(2, 6), None);
+        assert_eq!(list.row_at(3, 10), None);
+    }
+
+    #[test]
+    fn header_tabs_are_laid_out_in_order() {
+        let header = Rect::new(0, 0, 200, 2);
+        assert_eq!(view_at(header, 0, 0), Some(View::Profiles));
+        assert_eq!(view_at(header, 9, 0), Some(View::Profiles));
+        assert_eq!(view_at(header, 10, 0), Some(View::Vars));
+        assert_eq!(view_at(header, 10, 1), None);
+        let (_, help) = view_tabs(header)[8];
+        assert_eq!(
+            view_at(header, help.x + help.width - 1, 0),
+            Some(View::Help)
+        );
+        assert_eq!(view_at(header, help.x + help.width, 0), None);
+    }
+}
@@ -1,6 +1,10 @@
-use crate::config::{Entry, PathEntry, VarKind};
-use crate::tui::state::{AppState, EditorStyle, VarTypeOption};
+use crate::config::{
+    self, string_builtin_entry, CatalogItem, Entry, EnvProfile, ItemKind, VarKind, VarTypeOption,
+};
+use crate::tui::fuzzy;
+use crate::tui::state::{AppState, EditorStyle, ItemSort};
 use ratatui::widgets::ListState;
+use std::cmp::Reverse;
 
 #[derive(Clone)]
 pub struct VarRow {
@@ -8,6 +12,11 @@ pub struct VarRow {
     pub kind: VarKind,
     pub separator: String,
     pub count: usize,
+    /// Parts inherited from the profiles the active one extends.
+    pub inherited: usize,
+    /// False for vars used by the profile that have neither a builtin nor a
+    /// custom definition (e.g. after the definition was deleted).
+    pub defined: bool,
 }
 
 pub fn clamp_list_state(state: &mut ListState, len: usize) {
@@ -22,6 +31,17 @@ pub fn clamp_list_state(state: &mut ListState, len: usize) {
 
 pub fn compute_var_rows(app: &AppState) -> Vec<VarRow> {
     let profile = &app.profiles[app.active_profile_index];
+    let inherited: Vec<Entry> = app
+        .inherited_entries()
+        .into_iter()
+        .map(|(_, e)| e)
+        .collect();
+    let inherited_count = |name: &str| {
+        inherited
+            .iter()
+            .filter(|e| e.var_name().as_ref() == name)
+            .count()
+    };
     let mut rows: Vec<VarRow> = app
         .var_options
         .iter()
@@ -36,38 +56,68 @@ pub fn compute_var_rows(app: &AppState) -> Vec<VarRow> {
                 kind: o.kind.clone(),
                 separator: o.separator.clone(),
                 count,
+                inherited: inherited_count(&o.name),
+                defined: true,
             }
         })
         .collect();
 
-    // Add any unknown vars that exist in the profile but have no definition row.
-    for e in &profile.entries {
+    // Add any unknown vars that exist in the profile (or its parents) but have
+    // no definition row.
+    for e in profile.entries.iter().chain(&inherited) {
         let name = e.var_name().into_owned();
         if rows.iter().any(|r| r.name == name) {
             continue;
         }
         rows.push(VarRow {
             name: name.clone(),
-            kind: VarKind::List,
+            kind: match e.plain() {
+                Entry::CustomScalar { .. } => VarKind::Scalar,
+                _ => VarKind::List,
+            },
             separator: e.separator().into_owned(),
             count: profile
                 .entries
                 .iter()
                 .filter(|x| x.var_name().as_ref() == name)
                 .count(),
+            inherited: inherited_count(&name),
+            defined: false,
         });
     }
 
     rows.sort_by(|a, b| a.name.cmp(&b.name));
     if !app.vars_filter.is_empty() {
-        let q = app.vars_filter.to_lowercase();
-        rows.retain(|r| r.name.to_lowercase().contains(&q));
+        rows = fuzzy::filter_sorted(rows, &app.vars_filter, |r| r.name.clone());
     }
     rows
 }
 
+/// The Defs view rows: every var definition by name, filtered.
+pub fn visible_defs(app: &AppState) -> Vec<VarTypeOption> {
+    let mut defs = app.var_options.clone();
+    defs.sort_by(|a, b| a.name.cmp(&b.name));
+    if !app.defs_filter.is_empty() {
+        defs = fuzzy::filter_sorted(defs, &app.defs_filter, |d| d.name.clone());
+    }
+    defs
+}
+
+/// The custom definition selected in the Defs view, if the selection is one.
+pub fn selected_custom_def(app: &AppState) -> Option<String> {
+    let sel = app.defs_list_state.selected()?;
+    let name = visible_defs(app).get(sel)?.name.clone();
+    app.custom_var_defs
+        .iter()
+        .any(|d| d.name == name)
+        .then_some(name)
+}
+
 pub fn current_var_parts(app: &AppState, var_name: &str) -> Vec<Entry> {
-    let profile = &app.profiles[app.active_profile_index];
+    var_parts(&app.profiles[app.active_profile_index], var_name)
+}
+
+pub fn var_parts(profile: &EnvProfile, var_name: &str) -> Vec<Entry> {
     profile
         .entries
         .iter()
@@ -76,33 +126,118 @@ pub fn current_var_parts(app: &AppState, var_name: &str) -> Vec<Entry> {
         .collect()
 }
 
+/// Indices of the `parts` the Parts filter shows. Unlike other lists they
+/// are not sorted by how well they match: their order is the export order.
 pub fn visible_part_indices(app: &AppState, parts: &[Entry]) -> Vec<usize> {
     let mut indices: Vec<usize> = (0..parts.len()).collect();
     if !app.parts_filter.is_empty() {
-        let q = app.parts_filter.to_lowercase();
-        indices.retain(|i| parts[*i].to_string().to_lowercase().contains(&q));
+        indices.retain(|i| fuzzy::score(&app.parts_filter, &parts[*i].to_string()).is_some());
     }
     indices
 }
 
+/// Splits the Items filter into the tags its `#tag` words ask for, lowercased,
+/// and the other words, which are matched as usual.
+pub fn split_tag_filter(filter: &str) -> (Vec<String>, String) {
+    let mut tags = Vec::new();
+    let mut rest = Vec::new();
+    for word in filter.split_whitespace() {
+        match word.strip_prefix('#') {
+            // A lone `#` is a tag still being typed.
+            Some("") => {}
+            Some(tag) => tags.push(tag.to_lowercase()),
+            None => rest.push(word),
+        }
+    }
+    (tags, rest.join(" "))
+}
+
+/// The Items view rows in order: `#tag` words of the filter keep the items
+/// with exactly that tag (ignoring case), the rest of it is matched fuzzily.
 pub fn visible_item_indices(app: &AppState) -> Vec<usize> {
     let mut indices: Vec<usize> = (0..app.items.len()).collect();
-    if !app.items_filter.is_empty() {
-        let q = app.items_filter.to_lowercase();
-        indices.retain(|i| {
-            let it = &app.items[*i];
-            it.value.to_lowercase().contains(&q)
-                || it.tags.iter().any(|t| t.to_lowercase().contains(&q))
-        });
+    let items = &app.items;
+    match app.item_sort {
+        ItemSort::Id => indices.sort_by_key(|i| items[*i].id),
+        // Ties keep id order: the sort is stable.
+        ItemSort::Value => indices.sort_by(|a, b| items[*a].value.cmp(&items[*b].value)),
+        ItemSort::Kind => indices.sort_by_key(|i| {
+            let it = &items[*i];
+            (it.kind == ItemKind::Text, &it.value)
+        }),
+        ItemSort::Tag => indices.sort_by_key(|i| {
+            let tag = items[*i].tags.first().map(|t| t.to_lowercase());
+            (tag.is_none(), tag, &items[*i].value)
+        }),
+        ItemSort::Recent => indices.sort_by_key(|i| {
+            let it = &items[*i];
+            (Reverse(it.last_used_at), it.id)
+        }),
+        ItemSort::MostUsed => indices.sort_by_key(|i| {
+            let it = &items[*i];
+            (Reverse(it.use_count), Reverse(it.last_used_at), it.id)
+        }),
     }
-    indices
+    let (tags, query) = split_tag_filter(&app.items_filter);
+    indices.retain(|i| {
+        tags.iter()
+            .all(|tag| items[*i].tags.iter().any(|t| t.to_lowercase() == *tag))
+    });
+    if query.is_empty() {
+        return indices;
+    }
+    // Items match fuzzily on their value or a tag. Longer queries also go
+    // through the full-text index, which finds word prefixes in the program
+    // and version too; items only it finds come after the scored ones.
+    let fts = if query.chars().count() >= 3 {
+        app.item_hits(&query)
+    } else {
+        Default::default()
+    };
+    let mut scored: Vec<(Option<i64>, usize)> = indices
+        .into_iter()
+        .filter_map(|i| {
+            let it = &items[i];
+            let score = std::iter::once(&it.value)
+                .chain(&it.tags)
+                .filter_map(|text| fuzzy::score(&query, text))
+                .max();
+            (score.is_some() || fts.contains(&it.id)).then_some((score, i))
+        })
+        .collect();
+    scored.sort_by_key(|(score, _)| Reverse(*score));
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+/// How well a profile called `name` with `tags` matches the Profiles filter,
+/// `None` if it does not: `#arm` looks for a tag containing "arm", anything
+/// else is fuzzily matched against the name. Case is ignored.
+pub fn profile_score(filter: &str, name: &str, tags: &[String]) -> Option<i64> {
+    match filter.to_lowercase().strip_prefix('#') {
+        Some(tag) => tags
+            .iter()
+            .any(|t| t.to_lowercase().contains(tag))
+            .then_some(0),
+        None => fuzzy::score(filter, name),
+    }
+}
+
+pub fn profile_matches(filter: &str, name: &str, tags: &[String]) -> bool {
+    profile_score(filter, name, tags).is_some()
 }
 
 pub fn visible_profile_indices(app: &AppState) -> Vec<usize> {
     let mut indices: Vec<usize> = (0..app.profiles.len()).collect();
     if !app.profiles_filter.is_empty() {
-        let q = app.profiles_filter.to_lowercase();
-        indices.retain(|i| app.profiles[*i].name.to_lowercase().contains(&q));
+        let mut scored: Vec<(i64, usize)> = indices
+            .into_iter()
+            .filter_map(|i| {
+                let p = &app.profiles[i];
+                Some((profile_score(&app.profiles_filter, &p.name, &p.tags)?, i))
+            })
+            .collect();
+        scored.sort_by_key(|(score, _)| Reverse(*score));
+        indices = scored.into_iter().map(|(_, i)| i).collect();
     }
     indices
 }
@@ -121,86 +256,50 @@ pub fn selected_item_index(app: &AppState) -> Option<usize> {
         .and_then(|i| indices.get(i).copied())
 }
 
+/// `preview_value` as the shell sees it once exported, with a leading `~`
+/// expanded to $HOME.
+pub fn shell_value(e: &Entry) -> String {
+    let value = preview_value(e);
+    match std::env::var("HOME") {
+        Ok(home) => crate::export::expand_tilde(&value, &home),
+        Err(_) => value,
+    }
+}
+
 pub fn preview_value(e: &Entry) -> String {
     match e {
-        Entry::Path(PathEntry { path, .. }) => path.clone(),
-        Entry::CPath(v)
-        | Entry::CInclude(v)
-        | Entry::CPlusInclude(v)
-        | Entry::OBJCInclude(v)
-        | Entry::CPPFlag(v)
-        | Entry::CFlag(v)
-        | Entry::CXXFlag(v)
-        | Entry::LDFlag(v)
-        | Entry::LibraryPath(v)
-        | Entry::LDLibraryPath(v)
-        | Entry::LDRunPath(v)
-        | Entry::RanLib(v)
-        | Entry::CC(v)
-        | Entry::CXX(v)
-        | Entry::AR(v)
-        | Entry::Strip(v)
-        | Entry::GCCExecPrefix(v)
-        | Entry::CollectGCCOptions(v)
-        | Entry::Lang(v) => v.clone(),
+        Entry::Path(pe) => pe.path.clone(),
+        string_builtin_entry!() => {
+            let (_, value) = e.builtin().expect("builtin entries have a builtin var");
+            value.clone()
+        }
         Entry::CustomScalar { value, .. } => value.clone(),
         Entry::CustomPart { value, .. } => value.clone(),
+        Entry::Annotated { entry, .. } => preview_value(entry),
+        Entry::Unset { name } => format!("unset {name}"),
+        Entry::Unknown(_) => "(unsupported entry)".to_string(),
     }
 }
 
+/// `value` as a part of `var_name`, `None` if the var holds a single value.
 pub fn make_part_entry(app: &AppState, var_name: &str, value: String) -> Option<Entry> {
-    // Builtins
-    match var_name {
-        "PATH" => {
-            return Some(Entry::Path(PathEntry {
-                path: value,
-                program: String::new(),
-                version: String::new(),
-            }))
-        }
-        "CPATH" => return Some(Entry::CPath(value)),
-        "C_INCLUDE_PATH" => return Some(Entry::CInclude(value)),
-        "CPLUS_INCLUDE_PATH" => return Some(Entry::CPlusInclude(value)),
-        "OBJC_INCLUDE_PATH" => return Some(Entry::OBJCInclude(value)),
-        "CPPFLAGS" => return Some(Entry::CPPFlag(value)),
-        "CFLAGS" => return Some(Entry::CFlag(value)),
-        "CXXFLAGS" => return Some(Entry::CXXFlag(value)),
-        "LDFLAGS" => return Some(Entry::LDFlag(value)),
-        "LIBRARY_PATH" => return Some(Entry::LibraryPath(value)),
-        "LD_LIBRARY_PATH" => return Some(Entry::LDLibraryPath(value)),
-        "LD_RUN_PATH" => return Some(Entry::LDRunPath(value)),
-        "RANLIB" => return Some(Entry::RanLib(value)),
-        "CC" => return Some(Entry::CC(value)),
-        "CXX" => return Some(Entry::CXX(value)),
-        "AR" => return Some(Entry::AR(value)),
-        "STRIP" => return Some(Entry::Strip(value)),
-        "GCC_EXEC_PREFIX" => return Some(Entry::GCCExecPrefix(value)),
-        "COLLECT_GCC_OPTIONS" => return Some(Entry::CollectGCCOptions(value)),
-        "LANG" => return Some(Entry::Lang(value)),
-        _ => {}
-    }
-
-    // Custom vars: look up definition; only List accepts parts.
-    if let Some(def) = app.custom_var_defs.iter().find(|d| d.name == var_name) {
-        return match def.kind {
-            VarKind::Scalar => Some(Entry::CustomScalar {
-                name: def.name.clone(),
-                value,
-            }),
-            VarKind::List => Some(Entry::CustomPart {
-                name: def.name.clone(),
-                value,
-                separator: def.separator.clone(),
-            }),
-        };
-    }
+    (var_option_for(app, var_name).kind == VarKind::List)
+        .then(|| config::make_entry(var_name, value, &app.custom_var_defs))
+}
 
-    // Unknown: treat as list-part with ':' separator.
-    Some(Entry::CustomPart {
-        name: var_name.to_string(),
-        value,
-        separator: ":".to_string(),
-    })
+/// `item` dropped as a part of `var_name`, `None` if the var holds a single
+/// value. A path item dropped into PATH keeps its program and version.
+pub fn make_part_entry_from_item(
+    app: &AppState,
+    var_name: &str,
+    item: &CatalogItem,
+) -> Option<Entry> {
+    let mut entry = make_part_entry(app, var_name, item.value.clone())?;
+    if let (Entry::Path(pe), ItemKind::Path) = (&mut entry, &item.kind) {
+        pe.program = item.program.clone();
+        pe.version = item.version.clone();
+    }
+    Some(entry)
 }
 
 pub fn var_option_for(app: &AppState, var_name: &str) -> VarTypeOption {
@@ -215,3 +314,87 @@ pub fn var_option_for(app: &AppState, var_name: &str) -> VarTypeOption {
         editor: EditorStyle::PartsList,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::items::build_item;
+    use anyhow::Result;
+    use rusqlite::Connection;
+
+    #[test]
+    fn path_items_keep_program_and_version_only_in_path() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let app = AppState::for_tests(conn, vec![EnvProfile::new("p")])?;
+        let item = build_item(
+            "/opt/gcc-13/bin",
+            ItemKind::Path,
+            Some("gcc"),
+            Some("13.2"),
+            "",
+        )?;
+
+        let Some(Entry::Path(pe)) = make_part_entry_from_item(&app, "PATH", &item) else {
+            panic!("not a PATH part");
+        };
+        assert_eq!(pe.path, "/opt/gcc-13/bin");
+        assert_eq!(pe.label().as_deref(), Some("gcc 13.2"));
+
+        let entry = make_part_entry_from_item(&app, "CPATH", &item);
+        assert!(matches!(entry, Some(Entry::CPath(v)) if v == "/opt/gcc-13/bin"));
+        assert!(make_part_entry_from_item(&app, "CC", &item).is_none());
+
+        let text = build_item("/opt/bin", ItemKind::Text, None, None, "")?;
+        let Some(Entry::Path(pe)) = make_part_entry_from_item(&app, "PATH", &text) else {
+            panic!("not a PATH part");
+        };
+        assert_eq!(pe.label(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn hash_words_filter_items_by_exact_tag() -> Result<()> {
+        assert_eq!(
+            split_tag_filter("#GCC opt  #x86 bin #"),
+            (
+                vec!["gcc".to_string(), "x86".to_string()],
+                "opt bin".to_string()
+            )
+        );
+
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        for (value, tags) in [
+            ("/opt/gcc-13/bin", "gcc,x86"),
+            ("/opt/gcc-arm/bin", "gcc-arm"),
+            ("/usr/lib/gcc", ""),
+            ("/opt/clang/bin", "clang,X86"),
+        ] {
+            let mut item = build_item(value, ItemKind::Path, None, None, tags)?;
+            db::save_item(&conn, &mut item, false)?;
+        }
+        let mut app = AppState::for_tests(conn, vec![EnvProfile::new("p")])?;
+        app.refresh_items()?;
+        let values = |app: &AppState| -> Vec<String> {
+            visible_item_indices(app)
+                .into_iter()
+                .map(|i| app.items[i].value.clone())
+                .collect()
+        };
+
+        // Tags match whole, not as a substring or fuzzily like "gcc" does.
+        app.items_filter = "gcc".to_string();
+        assert_eq!(values(&app).len(), 3);
+        app.items_filter = "#gcc".to_string();
+        assert_eq!(values(&app), ["/opt/gcc-13/bin"]);
+        app.items_filter = "#x86".to_string();
+        assert_eq!(values(&app), ["/opt/gcc-13/bin", "/opt/clang/bin"]);
+        app.items_filter = "#x86 clang".to_string();
+        assert_eq!(values(&app), ["/opt/clang/bin"]);
+        app.items_filter = "#".to_string();
+        assert_eq!(values(&app).len(), 4);
+        Ok(())
+    }
+}
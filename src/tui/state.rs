@@ -1,178 +1,526 @@
-use crate::config::{CatalogItem, CustomVarDef, Entry, EnvProfile, VarKind};
+use crate::config::{
+    self, BrokenProfile, CatalogItem, CustomVarDef, Entry, EnvProfile, HistoryEntry, ProfileMeta,
+    SeparatorOverrides, VarTypeOption,
+};
 use crate::db;
+use crate::dialect::Posix;
+use crate::error::BathError;
+use crate::export::{self, OperationMode};
+use crate::template;
+use crate::tui::clipboard::{Clipboard, SystemClipboard};
+use crate::tui::line_input::LineInput;
+use crate::tui::mouse::ListArea;
+use crate::tui::select;
 use crate::tui::theme::{BathConfig, Theme};
 use crate::tui::view::View;
+use crate::validate::{self, Issue};
 use anyhow::Result;
+use ratatui::layout::Rect;
 use ratatui::widgets::ListState;
 use rusqlite::Connection;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Instant;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum InputMode {
     Normal,
     Command,
     Search,
+    /// Typing a new part of the selected var, opened with `i` in the Parts
+    /// view.
+    QuickAdd,
+    /// Editing the tags of an item, opened with `t` in the Items view.
+    Tags,
+}
+
+/// Order of the Items view, applied before the filter.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ItemSort {
+    #[default]
+    Id,
+    Value,
+    /// Path items before text ones.
+    Kind,
+    /// By first tag; untagged items come last.
+    Tag,
+    Recent,
+    MostUsed,
+}
+
+impl ItemSort {
+    pub fn next(self) -> Self {
+        match self {
+            ItemSort::Id => ItemSort::Value,
+            ItemSort::Value => ItemSort::Kind,
+            ItemSort::Kind => ItemSort::Tag,
+            ItemSort::Tag => ItemSort::Recent,
+            ItemSort::Recent => ItemSort::MostUsed,
+            ItemSort::MostUsed => ItemSort::Id,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ItemSort::Id => "id",
+            ItemSort::Value => "value",
+            ItemSort::Kind => "kind",
+            ItemSort::Tag => "tag",
+            ItemSort::Recent => "recently used",
+            ItemSort::MostUsed => "most used",
+        }
+    }
 }
 
 #[derive(Clone)]
 pub enum Holding {
-    Item(CatalogItem),
-    Part {
+    /// Catalog items picked up together, in the order they were listed.
+    Items(Vec<CatalogItem>),
+    /// Parts picked up from `var` of `profile`, with the indices they had, in
+    /// order. They are only removed in memory until dropped, so the move is
+    /// saved as one write.
+    Parts {
+        profile: String,
         var: String,
-        from: usize,
-        entry: Entry,
+        parts: Vec<(usize, Entry)>,
     },
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-pub enum EditorStyle {
-    Single,
-    PathPart,
-    PartsList,
+/// What `:sort` orders the parts of a var by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartOrder {
+    Value,
+    /// The program of PATH parts, parts without one last.
+    Program,
+    /// The version of PATH parts, numbers compared as numbers so 9 comes
+    /// before 13; parts without one last.
+    Version,
+}
+
+impl PartOrder {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "value" => Ok(Self::Value),
+            "program" => Ok(Self::Program),
+            "version" => Ok(Self::Version),
+            _ => anyhow::bail!("unknown sort key: {s} (expected value, program or version)"),
+        }
+    }
+
+    /// What `entry` sorts by: whether it lacks the field, the field, then
+    /// its value.
+    fn key(self, entry: &Entry) -> (bool, Vec<VersionChunk>, String) {
+        let value = export::entry_value(entry);
+        let path = match entry.plain() {
+            Entry::Path(pe) => Some(pe),
+            _ => None,
+        };
+        let field = match self {
+            Self::Value => return (false, Vec::new(), value),
+            Self::Program => path.and_then(|pe| pe.program.as_deref()),
+            Self::Version => path.and_then(|pe| pe.version.as_deref()),
+        };
+        (
+            field.is_none(),
+            field.map(version_chunks).unwrap_or_default(),
+            value,
+        )
+    }
+}
+
+/// A run of digits or of other characters in a version.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum VersionChunk {
+    Number(u64),
+    Text(String),
+}
+
+fn version_chunks(s: &str) -> Vec<VersionChunk> {
+    let mut chunks = Vec::new();
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        let digits = c.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != digits)
+            .unwrap_or(rest.len());
+        let (run, tail) = rest.split_at(end);
+        chunks.push(match run.parse() {
+            Ok(n) if digits => VersionChunk::Number(n),
+            _ => VersionChunk::Text(run.to_string()),
+        });
+        rest = tail;
+    }
+    chunks
+}
+
+/// The tag editor of the Items view: the tags the item will be saved with.
+/// The tag being typed is in `command_input`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagEdit {
+    pub item: i64,
+    pub tags: Vec<String>,
+    /// The typed prefix Tab completes, and which completion it is at.
+    pub completing: Option<(String, usize)>,
+}
+
+impl TagEdit {
+    /// Adds the comma separated tags of `input` not there yet, ignoring case.
+    /// Returns whether there were any tags in it.
+    pub fn add(&mut self, input: &str) -> bool {
+        let typed = crate::items::parse_tags(input);
+        for tag in &typed {
+            if !contains_tag(&self.tags, tag) {
+                self.tags.push(tag.clone());
+            }
+        }
+        !typed.is_empty()
+    }
+
+    /// The tags of `catalog` starting with `prefix` that the item does not
+    /// have yet, in order and without repeats, all ignoring case.
+    pub fn completions(&self, prefix: &str, catalog: &[CatalogItem]) -> Vec<String> {
+        let prefix = prefix.trim().to_lowercase();
+        let mut found: Vec<String> = Vec::new();
+        for tag in catalog.iter().flat_map(|it| &it.tags) {
+            if tag.to_lowercase().starts_with(&prefix)
+                && !contains_tag(&self.tags, tag)
+                && !contains_tag(&found, tag)
+            {
+                found.push(tag.clone());
+            }
+        }
+        found.sort();
+        found
+    }
+}
+
+/// Whether `tags` has `tag`, ignoring case.
+fn contains_tag(tags: &[String], tag: &str) -> bool {
+    let tag = tag.to_lowercase();
+    tags.iter().any(|t| t.to_lowercase() == tag)
+}
+
+/// A value derived from the state that is too costly to compute every frame,
+/// such as a database query or a whole export. It is kept until `get` is
+/// asked for it with a different key, the inputs it is computed from.
+pub struct Memo<K, V> {
+    cached: RefCell<Option<(K, Rc<V>)>>,
+}
+
+impl<K, V> Default for Memo<K, V> {
+    fn default() -> Self {
+        Memo {
+            cached: RefCell::new(None),
+        }
+    }
+}
+
+impl<K: Clone + PartialEq, V> Memo<K, V> {
+    /// The value for `key`, computed with `compute` unless the last one was
+    /// computed for an equal key.
+    pub fn get(&self, key: &K, compute: impl FnOnce() -> V) -> Rc<V> {
+        if let Some((cached_key, value)) = &*self.cached.borrow() {
+            if cached_key == key {
+                return Rc::clone(value);
+            }
+        }
+        let value = Rc::new(compute());
+        *self.cached.borrow_mut() = Some((key.clone(), Rc::clone(&value)));
+        value
+    }
+}
+
+/// How far a text view (Preview, Export) is scrolled, and the size of the
+/// text and of the pane as the last frame drew them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextScroll {
+    /// The first row shown.
+    pub offset: usize,
+    rows: usize,
+    height: usize,
 }
 
+impl TextScroll {
+    /// Records a text of `rows` rows drawn into `height` rows, scrolling back
+    /// if the text got shorter so no page ends below it.
+    pub fn update(&mut self, rows: usize, height: usize) {
+        (self.rows, self.height) = (rows, height);
+        self.offset = self.offset.min(self.max_offset());
+    }
+
+    /// The offset showing the last page.
+    fn max_offset(&self) -> usize {
+        self.rows.saturating_sub(self.height)
+    }
+
+    /// Scrolls `delta` rows down, or up for a negative one, staying within
+    /// the text.
+    pub fn scroll(&mut self, delta: isize) {
+        let offset = self.offset.saturating_add_signed(delta);
+        self.offset = offset.min(self.max_offset());
+    }
+
+    /// Scrolls as little as needed to show the `count` rows from `first`,
+    /// or as many of them as fit.
+    pub fn reveal(&mut self, first: usize, count: usize) {
+        let last = first + count.min(self.height);
+        if first < self.offset {
+            self.offset = first;
+        } else if last > self.offset + self.height {
+            self.offset = (last - self.height).min(self.max_offset());
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// "(first shown row/rows)" for the title, empty while the text fits.
+    pub fn indicator(&self) -> String {
+        if self.rows <= self.height {
+            String::new()
+        } else {
+            format!(" ({}/{})", self.offset + 1, self.rows)
+        }
+    }
+}
+
+/// Rows marked with `space` or a `v` range, for an action on all of them.
 #[derive(Clone)]
-pub struct VarTypeOption {
-    pub name: String,
-    pub kind: VarKind,
-    pub separator: String,
-    pub editor: EditorStyle,
+pub struct Marks<K> {
+    marked: BTreeSet<K>,
+    /// Where a `v` range started and the marks from before it.
+    visual: Option<(K, BTreeSet<K>)>,
+}
+
+impl<K> Default for Marks<K> {
+    fn default() -> Self {
+        Self {
+            marked: BTreeSet::new(),
+            visual: None,
+        }
+    }
+}
+
+impl<K: Ord + Clone> Marks<K> {
+    pub fn is_empty(&self) -> bool {
+        self.marked.is_empty()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.marked.contains(key)
+    }
+
+    pub fn clear(&mut self) {
+        self.marked.clear();
+        self.visual = None;
+    }
+
+    pub fn toggle(&mut self, key: K) {
+        self.visual = None;
+        if !self.marked.remove(&key) {
+            self.marked.insert(key);
+        }
+    }
+
+    /// Starts a range at `anchor`, grown with `extend_visual`.
+    pub fn start_visual(&mut self, anchor: K) {
+        self.visual = Some((anchor.clone(), self.marked.clone()));
+        self.marked.insert(anchor);
+    }
+
+    pub fn visual_anchor(&self) -> Option<K> {
+        self.visual.as_ref().map(|(anchor, _)| anchor.clone())
+    }
+
+    /// Marks `range` on top of the marks from before `v`.
+    pub fn extend_visual(&mut self, range: impl IntoIterator<Item = K>) {
+        if let Some((_, before)) = &self.visual {
+            self.marked = before.iter().cloned().chain(range).collect();
+        }
+    }
+
+    /// Ends the `v` range, keeping what it marked.
+    pub fn end_visual(&mut self) {
+        self.visual = None;
+    }
+
+    /// Moves every mark to `f` of it, dropping it for `None`. A `v` range in
+    /// progress is ended.
+    fn remap(&mut self, f: impl Fn(&K) -> Option<K>) {
+        self.visual = None;
+        self.marked = self.marked.iter().filter_map(f).collect();
+    }
+}
+
+/// Marks of the Parts view, for `d`, `y` and `m` to act on together. They are
+/// indices into all of the var's parts, not into the filtered rows, so a
+/// filter hiding marked parts keeps them.
+#[derive(Clone, Default)]
+pub struct PartSelection {
+    /// Profile and var the marks belong to.
+    owner: Option<(String, String)>,
+    marks: Marks<usize>,
+}
+
+impl PartSelection {
+    /// Drops the marks unless they belong to `owner`.
+    fn adopt(&mut self, owner: (String, String)) {
+        if self.owner.as_ref() != Some(&owner) {
+            *self = Self {
+                owner: Some(owner),
+                ..Self::default()
+            };
+        }
+    }
+
+    pub fn unmark(&mut self, parts: &[usize]) {
+        self.marks.remap(|i| (!parts.contains(i)).then_some(*i));
+    }
+
+    /// Follows `parts` (ascending) being removed from the list.
+    pub fn removed(&mut self, parts: &[usize]) {
+        self.marks.remap(|&i| {
+            let before = parts.partition_point(|&p| p < i);
+            (parts.get(before) != Some(&i)).then_some(i - before)
+        });
+    }
+
+    /// Follows `count` parts being inserted at `at`.
+    pub fn inserted(&mut self, at: usize, count: usize) {
+        self.marks
+            .remap(|&i| Some(if i >= at { i + count } else { i }));
+    }
+
+    /// Follows the parts at `a` and `b` trading places.
+    pub fn swapped(&mut self, a: usize, b: usize) {
+        self.marks.remap(|&i| {
+            Some(match i {
+                i if i == a => b,
+                i if i == b => a,
+                i => i,
+            })
+        });
+    }
+}
+
+impl std::ops::Deref for PartSelection {
+    type Target = Marks<usize>;
+
+    fn deref(&self) -> &Marks<usize> {
+        &self.marks
+    }
+}
+
+impl std::ops::DerefMut for PartSelection {
+    fn deref_mut(&mut self) -> &mut Marks<usize> {
+        &mut self.marks
+    }
+}
+
+/// Commands run from the palette, oldest first, and how far Up/Down went
+/// back through them.
+#[derive(Default)]
+pub struct CommandHistory {
+    entries: Vec<String>,
+    /// The recalled entry, `None` while not recalling.
+    cursor: Option<usize>,
+    /// Where the history is kept between sessions, if anywhere.
+    path: Option<PathBuf>,
 }
 
-pub fn builtin_var_options() -> Vec<VarTypeOption> {
-    vec![
-        VarTypeOption {
-            name: "PATH".to_string(),
-            kind: VarKind::List,
-            separator: ":".to_string(),
-            editor: EditorStyle::PathPart,
-        },
-        // colon-separated lists
-        VarTypeOption {
-            name: "CPATH".to_string(),
-            kind: VarKind::List,
-            separator: ":".to_string(),
-            editor: EditorStyle::PartsList,
-        },
-        VarTypeOption {
-            name: "C_INCLUDE_PATH".to_string(),
-            kind: VarKind::List,
-            separator: ":".to_string(),
-            editor: EditorStyle::PartsList,
-        },
-        VarTypeOption {
-            name: "CPLUS_INCLUDE_PATH".to_string(),
-            kind: VarKind::List,
-            separator: ":".to_string(),
-            editor: EditorStyle::PartsList,
-        },
-        VarTypeOption {
-            name: "OBJC_INCLUDE_PATH".to_string(),
-            kind: VarKind::List,
-            separator: ":".to_string(),
-            editor: EditorStyle::PartsList,
-        },
-        VarTypeOption {
-            name: "LIBRARY_PATH".to_string(),
-            kind: VarKind::List,
-            separator: ":".to_string(),
-            editor: EditorStyle::PartsList,
-        },
-        VarTypeOption {
-            name: "LD_LIBRARY_PATH".to_string(),
-            kind: VarKind::List,
-            separator: ":".to_string(),
-            editor: EditorStyle::PartsList,
-        },
-        VarTypeOption {
-            name: "LD_RUN_PATH".to_string(),
-            kind: VarKind::List,
-            separator: ":".to_string(),
-            editor: EditorStyle::PartsList,
-        },
-        // space-separated lists
-        VarTypeOption {
-            name: "CPPFLAGS".to_string(),
-            kind: VarKind::List,
-            separator: " ".to_string(),
-            editor: EditorStyle::PartsList,
-        },
-        VarTypeOption {
-            name: "CFLAGS".to_string(),
-            kind: VarKind::List,
-            separator: " ".to_string(),
-            editor: EditorStyle::PartsList,
-        },
-        VarTypeOption {
-            name: "CXXFLAGS".to_string(),
-            kind: VarKind::List,
-            separator: " ".to_string(),
-            editor: EditorStyle::PartsList,
-        },
-        VarTypeOption {
-            name: "LDFLAGS".to_string(),
-            kind: VarKind::List,
-            separator: " ".to_string(),
-            editor: EditorStyle::PartsList,
-        },
-        // scalars
-        VarTypeOption {
-            name: "RANLIB".to_string(),
-            kind: VarKind::Scalar,
-            separator: "".to_string(),
-            editor: EditorStyle::Single,
-        },
-        VarTypeOption {
-            name: "CC".to_string(),
-            kind: VarKind::Scalar,
-            separator: "".to_string(),
-            editor: EditorStyle::Single,
-        },
-        VarTypeOption {
-            name: "CXX".to_string(),
-            kind: VarKind::Scalar,
-            separator: "".to_string(),
-            editor: EditorStyle::Single,
-        },
-        VarTypeOption {
-            name: "AR".to_string(),
-            kind: VarKind::Scalar,
-            separator: "".to_string(),
-            editor: EditorStyle::Single,
-        },
-        VarTypeOption {
-            name: "STRIP".to_string(),
-            kind: VarKind::Scalar,
-            separator: "".to_string(),
-            editor: EditorStyle::Single,
-        },
-        VarTypeOption {
-            name: "GCC_EXEC_PREFIX".to_string(),
-            kind: VarKind::Scalar,
-            separator: "".to_string(),
-            editor: EditorStyle::Single,
-        },
-        VarTypeOption {
-            name: "COLLECT_GCC_OPTIONS".to_string(),
-            kind: VarKind::Scalar,
-            separator: "".to_string(),
-            editor: EditorStyle::Single,
-        },
-        VarTypeOption {
-            name: "LANG".to_string(),
-            kind: VarKind::Scalar,
-            separator: "".to_string(),
-            editor: EditorStyle::Single,
-        },
-    ]
+impl CommandHistory {
+    /// How many commands are kept.
+    pub const LIMIT: usize = 100;
+
+    /// The history stored at `path`; empty if there is none or it cannot be
+    /// read.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = std::fs::read_to_string(&path)
+            .map(|text| text.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        let mut history = Self {
+            entries,
+            cursor: None,
+            path: Some(path),
+        };
+        history.entries.retain(|e| !e.trim().is_empty());
+        history.truncate();
+        history
+    }
+
+    /// Whether Up/Down are going through the history.
+    pub fn is_recalling(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    /// Records `cmd` as the newest entry, unless it is the newest already, and
+    /// writes the history back to its file.
+    pub fn push(&mut self, cmd: &str) -> Result<()> {
+        self.cursor = None;
+        let cmd = cmd.trim();
+        if cmd.is_empty() || self.entries.last().is_some_and(|last| last == cmd) {
+            return Ok(());
+        }
+        self.entries.push(cmd.to_string());
+        self.truncate();
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut text = self.entries.join("\n");
+        text.push('\n');
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Steps back to the next older command and returns it; stays at the
+    /// oldest.
+    pub fn older(&mut self) -> Option<&str> {
+        let i = match self.cursor {
+            Some(i) => i.saturating_sub(1),
+            None => self.entries.len().checked_sub(1)?,
+        };
+        self.cursor = Some(i);
+        Some(&self.entries[i])
+    }
+
+    /// Steps forward to the next newer command and returns it; `None` once
+    /// past the newest, which ends the recall.
+    pub fn newer(&mut self) -> Option<&str> {
+        let i = self.cursor? + 1;
+        self.cursor = (i < self.entries.len()).then_some(i);
+        self.cursor.map(|i| self.entries[i].as_str())
+    }
+
+    /// Ends the recall, e.g. once the recalled command is edited.
+    pub fn stop_recalling(&mut self) {
+        self.cursor = None;
+    }
+
+    fn truncate(&mut self) {
+        let excess = self.entries.len().saturating_sub(Self::LIMIT);
+        self.entries.drain(..excess);
+    }
 }
 
+pub use crate::config::EditorStyle;
+
 pub struct AppState {
     pub conn: Connection,
     pub profiles: Vec<EnvProfile>,
+    /// Stored profiles whose entries cannot be read; listed but not editable.
+    pub broken_profiles: Vec<BrokenProfile>,
     pub active_profile_index: usize,
     pub profile_list_state: ListState,
     pub custom_var_defs: Vec<CustomVarDef>,
     pub var_options: Vec<VarTypeOption>,
+    /// Separators the user chose for builtin list vars, see `set_separator_override`.
+    pub separator_overrides: SeparatorOverrides,
 
     // UI state
     pub active_view: View,
@@ -185,6 +533,7 @@ pub struct AppState {
     pub defs_list_state: ListState,
     pub parts_list_state: ListState,
     pub items_list_state: ListState,
+    pub history_list_state: ListState,
 
     pub selected_var_name: Option<String>,
 
@@ -194,17 +543,105 @@ pub struct AppState {
     pub parts_filter: String,
     pub items_filter: String,
 
-    pub command_input: String,
+    pub command_input: LineInput,
     pub command_suggestions: Vec<String>,
     pub command_selected: usize,
+    /// The arguments the command being typed takes, see `commands`.
+    pub command_hint: Option<&'static str>,
+    pub command_history: CommandHistory,
     pub search_target: View,
 
     pub status: String,
+    /// Where `y`/`Y` of the Export view and `:copy-export` copy to.
+    pub clipboard: Box<dyn Clipboard>,
     pub holding: Option<Holding>,
+    /// Marks of the Parts view; read them with `marked_parts`.
+    pub part_selection: PartSelection,
+    /// Ids of the items marked in the Items view.
+    pub item_marks: Marks<i64>,
+
+    /// Mode picked with `o`/`:mode` for this session. When unset, the Preview and
+    /// Export views use the active profile's default mode (see `effective_mode`).
+    pub export_mode: Option<OperationMode>,
+    /// Drop repeated parts in the Preview and Export views, like `bath export
+    /// --dedup`; toggled with `:export-dedup`.
+    pub export_dedup: bool,
+    /// Replace a leading `~` with $HOME in values coming out of the editor.
+    pub expand_home_at_save: bool,
+    /// The environment bath was started in, read once; placeholders and
+    /// composed values in the views resolve against it.
+    pub env: HashMap<String, String>,
 
     pub items: Vec<CatalogItem>,
+    pub item_sort: ItemSort,
+    /// Show where the selected item was dropped in the details pane.
+    pub show_item_links: bool,
+    /// Recorded changes of the active profile, newest first (History view).
+    pub history: Vec<HistoryEntry>,
+
+    /// Where the last frame drew each view's list, for mouse clicks.
+    pub list_areas: HashMap<View, ListArea>,
+    /// How far the text views are scrolled.
+    pub text_scroll: HashMap<View, TextScroll>,
+    /// The var the Export view last scrolled to; selecting another one
+    /// scrolls to its lines once.
+    pub export_revealed: Option<String>,
+    /// Where the last frame drew the view tabs of the header.
+    pub header_area: Rect,
+    /// Time and cell of the last left click, to tell double-clicks.
+    pub last_click: Option<(Instant, u16, u16)>,
+    /// When `g` was pressed, waiting for the second `g` of `gg`.
+    pub pending_g: Option<Instant>,
+    /// The open tag editor, see `InputMode::Tags`.
+    pub tag_edit: Option<TagEdit>,
+    /// The stored metadata of every profile by name, for the number of
+    /// database changes it was read after; see `profile_meta`.
+    profile_metas: Memo<u64, HashMap<String, ProfileMeta>>,
+    /// The ids of the items the full-text index finds for a query, for the
+    /// number of database changes it was searched after; see `item_hits`.
+    item_hits: Memo<(String, u64), HashSet<Option<i64>>>,
+    /// The active profile expanded for the chain and the definitions it was
+    /// expanded from; see `expansion`.
+    expansion: Memo<(Chain, Vec<CustomVarDef>), Expansion>,
+    /// The issues of the active profile as it was and the definitions it was
+    /// validated with; see `profile_issues`.
+    issues: Memo<(EnvProfile, Vec<CustomVarDef>), Vec<Issue>>,
+    /// The Export view for the expansion, `export_dedup` and mode it was
+    /// generated with; see `export_view`.
+    export_view: Memo<(Rc<Expansion>, bool, OperationMode, SeparatorOverrides), ExportView>,
+}
+
+/// A profile's `profile_chain`, or the profile on its own and why its chain
+/// is broken; see `AppState::chain_at`.
+type Chain = (Vec<EnvProfile>, Option<String>);
+
+/// The active profile as it is exported, with what is derived from it.
+#[derive(PartialEq, Eq)]
+struct Expansion {
+    profile: EnvProfile,
+    /// See `export::reference_cycles`.
+    cycles: Vec<Vec<String>>,
+    /// The vars exported with the default of their definition, see
+    /// `export::defaulted_vars`.
+    defaulted: Vec<String>,
+    /// Why the profile is exported without what it inherits, if it is.
+    broken_chain: Option<String>,
 }
 
+/// What the Export view shows of the active profile.
+pub struct ExportView {
+    /// What `bath export` prints.
+    pub script: String,
+    /// What `bath unexport` prints.
+    pub undo: String,
+    /// How many duplicate parts `export_dedup` left out.
+    pub hidden: usize,
+}
+
+/// A profile index, the profile with its separators normalized and the vars
+/// that changed with the separator they now use.
+type SeparatorFix = (usize, EnvProfile, Vec<(String, String)>);
+
 impl AppState {
     pub fn new() -> Result<Self> {
         let conn = db::establish_connection()?;
@@ -216,9 +653,16 @@ impl AppState {
                         .unwrap();
                 (theme, crate::tui::theme::default_preset().to_string())
             });
-        let mut profiles = db::load_all_profiles(&conn)?;
+        let (mut profiles, broken_profiles) = db::load_profiles_checked(&conn)?;
         if profiles.is_empty() {
-            let default = EnvProfile::new("default");
+            // Saving over a broken profile would lose what is left of it.
+            let taken = |name: &str| broken_profiles.iter().any(|b| b.name == name);
+            let name = ["default".to_string(), "scratch".to_string()]
+                .into_iter()
+                .chain((2..).map(|n| format!("scratch-{n}")))
+                .find(|name| !taken(name))
+                .unwrap_or_default();
+            let default = EnvProfile::new(&name);
             db::save_profile(&conn, &default)?;
             profiles.push(default);
         }
@@ -236,10 +680,12 @@ impl AppState {
         let mut app = AppState {
             conn,
             profiles,
+            broken_profiles,
             active_profile_index: 0,
             profile_list_state,
             custom_var_defs: Vec::new(),
             var_options: Vec::new(),
+            separator_overrides: SeparatorOverrides::new(),
 
             active_view: View::Vars,
             input_mode: InputMode::Normal,
@@ -251,6 +697,7 @@ impl AppState {
             defs_list_state,
             parts_list_state,
             items_list_state,
+            history_list_state: ListState::default(),
 
             selected_var_name: None,
 
@@ -260,22 +707,124 @@ impl AppState {
             parts_filter: String::new(),
             items_filter: String::new(),
 
-            command_input: String::new(),
+            command_input: LineInput::default(),
             command_suggestions: Vec::new(),
             command_selected: 0,
+            command_hint: None,
+            command_history: crate::config::command_history_path()
+                .map(CommandHistory::load)
+                .unwrap_or_default(),
             search_target: View::Vars,
 
             status: String::new(),
+            clipboard: Box::new(SystemClipboard),
             holding: None,
+            part_selection: PartSelection::default(),
+            item_marks: Marks::default(),
+
+            export_mode: None,
+            export_dedup: false,
+            expand_home_at_save: false,
+            env: export::current_env(),
 
             items: Vec::new(),
+            item_sort: ItemSort::default(),
+            show_item_links: false,
+            history: Vec::new(),
+            list_areas: HashMap::new(),
+            text_scroll: HashMap::new(),
+            export_revealed: None,
+            header_area: Rect::default(),
+            last_click: None,
+            pending_g: None,
+            tag_edit: None,
+            profile_metas: Memo::default(),
+            item_hits: Memo::default(),
+            expansion: Memo::default(),
+            issues: Memo::default(),
+            export_view: Memo::default(),
         };
         app.refresh_var_options()?;
+        app.note_separator_mismatches();
         app.refresh_items()?;
         app.ensure_selected_var();
         Ok(app)
     }
 
+    /// The active profile and the var the Parts view shows.
+    fn parts_owner(&self) -> (String, String) {
+        let var = self.selected_var_name.as_deref().unwrap_or("PATH");
+        (
+            self.profiles[self.active_profile_index].name.clone(),
+            var.to_string(),
+        )
+    }
+
+    /// Whether the Parts view shows `var` of `profile`.
+    pub fn shows_parts_of(&self, profile: &str, var: &str) -> bool {
+        self.parts_owner() == (profile.to_string(), var.to_string())
+    }
+
+    /// Indices of the marked parts of the var the Parts view shows, ascending.
+    pub fn marked_parts(&self) -> BTreeSet<usize> {
+        if self.part_selection.owner == Some(self.parts_owner()) {
+            self.part_selection.marks.marked.clone()
+        } else {
+            BTreeSet::new()
+        }
+    }
+
+    /// The marks of the Parts view, dropped first if they were made in
+    /// another var or profile.
+    pub fn part_selection_mut(&mut self) -> &mut PartSelection {
+        let owner = self.parts_owner();
+        self.part_selection.adopt(owner);
+        &mut self.part_selection
+    }
+
+    /// Puts parts picked up with `m` back where they were taken from and
+    /// saves their profile, so a move is never left half done. Returns how
+    /// many went back.
+    pub fn put_back_held_parts(&mut self) -> Result<usize> {
+        let Some(Holding::Parts {
+            profile,
+            var,
+            parts: picked,
+        }) = self.holding.take_if(|h| matches!(h, Holding::Parts { .. }))
+        else {
+            return Ok(0);
+        };
+        let count = picked.len();
+        let source = self
+            .profile_index(&profile)
+            .unwrap_or(self.active_profile_index);
+        let shown = self.shows_parts_of(&profile, &var);
+        let mut parts = select::var_parts(&self.profiles[source], &var);
+        for (from, entry) in picked {
+            let insert_at = from.min(parts.len());
+            parts.insert(insert_at, entry);
+            if shown {
+                self.part_selection_mut().inserted(insert_at, 1);
+            }
+        }
+        self.splice_var_parts(source, &var, parts);
+        self.save_profiles(&[source])?;
+        Ok(count)
+    }
+
+    /// The selection of `view`'s list, `None` for views without one.
+    pub fn list_state_mut(&mut self, view: View) -> Option<&mut ListState> {
+        match view {
+            View::Profiles => Some(&mut self.profile_list_state),
+            View::Vars => Some(&mut self.vars_list_state),
+            View::Defs => Some(&mut self.defs_list_state),
+            View::Parts => Some(&mut self.parts_list_state),
+            View::Items => Some(&mut self.items_list_state),
+            View::History => Some(&mut self.history_list_state),
+            View::Preview | View::Export | View::Help => None,
+        }
+    }
+
     pub fn set_theme_preset(&mut self, preset: &str, persist: bool) -> Result<()> {
         let preset = preset.trim();
         if preset.is_empty() {
@@ -296,173 +845,805 @@ impl AppState {
         Ok(())
     }
 
-    pub fn refresh_items(&mut self) -> Result<()> {
-        self.items = db::load_items(&self.conn)?;
-        Ok(())
+    /// The mode the views export with: the one picked this session, else the
+    /// active profile's default, else prepend.
+    pub fn effective_mode(&self) -> OperationMode {
+        export::effective_mode(self.export_mode, &self.profiles[self.active_profile_index])
     }
 
-    pub fn ensure_selected_var(&mut self) {
-        if self.selected_var_name.is_some() {
-            return;
-        }
-        if let Some(first) = self.var_options.first() {
-            self.selected_var_name = Some(first.name.clone());
+    /// `effective_mode` for display, marked when it comes from the profile.
+    pub fn mode_label(&self) -> String {
+        let name = self.effective_mode().name();
+        match (
+            self.export_mode,
+            self.profiles[self.active_profile_index].default_mode,
+        ) {
+            (None, Some(_)) => format!("{name} (profile)"),
+            _ => name.to_string(),
         }
     }
 
-    pub fn refresh_var_options(&mut self) -> Result<()> {
-        self.custom_var_defs = db::load_custom_var_defs(&self.conn)?;
-        let mut opts = builtin_var_options();
-        for d in &self.custom_var_defs {
-            opts.push(VarTypeOption {
-                name: d.name.clone(),
-                kind: d.kind.clone(),
-                separator: d.separator.clone(),
-                editor: match d.kind {
-                    VarKind::Scalar => EditorStyle::Single,
-                    VarKind::List => EditorStyle::PartsList,
-                },
-            });
+    /// Cycles the effective mode: prepend -> append -> replace -> prepend.
+    pub fn cycle_export_mode(&mut self) {
+        self.export_mode = Some(match self.effective_mode() {
+            OperationMode::Prepend => OperationMode::Append,
+            OperationMode::Append => OperationMode::Replace,
+            OperationMode::Replace => OperationMode::Prepend,
+        });
+    }
+
+    /// Applies `expand_home_at_save` to an entry coming out of the editor.
+    pub fn normalize_saved(&self, mut entry: Entry) -> Entry {
+        if self.expand_home_at_save {
+            if let (Ok(home), Some(value)) = (std::env::var("HOME"), entry.value_mut()) {
+                *value = export::expand_tilde(value, &home);
+            }
         }
-        self.var_options = opts;
-        Ok(())
+        entry
     }
 
-    // CRUD for environment variables (active profile)
-    pub fn add_env_var(&mut self, entry: Entry) -> Result<()> {
-        let profile = &mut self.profiles[self.active_profile_index];
-        profile.entries.push(entry);
-        db::save_profile(&self.conn, profile)?;
-        Ok(())
+    /// The loaded profile called `name`.
+    fn loaded_profile(&self, name: &str) -> crate::error::Result<EnvProfile> {
+        self.profiles
+            .iter()
+            .find(|p| p.name == name)
+            .cloned()
+            .ok_or_else(|| BathError::ProfileNotFound {
+                name: name.to_string(),
+                suggestions: Vec::new(),
+            })
     }
 
-    #[allow(dead_code)]
-    pub fn delete_env_var(&mut self, index: usize) -> Result<()> {
-        let profile = &mut self.profiles[self.active_profile_index];
-        if index < profile.entries.len() {
-            profile.entries.remove(index);
-            db::save_profile(&self.conn, profile)?;
-        }
-        Ok(())
+    /// The `inheritance_chain` of profile `name` among the loaded profiles.
+    pub fn profile_chain(&self, name: &str) -> crate::error::Result<Vec<EnvProfile>> {
+        export::inheritance_chain(name, |n| self.loaded_profile(n))
     }
 
-    #[allow(dead_code)]
-    pub fn update_env_var(&mut self, index: usize, entry: Entry) -> Result<()> {
-        let profile = &mut self.profiles[self.active_profile_index];
-        if index < profile.entries.len() {
-            profile.entries[index] = entry;
-            db::save_profile(&self.conn, profile)?;
+    /// The `profile_chain` of profile `index`. A broken chain (a missing parent
+    /// or a cycle) leaves the profile on its own, with the reason.
+    fn chain_at(&self, index: usize) -> Chain {
+        let own = &self.profiles[index];
+        match self.profile_chain(&own.name) {
+            Ok(chain) => (chain, None),
+            Err(e) => (vec![own.clone()], Some(e.to_string())),
         }
-        Ok(())
     }
 
-    #[allow(dead_code)]
-    pub fn move_env_var_up(&mut self, index: usize) -> Result<()> {
-        let profile = &mut self.profiles[self.active_profile_index];
-        if index == 0 || index >= profile.entries.len() {
-            return Ok(());
-        }
-        profile.entries.swap(index - 1, index);
-        db::save_profile(&self.conn, profile)?;
-        Ok(())
+    /// `chain` inherited into its last profile with the placeholders expanded,
+    /// before the defaults of custom vars.
+    fn inherit_chain(&self, chain: &[EnvProfile]) -> EnvProfile {
+        let chain: Vec<EnvProfile> = chain
+            .iter()
+            .map(|p| template::expand_profile(p.clone(), &self.env))
+            .collect();
+        export::inherit(&chain)
     }
 
-    #[allow(dead_code)]
-    pub fn move_env_var_down(&mut self, index: usize) -> Result<()> {
-        let profile = &mut self.profiles[self.active_profile_index];
-        if profile.entries.is_empty() || index >= profile.entries.len().saturating_sub(1) {
-            return Ok(());
-        }
-        profile.entries.swap(index, index + 1);
-        db::save_profile(&self.conn, profile)?;
-        Ok(())
+    /// Profile `index` with what it inherits and its placeholders expanded,
+    /// before the defaults of custom vars.
+    fn inherited_profile_at(&self, index: usize) -> EnvProfile {
+        self.inherit_chain(&self.chain_at(index).0)
     }
 
-    pub fn replace_var_parts(&mut self, var_name: &str, new_parts: Vec<Entry>) -> Result<()> {
-        let profile = &mut self.profiles[self.active_profile_index];
+    /// Profile `index` as it is exported: with what it inherits, its
+    /// placeholders expanded and the defaults of the custom vars it leaves out.
+    pub fn expanded_profile_at(&self, index: usize) -> EnvProfile {
+        let profile = self.inherited_profile_at(index);
+        export::with_defaults(profile, &self.custom_var_defs, &self.env)
+    }
 
-        let mut idxs: Vec<usize> = profile
-            .entries
-            .iter()
-            .enumerate()
-            .filter_map(|(i, e)| {
-                if e.var_name().as_ref() == var_name {
-                    Some(i)
-                } else {
-                    None
-                }
-            })
-            .collect();
-        if idxs.is_empty() {
-            // If the var did not exist yet, append parts at the end.
-            profile.entries.extend(new_parts);
-            db::save_profile(&self.conn, profile)?;
-            return Ok(());
-        }
+    /// The expansion of the active profile, computed again only when its chain
+    /// or the custom var definitions changed.
+    fn expansion(&self) -> Rc<Expansion> {
+        let key = (
+            self.chain_at(self.active_profile_index),
+            self.custom_var_defs.clone(),
+        );
+        self.expansion.get(&key, || {
+            let ((chain, broken_chain), defs) = &key;
+            let inherited = self.inherit_chain(chain);
+            let defaulted = export::defaulted_vars(&inherited.entries, defs)
+                .into_iter()
+                .map(|(var, _)| var.to_string())
+                .collect();
+            let profile = export::with_defaults(inherited, defs, &self.env);
+            let cycles = export::reference_cycles(&profile.entries);
+            Expansion {
+                profile,
+                cycles,
+                defaulted,
+                broken_chain: broken_chain.clone(),
+            }
+        })
+    }
 
-        let insert_at = *idxs.iter().min().unwrap_or(&0);
-        idxs.sort_unstable();
-        for i in idxs.into_iter().rev() {
-            profile.entries.remove(i);
-        }
-        for (offset, e) in new_parts.into_iter().enumerate() {
-            profile.entries.insert(insert_at + offset, e);
-        }
+    /// Why the active profile cannot be exported with what it inherits (a
+    /// missing parent or a cycle), where `bath export` fails.
+    pub fn broken_chain(&self) -> Option<String> {
+        self.expansion().broken_chain.clone()
+    }
 
-        db::save_profile(&self.conn, profile)?;
-        Ok(())
+    /// Whether the active profile exports `var` with the default of its
+    /// definition, having no parts of its own.
+    pub fn uses_default(&self, var: &str) -> bool {
+        self.expansion().defaulted.iter().any(|v| v == var)
     }
 
-    // CRUD for profiles
-    pub fn add_profile(&mut self, profile: EnvProfile) -> Result<()> {
-        db::save_profile(&self.conn, &profile)?;
-        self.profiles.push(profile);
-        Ok(())
+    /// The stored timestamps and counts of profile `name`, read again only
+    /// after the database changed.
+    pub fn profile_meta(&self, name: &str) -> Option<ProfileMeta> {
+        let metas = self.profile_metas.get(&self.conn.total_changes(), || {
+            db::load_profile_metas(&self.conn)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|m| (m.name.clone(), m))
+                .collect()
+        });
+        metas.get(name).cloned()
     }
-    pub fn delete_profile(&mut self, index: usize) -> Result<()> {
-        if self.profiles.len() <= 1 {
-            // Keep at least one profile to avoid later panics from empty state.
-            return Ok(());
-        }
-        if index < self.profiles.len() {
-            let profile = self.profiles.remove(index);
-            db::delete_profile(&self.conn, &profile.name)?;
+
+    /// The ids of the items the full-text index finds for `query`, searched
+    /// again only when the query or the database changed.
+    pub fn item_hits(&self, query: &str) -> Rc<HashSet<Option<i64>>> {
+        let key = (query.to_string(), self.conn.total_changes());
+        self.item_hits.get(&key, || {
+            db::search_items(&self.conn, query)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|it| it.id)
+                .collect()
+        })
+    }
+
+    /// What `validate_profile` finds in the active profile, validated again
+    /// only when it or the custom var definitions changed.
+    pub fn profile_issues(&self) -> Rc<Vec<Issue>> {
+        let key = (
+            self.profiles[self.active_profile_index].clone(),
+            self.custom_var_defs.clone(),
+        );
+        self.issues.get(&key, || {
+            validate::validate_profile(&key.0, &key.1, &self.env)
+        })
+    }
+
+    /// The active profile as it is exported, see `expanded_profile_at`.
+    pub fn expanded_profile(&self) -> EnvProfile {
+        self.expansion().profile.clone()
+    }
+
+    /// The entries the active profile inherits, each with the name of the
+    /// profile it comes from, root first.
+    pub fn inherited_entries(&self) -> Vec<(String, Entry)> {
+        let name = &self.profiles[self.active_profile_index].name;
+        let Ok(mut chain) = self.profile_chain(name) else {
+            return Vec::new();
+        };
+        chain.pop();
+        chain
+            .into_iter()
+            .flat_map(|p| {
+                let owner = p.name;
+                p.entries.into_iter().map(move |e| (owner.clone(), e))
+            })
+            .collect()
+    }
+
+    /// Adds a broken inheritance chain or else the first `@{VAR}` reference
+    /// cycle of the active profile to the status line, which stays there
+    /// until it is fixed.
+    pub fn note_expansion_problems(&mut self) {
+        let expansion = self.expansion();
+        let note = match (&expansion.broken_chain, expansion.cycles.first()) {
+            (Some(e), _) => format!("cannot inherit: {e}"),
+            (None, Some(cycle)) => format!("reference cycle: {}", cycle.join(" -> ")),
+            (None, None) => return,
+        };
+        if !self.status.contains(&note) {
+            self.status = if self.status.is_empty() {
+                note
+            } else {
+                format!("{}; {note}", self.status)
+            };
+        }
+    }
+
+    /// The Export view of the active profile, generated again only when its
+    /// expansion, `export_dedup`, the mode or the separator overrides changed. The profile is exported
+    /// expanded, without duplicate parts under `export_dedup` and on its own,
+    /// its settings applied already.
+    pub fn export_view(&self) -> Rc<ExportView> {
+        // `Rc` compares the pointers first, so an unchanged expansion is cheap.
+        let key = (
+            self.expansion(),
+            self.export_dedup,
+            self.effective_mode(),
+            self.separator_overrides.clone(),
+        );
+        self.export_view.get(&key, || {
+            let (expansion, dedup, mode, _) = &key;
+            let expanded = &expansion.profile;
+            let (entries, hidden) = if *dedup {
+                export::dedup_entries(&expanded.entries)
+            } else {
+                (expanded.entries.clone(), 0)
+            };
+            let profile = EnvProfile {
+                entries,
+                ..EnvProfile::new(&expanded.name)
+            };
+            ExportView {
+                script: self.export_script_of(&profile),
+                undo: export::generate_full_unexport(&profile, *mode, &self.separator_overrides),
+                hidden,
+            }
+        })
+    }
+
+    /// The script of `export_view`.
+    pub fn export_script(&self) -> String {
+        self.export_view().script.clone()
+    }
+
+    /// What `bath export` prints for `profile` under `effective_mode`.
+    pub fn export_script_of(&self, profile: &EnvProfile) -> String {
+        export::generate_full_export_with_overrides(
+            &Posix::default(),
+            profile,
+            self.effective_mode(),
+            &export::ModeOverrides::new(),
+            export::VarOrder::default(),
+            &self.separator_overrides,
+        )
+    }
+
+    /// The line of `export_script` exporting `var`, if it does.
+    pub fn shown_export_line(&self, var: &str) -> Option<String> {
+        let prefix = format!("export {var}=");
+        self.export_script()
+            .lines()
+            .find(|l| l.starts_with(&prefix))
+            .map(str::to_string)
+    }
+
+    /// Copies the export line of `var`, or the whole export script, to the
+    /// clipboard and says how that went in the status line.
+    pub fn copy_export(&mut self, var: Option<&str>) {
+        let (text, what) = match var {
+            Some(var) => match self.shown_export_line(var) {
+                Some(line) => (line, format!("the export line of {var}")),
+                None => {
+                    self.status = format!("{var} is not exported, nothing to copy");
+                    return;
+                }
+            },
+            None => {
+                let name = &self.profiles[self.active_profile_index].name;
+                (self.export_script(), format!("the export script of {name}"))
+            }
+        };
+        self.status = match self.clipboard.copy(&text) {
+            Ok(()) => format!("copied {what}"),
+            Err(e) => format!("could not copy {what}: {e}"),
+        };
+    }
+
+    /// The export (or unset) statement for `var` in the active profile under
+    /// `effective_mode`, or an empty string if the profile does not set it.
+    pub fn export_line(&self, var: &str) -> String {
+        self.export_script_of(&self.expanded_profile())
+            .lines()
+            .find(|l| l.starts_with(&format!("export {var}=")) || *l == format!("unset {var};"))
+            .unwrap_or("")
+            .to_string()
+    }
+
+    pub fn refresh_items(&mut self) -> Result<()> {
+        self.items = db::load_items(&self.conn)?;
+        Ok(())
+    }
+
+    /// Records a use of the item with `id` in the database and in `items`.
+    pub fn touch_item(&mut self, id: Option<i64>) -> Result<()> {
+        let Some(id) = id else {
+            return Ok(());
+        };
+        let used_at = db::touch_item(&self.conn, id)?;
+        if let Some(item) = self.items.iter_mut().find(|it| it.id == Some(id)) {
+            item.use_count += 1;
+            item.last_used_at = Some(used_at);
+        }
+        Ok(())
+    }
+
+    /// Records that item `id` was dropped into `var` of the active profile as
+    /// `value`: the drop counts as a use, and the new part is linked to the item.
+    pub fn item_dropped(&mut self, id: Option<i64>, var: &str, value: &str) -> Result<()> {
+        let Some(item_id) = id else {
+            return Ok(());
+        };
+        let profile = &self.profiles[self.active_profile_index].name;
+        db::link_item(&self.conn, item_id, profile, var, value)?;
+        self.touch_item(id)
+    }
+
+    /// Makes the value of `item` the only part of the scalar `var` of the
+    /// active profile, recording the drop like `item_dropped`.
+    pub fn replace_scalar_with_item(&mut self, var: &str, item: &CatalogItem) -> Result<()> {
+        let entry = crate::config::make_entry(var, item.value.clone(), &self.custom_var_defs);
+        let entry = self.normalize_saved(entry);
+        let value = export::entry_value(&entry);
+        self.set_scalar(var, entry)?;
+        self.item_dropped(item.id, var, &value)
+    }
+
+    /// Makes the value of `entry` the value of the scalar `var` in the active
+    /// profile, keeping the settings of the part it replaces.
+    pub fn set_scalar(&mut self, var: &str, entry: Entry) -> Result<()> {
+        let replaced = match select::current_var_parts(self, var).into_iter().next() {
+            Some(mut old) => match old.value_mut() {
+                Some(slot) => {
+                    *slot = export::entry_value(&entry);
+                    old
+                }
+                None => entry,
+            },
+            None => entry,
+        };
+        self.replace_var_parts(var, vec![replaced])
+    }
+
+    pub fn ensure_selected_var(&mut self) {
+        if self.selected_var_name.is_some() {
+            return;
+        }
+        if let Some(first) = self.var_options.first() {
+            self.selected_var_name = Some(first.name.clone());
+        }
+    }
+
+    pub fn refresh_var_options(&mut self) -> Result<()> {
+        self.separator_overrides = db::load_separator_overrides(&self.conn)?;
+        self.custom_var_defs = db::load_custom_var_defs(&self.conn)?;
+        self.var_options = config::var_options(&self.custom_var_defs, &self.separator_overrides);
+        Ok(())
+    }
+
+    /// Deletes a custom var definition. Entries using it stay in the profiles and
+    /// are listed as undefined.
+    pub fn delete_custom_var_def(&mut self, name: &str) -> Result<()> {
+        db::delete_custom_var_def(&self.conn, name)?;
+        self.refresh_var_options()
+    }
+
+    /// Saves a changed custom var definition, moving the parts of its var in
+    /// every profile over to its separator.
+    pub fn update_custom_var_def(&mut self, def: &CustomVarDef) -> Result<()> {
+        db::save_custom_var_def(&self.conn, def)?;
+        self.refresh_var_options()?;
+        self.normalize_separators()
+    }
+
+    /// Joins the builtin list var `var` with `separator`, or with its own
+    /// separator again for `None`. Its stored parts are left as they are.
+    pub fn set_separator_override(&mut self, var: &str, separator: Option<&str>) -> Result<()> {
+        db::set_separator_override(&self.conn, var, separator)?;
+        self.refresh_var_options()
+    }
+
+    /// Renames a custom var definition and the entries of every profile using it.
+    pub fn rename_custom_var_def(&mut self, old: &str, new: &str) -> Result<()> {
+        db::rename_custom_var_def(&self.conn, old, new)?;
+        self.reload_profiles()?;
+        self.refresh_var_options()?;
+        if self.selected_var_name.as_deref() == Some(old) {
+            self.selected_var_name = Some(new.to_string());
+        }
+        Ok(())
+    }
+
+    /// The profiles with custom parts whose separator disagrees with their var
+    /// definition (or the var's first part), see `SeparatorFix`.
+    fn separator_fixes(&self) -> Vec<SeparatorFix> {
+        self.profiles
+            .iter()
+            .enumerate()
+            .filter_map(|(index, profile)| {
+                let mut profile = profile.clone();
+                let fixed = validate::normalize_separators(&mut profile, &self.custom_var_defs);
+                (!fixed.is_empty()).then_some((index, profile, fixed))
+            })
+            .collect()
+    }
+
+    /// Rewrites the parts `separator_fixes` finds, saving the affected profiles
+    /// and noting them in the status line.
+    pub fn normalize_separators(&mut self) -> Result<()> {
+        let fixes = self.separator_fixes();
+        db::atomically(&self.conn, |tx| -> Result<()> {
+            for (_, profile, _) in &fixes {
+                db::save_profile(tx, profile)?;
+            }
+            Ok(())
+        })?;
+        let mut notes = Vec::new();
+        for (index, profile, fixed) in fixes {
+            notes.extend(
+                fixed
+                    .into_iter()
+                    .map(|(var, sep)| format!("{var} in {} now uses {sep:?}", profile.name)),
+            );
+            self.profiles[index] = profile;
+        }
+        if !notes.is_empty() {
+            self.status = format!("separator mismatch fixed: {}", notes.join(", "));
+        }
+        Ok(())
+    }
+
+    /// Notes the separator mismatches `normalize_separators` would fix in the
+    /// status line, without changing anything.
+    pub fn note_separator_mismatches(&mut self) {
+        let mismatched: Vec<String> = self
+            .separator_fixes()
+            .into_iter()
+            .flat_map(|(_, profile, fixed)| {
+                fixed
+                    .into_iter()
+                    .map(move |(var, _)| format!("{var} in {}", profile.name))
+            })
+            .collect();
+        if !mismatched.is_empty() {
+            self.status = format!(
+                "separator mismatch: {} (:fix-separators rewrites them)",
+                mismatched.join(", ")
+            );
+        }
+    }
+
+    // CRUD for environment variables (active profile)
+    pub fn add_env_var(&mut self, entry: Entry) -> Result<()> {
+        let profile = &mut self.profiles[self.active_profile_index];
+        let action = format!("add {}", entry.var_name());
+        profile.entries.push(entry);
+        db::save_profile_logged(&self.conn, profile, &action)?;
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn delete_env_var(&mut self, index: usize) -> Result<()> {
+        let profile = &mut self.profiles[self.active_profile_index];
+        if index < profile.entries.len() {
+            let removed = profile.entries.remove(index);
+            let action = format!("delete {}", removed.var_name());
+            db::save_profile_logged(&self.conn, profile, &action)?;
+        }
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn update_env_var(&mut self, index: usize, entry: Entry) -> Result<()> {
+        let profile = &mut self.profiles[self.active_profile_index];
+        if index < profile.entries.len() {
+            let action = format!("edit {}", entry.var_name());
+            profile.entries[index] = entry;
+            db::save_profile_logged(&self.conn, profile, &action)?;
+        }
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn move_env_var_up(&mut self, index: usize) -> Result<()> {
+        let profile = &mut self.profiles[self.active_profile_index];
+        if index == 0 || index >= profile.entries.len() {
+            return Ok(());
+        }
+        profile.entries.swap(index - 1, index);
+        db::save_profile_logged(&self.conn, profile, "reorder")?;
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn move_env_var_down(&mut self, index: usize) -> Result<()> {
+        let profile = &mut self.profiles[self.active_profile_index];
+        if profile.entries.is_empty() || index >= profile.entries.len().saturating_sub(1) {
+            return Ok(());
+        }
+        profile.entries.swap(index, index + 1);
+        db::save_profile_logged(&self.conn, profile, "reorder")?;
+        Ok(())
+    }
+
+    pub fn replace_var_parts(&mut self, var_name: &str, new_parts: Vec<Entry>) -> Result<()> {
+        self.splice_var_parts(self.active_profile_index, var_name, new_parts);
+        db::save_profile_logged(
+            &self.conn,
+            &self.profiles[self.active_profile_index],
+            &format!("edit {var_name}"),
+        )?;
+        Ok(())
+    }
+
+    /// Sorts the parts of `var_name` of the active profile by `order`. Parts
+    /// that compare equal keep their order. Returns false, saving nothing, if
+    /// they already were in order.
+    pub fn sort_var_parts(&mut self, var_name: &str, order: PartOrder) -> Result<bool> {
+        let parts = select::current_var_parts(self, var_name);
+        let mut keyed: Vec<_> = parts
+            .into_iter()
+            .enumerate()
+            .map(|(i, e)| (order.key(&e), i, e))
+            .collect();
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        if keyed.iter().enumerate().all(|(i, (_, was, _))| i == *was) {
+            return Ok(false);
+        }
+        self.part_selection.clear();
+        let sorted = keyed.into_iter().map(|(_, _, e)| e).collect();
+        self.replace_var_parts(var_name, sorted)?;
+        Ok(true)
+    }
+
+    /// Drops the parts of `var_name` of the active profile that repeat an
+    /// earlier one exactly, settings included; parts sharing only a value are
+    /// left to the export (see `export::dedup_entries`). Returns how many went.
+    pub fn dedup_var_parts(&mut self, var_name: &str) -> Result<usize> {
+        let parts = select::current_var_parts(self, var_name);
+        let mut kept: Vec<Entry> = Vec::with_capacity(parts.len());
+        for part in &parts {
+            if !kept.contains(part) {
+                kept.push(part.clone());
+            }
+        }
+        let dropped = parts.len() - kept.len();
+        if dropped > 0 {
+            self.part_selection.clear();
+            self.replace_var_parts(var_name, kept)?;
+        }
+        Ok(dropped)
+    }
+
+    /// `replace_var_parts` on profile `index` without saving.
+    pub fn splice_var_parts(&mut self, index: usize, var_name: &str, new_parts: Vec<Entry>) {
+        let profile = &mut self.profiles[index];
+
+        let mut idxs: Vec<usize> = profile
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| {
+                if e.var_name().as_ref() == var_name {
+                    Some(i)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        if idxs.is_empty() {
+            // If the var did not exist yet, append parts at the end.
+            profile.entries.extend(new_parts);
+            return;
+        }
+
+        let insert_at = *idxs.iter().min().unwrap_or(&0);
+        idxs.sort_unstable();
+        for i in idxs.into_iter().rev() {
+            profile.entries.remove(i);
+        }
+        for (offset, e) in new_parts.into_iter().enumerate() {
+            profile.entries.insert(insert_at + offset, e);
+        }
+    }
+
+    /// Re-reads the profiles after a failed write so the screen matches what is
+    /// stored, keeping the active profile by name where possible.
+    pub fn reload_profiles(&mut self) -> Result<()> {
+        let active = self.profiles[self.active_profile_index].name.clone();
+        let (profiles, broken) = db::load_profiles_checked(&self.conn)?;
+        self.broken_profiles = broken;
+        if profiles.is_empty() {
+            return Ok(());
+        }
+        self.profiles = profiles;
+        self.active_profile_index = self.profile_index(&active).unwrap_or(0);
+        self.holding = None;
+        Ok(())
+    }
+
+    /// Re-reads the history of the active profile.
+    pub fn refresh_history(&mut self) -> Result<()> {
+        let name = &self.profiles[self.active_profile_index].name;
+        self.history = db::load_history(&self.conn, name)?;
+        Ok(())
+    }
+
+    /// Rolls the active profile back to before history entry `id` and shows the
+    /// restored entries.
+    pub fn restore_from_history(&mut self, id: i64) -> Result<()> {
+        let name = self.profiles[self.active_profile_index].name.clone();
+        let restored = db::restore_from_history(&self.conn, &name, id)?;
+        self.profiles[self.active_profile_index] = restored;
+        self.refresh_history()
+    }
+
+    /// Re-saves a broken profile with the entries that can still be read and
+    /// lists it again. Returns how many entries were kept and dropped.
+    pub fn repair_profile(&mut self, name: &str) -> Result<(usize, usize)> {
+        let counts = db::repair_profile(&self.conn, name)?;
+        self.reload_profiles()?;
+        Ok(counts)
+    }
+
+    /// Saves the profiles at `indices` together: either all writes land or none.
+    /// Each change is recorded in the profile's history as a part move.
+    pub fn save_profiles(&self, indices: &[usize]) -> Result<()> {
+        db::atomically(&self.conn, |tx| {
+            indices
+                .iter()
+                .try_for_each(|i| db::save_profile_logged(tx, &self.profiles[*i], "move part"))
+        })?;
+        Ok(())
+    }
+
+    pub fn profile_index(&self, name: &str) -> Option<usize> {
+        self.profiles.iter().position(|p| p.name == name)
+    }
+
+    /// Whether a profile, readable or not, is named `name`.
+    pub fn profile_name_taken(&self, name: &str) -> bool {
+        self.profile_index(name).is_some() || self.broken_profiles.iter().any(|b| b.name == name)
+    }
+
+    // CRUD for profiles
+    pub fn add_profile(&mut self, profile: EnvProfile) -> Result<()> {
+        db::save_profile_logged(&self.conn, &profile, "create")?;
+        self.profiles.push(profile);
+        Ok(())
+    }
+    pub fn delete_profile(&mut self, index: usize) -> Result<()> {
+        if self.profiles.len() <= 1 {
+            // Keep at least one profile to avoid later panics from empty state.
+            return Ok(());
+        }
+        if let Some(profile) = self.profiles.get(index) {
+            db::atomically(&self.conn, |tx| {
+                db::delete_profile(tx, &profile.name)?;
+                db::record_history(tx, &profile.name, "delete", Some(profile), None)
+            })?;
+            self.profiles.remove(index);
             if self.active_profile_index >= self.profiles.len() {
                 self.active_profile_index = self.profiles.len().saturating_sub(1);
             }
         }
         Ok(())
     }
+    /// Takes a deleted profile out of the trash (see `db::recover_profile`) and
+    /// makes it the active one.
+    pub fn recover_profile(&mut self, name: &str, new_name: Option<&str>) -> Result<()> {
+        db::recover_profile(&self.conn, name, new_name)?;
+        self.reload_profiles()?;
+        if let Some(i) = self.profile_index(new_name.unwrap_or(name)) {
+            self.active_profile_index = i;
+            self.profile_list_state.select(Some(i));
+        }
+        Ok(())
+    }
+
+    /// Moves the selected profile one place up (`delta` -1) or down (1) and saves
+    /// the new order. Blocked while the list is filtered, where the neighbour on
+    /// screen is not the neighbour in the order.
+    pub fn move_selected_profile(&mut self, delta: isize) -> Result<()> {
+        if !self.profiles_filter.is_empty() {
+            self.status = "clear the filter to reorder profiles".to_string();
+            return Ok(());
+        }
+        let Some(i) = select::selected_profile_index(self) else {
+            return Ok(());
+        };
+        let Some(j) = i
+            .checked_add_signed(delta)
+            .filter(|j| *j < self.profiles.len())
+        else {
+            return Ok(());
+        };
+        db::swap_profile_positions(&self.conn, &self.profiles[i].name, &self.profiles[j].name)?;
+        self.profiles.swap(i, j);
+        if self.active_profile_index == i {
+            self.active_profile_index = j;
+        } else if self.active_profile_index == j {
+            self.active_profile_index = i;
+        }
+        self.profile_list_state.select(Some(j));
+        self.status = format!("moved profile: {}", self.profiles[j].name);
+        Ok(())
+    }
+    pub fn describe_profile(&mut self, description: Option<String>) -> Result<()> {
+        let profile = &mut self.profiles[self.active_profile_index];
+        profile.description = description;
+        db::save_profile_logged(&self.conn, profile, "describe")?;
+        Ok(())
+    }
+
+    /// Stores the default export mode of the active profile and drops the mode
+    /// picked this session, so the views show the new default.
+    pub fn set_profile_mode(&mut self, mode: Option<OperationMode>) -> Result<()> {
+        let profile = &mut self.profiles[self.active_profile_index];
+        profile.default_mode = mode;
+        db::save_profile_logged(&self.conn, profile, "set mode")?;
+        self.export_mode = None;
+        Ok(())
+    }
+    /// Adds `tag` to the active profile, or removes it with `add` false.
+    /// Returns false if there was nothing to change.
+    pub fn tag_profile(&mut self, tag: &str, add: bool) -> Result<bool> {
+        let profile = &mut self.profiles[self.active_profile_index];
+        let has = profile.tags.iter().any(|t| t == tag);
+        if has == add {
+            return Ok(false);
+        }
+        if add {
+            profile.tags.push(tag.to_string());
+        } else {
+            profile.tags.retain(|t| t != tag);
+        }
+        let action = if add { "tag" } else { "untag" };
+        db::save_profile_logged(&self.conn, profile, action)?;
+        Ok(true)
+    }
+
+    /// Makes the active profile extend `parent`, or stop extending with `None`.
+    /// Refuses parents that do not exist or would extend the profile itself.
+    pub fn set_profile_parent(&mut self, parent: Option<String>) -> Result<()> {
+        let mut profile = self.profiles[self.active_profile_index].clone();
+        profile.extends = parent;
+        export::inheritance_chain(&profile.name, |n| {
+            if n == profile.name {
+                Ok(profile.clone())
+            } else {
+                self.loaded_profile(n)
+            }
+        })?;
+        db::save_profile_logged(&self.conn, &profile, "set parent")?;
+        self.profiles[self.active_profile_index] = profile;
+        Ok(())
+    }
+
     pub fn update_profile(&mut self, index: usize, new_name: String) -> Result<()> {
         if index < self.profiles.len() {
-            let old_name = self.profiles[index].name.clone();
-            db::rename_profile(&self.conn, &old_name, &new_name)?;
-            self.profiles[index].name = new_name;
+            let before = self.profiles[index].clone();
+            let after = EnvProfile {
+                name: new_name,
+                ..before.clone()
+            };
+            db::atomically(&self.conn, |tx| {
+                db::rename_profile(tx, &before.name, &after.name)?;
+                let action = format!("rename from {}", before.name);
+                db::record_history(tx, &after.name, &action, Some(&before), Some(&after))
+            })?;
+            for p in &mut self.profiles {
+                if p.extends.as_deref() == Some(before.name.as_str()) {
+                    p.extends = Some(after.name.clone());
+                }
+            }
+            self.profiles[index] = after;
         }
         Ok(())
     }
 }
 
 #[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::db;
-
-    #[test]
-    fn delete_profile_does_not_remove_last_profile() -> Result<()> {
-        let conn = Connection::open_in_memory()?;
-        db::initialize_db(&conn)?;
-
-        let default = EnvProfile::new("default");
-        db::save_profile(&conn, &default)?;
-
-        let mut app = AppState {
+impl AppState {
+    /// An app on `conn` showing `profiles`, without reading the config file.
+    pub(crate) fn for_tests(conn: Connection, profiles: Vec<EnvProfile>) -> Result<Self> {
+        Ok(Self {
             conn,
-            profiles: vec![default],
+            profiles,
+            broken_profiles: Vec::new(),
             active_profile_index: 0,
             profile_list_state: ListState::default(),
             custom_var_defs: Vec::new(),
-            var_options: builtin_var_options(),
+            var_options: config::builtin_var_options(&SeparatorOverrides::new()),
+            separator_overrides: SeparatorOverrides::new(),
             active_view: View::Vars,
             input_mode: InputMode::Normal,
             theme_preset: crate::tui::theme::default_preset().to_string(),
@@ -472,20 +1653,146 @@ mod tests {
             defs_list_state: ListState::default(),
             parts_list_state: ListState::default(),
             items_list_state: ListState::default(),
+            history_list_state: ListState::default(),
             selected_var_name: Some("PATH".to_string()),
             profiles_filter: String::new(),
             vars_filter: String::new(),
             defs_filter: String::new(),
             parts_filter: String::new(),
             items_filter: String::new(),
-            command_input: String::new(),
+            command_input: LineInput::default(),
             command_suggestions: Vec::new(),
             command_selected: 0,
+            command_hint: None,
+            command_history: CommandHistory::default(),
             search_target: View::Vars,
             status: String::new(),
+            clipboard: Box::new(crate::tui::clipboard::Captured::default()),
             holding: None,
+            part_selection: PartSelection::default(),
+            item_marks: Marks::default(),
+            export_mode: None,
+            export_dedup: false,
+            expand_home_at_save: false,
+            env: export::current_env(),
             items: Vec::new(),
+            item_sort: ItemSort::default(),
+            show_item_links: false,
+            history: Vec::new(),
+            list_areas: HashMap::new(),
+            text_scroll: HashMap::new(),
+            export_revealed: None,
+            header_area: Rect::default(),
+            last_click: None,
+            pending_g: None,
+            tag_edit: None,
+            profile_metas: Memo::default(),
+            item_hits: Memo::default(),
+            expansion: Memo::default(),
+            issues: Memo::default(),
+            export_view: Memo::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ItemKind, PathEntry, VarKind};
+    use crate::db;
+    use crate::items::build_item;
+
+    #[test]
+    fn profile_metas_are_read_again_after_a_change() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let profile = EnvProfile::new("p");
+        db::save_profile(&conn, &profile)?;
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+        assert_eq!(app.profile_meta("p").map(|m| m.entry_count), Some(0));
+        assert_eq!(app.profile_meta("q"), None);
+
+        app.add_env_var(Entry::CC("gcc".to_string()))?;
+        assert_eq!(app.profile_meta("p").map(|m| m.entry_count), Some(1));
+        Ok(())
+    }
+
+    #[test]
+    fn item_hits_are_searched_again_after_a_change() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let app = AppState::for_tests(conn, vec![EnvProfile::new("p")])?;
+        assert!(app.item_hits("gcc").is_empty());
+
+        let mut item = build_item("/opt/gcc/bin", ItemKind::Path, None, None, "")?;
+        db::save_item(&app.conn, &mut item, false)?;
+        assert_eq!(*app.item_hits("gcc"), HashSet::from([item.id]));
+        Ok(())
+    }
+
+    #[test]
+    fn the_expansion_is_computed_again_only_after_a_change() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let profile = EnvProfile::new("p");
+        db::save_profile(&conn, &profile)?;
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+        let first = app.expansion();
+        app.note_expansion_problems();
+        assert!(Rc::ptr_eq(&first, &app.expansion()));
+
+        app.add_env_var(Entry::CC("gcc".to_string()))?;
+        assert!(!Rc::ptr_eq(&first, &app.expansion()));
+        assert_eq!(app.expanded_profile().entries.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn profile_issues_follow_the_active_profile() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let profile = EnvProfile::new("p");
+        db::save_profile(&conn, &profile)?;
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+        assert!(app.profile_issues().is_empty());
+
+        app.add_env_var(Entry::CFlag("-O2".to_string()))?;
+        app.add_env_var(Entry::CFlag("-O2".to_string()))?;
+        assert!(!app.profile_issues().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn a_missing_parent_is_reported_instead_of_ignored() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let orphan = EnvProfile {
+            extends: Some("gone".to_string()),
+            ..EnvProfile::new("orphan")
         };
+        let mut app = AppState::for_tests(conn, vec![orphan])?;
+        assert!(app
+            .broken_chain()
+            .is_some_and(|e| e.contains("'gone' not found")));
+        app.note_expansion_problems();
+        assert!(app
+            .status
+            .starts_with("cannot inherit: profile 'gone' not found"));
+
+        app.profiles[0].extends = None;
+        assert_eq!(app.broken_chain(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn delete_profile_does_not_remove_last_profile() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+
+        let default = EnvProfile::new("default");
+        db::save_profile(&conn, &default)?;
+
+        let mut app = AppState::for_tests(conn, vec![default])?;
 
         app.delete_profile(0)?;
 
@@ -500,4 +1807,714 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn a_refused_delete_keeps_the_profile() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let base = EnvProfile::new("base");
+        let child = EnvProfile {
+            extends: Some("base".to_string()),
+            ..EnvProfile::new("child")
+        };
+        db::save_profile(&conn, &base)?;
+        db::save_profile(&conn, &child)?;
+        let mut app = AppState::for_tests(conn, vec![base, child])?;
+
+        assert!(app.delete_profile(0).is_err());
+        assert_eq!(app.profiles.len(), 2);
+        assert_eq!(app.profiles[0].name, "base");
+        Ok(())
+    }
+
+    #[test]
+    fn dropping_an_item_links_the_new_part() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let p = EnvProfile::new("p");
+        db::save_profile(&conn, &p)?;
+        let mut item = CatalogItem {
+            id: None,
+            kind: crate::config::ItemKind::Text,
+            value: "-O2".to_string(),
+            program: None,
+            version: None,
+            tags: Vec::new(),
+            use_count: 0,
+            last_used_at: None,
+        };
+        db::save_item(&conn, &mut item, false)?;
+        let mut app = AppState::for_tests(conn, vec![p])?;
+        app.refresh_items()?;
+
+        app.add_env_var(Entry::CFlag("-O2".to_string()))?;
+        app.item_dropped(item.id, "CFLAGS", "-O2")?;
+        let links = db::item_links(&app.conn, item.id.unwrap())?;
+        assert_eq!(links.len(), 1);
+        assert_eq!(
+            (links[0].profile.as_str(), links[0].var.as_str()),
+            ("p", "CFLAGS")
+        );
+        assert_eq!(app.items[0].use_count, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn items_sort_by_usage_before_filtering() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        for value in ["/a/bin", "/b/bin", "/c/bin", "-g"] {
+            let mut item = CatalogItem {
+                id: None,
+                kind: crate::config::ItemKind::Text,
+                value: value.to_string(),
+                program: None,
+                version: None,
+                tags: Vec::new(),
+                use_count: 0,
+                last_used_at: None,
+            };
+            db::save_item(&conn, &mut item, false)?;
+        }
+        let mut app = AppState::for_tests(conn, vec![EnvProfile::new("p")])?;
+        app.refresh_items()?;
+        app.touch_item(Some(3))?;
+        app.touch_item(Some(3))?;
+        app.touch_item(Some(2))?;
+        app.touch_item(Some(4))?;
+        assert_eq!(app.items, db::load_items(&app.conn)?);
+        app.conn
+            .execute("UPDATE items SET last_used_at = 100 WHERE id = 3", [])?;
+        app.conn
+            .execute("UPDATE items SET last_used_at = 50 WHERE id = 4", [])?;
+        app.refresh_items()?;
+
+        let values = |app: &AppState| -> Vec<String> {
+            select::visible_item_indices(app)
+                .into_iter()
+                .map(|i| app.items[i].value.clone())
+                .collect()
+        };
+        assert_eq!(values(&app), ["/a/bin", "/b/bin", "/c/bin", "-g"]);
+        app.item_sort = ItemSort::Recent;
+        assert_eq!(values(&app), ["/b/bin", "/c/bin", "-g", "/a/bin"]);
+        app.item_sort = ItemSort::MostUsed;
+        assert_eq!(values(&app), ["/c/bin", "/b/bin", "-g", "/a/bin"]);
+        app.items_filter = "bin".to_string();
+        assert_eq!(values(&app), ["/c/bin", "/b/bin", "/a/bin"]);
+        assert_eq!(app.item_sort.next(), ItemSort::Id);
+        Ok(())
+    }
+
+    #[test]
+    fn items_sort_by_value_kind_and_first_tag() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        for (value, kind, tags) in [
+            ("/opt/b", crate::config::ItemKind::Path, "zlib"),
+            ("-O2", crate::config::ItemKind::Text, ""),
+            ("/opt/a", crate::config::ItemKind::Path, ""),
+            ("-g", crate::config::ItemKind::Text, "Debug,zlib"),
+        ] {
+            let mut item = crate::items::build_item(value, kind, None, None, tags)?;
+            db::save_item(&conn, &mut item, false)?;
+        }
+        let mut app = AppState::for_tests(conn, vec![EnvProfile::new("p")])?;
+        app.refresh_items()?;
+        let values = |app: &AppState| -> Vec<String> {
+            select::visible_item_indices(app)
+                .into_iter()
+                .map(|i| app.items[i].value.clone())
+                .collect()
+        };
+
+        assert_eq!(values(&app), ["/opt/b", "-O2", "/opt/a", "-g"]);
+        app.item_sort = ItemSort::Value;
+        assert_eq!(values(&app), ["-O2", "-g", "/opt/a", "/opt/b"]);
+        app.item_sort = ItemSort::Kind;
+        assert_eq!(values(&app), ["/opt/a", "/opt/b", "-O2", "-g"]);
+        // Untagged items last, tags compared ignoring case.
+        app.item_sort = ItemSort::Tag;
+        assert_eq!(values(&app), ["-g", "/opt/b", "-O2", "/opt/a"]);
+        app.items_filter = "opt".to_string();
+        assert_eq!(values(&app), ["/opt/b", "/opt/a"]);
+
+        let mut sort = ItemSort::Id;
+        let mut names = Vec::new();
+        for _ in 0..6 {
+            sort = sort.next();
+            names.push(sort.name());
+        }
+        assert_eq!(
+            names,
+            ["value", "kind", "tag", "recently used", "most used", "id"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reordering_profiles_persists_and_is_blocked_while_filtered() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        for name in ["gcc", "clang", "musl"] {
+            db::save_profile(&conn, &EnvProfile::new(name))?;
+        }
+        let profiles = db::load_all_profiles(&conn)?;
+        let mut app = AppState::for_tests(conn, profiles)?;
+        app.profile_list_state.select(Some(2));
+        app.active_profile_index = 2;
+
+        app.move_selected_profile(-1)?;
+        let names = |app: &AppState| -> Result<Vec<String>> {
+            Ok(db::load_all_profiles(&app.conn)?
+                .into_iter()
+                .map(|p| p.name)
+                .collect())
+        };
+        assert_eq!(names(&app)?, ["gcc", "musl", "clang"]);
+        assert_eq!(app.profiles[1].name, "musl");
+        assert_eq!(app.active_profile_index, 1);
+        assert_eq!(app.profile_list_state.selected(), Some(1));
+
+        // Filtered to "c": row 1 is clang, whose on-screen neighbour gcc is two
+        // places away in the real order.
+        app.profiles_filter = "c".to_string();
+        app.profile_list_state.select(Some(1));
+        app.move_selected_profile(-1)?;
+        assert_eq!(names(&app)?, ["gcc", "musl", "clang"]);
+        assert!(app.status.contains("clear the filter"));
+
+        app.profiles_filter.clear();
+        app.profile_list_state.select(Some(0));
+        app.move_selected_profile(-1)?;
+        assert_eq!(names(&app)?, ["gcc", "musl", "clang"]);
+        Ok(())
+    }
+
+    #[test]
+    fn text_scroll_stays_within_the_text() {
+        let mut scroll = TextScroll::default();
+        scroll.update(12, 5);
+        assert_eq!(scroll.indicator(), " (1/12)");
+        scroll.scroll(-3);
+        assert_eq!(scroll.offset, 0);
+        scroll.scroll(4);
+        assert_eq!(scroll.indicator(), " (5/12)");
+        // The last page is full: rows 8 to 12.
+        scroll.scroll(isize::MAX);
+        assert_eq!(scroll.offset, 7);
+        // A shorter text, or a taller pane, scrolls back.
+        scroll.update(9, 5);
+        assert_eq!(scroll.offset, 4);
+        scroll.update(9, 20);
+        assert_eq!(scroll.offset, 0);
+        assert_eq!(scroll.indicator(), "");
+        scroll.scroll(1);
+        assert_eq!(scroll.offset, 0);
+    }
+
+    #[test]
+    fn revealing_rows_scrolls_as_little_as_needed() {
+        let mut scroll = TextScroll::default();
+        scroll.update(20, 5);
+        scroll.reveal(2, 2);
+        assert_eq!(scroll.offset, 0);
+        scroll.reveal(8, 2);
+        assert_eq!(scroll.offset, 5);
+        scroll.reveal(3, 1);
+        assert_eq!(scroll.offset, 3);
+        // Rows taller than the pane show from their first.
+        scroll.reveal(10, 8);
+        assert_eq!(scroll.offset, 10);
+        scroll.reveal(19, 3);
+        assert_eq!(scroll.offset, 15);
+    }
+
+    #[test]
+    fn held_parts_are_put_back_where_they_were() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        profile.entries = ["/a", "/b", "/c", "/d"]
+            .into_iter()
+            .map(|v| Entry::CPath(v.to_string()))
+            .collect();
+        db::save_profile(&conn, &profile)?;
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+        assert_eq!(app.put_back_held_parts()?, 0);
+
+        // Pick up /b and /d like `m` does, then save the profile without them.
+        let mut parts = select::current_var_parts(&app, "CPATH");
+        let d = parts.remove(3);
+        let b = parts.remove(1);
+        app.splice_var_parts(0, "CPATH", parts);
+        app.holding = Some(Holding::Parts {
+            profile: "p".to_string(),
+            var: "CPATH".to_string(),
+            parts: vec![(1, b), (3, d)],
+        });
+        app.add_env_var(Entry::CFlag("-g".to_string()))?;
+        assert_eq!(db::load_profile(&app.conn, "p")?.entries.len(), 3);
+
+        assert_eq!(app.put_back_held_parts()?, 2);
+        assert!(app.holding.is_none());
+        assert_eq!(values(&app, "CPATH"), ["/a", "/b", "/c", "/d"]);
+        let saved = db::load_profile(&app.conn, "p")?;
+        assert_eq!(select::var_parts(&saved, "CPATH").len(), 4);
+
+        app.holding = Some(Holding::Items(Vec::new()));
+        assert_eq!(app.put_back_held_parts()?, 0);
+        assert!(app.holding.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn moved_part_is_saved_with_its_drop() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        profile.entries = vec![
+            Entry::CPath("/a".to_string()),
+            Entry::CPath("/b".to_string()),
+        ];
+        db::save_profile(&conn, &profile)?;
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+
+        let mut parts = select::current_var_parts(&app, "CPATH");
+        let picked = parts.remove(0);
+        app.splice_var_parts(0, "CPATH", parts);
+        assert_eq!(db::load_profile(&app.conn, "p")?.entries.len(), 2);
+
+        let mut parts = select::current_var_parts(&app, "CPATH");
+        parts.push(picked);
+        app.splice_var_parts(0, "CPATH", parts);
+        app.save_profiles(&[0])?;
+        let saved: Vec<String> = db::load_profile(&app.conn, "p")?
+            .entries
+            .iter()
+            .map(export::entry_value)
+            .collect();
+        assert_eq!(saved, ["/b", "/a"]);
+        Ok(())
+    }
+
+    #[test]
+    fn dropping_an_item_onto_a_scalar_replaces_its_value() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        profile.entries = vec![
+            Entry::CC("clang".to_string()).with_comment(Some("pinned".to_string())),
+            Entry::CFlag("-g".to_string()),
+        ];
+        db::save_profile(&conn, &profile)?;
+        let mut item = build_item("/usr/bin/gcc-13", ItemKind::Text, None, None, "")?;
+        db::save_item(&conn, &mut item, false)?;
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+        app.refresh_items()?;
+
+        app.replace_scalar_with_item("CC", &item)?;
+        assert_eq!(values(&app, "CC"), ["/usr/bin/gcc-13"]);
+        assert_eq!(values(&app, "CFLAGS"), ["-g"]);
+        let saved = db::load_profile(&app.conn, "p")?;
+        assert_eq!(select::var_parts(&saved, "CC").len(), 1);
+        assert_eq!(select::var_parts(&saved, "CC")[0].comment(), Some("pinned"));
+        assert_eq!(db::item_links(&app.conn, item.id.unwrap())?.len(), 1);
+        Ok(())
+    }
+
+    fn values(app: &AppState, var: &str) -> Vec<String> {
+        select::current_var_parts(app, var)
+            .iter()
+            .map(export::entry_value)
+            .collect()
+    }
+
+    #[test]
+    fn sorting_parts_orders_them_by_value_program_or_version() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        for (path, program, version) in [
+            ("/opt/gcc-13/bin", Some("gcc"), Some("13.2")),
+            ("/usr/local/bin", None, None),
+            ("/opt/clang/bin", Some("clang"), Some("17")),
+            ("/opt/gcc-9/bin", Some("gcc"), Some("9.4")),
+        ] {
+            profile.entries.push(Entry::Path(PathEntry {
+                path: path.to_string(),
+                program: program.map(str::to_string),
+                version: version.map(str::to_string),
+                mode: None,
+            }));
+        }
+        profile.entries.push(Entry::CFlag("-O2".to_string()));
+        db::save_profile(&conn, &profile)?;
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+
+        assert!(app.sort_var_parts("PATH", PartOrder::Version)?);
+        assert_eq!(
+            values(&app, "PATH"),
+            [
+                "/opt/gcc-9/bin",
+                "/opt/gcc-13/bin",
+                "/opt/clang/bin",
+                "/usr/local/bin"
+            ]
+        );
+        assert!(app.sort_var_parts("PATH", PartOrder::Program)?);
+        // Parts of the same program go by value.
+        assert_eq!(
+            values(&app, "PATH"),
+            [
+                "/opt/clang/bin",
+                "/opt/gcc-13/bin",
+                "/opt/gcc-9/bin",
+                "/usr/local/bin"
+            ]
+        );
+        assert!(!app.sort_var_parts("PATH", PartOrder::Value)?);
+        let saved = db::load_profile(&app.conn, "p")?;
+        let saved: Vec<String> = saved.entries.iter().map(export::entry_value).collect();
+        assert_eq!(
+            saved,
+            [
+                "/opt/clang/bin",
+                "/opt/gcc-13/bin",
+                "/opt/gcc-9/bin",
+                "/usr/local/bin",
+                "-O2"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dedup_keeps_the_first_of_scattered_duplicates() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        profile.entries = ["/a", "/b", "/a", "/c", "/b", "/a"]
+            .into_iter()
+            .map(|v| Entry::CPath(v.to_string()))
+            .collect();
+        db::save_profile(&conn, &profile)?;
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+        app.part_selection_mut().toggle(4);
+
+        assert_eq!(app.dedup_var_parts("CPATH")?, 3);
+        assert_eq!(values(&app, "CPATH"), ["/a", "/b", "/c"]);
+        assert!(app.marked_parts().is_empty());
+        assert_eq!(db::load_profile(&app.conn, "p")?.entries.len(), 3);
+        assert_eq!(app.dedup_var_parts("CPATH")?, 0);
+
+        // A repeat with its own comment is a different part.
+        let noted = Entry::CPath("/a".to_string()).with_comment(Some("vendored".to_string()));
+        app.add_env_var(noted)?;
+        assert_eq!(app.dedup_var_parts("CPATH")?, 0);
+        assert_eq!(values(&app, "CPATH"), ["/a", "/b", "/c", "/a"]);
+        Ok(())
+    }
+
+    #[test]
+    fn cycling_export_mode_changes_the_export_line() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        profile.entries.push(Entry::Path(PathEntry {
+            path: "/opt/bin".to_string(),
+            program: None,
+            version: None,
+            mode: None,
+        }));
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+
+        assert_eq!(
+            app.export_line("PATH"),
+            "export PATH=\"/opt/bin${PATH:+:}${PATH}\";"
+        );
+        app.cycle_export_mode();
+        assert_eq!(app.effective_mode(), OperationMode::Append);
+        assert_eq!(
+            app.export_line("PATH"),
+            "export PATH=\"${PATH:+${PATH}:}/opt/bin\";"
+        );
+        app.cycle_export_mode();
+        assert_eq!(app.export_line("PATH"), "export PATH=\"/opt/bin\";");
+        app.cycle_export_mode();
+        assert_eq!(app.effective_mode(), OperationMode::Prepend);
+        Ok(())
+    }
+
+    #[test]
+    fn edits_are_recorded_and_can_be_rolled_back() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        db::save_profile(&conn, &EnvProfile::new("p"))?;
+        db::save_profile(&conn, &EnvProfile::new("q"))?;
+        let profiles = db::load_all_profiles(&conn)?;
+        let mut app = AppState::for_tests(conn, profiles)?;
+
+        for flag in ["-O2", "-g", "-fsanitize=address"] {
+            app.add_env_var(Entry::CFlag(flag.to_string()))?;
+        }
+        app.replace_var_parts("CFLAGS", Vec::new())?;
+        app.update_profile(0, "renamed".to_string())?;
+        app.refresh_history()?;
+        let actions: Vec<&str> = app.history.iter().map(|h| h.action.as_str()).collect();
+        assert_eq!(
+            actions,
+            [
+                "rename from p",
+                "edit CFLAGS",
+                "add CFLAGS",
+                "add CFLAGS",
+                "add CFLAGS"
+            ]
+        );
+
+        let wipe = app.history[1].id;
+        app.restore_from_history(wipe)?;
+        assert_eq!(app.profiles[0].name, "renamed");
+        assert_eq!(db::load_profile(&app.conn, "renamed")?.entries.len(), 3);
+        assert_eq!(app.history[0].action, format!("restore #{wipe}"));
+
+        app.delete_profile(1)?;
+        assert_eq!(db::load_history(&app.conn, "q")?[0].action, "delete");
+        Ok(())
+    }
+
+    #[test]
+    fn profile_default_mode_applies_until_a_mode_is_picked() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut app = AppState::for_tests(conn, vec![EnvProfile::new("p")])?;
+        assert_eq!(app.mode_label(), "prepend");
+
+        app.set_profile_mode(Some(OperationMode::Append))?;
+        assert_eq!(app.effective_mode(), OperationMode::Append);
+        assert_eq!(app.mode_label(), "append (profile)");
+        assert_eq!(
+            db::load_profile(&app.conn, "p")?.default_mode,
+            Some(OperationMode::Append)
+        );
+
+        // Cycling starts from the profile's mode and then overrides it.
+        app.cycle_export_mode();
+        assert_eq!(app.mode_label(), "replace");
+        // Storing a new default drops the session pick.
+        app.set_profile_mode(None)?;
+        assert_eq!(app.export_mode, None);
+        assert_eq!(app.effective_mode(), OperationMode::Prepend);
+        Ok(())
+    }
+
+    #[test]
+    fn parents_show_up_in_exports_but_not_among_own_parts() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut child = EnvProfile::new("child");
+        child.entries.push(Entry::CFlag("-g".to_string()));
+        let mut base = EnvProfile::new("base");
+        base.entries.push(Entry::CFlag("-O2".to_string()));
+        let mut app = AppState::for_tests(conn, vec![child, base])?;
+
+        app.set_profile_parent(Some("base".to_string()))?;
+        assert_eq!(
+            app.export_line("CFLAGS"),
+            "export CFLAGS=\"-O2 -g${CFLAGS:+ }${CFLAGS}\";"
+        );
+        assert_eq!(app.inherited_entries().len(), 1);
+        assert_eq!(select::current_var_parts(&app, "CFLAGS").len(), 1);
+        assert_eq!(
+            db::load_profile(&app.conn, "child")?.extends.as_deref(),
+            Some("base")
+        );
+
+        // base cannot extend its own child, nor can a profile extend a missing one.
+        app.active_profile_index = 1;
+        assert!(app.set_profile_parent(Some("child".to_string())).is_err());
+        assert!(app.set_profile_parent(Some("gone".to_string())).is_err());
+        assert_eq!(app.profiles[1].extends, None);
+        Ok(())
+    }
+
+    #[test]
+    fn hash_filters_profiles_by_tag() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let names = ["arm-gcc", "x86-musl", "armhf-clang"];
+        let profiles = names.iter().map(|n| EnvProfile::new(n)).collect();
+        let mut app = AppState::for_tests(conn, profiles)?;
+        app.tag_profile("arm", true)?;
+        app.active_profile_index = 2;
+        app.tag_profile("ARM", true)?;
+        app.tag_profile("work", true)?;
+        assert!(!app.tag_profile("work", true)?);
+
+        app.profiles_filter = "#arm".to_string();
+        assert_eq!(select::visible_profile_indices(&app), [0, 2]);
+        app.profiles_filter = "#wo".to_string();
+        assert_eq!(select::visible_profile_indices(&app), [2]);
+        app.profiles_filter = "musl".to_string();
+        assert_eq!(select::visible_profile_indices(&app), [1]);
+
+        app.tag_profile("work", false)?;
+        assert_eq!(db::load_profile(&app.conn, "armhf-clang")?.tags, ["ARM"]);
+        Ok(())
+    }
+
+    #[test]
+    fn tilde_is_rewritten_for_export_and_optionally_at_save() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        profile.entries.push(Entry::Path(PathEntry {
+            path: "~/tools/bin".to_string(),
+            program: None,
+            version: None,
+            mode: None,
+        }));
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+        assert_eq!(
+            app.export_line("PATH"),
+            "export PATH=\"$HOME/tools/bin${PATH:+:}${PATH}\";"
+        );
+
+        let edited = || Entry::CC("~/bin/gcc".to_string());
+        let saved = |app: &AppState| export::entry_value(&app.normalize_saved(edited()));
+        assert_eq!(saved(&app), "~/bin/gcc");
+        app.expand_home_at_save = true;
+        if let Ok(home) = std::env::var("HOME") {
+            assert_eq!(saved(&app), format!("{home}/bin/gcc"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn normalizing_rewrites_parts_after_a_def_changed() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut profile = EnvProfile::new("p");
+        for value in ["a", "b"] {
+            profile.entries.push(Entry::CustomPart {
+                name: "MYLIST".to_string(),
+                value: value.to_string(),
+                separator: ";".to_string(),
+                mode: None,
+            });
+        }
+        db::save_profile(&conn, &profile)?;
+        db::save_custom_var_def(&conn, &CustomVarDef::new("MYLIST", VarKind::List, ":"))?;
+        let mut app = AppState::for_tests(conn, vec![profile])?;
+        app.refresh_var_options()?;
+        app.note_separator_mismatches();
+        assert_eq!(
+            app.status,
+            "separator mismatch: MYLIST in p (:fix-separators rewrites them)"
+        );
+        let stored = db::load_profile(&app.conn, "p")?;
+        assert!(stored.entries.iter().any(|e| e.separator() != ":"));
+
+        app.normalize_separators()?;
+
+        assert_eq!(
+            app.status,
+            "separator mismatch fixed: MYLIST in p now uses \":\""
+        );
+        let stored = db::load_profile(&app.conn, "p")?;
+        assert!(stored.entries.iter().all(|e| e.separator() == ":"));
+        Ok(())
+    }
+
+    #[test]
+    fn editing_a_def_moves_its_parts_to_the_new_separator() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut def = CustomVarDef::new("MYLIST", VarKind::List, ":");
+        db::save_custom_var_def(&conn, &def)?;
+        let mut profiles = Vec::new();
+        for name in ["p", "q"] {
+            let mut profile = EnvProfile::new(name);
+            profile.entries = vec![
+                Entry::CustomPart {
+                    name: "MYLIST".to_string(),
+                    value: format!("{name}1"),
+                    separator: ":".to_string(),
+                    mode: None,
+                },
+                Entry::CFlag("-g".to_string()),
+            ];
+            db::save_profile(&conn, &profile)?;
+            profiles.push(profile);
+        }
+        let mut app = AppState::for_tests(conn, profiles)?;
+        app.refresh_var_options()?;
+
+        def.separator = ";".to_string();
+        def.default_value = Some("x".to_string());
+        app.update_custom_var_def(&def)?;
+        assert_eq!(app.custom_var_defs, [def]);
+        assert_eq!(select::var_option_for(&app, "MYLIST").separator, ";");
+        for name in ["p", "q"] {
+            let stored = db::load_profile(&app.conn, name)?;
+            assert_eq!(stored.entries[0].separator(), ";");
+            assert_eq!(export::entry_value(&stored.entries[1]), "-g");
+        }
+        assert_eq!(app.profiles[1].entries[0].separator(), ";");
+        Ok(())
+    }
+
+    #[test]
+    fn marks_follow_their_parts_and_stay_with_their_var() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        db::initialize_db(&conn)?;
+        let mut app = AppState::for_tests(conn, vec![EnvProfile::new("p")])?;
+        app.selected_var_name = Some("CPATH".to_string());
+        let marks = |app: &AppState| app.marked_parts().into_iter().collect::<Vec<_>>();
+
+        let selection = app.part_selection_mut();
+        for i in [1, 3, 4] {
+            selection.toggle(i);
+        }
+        selection.removed(&[0, 3]);
+        assert_eq!(marks(&app), [0, 2]);
+        app.part_selection_mut().inserted(1, 2);
+        app.part_selection_mut().swapped(3, 4);
+        assert_eq!(marks(&app), [0, 3]);
+
+        app.part_selection_mut().start_visual(5);
+        app.part_selection_mut().extend_visual([5, 6]);
+        app.part_selection_mut().extend_visual([4, 5]);
+        assert_eq!(marks(&app), [0, 3, 4, 5]);
+
+        app.selected_var_name = Some("PATH".to_string());
+        assert!(marks(&app).is_empty());
+        app.part_selection_mut().toggle(0);
+        app.selected_var_name = Some("CPATH".to_string());
+        assert!(marks(&app).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn command_history_is_capped_without_repeats_and_kept_on_disk() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("bath-history-{}", std::process::id()));
+        let path = dir.join("command_history");
+        let mut history = CommandHistory::load(path.clone());
+        assert!(history.entries.is_empty());
+        for cmd in ["vars", " use gcc ", "use gcc", "vars"] {
+            history.push(cmd)?;
+        }
+        assert_eq!(history.entries, ["vars", "use gcc", "vars"]);
+        for i in 0..CommandHistory::LIMIT {
+            history.push(&format!("use p{i}"))?;
+        }
+        assert_eq!(history.entries.len(), CommandHistory::LIMIT);
+        assert_eq!(history.entries[0], "use p0");
+
+        let reloaded = CommandHistory::load(path);
+        assert_eq!(reloaded.entries, history.entries);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
 }
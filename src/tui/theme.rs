@@ -1,7 +1,9 @@
+use crate::config::config_path;
 use crate::tui::daisyui_themes;
 use crate::tui::daisyui_themes::ColorScheme;
+use crate::validate::Severity;
 use anyhow::{anyhow, Context, Result};
-use ratatui::style::{Color, Style};
+use ratatui::style::{Color, Modifier, Style};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
@@ -82,6 +84,20 @@ impl Theme {
             .fg(self.colors.primary_content)
     }
 
+    /// Characters a list filter matched.
+    pub fn filter_match(&self) -> Style {
+        Style::default()
+            .fg(self.colors.accent)
+            .add_modifier(Modifier::BOLD)
+    }
+
+    /// Rows marked for a bulk operation.
+    pub fn marked(&self) -> Style {
+        Style::default()
+            .bg(self.colors.secondary)
+            .fg(self.colors.secondary_content)
+    }
+
     pub fn dim_text(&self) -> Style {
         Style::default()
             // Use `neutral` (not `neutral_content`) so it's readable on base backgrounds
@@ -102,6 +118,15 @@ impl Theme {
             .fg(self.colors.base_content)
             .bg(self.colors.base_100)
     }
+
+    /// Text flagging a problem of the given severity.
+    pub fn issue_text(&self, severity: Severity) -> Style {
+        let fg = match severity {
+            Severity::Warning => self.colors.warning,
+            Severity::Error => self.colors.error,
+        };
+        Style::default().fg(fg).bg(self.colors.base_100)
+    }
 }
 
 pub fn default_preset() -> &'static str {
@@ -239,18 +264,6 @@ pub fn resolve_theme(preset: &str, overrides: Option<&ThemeSection>) -> Result<T
     })
 }
 
-fn config_path() -> Option<PathBuf> {
-    let base = if let Ok(xdg) = std::env::var("XDG_CONFIG_HOME") {
-        PathBuf::from(xdg)
-    } else if let Ok(home) = std::env::var("HOME") {
-        PathBuf::from(home).join(".config")
-    } else {
-        return None;
-    };
-
-    Some(base.join("bath").join("config.toml"))
-}
-
 fn parse_css_color(s: &str) -> Result<Color> {
     let s = s.trim();
     if s.is_empty() {
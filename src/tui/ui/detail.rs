@@ -1,3 +1,5 @@
+use crate::config::time_ago;
+use crate::db;
 use crate::export;
 use crate::tui::select;
 use crate::tui::state::AppState;
@@ -16,6 +18,7 @@ pub fn draw<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, area: Rect,
         View::Defs => details_defs(app),
         View::Preview => details_vars(app),
         View::Export => details_vars(app),
+        View::History => details_history(app),
         View::Help => {
             "Use :profiles, :vars, :parts, :items, :defs\nUse / to filter the current view.\n"
                 .to_string()
@@ -31,6 +34,33 @@ pub fn draw<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, area: Rect,
     f.render_widget(p, area);
 }
 
+/// The selected change as the entries it removed (`-`) and added (`+`).
+fn details_history(app: &AppState) -> String {
+    let selected = app.history_list_state.selected();
+    let Some(h) = selected.and_then(|i| app.history.get(i)) else {
+        return "No recorded changes. Edits made here are listed as they happen.".to_string();
+    };
+    let lines = |p: &Option<crate::config::EnvProfile>| -> Vec<String> {
+        p.iter()
+            .flat_map(|p| &p.entries)
+            .map(|e| format!("{} {}", e.var_name(), export::entry_value(e)))
+            .collect()
+    };
+    let (before, after) = (lines(&h.before), lines(&h.after));
+    let mut text = format!("Change #{}: {}\n\n", h.id, h.action);
+    for line in before.iter().filter(|l| !after.contains(l)) {
+        text.push_str(&format!("- {line}\n"));
+    }
+    for line in after.iter().filter(|l| !before.contains(l)) {
+        text.push_str(&format!("+ {line}\n"));
+    }
+    if before.iter().all(|l| after.contains(l)) && after.iter().all(|l| before.contains(l)) {
+        text.push_str("(same entries; order, description or mode changed)\n");
+    }
+    text.push_str("\nR puts the profile back to before this change.");
+    text
+}
+
 fn details_profiles(app: &AppState) -> String {
     let selected = select::selected_profile_index(app).unwrap_or(app.active_profile_index);
     let p = app.profiles.get(selected);
@@ -38,11 +68,44 @@ fn details_profiles(app: &AppState) -> String {
         return "No profiles.".to_string();
     };
 
-    let full = export::generate_full_export(p, export::OperationMode::Prepend);
+    let full = app.export_script_of(&app.expanded_profile_at(selected));
     let preview = full.lines().take(12).collect::<Vec<_>>().join("\n");
+    let age = match app.profile_meta(&p.name) {
+        Some(meta) => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs() as i64);
+            format!(
+                "Updated: {}\nCreated: {}\n",
+                time_ago(meta.updated_at, now),
+                time_ago(meta.created_at, now)
+            )
+        }
+        None => String::new(),
+    };
+
+    let description = p
+        .description
+        .as_deref()
+        .map(|d| format!("{d}\n"))
+        .unwrap_or_default();
+    // Nearest parent first, e.g. "base-debug <- base".
+    let extends = match (&p.extends, app.profile_chain(&p.name)) {
+        (None, _) => String::new(),
+        (Some(_), Ok(chain)) => {
+            let parents: Vec<&str> = chain
+                .iter()
+                .rev()
+                .skip(1)
+                .map(|c| c.name.as_str())
+                .collect();
+            format!("Extends: {}\n", parents.join(" <- "))
+        }
+        (Some(parent), Err(e)) => format!("Extends: {parent} ({e})\n"),
+    };
 
     format!(
-        "Profile: {}\nEntries: {}\n\nExport (first lines):\n{}",
+        "Profile: {}\n{description}{extends}Entries: {}\n{age}\nExport (first lines):\n{}",
         p.name,
         p.entries.len(),
         preview
@@ -54,29 +117,26 @@ fn details_vars(app: &AppState) -> String {
         .selected_var_name
         .clone()
         .unwrap_or_else(|| "PATH".to_string());
-    let parts = select::current_var_parts(app, &var);
     let sep = app
         .var_options
         .iter()
         .find(|o| o.name == var)
         .map(|o| o.separator.clone())
         .unwrap_or_else(|| ":".to_string());
+    let parts = select::var_parts(&app.expanded_profile(), &var);
     let joined = parts
         .iter()
-        .map(select::preview_value)
+        .filter(|e| e.is_exported())
+        .map(select::shell_value)
         .collect::<Vec<_>>()
         .join(&sep);
-    let profile = &app.profiles[app.active_profile_index];
-    let export_all = export::generate_full_export(profile, export::OperationMode::Prepend);
-    let export_line = export_all
-        .lines()
-        .find(|l| l.starts_with(&format!("export {var}=")))
-        .unwrap_or("");
+    let export_line = app.export_line(&var);
 
     format!(
-        "Var: {var}\nParts: {}\nSeparator: '{}'\n\nPreview:\n{joined}\n\nExport:\n{export_line}\n",
+        "Var: {var}\nParts: {}\nSeparator: '{}'\n\nPreview:\n{joined}\n\nExport ({}):\n{export_line}\n",
         parts.len(),
-        sep
+        sep,
+        app.mode_label()
     )
 }
 
@@ -102,9 +162,17 @@ fn details_parts(app: &AppState) -> String {
     };
 
     format!(
-        "Var: {var}\nIndex: {idx}\nEntry:\n{}\n\nValue:\n{}\n",
+        "Var: {var}\nIndex: {idx}\nEntry:\n{}\n\nValue:\n{}\n{}\n{}\n",
         entry,
-        select::preview_value(entry)
+        select::preview_value(entry),
+        entry
+            .comment()
+            .map_or(String::new(), |c| format!("\nComment:\n{c}\n")),
+        if entry.is_enabled() {
+            "Enabled (x to disable)"
+        } else {
+            "Disabled: left out of exports (x to enable)"
+        }
     )
 }
 
@@ -117,8 +185,37 @@ fn details_items(app: &AppState) -> String {
         return "No item selected.".to_string();
     };
 
+    let used = match it.last_used_at {
+        Some(then) => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs() as i64);
+            format!("{} times, last {}", it.use_count, time_ago(then, now))
+        }
+        None => "never".to_string(),
+    };
+
+    let mut where_used = String::new();
+    if app.show_item_links {
+        let links = it
+            .id
+            .map(|id| db::item_links(&app.conn, id))
+            .transpose()
+            .unwrap_or_default()
+            .unwrap_or_default();
+        where_used = if links.is_empty() {
+            "Where used: nowhere\n".to_string()
+        } else {
+            let lines: Vec<String> = links
+                .iter()
+                .map(|l| format!("  {}  {}  {}", l.profile, l.var, l.value))
+                .collect();
+            format!("Where used:\n{}\n", lines.join("\n"))
+        };
+    }
+
     format!(
-        "Kind: {:?}\nValue: {}\nProgram: {}\nVersion: {}\nTags: {}\n",
+        "Kind: {:?}\nValue: {}\nProgram: {}\nVersion: {}\nTags: {}\nUsed: {}\n{}",
         it.kind,
         it.value,
         it.program.clone().unwrap_or_default(),
@@ -127,18 +224,14 @@ fn details_items(app: &AppState) -> String {
             String::new()
         } else {
             it.tags.join(", ")
-        }
+        },
+        used,
+        where_used
     )
 }
 
 fn details_defs(app: &AppState) -> String {
-    // Mirror the same ordering/filtering logic as the main list.
-    let mut defs = app.var_options.clone();
-    defs.sort_by(|a, b| a.name.cmp(&b.name));
-    if !app.defs_filter.is_empty() {
-        let q = app.defs_filter.to_lowercase();
-        defs.retain(|d| d.name.to_lowercase().contains(&q));
-    }
+    let defs = select::visible_defs(app);
 
     let sel = app.defs_list_state.selected().unwrap_or(0);
     let Some(def) = defs.get(sel) else {
@@ -1,3 +1,4 @@
+use crate::tui::mouse::tab_label;
 use crate::tui::state::AppState;
 use crate::tui::view::View;
 use ratatui::{
@@ -5,8 +6,13 @@ use ratatui::{
     text::{Span, Spans},
     widgets::{Block, Borders, Paragraph},
 };
+use unicode_width::UnicodeWidthStr;
 
-pub fn draw<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, area: Rect, app: &AppState) {
+pub fn draw<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    area: Rect,
+    app: &mut AppState,
+) {
     let profile_name = app
         .profiles
         .get(app.active_profile_index)
@@ -23,10 +29,29 @@ pub fn draw<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, area: Rect,
     };
 
     let context = format!(
-        "Profile: {profile_name} | View: {} | Var: {var_name}{}",
-        app.active_view.title(),
+        " Profile: {profile_name} | Var: {var_name} | Mode: {}{} ",
+        app.mode_label(),
         filter_s
     );
+    // The context comes first so a narrow terminal cuts off tabs rather than
+    // the profile name. The view names after it are tabs, see `mouse::view_tabs`.
+    let context_w = (context.width() as u16).min(area.width);
+    app.header_area = Rect {
+        x: area.x + context_w,
+        width: area.width - context_w,
+        ..area
+    };
+    let tabs = View::ALL.iter().map(|&view| {
+        let style = if view == app.active_view {
+            app.theme.list_highlight()
+        } else {
+            app.theme.dim_text()
+        };
+        Span::styled(tab_label(view), style)
+    });
+    let line1: Vec<Span> = std::iter::once(Span::styled(context, app.theme.text()))
+        .chain(tabs)
+        .collect();
 
     let hints = view_hints(app.active_view, app);
     let cmd_hints = command_hints();
@@ -38,7 +63,7 @@ pub fn draw<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, area: Rect,
     let line2 = format!("{hints}{status}");
 
     let text = vec![
-        Spans::from(Span::styled(context, app.theme.text())),
+        Spans::from(line1),
         Spans::from(Span::styled(line2, app.theme.dim_text())),
         Spans::from(Span::styled(cmd_hints, app.theme.dim_text())),
     ];
@@ -58,30 +83,33 @@ fn active_filter(app: &AppState) -> &str {
         View::Parts => app.parts_filter.as_str(),
         View::Items => app.items_filter.as_str(),
         View::Defs => app.defs_filter.as_str(),
-        View::Preview | View::Export | View::Help => "",
+        View::Preview | View::Export | View::History | View::Help => "",
     }
 }
 
 fn view_hints(view: View, _app: &AppState) -> String {
     match view {
         View::Profiles => {
-            "A:add E:rename D:del Enter:use  j/k:move  G:top g:bot  /:filter  ::cmd  q:quit"
+            "A:add E:rename D:del J/K:reorder Enter:use  j/k:move  gg:top G:bottom ^d/^u:half-page  /:filter  ::cmd  q:quit"
                 .to_string()
         }
-        View::Vars => "Enter:parts p:drop-held  j/k:move  G:top g:bot  /:filter  ::cmd  q:quit"
+        View::Vars => "Enter:parts p:drop-held  j/k:move  gg:top G:bottom ^d/^u:half-page  /:filter  ::cmd  q:quit"
             .to_string(),
-        View::Parts => "a:add e:edit d:del y:dup J/K:move m:pick p:drop  j/k:move  G:top g:bot  /:filter  ::cmd  q:quit"
+        View::Parts => "a:add i:quick-add e:edit d:del y:dup x/v:mark space:on/off J/K:move M:mode m:pick p:drop  j/k:move  gg:top G:bottom ^d/^u:half-page  /:filter  ::cmd  q:quit"
             .to_string(),
-        View::Items => "a:add e:edit d:del y:dup m:pick p:drop  j/k:move  G:top g:bot  /:filter  ::cmd  q:quit"
+        View::Items => "a:add e:edit t:tags d:del y:dup space/v:mark m:pick p:drop s:sort w:where-used  j/k:move  gg:top G:bottom ^d/^u:half-page  /:filter  ::cmd  q:quit"
             .to_string(),
-        View::Defs => "C:new-var-def  j/k:move  G:top g:bot  /:filter  ::cmd  q:quit".to_string(),
-        View::Preview => "Shows preview for selected var  ::cmd  q:quit".to_string(),
-        View::Export => "Shows export line for selected var  ::cmd  q:quit".to_string(),
+        View::Defs => "Enter:parts C:new-var-def e:edit E:rename d:delete s:separator  j/k:move  gg:top G:bottom ^d/^u:half-page  /:filter  ::cmd  q:quit".to_string(),
+        View::Preview => "Shows preview for selected var  j/k:scroll  o:mode  ::cmd  q:quit".to_string(),
+        View::Export => "Shows the export script, selected var highlighted  j/k:scroll  y:copy var line Y:copy script  o:mode  ::cmd  q:quit".to_string(),
+        View::History => {
+            "R:restore-before  j/k:move  gg:top G:bottom ^d/^u:half-page  ::cmd  q:quit".to_string()
+        }
         View::Help => "?:toggle-help  ::cmd  q:quit".to_string(),
     }
 }
 
 fn command_hints() -> String {
     // Keep this short-ish so it fits most terminals.
-    "Commands: :profiles :vars :parts :items :defs :preview :export :themes :theme <name> :use <profile> :new-var :new-item :quit".to_string()
+    "Commands: :profiles :vars :parts :items :defs :preview :export :themes :theme <name> :use <profile> :copy <name> :new-profile :rename-profile :delete-profile :mode <m> :sort :dedup :export-dedup :new-var [NAME kind] :new-item [value #tag] :quit".to_string()
 }
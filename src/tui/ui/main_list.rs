@@ -1,11 +1,17 @@
-use crate::config::ItemKind;
+use crate::config::{Entry, ItemKind};
 use crate::tui::select;
 use crate::tui::state::AppState;
+use crate::tui::util::highlight_matches;
 use crate::tui::view::View;
+use crate::validate::{Issue, Severity};
 use ratatui::{
-    layout::Rect,
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::Style,
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Widget, Wrap},
 };
+use unicode_width::UnicodeWidthStr;
 
 pub fn draw<B: ratatui::backend::Backend>(
     f: &mut ratatui::Frame<B>,
@@ -20,6 +26,7 @@ pub fn draw<B: ratatui::backend::Backend>(
         View::Defs => draw_defs(f, area, app),
         View::Preview => draw_preview(f, area, app),
         View::Export => draw_export(f, area, app),
+        View::History => draw_history(f, area, app),
         View::Help => draw_help(f, area, app),
     }
 }
@@ -30,32 +37,46 @@ fn draw_profiles<B: ratatui::backend::Backend>(
     app: &mut AppState,
 ) {
     let indices = select::visible_profile_indices(app);
-    let items: Vec<ListItem> = indices
+    // A `#tag` filter matches tags, not names.
+    let query = if app.profiles_filter.starts_with('#') {
+        ""
+    } else {
+        app.profiles_filter.as_str()
+    };
+    let mut items: Vec<ListItem> = indices
         .iter()
         .map(|i| {
             let p = &app.profiles[*i];
             let active = *i == app.active_profile_index;
-            let name = if active {
-                format!("* {}", p.name)
-            } else {
-                format!("  {}", p.name)
-            };
-            ListItem::new(name)
+            let mut spans = vec![Span::raw(if active { "* " } else { "  " })];
+            spans.extend(highlight_matches(query, &p.name, app.theme.filter_match()));
+            if !p.tags.is_empty() {
+                let tags: Vec<String> = p.tags.iter().map(|t| format!("#{t}")).collect();
+                spans.push(Span::styled(
+                    format!("  {}", tags.join(" ")),
+                    app.theme.dim_text(),
+                ));
+            }
+            ListItem::new(Spans::from(spans))
         })
         .collect();
-
     select::clamp_list_state(&mut app.profile_list_state, items.len());
-    let list = List::new(items)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(app.theme.border())
-                .title("Profiles"),
-        )
-        .style(app.theme.text())
-        .highlight_style(app.theme.list_highlight())
-        .highlight_symbol("» ");
-    f.render_stateful_widget(list, area, &mut app.profile_list_state);
+
+    // Broken profiles go last and cannot be selected.
+    items.extend(
+        app.broken_profiles
+            .iter()
+            .filter(|b| select::profile_matches(&app.profiles_filter, &b.name, &[]))
+            .map(|b| {
+                ListItem::new(format!("! {}  (unreadable, :repair {})", b.name, b.name))
+                    .style(app.theme.dim_text())
+            }),
+    );
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(app.theme.border())
+        .title("Profiles");
+    draw_list(f, app, View::Profiles, area, items, block);
 }
 
 fn draw_vars<B: ratatui::backend::Backend>(
@@ -71,25 +92,33 @@ fn draw_vars<B: ratatui::backend::Backend>(
                 crate::config::VarKind::Scalar => "S",
                 crate::config::VarKind::List => "L",
             };
-            ListItem::new(format!(
-                "{}  {:<18}  {:>3}  sep='{}'",
-                badge, r.name, r.count, r.separator
-            ))
+            let undefined = if r.defined { "" } else { "  (undefined)" };
+            let mut spans = vec![Span::raw(format!("{badge}  "))];
+            spans.extend(highlight_matches(
+                &app.vars_filter,
+                &format!("{:<18}", r.name),
+                app.theme.filter_match(),
+            ));
+            spans.push(Span::raw(format!(
+                "  {:>3}  sep='{}'{}",
+                r.count, r.separator, undefined
+            )));
+            if r.inherited > 0 {
+                spans.push(Span::styled(
+                    format!("  +{} inherited", r.inherited),
+                    app.theme.dim_text(),
+                ));
+            }
+            ListItem::new(Spans::from(spans))
         })
         .collect();
 
     select::clamp_list_state(&mut app.vars_list_state, items.len());
-    let list = List::new(items)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(app.theme.border())
-                .title("Vars"),
-        )
-        .style(app.theme.text())
-        .highlight_style(app.theme.list_highlight())
-        .highlight_symbol("» ");
-    f.render_stateful_widget(list, area, &mut app.vars_list_state);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(app.theme.border())
+        .title("Vars");
+    draw_list(f, app, View::Vars, area, items, block);
 }
 
 fn draw_parts<B: ratatui::backend::Backend>(
@@ -101,25 +130,113 @@ fn draw_parts<B: ratatui::backend::Backend>(
         .selected_var_name
         .clone()
         .unwrap_or_else(|| "PATH".to_string());
+    let area = draw_inherited_parts(f, area, app, &var);
     let parts = select::current_var_parts(app, &var);
     let indices = select::visible_part_indices(app, &parts);
+    let issues = part_issues(app, &var);
+    let marked = app.marked_parts();
     let items: Vec<ListItem> = indices
         .iter()
-        .map(|i| ListItem::new(parts[*i].to_string()))
+        .map(|i| {
+            let part = &parts[*i];
+            let mut spans = highlight_matches(
+                &app.parts_filter,
+                &part.to_string(),
+                app.theme.filter_match(),
+            );
+            if let Some(priority) = part.priority() {
+                spans.push(Span::styled(
+                    format!(" (p={priority})"),
+                    app.theme.dim_text(),
+                ));
+            }
+            if let Some(comment) = part.comment() {
+                spans.push(Span::styled(format!("  # {comment}"), app.theme.dim_text()));
+            }
+            if let Some(issue) = &issues[*i] {
+                spans.push(Span::styled(
+                    format!("  ! {}", issue.message),
+                    app.theme.issue_text(issue.severity),
+                ));
+            }
+            let item = ListItem::new(Spans::from(spans));
+            if marked.contains(i) {
+                item.style(app.theme.marked())
+            } else if part.is_enabled() {
+                item
+            } else {
+                item.style(app.theme.dim_text())
+            }
+        })
         .collect();
 
     select::clamp_list_state(&mut app.parts_list_state, items.len());
-    let list = List::new(items)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(app.theme.border())
-                .title(format!("Parts for {var}")),
-        )
-        .style(app.theme.text())
-        .highlight_style(app.theme.list_highlight())
-        .highlight_symbol("» ");
-    f.render_stateful_widget(list, area, &mut app.parts_list_state);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(app.theme.border())
+        .title(if marked.is_empty() {
+            format!("Parts for {var}")
+        } else {
+            format!("Parts for {var} ({} marked)", marked.len())
+        });
+    draw_list(f, app, View::Parts, area, items, block);
+}
+
+/// Lists the parts of `var` the active profile inherits, dimmed, above the
+/// (editable) parts of its own. Returns the area left for those.
+fn draw_inherited_parts<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    area: Rect,
+    app: &AppState,
+    var: &str,
+) -> Rect {
+    let inherited: Vec<Spans> = app
+        .inherited_entries()
+        .into_iter()
+        .filter(|(_, e)| e.var_name() == var)
+        .map(|(owner, e)| {
+            Spans::from(Span::styled(
+                format!("{e}  (from {owner})"),
+                app.theme.dim_text(),
+            ))
+        })
+        .collect();
+    if inherited.is_empty() {
+        return area;
+    }
+    let height = (inherited.len() as u16 + 2).min(area.height / 2);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(height), Constraint::Min(0)])
+        .split(area);
+    let p = Paragraph::new(inherited).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(app.theme.border())
+            .title("Inherited (edit them in their profile)"),
+    );
+    f.render_widget(p, chunks[0]);
+    chunks[1]
+}
+
+/// The worst issue of each part of `var` in the active profile (the first of
+/// equally bad ones), in part order.
+fn part_issues(app: &AppState, var: &str) -> Vec<Option<Issue>> {
+    let issues = app.profile_issues();
+    app.profiles[app.active_profile_index]
+        .entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.var_name() == var)
+        .map(|(index, _)| {
+            issues
+                .iter()
+                .filter(|i| i.index == index)
+                .rev()
+                .max_by_key(|i| i.severity)
+                .cloned()
+        })
+        .collect()
 }
 
 fn draw_items<B: ratatui::backend::Backend>(
@@ -128,6 +245,7 @@ fn draw_items<B: ratatui::backend::Backend>(
     app: &mut AppState,
 ) {
     let indices = select::visible_item_indices(app);
+    let (_, query) = select::split_tag_filter(&app.items_filter);
     let items: Vec<ListItem> = indices
         .iter()
         .map(|i| {
@@ -141,22 +259,28 @@ fn draw_items<B: ratatui::backend::Backend>(
             } else {
                 format!("  [{}]", it.tags.join(","))
             };
-            ListItem::new(format!("{:<4} {}{}", k, it.value, tags))
+            let mut spans = vec![Span::raw(format!("{k:<4} "))];
+            spans.extend(highlight_matches(
+                &query,
+                &it.value,
+                app.theme.filter_match(),
+            ));
+            spans.extend(highlight_matches(&query, &tags, app.theme.filter_match()));
+            let item = ListItem::new(Spans::from(spans));
+            if it.id.is_some_and(|id| app.item_marks.contains(&id)) {
+                item.style(app.theme.marked())
+            } else {
+                item
+            }
         })
         .collect();
 
     select::clamp_list_state(&mut app.items_list_state, items.len());
-    let list = List::new(items)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(app.theme.border())
-                .title("Items"),
-        )
-        .style(app.theme.text())
-        .highlight_style(app.theme.list_highlight())
-        .highlight_symbol("» ");
-    f.render_stateful_widget(list, area, &mut app.items_list_state);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(app.theme.border())
+        .title(format!("Items (by {})", app.item_sort.name()));
+    draw_list(f, app, View::Items, area, items, block);
 }
 
 fn draw_defs<B: ratatui::backend::Backend>(
@@ -164,12 +288,7 @@ fn draw_defs<B: ratatui::backend::Backend>(
     area: Rect,
     app: &mut AppState,
 ) {
-    let mut defs = app.var_options.clone();
-    defs.sort_by(|a, b| a.name.cmp(&b.name));
-    if !app.defs_filter.is_empty() {
-        let q = app.defs_filter.to_lowercase();
-        defs.retain(|d| d.name.to_lowercase().contains(&q));
-    }
+    let defs = select::visible_defs(app);
     let items: Vec<ListItem> = defs
         .iter()
         .map(|d| {
@@ -177,25 +296,72 @@ fn draw_defs<B: ratatui::backend::Backend>(
                 crate::config::VarKind::Scalar => "Scalar",
                 crate::config::VarKind::List => "List",
             };
-            ListItem::new(format!(
-                "{:<18}  {:<6}  sep='{}'",
-                d.name, kind, d.separator
-            ))
+            let mut spans = highlight_matches(
+                &app.defs_filter,
+                &format!("{:<18}", d.name),
+                app.theme.filter_match(),
+            );
+            spans.push(Span::raw(format!("  {kind:<6}  sep='{}'", d.separator)));
+            if crate::config::builtin_var(&d.name).is_some() {
+                // Builtins cannot be edited or deleted, only joined differently.
+                if app.separator_overrides.contains_key(&d.name) {
+                    spans.push(Span::raw("  (builtin, overridden)"));
+                } else {
+                    spans.push(Span::raw("  (builtin)"));
+                }
+                ListItem::new(Spans::from(spans)).style(app.theme.dim_text())
+            } else {
+                ListItem::new(Spans::from(spans))
+            }
         })
         .collect();
 
     select::clamp_list_state(&mut app.defs_list_state, items.len());
-    let list = List::new(items)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(app.theme.border())
-                .title("Var defs"),
-        )
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(app.theme.border())
+        .title("Var defs");
+    draw_list(f, app, View::Defs, area, items, block);
+}
+
+/// Draws the `items` of `view`'s list in `block` with its selection
+/// highlighted, and notes where for mouse clicks. The rows are scrolled by the
+/// list's `ListArea`, which draws them from its offset on, so that clicks map
+/// to the rows on screen; ratatui's own offset cannot be read back.
+fn draw_list<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &mut AppState,
+    view: View,
+    area: Rect,
+    items: Vec<ListItem>,
+    block: Block,
+) {
+    let selected = app.list_state_mut(view).and_then(|s| s.selected());
+    let list_area = app.list_areas.entry(view).or_default();
+    list_area.update(area, selected, items.len());
+    let offset = list_area.offset;
+    let mut state = ListState::default();
+    state.select(selected.and_then(|s| s.checked_sub(offset)));
+    let list = List::new(items.into_iter().skip(offset).collect::<Vec<_>>())
+        .block(block)
         .style(app.theme.text())
         .highlight_style(app.theme.list_highlight())
         .highlight_symbol("» ");
-    f.render_stateful_widget(list, area, &mut app.defs_list_state);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn disabled_note(disabled: usize) -> String {
+    match disabled {
+        0 => String::new(),
+        n => format!("\n({n} disabled)"),
+    }
+}
+
+fn duplicates_note(hidden: usize) -> String {
+    match hidden {
+        0 => String::new(),
+        n => format!("\n({n} duplicates hidden)"),
+    }
 }
 
 fn draw_preview<B: ratatui::backend::Backend>(
@@ -207,28 +373,117 @@ fn draw_preview<B: ratatui::backend::Backend>(
         .selected_var_name
         .clone()
         .unwrap_or_else(|| "PATH".to_string());
-    let parts = select::current_var_parts(app, &var);
+    let profile = &app.expanded_profile();
+    let parts = select::var_parts(profile, &var);
     let sep = app
         .var_options
         .iter()
         .find(|o| o.name == var)
         .map(|o| o.separator.clone())
         .unwrap_or_else(|| ":".to_string());
-    let joined = parts
+    let enabled: Vec<Entry> = crate::export::in_priority_order(&parts)
+        .into_iter()
+        .filter(|e| e.is_exported())
+        .cloned()
+        .collect();
+    let (unique, hidden) = if app.export_dedup {
+        crate::export::dedup_entries(&enabled)
+    } else {
+        (enabled.clone(), 0)
+    };
+    let joined = unique
         .iter()
-        .map(select::preview_value)
+        .map(select::shell_value)
         .collect::<Vec<_>>()
         .join(&sep);
-    let text = format!("{var} = {joined}\n\n(parts: {})", parts.len());
-    let p = Paragraph::new(text).style(app.theme.text()).block(
-        Block::default()
-            .borders(Borders::ALL)
-            .border_style(app.theme.border())
-            .title("Preview"),
+    let composed = crate::export::compose_var(
+        profile,
+        &var,
+        app.effective_mode(),
+        &app.env,
+        &app.separator_overrides,
+    )
+    .unwrap_or_default();
+    // A defaulted var has the definition's default as its only part.
+    let (default_note, own_parts) = if app.uses_default(&var) {
+        ("  (default)", 0)
+    } else {
+        ("", parts.len())
+    };
+    let text = format!(
+        "{var} = {joined}{default_note}\n\n(parts: {own_parts}){}{}\n\nWith the current environment ({}):\n{composed}",
+        disabled_note(parts.len() - enabled.len()),
+        duplicates_note(hidden),
+        app.mode_label()
     );
+    let lines = text
+        .lines()
+        .map(|l| (l.to_string(), Style::default()))
+        .collect();
+    draw_scrolled(f, area, app, View::Preview, lines, "Preview", None);
+}
+
+/// Draws `lines`, each in its style, wrapped and scrolled as far as the
+/// view's `TextScroll` says, with the scroll position in the title. Line
+/// `reveal`, if any, is scrolled into view first.
+fn draw_scrolled<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    area: Rect,
+    app: &mut AppState,
+    view: View,
+    lines: Vec<(String, Style)>,
+    title: &str,
+    reveal: Option<usize>,
+) {
+    let width = area.width.saturating_sub(2);
+    let heights: Vec<usize> = lines
+        .iter()
+        .map(|(line, _)| wrapped_height(line, width))
+        .collect();
+    let scroll = app.text_scroll.entry(view).or_default();
+    scroll.update(heights.iter().sum(), area.height.saturating_sub(2) as usize);
+    if let Some(i) = reveal.filter(|&i| i < heights.len()) {
+        scroll.reveal(heights[..i].iter().sum(), heights[i]);
+    }
+    let (offset, indicator) = (scroll.offset, scroll.indicator());
+    let text: Vec<Spans> = lines
+        .into_iter()
+        .map(|(line, style)| Spans::from(Span::styled(line, style)))
+        .collect();
+    let p = Paragraph::new(Text::from(text))
+        .style(app.theme.text())
+        .wrap(Wrap { trim: false })
+        .scroll((offset.min(u16::MAX as usize) as u16, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(app.theme.border())
+                .title(format!("{title}{indicator}")),
+        );
     f.render_widget(p, area);
 }
 
+/// How many rows `line` takes in a `Paragraph` wrapped to `width` columns
+/// without trimming, as ratatui lays it out.
+fn wrapped_height(line: &str, width: u16) -> usize {
+    if width == 0 {
+        return 0;
+    }
+    // Two rows of a wrapped line hold more than a row's width between them,
+    // and one more row holds the marker drawn after the line.
+    let bound = 2 * (line.width() / width as usize + 1) + 1;
+    let area = Rect::new(0, 0, width, bound.min(u16::MAX as usize) as u16);
+    let mut buf = Buffer::empty(area);
+    Paragraph::new(Text::from(vec![Spans::from(line), Spans::from("|")]))
+        .wrap(Wrap { trim: false })
+        .render(area, &mut buf);
+    // The marker is the last thing drawn, on the row after the line.
+    (0..area.height)
+        .rev()
+        .find(|&y| (0..width).any(|x| buf.get(x, y).symbol != " "))
+        .map_or(0, usize::from)
+}
+
 fn draw_export<B: ratatui::backend::Backend>(
     f: &mut ratatui::Frame<B>,
     area: Rect,
@@ -238,21 +493,76 @@ fn draw_export<B: ratatui::backend::Backend>(
         .selected_var_name
         .clone()
         .unwrap_or_else(|| "PATH".to_string());
-    let profile = &app.profiles[app.active_profile_index];
-    let full = crate::export::generate_full_export(profile, crate::export::OperationMode::Prepend);
-    let line = full
+    let title = format!("Export ({})", app.mode_label());
+    // `bath export` fails for such a profile, so there is no script to show.
+    if let Some(e) = app.broken_chain() {
+        let lines = vec![(
+            format!("cannot export: {e}"),
+            app.theme.issue_text(Severity::Error),
+        )];
+        draw_scrolled(f, area, app, View::Export, lines, &title, None);
+        return;
+    }
+    let export = app.export_view();
+    // The selected var's statements stand out, as `y` copies its export line.
+    let own = [format!("export {var}="), format!("unset {var};")];
+    let is_own = |line: &str| own.iter().any(|p| line.starts_with(p.as_str()));
+    let mut lines: Vec<(String, Style)> = export
+        .script
         .lines()
-        .find(|l| l.starts_with(&format!("export {var}=")))
-        .unwrap_or("");
-    let p = Paragraph::new(line.to_string())
-        .style(app.theme.text())
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(app.theme.border())
-                .title("Export"),
+        .chain(duplicates_note(export.hidden).lines().skip(1))
+        .map(|l| {
+            let style = if is_own(l) {
+                app.theme.list_highlight()
+            } else {
+                Style::default()
+            };
+            (l.to_string(), style)
+        })
+        .collect();
+    if !export.undo.is_empty() {
+        lines.push((String::new(), Style::default()));
+        lines.push(("Undo:".to_string(), Style::default()));
+        lines.extend(
+            export
+                .undo
+                .lines()
+                .map(|l| (l.to_string(), Style::default())),
         );
-    f.render_widget(p, area);
+    }
+    // Another selected var is scrolled to once; after that the view scrolls
+    // freely.
+    let reveal = if app.export_revealed.as_deref() == Some(var.as_str()) {
+        None
+    } else {
+        let line = lines.iter().position(|(l, _)| is_own(l));
+        app.export_revealed = Some(var);
+        line
+    };
+    draw_scrolled(f, area, app, View::Export, lines, &title, reveal);
+}
+
+fn draw_history<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    area: Rect,
+    app: &mut AppState,
+) {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let items: Vec<ListItem> = app
+        .history
+        .iter()
+        .map(|h| ListItem::new(crate::history::history_line(h, now)))
+        .collect();
+
+    select::clamp_list_state(&mut app.history_list_state, items.len());
+    let name = &app.profiles[app.active_profile_index].name;
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(app.theme.border())
+        .title(format!("History of {name}"));
+    draw_list(f, app, View::History, area, items, block);
 }
 
 fn draw_help<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, area: Rect, app: &AppState) {
@@ -269,10 +579,14 @@ fn draw_help<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, area: Rect
   :  command palette (jump to views, actions)
   /  filter current view
   Tab  cycle views
+  j/k  move down/up
+  gg/Home  first row
+  G/End  last row
+  Ctrl+d/Ctrl+u  half a page down/up
   q  quit
 
 Views
-  profiles  vars  parts  items  defs  preview  export  help
+  profiles  vars  parts  items  defs  preview  export  history  help
 
 Theme
   current: {current}
@@ -2,7 +2,11 @@ pub mod detail;
 pub mod header;
 pub mod main_list;
 
+use crate::dialect::Posix;
+use crate::export;
+use crate::tui::input;
 use crate::tui::state::{AppState, InputMode};
+use crate::tui::util::set_input_cursor;
 use ratatui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
@@ -34,23 +38,29 @@ pub fn draw_main_ui<B: Backend>(f: &mut ratatui::Frame<B>, app: &mut AppState) {
     draw_overlays(f, size, app);
 }
 
+/// The full-width strip of `size` at its bottom, `h` rows high or as high as
+/// `size` if that is less.
+fn bottom_overlay(size: Rect, h: u16) -> Rect {
+    let h = h.min(size.height);
+    Rect {
+        x: size.x,
+        y: size.y + size.height - h,
+        width: size.width,
+        height: h,
+    }
+}
+
 fn draw_overlays<B: Backend>(f: &mut ratatui::Frame<B>, size: Rect, app: &mut AppState) {
     if matches!(app.input_mode, InputMode::Command) {
         let sugg = app.command_suggestions.len().min(8);
-        let overlay_h = (sugg + 2) as u16; // prompt + suggestions
-        let overlay_h = overlay_h.min(size.height);
-        let overlay = Rect {
-            x: size.x,
-            y: size.y + size.height.saturating_sub(overlay_h),
-            width: size.width,
-            height: overlay_h,
-        };
+        // prompt + suggestions, both framed
+        let overlay = bottom_overlay(size, (sugg + 5) as u16);
 
         f.render_widget(Clear, overlay);
 
         let chunks = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
+            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
             .split(overlay);
 
         let prompt = Paragraph::new(format!(":{}", app.command_input))
@@ -59,9 +69,13 @@ fn draw_overlays<B: Backend>(f: &mut ratatui::Frame<B>, size: Rect, app: &mut Ap
                 Block::default()
                     .borders(Borders::ALL)
                     .border_style(app.theme.border())
-                    .title("Command"),
+                    .title(match app.command_hint {
+                        Some(hint) => format!("Command: {hint}"),
+                        None => "Command".to_string(),
+                    }),
             );
         f.render_widget(prompt, chunks[0]);
+        set_input_cursor(f, chunks[0], 0, ":", &app.command_input);
 
         let items: Vec<ListItem> = app
             .command_suggestions
@@ -90,13 +104,7 @@ fn draw_overlays<B: Backend>(f: &mut ratatui::Frame<B>, size: Rect, app: &mut Ap
 
         f.render_stateful_widget(list, chunks[1], &mut state);
     } else if matches!(app.input_mode, InputMode::Search) {
-        let overlay_h = 3u16.min(size.height);
-        let overlay = Rect {
-            x: size.x,
-            y: size.y + size.height.saturating_sub(overlay_h),
-            width: size.width,
-            height: overlay_h,
-        };
+        let overlay = bottom_overlay(size, 3);
         f.render_widget(Clear, overlay);
 
         let prompt = Paragraph::new(format!("/{}", app.command_input))
@@ -108,5 +116,50 @@ fn draw_overlays<B: Backend>(f: &mut ratatui::Frame<B>, size: Rect, app: &mut Ap
                     .title("Filter (Enter: apply, Esc: cancel)"),
             );
         f.render_widget(prompt, overlay);
+        set_input_cursor(f, overlay, 0, "/", &app.command_input);
+    } else if matches!(app.input_mode, InputMode::QuickAdd) {
+        let overlay = bottom_overlay(size, 3);
+        f.render_widget(Clear, overlay);
+
+        let var = app.selected_var_name.as_deref().unwrap_or("PATH");
+        // What the part would export on its own.
+        let title = match input::quick_add_entry(app, var, app.command_input.as_str()) {
+            Some(entry) => {
+                export::generate_export_line(&Posix::default(), &entry, app.effective_mode())
+            }
+            None => format!("Add to {var} (Enter: add, Esc: cancel)"),
+        };
+        let prompt = Paragraph::new(format!("+{}", app.command_input))
+            .style(app.theme.text())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(app.theme.border())
+                    .title(title),
+            );
+        f.render_widget(prompt, overlay);
+        set_input_cursor(f, overlay, 0, "+", &app.command_input);
+    } else if let (InputMode::Tags, Some(edit)) = (&app.input_mode, &app.tag_edit) {
+        let overlay = bottom_overlay(size, 3);
+        f.render_widget(Clear, overlay);
+
+        let value = app
+            .items
+            .iter()
+            .find(|it| it.id == Some(edit.item))
+            .map_or("", |it| it.value.as_str());
+        let chips: String = edit.tags.iter().map(|t| format!("[{t}] ")).collect();
+        let prompt = Paragraph::new(format!("{chips}#{}", app.command_input))
+            .style(app.theme.text())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(app.theme.border())
+                    .title(format!(
+                        "Tags of {value} (Enter: add, again: save, Backspace: remove, Tab: complete, Esc: cancel)"
+                    )),
+            );
+        f.render_widget(prompt, overlay);
+        set_input_cursor(f, overlay, 0, &format!("{chips}#"), &app.command_input);
     }
 }
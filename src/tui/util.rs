@@ -1,4 +1,12 @@
+use crate::tui::fuzzy;
+use crate::tui::line_input::LineInput;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::backend::Backend;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::Style;
+use ratatui::text::Span;
+use ratatui::Frame;
+use unicode_width::UnicodeWidthStr;
 
 pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
@@ -25,3 +33,53 @@ pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         .split(popup_layout[1]);
     horizontal[1]
 }
+
+/// `text` as spans with the characters the filter `query` matched in
+/// `matched` style; one plain span when nothing matched.
+pub fn highlight_matches(query: &str, text: &str, matched: Style) -> Vec<Span<'static>> {
+    let positions = fuzzy::find(query, text).map(|m| m.positions);
+    fuzzy::segments(text, positions.as_deref().unwrap_or_default())
+        .into_iter()
+        .map(|(run, hit)| {
+            let style = if hit { matched } else { Style::default() };
+            Span::styled(run.to_string(), style)
+        })
+        .collect()
+}
+
+/// Shows the terminal cursor at `input`'s cursor, drawn on line `row` inside
+/// the bordered `area` after `prefix`. Text wider than the area is cut off by
+/// the paragraph, so the cursor stops at the right border.
+pub fn set_input_cursor<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    row: u16,
+    prefix: &str,
+    input: &LineInput,
+) {
+    if area.width < 3 || area.height < 3 {
+        return;
+    }
+    let column = (prefix.width() as u16).saturating_add(input.cursor_column());
+    f.set_cursor(
+        area.x + 1 + column.min(area.width - 3),
+        area.y + 1 + row.min(area.height - 3),
+    );
+}
+
+/// Whether `key` is Ctrl+C.
+pub fn is_ctrl_c(key: &KeyEvent) -> bool {
+    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// The key pressed in `event`, for the dialogs' event loops. Key releases
+/// (reported by Windows and kitty-protocol terminals) are dropped so keys do
+/// not act twice, and Ctrl+C is turned into Esc, which cancels every dialog.
+pub fn key_press(event: Event) -> Option<KeyEvent> {
+    match event {
+        Event::Key(key) if key.kind == KeyEventKind::Release => None,
+        Event::Key(key) if is_ctrl_c(&key) => Some(KeyEvent::from(KeyCode::Esc)),
+        Event::Key(key) => Some(key),
+        _ => None,
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum View {
     Profiles,
     Vars,
@@ -7,10 +7,24 @@ pub enum View {
     Defs,
     Preview,
     Export,
+    History,
     Help,
 }
 
 impl View {
+    /// Every view, in the order of the header tabs.
+    pub const ALL: [View; 9] = [
+        View::Profiles,
+        View::Vars,
+        View::Parts,
+        View::Items,
+        View::Defs,
+        View::Preview,
+        View::Export,
+        View::History,
+        View::Help,
+    ];
+
     pub fn title(self) -> &'static str {
         match self {
             View::Profiles => "Profiles",
@@ -20,6 +34,7 @@ impl View {
             View::Defs => "Defs",
             View::Preview => "Preview",
             View::Export => "Export",
+            View::History => "History",
             View::Help => "Help",
         }
     }
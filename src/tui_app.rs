@@ -1,5 +1,5 @@
 use anyhow::Result;
 
-pub fn run() -> Result<()> {
-    crate::tui::run()
+pub fn run(expand_home_at_save: bool) -> Result<()> {
+    crate::tui::run(expand_home_at_save)
 }
@@ -0,0 +1,764 @@
+// src/validate.rs
+
+use crate::config::{self, lookup_var_option, CustomVarDef, Entry, EnvProfile, VarKind};
+use crate::db;
+use crate::export::{self, entry_value};
+use crate::template;
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    /// A part of a path-like var points at something that does not exist.
+    MissingPath,
+    /// The same part appears more than once in a var.
+    DuplicatePart,
+    /// A scalar var has more than one entry (only one can win).
+    ScalarHasMultipleEntries,
+    /// A custom part uses a different separator than its var definition or,
+    /// without one, than the first part of the var.
+    SeparatorMismatch,
+    /// A `~` after the start of a value, which no shell expands.
+    EmbeddedTilde,
+    /// A `{NAME}` placeholder bath does not know; it is exported as written.
+    UnknownPlaceholder,
+    /// `@{VAR}` references that lead back to their own variable.
+    ReferenceCycle,
+    /// An empty or whitespace-only part of a list var, which export drops.
+    EmptyValue,
+}
+
+/// How bad an issue is. Errors are parts the export cannot use as stored;
+/// warnings are exported but probably not what was meant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+impl IssueKind {
+    pub fn severity(self) -> Severity {
+        match self {
+            IssueKind::ReferenceCycle | IssueKind::EmptyValue => Severity::Error,
+            IssueKind::MissingPath
+            | IssueKind::DuplicatePart
+            | IssueKind::ScalarHasMultipleEntries
+            | IssueKind::SeparatorMismatch
+            | IssueKind::EmbeddedTilde
+            | IssueKind::UnknownPlaceholder => Severity::Warning,
+        }
+    }
+}
+
+/// A single problem found in a profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub severity: Severity,
+    pub var: String,
+    /// Index of the offending entry in `EnvProfile::entries`.
+    pub index: usize,
+    pub message: String,
+}
+
+impl Issue {
+    pub fn new(kind: IssueKind, var: &str, index: usize, message: String) -> Self {
+        Issue {
+            kind,
+            severity: kind.severity(),
+            var: var.to_string(),
+            index,
+            message,
+        }
+    }
+
+    /// Whether `fix_profile` can resolve this issue by dropping the entry.
+    pub fn is_fixable(&self) -> bool {
+        matches!(
+            self.kind,
+            IssueKind::MissingPath | IssueKind::DuplicatePart | IssueKind::EmptyValue
+        )
+    }
+}
+
+/// Path-likeness comes from the var definition: builtin ':'-separated lists, and
+/// custom ':'-separated lists whose name ends in PATH.
+fn is_path_var(name: &str, custom_defs: &[CustomVarDef]) -> bool {
+    let is_builtin = config::builtin_var(name).is_some();
+    match lookup_var_option(name, custom_defs) {
+        Some(o) => {
+            o.kind == VarKind::List && o.separator == ":" && (is_builtin || name.ends_with("PATH"))
+        }
+        None => false,
+    }
+}
+
+pub(crate) fn is_scalar_var(name: &str, entries: &[Entry], custom_defs: &[CustomVarDef]) -> bool {
+    match lookup_var_option(name, custom_defs) {
+        Some(o) => o.kind == VarKind::Scalar,
+        None => entries
+            .iter()
+            .any(|e| matches!(e.plain(), Entry::CustomScalar { name: n, .. } if n == name)),
+    }
+}
+
+/// Expands `~`, `$VAR` and `${VAR}` against `env` so a stored value can be checked
+/// on disk. Returns None if it references a var that is not set.
+fn expand_for_check(value: &str, env: &HashMap<String, String>) -> Option<String> {
+    let value = match value.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{rest}", env.get("HOME")?)
+        }
+        _ => value.to_string(),
+    };
+
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '$' {
+            out.push(ch);
+            continue;
+        }
+        let mut name = String::new();
+        if chars.peek() == Some(&'{') {
+            chars.next();
+            for c in chars.by_ref() {
+                if c == '}' {
+                    break;
+                }
+                name.push(c);
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+        }
+        if name.is_empty() {
+            out.push('$');
+        } else {
+            out.push_str(env.get(&name)?);
+        }
+    }
+    Some(out)
+}
+
+/// The separator a list var is defined with, builtin or custom.
+fn defined_separator(var: &str, custom_defs: &[CustomVarDef]) -> Option<String> {
+    lookup_var_option(var, custom_defs)
+        .filter(|o| o.kind == VarKind::List)
+        .map(|o| o.separator)
+}
+
+fn first_part_separator(entries: &[Entry], var: &str) -> Option<String> {
+    entries.iter().find_map(|e| match e.plain() {
+        Entry::CustomPart {
+            name, separator, ..
+        } if name == var => Some(separator.clone()),
+        _ => None,
+    })
+}
+
+/// Rewrites custom parts whose separator disagrees with the rest of their var:
+/// the separator of the var definition wins, else the one of the first part.
+/// Returns each rewritten var with the separator it now uses.
+pub fn normalize_separators(
+    profile: &mut EnvProfile,
+    custom_defs: &[CustomVarDef],
+) -> Vec<(String, String)> {
+    let mut rewritten: Vec<(String, String)> = Vec::new();
+    for i in 0..profile.entries.len() {
+        let Entry::CustomPart {
+            name, separator, ..
+        } = profile.entries[i].plain()
+        else {
+            continue;
+        };
+        let Some(expected) = defined_separator(name, custom_defs)
+            .or_else(|| first_part_separator(&profile.entries, name))
+        else {
+            continue;
+        };
+        if *separator == expected {
+            continue;
+        }
+        let name = name.clone();
+        if let Entry::CustomPart { separator, .. } = profile.entries[i].plain_mut() {
+            *separator = expected.clone();
+        }
+        if !rewritten.iter().any(|(v, _)| *v == name) {
+            rewritten.push((name, expected));
+        }
+    }
+    rewritten
+}
+
+/// Checks a single entry on its own: an empty list part, a tilde that will stay
+/// literal, placeholders that are not replaced and, for path-like vars, a path
+/// that does not exist. The value is checked with the placeholders of `profile`
+/// expanded.
+///
+/// The editor runs this before saving; issues carry index 0.
+pub fn validate_entry(
+    entry: &Entry,
+    profile: &str,
+    custom_defs: &[CustomVarDef],
+    env: &HashMap<String, String>,
+) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let var = entry.var_name().into_owned();
+    let raw = entry_value(entry);
+    let value = template::expand(&raw, profile, env);
+
+    let scalar = is_scalar_var(&var, std::slice::from_ref(entry), custom_defs);
+    if !scalar && raw.trim().is_empty() {
+        issues.push(Issue::new(
+            IssueKind::EmptyValue,
+            &var,
+            0,
+            format!("{var} parts cannot be empty"),
+        ));
+    }
+
+    if raw.char_indices().any(|(i, c)| c == '~' && i > 0) {
+        issues.push(Issue::new(
+            IssueKind::EmbeddedTilde,
+            &var,
+            0,
+            format!("'~' is only expanded at the start: {raw}"),
+        ));
+    }
+
+    let unknown = template::unknown_placeholders(&raw);
+    if !unknown.is_empty() {
+        issues.push(Issue::new(
+            IssueKind::UnknownPlaceholder,
+            &var,
+            0,
+            format!("unknown placeholder {}: {raw}", unknown.join(", ")),
+        ));
+    }
+
+    if is_path_var(&var, custom_defs) && !value.trim().is_empty() {
+        if let Some(expanded) = expand_for_check(&value, env) {
+            if !Path::new(&expanded).exists() {
+                issues.push(Issue::new(
+                    IssueKind::MissingPath,
+                    &var,
+                    0,
+                    format!("missing path: {value}"),
+                ));
+            }
+        }
+    }
+
+    issues
+}
+
+/// Checks a profile for everything `validate_entry` checks, plus duplicate
+/// parts, scalars set more than once, custom parts whose separator disagrees
+/// with their definition and variables whose `@{VAR}` references form a cycle.
+/// Values are compared with their placeholders expanded. Disabled parts are not
+/// checked.
+///
+/// Issues are returned in entry order.
+pub fn validate_profile(
+    profile: &EnvProfile,
+    custom_defs: &[CustomVarDef],
+    env: &HashMap<String, String>,
+) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut seen: HashMap<String, Vec<String>> = HashMap::new();
+
+    for (index, entry) in profile.entries.iter().enumerate() {
+        if !entry.is_exported() {
+            continue;
+        }
+        let var = entry.var_name().into_owned();
+        let value = template::expand(&entry_value(entry), &profile.name, env);
+        let previous = seen.entry(var.clone()).or_default();
+
+        if is_scalar_var(&var, &profile.entries, custom_defs) {
+            if !previous.is_empty() {
+                issues.push(Issue::new(
+                    IssueKind::ScalarHasMultipleEntries,
+                    &var,
+                    index,
+                    format!("scalar set more than once: {value}"),
+                ));
+            }
+        } else if previous.contains(&value) && !value.trim().is_empty() {
+            issues.push(Issue::new(
+                IssueKind::DuplicatePart,
+                &var,
+                index,
+                format!("duplicate part: {value}"),
+            ));
+        }
+        previous.push(value);
+
+        if let Entry::CustomPart { separator, .. } = entry.plain() {
+            let expected = match defined_separator(&var, custom_defs) {
+                Some(sep) => Some((sep, "definition")),
+                None => first_part_separator(&profile.entries, &var).map(|s| (s, "first part")),
+            };
+            if let Some((expected, source)) = expected {
+                if &expected != separator {
+                    issues.push(Issue::new(
+                        IssueKind::SeparatorMismatch,
+                        &var,
+                        index,
+                        format!("separator {separator:?} differs from {source} ({expected:?})"),
+                    ));
+                }
+            }
+        }
+
+        issues.extend(
+            validate_entry(entry, &profile.name, custom_defs, env)
+                .into_iter()
+                .map(|i| Issue { index, ..i }),
+        );
+    }
+
+    for cycle in crate::export::reference_cycles(&profile.entries) {
+        let reference = format!("@{{{}}}", cycle[1]);
+        let index = profile.entries.iter().position(|e| {
+            e.is_exported() && e.var_name() == cycle[0] && entry_value(e).contains(&reference)
+        });
+        if let Some(index) = index {
+            issues.push(Issue::new(
+                IssueKind::ReferenceCycle,
+                &cycle[0],
+                index,
+                format!("reference cycle: {}", cycle.join(" -> ")),
+            ));
+        }
+    }
+    issues.sort_by_key(|i| i.index);
+
+    issues
+}
+
+/// Drops every entry with a fixable issue (missing paths and duplicates; the first
+/// occurrence of a duplicated part is kept). Returns how many entries were removed.
+pub fn fix_profile(profile: &mut EnvProfile, issues: &[Issue]) -> usize {
+    let drop: BTreeSet<usize> = issues
+        .iter()
+        .filter(|i| i.is_fixable())
+        .map(|i| i.index)
+        .collect();
+    let mut index = 0;
+    profile.entries.retain(|_| {
+        let keep = !drop.contains(&index);
+        index += 1;
+        keep
+    });
+    drop.len()
+}
+
+/// Removes the parts of path-like vars that point at nothing on disk and returns
+/// the issues describing them. Path-likeness comes from the var definition, so
+/// e.g. a CFLAGS value that looks like a path is never touched.
+pub fn prune_profile(
+    profile: &mut EnvProfile,
+    custom_defs: &[CustomVarDef],
+    env: &HashMap<String, String>,
+) -> Vec<Issue> {
+    let dead: Vec<Issue> = validate_profile(profile, custom_defs, env)
+        .into_iter()
+        .filter(|i| i.kind == IssueKind::MissingPath)
+        .collect();
+    fix_profile(profile, &dead);
+    dead
+}
+
+/// Prunes dead paths from one or all stored profiles, printing what was removed.
+/// With `dry_run` nothing is saved.
+pub fn prune_command(profile: Option<&str>, dry_run: bool) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let custom_defs = db::load_custom_var_defs(&conn)?;
+    let profiles = match profile {
+        Some(name) => vec![db::load_profile(&conn, name)?],
+        None => crate::profiles::readable_profiles(&conn)?,
+    };
+    let env = export::current_env();
+    let verb = if dry_run { "would remove" } else { "removed" };
+
+    let mut total = 0;
+    for mut p in profiles {
+        let dead = prune_profile(&mut p, &custom_defs, &env);
+        if dead.is_empty() {
+            continue;
+        }
+        println!("{}: {verb} {} entries", p.name, dead.len());
+        for i in &dead {
+            println!("  {}[{}] {}", i.var, i.index, i.message);
+        }
+        if !dry_run {
+            db::save_profile(&conn, &p)?;
+        }
+        total += dead.len();
+    }
+    if total == 0 {
+        println!("No dead paths found.");
+    }
+    Ok(())
+}
+
+/// Validates one or all stored profiles and prints the problems per profile.
+///
+/// With `fix`, the fixes for fixable problems are listed; only with `yes` too are
+/// they applied and the profile saved. Returns true if any problems remain.
+pub fn doctor_command(profile: Option<&str>, fix: bool, yes: bool) -> Result<bool> {
+    let conn = db::establish_connection()?;
+    let custom_defs = db::load_custom_var_defs(&conn)?;
+    let (profiles, broken) = match profile {
+        Some(name) => (vec![db::load_profile(&conn, name)?], Vec::new()),
+        None => db::load_profiles_checked(&conn)?,
+    };
+    let env = export::current_env();
+
+    let verb = if yes { "fixed" } else { "would fix" };
+    let mut remaining = broken.len();
+    let mut affected = broken.len();
+    let mut unapplied = false;
+    for b in &broken {
+        println!("{}:", b.name);
+        println!(
+            "  entries cannot be read: {} (`:repair {}` in the TUI)",
+            b.error, b.name
+        );
+    }
+    for mut p in profiles.iter().cloned() {
+        let issues = validate_profile(&p, &custom_defs, &env);
+        if issues.is_empty() {
+            continue;
+        }
+        affected += 1;
+        println!("{}:", p.name);
+        for i in &issues {
+            println!(
+                "  {}[{}] {}: {}",
+                i.var,
+                i.index,
+                i.severity.name(),
+                i.message
+            );
+        }
+        let mismatched = |i: &Issue| i.kind == IssueKind::SeparatorMismatch;
+        if fix && issues.iter().any(|i| i.is_fixable() || mismatched(i)) {
+            for (var, sep) in normalize_separators(&mut p, &custom_defs) {
+                println!("  {verb}: {var} parts use separator {sep:?}");
+            }
+            let removed = fix_profile(&mut p, &issues);
+            if removed > 0 {
+                println!("  {verb}: remove {removed} entries");
+            }
+            if yes {
+                db::save_profile(&conn, &p)?;
+                remaining += issues
+                    .iter()
+                    .filter(|i| !i.is_fixable() && !mismatched(i))
+                    .count();
+            } else {
+                unapplied = true;
+                remaining += issues.len();
+            }
+        } else {
+            remaining += issues.len();
+        }
+    }
+
+    let total = profiles.len() + broken.len();
+    if affected == 0 {
+        println!("No problems found in {total} profile(s).");
+    } else {
+        println!("{remaining} problem(s) remaining in {affected} of {total} profile(s).");
+    }
+    if unapplied {
+        println!("Nothing was changed; run again with --fix --yes to apply the fixes.");
+    }
+    Ok(remaining > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(entries: Vec<Entry>) -> EnvProfile {
+        EnvProfile {
+            entries,
+            ..EnvProfile::new("p")
+        }
+    }
+
+    fn kinds(issues: &[Issue]) -> Vec<(IssueKind, usize)> {
+        issues.iter().map(|i| (i.kind, i.index)).collect()
+    }
+
+    #[test]
+    fn flags_missing_paths_but_not_flags() {
+        let existing = std::env::temp_dir().display().to_string();
+        let p = profile(vec![
+            Entry::CPath(existing),
+            Entry::LibraryPath("/definitely/not/here/lib".to_string()),
+            Entry::CFlag("-I/definitely/not/here".to_string()),
+        ]);
+        let issues = validate_profile(&p, &[], &HashMap::new());
+        assert_eq!(kinds(&issues), vec![(IssueKind::MissingPath, 1)]);
+    }
+
+    #[test]
+    fn expands_env_references_and_skips_unset_ones() {
+        let env = HashMap::from([("ROOT".to_string(), "/definitely/not".to_string())]);
+        assert_eq!(
+            expand_for_check("${ROOT}/include:$ROOT", &env).as_deref(),
+            Some("/definitely/not/include:/definitely/not")
+        );
+        assert_eq!(expand_for_check("$UNSET/lib", &env), None);
+
+        let p = profile(vec![Entry::CPath("$UNSET/include".to_string())]);
+        assert!(validate_profile(&p, &[], &env).is_empty());
+    }
+
+    #[test]
+    fn flags_duplicates_scalars_and_separator_mismatches() {
+        let defs = vec![CustomVarDef::new("MYLIST", VarKind::List, ",")];
+        let p = profile(vec![
+            Entry::CFlag("-O2".to_string()),
+            Entry::CFlag("-O2".to_string()),
+            Entry::CC("gcc".to_string()),
+            Entry::CC("clang".to_string()),
+            Entry::CustomPart {
+                name: "MYLIST".to_string(),
+                value: "a".to_string(),
+                separator: ";".to_string(),
+                mode: None,
+            },
+        ]);
+        let issues = validate_profile(&p, &defs, &HashMap::new());
+        assert_eq!(
+            kinds(&issues),
+            vec![
+                (IssueKind::DuplicatePart, 1),
+                (IssueKind::ScalarHasMultipleEntries, 3),
+                (IssueKind::SeparatorMismatch, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_list_parts_are_errors_but_empty_scalars_are_fine() {
+        let p = profile(vec![
+            Entry::CFlag(" ".to_string()),
+            Entry::CC(String::new()),
+            Entry::CustomPart {
+                name: "MYLIST".to_string(),
+                value: String::new(),
+                separator: ",".to_string(),
+                mode: None,
+            },
+            Entry::CFlag(String::new()).with_enabled(false),
+        ]);
+        let issues = validate_profile(&p, &[], &HashMap::new());
+        assert_eq!(
+            kinds(&issues),
+            vec![(IssueKind::EmptyValue, 0), (IssueKind::EmptyValue, 2)]
+        );
+        assert_eq!(issues[0].message, "CFLAGS parts cannot be empty");
+        assert!(issues.iter().all(|i| i.is_fixable()));
+    }
+
+    #[test]
+    fn every_kind_has_a_fixed_severity() {
+        let errors = [IssueKind::ReferenceCycle, IssueKind::EmptyValue];
+        let warnings = [
+            IssueKind::MissingPath,
+            IssueKind::DuplicatePart,
+            IssueKind::ScalarHasMultipleEntries,
+            IssueKind::SeparatorMismatch,
+            IssueKind::EmbeddedTilde,
+            IssueKind::UnknownPlaceholder,
+        ];
+        assert!(errors.iter().all(|k| k.severity() == Severity::Error));
+        assert!(warnings.iter().all(|k| k.severity() == Severity::Warning));
+        assert!(Severity::Error > Severity::Warning);
+
+        let issue = Issue::new(IssueKind::EmptyValue, "PATH", 3, "m".to_string());
+        assert_eq!((issue.severity, issue.index), (Severity::Error, 3));
+    }
+
+    #[test]
+    fn single_entries_are_checked_without_their_profile() {
+        let env = HashMap::from([("HOME".to_string(), "/definitely/not".to_string())]);
+        let check = |entry: Entry| -> Vec<(IssueKind, usize)> {
+            kinds(&validate_entry(&entry, "p", &[], &env))
+        };
+        assert_eq!(
+            check(Entry::LibraryPath("{HOME}/lib".to_string())),
+            vec![(IssueKind::MissingPath, 0)]
+        );
+        assert_eq!(
+            check(Entry::CPath("".to_string())),
+            vec![(IssueKind::EmptyValue, 0)]
+        );
+        assert_eq!(
+            check(Entry::CFlag("-I~/x {WHAT}".to_string())),
+            vec![
+                (IssueKind::EmbeddedTilde, 0),
+                (IssueKind::UnknownPlaceholder, 0)
+            ]
+        );
+        assert!(check(Entry::CustomScalar {
+            name: "EMPTY_OK".to_string(),
+            value: String::new(),
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn flags_tildes_that_are_not_leading() {
+        let p = profile(vec![
+            Entry::CFlag("-I~/include".to_string()),
+            Entry::CC("~/bin/gcc".to_string()),
+            Entry::CXXFlag("-I/opt/x~1".to_string()),
+        ]);
+        let issues = validate_profile(&p, &[], &HashMap::new());
+        assert_eq!(
+            kinds(&issues),
+            vec![(IssueKind::EmbeddedTilde, 0), (IssueKind::EmbeddedTilde, 2)]
+        );
+        assert!(!issues[0].is_fixable());
+    }
+
+    #[test]
+    fn placeholders_are_expanded_before_checks_and_unknown_ones_flagged() {
+        let env = HashMap::from([("HOME".to_string(), "/home/me".to_string())]);
+        let p = profile(vec![
+            Entry::CFlag("-I{HOME}/include".to_string()),
+            Entry::CFlag("-I/home/me/include".to_string()),
+            Entry::CFlag("-I{SYSROOT}/include".to_string()),
+        ]);
+        let issues = validate_profile(&p, &[], &env);
+        assert_eq!(
+            kinds(&issues),
+            vec![
+                (IssueKind::DuplicatePart, 1),
+                (IssueKind::UnknownPlaceholder, 2)
+            ]
+        );
+        assert_eq!(
+            issues[1].message,
+            "unknown placeholder {SYSROOT}: -I{SYSROOT}/include"
+        );
+        assert!(!issues[1].is_fixable());
+    }
+
+    #[test]
+    fn flags_reference_cycles_on_the_referencing_part() {
+        let p = profile(vec![
+            Entry::CFlag("-O2".to_string()),
+            Entry::CFlag("@{LDFLAGS}".to_string()),
+            Entry::LDFlag("@{CFLAGS}".to_string()),
+        ]);
+        let issues = validate_profile(&p, &[], &HashMap::new());
+        assert_eq!(kinds(&issues), vec![(IssueKind::ReferenceCycle, 1)]);
+        assert_eq!(
+            issues[0].message,
+            "reference cycle: CFLAGS -> LDFLAGS -> CFLAGS"
+        );
+    }
+
+    #[test]
+    fn fix_drops_missing_paths_and_later_duplicates() {
+        let mut p = profile(vec![
+            Entry::CFlag("-g".to_string()),
+            Entry::CPath("/definitely/not/here".to_string()),
+            Entry::CFlag("-g".to_string()),
+            Entry::CC("gcc".to_string()),
+            Entry::CC("clang".to_string()),
+        ]);
+        let issues = validate_profile(&p, &[], &HashMap::new());
+        assert_eq!(fix_profile(&mut p, &issues), 2);
+        let left: Vec<String> = p.entries.iter().map(entry_value).collect();
+        assert_eq!(left, vec!["-g", "gcc", "clang"]);
+    }
+
+    #[test]
+    fn prune_removes_only_missing_parts_of_path_vars() -> std::io::Result<()> {
+        let root = std::env::temp_dir().join(format!("bath-prune-{}", std::process::id()));
+        let real = root.join("real");
+        std::fs::create_dir_all(&real)?;
+        let real = real.display().to_string();
+        let gone = root.join("gone").display().to_string();
+
+        let mut p = profile(vec![
+            Entry::LibraryPath(real.clone()),
+            Entry::LibraryPath(gone.clone()),
+            Entry::LDLibraryPath(gone.clone()),
+            Entry::CFlag(gone.clone()),
+        ]);
+        let dead = prune_profile(&mut p, &[], &HashMap::new());
+        std::fs::remove_dir_all(&root)?;
+
+        assert_eq!(
+            kinds(&dead),
+            vec![(IssueKind::MissingPath, 1), (IssueKind::MissingPath, 2)]
+        );
+        let left: Vec<String> = p.entries.iter().map(entry_value).collect();
+        assert_eq!(left, vec![real, gone]);
+        Ok(())
+    }
+
+    #[test]
+    fn parts_follow_a_changed_definition_separator() {
+        let part = |value: &str, separator: &str| Entry::CustomPart {
+            name: "MYLIST".to_string(),
+            value: value.to_string(),
+            separator: separator.to_string(),
+            mode: None,
+        };
+        // Parts created while MYLIST was ';'-separated, then the def changed to ':'.
+        let mut p = profile(vec![part("a", ";"), part("b", ";"), part("c", ":")]);
+        let defs = vec![CustomVarDef::new("MYLIST", VarKind::List, ":")];
+        assert_eq!(
+            kinds(&validate_profile(&p, &defs, &HashMap::new())),
+            vec![
+                (IssueKind::SeparatorMismatch, 0),
+                (IssueKind::SeparatorMismatch, 1)
+            ]
+        );
+        assert_eq!(
+            normalize_separators(&mut p, &defs),
+            vec![("MYLIST".to_string(), ":".to_string())]
+        );
+        assert!(validate_profile(&p, &defs, &HashMap::new()).is_empty());
+        assert!(normalize_separators(&mut p, &defs).is_empty());
+
+        // Without a definition the first part decides.
+        let mut p = profile(vec![part("a", ","), part("b", ";")]);
+        assert_eq!(
+            kinds(&validate_profile(&p, &[], &HashMap::new())),
+            vec![(IssueKind::SeparatorMismatch, 1)]
+        );
+        normalize_separators(&mut p, &[]);
+        assert_eq!(
+            crate::export::grouped_vars(&p.entries),
+            vec![("MYLIST".to_string(), "a,b".to_string(), ",".to_string())]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+// src/which.rs
+
+use crate::config::{self, Entry, EnvProfile};
+use crate::db;
+use crate::error::BathError;
+use crate::export::{self, entry_value, OperationMode};
+use anyhow::Result;
+
+/// One line per entry of `var`: its index in the profile, whether the var is a
+/// builtin or custom one, the value and, for PATH entries, the program/version.
+/// Disabled parts are listed too, marked as such.
+pub fn explain_lines(profile: &EnvProfile, var: &str) -> Vec<String> {
+    let kind = if config::builtin_var(var).is_some() {
+        "builtin"
+    } else {
+        "custom"
+    };
+    profile
+        .entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.var_name() == var)
+        .map(|(index, entry)| {
+            let mut line = format!("  [{index}] {kind}  {}", entry_value(entry));
+            if let Entry::Path(pe) = entry.plain() {
+                let meta: Vec<String> = [("program", &pe.program), ("version", &pe.version)]
+                    .into_iter()
+                    .filter_map(|(k, v)| v.as_ref().map(|v| format!("{k}: {v}")))
+                    .collect();
+                if !meta.is_empty() {
+                    line.push_str(&format!("  ({})", meta.join(", ")));
+                }
+            }
+            if !entry.is_enabled() {
+                line.push_str("  (disabled)");
+            }
+            line
+        })
+        .collect()
+}
+
+/// Prints the final value of `var` with the profile applied on top of the current
+/// environment, optionally followed by the entries contributing to it.
+pub fn which_command(
+    profile_name: &str,
+    var: &str,
+    mode: OperationMode,
+    explain: bool,
+) -> Result<()> {
+    let conn = db::establish_connection()?;
+    let chain = export::load_chain(&conn, profile_name)?;
+    let profile = export::inherit(&chain);
+    let base = export::current_env();
+    let expanded = export::load_inherited(&conn, profile_name, &base)?;
+    let separators = db::load_separator_overrides(&conn)?;
+    let Some(value) = export::compose_var(&expanded, var, mode, &base, &separators) else {
+        return Err(BathError::VarNotInProfile {
+            var: var.to_string(),
+            profile: profile_name.to_string(),
+        }
+        .into());
+    };
+    println!("{value}");
+    if explain {
+        let lines = explain_lines(&profile, var);
+        if lines.is_empty() {
+            println!("  (the default of the custom var definition)");
+        }
+        for line in lines {
+            println!("{line}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{PathEntry, SeparatorOverrides};
+    use std::collections::HashMap;
+
+    #[test]
+    fn explain_lists_contributing_entries_with_metadata() {
+        let profile = EnvProfile {
+            entries: vec![
+                Entry::Path(PathEntry {
+                    path: "/opt/gcc/bin".to_string(),
+                    program: Some("gcc".to_string()),
+                    version: Some("13".to_string()),
+                    mode: None,
+                }),
+                Entry::CFlag("-O2".to_string()),
+                Entry::Path(PathEntry {
+                    path: "/opt/tools/bin".to_string(),
+                    program: None,
+                    version: None,
+                    mode: None,
+                }),
+                Entry::CustomPart {
+                    name: "MYLIST".to_string(),
+                    value: "a".to_string(),
+                    separator: ",".to_string(),
+                    mode: None,
+                },
+            ],
+            ..EnvProfile::new("p")
+        };
+        assert_eq!(
+            explain_lines(&profile, "PATH"),
+            vec![
+                "  [0] builtin  /opt/gcc/bin  (program: gcc, version: 13)",
+                "  [2] builtin  /opt/tools/bin",
+            ]
+        );
+        assert_eq!(explain_lines(&profile, "MYLIST"), vec!["  [3] custom  a"]);
+
+        let base = HashMap::from([("PATH".to_string(), "/usr/bin".to_string())]);
+        assert_eq!(
+            export::compose_var(
+                &profile,
+                "PATH",
+                OperationMode::Prepend,
+                &base,
+                &SeparatorOverrides::new()
+            )
+            .as_deref(),
+            Some("/opt/gcc/bin:/opt/tools/bin:/usr/bin")
+        );
+        assert_eq!(
+            export::compose_var(
+                &profile,
+                "LDFLAGS",
+                OperationMode::Prepend,
+                &base,
+                &SeparatorOverrides::new()
+            ),
+            None
+        );
+    }
+}